@@ -0,0 +1,374 @@
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use clap::Parser;
+use futures::{stream, StreamExt};
+use ilearn::{
+    client::{Client, ClientError, Cmd, ConnectOptions},
+    frame::Frame,
+    server::DEFAULT_PORT,
+};
+use tokio::io::{self, AsyncBufRead, AsyncBufReadExt, BufReader};
+
+/// 每次最多等待回复的命令数，见 `pipe`
+const PIPE_WINDOW: usize = 1024;
+
+/// 保存在 HOME 下的历史记录最多保留的条数
+const HISTORY_LIMIT: usize = 1000;
+
+/// 命令行客户端，用法与 redis-cli 相同
+///
+/// 带命令参数时执行这一条命令后退出：`cli SET k v`。没有参数时进入交互模式，每行一条命令，
+/// 参数可以用单引号或双引号包含空格，双引号中支持 \n、\t、\" 和 \xHH 转义。历史记录保存在
+/// ~/.ilearn_cli_history，输入 history 查看。行编辑由终端提供，不支持方向键翻阅历史。
+///
+/// `--pipe` 从标准输入按行读取命令批量发送，适合导入数据，最后打印回复数和错误数。
+#[derive(Debug, Parser)]
+#[command(name = "cli", version, disable_help_flag = true)]
+struct Cli {
+    /// 与 redis-cli 相同，-h 表示 host，帮助只有 --help
+    #[arg(long, action = clap::ArgAction::Help)]
+    help: Option<bool>,
+    /// 服务端的地址
+    #[arg(short = 'h', long, default_value = "127.0.0.1")]
+    host: String,
+    #[arg(short, long, default_value_t = DEFAULT_PORT)]
+    port: u16,
+    /// unix socket 的路径，设置后忽略 host 和 port
+    #[arg(short, long)]
+    socket: Option<PathBuf>,
+    /// redis://、rediss:// 或 redis+unix:// URL，设置后忽略其他连接参数
+    #[arg(short, long)]
+    url: Option<String>,
+    /// 连接后执行 AUTH 的密码
+    #[arg(short = 'a', long)]
+    password: Option<String>,
+    #[arg(short = 'n', long, default_value_t = 0)]
+    db: usize,
+    /// 从标准输入读取命令批量发送
+    #[arg(long)]
+    pipe: bool,
+    /// 执行的命令，为空时进入交互模式
+    command: Vec<String>,
+}
+
+#[tokio::main]
+async fn main() -> ilearn::server::Result<()> {
+    let cli = Cli::parse();
+    let options = match &cli.url {
+        Some(url) => ConnectOptions::from_url(url)?,
+        None => {
+            let mut options = ConnectOptions::default().host(&cli.host).port(cli.port);
+            options.unix_socket = cli.socket.clone();
+            options.password = cli.password.clone();
+            options.db(cli.db)
+        }
+    };
+    let prompt = match &options.unix_socket {
+        Some(path) => format!("{}> ", path.display()),
+        None => format!("{}:{}> ", options.host, options.port),
+    };
+    let mut client = options.connect().await?;
+
+    if cli.pipe {
+        return pipe(client).await;
+    }
+    if let Some((name, args)) = cli.command.split_first() {
+        let reply = client.execute(Cmd::new(name).args(args)).await;
+        return print_reply(reply);
+    }
+    repl(client, &prompt).await
+}
+
+/// 交互模式：读取一行、执行、打印，直到输入 quit 或者标准输入结束
+async fn repl(mut client: Client, prompt: &str) -> ilearn::server::Result<()> {
+    let mut history = History::load();
+    let mut lines = BufReader::new(io::stdin()).lines();
+    loop {
+        print!("{}", prompt);
+        std::io::stdout().flush()?;
+        let Some(line) = lines.next_line().await? else {
+            println!();
+            return Ok(());
+        };
+        let args = match split_args(&line) {
+            Ok(args) if args.is_empty() => continue,
+            Ok(args) => args,
+            Err(e) => {
+                println!("(error) {}", e);
+                continue;
+            }
+        };
+        history.push(line.trim());
+        match String::from_utf8_lossy(&args[0]).to_lowercase().as_str() {
+            "quit" | "exit" => return Ok(()),
+            "history" => {
+                for (i, line) in history.lines.iter().enumerate() {
+                    println!("{:>4}  {}", i + 1, line);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        let reply = client.execute(to_cmd(&args)).await;
+        // 连接错误不退出，下一条命令重新连接
+        if let Err(e) = print_reply(reply) {
+            println!("(error) {}", e);
+        }
+    }
+}
+
+/// 批量模式：按行读取命令，最多 `PIPE_WINDOW` 条同时等待回复
+async fn pipe(client: Client) -> ilearn::server::Result<()> {
+    let multiplexed = client.multiplexed();
+    let cmds = read_commands(BufReader::new(io::stdin())).await?;
+
+    let (mut replies, mut errors) = (0, 0);
+    let mut results = stream::iter(cmds)
+        .map(|cmd| multiplexed.execute(cmd))
+        .buffered(PIPE_WINDOW);
+    while let Some(result) = results.next().await {
+        replies += 1;
+        match result {
+            Ok(_) => {}
+            Err(ClientError::Server(e)) => {
+                errors += 1;
+                eprintln!("(error) {}", e);
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    println!(
+        "All data transferred. errors: {}, replies: {}",
+        errors, replies
+    );
+    Ok(())
+}
+
+/// 按行读取批量模式的命令，跳过空行，有一行不能解析时返回错误，不发送任何命令
+async fn read_commands(input: impl AsyncBufRead + Unpin) -> ilearn::server::Result<Vec<Cmd>> {
+    let mut cmds = Vec::new();
+    let mut lines = input.lines();
+    while let Some(line) = lines.next_line().await? {
+        let args = split_args(&line)?;
+        if !args.is_empty() {
+            cmds.push(to_cmd(&args));
+        }
+    }
+    Ok(cmds)
+}
+
+/// `args` 不能为空
+fn to_cmd(args: &[Vec<u8>]) -> Cmd {
+    Cmd::new(&String::from_utf8_lossy(&args[0])).args(args[1..].iter().cloned())
+}
+
+/// 服务端的错误回复按 redis-cli 的格式打印，其他错误返回给调用者
+fn print_reply(reply: Result<Frame, ClientError>) -> ilearn::server::Result<()> {
+    match reply {
+        Ok(frame) => println!("{}", pretty(&frame, 0)),
+        Err(ClientError::Server(e)) => println!("(error) {}", e),
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+/// 按 redis-cli 的格式显示回复，数组的元素编号，嵌套的数组按编号的宽度缩进
+fn pretty(frame: &Frame, indent: usize) -> String {
+    match frame {
+        Frame::Simple(s) => s.clone(),
+        Frame::Error(e) => format!("(error) {}", e),
+        Frame::Integer(n) => format!("(integer) {}", n),
+        Frame::Bulk(bytes) => quote(bytes),
+        Frame::Null => "(nil)".to_string(),
+        Frame::Array(items) | Frame::Push(items) if items.is_empty() => "(empty array)".to_string(),
+        Frame::Array(items) | Frame::Push(items) => {
+            let width = items.len().to_string().len();
+            let mut out = String::new();
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                }
+                let label = format!("{:>width$}) ", i + 1, width = width);
+                out.push_str(&label);
+                out.push_str(&pretty(item, indent + label.len()));
+            }
+            out
+        }
+    }
+}
+
+/// 加上双引号，不可打印的字节显示为 \xHH
+fn quote(bytes: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for &b in bytes {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// 按空白拆分参数，单引号中的内容原样保留，双引号中支持转义
+fn split_args(line: &str) -> Result<Vec<Vec<u8>>, String> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            return Ok(args);
+        };
+        let mut arg = Vec::new();
+        let mut buf = [0; 4];
+        if first == '"' || first == '\'' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    None => return Err("unbalanced quotes".to_string()),
+                    Some(c) if c == first => break,
+                    Some('\\') if first == '"' => match chars.next() {
+                        Some('n') => arg.push(b'\n'),
+                        Some('r') => arg.push(b'\r'),
+                        Some('t') => arg.push(b'\t'),
+                        Some('x') => {
+                            let hex: String = chars.by_ref().take(2).collect();
+                            let byte = u8::from_str_radix(&hex, 16)
+                                .map_err(|_| format!("invalid escape \\x{}", hex))?;
+                            arg.push(byte);
+                        }
+                        Some(c) => arg.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+                        None => return Err("unbalanced quotes".to_string()),
+                    },
+                    Some(c) => arg.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+                }
+            }
+            if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                return Err("closing quote must be followed by a space".to_string());
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+        args.push(arg);
+    }
+}
+
+/// 交互模式的历史记录，每条命令追加到文件中
+struct History {
+    path: Option<PathBuf>,
+    lines: Vec<String>,
+}
+
+impl History {
+    fn load() -> History {
+        let path = env::var_os("HOME").map(|home| PathBuf::from(home).join(".ilearn_cli_history"));
+        let mut lines: Vec<String> = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(String::from).collect())
+            .unwrap_or_default();
+        let excess = lines.len().saturating_sub(HISTORY_LIMIT);
+        lines.drain(..excess);
+        History { path, lines }
+    }
+
+    fn push(&mut self, line: &str) {
+        self.lines.push(line.to_string());
+        let Some(path) = &self.path else {
+            return;
+        };
+        // 写入失败时只保留在内存中
+        if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn parses_pipe_mode_arguments() {
+        let cli = Cli::try_parse_from(["cli", "--pipe", "-h", "redis.local", "-n", "2"]).unwrap();
+        assert!(cli.pipe);
+        assert_eq!(cli.host, "redis.local");
+        assert_eq!(cli.db, 2);
+        assert!(cli.command.is_empty());
+        assert!(!Cli::try_parse_from(["cli", "GET", "k"]).unwrap().pipe);
+    }
+
+    #[tokio::test]
+    async fn reads_one_command_per_line() {
+        let input = "SET k v\n\n  \nSET \"a b\" 'c d'\nSET bin \"\\x00\\n\"\n";
+        let cmds = read_commands(input.as_bytes()).await.unwrap();
+        assert_eq!(
+            cmds,
+            vec![
+                Cmd::new("SET").arg("k").arg("v"),
+                Cmd::new("SET").arg("a b").arg("c d"),
+                Cmd::new("SET").arg("bin").arg(Bytes::from_static(b"\0\n")),
+            ]
+        );
+
+        assert!(read_commands("SET k v\nSET \"k v\n".as_bytes())
+            .await
+            .is_err());
+        assert!(read_commands("GET \"k\"v\n".as_bytes()).await.is_err());
+    }
+
+    #[test]
+    fn pretty_prints_replies_like_redis_cli() {
+        assert_eq!(pretty(&Frame::Null, 0), "(nil)");
+        assert_eq!(
+            pretty(&Frame::Error("ERR unknown command".into()), 0),
+            "(error) ERR unknown command"
+        );
+        assert_eq!(pretty(&Frame::Integer(3), 0), "(integer) 3");
+        assert_eq!(
+            pretty(&Frame::Bulk(Bytes::from_static(b"a\"\x01")), 0),
+            "\"a\\\"\\x01\""
+        );
+        assert_eq!(pretty(&Frame::Array(vec![]), 0), "(empty array)");
+
+        // 嵌套的数组按外层编号的宽度缩进，超过 9 个元素时编号右对齐
+        let inner = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"x")),
+            Frame::Null,
+            Frame::Error("ERR inner".into()),
+        ]);
+        let mut items = vec![inner];
+        items.extend((0..9).map(Frame::Integer));
+        let expected = [
+            " 1) 1) \"x\"",
+            "    2) (nil)",
+            "    3) (error) ERR inner",
+            " 2) (integer) 0",
+            " 3) (integer) 1",
+            " 4) (integer) 2",
+            " 5) (integer) 3",
+            " 6) (integer) 4",
+            " 7) (integer) 5",
+            " 8) (integer) 6",
+            " 9) (integer) 7",
+            "10) (integer) 8",
+        ]
+        .join("\n");
+        assert_eq!(pretty(&Frame::Array(items), 0), expected);
+    }
+}
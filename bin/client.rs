@@ -0,0 +1,23 @@
+use std::env;
+
+use ilearn::{
+    client::{Client, ClientError, Cmd},
+    server::DEFAULT_PORT,
+};
+
+/// 把命令行参数作为一条命令发送给服务端并打印响应：`client SET foo bar`
+#[tokio::main]
+async fn main() -> ilearn::server::Result<()> {
+    let mut args = env::args().skip(1);
+    let Some(name) = args.next() else {
+        return Err("usage: client <command> [arg ...]".into());
+    };
+
+    let mut client = Client::connect(("127.0.0.1", DEFAULT_PORT)).await?;
+    match client.execute(Cmd::new(&name).args(args)).await {
+        Ok(response) => println!("{}", response),
+        Err(ClientError::Server(e)) => println!("error: {}", e),
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
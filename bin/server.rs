@@ -1,56 +1,305 @@
-use std::sync::Arc;
-use std::{collections::HashMap, sync::Mutex};
+use std::{future::Future, io, path::PathBuf};
 
-use bytes::Bytes;
-use mini_redis::{
-    Command::{self, Get, Set},
-    Connection, Frame, Result,
+use clap::Parser;
+use ilearn::{
+    config::{Config, ConfigError},
+    server::{self, Listener},
 };
-use tokio::net::{TcpListener, TcpStream};
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    type DB = Arc<Mutex<HashMap<String, Bytes>>>;
-
-    let listener = TcpListener::bind("127.0.0.1:6379").await?;
-    let db: DB = Arc::new(Mutex::new(HashMap::new()));
-    loop {
-        let (stream, addr) = listener.accept().await?;
-        let _db = Arc::clone(&db);
-        tokio::spawn(async move {
-            process(stream, _db).await;
-        });
+use tokio::{signal, sync::watch};
+
+/// 兼容 Redis 协议的服务端
+///
+/// 配置依次来自默认值、配置文件、ILEARN_ 开头的环境变量和命令行参数，后面的覆盖前面的。
+/// 收到 ctrl_c 或 SIGTERM 时关闭，收到 SIGHUP 时重新读取配置。
+/// 命令行参数的值与配置文件中的写法相同。
+#[derive(Debug, Parser)]
+#[command(name = "server", version)]
+struct Cli {
+    /// TOML 格式的配置文件
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// 监听的地址，以空格分隔多个地址
+    #[arg(long)]
+    bind: Option<String>,
+    /// 监听的端口，默认为 6379
+    #[arg(long)]
+    port: Option<String>,
+    /// 同时监听的 unix socket 路径
+    #[arg(long, value_name = "PATH")]
+    unixsocket: Option<String>,
+    /// yes 或 no，监听非回环地址并且没有密码时只允许本机的客户端执行命令
+    #[arg(long)]
+    protected_mode: Option<String>,
+    /// 允许连接的网段，以空格分隔，如 "10.0.0.0/8 192.168.1.10"
+    #[arg(long, value_name = "CIDRS")]
+    allowlist: Option<String>,
+    /// yes 或 no，TCP 连接先发送 HAProxy PROXY 协议的头部，客户端的地址取自头部
+    #[arg(long)]
+    proxy_protocol: Option<String>,
+    /// 每个数据库的内存上限，可以带 kb、mb、gb 单位
+    #[arg(long, value_name = "BYTES")]
+    maxmemory: Option<String>,
+    /// 最大连接数
+    #[arg(long)]
+    maxclients: Option<String>,
+    /// 空闲连接的超时秒数
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<String>,
+    /// 逻辑数据库的数量
+    #[arg(long)]
+    databases: Option<String>,
+    /// yes 或 no
+    #[arg(long)]
+    appendonly: Option<String>,
+    /// always、everysec 或 no
+    #[arg(long)]
+    appendfsync: Option<String>,
+    /// 存储层的路径，需要 sled 特性
+    #[arg(long, value_name = "PATH")]
+    storage: Option<String>,
+    /// deadline 或 sampling
+    #[arg(long)]
+    expire_mode: Option<String>,
+    /// key 的最大长度
+    #[arg(long, value_name = "BYTES")]
+    max_key_len: Option<String>,
+    /// 字符串值的最大长度
+    #[arg(long, value_name = "BYTES")]
+    max_value_size: Option<String>,
+    /// 容器的容量超过元素个数的多少倍时收缩，0 表示不收缩
+    #[arg(long)]
+    defrag_ratio: Option<String>,
+    /// 启动时加载到 0 号数据库的快照或 CSV 文件
+    #[arg(long, value_name = "FILE")]
+    warm: Option<String>,
+    /// 连接需要先通过 AUTH 认证的密码
+    #[arg(long)]
+    requirepass: Option<String>,
+    /// 作为副本连接的主节点，格式为 "host port"
+    #[arg(long, value_name = "HOST PORT")]
+    replicaof: Option<String>,
+    /// 连接主节点时使用的密码
+    #[arg(long)]
+    masterauth: Option<String>,
+    /// 执行时间超过多少微秒的命令记录到慢查询日志，负数表示不记录
+    #[arg(long, value_name = "MICROSECONDS", allow_hyphen_values = true)]
+    slowlog_log_slower_than: Option<String>,
+    /// 慢查询日志最多保留的条数
+    #[arg(long, value_name = "N")]
+    slowlog_max_len: Option<String>,
+    /// 每个 IP 每秒允许的新连接数，0 表示不限制
+    #[arg(long, value_name = "N")]
+    max_connections_per_sec: Option<String>,
+    /// 每个 IP 每秒允许的命令数，0 表示不限制
+    #[arg(long, value_name = "N")]
+    max_commands_per_sec: Option<String>,
+    /// 每个 IP 允许的突发数量，默认与每秒的速率相同
+    #[arg(long, value_name = "N")]
+    rate_limit_burst: Option<String>,
+    /// PEM 格式的证书，与 --tls-key 一起配置时只接受 TLS 连接，需要 tls 特性
+    #[arg(long, value_name = "FILE")]
+    tls_cert: Option<String>,
+    /// PEM 格式的私钥
+    #[arg(long, value_name = "FILE")]
+    tls_key: Option<String>,
+    /// 日志的最低级别：trace、debug、info、warn 或 error
+    #[arg(long)]
+    loglevel: Option<String>,
+    /// 日志的格式：text、pretty 或 json
+    #[arg(long)]
+    log_format: Option<String>,
+    /// 导出 Prometheus 指标的端口，需要 metrics 特性
+    #[arg(long, value_name = "PORT")]
+    metrics_port: Option<String>,
+    /// 单条命令的最长执行毫秒数，0 表示不限制
+    #[arg(long, value_name = "MILLISECONDS")]
+    command_timeout: Option<String>,
+    /// 关闭时等待连接的最长秒数，0 表示一直等待
+    #[arg(long, value_name = "SECONDS")]
+    shutdown_timeout: Option<String>,
+    /// 运行时的类型：multi-thread 或 current-thread
+    #[arg(long)]
+    runtime: Option<String>,
+    /// 多线程运行时的工作线程数，0 表示与 CPU 核数相同
+    #[arg(long, value_name = "N")]
+    worker_threads: Option<String>,
+    /// yes 或 no，在专门的线程中接受连接
+    #[arg(long)]
+    accept_thread: Option<String>,
+}
+
+impl Cli {
+    /// 命令行中出现的配置项
+    fn overrides(self) -> impl Iterator<Item = (&'static str, String)> {
+        [
+            ("bind", self.bind),
+            ("port", self.port),
+            ("unixsocket", self.unixsocket),
+            ("protected-mode", self.protected_mode),
+            ("allowlist", self.allowlist),
+            ("proxy-protocol", self.proxy_protocol),
+            ("maxmemory", self.maxmemory),
+            ("maxclients", self.maxclients),
+            ("timeout", self.timeout),
+            ("databases", self.databases),
+            ("appendonly", self.appendonly),
+            ("appendfsync", self.appendfsync),
+            ("storage", self.storage),
+            ("expire-mode", self.expire_mode),
+            ("max-key-len", self.max_key_len),
+            ("max-value-size", self.max_value_size),
+            ("defrag-ratio", self.defrag_ratio),
+            ("warm", self.warm),
+            ("requirepass", self.requirepass),
+            ("replicaof", self.replicaof),
+            ("masterauth", self.masterauth),
+            ("slowlog-log-slower-than", self.slowlog_log_slower_than),
+            ("slowlog-max-len", self.slowlog_max_len),
+            ("max-connections-per-sec", self.max_connections_per_sec),
+            ("max-commands-per-sec", self.max_commands_per_sec),
+            ("rate-limit-burst", self.rate_limit_burst),
+            ("tls-cert-file", self.tls_cert),
+            ("tls-key-file", self.tls_key),
+            ("loglevel", self.loglevel),
+            ("log-format", self.log_format),
+            ("metrics-port", self.metrics_port),
+            ("command-timeout", self.command_timeout),
+            ("shutdown-timeout", self.shutdown_timeout),
+            ("runtime", self.runtime),
+            ("worker-threads", self.worker_threads),
+            ("accept-thread", self.accept_thread),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
     }
+}
 
-    async fn process(stream: TcpStream, db: DB) {
-        // `mini-redis` 提供的便利函数，使用返回的 `connection` 可以用于从 socket 中读取数据并解析为数据帧
-        // 根据 stream 生成 Connection 实例，它支持以数据帧读取数据
-        let mut connection = Connection::new(stream);
-
-        // 在一个连接中可以传送多个帧数据，因此需要使用 while let 而不是 if let
-        while let Some(frame) = connection.read_frame().await.unwrap() {
-            println!("GOT: {}", frame);
-
-            let response = match Command::from_frame(frame).unwrap() {
-                Set(cmd) => {
-                    // 值被存储为 `Vec<u8>` 的形式
-                    db.lock()
-                        .unwrap()
-                        .insert(cmd.key().to_string(), cmd.value().clone());
-                    Frame::Simple("OK".to_string())
-                }
-                Get(cmd) => {
-                    // `Frame::Bulk` 期待数据的类型是 `Bytes`，`&Vec<u8>` 可以使用 `into()` 方法转换成 `Bytes` 类型
-                    if let Some(value) = db.lock().unwrap().get(cmd.key()) {
-                        Frame::Bulk(value.clone().into())
-                    } else {
-                        Frame::Null
-                    }
-                }
-                cmd => panic!("unimpement {:?}", cmd),
-            };
+/// 配置的来源，重新加载时按同样的顺序再读一遍
+struct Source {
+    path: Option<PathBuf>,
+    overrides: Vec<(&'static str, String)>,
+}
+
+impl Source {
+    fn load(&self) -> Result<Config, ConfigError> {
+        let mut config = Config::load(self.path.as_deref())?;
+        for (name, value) in &self.overrides {
+            config.set(name, value)?;
+        }
+        Ok(config)
+    }
+}
 
-            connection.write_frame(&response).await.unwrap();
+/// ctrl_c 或者 SIGTERM，systemd 和容器编排系统用 SIGTERM 通知进程退出
+#[cfg(unix)]
+fn shutdown_signal() -> io::Result<impl Future<Output = ()>> {
+    let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())?;
+    Ok(async move {
+        tokio::select! {
+            _ = signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
         }
+    })
+}
+
+#[cfg(not(unix))]
+fn shutdown_signal() -> io::Result<impl Future<Output = ()>> {
+    Ok(async {
+        let _ = signal::ctrl_c().await;
+    })
+}
+
+/// 收到 SIGHUP 时重新加载配置，配置无效时保持原来的配置
+#[cfg(unix)]
+fn reload_on_hangup(source: Source, updates: watch::Sender<Config>) -> io::Result<()> {
+    let mut hangup = signal::unix::signal(signal::unix::SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            match source.load() {
+                Ok(config) => {
+                    updates.send_replace(config);
+                }
+                Err(e) => tracing::error!(error = %e, "Error reloading config"),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn reload_on_hangup(_source: Source, _updates: watch::Sender<Config>) -> io::Result<()> {
+    Ok(())
+}
+
+fn main() -> server::Result<()> {
+    let cli = Cli::parse();
+    let source = Source {
+        path: cli.config.clone(),
+        overrides: cli.overrides().collect(),
+    };
+    let config = source.load()?;
+    server::logging::init(&config)?;
+    server::runtime::build(&config)?.block_on(serve(source, config))
+}
+
+async fn serve(source: Source, config: Config) -> server::Result<()> {
+    let mut listeners = Vec::new();
+    for &addr in &config.bind {
+        listeners.push(Listener::tcp((addr, config.port).into()).await?);
+    }
+    if let Some(path) = &config.unixsocket {
+        listeners.push(Listener::unix(path)?);
+    }
+    let (updates, rx) = watch::channel(config);
+    reload_on_hangup(source, updates)?;
+    server::run_reloadable(listeners, rx, shutdown_signal()?).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_override_the_config_file() {
+        let path = std::env::temp_dir().join(format!("ilearn-flags-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "port = 7000\nmaxmemory = \"1mb\"\nappendonly = false\n",
+        )
+        .unwrap();
+        let cli = Cli::try_parse_from([
+            "server",
+            "--config",
+            path.to_str().unwrap(),
+            "--port",
+            "7001",
+            "--bind",
+            "127.0.0.1 ::1",
+            "--appendonly",
+            "yes",
+        ])
+        .unwrap();
+        let source = Source {
+            path: cli.config.clone(),
+            overrides: cli.overrides().collect(),
+        };
+        let config = source.load().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.port, 7001);
+        assert_eq!(config.bind.len(), 2);
+        assert!(config.appendonly);
+        // 命令行中没有出现的配置项保留配置文件中的值
+        assert_eq!(config.maxmemory, 1 << 20);
+    }
+
+    #[test]
+    fn invalid_flag_values_are_rejected() {
+        let cli = Cli::try_parse_from(["server", "--maxmemory", "lots"]).unwrap();
+        let source = Source {
+            path: None,
+            overrides: cli.overrides().collect(),
+        };
+        assert!(source.load().is_err());
     }
 }
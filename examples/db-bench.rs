@@ -0,0 +1,93 @@
+//! Db 吞吐量对比：单把全局锁 vs 锁分段 vs DashMap
+//!
+//! 先用 `Mutex<HashMap>` 跑一遍相同的负载作为基线，其余结果同时给出相对基线的倍数，
+//! 不同机器上的绝对数值不可比，倍数可以。
+//!
+//! 运行：`cargo run --release --example db-bench --features dashmap [分片数]`
+use std::{
+    collections::HashMap,
+    env,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use ilearn::db::{Backend, Db, Single, Striped};
+
+const THREADS: usize = 8;
+const OPS_PER_THREAD: usize = 200_000;
+const KEYS: usize = 10_000;
+
+/// 每个线程按固定的 key 序列以 1:4 的比例执行 SET/GET，返回每秒操作数
+fn run(set: impl Fn(String, Bytes) + Sync, get: impl Fn(&str) + Sync) -> f64 {
+    let value = Bytes::from_static(b"value");
+    let start = Instant::now();
+
+    thread::scope(|s| {
+        for t in 0..THREADS {
+            let (set, get, value) = (&set, &get, value.clone());
+            s.spawn(move || {
+                for i in 0..OPS_PER_THREAD {
+                    let key = format!("key:{}", (i * 31 + t) % KEYS);
+                    if i % 5 == 0 {
+                        set(key, value.clone());
+                    } else {
+                        get(&key);
+                    }
+                }
+            });
+        }
+    });
+
+    (THREADS * OPS_PER_THREAD) as f64 / start.elapsed().as_secs_f64()
+}
+
+/// 基线：一把 `Mutex` 保护的 HashMap，没有过期时间和内存统计
+fn baseline() -> f64 {
+    let map = Mutex::new(HashMap::new());
+    run(
+        |key, value| {
+            map.lock().unwrap().insert(key, value);
+        },
+        |key| {
+            let _ = map.lock().unwrap().get(key).cloned();
+        },
+    )
+}
+
+fn report<B: Backend>(name: &str, db: Db<B>, baseline: f64) {
+    let ops = run(
+        |key, value| {
+            db.set(key, value, Some(Duration::from_secs(60))).unwrap();
+        },
+        |key| {
+            db.get(key).unwrap();
+        },
+    );
+    println!(
+        "{:<8} shards = {:>3}: {:>12.0} ops/s ({:.2}x)",
+        name,
+        db.shard_count(),
+        ops,
+        ops / baseline
+    );
+}
+
+fn main() {
+    let shards = match env::args().nth(1) {
+        Some(shards) => shards.parse().expect("shard count must be a number"),
+        None => thread::available_parallelism().map_or(1, |n| n.get()) * 4,
+    };
+
+    let baseline = baseline();
+    println!("{:<8} shards = {:>3}: {:>12.0} ops/s", "mutex", 1, baseline);
+    report("single", Db::with_backend(Single::default()), baseline);
+    report("striped", Db::with_backend(Striped::new(shards)), baseline);
+    #[cfg(feature = "dashmap")]
+    report(
+        "dashmap",
+        Db::with_backend(ilearn::db::backend::DashMapBackend::new(shards)),
+        baseline,
+    );
+}
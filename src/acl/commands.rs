@@ -0,0 +1,94 @@
+//! ACL 使用的命令表，记录每个命令所属的分类
+//!
+//! 新增命令时需要加到这里，不在表中的命令只有拥有所有命令权限的用户才能执行。
+
+/// 所有分类，`all` 包含所有命令，不在表中列出
+pub(super) const CATEGORIES: &[&str] = &[
+    "admin",
+    "connection",
+    "dangerous",
+    "keyspace",
+    "pubsub",
+    "read",
+    "stream",
+    "string",
+    "write",
+];
+
+/// 命令名及其所属的分类，命令名为小写
+pub(super) const COMMANDS: &[(&str, &[&str])] = &[
+    ("acl", &["admin", "dangerous"]),
+    ("asking", &["connection"]),
+    ("auth", &["connection"]),
+    ("bgrewriteaof", &["admin", "dangerous"]),
+    ("bgsave", &["admin", "dangerous"]),
+    ("client", &["admin", "connection", "dangerous"]),
+    ("cluster", &["admin"]),
+    ("config", &["admin", "dangerous"]),
+    ("debug", &["admin", "dangerous"]),
+    ("decr", &["write", "string"]),
+    ("decrby", &["write", "string"]),
+    ("del", &["keyspace", "write"]),
+    ("dump", &["keyspace", "read"]),
+    ("get", &["read", "string"]),
+    ("hello", &["connection"]),
+    ("incr", &["write", "string"]),
+    ("incrby", &["write", "string"]),
+    ("info", &["dangerous"]),
+    ("memory", &["read"]),
+    ("monitor", &["admin", "dangerous"]),
+    ("object", &["keyspace", "read"]),
+    ("ping", &["connection"]),
+    ("psubscribe", &["pubsub"]),
+    ("publish", &["pubsub"]),
+    ("punsubscribe", &["pubsub"]),
+    ("quit", &["connection"]),
+    ("rename", &["keyspace", "write"]),
+    ("replconf", &["admin", "dangerous"]),
+    ("replicaof", &["admin", "dangerous"]),
+    ("restore", &["keyspace", "write", "dangerous"]),
+    ("role", &["admin", "dangerous"]),
+    ("save", &["admin", "dangerous"]),
+    ("scan", &["keyspace", "read"]),
+    ("select", &["connection"]),
+    ("set", &["write", "string"]),
+    ("shutdown", &["admin", "dangerous"]),
+    ("slowlog", &["admin", "dangerous"]),
+    ("subscribe", &["pubsub"]),
+    ("sync", &["admin", "dangerous"]),
+    ("unsubscribe", &["pubsub"]),
+    ("xack", &["write", "stream"]),
+    ("xadd", &["write", "stream"]),
+    ("xclaim", &["write", "stream"]),
+    ("xdel", &["write", "stream"]),
+    ("xgroup", &["write", "stream"]),
+    ("xlen", &["read", "stream"]),
+    ("xrange", &["read", "stream"]),
+    ("xreadgroup", &["write", "stream"]),
+    ("xsetid", &["write", "stream"]),
+    ("xtrim", &["write", "stream"]),
+];
+
+/// 表中的命令名，返回 `'static` 的名字以便保存在用户的命令集合中
+pub(super) fn lookup(name: &str) -> Option<&'static str> {
+    COMMANDS
+        .iter()
+        .find(|(command, _)| *command == name)
+        .map(|(command, _)| *command)
+}
+
+/// 分类中的所有命令，分类不存在时返回 None
+pub(super) fn category(name: &str) -> Option<Vec<&'static str>> {
+    if name == "all" {
+        return Some(COMMANDS.iter().map(|(command, _)| *command).collect());
+    }
+    if !CATEGORIES.contains(&name) {
+        return None;
+    }
+    let commands = COMMANDS
+        .iter()
+        .filter(|(_, categories)| categories.contains(&name))
+        .map(|(command, _)| *command)
+        .collect();
+    Some(commands)
+}
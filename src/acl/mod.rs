@@ -0,0 +1,210 @@
+//! 访问控制（ACL）
+//!
+//! 每个连接以某个用户的身份执行命令，服务端在执行命令之前检查用户是否可以执行这个命令、
+//! 访问命令涉及的 key。用户由 ACL SETUSER 创建和修改，规则与 Redis 相同：
+//!
+//! - `on`/`off`：启用或禁用用户，禁用的用户无法认证
+//! - `>password`、`<password`、`#sha256`、`!sha256`、`nopass`、`resetpass`：密码
+//! - `+command`、`-command`、`+@category`、`-@category`、`allcommands`、`nocommands`：命令
+//! - `~pattern`、`allkeys`、`resetkeys`：key 的 glob 模式
+//! - `&pattern`、`allchannels`、`resetchannels`：频道的 glob 模式，SUBSCRIBE 和 PUBLISH 的频道需要匹配其中一个，
+//!   PSUBSCRIBE 的模式需要与其中一个相同
+//! - `reset`：清空所有权限并禁用
+//!
+//! 总是存在一个 default 用户，新连接以它的身份开始，`requirepass` 即它的密码。
+
+use std::{
+    collections::HashMap,
+    sync::{RwLock, RwLockReadGuard},
+};
+
+use thiserror::Error;
+
+mod commands;
+
+mod user;
+pub use user::User;
+
+/// 新连接使用的用户
+pub const DEFAULT_USER: &str = "default";
+
+/// 已知的命令返回 `'static` 的命令名，`name` 为小写
+pub fn command_name(name: &str) -> Option<&'static str> {
+    commands::lookup(name)
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AclError {
+    #[error("ERR Error in ACL SETUSER modifier '{rule}': {reason}")]
+    Rule { rule: String, reason: &'static str },
+    #[error("NOPERM User {user} has no permissions to run the '{command}' command")]
+    Command { user: String, command: String },
+    #[error("NOPERM No permissions to access a key")]
+    Key,
+    #[error("NOPERM No permissions to access a channel")]
+    Channel,
+}
+
+/// 所有用户，由所有连接共享
+#[derive(Debug)]
+pub struct Users {
+    users: RwLock<HashMap<String, User>>,
+}
+
+impl Users {
+    /// 只有 default 用户，`requirepass` 为它的密码，没有时不需要认证
+    pub fn new(requirepass: Option<&str>) -> Users {
+        let mut default = User::superuser();
+        if let Some(password) = requirepass {
+            default
+                .apply_rule(&format!(">{}", password))
+                .expect("valid rule");
+        }
+        Users {
+            users: RwLock::new(HashMap::from([(DEFAULT_USER.to_string(), default)])),
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<String, User>> {
+        self.users.read().unwrap()
+    }
+
+    /// 用户是否存在、已启用并且密码正确
+    pub fn authenticate(&self, username: &str, password: &[u8]) -> bool {
+        self.read()
+            .get(username)
+            .is_some_and(|user| user.is_enabled() && user.check_password(password))
+    }
+
+    /// 用户是否已启用并且不需要密码，新连接据此决定是否已经认证
+    pub fn is_nopass(&self, username: &str) -> bool {
+        self.read()
+            .get(username)
+            .is_some_and(|user| user.is_enabled() && user.is_nopass())
+    }
+
+    /// 修改 `requirepass` 时替换 default 用户的密码，与 Redis 相同，None 表示不需要密码
+    pub fn set_requirepass(&self, requirepass: Option<&str>) {
+        let password = match requirepass {
+            Some(password) => format!(">{}", password),
+            None => "nopass".to_string(),
+        };
+        self.set_user(DEFAULT_USER, &["resetpass".to_string(), password])
+            .expect("valid rules");
+    }
+
+    /// 依次应用规则，任何一条规则无效时用户保持不变，用户不存在时先创建
+    pub fn set_user(&self, username: &str, rules: &[String]) -> Result<(), AclError> {
+        let mut users = self.users.write().unwrap();
+        let mut user = users.get(username).cloned().unwrap_or_default();
+        for rule in rules {
+            user.apply_rule(rule)?;
+        }
+        users.insert(username.to_string(), user);
+        Ok(())
+    }
+
+    pub fn get_user(&self, username: &str) -> Option<User> {
+        self.read().get(username).cloned()
+    }
+
+    /// 按用户名排序的 ACL LIST
+    pub fn list(&self) -> Vec<String> {
+        let users = self.read();
+        let mut names: Vec<&String> = users.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| users[name].describe(name))
+            .collect()
+    }
+
+    /// 检查用户是否可以执行命令并访问其中的 key，`command` 为小写的命令名
+    pub fn check(&self, username: &str, command: &str, keys: &[&str]) -> Result<(), AclError> {
+        let users = self.read();
+        let user = users.get(username).filter(|user| user.can_run(command));
+        let Some(user) = user else {
+            return Err(AclError::Command {
+                user: username.to_string(),
+                command: command.to_string(),
+            });
+        };
+        if !keys.iter().all(|key| user.can_access_key(key)) {
+            return Err(AclError::Key);
+        }
+        Ok(())
+    }
+
+    /// 检查用户是否可以访问频道，供 SUBSCRIBE 和 PUBLISH 使用
+    pub fn check_channel(&self, username: &str, channel: &str) -> Result<(), AclError> {
+        self.check_user(username, |user| user.can_access_channel(channel))
+    }
+
+    /// 检查用户是否可以订阅频道的模式，供 PSUBSCRIBE 使用，见 [`User::can_access_channel_pattern`]
+    pub fn check_channel_pattern(&self, username: &str, pattern: &str) -> Result<(), AclError> {
+        self.check_user(username, |user| user.can_access_channel_pattern(pattern))
+    }
+
+    fn check_user(
+        &self,
+        username: &str,
+        allowed: impl FnOnce(&User) -> bool,
+    ) -> Result<(), AclError> {
+        if self.read().get(username).is_some_and(allowed) {
+            Ok(())
+        } else {
+            Err(AclError::Channel)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(rules: &str) -> Vec<String> {
+        rules.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn users_are_restricted_by_rules() {
+        let users = Users::new(Some("secret"));
+        assert!(!users.is_nopass(DEFAULT_USER));
+        assert!(users.authenticate(DEFAULT_USER, b"secret"));
+
+        users
+            .set_user("alice", &rules("on >pw ~cache:* &news.* +@read -scan +set"))
+            .unwrap();
+        assert!(users.authenticate("alice", b"pw"));
+        assert!(!users.authenticate("alice", b"wrong"));
+        assert!(users.check("alice", "get", &["cache:a"]).is_ok());
+        assert!(users.check("alice", "set", &["cache:a"]).is_ok());
+        assert_eq!(users.check("alice", "get", &["other"]), Err(AclError::Key));
+        assert!(matches!(
+            users.check("alice", "scan", &[]),
+            Err(AclError::Command { .. })
+        ));
+        assert!(users.check_channel("alice", "news.tech").is_ok());
+        assert_eq!(
+            users.check_channel("alice", "sports"),
+            Err(AclError::Channel)
+        );
+        assert!(users.check_channel_pattern("alice", "news.*").is_ok());
+        assert_eq!(
+            users.check_channel_pattern("alice", "news.tech.*"),
+            Err(AclError::Channel)
+        );
+        assert!(users.check_channel_pattern(DEFAULT_USER, "*").is_ok());
+
+        // 无效的规则不会修改用户
+        let err = users.set_user("alice", &rules("off +nosuchcommand"));
+        assert!(matches!(err, Err(AclError::Rule { .. })));
+        assert!(users.get_user("alice").unwrap().is_enabled());
+
+        let list = users.list();
+        assert!(list[0].starts_with("user alice on #"));
+        assert!(list[0]
+            .ends_with("~cache:* &news.* -@all +dump +get +memory +object +set +xlen +xrange"));
+        assert!(list[1].ends_with("~* &* +@all"));
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::BTreeSet;
+
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use super::{commands, AclError};
+use crate::cmd::glob_match;
+
+/// 一个 ACL 用户
+///
+/// 新建的用户没有任何权限，需要通过规则逐项授予。密码只保存 SHA-256，
+/// ACL LIST 和 ACL GETUSER 中以十六进制显示。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct User {
+    enabled: bool,
+    /// 接受任意密码
+    nopass: bool,
+    passwords: BTreeSet<String>,
+    /// 允许执行的命令
+    commands: BTreeSet<&'static str>,
+    /// 允许访问的 key 的 glob 模式
+    keys: Vec<String>,
+    /// 允许访问的频道的 glob 模式
+    channels: Vec<String>,
+}
+
+impl User {
+    /// 拥有所有权限、不需要密码的用户，即没有配置 requirepass 时的 default 用户
+    pub(super) fn superuser() -> User {
+        let mut user = User::default();
+        for rule in ["on", "nopass", "allkeys", "allchannels", "allcommands"] {
+            user.apply_rule(rule).expect("valid rule");
+        }
+        user
+    }
+
+    /// 按 ACL SETUSER 的规则修改用户
+    pub(super) fn apply_rule(&mut self, rule: &str) -> Result<(), AclError> {
+        let invalid = |reason| AclError::Rule {
+            rule: rule.to_string(),
+            reason,
+        };
+        let (prefix, rest) = rule.split_at(rule.chars().next().map_or(0, char::len_utf8));
+        match (prefix, rest) {
+            (">", password) => {
+                self.passwords.insert(hash(password.as_bytes()));
+                self.nopass = false;
+            }
+            ("<", password) => {
+                if !self.passwords.remove(&hash(password.as_bytes())) {
+                    return Err(invalid("no such password"));
+                }
+            }
+            ("#", digest) => {
+                if !is_digest(digest) {
+                    return Err(invalid("invalid password hash"));
+                }
+                self.passwords.insert(digest.to_string());
+                self.nopass = false;
+            }
+            ("!", digest) => {
+                if !self.passwords.remove(digest) {
+                    return Err(invalid("no such password"));
+                }
+            }
+            ("~", pattern) => push_unique(&mut self.keys, pattern),
+            ("&", pattern) => push_unique(&mut self.channels, pattern),
+            ("+" | "-", name) => {
+                let names = match name.strip_prefix('@') {
+                    Some(category) => commands::category(&category.to_lowercase()),
+                    None => commands::lookup(&name.to_lowercase()).map(|command| vec![command]),
+                };
+                let names = names.ok_or_else(|| invalid("unknown command or category"))?;
+                for name in names {
+                    if prefix == "+" {
+                        self.commands.insert(name);
+                    } else {
+                        self.commands.remove(name);
+                    }
+                }
+            }
+            _ => match &rule.to_lowercase()[..] {
+                "on" => self.enabled = true,
+                "off" => self.enabled = false,
+                "nopass" => {
+                    self.nopass = true;
+                    self.passwords.clear();
+                }
+                "resetpass" => {
+                    self.nopass = false;
+                    self.passwords.clear();
+                }
+                "allkeys" => self.keys = vec!["*".into()],
+                "resetkeys" => self.keys.clear(),
+                "allchannels" => self.channels = vec!["*".into()],
+                "resetchannels" => self.channels.clear(),
+                "allcommands" => return self.apply_rule("+@all"),
+                "nocommands" => return self.apply_rule("-@all"),
+                "reset" => *self = User::default(),
+                _ => return Err(invalid("syntax error")),
+            },
+        }
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn is_nopass(&self) -> bool {
+        self.nopass
+    }
+
+    /// 密码是否正确，与所有密码都做常数时间比较
+    pub fn check_password(&self, password: &[u8]) -> bool {
+        let digest = hash(password);
+        let matched = self.passwords.iter().fold(0u8, |matched, expected| {
+            matched | expected.as_bytes().ct_eq(digest.as_bytes()).unwrap_u8()
+        });
+        self.nopass || matched == 1
+    }
+
+    /// `command` 为小写的命令名
+    pub fn can_run(&self, command: &str) -> bool {
+        self.commands.contains(command)
+    }
+
+    pub fn can_access_key(&self, key: &str) -> bool {
+        matches_any(&self.keys, key)
+    }
+
+    pub fn can_access_channel(&self, channel: &str) -> bool {
+        matches_any(&self.channels, channel)
+    }
+
+    /// PSUBSCRIBE 的模式需要与某个允许的模式完全相同，或者允许访问所有频道
+    ///
+    /// 与 Redis 相同，不判断一个模式能匹配的频道是否都被允许的模式覆盖。
+    pub fn can_access_channel_pattern(&self, pattern: &str) -> bool {
+        self.channels
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == pattern)
+    }
+
+    /// ACL GETUSER 中的 flags
+    pub fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+
+    /// 密码的 SHA-256
+    pub fn passwords(&self) -> impl Iterator<Item = &str> {
+        self.passwords.iter().map(String::as_str)
+    }
+
+    /// 命令权限的规则，拥有所有命令时为 `+@all`，否则列出每个命令
+    pub fn describe_commands(&self) -> String {
+        if self.commands.len() == commands::COMMANDS.len() {
+            return "+@all".into();
+        }
+        let mut rules = vec!["-@all".to_string()];
+        rules.extend(self.commands.iter().map(|command| format!("+{}", command)));
+        rules.join(" ")
+    }
+
+    pub fn describe_keys(&self) -> String {
+        describe_patterns('~', &self.keys)
+    }
+
+    pub fn describe_channels(&self) -> String {
+        describe_patterns('&', &self.channels)
+    }
+
+    /// ACL LIST 中的一行，可以作为 ACL SETUSER 的规则重新创建同样的用户
+    pub fn describe(&self, name: &str) -> String {
+        let mut rules = vec![format!("user {}", name)];
+        rules.extend(self.flags().into_iter().map(String::from));
+        rules.extend(self.passwords().map(|digest| format!("#{}", digest)));
+        if !self.keys.is_empty() {
+            rules.push(self.describe_keys());
+        }
+        if self.channels.is_empty() {
+            rules.push("resetchannels".into());
+        } else {
+            rules.push(self.describe_channels());
+        }
+        rules.push(self.describe_commands());
+        rules.join(" ")
+    }
+}
+
+/// 十六进制的 SHA-256
+fn hash(password: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(password))
+}
+
+fn is_digest(digest: &str) -> bool {
+    digest.len() == 64
+        && digest
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn push_unique(patterns: &mut Vec<String>, pattern: &str) {
+    if !patterns.iter().any(|p| p == pattern) {
+        patterns.push(pattern.to_string());
+    }
+}
+
+fn matches_any(patterns: &[String], text: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| glob_match(pattern.as_bytes(), text.as_bytes()))
+}
+
+fn describe_patterns(prefix: char, patterns: &[String]) -> String {
+    patterns
+        .iter()
+        .map(|pattern| format!("{}{}", prefix, pattern))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+use tokio::{
+    net::ToSocketAddrs,
+    runtime::{self, Runtime},
+};
+
+use super::{Client, Cmd, Event, FromValue, Result, Subscriber, ToArg};
+use crate::frame::Frame;
+
+/// 同步代码中使用的客户端，每个方法阻塞到收到回复
+///
+/// 持有一个单线程的 tokio 运行时，每次调用在这个运行时上执行 [`Client`] 对应的方法。
+/// 不能在异步任务中调用，否则 tokio 会因为在运行时中再启动运行时而 panic。
+#[derive(Debug)]
+pub struct BlockingClient {
+    runtime: Runtime,
+    client: Client,
+}
+
+impl BlockingClient {
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<BlockingClient> {
+        let runtime = new_runtime()?;
+        let client = runtime.block_on(Client::connect(addr))?;
+        Ok(BlockingClient { runtime, client })
+    }
+
+    /// 见 [`Client::connect_url`]
+    pub fn connect_url(url: &str) -> Result<BlockingClient> {
+        let runtime = new_runtime()?;
+        let client = runtime.block_on(Client::connect_url(url))?;
+        Ok(BlockingClient { runtime, client })
+    }
+
+    /// 内部的异步客户端，用于修改重新连接、超时等设置
+    pub fn client_mut(&mut self) -> &mut Client {
+        &mut self.client
+    }
+
+    /// 见 [`Client::close`]
+    pub fn close(self) -> Result<()> {
+        self.runtime.block_on(self.client.close())
+    }
+
+    pub fn execute(&mut self, cmd: Cmd) -> Result<Frame> {
+        self.runtime.block_on(self.client.execute(cmd))
+    }
+
+    pub fn query<T: FromValue>(&mut self, cmd: Cmd) -> Result<T> {
+        self.runtime.block_on(self.client.query(cmd))
+    }
+
+    pub fn ping(&mut self) -> Result<()> {
+        self.runtime.block_on(self.client.ping())
+    }
+
+    pub fn get<T: FromValue>(&mut self, key: &str) -> Result<T> {
+        self.runtime.block_on(self.client.get(key))
+    }
+
+    pub fn set(&mut self, key: &str, value: impl ToArg) -> Result<()> {
+        self.runtime.block_on(self.client.set(key, value))
+    }
+
+    pub fn set_expires(&mut self, key: &str, value: impl ToArg, expire: Duration) -> Result<()> {
+        self.runtime
+            .block_on(self.client.set_expires(key, value, expire))
+    }
+
+    pub fn del(&mut self, keys: &[&str]) -> Result<u64> {
+        self.runtime.block_on(self.client.del(keys))
+    }
+
+    pub fn incr(&mut self, key: &str) -> Result<i64> {
+        self.runtime.block_on(self.client.incr(key))
+    }
+
+    pub fn incr_by(&mut self, key: &str, delta: i64) -> Result<i64> {
+        self.runtime.block_on(self.client.incr_by(key, delta))
+    }
+
+    /// 见 [`Client::subscribe`]，运行时转移给返回的 [`BlockingSubscriber`]
+    pub fn subscribe(self, channels: &[&str]) -> Result<BlockingSubscriber> {
+        let subscriber = self.runtime.block_on(self.client.subscribe(channels))?;
+        Ok(BlockingSubscriber {
+            runtime: self.runtime,
+            subscriber,
+        })
+    }
+}
+
+/// 同步代码中使用的 [`Subscriber`]
+///
+/// 后台任务运行在单线程的运行时上，只有在调用这里的方法时才从连接读取，
+/// 没有调用时收到的消息留在 socket 的缓冲区中。
+#[derive(Debug)]
+pub struct BlockingSubscriber {
+    runtime: Runtime,
+    subscriber: Subscriber,
+}
+
+impl BlockingSubscriber {
+    pub fn subscribe(&self, channels: &[&str]) -> Result<()> {
+        self.runtime.block_on(self.subscriber.subscribe(channels))
+    }
+
+    pub fn unsubscribe(&self, channels: &[&str]) -> Result<()> {
+        self.runtime.block_on(self.subscriber.unsubscribe(channels))
+    }
+
+    pub fn psubscribe(&self, patterns: &[&str]) -> Result<()> {
+        self.runtime.block_on(self.subscriber.psubscribe(patterns))
+    }
+
+    pub fn punsubscribe(&self, patterns: &[&str]) -> Result<()> {
+        self.runtime
+            .block_on(self.subscriber.punsubscribe(patterns))
+    }
+
+    /// 阻塞到收到下一条消息或者重新连接的通知，无法重新连接时返回错误，之后返回 None
+    pub fn next_event(&mut self) -> Option<Result<Event>> {
+        self.runtime.block_on(self.subscriber.next_event())
+    }
+}
+
+impl Iterator for BlockingSubscriber {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Result<Event>> {
+        self.next_event()
+    }
+}
+
+fn new_runtime() -> Result<Runtime> {
+    Ok(runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{tests::start_server, ClientError};
+
+    #[test]
+    fn blocks_until_the_reply_arrives() {
+        // 服务端运行在另一个运行时中，测试线程本身不在异步上下文里
+        let server = Runtime::new().unwrap();
+        let addr = server.block_on(start_server());
+
+        let mut client = BlockingClient::connect(addr).unwrap();
+        client.ping().unwrap();
+        client.set("a", 41).unwrap();
+        assert_eq!(client.incr("a").unwrap(), 42);
+        assert_eq!(client.get::<Option<i64>>("b").unwrap(), None);
+        assert!(matches!(
+            client.incr_by("a", i64::MAX),
+            Err(ClientError::Server(_))
+        ));
+        assert_eq!(client.del(&["a"]).unwrap(), 1);
+        client
+            .client_mut()
+            .set_timeout(Some(Duration::from_secs(1)));
+        let len = Cmd::new("XLEN").arg("stream");
+        assert_eq!(client.query::<u64>(len).unwrap(), 0);
+    }
+}
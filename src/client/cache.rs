@@ -0,0 +1,310 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use tokio::sync::{mpsc, oneshot};
+use tracing::debug;
+
+use super::{
+    expect_ok, multiplexed::duplicate, ok, read_reply, round_trip, Client, ClientError, Cmd,
+    FromValue, Result, ToArg,
+};
+use crate::frame::Frame;
+
+/// 本地缓存的命中情况，见 [`CachedClient::stats`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+#[derive(Debug)]
+struct Cache {
+    entries: Mutex<HashMap<String, Frame>>,
+    max_entries: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Cache {
+    fn get(&self, key: &str) -> Option<Frame> {
+        let frame = self.entries.lock().unwrap().get(key).cloned();
+        let counter = if frame.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        frame
+    }
+
+    /// 超过上限时淘汰任意一项，服务端仍然会发送它的失效消息，收到后忽略
+    fn insert(&self, key: String, frame: Frame) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            let Some(evicted) = entries.keys().next().cloned() else {
+                return;
+            };
+            entries.remove(&evicted);
+        }
+        entries.insert(key, frame);
+    }
+
+    /// 失效消息的 key 为数组，Null 表示服务端无法确定哪些 key 被修改，清空缓存
+    fn invalidate(&self, keys: &Frame) {
+        let mut entries = self.entries.lock().unwrap();
+        match keys {
+            Frame::Array(keys) => {
+                for key in keys {
+                    entries.remove(&key.to_string());
+                }
+            }
+            _ => entries.clear(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Request {
+    cmd: Cmd,
+    /// GET 的 key，回复在后台任务中放入缓存
+    cache_key: Option<String>,
+    reply: oneshot::Sender<Result<Frame>>,
+}
+
+/// 带本地缓存的客户端，见 [`Client::cached`]
+///
+/// 连接切换到 RESP3 并开启 CLIENT TRACKING，服务端记住这个连接读取过的 key，修改时发送失效消息。
+/// [`CachedClient::get`] 先查本地缓存，没有时才发送命令，热点 key 的读取不需要往返。
+///
+/// 连接由后台任务持有，命令按顺序发送，失效消息和回复在同一个连接上按服务端发送的顺序处理：
+/// GET 的回复在任务中放入缓存，之后收到的失效消息一定会移除它，不会留下过期的值。
+/// 连接断开时清空缓存，下一条命令之前重新连接并重新开启 tracking。clone 得到的句柄共用缓存和连接。
+#[derive(Debug, Clone)]
+pub struct CachedClient {
+    requests: mpsc::UnboundedSender<Request>,
+    cache: Arc<Cache>,
+}
+
+impl CachedClient {
+    pub(super) async fn new(mut client: Client, max_entries: usize) -> Result<CachedClient> {
+        enable_tracking(&mut client).await?;
+        let cache = Arc::new(Cache {
+            entries: Mutex::default(),
+            max_entries,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        });
+        let (requests, rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(client, rx, Arc::clone(&cache)));
+        Ok(CachedClient { requests, cache })
+    }
+
+    /// 发送一条命令，不经过缓存，错误回复转换为 [`ClientError::Server`]
+    pub async fn execute(&self, cmd: Cmd) -> Result<Frame> {
+        self.request(cmd, None).await
+    }
+
+    async fn request(&self, cmd: Cmd, cache_key: Option<String>) -> Result<Frame> {
+        let (reply, rx) = oneshot::channel();
+        let request = Request {
+            cmd,
+            cache_key,
+            reply,
+        };
+        self.requests
+            .send(request)
+            .map_err(|_| ClientError::ConnectionClosed)?;
+        match rx.await.map_err(|_| ClientError::ConnectionClosed)?? {
+            Frame::Error(e) => Err(ClientError::Server(e)),
+            frame => Ok(frame),
+        }
+    }
+
+    pub async fn query<T: FromValue>(&self, cmd: Cmd) -> Result<T> {
+        T::from_value(self.execute(cmd).await?)
+    }
+
+    /// 先查本地缓存，没有时发送 GET 并缓存回复，不存在的 key 也会缓存
+    pub async fn get<T: FromValue>(&self, key: &str) -> Result<T> {
+        if let Some(frame) = self.cache.get(key) {
+            return T::from_value(frame);
+        }
+        let cmd = Cmd::new("GET").arg(key);
+        T::from_value(self.request(cmd, Some(key.to_string())).await?)
+    }
+
+    pub async fn set(&self, key: &str, value: impl ToArg) -> Result<()> {
+        ok(self.execute(Cmd::new("SET").arg(key).arg(value)).await?)
+    }
+
+    pub async fn del(&self, keys: &[&str]) -> Result<u64> {
+        self.query(Cmd::new("DEL").args(keys.iter().copied())).await
+    }
+
+    pub async fn incr(&self, key: &str) -> Result<i64> {
+        self.query(Cmd::new("INCR").arg(key)).await
+    }
+
+    pub async fn incr_by(&self, key: &str, delta: i64) -> Result<i64> {
+        self.query(Cmd::new("INCRBY").arg(key).arg(delta)).await
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.cache.hits.load(Ordering::Relaxed),
+            misses: self.cache.misses.load(Ordering::Relaxed),
+            entries: self.cache.entries.lock().unwrap().len(),
+        }
+    }
+}
+
+/// HELLO 3 之后开启 CLIENT TRACKING，连接断开时重新连接
+async fn enable_tracking(client: &mut Client) -> Result<()> {
+    let connection = client.connection().await?;
+    let hello = Cmd::new("HELLO").arg(3);
+    if let Frame::Error(e) = round_trip(connection, &hello.to_frame()).await? {
+        return Err(ClientError::Server(e));
+    }
+    let tracking = Cmd::new("CLIENT").arg("TRACKING").arg("ON");
+    expect_ok(round_trip(connection, &tracking.to_frame()).await?)
+}
+
+/// 后台任务：连接断开后等到下一条命令再重新连接，所有句柄都释放后退出
+async fn run(
+    mut client: Client,
+    mut requests: mpsc::UnboundedReceiver<Request>,
+    cache: Arc<Cache>,
+) {
+    let mut pending = VecDeque::new();
+    while let Some(request) = requests.recv().await {
+        if client.connection.is_none() {
+            if let Err(e) = enable_tracking(&mut client).await {
+                client.connection = None;
+                let _ = request.reply.send(Err(e));
+                continue;
+            }
+        }
+        pending.push_back(request);
+        let e = match serve(&mut client, &mut requests, &cache, &mut pending).await {
+            Ok(()) => return,
+            Err(e) => e,
+        };
+        debug!(error = %e, "Tracking connection lost, clearing the cache");
+        client.connection = None;
+        cache.invalidate(&Frame::Null);
+        for request in pending.drain(..) {
+            let _ = request.reply.send(Err(duplicate(&e)));
+        }
+    }
+}
+
+/// 发送 `pending` 中的命令以及之后的命令，读取回复和失效消息，连接出错时返回错误
+async fn serve(
+    client: &mut Client,
+    requests: &mut mpsc::UnboundedReceiver<Request>,
+    cache: &Cache,
+    pending: &mut VecDeque<Request>,
+) -> Result<()> {
+    let connection = client.connection().await?;
+    for request in pending.iter() {
+        connection.queue_frame(&request.cmd.to_frame());
+    }
+    connection.flush().await?;
+    loop {
+        tokio::select! {
+            request = requests.recv() => {
+                let Some(request) = request else {
+                    return Ok(());
+                };
+                connection.write_frame(&request.cmd.to_frame()).await?;
+                pending.push_back(request);
+            }
+            frame = read_reply(connection) => match frame? {
+                Frame::Push(parts) => match &parts[..] {
+                    [Frame::Bulk(kind), keys] if &kind[..] == b"invalidate" => {
+                        cache.invalidate(keys)
+                    }
+                    _ => debug!(push = ?parts, "Ignoring an unknown push"),
+                },
+                frame => {
+                    let Some(request) = pending.pop_front() else {
+                        return Err(ClientError::UnexpectedReply(frame));
+                    };
+                    if let Some(key) = request.cache_key {
+                        if !matches!(frame, Frame::Error(_)) {
+                            cache.insert(key, frame.clone());
+                        }
+                    }
+                    let _ = request.reply.send(Ok(frame));
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::Future, time::Duration};
+
+    use tokio::time;
+
+    use super::*;
+    use crate::client::tests::start_server;
+
+    /// 等待失效消息到达，最多等 1 秒
+    async fn eventually<F: Future<Output = bool>>(mut check: impl FnMut() -> F) {
+        for _ in 0..100 {
+            if check().await {
+                return;
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("condition not reached");
+    }
+
+    #[tokio::test]
+    async fn reads_are_served_locally_until_invalidated() {
+        let addr = start_server().await;
+        let mut other = Client::connect(addr).await.unwrap();
+        let client = Client::connect(addr).await.unwrap();
+        // 检查条件的闭包需要多次使用同一个引用
+        let cached = &client.cached(100).await.unwrap();
+
+        other.set("a", 1).await.unwrap();
+        assert_eq!(cached.get::<i64>("a").await.unwrap(), 1);
+        assert_eq!(cached.get::<i64>("a").await.unwrap(), 1);
+        assert_eq!(cached.get::<Option<i64>>("b").await.unwrap(), None);
+        let stats = cached.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 2, 2));
+
+        // 其他连接和自己的修改都会让缓存失效
+        other.set("a", 2).await.unwrap();
+        eventually(|| async move { cached.stats().entries == 1 }).await;
+        assert_eq!(cached.get::<i64>("a").await.unwrap(), 2);
+        cached.set("b", 3).await.unwrap();
+        eventually(|| async move { cached.get::<Option<i64>>("b").await.unwrap() == Some(3) })
+            .await;
+
+        // 连接断开后清空缓存，重新连接时重新开启 tracking
+        let id: u64 = cached.query(Cmd::new("CLIENT").arg("ID")).await.unwrap();
+        let kill = Cmd::new("CLIENT").arg("KILL").arg("ID").arg(id);
+        other.execute(kill).await.unwrap();
+        eventually(|| async move { cached.stats().entries == 0 }).await;
+        assert_eq!(cached.get::<i64>("a").await.unwrap(), 2);
+        other.incr("a").await.unwrap();
+        eventually(|| async move { cached.get::<i64>("a").await.unwrap() == 3 }).await;
+
+        // 没有切换到 RESP3 时不能开启 tracking
+        let err = other
+            .execute(Cmd::new("CLIENT").arg("TRACKING").arg("ON"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ClientError::Server(e) if e.contains("RESP3")));
+    }
+}
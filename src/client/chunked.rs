@@ -0,0 +1,201 @@
+use futures::future;
+
+use super::{Client, Cmd, FromValue, Pool, Result, ToArg};
+
+/// 每 `chunk` 个 key 一条 MGET，`chunk` 为 0 时按 1 处理
+fn mget_cmds(keys: &[&str], chunk: usize) -> Vec<Cmd> {
+    keys.chunks(chunk.max(1))
+        .map(|keys| Cmd::new("MGET").args(keys.iter().copied()))
+        .collect()
+}
+
+/// 每 `chunk` 对 key 和 value 一条 MSET，`chunk` 为 0 时按 1 处理
+fn mset_cmds<K: ToArg, V: ToArg>(
+    pairs: impl IntoIterator<Item = (K, V)>,
+    chunk: usize,
+) -> Vec<Cmd> {
+    let pairs: Vec<_> = pairs
+        .into_iter()
+        .map(|(key, value)| (key.to_arg(), value.to_arg()))
+        .collect();
+    pairs
+        .chunks(chunk.max(1))
+        .map(|pairs| {
+            pairs.iter().fold(Cmd::new("MSET"), |cmd, (key, value)| {
+                cmd.arg(key).arg(value)
+            })
+        })
+        .collect()
+}
+
+/// 按命令的顺序拼接每条 MGET 的回复
+fn concat<T>(replies: Vec<Vec<T>>) -> Vec<T> {
+    replies.into_iter().flatten().collect()
+}
+
+impl Client {
+    /// 把 `keys` 分成每批最多 `chunk` 个，每批一条 MGET，按 `keys` 的顺序返回值
+    ///
+    /// 所有批次在同一个 pipeline 中发送，只等待一次往返，同时避免一条命令的帧过大。
+    /// key 不存在时回复 Null，用 `Option<T>` 接收。
+    pub async fn mget_chunked<T: FromValue>(
+        &mut self,
+        keys: &[&str],
+        chunk: usize,
+    ) -> Result<Vec<T>> {
+        let cmds = mget_cmds(keys, chunk);
+        if cmds.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut pipeline = self.pipeline();
+        for cmd in cmds {
+            pipeline = pipeline.cmd(cmd);
+        }
+        Ok(concat(pipeline.execute().await?))
+    }
+
+    /// 把 `pairs` 分成每批最多 `chunk` 对，每批一条 MSET
+    ///
+    /// 每批各自原子执行，整体不是原子的：出错时之前的批次已经写入。
+    pub async fn mset_chunked<K: ToArg, V: ToArg>(
+        &mut self,
+        pairs: impl IntoIterator<Item = (K, V)>,
+        chunk: usize,
+    ) -> Result<()> {
+        let cmds = mset_cmds(pairs, chunk);
+        if cmds.is_empty() {
+            return Ok(());
+        }
+        let mut pipeline = self.pipeline();
+        for cmd in cmds {
+            pipeline = pipeline.cmd(cmd);
+        }
+        pipeline.execute::<Vec<()>>().await.map(drop)
+    }
+}
+
+impl Pool {
+    /// 与 [`Client::mget_chunked`] 相同，但每批从连接池取出一个连接并发执行
+    ///
+    /// 同时使用的连接数受 [`PoolOptions::max_size`](super::PoolOptions::max_size) 限制。
+    pub async fn mget_chunked<T: FromValue>(&self, keys: &[&str], chunk: usize) -> Result<Vec<T>> {
+        let batches = mget_cmds(keys, chunk).into_iter().map(|cmd| async move {
+            let mut client = self.get().await?;
+            client.query::<Vec<T>>(cmd).await
+        });
+        Ok(concat(future::try_join_all(batches).await?))
+    }
+
+    /// 与 [`Client::mset_chunked`] 相同，但每批从连接池取出一个连接并发执行
+    ///
+    /// 批次之间没有顺序，同一个 key 出现在多个批次中时不确定哪个值最后写入。
+    pub async fn mset_chunked<K: ToArg, V: ToArg>(
+        &self,
+        pairs: impl IntoIterator<Item = (K, V)>,
+        chunk: usize,
+    ) -> Result<()> {
+        let batches = mset_cmds(pairs, chunk).into_iter().map(|cmd| async move {
+            let mut client = self.get().await?;
+            client.query::<()>(cmd).await
+        });
+        future::try_join_all(batches).await.map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use bytes::Bytes;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+    use crate::{client::PoolOptions, connection::Connection, frame::Frame};
+
+    #[derive(Debug, Default)]
+    struct Store {
+        values: HashMap<String, Bytes>,
+        /// 每条命令的参数个数，不包括命令名
+        sizes: Vec<usize>,
+    }
+
+    /// 服务端不支持 MGET/MSET，用共享的 HashMap 模拟
+    async fn serve(mut connection: Connection<TcpStream>, store: Arc<Mutex<Store>>) {
+        while let Ok(Some(Frame::Array(parts))) = connection.read_frame().await {
+            let args: Vec<String> = parts.iter().map(|part| part.to_string()).collect();
+            let reply = {
+                let mut store = store.lock().unwrap();
+                match args[0].as_str() {
+                    "PING" => Frame::Simple("PONG".into()),
+                    "MGET" => {
+                        store.sizes.push(args.len() - 1);
+                        Frame::Array(
+                            args[1..]
+                                .iter()
+                                .map(|key| {
+                                    store
+                                        .values
+                                        .get(key)
+                                        .cloned()
+                                        .map_or(Frame::Null, Frame::Bulk)
+                                })
+                                .collect(),
+                        )
+                    }
+                    "MSET" => {
+                        store.sizes.push(args.len() - 1);
+                        for pair in args[1..].chunks(2) {
+                            store.values.insert(pair[0].clone(), pair[1].clone().into());
+                        }
+                        Frame::Simple("OK".into())
+                    }
+                    _ => Frame::Error("ERR unknown command".into()),
+                }
+            };
+            connection.write_frame(&reply).await.unwrap();
+        }
+    }
+
+    async fn start(store: &Arc<Mutex<Store>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let store = Arc::clone(store);
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(serve(Connection::new(socket), Arc::clone(&store)));
+            }
+        });
+        addr.to_string()
+    }
+
+    #[tokio::test]
+    async fn batches_are_bounded_and_reassembled_in_order() {
+        let store = Arc::default();
+        let addr = start(&store).await;
+        let keys: Vec<String> = (0..25).map(|i| format!("k{}", i)).collect();
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+
+        let mut client = Client::connect(&addr).await.unwrap();
+        client
+            .mset_chunked(keys.iter().copied().zip(0i64..).skip(1), 10)
+            .await
+            .unwrap();
+        let values: Vec<Option<i64>> = client.mget_chunked(&keys, 10).await.unwrap();
+        assert_eq!(values[0], None);
+        assert_eq!(values[1..], (1..25).map(Some).collect::<Vec<_>>());
+        // 24 对 key 和 value 分成 10、10、4 对，25 个 key 分成 10、10、5 个
+        assert_eq!(store.lock().unwrap().sizes, [20, 20, 8, 10, 10, 5]);
+
+        let pool = Pool::new(addr, PoolOptions::default()).await.unwrap();
+        pool.mset_chunked(keys.iter().map(|key| (*key, "x")), 7)
+            .await
+            .unwrap();
+        let values: Vec<String> = pool.mget_chunked(&keys, 3).await.unwrap();
+        assert_eq!(values, vec!["x"; 25]);
+        let empty: Vec<String> = pool.mget_chunked(&[], 3).await.unwrap();
+        assert!(empty.is_empty());
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use tracing::debug;
+
+use super::{ok, ClientError, Cmd, FromValue, Pool, PoolOptions, Result, ToArg};
+use crate::{
+    cluster::{key_slot, Redirect, SlotRange, Topology},
+    frame::Frame,
+};
+
+/// 一条命令最多跟随的重定向次数
+const MAX_REDIRECTS: usize = 5;
+
+/// 集群客户端，按 key 所在的 slot 把命令发送给负责的节点
+///
+/// 创建时通过 CLUSTER SLOTS 读取 slot 表，每个节点使用一个 [`Pool`]。收到 MOVED 时重新读取
+/// slot 表，并把这条命令发送到回复中的节点；收到 ASK 时只把这一条命令带上 ASKING
+/// 发送到目标节点，不修改 slot 表。没有 key 的命令发送给任意一个节点。
+#[derive(Debug)]
+pub struct ClusterClient {
+    /// 创建时指定的节点，slot 表中的节点都不可用时从这里重新读取
+    seeds: Vec<String>,
+    topology: RwLock<Arc<Topology>>,
+    pools: Mutex<HashMap<String, Pool>>,
+    options: PoolOptions,
+}
+
+impl ClusterClient {
+    /// `seeds` 为集群中任意几个节点的地址，`host:port`
+    pub async fn connect(seeds: &[&str], options: PoolOptions) -> Result<ClusterClient> {
+        let client = ClusterClient {
+            seeds: seeds.iter().map(|seed| seed.to_string()).collect(),
+            topology: RwLock::default(),
+            pools: Mutex::default(),
+            options,
+        };
+        client.refresh().await?;
+        Ok(client)
+    }
+
+    /// 依次向已知的节点读取 slot 表，直到成功
+    pub async fn refresh(&self) -> Result<()> {
+        let topology = self.topology();
+        let known = topology.nodes().into_iter().map(String::from);
+        let nodes: Vec<String> = known.chain(self.seeds.iter().cloned()).collect();
+        let mut last_error = None;
+        for node in nodes {
+            let reply = match self.pool(&node).await {
+                Ok(pool) => match pool.get().await {
+                    Ok(mut client) => client.execute(Cmd::new("CLUSTER").arg("SLOTS")).await,
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            };
+            match reply.and_then(parse_slots) {
+                Ok(topology) => {
+                    *self.topology.write().unwrap() = Arc::new(topology);
+                    return Ok(());
+                }
+                Err(e) => {
+                    debug!(%node, error = %e, "Failed to read the cluster slots");
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or(ClientError::ConnectionClosed))
+    }
+
+    fn topology(&self) -> Arc<Topology> {
+        Arc::clone(&self.topology.read().unwrap())
+    }
+
+    /// 节点的连接池，第一次使用时创建
+    async fn pool(&self, node: &str) -> Result<Pool> {
+        if let Some(pool) = self.pools.lock().unwrap().get(node) {
+            return Ok(pool.clone());
+        }
+        let pool = Pool::new(node, self.options.clone()).await?;
+        let mut pools = self.pools.lock().unwrap();
+        Ok(pools.entry(node.to_string()).or_insert(pool).clone())
+    }
+
+    /// 发送一条命令并跟随重定向，错误回复转换为 [`ClientError::Server`]
+    pub async fn execute(&self, cmd: Cmd) -> Result<Frame> {
+        let topology = self.topology();
+        let owner = cmd.key().and_then(|key| topology.owner(key_slot(key)));
+        let mut target = owner.map(String::from);
+        let mut asking = false;
+        for redirects in 0.. {
+            let node = match target.take() {
+                Some(node) => node,
+                None => self.any_node(),
+            };
+            let mut client = self.pool(&node).await?.get().await?;
+            let result = if asking {
+                let cmds = [Cmd::new("ASKING"), cmd.clone()];
+                match client.execute_batch(&cmds).await?.pop() {
+                    Some(Frame::Error(e)) => Err(ClientError::Server(e)),
+                    Some(frame) => Ok(frame),
+                    None => Err(ClientError::ConnectionClosed),
+                }
+            } else {
+                client.execute(cmd.clone()).await
+            };
+            drop(client);
+            let redirect = match &result {
+                Err(ClientError::Server(e)) if redirects < MAX_REDIRECTS => Redirect::parse(e),
+                _ => None,
+            };
+            match redirect {
+                Some(Redirect::Moved(_, node)) => {
+                    if let Err(e) = self.refresh().await {
+                        debug!(error = %e, "Failed to refresh the cluster slots after MOVED");
+                    }
+                    (target, asking) = (Some(node), false);
+                }
+                Some(Redirect::Ask(_, node)) => (target, asking) = (Some(node), true),
+                _ => return result,
+            }
+        }
+        unreachable!()
+    }
+
+    /// 没有 key 的命令发送的节点
+    fn any_node(&self) -> String {
+        let topology = self.topology();
+        match topology.ranges().first() {
+            Some(range) => range.node.clone(),
+            None => self.seeds[0].clone(),
+        }
+    }
+
+    /// 发送一条命令，回复转换为 `T`
+    pub async fn query<T: FromValue>(&self, cmd: Cmd) -> Result<T> {
+        T::from_value(self.execute(cmd).await?)
+    }
+
+    pub async fn get<T: FromValue>(&self, key: &str) -> Result<T> {
+        self.query(Cmd::new("GET").arg(key)).await
+    }
+
+    pub async fn set(&self, key: &str, value: impl ToArg) -> Result<()> {
+        ok(self.execute(Cmd::new("SET").arg(key).arg(value)).await?)
+    }
+
+    /// 所有 key 必须在同一个 slot，否则服务端回复 CROSSSLOT
+    pub async fn del(&self, keys: &[&str]) -> Result<u64> {
+        self.query(Cmd::new("DEL").args(keys.iter().copied())).await
+    }
+
+    pub async fn incr(&self, key: &str) -> Result<i64> {
+        self.query(Cmd::new("INCR").arg(key)).await
+    }
+
+    pub async fn incr_by(&self, key: &str, delta: i64) -> Result<i64> {
+        self.query(Cmd::new("INCRBY").arg(key).arg(delta)).await
+    }
+}
+
+/// CLUSTER SLOTS 的回复：每一项为 `[start, end, [host, port, ...], 副本...]`
+fn parse_slots(reply: Frame) -> Result<Topology> {
+    let Frame::Array(entries) = reply else {
+        return Err(ClientError::UnexpectedReply(reply));
+    };
+    let ranges = entries
+        .iter()
+        .map(|entry| match entry {
+            Frame::Array(parts) => match &parts[..] {
+                [Frame::Integer(start), Frame::Integer(end), Frame::Array(node), ..] => {
+                    match &node[..] {
+                        [Frame::Bulk(host), Frame::Integer(port), ..] => Some(SlotRange {
+                            start: u16::try_from(*start).ok()?,
+                            end: u16::try_from(*end).ok()?,
+                            node: format!("{}:{}", String::from_utf8_lossy(host), port),
+                        }),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    match ranges {
+        Some(ranges) => Ok(Topology::new(ranges, BTreeMap::new(), BTreeSet::new())),
+        None => Err(ClientError::UnexpectedReply(Frame::Array(entries))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{client::Client, config::Config, server};
+
+    /// 启动两个节点，`0-8191` 由第一个负责，`8192-16383` 由第二个负责
+    async fn start_cluster() -> [String; 2] {
+        let listeners = [
+            TcpListener::bind("127.0.0.1:0").await.unwrap(),
+            TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        ];
+        let nodes = listeners
+            .each_ref()
+            .map(|listener| listener.local_addr().unwrap().to_string());
+        let slots = format!("0-8191 {}, 8192-16383 {}", nodes[0], nodes[1]);
+        for (listener, node) in listeners.into_iter().zip(&nodes) {
+            let mut config = Config {
+                databases: 1,
+                ..Config::default()
+            };
+            config.set("cluster-enabled", "yes").unwrap();
+            config.set("cluster-announce", node).unwrap();
+            config.set("cluster-slots", &slots).unwrap();
+            tokio::spawn(async move {
+                server::run_with(listener, &config, std::future::pending::<()>()).await
+            });
+        }
+        nodes
+    }
+
+    async fn config_set(node: &str, name: &str, value: &str) {
+        let mut client = Client::connect(node).await.unwrap();
+        let cmd = Cmd::new("CONFIG").arg("SET").arg(name).arg(value);
+        client.execute(cmd).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn commands_follow_the_slot_map() {
+        let nodes = start_cluster().await;
+        let cluster = ClusterClient::connect(&[&nodes[0]], PoolOptions::default())
+            .await
+            .unwrap();
+        // bar 在 slot 5061，foo 在 slot 12182
+        cluster.set("bar", 1).await.unwrap();
+        cluster.set("foo", 2).await.unwrap();
+        assert_eq!(cluster.incr("foo").await.unwrap(), 3);
+        let mut second = Client::connect(&nodes[1]).await.unwrap();
+        assert_eq!(second.get::<i64>("foo").await.unwrap(), 3);
+
+        // slot 迁入第二个节点：迁出节点上不存在的 key 通过 ASK 在目标节点写入
+        config_set(
+            &nodes[0],
+            "cluster-migrating",
+            &format!("5061 {}", nodes[1]),
+        )
+        .await;
+        config_set(&nodes[1], "cluster-importing", "5061").await;
+        cluster.set("{bar}.new", 1).await.unwrap();
+        assert_eq!(cluster.get::<i64>("bar").await.unwrap(), 1);
+
+        // 迁移完成后修改所有节点的 slot 表，客户端收到 MOVED 后更新
+        let slots = format!(
+            "0-5060 {0}, 5061 {1}, 5062-8191 {0}, 8192-16383 {1}",
+            nodes[0], nodes[1]
+        );
+        for node in &nodes {
+            config_set(node, "cluster-slots", &slots).await;
+        }
+        assert_eq!(cluster.get::<i64>("{bar}.new").await.unwrap(), 1);
+        assert_eq!(cluster.topology().owner(5061), Some(nodes[1].as_str()));
+        assert_eq!(cluster.del(&["foo"]).await.unwrap(), 1);
+    }
+}
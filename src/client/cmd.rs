@@ -0,0 +1,193 @@
+use bytes::Bytes;
+
+use crate::frame::Frame;
+
+/// 客户端发送的一条命令：命令名和参数，编码为 Bulk 组成的数组
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cmd {
+    args: Vec<Bytes>,
+}
+
+impl Cmd {
+    pub fn new(name: &str) -> Cmd {
+        Cmd {
+            args: vec![Bytes::copy_from_slice(name.as_bytes())],
+        }
+    }
+
+    pub fn arg(mut self, arg: impl ToArg) -> Cmd {
+        self.args.push(arg.to_arg());
+        self
+    }
+
+    pub fn args<T: ToArg>(mut self, args: impl IntoIterator<Item = T>) -> Cmd {
+        self.args.extend(args.into_iter().map(ToArg::to_arg));
+        self
+    }
+
+    /// 命令名，保持调用者使用的大小写
+    pub fn name(&self) -> &[u8] {
+        &self.args[0]
+    }
+
+    /// 集群模式下决定由哪个节点执行的 key
+    ///
+    /// 大多数命令的第一个参数就是 key；没有 key 的命令返回 None，可以由任意节点执行。
+    /// EVAL 和 EVALSHA 的 key 在 numkeys 之后，没有 key 时同样返回 None。
+    pub fn key(&self) -> Option<&[u8]> {
+        const KEYLESS: &[&str] = &[
+            "AUTH", "CLIENT", "CLUSTER", "CONFIG", "DBSIZE", "ECHO", "HELLO", "INFO", "PING",
+            "ROLE", "SCAN", "SCRIPT", "SELECT",
+        ];
+        let name = self.name();
+        if name.eq_ignore_ascii_case(b"EVAL") || name.eq_ignore_ascii_case(b"EVALSHA") {
+            let numkeys = self.args.get(2)?;
+            return (&numkeys[..] != b"0")
+                .then(|| self.args.get(3).map(|key| &key[..]))
+                .flatten();
+        }
+        if KEYLESS
+            .iter()
+            .any(|keyless| name.eq_ignore_ascii_case(keyless.as_bytes()))
+        {
+            return None;
+        }
+        self.args.get(1).map(|key| &key[..])
+    }
+
+    /// 执行多次与执行一次效果相同的命令，连接断开时可以重新发送
+    pub fn is_idempotent(&self) -> bool {
+        const IDEMPOTENT: &[&str] = &[
+            "AUTH",
+            "DUMP",
+            "ECHO",
+            "EXISTS",
+            "GET",
+            "GETRANGE",
+            "INFO",
+            "MGET",
+            "PING",
+            "PTTL",
+            "ROLE",
+            "SCAN",
+            "SELECT",
+            "STRLEN",
+            "TTL",
+            "TYPE",
+            "XLEN",
+            "XRANGE",
+            "XREVRANGE",
+        ];
+        let name = self.name();
+        IDEMPOTENT
+            .iter()
+            .any(|idempotent| name.eq_ignore_ascii_case(idempotent.as_bytes()))
+    }
+
+    /// 不修改数据的命令，[`ReplicaClient`](super::ReplicaClient) 把它们发送给副本
+    pub fn is_read_only(&self) -> bool {
+        const READ_ONLY: &[&str] = &[
+            "DBSIZE",
+            "DUMP",
+            "EXISTS",
+            "GET",
+            "GETRANGE",
+            "HEXISTS",
+            "HGET",
+            "HGETALL",
+            "HKEYS",
+            "HLEN",
+            "HMGET",
+            "HSCAN",
+            "HVALS",
+            "KEYS",
+            "LINDEX",
+            "LLEN",
+            "LRANGE",
+            "MGET",
+            "PTTL",
+            "SCAN",
+            "SCARD",
+            "SISMEMBER",
+            "SMEMBERS",
+            "SSCAN",
+            "STRLEN",
+            "TTL",
+            "TYPE",
+            "XLEN",
+            "XRANGE",
+            "XREVRANGE",
+            "ZCARD",
+            "ZRANGE",
+            "ZRANK",
+            "ZSCORE",
+        ];
+        let name = self.name();
+        READ_ONLY
+            .iter()
+            .any(|read_only| name.eq_ignore_ascii_case(read_only.as_bytes()))
+    }
+
+    pub fn to_frame(&self) -> Frame {
+        Frame::Array(self.args.iter().cloned().map(Frame::Bulk).collect())
+    }
+}
+
+/// 可以作为命令参数的类型，整数按十进制发送
+pub trait ToArg {
+    fn to_arg(self) -> Bytes;
+}
+
+impl ToArg for Bytes {
+    fn to_arg(self) -> Bytes {
+        self
+    }
+}
+
+impl ToArg for &Bytes {
+    fn to_arg(self) -> Bytes {
+        self.clone()
+    }
+}
+
+impl ToArg for &str {
+    fn to_arg(self) -> Bytes {
+        Bytes::copy_from_slice(self.as_bytes())
+    }
+}
+
+impl ToArg for &String {
+    fn to_arg(self) -> Bytes {
+        Bytes::copy_from_slice(self.as_bytes())
+    }
+}
+
+impl ToArg for String {
+    fn to_arg(self) -> Bytes {
+        Bytes::from(self)
+    }
+}
+
+impl ToArg for &[u8] {
+    fn to_arg(self) -> Bytes {
+        Bytes::copy_from_slice(self)
+    }
+}
+
+impl ToArg for Vec<u8> {
+    fn to_arg(self) -> Bytes {
+        Bytes::from(self)
+    }
+}
+
+macro_rules! integer_arg {
+    ($($ty:ty),*) => {
+        $(impl ToArg for $ty {
+            fn to_arg(self) -> Bytes {
+                Bytes::from(self.to_string())
+            }
+        })*
+    };
+}
+
+integer_arg!(u8, i32, i64, u32, u64, usize);
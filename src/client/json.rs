@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{Client, Result};
+
+impl Client {
+    /// 把 `value` 序列化为 JSON 后 SET
+    pub async fn set_json<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<()> {
+        let json = serde_json::to_vec(value)?;
+        self.set(key, Bytes::from(json)).await
+    }
+
+    /// 与 [`Client::set_json`] 相同，同时设置过期时间，见 [`Client::set_expires`]
+    pub async fn set_json_expires<T: Serialize + ?Sized>(
+        &mut self,
+        key: &str,
+        value: &T,
+        expire: Duration,
+    ) -> Result<()> {
+        let json = serde_json::to_vec(value)?;
+        self.set_expires(key, Bytes::from(json), expire).await
+    }
+
+    /// GET 后把值解析为 JSON，key 不存在时返回 None
+    ///
+    /// 值不是合法的 JSON 或者与 `T` 不匹配时返回 [`ClientError::Json`](super::ClientError::Json)。
+    pub async fn get_json<T: DeserializeOwned>(&mut self, key: &str) -> Result<Option<T>> {
+        match self.get::<Option<Bytes>>(key).await? {
+            Some(json) => Ok(Some(serde_json::from_slice(&json)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::client::{tests::start_server, ClientError};
+
+    #[tokio::test]
+    async fn values_round_trip_as_json() {
+        let mut client = Client::connect(start_server().await).await.unwrap();
+        let scores = HashMap::from([
+            ("alice".to_string(), vec![1, 2]),
+            ("bob".to_string(), vec![]),
+        ]);
+        client.set_json("scores", &scores).await.unwrap();
+        let read: Option<HashMap<String, Vec<i64>>> = client.get_json("scores").await.unwrap();
+        assert_eq!(read, Some(scores));
+        assert_eq!(
+            client.get::<String>("scores").await.unwrap().len(),
+            r#"{"alice":[1,2],"bob":[]}"#.len()
+        );
+
+        let missing: Option<Vec<i64>> = client.get_json("missing").await.unwrap();
+        assert_eq!(missing, None);
+        client
+            .set_json_expires("t", "text", Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert!(matches!(
+            client.get_json::<Vec<i64>>("t").await,
+            Err(ClientError::Json(_))
+        ));
+    }
+}
@@ -0,0 +1,169 @@
+use std::{collections::HashMap, fmt, sync::Arc, sync::Mutex, time::Duration};
+
+use super::{ClientError, Cmd};
+
+/// 耗时分布的桶的上界，超过最后一个的计入额外的一个桶
+pub const LATENCY_BUCKETS: [Duration; 9] = [
+    Duration::from_micros(100),
+    Duration::from_micros(500),
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+];
+
+/// 一种命令的统计，见 [`Client::metrics`](super::Client::metrics)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandMetrics {
+    pub count: u64,
+    /// 返回错误的次数，包括服务端的错误回复
+    pub errors: u64,
+    pub total: Duration,
+    pub max: Duration,
+    /// 每个桶的计数，不累加；最后一项为超过 [`LATENCY_BUCKETS`] 最大值的次数
+    pub buckets: [u64; LATENCY_BUCKETS.len() + 1],
+}
+
+impl CommandMetrics {
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => self.total / count as u32,
+        }
+    }
+
+    /// 分位数 `q`（0 到 1）所在的桶的上界，落在最后一个桶时返回最大耗时
+    pub fn quantile(&self, q: f64) -> Duration {
+        let rank = (q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+        for (count, le) in self.buckets.iter().zip(LATENCY_BUCKETS) {
+            seen += count;
+            if seen >= rank.max(1) {
+                return le.min(self.max);
+            }
+        }
+        self.max
+    }
+
+    fn record(&mut self, latency: Duration, failed: bool) {
+        let index = LATENCY_BUCKETS
+            .iter()
+            .position(|le| latency <= *le)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[index] += 1;
+        self.count += 1;
+        self.errors += u64::from(failed);
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+}
+
+/// 每条命令完成后传给钩子的信息，见 [`Client::set_metrics_hook`](super::Client::set_metrics_hook)
+#[derive(Debug)]
+pub struct CommandEvent<'a> {
+    /// 大写的命令名
+    pub name: &'a str,
+    pub latency: Duration,
+    pub error: Option<&'a ClientError>,
+}
+
+type Hook = Arc<dyn Fn(&CommandEvent<'_>) + Send + Sync>;
+
+/// 客户端内部的统计，按大写的命令名分别记录
+#[derive(Default)]
+pub(super) struct Metrics {
+    commands: Mutex<HashMap<String, CommandMetrics>>,
+    hook: Option<Hook>,
+}
+
+impl Metrics {
+    pub(super) fn set_hook(&mut self, hook: Hook) {
+        self.hook = Some(hook);
+    }
+
+    pub(super) fn record(&self, cmd: &Cmd, latency: Duration, error: Option<&ClientError>) {
+        let name = String::from_utf8_lossy(cmd.name()).to_uppercase();
+        if let Some(hook) = &self.hook {
+            hook(&CommandEvent {
+                name: &name,
+                latency,
+                error,
+            });
+        }
+        let mut commands = self.commands.lock().unwrap();
+        commands
+            .entry(name)
+            .or_default()
+            .record(latency, error.is_some());
+    }
+
+    pub(super) fn snapshot(&self) -> HashMap<String, CommandMetrics> {
+        self.commands.lock().unwrap().clone()
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics")
+            .field("commands", &self.commands)
+            .field("hook", &self.hook.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::client::{tests::start_server, Client};
+
+    #[test]
+    fn quantiles_use_bucket_bounds() {
+        let mut metrics = CommandMetrics::default();
+        for micros in [50, 80, 300, 2_000, 2_000_000] {
+            metrics.record(Duration::from_micros(micros), false);
+        }
+        assert_eq!(metrics.buckets[0], 2);
+        assert_eq!(metrics.buckets[LATENCY_BUCKETS.len()], 1);
+        assert_eq!(metrics.quantile(0.4), Duration::from_micros(100));
+        assert_eq!(metrics.quantile(0.6), Duration::from_micros(500));
+        assert_eq!(metrics.quantile(1.0), Duration::from_secs(2));
+        assert_eq!(metrics.mean(), Duration::from_micros(400_486));
+    }
+
+    #[tokio::test]
+    async fn commands_are_recorded_with_errors() {
+        let mut client = Client::connect(start_server().await).await.unwrap();
+        let failures = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&failures);
+        client.set_metrics_hook(move |event| {
+            if event.error.is_some() {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+
+        client.set("a", "x").await.unwrap();
+        client.get::<String>("a").await.unwrap();
+        client.get::<String>("a").await.unwrap();
+        client.incr("a").await.unwrap_err();
+        client
+            .pipeline()
+            .incr("n")
+            .incr("n")
+            .execute::<()>()
+            .await
+            .unwrap();
+
+        let metrics = client.metrics();
+        assert_eq!(metrics["GET"].count, 2);
+        assert_eq!(metrics["SET"].errors, 0);
+        assert_eq!((metrics["INCR"].count, metrics["INCR"].errors), (3, 1));
+        assert!(metrics["GET"].max >= metrics["GET"].mean());
+        assert_eq!(metrics["GET"].buckets.iter().sum::<u64>(), 2);
+        assert_eq!(failures.load(Ordering::Relaxed), 1);
+    }
+}
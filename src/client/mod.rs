@@ -0,0 +1,930 @@
+//! 异步客户端
+//!
+//! [`Client`] 持有一条到服务端的连接，每个方法发送一条命令并等待回复。服务端的错误回复转换为
+//! [`ClientError::Server`]，回复通过 [`FromValue`] 转换为调用者指定的类型，例如
+//! `client.get::<i64>("counter")`。没有对应方法的命令用 [`Cmd`] 构造后通过 [`Client::query`] 发送。
+//!
+//! [`Client::pipeline`] 一次写入多条命令再读取回复。多个任务可以各自从 [`Pool`] 取出连接，
+//! 也可以通过 [`Client::multiplexed`] 共用一条连接。[`Client::subscribe`] 和
+//! [`Client::psubscribe`] 把连接转换为接收消息的 [`Subscriber`]，[`Client::cached`] 借助服务端的失效通知在本地缓存读取的值。集群模式的服务端使用 [`ClusterClient`]，同步代码使用 [`BlockingClient`]。
+//! 主从部署用 [`ReplicaClient`] 把只读命令分给副本，副本不可用时改由主节点执行。
+//!
+//! [`Client::scan_match`] 等方法把游标命令包装成 [`futures::Stream`]，不需要自己处理游标。
+//!
+//! 大量 key 的读写用 [`Client::mget_chunked`] 和 [`Client::mset_chunked`] 分批发送，
+//! [`Pool`] 上的同名方法把批次分给多个连接并发执行。
+//!
+//! 很大的值用 [`Client::get_streaming`] 分段读取。
+//!
+//! 开启 `serde_json` 特性后，`Client::set_json` 和 `Client::get_json` 以 JSON 保存和读取结构体。
+//!
+//! Lua 脚本用 [`Script`] 执行，按 SHA1 发送，服务端没有缓存时才发送源码。
+//!
+//! [`Client`]、[`Multiplexed`] 和 [`PooledClient`] 都实现了 [`Service`]，可以用 [`Layer`]
+//! 包装请求路径，加入日志、熔断等策略。
+//!
+//! 每条命令的耗时和错误记录在客户端中，通过 [`Client::metrics`] 读取，
+//! 也可以用 [`Client::set_metrics_hook`] 在命令完成时得到通知。每条命令还在调用者当前的
+//! tracing span 下创建一个 `command` span，key 可以用 [`Client::set_redact_keys`] 隐藏。
+//!
+//! 连接断开后，下一条命令发送之前按 [`ReconnectOptions`] 重新连接，并重新执行
+//! [`Client::auth`] 和 [`Client::select`] 设置的认证和数据库。失败的命令是否重新发送由
+//! [`RetryPolicy`] 决定，默认只重试幂等的命令（见 [`Cmd::is_idempotent`]）。
+//! 设置了 [`Client::set_timeout`] 时，等待回复超时后关闭连接，返回 [`ClientError::Timeout`]。
+//!
+//! 开启 `tls` 特性后可以用 `Client::connect_tls` 连接只接受 TLS 的服务端，重新连接时重新握手。
+//! [`Client::connect_url`] 从 `redis://` 或 `rediss://` URL 读取地址、认证、数据库和超时时间。
+//! 同一台机器上的服务端可以用 [`Client::connect_unix`] 或者 `redis+unix://` URL 通过 unix socket 连接。
+
+mod blocking;
+pub use blocking::{BlockingClient, BlockingSubscriber};
+
+mod cache;
+pub use cache::{CacheStats, CachedClient};
+
+mod chunked;
+
+mod cluster;
+pub use cluster::ClusterClient;
+
+mod cmd;
+pub use cmd::{Cmd, ToArg};
+
+#[cfg(feature = "serde_json")]
+mod json;
+
+mod metrics;
+use metrics::Metrics;
+pub use metrics::{CommandEvent, CommandMetrics, LATENCY_BUCKETS};
+
+mod multiplexed;
+pub use multiplexed::{Multiplexed, MultiplexedOptions, WhenFull};
+
+mod options;
+pub use options::ConnectOptions;
+
+mod pipeline;
+pub use pipeline::Pipeline;
+
+mod pool;
+pub use pool::{Pool, PoolOptions, PoolStatus, PooledClient};
+
+mod range;
+pub use range::STREAMING_CHUNK;
+
+mod replica;
+pub use replica::{ReadFrom, ReplicaClient};
+
+mod retry;
+pub use retry::{ReconnectOptions, RetryPolicy};
+
+mod scan;
+
+mod script;
+pub use script::{Invocation, Script};
+
+mod service;
+pub use service::{layer_fn, Layer, LayerFn, Service, ServiceExt};
+
+mod stream;
+use stream::{Endpoint, Stream};
+
+mod subscriber;
+pub use subscriber::{Event, Message, Messages, Subscriber, Subscriptions};
+
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "tls")]
+pub use tls::TlsOptions;
+
+mod trace;
+use trace::Tracer;
+
+mod value;
+pub use value::FromValue;
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    io, mem,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use thiserror::Error;
+use tokio::{
+    net::{self, TcpStream, ToSocketAddrs},
+    time,
+};
+use tracing::{debug, Instrument};
+
+use crate::{connection::Connection, frame::Frame};
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// 服务端的错误回复，保留原始的消息
+    #[error("{0}")]
+    Server(String),
+    #[error("unexpected reply: {0:?}")]
+    UnexpectedReply(Frame),
+    /// 回复不能转换为要求的类型，见 [`FromValue`]
+    #[error("cannot convert reply {0:?} to {1}")]
+    Conversion(Frame, &'static str),
+    #[error("connection closed by server")]
+    ConnectionClosed,
+    #[error("timed out waiting for the server")]
+    Timeout,
+    /// WATCH 的 key 被修改，EXEC 没有执行事务
+    #[error("transaction aborted: a watched key was modified")]
+    TransactionAborted,
+    /// [`ConnectOptions::from_url`] 不能解析的 URL
+    #[error("invalid url: {0}")]
+    InvalidUrl(String),
+    /// [`Client::set_json`] 不能序列化的值，或者 [`Client::get_json`] 读到的值不是要求的类型
+    #[cfg(feature = "serde_json")]
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// [`Multiplexed`] 等待回复的命令达到上限，见 [`WhenFull::Error`]
+    #[error("too many commands in flight")]
+    TooManyInFlight,
+}
+
+impl ClientError {
+    /// 连接已经断开或者状态未知，不能继续使用
+    pub fn is_disconnect(&self) -> bool {
+        matches!(
+            self,
+            ClientError::Io(_) | ClientError::ConnectionClosed | ClientError::Timeout
+        )
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// 重新连接后需要恢复的连接状态
+#[derive(Debug, Default)]
+struct Handshake {
+    /// `(username, password)`
+    auth: Option<(Option<String>, String)>,
+    db: usize,
+    /// CLIENT SETNAME 的名字
+    name: Option<String>,
+    /// 设置后用 HELLO 切换协议版本，同时完成认证和设置名字
+    protocol: Option<u8>,
+}
+
+#[derive(Debug)]
+pub struct Client {
+    /// 连接断开后为 None，下一条命令发送之前重新连接
+    connection: Option<Connection<Stream>>,
+    /// 连接上有已经发送、还没有读完回复的命令，见 [`Client::begin_request`]
+    in_flight: bool,
+    endpoint: Endpoint,
+    handshake: Handshake,
+    reconnect: ReconnectOptions,
+    retry: RetryPolicy,
+    /// 建立连接和等待回复的超时时间
+    timeout: Option<Duration>,
+    metrics: Metrics,
+    tracer: Tracer,
+    /// 设置后每个连接先完成 TLS 握手
+    #[cfg(feature = "tls")]
+    tls: Option<TlsOptions>,
+}
+
+impl Client {
+    /// 连接服务端，失败时不重试，需要认证、选择数据库等设置时使用 [`ConnectOptions`]
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Client> {
+        let mut client = Client::new(Endpoint::Tcp(net::lookup_host(addr).await?.collect()));
+        client.connection = Some(client.open().await?);
+        Ok(client)
+    }
+
+    /// 通过 unix socket 连接同一台机器上的服务端，失败时不重试
+    ///
+    /// 不经过 TCP 协议栈，延迟更低。只在 unix 平台上可用，其他平台返回错误。
+    pub async fn connect_unix(path: impl AsRef<Path>) -> Result<Client> {
+        let mut client = Client::new(Endpoint::unix(path.as_ref())?);
+        client.connection = Some(client.open().await?);
+        Ok(client)
+    }
+
+    /// 通过 TLS 连接服务端，失败时不重试
+    #[cfg(feature = "tls")]
+    pub async fn connect_tls(addr: impl ToSocketAddrs, tls: TlsOptions) -> Result<Client> {
+        let mut client = Client::new(Endpoint::Tcp(net::lookup_host(addr).await?.collect()));
+        client.tls = Some(tls);
+        client.connection = Some(client.open().await?);
+        Ok(client)
+    }
+
+    /// 按 [`ConnectOptions`] 连接服务端并完成握手，失败时不重试
+    pub async fn connect_with(options: &ConnectOptions) -> Result<Client> {
+        let endpoint = match &options.unix_socket {
+            Some(path) => Endpoint::unix(path)?,
+            None => {
+                let addr = (options.host.as_str(), options.port);
+                Endpoint::Tcp(net::lookup_host(addr).await?.collect())
+            }
+        };
+        let mut client = Client::new(endpoint);
+        if let Some(password) = &options.password {
+            client.handshake.auth = Some((options.username.clone(), password.clone()));
+        }
+        client.handshake.db = options.db;
+        client.handshake.name = options.client_name.clone();
+        client.handshake.protocol = options.protocol;
+        client.timeout = options.timeout;
+        #[cfg(feature = "tls")]
+        {
+            client.tls = options.tls.clone();
+        }
+        client.connection = Some(within(client.timeout, client.open()).await?);
+        Ok(client)
+    }
+
+    /// 解析 URL 后连接，见 [`ConnectOptions::from_url`]
+    pub async fn connect_url(url: &str) -> Result<Client> {
+        Client::connect_with(&ConnectOptions::from_url(url)?).await
+    }
+
+    fn new(endpoint: Endpoint) -> Client {
+        Client {
+            connection: None,
+            in_flight: false,
+            tracer: Tracer::new(endpoint.to_string()),
+            endpoint,
+            handshake: Handshake::default(),
+            reconnect: ReconnectOptions::default(),
+            retry: RetryPolicy::default(),
+            timeout: None,
+            metrics: Metrics::default(),
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    pub fn set_reconnect(&mut self, options: ReconnectOptions) {
+        self.reconnect = options;
+    }
+
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy;
+    }
+
+    /// 设置建立连接和等待回复的超时时间，None 表示一直等待
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// 每种命令的耗时分布和错误次数，key 为大写的命令名
+    pub fn metrics(&self) -> HashMap<String, CommandMetrics> {
+        self.metrics.snapshot()
+    }
+
+    /// 每条命令完成后调用 `hook`，用于把耗时和错误转发给应用自己的监控系统
+    ///
+    /// 在发送命令的任务中同步调用，不应阻塞。再次设置时替换之前的钩子。
+    pub fn set_metrics_hook(&mut self, hook: impl Fn(&CommandEvent<'_>) + Send + Sync + 'static) {
+        self.metrics.set_hook(Arc::new(hook));
+    }
+
+    /// 为 true 时命令的 tracing span 不记录 key 的内容，见 [`Client::execute`]
+    pub fn set_redact_keys(&mut self, redact: bool) {
+        self.tracer.redact_keys = redact;
+    }
+
+    /// 发送一条命令并读取回复，错误回复转换为 [`ClientError::Server`]
+    ///
+    /// 失败时按 [`RetryPolicy`] 重新发送，记录的耗时包括重试的时间。每次调用创建一个名为
+    /// `command` 的 info 级别 span，记录命令名、key、服务端地址和结果，父 span 是调用者当前的 span。
+    pub async fn execute(&mut self, cmd: Cmd) -> Result<Frame> {
+        let span = self.tracer.command(&cmd);
+        let start = Instant::now();
+        let result = self.execute_with_retry(&cmd).instrument(span.clone()).await;
+        self.metrics
+            .record(&cmd, start.elapsed(), result.as_ref().err());
+        trace::record(&span, &result);
+        result
+    }
+
+    async fn execute_with_retry(&mut self, cmd: &Cmd) -> Result<Frame> {
+        let frame = cmd.to_frame();
+        let mut attempt = 1;
+        loop {
+            let timeout = self.timeout;
+            self.begin_request();
+            let connection = self.connection().await?;
+            let result = match within(timeout, round_trip(connection, &frame)).await {
+                Ok(Frame::Error(e)) => Err(ClientError::Server(e)),
+                result => result,
+            };
+            self.in_flight = false;
+            let e = match result {
+                Err(e) => e,
+                reply => return reply,
+            };
+            if e.is_disconnect() {
+                self.connection = None;
+            }
+            if attempt >= self.retry.max_attempts || !(self.retry.retryable)(cmd, &e) {
+                return Err(e);
+            }
+            debug!(error = %e, attempt, "Retrying a failed command");
+            time::sleep(self.retry.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    /// 用 PING 检查当前的连接，不重新连接也不重试，连接已经断开时返回错误
+    pub(super) async fn check(&mut self) -> Result<()> {
+        self.begin_request();
+        let timeout = self.timeout;
+        let connection = self
+            .connection
+            .as_mut()
+            .ok_or(ClientError::ConnectionClosed)?;
+        let result = within(
+            timeout,
+            round_trip(connection, &Cmd::new("PING").to_frame()),
+        )
+        .await;
+        self.in_flight = false;
+        if result.is_err() {
+            self.connection = None;
+        }
+        match result? {
+            Frame::Simple(pong) if pong == "PONG" => Ok(()),
+            frame => Err(ClientError::UnexpectedReply(frame)),
+        }
+    }
+
+    /// 发送命令之前标记连接上有命令在执行，读完回复后清除标记
+    ///
+    /// 调用方的 future 在读到回复之前被取消时标记一直保留，回复还留在连接中，
+    /// 之后的命令会读到它，因此丢弃这个连接，下一条命令重新连接。
+    fn begin_request(&mut self) {
+        if mem::replace(&mut self.in_flight, true) {
+            debug!("Dropping a connection with an unread reply");
+            self.connection = None;
+        }
+    }
+
+    /// 当前的连接，已经断开时重新连接
+    async fn connection(&mut self) -> Result<&mut Connection<Stream>> {
+        if self.connection.is_none() {
+            self.connection = Some(self.reconnect().await?);
+        }
+        Ok(self.connection.as_mut().unwrap())
+    }
+
+    /// 按 [`ReconnectOptions`] 重试，服务端拒绝握手时不再重试
+    async fn reconnect(&self) -> Result<Connection<Stream>> {
+        let mut attempt = 0;
+        loop {
+            match within(self.timeout, self.open()).await {
+                Ok(connection) => return Ok(connection),
+                Err(e) if !e.is_disconnect() || attempt >= self.reconnect.retries => return Err(e),
+                Err(e) => {
+                    debug!(error = %e, attempt, "Failed to reconnect");
+                    time::sleep(self.reconnect.backoff(attempt)).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// 建立连接并恢复协议版本、认证、数据库和名字
+    async fn open(&self) -> Result<Connection<Stream>> {
+        let mut connection = Connection::new(self.open_stream().await?);
+        let handshake = &self.handshake;
+        if let Some(protocol) = handshake.protocol {
+            let mut cmd = Cmd::new("HELLO").arg(protocol);
+            if let Some((username, password)) = &handshake.auth {
+                // HELLO 的 AUTH 选项必须带用户名
+                let username = username.as_deref().unwrap_or("default");
+                cmd = cmd.arg("AUTH").arg(username).arg(password);
+            }
+            if let Some(name) = &handshake.name {
+                cmd = cmd.arg("SETNAME").arg(name);
+            }
+            if let Frame::Error(e) = round_trip(&mut connection, &cmd.to_frame()).await? {
+                return Err(ClientError::Server(e));
+            }
+        } else {
+            if let Some((username, password)) = &handshake.auth {
+                let cmd = Cmd::new("AUTH").args(username).arg(password);
+                expect_ok(round_trip(&mut connection, &cmd.to_frame()).await?)?;
+            }
+            if let Some(name) = &handshake.name {
+                let cmd = Cmd::new("CLIENT").arg("SETNAME").arg(name);
+                expect_ok(round_trip(&mut connection, &cmd.to_frame()).await?)?;
+            }
+        }
+        if handshake.db != 0 {
+            let cmd = Cmd::new("SELECT").arg(handshake.db);
+            expect_ok(round_trip(&mut connection, &cmd.to_frame()).await?)?;
+        }
+        Ok(connection)
+    }
+
+    /// 建立传输层的连接，unix socket 不使用 TLS
+    async fn open_stream(&self) -> Result<Stream> {
+        let addrs = match &self.endpoint {
+            Endpoint::Tcp(addrs) => addrs,
+            #[cfg(unix)]
+            Endpoint::Unix(path) => return Ok(Stream::Unix(net::UnixStream::connect(path).await?)),
+        };
+        let socket = TcpStream::connect(&addrs[..]).await?;
+        socket.set_nodelay(true)?;
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            return Ok(tls.handshake(socket).await?);
+        }
+        Ok(Stream::Tcp(socket))
+    }
+
+    /// 订阅频道，连接进入订阅模式，不能再执行其他命令
+    pub async fn subscribe(self, channels: &[&str]) -> Result<Subscriber> {
+        Subscriber::new(self, channels).await
+    }
+
+    /// 订阅 glob 模式，连接进入订阅模式，见 [`Subscriber::psubscribe`]
+    pub async fn psubscribe(self, patterns: &[&str]) -> Result<Subscriber> {
+        let subscriber = Subscriber::new(self, &[]).await?;
+        subscriber.psubscribe(patterns).await?;
+        Ok(subscriber)
+    }
+
+    /// 发送一条命令，回复转换为 `T`
+    pub async fn query<T: FromValue>(&mut self, cmd: Cmd) -> Result<T> {
+        T::from_value(self.execute(cmd).await?)
+    }
+
+    /// 转换为带本地缓存的 [`CachedClient`]，最多缓存 `max_entries` 个 key
+    ///
+    /// 连接切换到 RESP3 并开启 CLIENT TRACKING，服务端不支持时返回错误。
+    pub async fn cached(self, max_entries: usize) -> Result<CachedClient> {
+        CachedClient::new(self, max_entries).await
+    }
+
+    /// 转换为可以在多个任务之间共用的 [`Multiplexed`]
+    ///
+    /// 使用默认的 [`MultiplexedOptions`]：最多 1024 条命令同时等待回复，达到上限时等待。
+    pub fn multiplexed(self) -> Multiplexed {
+        Multiplexed::new(self, MultiplexedOptions::default())
+    }
+
+    /// 与 [`Client::multiplexed`] 相同，按 `options` 限制同时等待回复的命令数
+    pub fn multiplexed_with(self, options: MultiplexedOptions) -> Multiplexed {
+        Multiplexed::new(self, options)
+    }
+
+    /// 开始构造一个 [`Pipeline`]
+    pub fn pipeline(&mut self) -> Pipeline<'_> {
+        Pipeline::new(self, false)
+    }
+
+    /// 开始构造一个用 MULTI/EXEC 原子执行的 [`Pipeline`]
+    pub fn transaction(&mut self) -> Pipeline<'_> {
+        Pipeline::new(self, true)
+    }
+
+    /// WATCH key...，之后的事务在这些 key 被其他连接修改时放弃执行
+    pub async fn watch(&mut self, keys: &[&str]) -> Result<()> {
+        ok(self
+            .execute(Cmd::new("WATCH").args(keys.iter().copied()))
+            .await?)
+    }
+
+    pub async fn unwatch(&mut self) -> Result<()> {
+        ok(self.execute(Cmd::new("UNWATCH")).await?)
+    }
+
+    /// 写入所有命令后依次读取回复，错误回复保留为 [`Frame::Error`]
+    ///
+    /// 连接断开时不知道哪些命令已经执行，不重新发送。每条命令记录的耗时都是整批的耗时。
+    async fn execute_batch(&mut self, cmds: &[Cmd]) -> Result<Vec<Frame>> {
+        let span = self.tracer.pipeline(cmds.len());
+        let start = Instant::now();
+        let timeout = self.timeout;
+        self.begin_request();
+        let result = async {
+            let connection = self.connection().await?;
+            within(timeout, batch(connection, cmds)).await
+        }
+        .instrument(span.clone())
+        .await;
+        self.in_flight = false;
+        trace::record(&span, &result);
+        if matches!(&result, Err(e) if e.is_disconnect()) {
+            self.connection = None;
+        }
+        let elapsed = start.elapsed();
+        match &result {
+            Ok(frames) => {
+                for (cmd, frame) in cmds.iter().zip(frames) {
+                    let error = match frame {
+                        Frame::Error(e) => Some(ClientError::Server(e.clone())),
+                        _ => None,
+                    };
+                    self.metrics.record(cmd, elapsed, error.as_ref());
+                }
+            }
+            Err(e) => {
+                for cmd in cmds {
+                    self.metrics.record(cmd, elapsed, Some(e));
+                }
+            }
+        }
+        result
+    }
+
+    /// 发送 QUIT 并等待服务端回复或者关闭连接，之后释放连接
+    ///
+    /// 直接 drop 时 socket 被立即关闭，服务端可能把它当作异常断开；`close` 让服务端先处理完
+    /// 之前的命令并主动关闭连接。连接已经断开时直接返回，不重新连接。
+    pub async fn close(mut self) -> Result<()> {
+        let Some(mut connection) = self.connection.take() else {
+            return Ok(());
+        };
+        let result = within(self.timeout, async move {
+            connection.write_frame(&Cmd::new("QUIT").to_frame()).await?;
+            // 回复 OK 之后服务端关闭连接，读到 EOF 为止
+            while let Some(frame) = connection.read_frame().await? {
+                if let Frame::Error(e) = frame {
+                    return Err(ClientError::Server(e));
+                }
+            }
+            Ok(())
+        })
+        .await;
+        match result {
+            // 服务端已经关闭了连接
+            Err(ClientError::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::ConnectionReset | io::ErrorKind::BrokenPipe
+                ) =>
+            {
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    pub async fn ping(&mut self) -> Result<()> {
+        match self.execute(Cmd::new("PING")).await? {
+            Frame::Simple(pong) if pong == "PONG" => Ok(()),
+            frame => Err(ClientError::UnexpectedReply(frame)),
+        }
+    }
+
+    /// AUTH [username] password，成功后重新连接时自动执行
+    pub async fn auth(&mut self, username: Option<&str>, password: &str) -> Result<()> {
+        let cmd = Cmd::new("AUTH").args(username).arg(password);
+        ok(self.execute(cmd).await?)?;
+        self.handshake.auth = Some((username.map(String::from), password.to_string()));
+        Ok(())
+    }
+
+    /// 切换数据库，成功后重新连接时自动执行
+    pub async fn select(&mut self, index: usize) -> Result<()> {
+        ok(self.execute(Cmd::new("SELECT").arg(index)).await?)?;
+        self.handshake.db = index;
+        Ok(())
+    }
+
+    /// key 不存在时回复 Null，用 `Option<T>` 接收
+    pub async fn get<T: FromValue>(&mut self, key: &str) -> Result<T> {
+        self.query(Cmd::new("GET").arg(key)).await
+    }
+
+    pub async fn set(&mut self, key: &str, value: impl ToArg) -> Result<()> {
+        ok(self.execute(Cmd::new("SET").arg(key).arg(value)).await?)
+    }
+
+    /// SET key value PX milliseconds
+    pub async fn set_expires(
+        &mut self,
+        key: &str,
+        value: impl ToArg,
+        expire: Duration,
+    ) -> Result<()> {
+        let millis = expire.as_millis() as u64;
+        let cmd = Cmd::new("SET").arg(key).arg(value).arg("PX").arg(millis);
+        ok(self.execute(cmd).await?)
+    }
+
+    /// 返回删除的 key 的数量
+    pub async fn del(&mut self, keys: &[&str]) -> Result<u64> {
+        self.query(Cmd::new("DEL").args(keys.iter().copied())).await
+    }
+
+    pub async fn incr(&mut self, key: &str) -> Result<i64> {
+        self.query(Cmd::new("INCR").arg(key)).await
+    }
+
+    pub async fn incr_by(&mut self, key: &str, delta: i64) -> Result<i64> {
+        self.query(Cmd::new("INCRBY").arg(key).arg(delta)).await
+    }
+
+    /// 发布消息，返回收到消息的订阅数，通过多个模式订阅的连接每个模式计一次
+    pub async fn publish(&mut self, channel: &str, message: impl ToArg) -> Result<i64> {
+        self.query(Cmd::new("PUBLISH").arg(channel).arg(message))
+            .await
+    }
+
+    /// 按 [`STREAMING_CHUNK`] 分段读取 `key` 的值，见 [`Client::get_streaming_chunked`]
+    pub fn get_streaming(&mut self, key: &str) -> impl futures::Stream<Item = Result<Bytes>> + '_ {
+        self.get_streaming_chunked(key, STREAMING_CHUNK)
+    }
+
+    /// 用 GETRANGE 每次读取 `chunk` 字节，得到由值的各段组成的流，不需要把整个值放在内存中
+    ///
+    /// key 不存在或者值为空时流为空。各段分别读取，读取期间值被修改时得到的内容可能前后不一致，
+    /// 需要一致的快照时先 RENAME 或者 DUMP 到不会被修改的 key。
+    pub fn get_streaming_chunked(
+        &mut self,
+        key: &str,
+        chunk: usize,
+    ) -> impl futures::Stream<Item = Result<Bytes>> + '_ {
+        range::range_stream(self, key, chunk)
+    }
+
+    /// 用 SCAN 遍历匹配 `pattern` 的 key，自动处理游标
+    ///
+    /// 与 SCAN 相同，遍历期间被修改的 key 可能重复返回或者被漏掉。流借用客户端，读完或者
+    /// 释放之前不能发送其他命令。
+    pub fn scan_match(&mut self, pattern: &str) -> impl futures::Stream<Item = Result<Bytes>> + '_ {
+        let pattern = pattern.to_string();
+        let cmd = move |cursor| scan::scan_cmd("SCAN", None, &pattern, cursor);
+        scan::cursor_stream(self, cmd, scan::members)
+    }
+
+    /// 用 SSCAN 遍历集合 `key` 中匹配 `pattern` 的成员
+    pub fn sscan_match(
+        &mut self,
+        key: &str,
+        pattern: &str,
+    ) -> impl futures::Stream<Item = Result<Bytes>> + '_ {
+        let (key, pattern) = (key.to_string(), pattern.to_string());
+        let cmd = move |cursor| scan::scan_cmd("SSCAN", Some(&key), &pattern, cursor);
+        scan::cursor_stream(self, cmd, scan::members)
+    }
+
+    /// 用 HSCAN 遍历哈希 `key` 中 field 匹配 `pattern` 的 `(field, value)`
+    pub fn hscan_match(
+        &mut self,
+        key: &str,
+        pattern: &str,
+    ) -> impl futures::Stream<Item = Result<(Bytes, Bytes)>> + '_ {
+        let (key, pattern) = (key.to_string(), pattern.to_string());
+        let cmd = move |cursor| scan::scan_cmd("HSCAN", Some(&key), &pattern, cursor);
+        scan::cursor_stream(self, cmd, scan::pairs)
+    }
+
+    pub async fn rename(&mut self, key: &str, new_key: &str) -> Result<()> {
+        ok(self
+            .execute(Cmd::new("RENAME").arg(key).arg(new_key))
+            .await?)
+    }
+}
+
+/// 超过 `timeout` 时返回 [`ClientError::Timeout`]
+async fn within<T>(timeout: Option<Duration>, f: impl Future<Output = Result<T>>) -> Result<T> {
+    match timeout {
+        Some(timeout) => time::timeout(timeout, f)
+            .await
+            .unwrap_or(Err(ClientError::Timeout)),
+        None => f.await,
+    }
+}
+
+async fn round_trip(connection: &mut Connection<Stream>, frame: &Frame) -> Result<Frame> {
+    connection.write_frame(frame).await?;
+    read_reply(connection).await
+}
+
+async fn batch(connection: &mut Connection<Stream>, cmds: &[Cmd]) -> Result<Vec<Frame>> {
+    for cmd in cmds {
+        connection.queue_frame(&cmd.to_frame());
+    }
+    connection.flush().await?;
+    let mut replies = Vec::with_capacity(cmds.len());
+    for _ in cmds {
+        replies.push(read_reply(connection).await?);
+    }
+    Ok(replies)
+}
+
+async fn read_reply(connection: &mut Connection<Stream>) -> Result<Frame> {
+    connection
+        .read_frame()
+        .await?
+        .ok_or(ClientError::ConnectionClosed)
+}
+
+/// 握手命令的回复，错误回复转换为 [`ClientError::Server`]
+fn expect_ok(frame: Frame) -> Result<()> {
+    match frame {
+        Frame::Error(e) => Err(ClientError::Server(e)),
+        frame => ok(frame),
+    }
+}
+
+fn ok(frame: Frame) -> Result<()> {
+    match frame {
+        Frame::Simple(_) => Ok(()),
+        frame => Err(ClientError::UnexpectedReply(frame)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use bytes::Bytes;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::server;
+
+    pub(super) async fn start_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { server::run(listener, std::future::pending::<()>()).await });
+        addr
+    }
+
+    #[tokio::test]
+    async fn typed_commands() {
+        let mut client = Client::connect(start_server().await).await.unwrap();
+        client.ping().await.unwrap();
+        assert_eq!(client.get::<Option<Bytes>>("a").await.unwrap(), None);
+        client.set("a", 1).await.unwrap();
+        assert_eq!(client.get::<Bytes>("a").await.unwrap(), "1");
+        assert_eq!(client.incr_by("a", 41).await.unwrap(), 42);
+        client.rename("a", "b").await.unwrap();
+        assert_eq!(client.del(&["a", "b"]).await.unwrap(), 1);
+
+        // 错误回复不影响连接继续使用
+        client.set("s", "x").await.unwrap();
+        let err = client.incr("s").await.unwrap_err();
+        assert!(matches!(err, ClientError::Server(msg) if msg.starts_with("ERR")));
+        client.select(1).await.unwrap();
+        assert_eq!(client.get::<Option<String>>("s").await.unwrap(), None);
+        let err = client.get::<i64>("s").await.unwrap_err();
+        assert!(matches!(err, ClientError::Conversion(Frame::Null, "i64")));
+    }
+
+    /// 通过另一个连接用 CLIENT KILL 关闭 `client` 的连接
+    pub(super) async fn kill_connection(client: &mut Client, addr: SocketAddr) {
+        let Frame::Integer(id) = client.execute(Cmd::new("CLIENT").arg("ID")).await.unwrap() else {
+            panic!("expected an integer");
+        };
+        let mut admin = Client::connect(addr).await.unwrap();
+        let kill = Cmd::new("CLIENT").arg("KILL").arg("ID").arg(id);
+        admin.execute(kill).await.unwrap();
+        time::sleep(Duration::from_millis(50)).await;
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_the_connection_is_killed() {
+        let addr = start_server().await;
+        let mut client = Client::connect(addr).await.unwrap();
+        client.select(2).await.unwrap();
+        client.set("a", "1").await.unwrap();
+
+        // 幂等的命令在重新连接后重新发送，数据库恢复为之前选择的
+        kill_connection(&mut client, addr).await;
+        assert_eq!(client.get::<i64>("a").await.unwrap(), 1);
+
+        // 其他命令把错误返回给调用者，下一条命令重新连接
+        kill_connection(&mut client, addr).await;
+        assert!(client.incr("a").await.unwrap_err().is_disconnect());
+        assert_eq!(client.incr("a").await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn close_waits_for_the_server() {
+        let addr = start_server().await;
+        let mut admin = Client::connect(addr).await.unwrap();
+        let clients = || async {
+            let list: Bytes = Client::connect(addr)
+                .await
+                .unwrap()
+                .query(Cmd::new("CLIENT").arg("LIST"))
+                .await
+                .unwrap();
+            list.split(|&b| b == b'\n')
+                .filter(|l| !l.is_empty())
+                .count()
+        };
+        let mut client = Client::connect(addr).await.unwrap();
+        client.set("a", 1).await.unwrap();
+        assert_eq!(clients().await, 3);
+        client.close().await.unwrap();
+        time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(clients().await, 2);
+
+        // 服务端已经关闭的连接同样正常返回
+        kill_connection(&mut admin, addr).await;
+        admin.close().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn connects_over_a_unix_socket() {
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let path = std::env::temp_dir().join(format!("ilearn-client-{}.sock", std::process::id()));
+        let listeners = vec![
+            server::Listener::Tcp(tcp),
+            server::Listener::unix(&path).unwrap(),
+        ];
+        let config = crate::config::Config::default();
+        tokio::spawn(async move {
+            server::run_listeners(listeners, &config, std::future::pending::<()>()).await
+        });
+
+        let mut client = Client::connect_unix(&path).await.unwrap();
+        client.set("a", 1).await.unwrap();
+        let mut tcp = Client::connect(addr).await.unwrap();
+        assert_eq!(tcp.get::<i64>("a").await.unwrap(), 1);
+
+        // 重新连接时同样使用 unix socket
+        kill_connection(&mut client, addr).await;
+        assert_eq!(client.get::<i64>("a").await.unwrap(), 1);
+
+        let url = format!("redis+unix://{}?db=1", path.display());
+        let mut client = Client::connect_url(&url).await.unwrap();
+        assert_eq!(client.get::<Option<i64>>("a").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn timeouts_and_retries() {
+        // 第一条命令回复 LOADING，之后的命令不回复
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            let mut replies = vec![Frame::Simple("PONG".into()), Frame::Error("LOADING".into())];
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let mut connection = Connection::new(socket);
+                while let Some(reply) = replies.pop() {
+                    connection.read_frame().await.unwrap();
+                    connection.write_frame(&reply).await.unwrap();
+                }
+                connections.push(connection);
+            }
+        });
+
+        let mut client = Client::connect(addr).await.unwrap();
+        client.set_timeout(Some(Duration::from_millis(20)));
+        client.ping().await.unwrap();
+        let err = client.get::<Bytes>("a").await.unwrap_err();
+        assert!(matches!(err, ClientError::Timeout));
+        // 超时的连接被关闭，下一条命令重新连接
+        client.set_retry_policy(RetryPolicy::never());
+        assert!(matches!(client.incr("a").await, Err(ClientError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn cancelled_commands_do_not_leave_stale_replies() {
+        // 每条命令在 50ms 后回复命令名
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut connection = Connection::new(socket);
+                    while let Ok(Some(Frame::Array(parts))) = connection.read_frame().await {
+                        let Some(Frame::Bulk(name)) = parts.first() else {
+                            break;
+                        };
+                        let reply = Frame::Simple(String::from_utf8_lossy(name).into_owned());
+                        time::sleep(Duration::from_millis(50)).await;
+                        if connection.write_frame(&reply).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut client = Client::connect(addr).await.unwrap();
+        let first = time::timeout(Duration::from_millis(10), client.execute(Cmd::new("FIRST")));
+        assert!(first.await.is_err());
+        // 被取消的命令的回复已经到达，下一条命令不能读到它
+        time::sleep(Duration::from_millis(100)).await;
+        let reply = client.execute(Cmd::new("SECOND")).await.unwrap();
+        assert_eq!(reply, Frame::Simple("SECOND".into()));
+    }
+}
@@ -0,0 +1,372 @@
+use std::{io, sync::Arc};
+
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit, Semaphore};
+use tracing::Instrument;
+
+use super::{ok, trace, Client, ClientError, Cmd, FromValue, Result, ToArg, Tracer};
+use crate::frame::Frame;
+
+/// 一次写入连接的最多命令数
+const MAX_BATCH: usize = 256;
+
+/// 等待回复的命令达到上限时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WhenFull {
+    /// 等待之前的命令完成
+    #[default]
+    Wait,
+    /// 立即返回 [`ClientError::TooManyInFlight`]
+    Error,
+}
+
+/// 见 [`Client::multiplexed_with`]
+#[derive(Debug, Clone)]
+pub struct MultiplexedOptions {
+    /// 最多同时等待回复的命令数，包括还没有写入连接的命令
+    pub max_in_flight: usize,
+    pub when_full: WhenFull,
+}
+
+impl Default for MultiplexedOptions {
+    fn default() -> MultiplexedOptions {
+        MultiplexedOptions {
+            max_in_flight: 1024,
+            when_full: WhenFull::Wait,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Request {
+    cmd: Cmd,
+    reply: oneshot::Sender<Result<Frame>>,
+    /// 后台任务处理完这条命令时释放，调用者放弃等待时不释放
+    _permit: OwnedSemaphorePermit,
+}
+
+/// 多个任务共用一条连接的客户端，见 [`Client::multiplexed`]
+///
+/// 连接由后台任务持有，调用者把命令和接收回复的 oneshot 通过通道发给它。后台任务把等待中的
+/// 命令一次写入连接，按顺序读取回复后分别交给调用者，因此并发的调用者越多，每条命令分摊的往返越少。
+/// clone 得到的句柄共用同一条连接，所有句柄都释放后后台任务用 [`Client::close`] 关闭连接并退出，
+/// 也可以用 [`Multiplexed::close`] 主动关闭。
+///
+/// 连接断开时同一批的命令都返回错误，不知道其中哪些已经执行，因此不重新发送。
+///
+/// 同时等待回复的命令数受 [`MultiplexedOptions::max_in_flight`] 限制，达到上限时按
+/// [`WhenFull`] 等待或者返回错误，某个调用者发送过多的命令时不会让后台任务积压无限多的回复。
+#[derive(Debug, Clone)]
+pub struct Multiplexed {
+    requests: mpsc::UnboundedSender<Request>,
+    /// 通知后台任务关闭连接，完成后通过 oneshot 返回结果
+    close: mpsc::UnboundedSender<oneshot::Sender<Result<()>>>,
+    /// 每条等待回复的命令持有一个 permit
+    permits: Arc<Semaphore>,
+    options: MultiplexedOptions,
+    tracer: Tracer,
+}
+
+impl Multiplexed {
+    pub(super) fn new(client: Client, options: MultiplexedOptions) -> Multiplexed {
+        let (requests, rx) = mpsc::unbounded_channel();
+        let (close, close_rx) = mpsc::unbounded_channel();
+        let tracer = client.tracer.clone();
+        tokio::spawn(run(client, rx, close_rx));
+        Multiplexed {
+            requests,
+            close,
+            permits: Arc::new(Semaphore::new(options.max_in_flight)),
+            options,
+            tracer,
+        }
+    }
+
+    /// 当前等待回复的命令数
+    pub fn in_flight(&self) -> usize {
+        self.options.max_in_flight - self.permits.available_permits()
+    }
+
+    /// 关闭共用的连接，所有句柄之后的命令都返回 [`ClientError::ConnectionClosed`]
+    ///
+    /// 已经发出的命令先执行完并收到回复，然后后台任务用 [`Client::close`] 关闭连接并退出。
+    pub async fn close(self) -> Result<()> {
+        let (done, rx) = oneshot::channel();
+        self.close
+            .send(done)
+            .map_err(|_| ClientError::ConnectionClosed)?;
+        rx.await.map_err(|_| ClientError::ConnectionClosed)?
+    }
+
+    /// 发送一条命令并等待回复，错误回复转换为 [`ClientError::Server`]
+    ///
+    /// 与 [`Client::execute`] 相同，在调用者的任务中创建 `command` span，包括等待后台任务的时间。
+    pub async fn execute(&self, cmd: Cmd) -> Result<Frame> {
+        let span = self.tracer.command(&cmd);
+        let result = self.send(cmd).instrument(span.clone()).await;
+        trace::record(&span, &result);
+        result
+    }
+
+    async fn send(&self, cmd: Cmd) -> Result<Frame> {
+        let permits = Arc::clone(&self.permits);
+        let permit = match self.options.when_full {
+            WhenFull::Wait => permits
+                .acquire_owned()
+                .await
+                .expect("multiplexed semaphore is never closed"),
+            WhenFull::Error => permits
+                .try_acquire_owned()
+                .map_err(|_| ClientError::TooManyInFlight)?,
+        };
+        let (reply, rx) = oneshot::channel();
+        self.requests
+            .send(Request {
+                cmd,
+                reply,
+                _permit: permit,
+            })
+            .map_err(|_| ClientError::ConnectionClosed)?;
+        match rx.await.map_err(|_| ClientError::ConnectionClosed)?? {
+            Frame::Error(e) => Err(ClientError::Server(e)),
+            frame => Ok(frame),
+        }
+    }
+
+    /// 发送一条命令，回复转换为 `T`
+    pub async fn query<T: FromValue>(&self, cmd: Cmd) -> Result<T> {
+        T::from_value(self.execute(cmd).await?)
+    }
+
+    pub async fn ping(&self) -> Result<()> {
+        ok(self.execute(Cmd::new("PING")).await?)
+    }
+
+    pub async fn get<T: FromValue>(&self, key: &str) -> Result<T> {
+        self.query(Cmd::new("GET").arg(key)).await
+    }
+
+    pub async fn set(&self, key: &str, value: impl ToArg) -> Result<()> {
+        ok(self.execute(Cmd::new("SET").arg(key).arg(value)).await?)
+    }
+
+    pub async fn del(&self, keys: &[&str]) -> Result<u64> {
+        self.query(Cmd::new("DEL").args(keys.iter().copied())).await
+    }
+
+    pub async fn incr(&self, key: &str) -> Result<i64> {
+        self.query(Cmd::new("INCR").arg(key)).await
+    }
+
+    pub async fn incr_by(&self, key: &str, delta: i64) -> Result<i64> {
+        self.query(Cmd::new("INCRBY").arg(key).arg(delta)).await
+    }
+}
+
+/// 后台任务：取出所有等待中的命令一次发送，把回复按顺序交给调用者
+async fn run(
+    mut client: Client,
+    mut requests: mpsc::UnboundedReceiver<Request>,
+    mut close: mpsc::UnboundedReceiver<oneshot::Sender<Result<()>>>,
+) {
+    let mut batch = Vec::with_capacity(MAX_BATCH);
+    loop {
+        tokio::select! {
+            biased;
+            Some(done) = close.recv() => {
+                // 不再接受新的命令，通道中已有的命令执行完后关闭连接
+                requests.close();
+                while requests.recv_many(&mut batch, MAX_BATCH).await > 0 {
+                    send_batch(&mut client, &mut batch).await;
+                }
+                let _ = done.send(client.close().await);
+                return;
+            }
+            received = requests.recv_many(&mut batch, MAX_BATCH) => {
+                if received == 0 {
+                    // 所有句柄都已经释放
+                    let _ = client.close().await;
+                    return;
+                }
+                send_batch(&mut client, &mut batch).await;
+            }
+        }
+    }
+}
+
+/// 发送一批命令，回复或者错误交给每个调用者，`batch` 被清空
+async fn send_batch(client: &mut Client, batch: &mut Vec<Request>) {
+    // 等待回复时调用者已经放弃的命令不需要发送
+    batch.retain(|request| !request.reply.is_closed());
+    if batch.is_empty() {
+        return;
+    }
+    let cmds: Vec<Cmd> = batch.iter().map(|request| request.cmd.clone()).collect();
+    match client.execute_batch(&cmds).await {
+        Ok(replies) => {
+            for (request, reply) in batch.drain(..).zip(replies) {
+                let _ = request.reply.send(Ok(reply));
+            }
+        }
+        Err(e) => {
+            for request in batch.drain(..) {
+                let _ = request.reply.send(Err(duplicate(&e)));
+            }
+        }
+    }
+}
+
+/// 同一批的每个调用者都需要一份错误
+pub(super) fn duplicate(e: &ClientError) -> ClientError {
+    match e {
+        ClientError::Io(e) => ClientError::Io(io::Error::new(e.kind(), e.to_string())),
+        ClientError::Server(e) => ClientError::Server(e.clone()),
+        ClientError::UnexpectedReply(frame) => ClientError::UnexpectedReply(frame.clone()),
+        ClientError::Conversion(frame, ty) => ClientError::Conversion(frame.clone(), ty),
+        ClientError::ConnectionClosed => ClientError::ConnectionClosed,
+        ClientError::Timeout => ClientError::Timeout,
+        ClientError::TransactionAborted => ClientError::TransactionAborted,
+        ClientError::InvalidUrl(url) => ClientError::InvalidUrl(url.clone()),
+        ClientError::TooManyInFlight => ClientError::TooManyInFlight,
+        // serde_json::Error 不能 clone，只保留消息
+        #[cfg(feature = "serde_json")]
+        ClientError::Json(e) => ClientError::Json(serde::de::Error::custom(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{
+        net::{TcpListener, TcpStream},
+        time,
+    };
+
+    use super::*;
+    use crate::{client::tests::start_server, connection::Connection};
+
+    /// 每条命令等待 100ms 后回复 PONG
+    async fn slow(mut connection: Connection<TcpStream>) {
+        while let Ok(Some(_)) = connection.read_frame().await {
+            time::sleep(Duration::from_millis(100)).await;
+            let pong = Frame::Simple("PONG".into());
+            connection.write_frame(&pong).await.unwrap();
+        }
+    }
+
+    async fn slow_server(when_full: WhenFull) -> Multiplexed {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            slow(Connection::new(socket)).await;
+        });
+        let options = MultiplexedOptions {
+            max_in_flight: 2,
+            when_full,
+        };
+        Client::connect(addr)
+            .await
+            .unwrap()
+            .multiplexed_with(options)
+    }
+
+    #[tokio::test]
+    async fn concurrent_callers_share_one_connection() {
+        let client = Client::connect(start_server().await).await.unwrap();
+        let shared = client.multiplexed();
+        let tasks: Vec<_> = (0..100u64)
+            .map(|i| {
+                let shared = shared.clone();
+                tokio::spawn(async move {
+                    let key = format!("k{}", i);
+                    shared.set(&key, i).await.unwrap();
+                    shared.incr("n").await.unwrap();
+                    assert_eq!(shared.get::<u64>(&key).await.unwrap(), i);
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(shared.get::<i64>("n").await.unwrap(), 100);
+        assert!(matches!(
+            shared.incr_by("k1", i64::MAX).await,
+            Err(ClientError::Server(_))
+        ));
+        let Frame::Bulk(list) = shared
+            .execute(Cmd::new("CLIENT").arg("LIST"))
+            .await
+            .unwrap()
+        else {
+            panic!("expected a bulk string");
+        };
+        assert_eq!(
+            list.split(|&b| b == b'\n')
+                .filter(|l| !l.is_empty())
+                .count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn in_flight_commands_are_bounded() {
+        let shared = slow_server(WhenFull::Error).await;
+        let pending: Vec<_> = (0..2)
+            .map(|_| {
+                let shared = shared.clone();
+                tokio::spawn(async move { shared.ping().await })
+            })
+            .collect();
+        time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(shared.in_flight(), 2);
+        assert!(matches!(
+            shared.ping().await,
+            Err(ClientError::TooManyInFlight)
+        ));
+        for task in pending {
+            task.await.unwrap().unwrap();
+        }
+        assert_eq!(shared.in_flight(), 0);
+        shared.ping().await.unwrap();
+
+        // 等待时第三条命令在前两条完成后发送
+        let shared = slow_server(WhenFull::Wait).await;
+        let tasks: Vec<_> = (0..3)
+            .map(|_| {
+                let shared = shared.clone();
+                tokio::spawn(async move { shared.ping().await })
+            })
+            .collect();
+        time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(shared.in_flight(), 2);
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn close_finishes_pending_commands() {
+        let addr = start_server().await;
+        let shared = Client::connect(addr).await.unwrap().multiplexed();
+        let other = shared.clone();
+        let pending: Vec<_> = (0..10)
+            .map(|_| {
+                let shared = shared.clone();
+                tokio::spawn(async move { shared.incr("n").await })
+            })
+            .collect();
+        tokio::task::yield_now().await;
+        shared.close().await.unwrap();
+        for task in pending {
+            task.await.unwrap().unwrap();
+        }
+        assert!(matches!(
+            other.ping().await,
+            Err(ClientError::ConnectionClosed)
+        ));
+
+        let mut admin = Client::connect(addr).await.unwrap();
+        assert_eq!(admin.get::<i64>("n").await.unwrap(), 10);
+    }
+}
@@ -0,0 +1,256 @@
+use std::time::Duration;
+
+use super::{Client, ClientError, Cmd, FromValue, Result, ToArg};
+use crate::frame::Frame;
+
+/// 一次发送的多条命令，见 [`Client::pipeline`] 和 [`Client::transaction`]
+///
+/// 先写入所有命令再读取回复，多条命令只需要等待一次往返。命令很多时每 [`Pipeline::CHUNK`]
+/// 条读取一次回复，避免两端都在等对方读取、写缓冲区被填满。
+///
+/// 事务模式下命令放在 MULTI 和 EXEC 之间一次写入，服务端回复的 QUEUED 被丢弃，结果取自
+/// EXEC 的回复。入队时被拒绝的命令使整个事务被丢弃，返回该命令的错误；WATCH 的 key
+/// 被修改时 EXEC 回复 Null，返回 [`ClientError::TransactionAborted`]。
+#[derive(Debug)]
+pub struct Pipeline<'a> {
+    client: &'a mut Client,
+    cmds: Vec<Cmd>,
+    /// 是否用 MULTI/EXEC 包裹
+    atomic: bool,
+}
+
+impl<'a> Pipeline<'a> {
+    /// 读取一次回复之前最多写入的命令数
+    pub const CHUNK: usize = 1024;
+
+    pub(super) fn new(client: &'a mut Client, atomic: bool) -> Pipeline<'a> {
+        Pipeline {
+            client,
+            cmds: Vec::new(),
+            atomic,
+        }
+    }
+
+    pub fn cmd(mut self, cmd: Cmd) -> Pipeline<'a> {
+        self.cmds.push(cmd);
+        self
+    }
+
+    pub fn ping(self) -> Pipeline<'a> {
+        self.cmd(Cmd::new("PING"))
+    }
+
+    pub fn get(self, key: &str) -> Pipeline<'a> {
+        self.cmd(Cmd::new("GET").arg(key))
+    }
+
+    pub fn set(self, key: &str, value: impl ToArg) -> Pipeline<'a> {
+        self.cmd(Cmd::new("SET").arg(key).arg(value))
+    }
+
+    pub fn set_expires(self, key: &str, value: impl ToArg, expire: Duration) -> Pipeline<'a> {
+        let millis = expire.as_millis() as u64;
+        self.cmd(Cmd::new("SET").arg(key).arg(value).arg("PX").arg(millis))
+    }
+
+    pub fn del(self, keys: &[&str]) -> Pipeline<'a> {
+        self.cmd(Cmd::new("DEL").args(keys.iter().copied()))
+    }
+
+    pub fn incr(self, key: &str) -> Pipeline<'a> {
+        self.cmd(Cmd::new("INCR").arg(key))
+    }
+
+    pub fn incr_by(self, key: &str, delta: i64) -> Pipeline<'a> {
+        self.cmd(Cmd::new("INCRBY").arg(key).arg(delta))
+    }
+
+    pub fn rename(self, key: &str, new_key: &str) -> Pipeline<'a> {
+        self.cmd(Cmd::new("RENAME").arg(key).arg(new_key))
+    }
+
+    pub fn len(&self) -> usize {
+        self.cmds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cmds.is_empty()
+    }
+
+    /// 按命令的顺序返回回复，组成数组后转换为 `T`，通常是元组或者 `Vec`
+    ///
+    /// 所有回复都读取之后，如果其中有错误回复，返回第一个错误回复对应的 [`ClientError::Server`]。
+    pub async fn execute<T: FromValue>(self) -> Result<T> {
+        if self.atomic {
+            return self.execute_atomic().await;
+        }
+        let mut replies = Vec::with_capacity(self.cmds.len());
+        for chunk in self.cmds.chunks(Self::CHUNK) {
+            replies.extend(self.client.execute_batch(chunk).await?);
+        }
+        into_value(replies)
+    }
+
+    async fn execute_atomic<T: FromValue>(self) -> Result<T> {
+        let mut cmds = Vec::with_capacity(self.cmds.len() + 2);
+        cmds.push(Cmd::new("MULTI"));
+        cmds.extend(self.cmds);
+        cmds.push(Cmd::new("EXEC"));
+        let mut replies = self.client.execute_batch(&cmds).await?;
+        let exec = replies.pop().expect("one reply per command");
+        // MULTI 本身失败，或者入队时被拒绝的命令
+        let rejected = replies.into_iter().find_map(|reply| match reply {
+            Frame::Error(e) => Some(e),
+            _ => None,
+        });
+        match (rejected, exec) {
+            (Some(e), _) | (None, Frame::Error(e)) => Err(ClientError::Server(e)),
+            (None, Frame::Array(results)) => into_value(results),
+            (None, Frame::Null) => Err(ClientError::TransactionAborted),
+            (None, frame) => Err(ClientError::UnexpectedReply(frame)),
+        }
+    }
+}
+
+/// 有错误回复时返回第一个，否则把所有回复组成数组转换为 `T`
+fn into_value<T: FromValue>(replies: Vec<Frame>) -> Result<T> {
+    let error = replies.iter().find_map(|reply| match reply {
+        Frame::Error(e) => Some(e.clone()),
+        _ => None,
+    });
+    match error {
+        Some(e) => Err(ClientError::Server(e)),
+        None => T::from_value(Frame::Array(replies)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{client::tests::start_server, connection::Connection};
+
+    #[tokio::test]
+    async fn replies_follow_command_order() {
+        let mut client = Client::connect(start_server().await).await.unwrap();
+        let replies: Vec<Frame> = client
+            .pipeline()
+            .set("a", "1")
+            .incr("b")
+            .get("a")
+            .get("missing")
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(
+            replies,
+            vec![
+                Frame::Simple("OK".into()),
+                Frame::Integer(1),
+                Frame::Bulk("1".into()),
+                Frame::Null,
+            ]
+        );
+
+        // 超过一批的命令分多次读取回复
+        let mut pipeline = client.pipeline();
+        for _ in 0..Pipeline::CHUNK * 2 + 1 {
+            pipeline = pipeline.incr("n");
+        }
+        let replies: Vec<i64> = pipeline.execute().await.unwrap();
+        assert_eq!(replies.last(), Some(&2049));
+
+        // 回复转换为元组
+        let (ok, n, missing): ((), i64, Option<String>) = client
+            .pipeline()
+            .set("c", 1)
+            .incr("c")
+            .get("missing")
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!((ok, n, missing), ((), 2, None));
+
+        // 错误回复不影响之后的命令执行，连接仍然可用
+        let err = client
+            .pipeline()
+            .incr("a")
+            .incr("a")
+            .set("a", "x")
+            .incr("a");
+        assert!(matches!(
+            err.execute::<()>().await,
+            Err(ClientError::Server(_))
+        ));
+        assert_eq!(client.get::<String>("a").await.unwrap(), "x");
+    }
+
+    /// 只支持事务的服务端：EXEC 回复每条入队命令的序号，WATCH 之后的事务被放弃
+    async fn serve_transactions(listener: TcpListener) {
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut connection = Connection::new(socket);
+        let (mut queue, mut watching, mut rejected) = (None, false, false);
+        while let Ok(Some(Frame::Array(parts))) = connection.read_frame().await {
+            let name = parts[0].to_string().to_uppercase();
+            let reply = match (&name[..], &mut queue) {
+                ("MULTI", _) => {
+                    queue = Some(Vec::new());
+                    Frame::Simple("OK".into())
+                }
+                ("EXEC", _) => {
+                    let results = queue.take().unwrap();
+                    match (rejected, watching) {
+                        (true, _) => Frame::Error("EXECABORT Transaction discarded".into()),
+                        (_, true) => Frame::Null,
+                        _ => Frame::Array(results),
+                    }
+                }
+                ("WATCH", _) => {
+                    watching = true;
+                    Frame::Simple("OK".into())
+                }
+                ("BAD", Some(_)) => {
+                    rejected = true;
+                    Frame::Error("ERR unknown command 'BAD'".into())
+                }
+                (_, Some(queue)) => {
+                    queue.push(Frame::Integer(queue.len() as i64));
+                    Frame::Simple("QUEUED".into())
+                }
+                _ => Frame::Simple("OK".into()),
+            };
+            if name == "EXEC" {
+                (watching, rejected) = (false, false);
+            }
+            connection.write_frame(&reply).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn transactions_wrap_commands_in_multi_exec() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = Client::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        tokio::spawn(serve_transactions(listener));
+
+        let results: (i64, i64, i64) = client
+            .transaction()
+            .set("a", 1)
+            .incr("a")
+            .get("a")
+            .execute()
+            .await
+            .unwrap();
+        assert_eq!(results, (0, 1, 2));
+
+        let rejected = client.transaction().incr("a").cmd(Cmd::new("BAD"));
+        let err = rejected.execute::<()>().await.unwrap_err();
+        assert!(matches!(err, ClientError::Server(e) if e.starts_with("ERR unknown")));
+
+        client.watch(&["a"]).await.unwrap();
+        let aborted = client.transaction().incr("a").execute::<()>().await;
+        assert!(matches!(aborted, Err(ClientError::TransactionAborted)));
+    }
+}
@@ -0,0 +1,370 @@
+//! 连接池
+//!
+//! [`Pool::get`] 取出一个空闲的连接，用 PING 确认连接可用后交给调用者，没有空闲连接时建立新连接，
+//! 连接数达到 `max_size` 时等待其他连接归还。[`PooledClient`] drop 时连接回到池中。
+//! 后台任务关闭空闲超过 `idle_timeout` 的连接，并保持至少 `min_idle` 个空闲连接。
+//! 设置了 `health_check_interval` 时，后台任务还定期向空闲连接发送 PING，提前关闭已经断开的连接，
+//! 空闲一段时间之后的第一条命令不会遇到被服务端或者中间设备关闭的连接。
+//! `timeout` 和 `retry` 设置到池中的每个连接上，`timeout` 同时限制等待连接归还的时间。
+
+use std::{
+    collections::VecDeque,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time,
+};
+use tracing::debug;
+
+use super::{within, Client, ClientError, Result, RetryPolicy};
+
+/// 检查空闲连接的最大间隔
+const REAP_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    /// 最多同时存在的连接数，包括空闲的连接
+    pub max_size: usize,
+    /// 保持的空闲连接数，创建连接池时先建立这些连接
+    pub min_idle: usize,
+    /// 空闲超过这个时间的连接被关闭，`min_idle` 以内的除外
+    pub idle_timeout: Duration,
+    /// 见 [`Client::set_timeout`]
+    pub timeout: Option<Duration>,
+    pub retry: RetryPolicy,
+    /// 空闲连接每隔这个时间 PING 一次，失败的连接被关闭，None 表示不检查
+    pub health_check_interval: Option<Duration>,
+}
+
+impl Default for PoolOptions {
+    fn default() -> PoolOptions {
+        PoolOptions {
+            max_size: 16,
+            min_idle: 0,
+            idle_timeout: Duration::from_secs(300),
+            timeout: None,
+            retry: RetryPolicy::default(),
+            health_check_interval: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+/// 连接池中的连接数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStatus {
+    pub idle: usize,
+    pub in_use: usize,
+}
+
+#[derive(Debug)]
+struct IdleClient {
+    client: Client,
+    since: Instant,
+    /// 最近一次确认连接可用的时间
+    checked: Instant,
+}
+
+#[derive(Debug)]
+struct Shared {
+    addr: String,
+    options: PoolOptions,
+    /// 最近归还的连接在末尾，优先使用
+    idle: Mutex<VecDeque<IdleClient>>,
+    /// 每个取出的连接持有一个 permit
+    permits: Arc<Semaphore>,
+}
+
+impl Shared {
+    fn status(&self) -> PoolStatus {
+        PoolStatus {
+            idle: self.idle.lock().unwrap().len(),
+            in_use: self.options.max_size - self.permits.available_permits(),
+        }
+    }
+
+    /// 关闭空闲太久的连接，保留最近归还的 `min_idle` 个
+    fn reap(&self) {
+        let mut idle = self.idle.lock().unwrap();
+        let expired = idle
+            .iter()
+            .take_while(|entry| entry.since.elapsed() >= self.options.idle_timeout)
+            .count();
+        let reaped = expired.min(idle.len().saturating_sub(self.options.min_idle));
+        if reaped > 0 {
+            idle.drain(..reaped);
+            debug!(addr = %self.addr, reaped, "Closed idle pooled connections");
+        }
+    }
+
+    /// 空闲连接不足 `min_idle` 时建立新连接，总数不超过 `max_size`
+    async fn fill(&self) -> Result<()> {
+        loop {
+            let status = self.status();
+            let total = status.idle + status.in_use;
+            if status.idle >= self.options.min_idle || total >= self.options.max_size {
+                return Ok(());
+            }
+            let client = self.connect().await?;
+            self.release(client);
+        }
+    }
+
+    async fn connect(&self) -> Result<Client> {
+        let mut client = within(self.options.timeout, Client::connect(&self.addr)).await?;
+        client.set_timeout(self.options.timeout);
+        client.set_retry_policy(self.options.retry.clone());
+        Ok(client)
+    }
+
+    fn release(&self, client: Client) {
+        let now = Instant::now();
+        let entry = IdleClient {
+            client,
+            since: now,
+            checked: now,
+        };
+        self.idle.lock().unwrap().push_back(entry);
+    }
+
+    /// PING 超过 `interval` 没有检查的空闲连接，关闭失败的连接
+    ///
+    /// 检查期间连接不在池中，[`Pool::get`] 取不到时建立新连接。不重新连接也不重试，
+    /// 断开的连接直接关闭，由 `fill` 补足 `min_idle`。
+    async fn check(&self, interval: Duration) {
+        let due: VecDeque<IdleClient> = {
+            let mut idle = self.idle.lock().unwrap();
+            let (due, fresh) = idle
+                .drain(..)
+                .partition(|entry| entry.checked.elapsed() >= interval);
+            *idle = fresh;
+            due
+        };
+        if due.is_empty() {
+            return;
+        }
+        let mut alive = Vec::with_capacity(due.len());
+        for mut entry in due {
+            match entry.client.check().await {
+                Ok(()) => {
+                    entry.checked = Instant::now();
+                    alive.push(entry);
+                }
+                Err(e) => debug!(addr = %self.addr, error = %e, "Closed a dead pooled connection"),
+            }
+        }
+        let mut idle = self.idle.lock().unwrap();
+        idle.extend(alive);
+        // 保持按归还时间排序，`reap` 从头部关闭最早归还的连接
+        idle.make_contiguous().sort_by_key(|entry| entry.since);
+    }
+}
+
+/// 连接到同一个服务端的连接池，clone 得到的是同一个池
+#[derive(Debug, Clone)]
+pub struct Pool {
+    shared: Arc<Shared>,
+}
+
+impl Pool {
+    /// 建立 `min_idle` 个连接，失败时返回错误
+    pub async fn new(addr: impl Into<String>, options: PoolOptions) -> Result<Pool> {
+        let max_size = options.max_size;
+        let shared = Arc::new(Shared {
+            addr: addr.into(),
+            options,
+            idle: Mutex::new(VecDeque::new()),
+            permits: Arc::new(Semaphore::new(max_size)),
+        });
+        shared.fill().await?;
+        tokio::spawn(reap_task(Arc::downgrade(&shared)));
+        Ok(Pool { shared })
+    }
+
+    /// 取出一个可用的连接，连接数已经达到上限时等待
+    pub async fn get(&self) -> Result<PooledClient> {
+        let permits = Arc::clone(&self.shared.permits);
+        let permit = match self.shared.options.timeout {
+            Some(timeout) => time::timeout(timeout, permits.acquire_owned())
+                .await
+                .map_err(|_| ClientError::Timeout)?,
+            None => permits.acquire_owned().await,
+        };
+        let permit = permit.expect("pool semaphore is never closed");
+        let client = loop {
+            let entry = self.shared.idle.lock().unwrap().pop_back();
+            let Some(IdleClient {
+                mut client, since, ..
+            }) = entry
+            else {
+                break self.shared.connect().await?;
+            };
+            if since.elapsed() >= self.shared.options.idle_timeout {
+                continue;
+            }
+            match client.ping().await {
+                Ok(()) => break client,
+                Err(e) => debug!(error = %e, "Discarded a broken pooled connection"),
+            }
+        };
+        Ok(PooledClient {
+            client: Some(client),
+            shared: Arc::clone(&self.shared),
+            _permit: permit,
+        })
+    }
+
+    pub fn status(&self) -> PoolStatus {
+        self.shared.status()
+    }
+}
+
+/// 从连接池取出的连接，drop 时归还
+#[derive(Debug)]
+pub struct PooledClient {
+    client: Option<Client>,
+    shared: Arc<Shared>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl PooledClient {
+    /// 关闭连接而不是归还，用于连接状态未知的情况，例如读取回复时被取消
+    pub fn discard(mut self) {
+        self.client.take();
+    }
+}
+
+impl Deref for PooledClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            self.shared.release(client);
+        }
+    }
+}
+
+/// 定期检查空闲连接、关闭空闲太久的连接并补足 `min_idle`，只持有弱引用，连接池释放后退出
+async fn reap_task(shared: Weak<Shared>) {
+    let (period, health_check) = {
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        let health_check = shared.options.health_check_interval;
+        let period = shared.options.idle_timeout.min(REAP_INTERVAL);
+        (
+            health_check.map_or(period, |interval| period.min(interval)),
+            health_check,
+        )
+    };
+    loop {
+        time::sleep(period).await;
+        let Some(shared) = shared.upgrade() else {
+            break;
+        };
+        if let Some(interval) = health_check {
+            shared.check(interval).await;
+        }
+        shared.reap();
+        if let Err(e) = shared.fill().await {
+            debug!(addr = %shared.addr, error = %e, "Failed to refill the connection pool");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::{kill_connection, start_server};
+
+    #[tokio::test]
+    async fn connections_are_reused_and_bounded() {
+        let addr = start_server().await;
+        let options = PoolOptions {
+            max_size: 2,
+            min_idle: 1,
+            ..PoolOptions::default()
+        };
+        let pool = Pool::new(addr.to_string(), options).await.unwrap();
+        assert_eq!(pool.status(), PoolStatus { idle: 1, in_use: 0 });
+
+        pool.get().await.unwrap().set("a", "1").await.unwrap();
+        let mut first = pool.get().await.unwrap();
+        let second = pool.get().await.unwrap();
+        assert_eq!(pool.status(), PoolStatus { idle: 0, in_use: 2 });
+        // 达到上限后等待归还
+        assert!(time::timeout(Duration::from_millis(50), pool.get())
+            .await
+            .is_err());
+        let options = PoolOptions {
+            max_size: 0,
+            timeout: Some(Duration::from_millis(10)),
+            ..PoolOptions::default()
+        };
+        let impatient = Pool::new(addr.to_string(), options).await.unwrap();
+        assert!(matches!(impatient.get().await, Err(ClientError::Timeout)));
+        drop(second);
+        let _third = pool.get().await.unwrap();
+
+        // 被服务端关闭的连接在取出时的 PING 重新连接
+        kill_connection(&mut first, addr).await;
+        drop(first);
+        let mut client = pool.get().await.unwrap();
+        assert_eq!(client.get::<i64>("a").await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn idle_connections_are_reaped() {
+        let addr = start_server().await.to_string();
+        let options = PoolOptions {
+            max_size: 4,
+            min_idle: 1,
+            idle_timeout: Duration::from_millis(20),
+            ..PoolOptions::default()
+        };
+        let pool = Pool::new(addr, options).await.unwrap();
+        let clients = [pool.get().await.unwrap(), pool.get().await.unwrap()];
+        drop(clients);
+        assert_eq!(pool.status().idle, 2);
+        time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(pool.status(), PoolStatus { idle: 1, in_use: 0 });
+    }
+
+    #[tokio::test]
+    async fn dead_idle_connections_are_evicted() {
+        let addr = start_server().await;
+        let options = PoolOptions {
+            health_check_interval: Some(Duration::from_millis(20)),
+            ..PoolOptions::default()
+        };
+        let pool = Pool::new(addr.to_string(), options).await.unwrap();
+        let (mut dead, alive) = (pool.get().await.unwrap(), pool.get().await.unwrap());
+        kill_connection(&mut dead, addr).await;
+        drop((dead, alive));
+        assert_eq!(pool.status().idle, 2);
+
+        // 检查失败的连接被关闭，不会重新连接；并行的测试较多时检查可能推迟
+        for _ in 0..50 {
+            if pool.status().idle == 1 {
+                break;
+            }
+            time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(pool.status(), PoolStatus { idle: 1, in_use: 0 });
+    }
+}
@@ -0,0 +1,110 @@
+use bytes::Bytes;
+use futures::{stream, Stream};
+
+use super::{Client, Cmd, Result};
+
+/// [`Client::get_streaming`] 每次 GETRANGE 读取的字节数
+pub const STREAMING_CHUNK: usize = 1024 * 1024;
+
+/// 下一次读取的偏移，None 表示已经读完或者出错
+struct Range<'a> {
+    client: &'a mut Client,
+    key: String,
+    chunk: usize,
+    offset: Option<usize>,
+}
+
+/// 用 GETRANGE 从 `offset` 开始每次读取 `chunk` 字节，读到的字节少于 `chunk` 时结束
+pub(super) fn range_stream<'a>(
+    client: &'a mut Client,
+    key: &str,
+    chunk: usize,
+) -> impl Stream<Item = Result<Bytes>> + 'a {
+    let state = Range {
+        client,
+        key: key.to_string(),
+        chunk: chunk.max(1),
+        offset: Some(0),
+    };
+    stream::unfold(state, |mut state| async move {
+        let offset = state.offset?;
+        let end = offset + state.chunk - 1;
+        let cmd = Cmd::new("GETRANGE").arg(&state.key).arg(offset).arg(end);
+        match state.client.query::<Bytes>(cmd).await {
+            Ok(bytes) => {
+                state.offset = (bytes.len() == state.chunk).then_some(offset + state.chunk);
+                if bytes.is_empty() {
+                    return None;
+                }
+                Some((Ok(bytes), state))
+            }
+            Err(e) => {
+                state.offset = None;
+                Some((Err(e), state))
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+    use crate::{connection::Connection, frame::Frame};
+
+    /// 服务端不支持 GETRANGE，只保存一个值 `big`，按 Redis 的规则截取
+    async fn serve(mut connection: Connection<TcpStream>, value: Bytes) {
+        while let Ok(Some(Frame::Array(parts))) = connection.read_frame().await {
+            let args: Vec<String> = parts.iter().map(|part| part.to_string()).collect();
+            let reply = match args[0].as_str() {
+                "GETRANGE" => {
+                    let value = if args[1] == "big" {
+                        &value[..]
+                    } else {
+                        &[][..]
+                    };
+                    let start: usize = args[2].parse().unwrap();
+                    let end: usize = args[3].parse().unwrap();
+                    let range = start.min(value.len())..(end + 1).min(value.len());
+                    Frame::Bulk(Bytes::copy_from_slice(&value[range]))
+                }
+                _ => Frame::Error("ERR unknown command".into()),
+            };
+            connection.write_frame(&reply).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn large_values_arrive_in_chunks() {
+        let value: Bytes = (0..2500u32).map(|i| i as u8).collect::<Vec<_>>().into();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let served = value.clone();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            serve(Connection::new(socket), served).await;
+        });
+
+        let mut client = Client::connect(addr).await.unwrap();
+        let chunks: Vec<Bytes> = client
+            .get_streaming_chunked("big", 1000)
+            .try_collect()
+            .await
+            .unwrap();
+        let lens: Vec<usize> = chunks.iter().map(Bytes::len).collect();
+        assert_eq!(lens, [1000, 1000, 500]);
+        assert_eq!(chunks.concat(), value);
+
+        // 长度刚好是整数倍时多读一次空的范围
+        let chunks: Vec<Bytes> = client
+            .get_streaming_chunked("big", 500)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(chunks.len(), 5);
+        let missing: Vec<Bytes> = client.get_streaming("missing").try_collect().await.unwrap();
+        assert!(missing.is_empty());
+    }
+}
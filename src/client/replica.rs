@@ -0,0 +1,237 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tracing::debug;
+
+use super::{ClientError, Cmd, FromValue, Pool, PoolOptions, Result};
+use crate::frame::Frame;
+
+/// 副本失败后暂停使用的时间，之后的读命令重新尝试
+const REPLICA_COOLDOWN: Duration = Duration::from_secs(5);
+
+/// 只读命令选择副本的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadFrom {
+    /// 所有命令都发送给主节点
+    Primary,
+    /// 依次使用每个可用的副本
+    #[default]
+    RoundRobin,
+    /// 使用平均往返时间最短的副本，还没有测量过的副本优先
+    LowestLatency,
+}
+
+#[derive(Debug)]
+struct Replica {
+    addr: String,
+    pool: Pool,
+    /// 往返时间的指数移动平均，微秒，0 表示还没有测量
+    latency: AtomicU64,
+    /// 失败后在这个时间之前不再使用
+    down_until: Mutex<Option<Instant>>,
+}
+
+impl Replica {
+    fn is_available(&self, now: Instant) -> bool {
+        self.down_until
+            .lock()
+            .unwrap()
+            .is_none_or(|until| now >= until)
+    }
+
+    fn mark_down(&self) {
+        *self.down_until.lock().unwrap() = Some(Instant::now() + REPLICA_COOLDOWN);
+    }
+
+    /// 新的样本占 1/8 的权重
+    fn record(&self, elapsed: Duration) {
+        let sample = (elapsed.as_micros() as u64).max(1);
+        let old = self.latency.load(Ordering::Relaxed);
+        let latency = if old == 0 {
+            sample
+        } else {
+            old - old / 8 + sample / 8
+        };
+        self.latency.store(latency, Ordering::Relaxed);
+    }
+}
+
+/// 主从部署的客户端，写命令发送给主节点，只读命令（见 [`Cmd::is_read_only`]）发送给副本
+///
+/// 每个节点使用一个 [`Pool`]。副本连接失败、断开或者回复 LOADING、MASTERDOWN 时，这条命令改为
+/// 发送给主节点，这个副本暂停使用一段时间。副本的数据可能落后于主节点，写入后需要立即读到的值
+/// 应该用 [`ReplicaClient::primary`] 读取。
+#[derive(Debug)]
+pub struct ReplicaClient {
+    primary: Pool,
+    replicas: Vec<Replica>,
+    read_from: ReadFrom,
+    /// 轮询的计数
+    next: AtomicUsize,
+}
+
+impl ReplicaClient {
+    /// `primary` 和 `replicas` 为 `host:port`，副本不可用时不返回错误，读命令发送给主节点
+    pub async fn connect(
+        primary: &str,
+        replicas: &[&str],
+        options: PoolOptions,
+    ) -> Result<ReplicaClient> {
+        let mut pools = Vec::with_capacity(replicas.len());
+        for &addr in replicas {
+            // 不预先建立连接，不可用的副本在第一次使用时才发现
+            let options = PoolOptions {
+                min_idle: 0,
+                ..options.clone()
+            };
+            pools.push(Replica {
+                addr: addr.to_string(),
+                pool: Pool::new(addr, options).await?,
+                latency: AtomicU64::new(0),
+                down_until: Mutex::new(None),
+            });
+        }
+        Ok(ReplicaClient {
+            primary: Pool::new(primary, options).await?,
+            replicas: pools,
+            read_from: ReadFrom::default(),
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn set_read_from(&mut self, read_from: ReadFrom) {
+        self.read_from = read_from;
+    }
+
+    /// 主节点的连接池，用于需要读到最新数据的命令
+    pub fn primary(&self) -> &Pool {
+        &self.primary
+    }
+
+    /// 发送一条命令，错误回复转换为 [`ClientError::Server`]
+    pub async fn execute(&self, cmd: Cmd) -> Result<Frame> {
+        if cmd.is_read_only() {
+            if let Some(replica) = self.pick() {
+                let start = Instant::now();
+                let result = match replica.pool.get().await {
+                    Ok(mut client) => client.execute(cmd.clone()).await,
+                    Err(e) => Err(e),
+                };
+                match result {
+                    Err(e) if is_unavailable(&e) => {
+                        debug!(replica = %replica.addr, error = %e, "Falling back to the primary");
+                        replica.mark_down();
+                    }
+                    result => {
+                        replica.record(start.elapsed());
+                        return result;
+                    }
+                }
+            }
+        }
+        self.primary.get().await?.execute(cmd).await
+    }
+
+    /// 发送一条命令，回复转换为 `T`
+    pub async fn query<T: FromValue>(&self, cmd: Cmd) -> Result<T> {
+        T::from_value(self.execute(cmd).await?)
+    }
+
+    /// 按 [`ReadFrom`] 选择一个可用的副本，没有时返回 None
+    fn pick(&self) -> Option<&Replica> {
+        let now = Instant::now();
+        let available: Vec<&Replica> = self
+            .replicas
+            .iter()
+            .filter(|replica| replica.is_available(now))
+            .collect();
+        if available.is_empty() {
+            return None;
+        }
+        match self.read_from {
+            ReadFrom::Primary => None,
+            ReadFrom::RoundRobin => {
+                let next = self.next.fetch_add(1, Ordering::Relaxed);
+                Some(available[next % available.len()])
+            }
+            ReadFrom::LowestLatency => available
+                .into_iter()
+                .min_by_key(|replica| replica.latency.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// 副本暂时不能执行命令，应该改用主节点
+fn is_unavailable(e: &ClientError) -> bool {
+    match e {
+        ClientError::Server(e) => e.starts_with("LOADING") || e.starts_with("MASTERDOWN"),
+        e => e.is_disconnect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::client::{tests::start_server, Client};
+
+    /// 每个服务端保存不同的值，从回复可以看出命令由哪个节点执行
+    async fn node(name: &str) -> String {
+        let addr = start_server().await;
+        let mut client = Client::connect(addr).await.unwrap();
+        client.set("who", name).await.unwrap();
+        addr.to_string()
+    }
+
+    async fn who(client: &ReplicaClient) -> String {
+        client.query(Cmd::new("GET").arg("who")).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn reads_go_to_replicas_and_writes_to_the_primary() {
+        let primary = node("primary").await;
+        let (a, b) = (node("a").await, node("b").await);
+        let mut client = ReplicaClient::connect(&primary, &[&a, &b], PoolOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(who(&client).await, "a");
+        assert_eq!(who(&client).await, "b");
+        assert_eq!(who(&client).await, "a");
+
+        client
+            .query::<()>(Cmd::new("SET").arg("who").arg("written"))
+            .await
+            .unwrap();
+        let mut primary = Client::connect(&primary).await.unwrap();
+        assert_eq!(primary.get::<String>("who").await.unwrap(), "written");
+
+        client.set_read_from(ReadFrom::Primary);
+        assert_eq!(who(&client).await, "written");
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_primary_when_a_replica_is_down() {
+        let primary = node("primary").await;
+        let alive = node("alive").await;
+        let dead = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        let mut client = ReplicaClient::connect(&primary, &[&dead, &alive], PoolOptions::default())
+            .await
+            .unwrap();
+        // 第一次轮到不可用的副本，改为由主节点执行，之后跳过这个副本
+        assert_eq!(who(&client).await, "primary");
+        assert_eq!(who(&client).await, "alive");
+        assert_eq!(who(&client).await, "alive");
+
+        client.set_read_from(ReadFrom::LowestLatency);
+        assert_eq!(who(&client).await, "alive");
+    }
+}
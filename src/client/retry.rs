@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use super::{ClientError, Cmd};
+
+/// 连接断开后重新连接的次数和间隔
+///
+/// 第一次立即重新连接，之后第 n 次失败后等待 `base_delay * 2^n`，不超过 `max_delay`，
+/// 实际等待的时间在这个值的一半到全部之间随机选取，避免大量客户端同时重新连接。
+#[derive(Debug, Clone)]
+pub struct ReconnectOptions {
+    /// 第一次重新连接失败之后最多再尝试的次数，0 表示只尝试一次
+    pub retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for ReconnectOptions {
+    fn default() -> ReconnectOptions {
+        ReconnectOptions {
+            retries: 5,
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+impl ReconnectOptions {
+    /// 第 `attempt` 次（从 0 开始）失败之后等待的时间
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        jittered(self.base_delay, self.max_delay, attempt)
+    }
+}
+
+/// 一条命令失败后是否重新发送
+///
+/// 每次失败后用 `retryable` 判断，能重试时按与 [`ReconnectOptions`] 相同的方式等待后重新发送，
+/// 最多执行 `max_attempts` 次。默认的 [`RetryPolicy::default_retryable`] 只重试不会重复执行的情况。
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// 包括第一次在内最多执行的次数，1 表示不重试
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub retryable: fn(&Cmd, &ClientError) -> bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 2,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(1),
+            retryable: RetryPolicy::default_retryable,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 不重试任何命令
+    pub fn never() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// 连接断开或者超时后重试幂等的命令；服务端还在加载数据（LOADING）、
+    /// 要求稍后重试（TRYAGAIN）时命令没有执行，任何命令都可以重试
+    pub fn default_retryable(cmd: &Cmd, error: &ClientError) -> bool {
+        match error {
+            ClientError::Server(e) => e.starts_with("LOADING") || e.starts_with("TRYAGAIN"),
+            e if e.is_disconnect() => cmd.is_idempotent(),
+            _ => false,
+        }
+    }
+
+    /// 第 `attempt` 次（从 1 开始）执行失败之后等待的时间
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        jittered(self.base_delay, self.max_delay, attempt.saturating_sub(1))
+    }
+}
+
+/// `base * 2^attempt`，不超过 `max`，在一半到全部之间随机选取
+fn jittered(base: Duration, max: Duration, attempt: u32) -> Duration {
+    let delay = base.saturating_mul(1 << attempt.min(16)).min(max);
+    rand::thread_rng().gen_range(delay / 2..=delay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_up_to_the_limit() {
+        let options = ReconnectOptions::default();
+        for _ in 0..100 {
+            let first = options.backoff(0);
+            assert!(first >= Duration::from_millis(25) && first <= Duration::from_millis(50));
+            let third = options.backoff(2);
+            assert!(third >= Duration::from_millis(100) && third <= Duration::from_millis(200));
+            assert!(options.backoff(40) <= options.max_delay);
+        }
+    }
+
+    #[test]
+    fn default_policy_retries_only_safe_failures() {
+        let retryable = RetryPolicy::default().retryable;
+        let (get, incr) = (Cmd::new("get").arg("a"), Cmd::new("INCR").arg("a"));
+        assert!(retryable(&get, &ClientError::Timeout));
+        assert!(!retryable(&incr, &ClientError::ConnectionClosed));
+        assert!(retryable(&incr, &ClientError::Server("LOADING".into())));
+        assert!(!retryable(&get, &ClientError::Server("ERR".into())));
+    }
+}
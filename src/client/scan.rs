@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+
+use bytes::Bytes;
+use futures::{stream, Stream};
+
+use super::{Client, ClientError, Cmd, Result};
+use crate::frame::Frame;
+
+/// 驱动游标的状态，`cursor` 为 None 时已经读完最后一页
+struct Cursor<'a, F, P, T> {
+    client: &'a mut Client,
+    cmd: F,
+    page: P,
+    cursor: Option<u64>,
+    buffered: VecDeque<T>,
+}
+
+/// 用 `cmd(cursor)` 逐页读取，直到服务端返回游标 0，`page` 把一页的元素转换为流中的项
+///
+/// 读完一页之后才发送下一页的命令，调用者不再读取时不会继续扫描。出错时返回错误并结束。
+pub(super) fn cursor_stream<'a, F, P, T>(
+    client: &'a mut Client,
+    cmd: F,
+    page: P,
+) -> impl Stream<Item = Result<T>> + 'a
+where
+    F: Fn(u64) -> Cmd + 'a,
+    P: Fn(Vec<Bytes>) -> Result<Vec<T>> + 'a,
+    T: 'a,
+{
+    let state = Cursor {
+        client,
+        cmd,
+        page,
+        cursor: Some(0),
+        buffered: VecDeque::new(),
+    };
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.buffered.pop_front() {
+                return Some((Ok(item), state));
+            }
+            let cursor = state.cursor?;
+            let reply = state
+                .client
+                .query::<(u64, Vec<Bytes>)>((state.cmd)(cursor))
+                .await;
+            match reply.and_then(|(next, items)| Ok((next, (state.page)(items)?))) {
+                Ok((next, items)) => {
+                    state.cursor = Some(next).filter(|next| *next != 0);
+                    state.buffered.extend(items);
+                }
+                Err(e) => {
+                    state.cursor = None;
+                    return Some((Err(e), state));
+                }
+            }
+        }
+    })
+}
+
+/// SCAN 和 SSCAN 的一页直接作为流中的项
+pub(super) fn members(items: Vec<Bytes>) -> Result<Vec<Bytes>> {
+    Ok(items)
+}
+
+/// HSCAN 的一页是 field 和 value 交替的数组
+pub(super) fn pairs(items: Vec<Bytes>) -> Result<Vec<(Bytes, Bytes)>> {
+    if !items.len().is_multiple_of(2) {
+        let frame = Frame::Array(items.into_iter().map(Frame::Bulk).collect());
+        return Err(ClientError::Conversion(frame, "field-value pairs"));
+    }
+    let mut iter = items.into_iter();
+    let mut pairs = Vec::new();
+    while let (Some(field), Some(value)) = (iter.next(), iter.next()) {
+        pairs.push((field, value));
+    }
+    Ok(pairs)
+}
+
+/// 带 MATCH 的游标命令，SCAN 没有 key
+pub(super) fn scan_cmd(name: &str, key: Option<&str>, pattern: &str, cursor: u64) -> Cmd {
+    Cmd::new(name)
+        .args(key)
+        .arg(cursor)
+        .arg("MATCH")
+        .arg(pattern)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use futures::TryStreamExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+    use crate::{client::tests::start_server, connection::Connection};
+
+    #[tokio::test]
+    async fn scan_match_follows_the_cursor() {
+        let mut client = Client::connect(start_server().await).await.unwrap();
+        for i in 0..50 {
+            client.set(&format!("user:{}", i), i).await.unwrap();
+            client.set(&format!("order:{}", i), i).await.unwrap();
+        }
+
+        // 默认 COUNT 为 10，需要多页才能读完
+        let keys: HashSet<Bytes> = client.scan_match("user:*").try_collect().await.unwrap();
+        let expected: HashSet<Bytes> = (0..50).map(|i| format!("user:{}", i).into()).collect();
+        assert_eq!(keys, expected);
+        let none: Vec<Bytes> = client.scan_match("missing:*").try_collect().await.unwrap();
+        assert!(none.is_empty());
+    }
+
+    /// 服务端不支持哈希类型，用两页固定的回复模拟 HSCAN
+    async fn serve_hscan(mut connection: Connection<TcpStream>) {
+        while let Ok(Some(Frame::Array(parts))) = connection.read_frame().await {
+            let page = |cursor: &'static str, items: &[&'static str]| {
+                Frame::Array(vec![
+                    Frame::Bulk(Bytes::from_static(cursor.as_bytes())),
+                    Frame::Array(
+                        items
+                            .iter()
+                            .map(|item| Frame::Bulk(Bytes::from_static(item.as_bytes())))
+                            .collect(),
+                    ),
+                ])
+            };
+            let reply = match parts[2].to_string().as_str() {
+                "0" => page("7", &["a", "1", "b", "2"]),
+                _ => page("0", &["c", "3"]),
+            };
+            connection.write_frame(&reply).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn hscan_yields_field_value_pairs() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            serve_hscan(Connection::new(socket)).await;
+        });
+
+        let mut client = Client::connect(addr).await.unwrap();
+        let fields: Vec<(Bytes, Bytes)> = client.hscan_match("h", "*").try_collect().await.unwrap();
+        let fields: Vec<(&[u8], &[u8])> = fields.iter().map(|(f, v)| (&f[..], &v[..])).collect();
+        assert_eq!(fields, [(&b"a"[..], &b"1"[..]), (b"b", b"2"), (b"c", b"3")]);
+        assert!(pairs(vec![Bytes::from_static(b"a")]).is_err());
+    }
+}
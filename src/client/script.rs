@@ -0,0 +1,260 @@
+use std::fmt::Write;
+
+use bytes::Bytes;
+
+use super::{Client, ClientError, Cmd, FromValue, Result, ToArg};
+use crate::frame::Frame;
+
+/// 一段 Lua 脚本，按 SHA1 执行，服务端没有缓存时发送源码
+///
+/// ```ignore
+/// let script = Script::new("return redis.call('INCRBY', KEYS[1], ARGV[1])");
+/// let n: i64 = script.key("counter").arg(5).invoke(&mut client).await?;
+/// ```
+///
+/// 先发送 EVALSHA，服务端回复 NOSCRIPT 时改用 EVAL，EVAL 同时让服务端缓存脚本，之后的调用
+/// 只需要发送 SHA1。服务端重启或者执行了 SCRIPT FLUSH 时同样会回退到 EVAL。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    code: String,
+    /// 小写十六进制的 SHA1，与服务端计算的相同
+    hash: String,
+}
+
+impl Script {
+    pub fn new(code: &str) -> Script {
+        let hash = sha1(code.as_bytes())
+            .iter()
+            .fold(String::with_capacity(40), |mut hex, byte| {
+                let _ = write!(hex, "{:02x}", byte);
+                hex
+            });
+        Script {
+            code: code.to_string(),
+            hash,
+        }
+    }
+
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// 开始构造一次调用，添加一个 KEYS
+    pub fn key(&self, key: impl ToArg) -> Invocation<'_> {
+        self.prepare().key(key)
+    }
+
+    /// 开始构造一次调用，添加一个 ARGV
+    pub fn arg(&self, arg: impl ToArg) -> Invocation<'_> {
+        self.prepare().arg(arg)
+    }
+
+    /// 不带 KEYS 和 ARGV 执行
+    pub async fn invoke<T: FromValue>(&self, client: &mut Client) -> Result<T> {
+        self.prepare().invoke(client).await
+    }
+
+    /// SCRIPT LOAD，预先让服务端缓存脚本，第一次调用就不需要回退到 EVAL
+    pub async fn load(&self, client: &mut Client) -> Result<()> {
+        let hash: String = client
+            .query(Cmd::new("SCRIPT").arg("LOAD").arg(&self.code))
+            .await?;
+        if hash != self.hash {
+            return Err(ClientError::UnexpectedReply(Frame::Bulk(hash.into())));
+        }
+        Ok(())
+    }
+
+    fn prepare(&self) -> Invocation<'_> {
+        Invocation {
+            script: self,
+            keys: Vec::new(),
+            args: Vec::new(),
+        }
+    }
+}
+
+/// 绑定了 KEYS 和 ARGV 的一次调用，见 [`Script`]
+#[derive(Debug, Clone)]
+pub struct Invocation<'a> {
+    script: &'a Script,
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+}
+
+impl Invocation<'_> {
+    pub fn key(mut self, key: impl ToArg) -> Self {
+        self.keys.push(key.to_arg());
+        self
+    }
+
+    pub fn arg(mut self, arg: impl ToArg) -> Self {
+        self.args.push(arg.to_arg());
+        self
+    }
+
+    /// EVALSHA，服务端没有缓存脚本时改用 EVAL，回复转换为 `T`
+    pub async fn invoke<T: FromValue>(&self, client: &mut Client) -> Result<T> {
+        match client.query(self.cmd("EVALSHA", &self.script.hash)).await {
+            Err(ClientError::Server(e)) if e.starts_with("NOSCRIPT") => {
+                client.query(self.cmd("EVAL", &self.script.code)).await
+            }
+            result => result,
+        }
+    }
+
+    /// `name script numkeys key... arg...`
+    fn cmd(&self, name: &str, script: &str) -> Cmd {
+        Cmd::new(name)
+            .arg(script)
+            .arg(self.keys.len())
+            .args(&self.keys)
+            .args(&self.args)
+    }
+}
+
+/// SHA1（RFC 3174），只用于计算脚本的 SHA1，不用于安全相关的场景
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [
+        0x6745_2301,
+        0xefcd_ab89,
+        0x98ba_dcfe,
+        0x1032_5476,
+        0xc3d2_e1f0,
+    ];
+    // 补一个 1 位、若干 0 位，最后 8 字节为原始数据的位数，总长度为 64 字节的整数倍
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (chunk, s) in digest.chunks_exact_mut(4).zip(state) {
+        chunk.copy_from_slice(&s.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+    use crate::connection::Connection;
+
+    #[test]
+    fn hashes_match_redis() {
+        assert_eq!(
+            Script::new("").hash(),
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709"
+        );
+        assert_eq!(
+            Script::new("abc").hash(),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        // 跨越两个分组
+        assert_eq!(
+            Script::new("abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq").hash(),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+
+    /// 服务端不支持脚本，模拟 EVAL/EVALSHA 的缓存：执行时回复 KEYS 和 ARGV 的数量
+    async fn serve(mut connection: Connection<TcpStream>, received: Arc<Mutex<Vec<String>>>) {
+        let mut scripts = HashMap::new();
+        while let Ok(Some(Frame::Array(parts))) = connection.read_frame().await {
+            let args: Vec<String> = parts.iter().map(|part| part.to_string()).collect();
+            received.lock().unwrap().push(args[0].clone());
+            let reply = match args[0].as_str() {
+                "EVAL" => {
+                    scripts.insert(Script::new(&args[1]).hash, args[1].clone());
+                    Frame::Integer(args.len() as i64 - 3)
+                }
+                "EVALSHA" if scripts.contains_key(&args[1]) => {
+                    Frame::Integer(args.len() as i64 - 3)
+                }
+                "EVALSHA" => Frame::Error("NOSCRIPT No matching script.".into()),
+                _ => Frame::Error("ERR unknown command".into()),
+            };
+            connection.write_frame(&reply).await.unwrap();
+        }
+    }
+
+    #[test]
+    fn routes_by_the_first_key() {
+        let script = Script::new("return 1");
+        let cmd = script.key("a").arg("b").cmd("EVALSHA", script.hash());
+        assert_eq!(cmd.key(), Some(&b"a"[..]));
+        assert_eq!(script.arg("b").cmd("EVAL", "return 1").key(), None);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_eval_once() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&received);
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            serve(Connection::new(socket), log).await;
+        });
+
+        let mut client = Client::connect(addr).await.unwrap();
+        let script = Script::new("return #KEYS + #ARGV");
+        let n: i64 = script
+            .key("a")
+            .arg(1)
+            .arg("x")
+            .invoke(&mut client)
+            .await
+            .unwrap();
+        assert_eq!(n, 3);
+        let n: i64 = script.invoke(&mut client).await.unwrap();
+        assert_eq!(n, 0);
+        assert_eq!(*received.lock().unwrap(), ["EVALSHA", "EVAL", "EVALSHA"]);
+
+        // 其他错误不回退
+        let err = client.query::<i64>(Cmd::new("SCRIPT").arg("FLUSH")).await;
+        assert!(matches!(err, Err(ClientError::Server(e)) if e.starts_with("ERR")));
+    }
+}
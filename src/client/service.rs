@@ -0,0 +1,176 @@
+//! 客户端的请求路径抽象为 [`Service`]，用 [`Layer`] 包装
+//!
+//! 与 tower 的 Service/Layer 类似，但只处理一种请求：[`Cmd`] 进，回复出，错误回复已经转换为
+//! [`ClientError::Server`](super::ClientError::Server)。日志、熔断、对冲请求等策略由调用者实现为
+//! [`Layer`]，按需叠加在 [`Client`]、[`Multiplexed`] 或者 [`PooledClient`] 上：
+//!
+//! ```ignore
+//! let mut service = client.layer(Logging).layer(CircuitBreaker::new(5));
+//! let n: i64 = service.query(Cmd::new("INCR").arg("counter")).await?;
+//! ```
+
+use std::future::Future;
+
+use futures::future::BoxFuture;
+
+use super::{Client, Cmd, FromValue, Multiplexed, PooledClient, Result};
+use crate::frame::Frame;
+
+/// 发送一条命令并返回回复
+pub trait Service {
+    fn call(&mut self, cmd: Cmd) -> BoxFuture<'_, Result<Frame>>;
+}
+
+/// 把一个 [`Service`] 包装为另一个
+pub trait Layer<S> {
+    type Service: Service;
+
+    fn layer(&self, inner: S) -> Self::Service;
+}
+
+/// [`Service`] 的辅助方法
+pub trait ServiceExt: Service + Sized {
+    /// 用 `layer` 包装，先调用的 layer 在内层
+    fn layer<L: Layer<Self>>(self, layer: L) -> L::Service {
+        layer.layer(self)
+    }
+
+    /// 发送一条命令，回复转换为 `T`
+    fn query<T: FromValue>(&mut self, cmd: Cmd) -> impl Future<Output = Result<T>> + '_ {
+        async move { T::from_value(self.call(cmd).await?) }
+    }
+}
+
+impl<S: Service> ServiceExt for S {}
+
+/// 用闭包实现的 [`Layer`]，见 [`layer_fn`]
+#[derive(Debug, Clone)]
+pub struct LayerFn<F> {
+    f: F,
+}
+
+/// 用闭包构造 [`Layer`]，闭包接收内层的 service，返回包装后的 service
+pub fn layer_fn<F>(f: F) -> LayerFn<F> {
+    LayerFn { f }
+}
+
+impl<S, T, F> Layer<S> for LayerFn<F>
+where
+    F: Fn(S) -> T,
+    T: Service,
+{
+    type Service = T;
+
+    fn layer(&self, inner: S) -> T {
+        (self.f)(inner)
+    }
+}
+
+impl Service for Client {
+    /// 见 [`Client::execute`]，包括重新连接和重试
+    fn call(&mut self, cmd: Cmd) -> BoxFuture<'_, Result<Frame>> {
+        Box::pin(self.execute(cmd))
+    }
+}
+
+impl Service for PooledClient {
+    fn call(&mut self, cmd: Cmd) -> BoxFuture<'_, Result<Frame>> {
+        Box::pin(self.execute(cmd))
+    }
+}
+
+impl Service for Multiplexed {
+    /// 返回的 future 不借用 `self` 的连接，可以 clone 句柄并发调用
+    fn call(&mut self, cmd: Cmd) -> BoxFuture<'_, Result<Frame>> {
+        let multiplexed = self.clone();
+        Box::pin(async move { multiplexed.execute(cmd).await })
+    }
+}
+
+impl<S: Service + ?Sized> Service for Box<S> {
+    fn call(&mut self, cmd: Cmd) -> BoxFuture<'_, Result<Frame>> {
+        (**self).call(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::client::{tests::start_server, ClientError};
+
+    /// 记录经过的命令名
+    struct Logging<S> {
+        inner: S,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl<S: Service + Send> Service for Logging<S> {
+        fn call(&mut self, cmd: Cmd) -> BoxFuture<'_, Result<Frame>> {
+            let name = String::from_utf8_lossy(cmd.name()).into_owned();
+            self.log.lock().unwrap().push(name);
+            self.inner.call(cmd)
+        }
+    }
+
+    /// 连续失败 `threshold` 次后不再调用内层，直接返回错误
+    struct CircuitBreaker<S> {
+        inner: S,
+        threshold: usize,
+        failures: usize,
+    }
+
+    impl<S: Service + Send> Service for CircuitBreaker<S> {
+        fn call(&mut self, cmd: Cmd) -> BoxFuture<'_, Result<Frame>> {
+            Box::pin(async move {
+                if self.failures >= self.threshold {
+                    return Err(ClientError::Server("circuit open".into()));
+                }
+                let result = self.inner.call(cmd).await;
+                self.failures = if result.is_err() {
+                    self.failures + 1
+                } else {
+                    0
+                };
+                result
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn layers_wrap_the_request_path() {
+        let client = Client::connect(start_server().await).await.unwrap();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let logging = {
+            let log = Arc::clone(&log);
+            layer_fn(move |inner| Logging {
+                inner,
+                log: Arc::clone(&log),
+            })
+        };
+        let breaker = layer_fn(|inner| CircuitBreaker {
+            inner,
+            threshold: 2,
+            failures: 0,
+        });
+        let mut service: Box<dyn Service + Send> = Box::new(client.layer(logging).layer(breaker));
+
+        service
+            .query::<()>(Cmd::new("SET").arg("a").arg("x"))
+            .await
+            .unwrap();
+        assert!(service
+            .query::<i64>(Cmd::new("INCR").arg("a"))
+            .await
+            .is_err());
+        assert!(service
+            .query::<i64>(Cmd::new("INCR").arg("a"))
+            .await
+            .is_err());
+        // 熔断后请求不再到达内层
+        let err = service.query::<String>(Cmd::new("GET").arg("a")).await;
+        assert!(matches!(err, Err(ClientError::Server(e)) if e == "circuit open"));
+        assert_eq!(*log.lock().unwrap(), ["SET", "INCR", "INCR"]);
+    }
+}
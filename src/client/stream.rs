@@ -0,0 +1,104 @@
+use std::{
+    fmt, io,
+    net::SocketAddr,
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+/// 客户端连接使用的传输层
+#[derive(Debug)]
+pub(super) enum Stream {
+    Tcp(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<tokio_rustls::client::TlsStream<TcpStream>>),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+/// 客户端连接的地址，重新连接时使用
+#[derive(Debug, Clone)]
+pub(super) enum Endpoint {
+    /// 依次尝试每个地址
+    Tcp(Vec<SocketAddr>),
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+}
+
+impl fmt::Display for Endpoint {
+    /// 第一个地址或者 socket 的路径，用于日志和 tracing
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp(addrs) => match addrs.first() {
+                Some(addr) => write!(f, "{}", addr),
+                None => f.write_str("-"),
+            },
+            #[cfg(unix)]
+            Endpoint::Unix(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl Endpoint {
+    #[cfg(unix)]
+    pub(super) fn unix(path: &Path) -> io::Result<Endpoint> {
+        Ok(Endpoint::Unix(path.to_path_buf()))
+    }
+
+    #[cfg(not(unix))]
+    pub(super) fn unix(_path: &Path) -> io::Result<Endpoint> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "unix sockets are not supported on this platform",
+        ))
+    }
+}
+
+/// 对每种传输层调用同一个方法
+macro_rules! dispatch {
+    ($self:ident, $stream:ident => $call:expr) => {
+        match $self.get_mut() {
+            Stream::Tcp($stream) => $call,
+            #[cfg(feature = "tls")]
+            Stream::Tls($stream) => $call,
+            #[cfg(unix)]
+            Stream::Unix($stream) => $call,
+        }
+    };
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        dispatch!(self, stream => Pin::new(stream).poll_read(cx, buf))
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        dispatch!(self, stream => Pin::new(stream).poll_write(cx, buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        dispatch!(self, stream => Pin::new(stream).poll_flush(cx))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        dispatch!(self, stream => Pin::new(stream).poll_shutdown(cx))
+    }
+}
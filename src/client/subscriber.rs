@@ -0,0 +1,609 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures::Stream;
+use tokio::sync::{mpsc, oneshot};
+use tracing::debug;
+
+use super::{multiplexed::duplicate, read_reply, Client, ClientError, Cmd, Result};
+use crate::frame::Frame;
+
+/// 收到但还没有被读取的消息的上限，超过后暂停从连接读取
+const MESSAGE_BUFFER: usize = 1024;
+
+/// 订阅的频道收到的一条消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub channel: String,
+    /// 通过 PSUBSCRIBE 收到时为匹配的模式，直接订阅的频道为 None
+    ///
+    /// 同一个频道匹配多个模式时，每个模式各收到一条消息。
+    pub pattern: Option<String>,
+    pub payload: Bytes,
+}
+
+/// [`Subscriber`] 的消息流中的一项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Message(Message),
+    /// 连接断开后重新连接，之前的订阅已经重新发送
+    ///
+    /// 断开期间发布的消息已经丢失，依赖完整消息的调用者需要自己补齐，例如重新读取状态。
+    Reconnected,
+}
+
+/// 修改订阅的请求，所有频道都得到服务端的确认后回复
+#[derive(Debug)]
+struct Request {
+    cmd: Cmd,
+    /// 需要等待的确认数，UNSUBSCRIBE、PUNSUBSCRIBE 不带参数时为 None，
+    /// 等到订阅数只剩下另一种订阅（模式或者频道）的数量
+    confirmations: Option<usize>,
+    reply: oneshot::Sender<Result<()>>,
+}
+
+/// 进入订阅模式的连接，见 [`Client::subscribe`]
+///
+/// 连接由后台任务持有，消息按收到的顺序缓存在通道中。[`Subscriber::into_stream`] 之后
+/// 仍然可以通过 [`Subscriber::subscriptions`] 得到的 [`Subscriptions`] 修改订阅。
+///
+/// 除了频道，还可以用 [`Subscriber::psubscribe`] 订阅 glob 模式，例如 `news.*`，
+/// 收到的 [`Message::pattern`] 为匹配的模式。
+///
+/// 连接断开后按 [`ReconnectOptions`](super::ReconnectOptions) 重新连接，重新订阅服务端已经
+/// 确认的频道和模式，再重新发送还没有得到确认的请求，并在消息流中插入 [`Event::Reconnected`]。
+/// 无法重新连接时消息流返回错误，之后结束。
+#[derive(Debug)]
+pub struct Subscriber {
+    subscriptions: Subscriptions,
+    messages: mpsc::Receiver<Result<Event>>,
+}
+
+impl Subscriber {
+    pub(super) async fn new(client: Client, channels: &[&str]) -> Result<Subscriber> {
+        let (requests, rx) = mpsc::unbounded_channel();
+        let (tx, messages) = mpsc::channel(MESSAGE_BUFFER);
+        tokio::spawn(run(client, rx, tx));
+        let subscriber = Subscriber {
+            subscriptions: Subscriptions { requests },
+            messages,
+        };
+        subscriber.subscribe(channels).await?;
+        Ok(subscriber)
+    }
+
+    pub async fn subscribe(&self, channels: &[&str]) -> Result<()> {
+        self.subscriptions.subscribe(channels).await
+    }
+
+    pub async fn unsubscribe(&self, channels: &[&str]) -> Result<()> {
+        self.subscriptions.unsubscribe(channels).await
+    }
+
+    pub async fn psubscribe(&self, patterns: &[&str]) -> Result<()> {
+        self.subscriptions.psubscribe(patterns).await
+    }
+
+    pub async fn punsubscribe(&self, patterns: &[&str]) -> Result<()> {
+        self.subscriptions.punsubscribe(patterns).await
+    }
+
+    /// 修改订阅的句柄，可以在消息流被其他任务读取时使用
+    pub fn subscriptions(&self) -> Subscriptions {
+        self.subscriptions.clone()
+    }
+
+    /// 下一条消息或者重新连接的通知，无法重新连接时返回错误，之后返回 None
+    pub async fn next_event(&mut self) -> Option<Result<Event>> {
+        self.messages.recv().await
+    }
+
+    pub fn into_stream(self) -> Messages {
+        Messages {
+            messages: self.messages,
+            _subscriptions: self.subscriptions,
+        }
+    }
+}
+
+/// [`Subscriber`] 收到的消息组成的流
+#[derive(Debug)]
+pub struct Messages {
+    messages: mpsc::Receiver<Result<Event>>,
+    /// 保持后台任务运行，所有句柄都释放后任务退出
+    _subscriptions: Subscriptions,
+}
+
+impl Stream for Messages {
+    type Item = Result<Event>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.messages.poll_recv(cx)
+    }
+}
+
+/// 修改 [`Subscriber`] 订阅的频道
+#[derive(Debug, Clone)]
+pub struct Subscriptions {
+    requests: mpsc::UnboundedSender<Request>,
+}
+
+impl Subscriptions {
+    pub async fn subscribe(&self, channels: &[&str]) -> Result<()> {
+        if channels.is_empty() {
+            return Ok(());
+        }
+        let cmd = Cmd::new("SUBSCRIBE").args(channels.iter().copied());
+        self.request(cmd, Some(channels.len())).await
+    }
+
+    /// 取消订阅，`channels` 为空时取消所有频道的订阅，模式的订阅不受影响
+    pub async fn unsubscribe(&self, channels: &[&str]) -> Result<()> {
+        let cmd = Cmd::new("UNSUBSCRIBE").args(channels.iter().copied());
+        let confirmations = (!channels.is_empty()).then_some(channels.len());
+        self.request(cmd, confirmations).await
+    }
+
+    /// 订阅 glob 模式，发布到匹配的频道的消息带有匹配的模式
+    pub async fn psubscribe(&self, patterns: &[&str]) -> Result<()> {
+        if patterns.is_empty() {
+            return Ok(());
+        }
+        let cmd = Cmd::new("PSUBSCRIBE").args(patterns.iter().copied());
+        self.request(cmd, Some(patterns.len())).await
+    }
+
+    /// 取消模式的订阅，`patterns` 为空时取消所有模式，频道的订阅不受影响
+    pub async fn punsubscribe(&self, patterns: &[&str]) -> Result<()> {
+        let cmd = Cmd::new("PUNSUBSCRIBE").args(patterns.iter().copied());
+        let confirmations = (!patterns.is_empty()).then_some(patterns.len());
+        self.request(cmd, confirmations).await
+    }
+
+    async fn request(&self, cmd: Cmd, confirmations: Option<usize>) -> Result<()> {
+        let (reply, rx) = oneshot::channel();
+        let request = Request {
+            cmd,
+            confirmations,
+            reply,
+        };
+        self.requests
+            .send(request)
+            .map_err(|_| ClientError::ConnectionClosed)?;
+        rx.await.map_err(|_| ClientError::ConnectionClosed)?
+    }
+}
+
+/// 订阅模式下服务端发送的帧
+enum Push {
+    Message(Message),
+    /// SUBSCRIBE、UNSUBSCRIBE、PSUBSCRIBE、PUNSUBSCRIBE 对每个频道或者模式的确认，
+    /// 带有之后频道和模式的订阅总数
+    Confirmation {
+        subscribed: bool,
+        pattern: bool,
+        channel: String,
+        count: i64,
+    },
+    Error(String),
+}
+
+fn parse_push(frame: Frame) -> Result<Push> {
+    let parts = match frame {
+        Frame::Array(parts) => parts,
+        Frame::Error(e) => return Ok(Push::Error(e)),
+        frame => return Err(ClientError::UnexpectedReply(frame)),
+    };
+    match &parts[..] {
+        [Frame::Bulk(kind), Frame::Bulk(channel), Frame::Bulk(payload)]
+            if kind.eq_ignore_ascii_case(b"message") =>
+        {
+            Ok(Push::Message(Message {
+                channel: String::from_utf8_lossy(channel).into_owned(),
+                pattern: None,
+                payload: payload.clone(),
+            }))
+        }
+        [Frame::Bulk(kind), Frame::Bulk(pattern), Frame::Bulk(channel), Frame::Bulk(payload)]
+            if kind.eq_ignore_ascii_case(b"pmessage") =>
+        {
+            Ok(Push::Message(Message {
+                channel: String::from_utf8_lossy(channel).into_owned(),
+                pattern: Some(String::from_utf8_lossy(pattern).into_owned()),
+                payload: payload.clone(),
+            }))
+        }
+        [Frame::Bulk(kind), channel, Frame::Integer(count)] => {
+            let kind = String::from_utf8_lossy(kind).to_lowercase();
+            let (subscribed, pattern) = match kind.as_str() {
+                "subscribe" => (true, false),
+                "unsubscribe" => (false, false),
+                "psubscribe" => (true, true),
+                "punsubscribe" => (false, true),
+                _ => return Err(ClientError::UnexpectedReply(Frame::Array(parts))),
+            };
+            Ok(Push::Confirmation {
+                subscribed,
+                pattern,
+                channel: channel.to_string(),
+                count: *count,
+            })
+        }
+        _ => Err(ClientError::UnexpectedReply(Frame::Array(parts))),
+    }
+}
+
+/// 持有连接的后台任务：发送修改订阅的命令，把确认交给等待的请求，把消息发送到通道
+async fn run(
+    mut client: Client,
+    mut requests: mpsc::UnboundedReceiver<Request>,
+    messages: mpsc::Sender<Result<Event>>,
+) {
+    let mut pending: VecDeque<Request> = VecDeque::new();
+    // 服务端确认过的频道和模式，重新连接后重新订阅
+    let mut subscribed = Subscribed::default();
+    loop {
+        let e = match serve(
+            &mut client,
+            &mut requests,
+            &messages,
+            &mut pending,
+            &mut subscribed,
+        )
+        .await
+        {
+            Ok(()) => return,
+            Err(e) => e,
+        };
+        if !e.is_disconnect() {
+            debug!(error = %e, "Subscriber failed");
+            fail(pending, &messages, e).await;
+            return;
+        }
+        debug!(error = %e, "Subscriber connection lost, reconnecting");
+        client.connection = None;
+        if let Err(e) = client.connection().await {
+            fail(pending, &messages, e).await;
+            return;
+        }
+        if messages.send(Ok(Event::Reconnected)).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// 服务端确认过的订阅
+#[derive(Debug, Default)]
+struct Subscribed {
+    channels: HashSet<String>,
+    patterns: HashSet<String>,
+}
+
+/// 重新订阅 `subscribed`，重新发送 `pending` 中的请求，之后处理新的请求和服务端发送的帧
+///
+/// 消息通道或者请求通道关闭时返回 `Ok`，后台任务退出。
+async fn serve(
+    client: &mut Client,
+    requests: &mut mpsc::UnboundedReceiver<Request>,
+    messages: &mpsc::Sender<Result<Event>>,
+    pending: &mut VecDeque<Request>,
+    subscribed: &mut Subscribed,
+) -> Result<()> {
+    let connection = client.connection().await?;
+    let resubscribe = [
+        ("SUBSCRIBE", &subscribed.channels),
+        ("PSUBSCRIBE", &subscribed.patterns),
+    ];
+    let mut remaining = 0;
+    for (name, subscriptions) in resubscribe {
+        if !subscriptions.is_empty() {
+            connection.queue_frame(&Cmd::new(name).args(subscriptions.iter()).to_frame());
+            remaining += subscriptions.len();
+        }
+    }
+    if remaining > 0 {
+        connection.flush().await?;
+        // 确认之间可能已经收到新订阅的频道的消息
+        while remaining > 0 {
+            match parse_push(read_reply(connection).await?)? {
+                Push::Message(message) => {
+                    if messages.send(Ok(Event::Message(message))).await.is_err() {
+                        return Ok(());
+                    }
+                }
+                Push::Confirmation { .. } => remaining -= 1,
+                Push::Error(e) => return Err(ClientError::Server(e)),
+            }
+        }
+    }
+    for request in pending.iter() {
+        connection.queue_frame(&request.cmd.to_frame());
+    }
+    connection.flush().await?;
+    loop {
+        tokio::select! {
+            request = requests.recv() => {
+                let Some(request) = request else {
+                    return Ok(());
+                };
+                connection.write_frame(&request.cmd.to_frame()).await?;
+                pending.push_back(request);
+            }
+            frame = read_reply(connection) => {
+                match parse_push(frame?)? {
+                    Push::Message(message) => {
+                        if messages.send(Ok(Event::Message(message))).await.is_err() {
+                            return Ok(());
+                        }
+                    }
+                    Push::Confirmation { subscribed: added, pattern, channel, count } => {
+                        let (same, other) = if pattern {
+                            (&mut subscribed.patterns, &subscribed.channels)
+                        } else {
+                            (&mut subscribed.channels, &subscribed.patterns)
+                        };
+                        if added {
+                            same.insert(channel);
+                        } else {
+                            same.remove(&channel);
+                        }
+                        confirm(pending, count, other.len());
+                    }
+                    Push::Error(e) => {
+                        if let Some(request) = pending.pop_front() {
+                            let _ = request.reply.send(Err(ClientError::Server(e)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 后台任务退出之前让等待的请求和消息流都得到错误
+async fn fail(pending: VecDeque<Request>, messages: &mpsc::Sender<Result<Event>>, e: ClientError) {
+    for request in pending {
+        let _ = request.reply.send(Err(duplicate(&e)));
+    }
+    let _ = messages.send(Err(e)).await;
+}
+
+/// 收到一个确认，最早的请求所有频道都确认后回复
+///
+/// 不带参数的取消订阅不知道需要几个确认，订阅总数只剩下另一种订阅的数量 `others` 时完成。
+/// 服务端按顺序执行请求，这时客户端确认过的另一种订阅与服务端的相同。
+fn confirm(pending: &mut VecDeque<Request>, count: i64, others: usize) {
+    let Some(request) = pending.front_mut() else {
+        return;
+    };
+    let done = match &mut request.confirmations {
+        Some(remaining) => {
+            *remaining -= 1;
+            *remaining == 0
+        }
+        None => count == others as i64,
+    };
+    if done {
+        let request = pending.pop_front().unwrap();
+        let _ = request.reply.send(Ok(()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+    use crate::{cmd::glob_match, connection::Connection};
+
+    fn push(parts: &[&str]) -> Frame {
+        Frame::Array(
+            parts
+                .iter()
+                .map(|part| Frame::Bulk(Bytes::copy_from_slice(part.as_bytes())))
+                .collect(),
+        )
+    }
+
+    /// 回复订阅确认的服务端，每次订阅频道之后向第一个新频道发送一条消息，每次订阅模式之后
+    /// 向 `news.tech` 发布一条消息，每个匹配的模式各收到一条
+    ///
+    /// 收到订阅 `disconnect_on` 的请求时不回复，直接关闭连接。
+    async fn serve(mut connection: Connection<TcpStream>, disconnect_on: Option<&str>) {
+        let mut channels: Vec<String> = Vec::new();
+        let mut patterns: Vec<String> = Vec::new();
+        while let Ok(Some(Frame::Array(parts))) = connection.read_frame().await {
+            let mut args: Vec<String> = parts.iter().map(|part| part.to_string()).collect();
+            let kind = args[0].to_lowercase();
+            if kind == "subscribe"
+                && disconnect_on.is_some_and(|channel| args[1..].contains(&channel.to_string()))
+            {
+                return;
+            }
+            let (subscribed, others) = if kind.starts_with('p') {
+                (&mut patterns, &channels)
+            } else {
+                (&mut channels, &patterns)
+            };
+            if args.len() == 1 {
+                args.extend(subscribed.iter().cloned());
+            }
+            for channel in &args[1..] {
+                if kind.ends_with("unsubscribe") {
+                    subscribed.retain(|subscribed| subscribed != channel);
+                } else {
+                    subscribed.push(channel.clone());
+                }
+                let mut confirmation = push(&[&kind, channel]);
+                if let Frame::Array(parts) = &mut confirmation {
+                    let count = subscribed.len() + others.len();
+                    parts.push(Frame::Integer(count as i64));
+                }
+                connection.queue_frame(&confirmation);
+            }
+            if kind == "subscribe" {
+                connection.queue_frame(&push(&["message", &args[1], "hello"]));
+            }
+            if kind == "psubscribe" {
+                for pattern in &patterns {
+                    if glob_match(pattern.as_bytes(), b"news.tech") {
+                        let message = push(&["pmessage", pattern, "news.tech", "hello"]);
+                        connection.queue_frame(&message);
+                    }
+                }
+            }
+            connection.flush().await.unwrap();
+        }
+    }
+
+    fn expected(channel: &str) -> Event {
+        Event::Message(Message {
+            channel: channel.into(),
+            pattern: None,
+            payload: "hello".into(),
+        })
+    }
+
+    fn expected_pattern(pattern: &str) -> Event {
+        Event::Message(Message {
+            channel: "news.tech".into(),
+            pattern: Some(pattern.into()),
+            payload: "hello".into(),
+        })
+    }
+
+    #[tokio::test]
+    async fn messages_stream_while_subscriptions_change() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            serve(Connection::new(socket), None).await;
+        });
+
+        let client = Client::connect(addr).await.unwrap();
+        let subscriber = client.subscribe(&["a", "b"]).await.unwrap();
+        let subscriptions = subscriber.subscriptions();
+        let mut messages = subscriber.into_stream();
+        assert_eq!(messages.next().await.unwrap().unwrap(), expected("a"));
+
+        subscriptions.subscribe(&["c"]).await.unwrap();
+        assert_eq!(messages.next().await.unwrap().unwrap(), expected("c"));
+        subscriptions.unsubscribe(&["a"]).await.unwrap();
+        subscriptions.unsubscribe(&[]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn patterns_deliver_one_message_per_match() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            serve(Connection::new(socket), None).await;
+        });
+
+        let client = Client::connect(addr).await.unwrap();
+        let mut subscriber = client.psubscribe(&["news.*", "*.tech"]).await.unwrap();
+        assert_eq!(
+            subscriber.next_event().await.unwrap().unwrap(),
+            expected_pattern("news.*")
+        );
+        assert_eq!(
+            subscriber.next_event().await.unwrap().unwrap(),
+            expected_pattern("*.tech")
+        );
+
+        // 取消所有模式时频道的订阅保留，订阅数不会降到 0
+        subscriber.subscribe(&["a"]).await.unwrap();
+        assert_eq!(
+            subscriber.next_event().await.unwrap().unwrap(),
+            expected("a")
+        );
+        subscriber.punsubscribe(&["*.tech"]).await.unwrap();
+        subscriber.punsubscribe(&[]).await.unwrap();
+        subscriber.psubscribe(&["sports.*"]).await.unwrap();
+        subscriber.unsubscribe(&[]).await.unwrap();
+        subscriber.punsubscribe(&[]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn server_delivers_channel_and_pattern_messages() {
+        let addr = crate::client::tests::start_server().await;
+        let mut subscriber = Client::connect(addr)
+            .await
+            .unwrap()
+            .psubscribe(&["news.*", "*.tech"])
+            .await
+            .unwrap();
+        subscriber.subscribe(&["news.tech"]).await.unwrap();
+
+        let mut publisher = Client::connect(addr).await.unwrap();
+        assert_eq!(publisher.publish("news.tech", "hello").await.unwrap(), 3);
+        assert_eq!(publisher.publish("sports", "hello").await.unwrap(), 0);
+        let mut events = vec![];
+        for _ in 0..3 {
+            events.push(subscriber.next_event().await.unwrap().unwrap());
+        }
+        // 频道的订阅者先收到，模式之间的顺序不确定
+        assert_eq!(events[0], expected("news.tech"));
+        assert!(events.contains(&expected_pattern("news.*")));
+        assert!(events.contains(&expected_pattern("*.tech")));
+
+        subscriber.punsubscribe(&[]).await.unwrap();
+        assert_eq!(publisher.publish("news.tech", "hello").await.unwrap(), 1);
+        assert_eq!(
+            subscriber.next_event().await.unwrap().unwrap(),
+            expected("news.tech")
+        );
+    }
+
+    #[tokio::test]
+    async fn resubscribes_after_reconnecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            // 第一个连接在订阅 b 时断开，第二个连接正常回复
+            let (socket, _) = listener.accept().await.unwrap();
+            serve(Connection::new(socket), Some("b")).await;
+            let (socket, _) = listener.accept().await.unwrap();
+            serve(Connection::new(socket), None).await;
+        });
+
+        let client = Client::connect(addr).await.unwrap();
+        let mut subscriber = client.subscribe(&["a"]).await.unwrap();
+        let subscriptions = subscriber.subscriptions();
+        assert_eq!(
+            subscriber.next_event().await.unwrap().unwrap(),
+            expected("a")
+        );
+        subscriptions.psubscribe(&["news.*"]).await.unwrap();
+        assert_eq!(
+            subscriber.next_event().await.unwrap().unwrap(),
+            expected_pattern("news.*")
+        );
+
+        // 没有得到确认的请求在新的连接上重新发送
+        subscriptions.subscribe(&["b"]).await.unwrap();
+        assert_eq!(
+            subscriber.next_event().await.unwrap().unwrap(),
+            Event::Reconnected
+        );
+        assert_eq!(
+            subscriber.next_event().await.unwrap().unwrap(),
+            expected("a")
+        );
+        assert_eq!(
+            subscriber.next_event().await.unwrap().unwrap(),
+            expected_pattern("news.*")
+        );
+        assert_eq!(
+            subscriber.next_event().await.unwrap().unwrap(),
+            expected("b")
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! TLS 连接
+//!
+//! 需要开启 `tls` 特性。[`TlsOptions`] 指定验证服务端证书使用的 CA 和 SNI 中发送的服务端名称，
+//! 也可以直接使用调用者构造的 rustls [`ClientConfig`]。
+
+use std::{fmt, fs::File, io, io::BufReader, path::Path, sync::Arc};
+
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    rustls::{crypto::ring, pki_types::ServerName, ClientConfig, RootCertStore},
+    TlsConnector,
+};
+
+use super::stream::Stream;
+
+/// 通过 ALPN 协商的协议名，与服务端相同
+const ALPN_PROTOCOLS: &[&[u8]] = &[b"redis"];
+
+/// 建立 TLS 连接的参数，见 [`Client::connect_tls`](super::Client::connect_tls)
+#[derive(Clone)]
+pub struct TlsOptions {
+    connector: TlsConnector,
+    /// 发送在 SNI 中，并用于验证服务端的证书
+    server_name: ServerName<'static>,
+}
+
+impl TlsOptions {
+    /// 只信任 PEM 文件中的 CA 证书，`server_name` 为域名或者 IP 地址
+    pub fn with_ca_file(server_name: &str, ca_file: impl AsRef<Path>) -> io::Result<TlsOptions> {
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut BufReader::new(File::open(ca_file)?)) {
+            roots.add(cert?).map_err(invalid)?;
+        }
+        TlsOptions::with_roots(server_name, roots)
+    }
+
+    /// 只信任 `roots` 中的 CA 证书
+    pub fn with_roots(server_name: &str, roots: RootCertStore) -> io::Result<TlsOptions> {
+        let mut config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(invalid)?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        config.alpn_protocols = ALPN_PROTOCOLS.iter().map(|p| p.to_vec()).collect();
+        TlsOptions::with_config(server_name, Arc::new(config))
+    }
+
+    /// 使用调用者构造的配置，例如需要客户端证书时
+    pub fn with_config(server_name: &str, config: Arc<ClientConfig>) -> io::Result<TlsOptions> {
+        Ok(TlsOptions {
+            connector: TlsConnector::from(config),
+            server_name: ServerName::try_from(server_name.to_string()).map_err(invalid)?,
+        })
+    }
+
+    /// 在已经建立的 TCP 连接上完成握手
+    pub(super) async fn handshake(&self, socket: TcpStream) -> io::Result<Stream> {
+        let stream = self
+            .connector
+            .connect(self.server_name.clone(), socket)
+            .await?;
+        Ok(Stream::Tls(Box::new(stream)))
+    }
+}
+
+impl fmt::Debug for TlsOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsOptions")
+            .field("server_name", &self.server_name)
+            .finish_non_exhaustive()
+    }
+}
+
+fn invalid(e: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{
+        client::{Client, ClientError},
+        config::Config,
+        server,
+    };
+
+    fn testdata(name: &str) -> String {
+        format!(
+            "{}/src/client/testdata/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        )
+    }
+
+    /// 只接受 TLS 连接的服务端，证书由 testdata/ca.pem 签发，名称为 localhost 和 127.0.0.1
+    async fn start_tls_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut config = Config::default();
+        config
+            .set("tls-cert-file", &testdata("server.pem"))
+            .unwrap();
+        config.set("tls-key-file", &testdata("server.key")).unwrap();
+        tokio::spawn(async move {
+            server::run_with(listener, &config, std::future::pending::<()>()).await
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn connects_to_a_tls_server() {
+        let addr = start_tls_server().await;
+        let tls = TlsOptions::with_ca_file("localhost", testdata("ca.pem")).unwrap();
+        let mut client = Client::connect_tls(addr, tls).await.unwrap();
+        client.set("a", 1).await.unwrap();
+        assert_eq!(client.get::<i64>("a").await.unwrap(), 1);
+        let url = format!(
+            "rediss://localhost:{}?ca={}",
+            addr.port(),
+            testdata("ca.pem")
+        );
+        let mut client = Client::connect_url(&url).await.unwrap();
+        assert_eq!(client.get::<i64>("a").await.unwrap(), 1);
+
+        // 证书中没有的名称和不信任的 CA 都在握手时失败
+        let tls = TlsOptions::with_ca_file("example.com", testdata("ca.pem")).unwrap();
+        let err = Client::connect_tls(addr, tls).await.unwrap_err();
+        assert!(matches!(err, ClientError::Io(_)));
+        let tls = TlsOptions::with_roots("localhost", RootCertStore::empty()).unwrap();
+        assert!(Client::connect_tls(addr, tls).await.is_err());
+        assert!(TlsOptions::with_ca_file("not a name", testdata("ca.pem")).is_err());
+    }
+}
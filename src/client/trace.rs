@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use tracing::{field, info_span, Span};
+
+use super::{ClientError, Cmd, Result};
+
+/// 隐藏 key 时记录的值
+const REDACTED: &str = "<redacted>";
+
+/// 为每条命令创建 span，见 [`Client::set_redact_keys`](super::Client::set_redact_keys)
+///
+/// span 的父 span 是调用者当前所在的 span，应用的请求和其中的命令在分布式追踪中显示为同一条链路。
+/// 字段：`command` 为大写的命令名，`key` 为第一个 key（见 [`Cmd::key`]），`server` 为服务端地址，
+/// 完成后写入 `outcome`（`ok`、`server_error` 或者 `error`），失败时写入 `error`。
+#[derive(Debug, Clone)]
+pub(super) struct Tracer {
+    server: Arc<str>,
+    /// 为 true 时 key 记录为 `<redacted>`，key 中包含用户数据时使用
+    pub(super) redact_keys: bool,
+}
+
+impl Tracer {
+    pub(super) fn new(server: String) -> Tracer {
+        Tracer {
+            server: server.into(),
+            redact_keys: false,
+        }
+    }
+
+    pub(super) fn command(&self, cmd: &Cmd) -> Span {
+        let name = String::from_utf8_lossy(cmd.name()).to_uppercase();
+        let span = info_span!(
+            "command",
+            command = %name,
+            key = field::Empty,
+            server = %self.server,
+            outcome = field::Empty,
+            error = field::Empty,
+        );
+        if let Some(key) = cmd.key() {
+            if self.redact_keys {
+                span.record("key", REDACTED);
+            } else {
+                span.record("key", field::display(String::from_utf8_lossy(key)));
+            }
+        }
+        span
+    }
+
+    /// 一批命令共用一个 span，不记录 key
+    pub(super) fn pipeline(&self, commands: usize) -> Span {
+        info_span!(
+            "pipeline",
+            commands,
+            server = %self.server,
+            outcome = field::Empty,
+            error = field::Empty,
+        )
+    }
+}
+
+/// 把结果写入 span 的 `outcome` 和 `error`
+pub(super) fn record<T>(span: &Span, result: &Result<T>) {
+    let e = match result {
+        Ok(_) => {
+            span.record("outcome", "ok");
+            return;
+        }
+        Err(e) => e,
+    };
+    let outcome = match e {
+        ClientError::Server(_) => "server_error",
+        _ => "error",
+    };
+    span.record("outcome", outcome);
+    span.record("error", field::display(e));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::{info_span, Instrument};
+    use tracing_subscriber::fmt::format::FmtSpan;
+
+    use crate::client::{tests::start_server, Client, Cmd};
+
+    /// 把格式化的日志写入共享的缓冲区
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn commands_are_traced_inside_the_callers_span() {
+        let addr = start_server().await;
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_span_events(FmtSpan::CLOSE)
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let mut client = Client::connect(addr).await.unwrap();
+        async {
+            client.set("user:1", "x").await.unwrap();
+            client.incr("user:1").await.unwrap_err();
+            client.set_redact_keys(true);
+            client.get::<String>("user:1").await.unwrap();
+        }
+        .instrument(info_span!("request", id = 7))
+        .await;
+        let multiplexed = client.multiplexed();
+        multiplexed.execute(Cmd::new("PING")).await.unwrap();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output
+            .lines()
+            .filter(|line| line.contains("command{") && line.contains("close"))
+            .collect();
+        let expected = [
+            format!(
+                "request{{id=7}}:command{{command=SET server={} key=user:1 outcome=\"ok\"}}",
+                addr
+            ),
+            format!(
+                "request{{id=7}}:command{{command=INCR server={} key=user:1 outcome=\"server_error\" error=ERR value is not an integer or out of range}}",
+                addr
+            ),
+            format!(
+                "request{{id=7}}:command{{command=GET server={} key=\"<redacted>\" outcome=\"ok\"}}",
+                addr
+            ),
+            format!("command{{command=PING server={} outcome=\"ok\"}}", addr),
+        ];
+        assert_eq!(lines.len(), 4, "{}", output);
+        for (line, expected) in lines.iter().zip(&expected) {
+            assert!(line.contains(expected.as_str()), "{}\n{}", line, expected);
+        }
+    }
+}
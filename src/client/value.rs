@@ -0,0 +1,211 @@
+use std::{collections::HashMap, hash::Hash, str};
+
+use bytes::Bytes;
+
+use super::{ClientError, Result};
+use crate::frame::Frame;
+
+/// 可以从回复转换得到的类型，见 [`Client::query`]
+///
+/// 数字可以从整数回复或者十进制的字符串转换；`Option<T>` 把 Null 转换为 None，其他回复转换为 T；
+/// `Vec<T>` 和元组从数组转换，`HashMap<K, V>` 从键值交替的数组转换。错误回复在转换之前已经
+/// 变成 [`ClientError::Server`]，不会传给这里。
+///
+/// [`Client::query`]: super::Client::query
+pub trait FromValue: Sized {
+    fn from_value(frame: Frame) -> Result<Self>;
+}
+
+fn invalid<T>(frame: Frame) -> Result<T> {
+    Err(ClientError::Conversion(frame, std::any::type_name::<T>()))
+}
+
+/// 字符串类型的回复的内容
+fn text(frame: &Frame) -> Option<&[u8]> {
+    match frame {
+        Frame::Bulk(bytes) => Some(bytes),
+        Frame::Simple(s) => Some(s.as_bytes()),
+        _ => None,
+    }
+}
+
+impl FromValue for Frame {
+    fn from_value(frame: Frame) -> Result<Frame> {
+        Ok(frame)
+    }
+}
+
+/// 忽略回复的内容
+impl FromValue for () {
+    fn from_value(_frame: Frame) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl FromValue for Bytes {
+    fn from_value(frame: Frame) -> Result<Bytes> {
+        match frame {
+            Frame::Bulk(bytes) => Ok(bytes),
+            Frame::Simple(s) => Ok(Bytes::from(s)),
+            frame => invalid(frame),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(frame: Frame) -> Result<String> {
+        match frame {
+            Frame::Simple(s) => Ok(s),
+            Frame::Integer(n) => Ok(n.to_string()),
+            Frame::Bulk(bytes) => match String::from_utf8(bytes.to_vec()) {
+                Ok(s) => Ok(s),
+                Err(_) => invalid(Frame::Bulk(bytes)),
+            },
+            frame => invalid(frame),
+        }
+    }
+}
+
+macro_rules! number_value {
+    ($($ty:ty),*) => {
+        $(impl FromValue for $ty {
+            fn from_value(frame: Frame) -> Result<$ty> {
+                let parsed = match &frame {
+                    Frame::Integer(n) => <$ty>::try_from(*n).ok(),
+                    frame => text(frame)
+                        .and_then(|text| str::from_utf8(text).ok())
+                        .and_then(|text| text.parse().ok()),
+                };
+                parsed.map_or_else(|| invalid(frame), Ok)
+            }
+        })*
+    };
+}
+
+number_value!(i64, u64, i32, u32, usize);
+
+impl FromValue for f64 {
+    fn from_value(frame: Frame) -> Result<f64> {
+        let parsed = match &frame {
+            Frame::Integer(n) => Some(*n as f64),
+            frame => text(frame)
+                .and_then(|text| str::from_utf8(text).ok())
+                .and_then(|text| text.parse().ok()),
+        };
+        parsed.map_or_else(|| invalid(frame), Ok)
+    }
+}
+
+/// 整数回复非 0 为 true，字符串回复只接受 "1" 和 "0"，`+OK` 为 true
+impl FromValue for bool {
+    fn from_value(frame: Frame) -> Result<bool> {
+        match &frame {
+            Frame::Integer(n) => Ok(*n != 0),
+            Frame::Simple(s) if s == "OK" => Ok(true),
+            frame => match text(frame) {
+                Some(b"1") => Ok(true),
+                Some(b"0") => Ok(false),
+                _ => invalid(frame.clone()),
+            },
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(frame: Frame) -> Result<Option<T>> {
+        match frame {
+            Frame::Null => Ok(None),
+            frame => T::from_value(frame).map(Some),
+        }
+    }
+}
+
+/// Null 转换为空的 Vec
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(frame: Frame) -> Result<Vec<T>> {
+        match frame {
+            Frame::Array(items) => items.into_iter().map(T::from_value).collect(),
+            Frame::Null => Ok(Vec::new()),
+            frame => invalid(frame),
+        }
+    }
+}
+
+impl<K: FromValue + Eq + Hash, V: FromValue> FromValue for HashMap<K, V> {
+    fn from_value(frame: Frame) -> Result<HashMap<K, V>> {
+        let items = match frame {
+            Frame::Array(items) if items.len() % 2 == 0 => items,
+            Frame::Null => return Ok(HashMap::new()),
+            frame => return invalid(frame),
+        };
+        let mut map = HashMap::with_capacity(items.len() / 2);
+        let mut items = items.into_iter();
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            map.insert(K::from_value(key)?, V::from_value(value)?);
+        }
+        Ok(map)
+    }
+}
+
+macro_rules! tuple_value {
+    ($len:literal; $($name:ident),*) => {
+        /// 从长度相同的数组转换，用于 [`Pipeline::execute`](super::Pipeline::execute) 的结果
+        impl<$($name: FromValue),*> FromValue for ($($name,)*) {
+            fn from_value(frame: Frame) -> Result<($($name,)*)> {
+                let items = match frame {
+                    Frame::Array(items) if items.len() == $len => items,
+                    frame => return invalid(frame),
+                };
+                let mut items = items.into_iter();
+                Ok(($($name::from_value(items.next().unwrap())?,)*))
+            }
+        }
+    };
+}
+
+tuple_value!(1; A);
+tuple_value!(2; A, B);
+tuple_value!(3; A, B, C);
+tuple_value!(4; A, B, C, D);
+tuple_value!(5; A, B, C, D, E);
+tuple_value!(6; A, B, C, D, E, F);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bulk(s: &'static str) -> Frame {
+        Frame::Bulk(Bytes::from_static(s.as_bytes()))
+    }
+
+    #[test]
+    fn converts_replies_to_rust_types() {
+        assert_eq!(i64::from_value(bulk("-42")).unwrap(), -42);
+        assert_eq!(u32::from_value(Frame::Integer(7)).unwrap(), 7);
+        assert!(u32::from_value(Frame::Integer(-1)).is_err());
+        assert_eq!(f64::from_value(bulk("1.5")).unwrap(), 1.5);
+        assert!(bool::from_value(Frame::Integer(1)).unwrap());
+        assert!(!bool::from_value(bulk("0")).unwrap());
+        assert_eq!(
+            String::from_value(Frame::Simple("OK".into())).unwrap(),
+            "OK"
+        );
+        assert_eq!(Option::<i64>::from_value(Frame::Null).unwrap(), None);
+        assert!(matches!(
+            i64::from_value(bulk("abc")),
+            Err(ClientError::Conversion(_, "i64"))
+        ));
+
+        let array = Frame::Array(vec![bulk("a"), Frame::Integer(1), bulk("b"), Frame::Null]);
+        let map = HashMap::<String, Option<i64>>::from_value(array.clone()).unwrap();
+        assert_eq!(map["a"], Some(1));
+        assert_eq!(map["b"], None);
+        let (a, one, b, none) = <(String, i64, Bytes, Option<Bytes>)>::from_value(array).unwrap();
+        assert_eq!((a.as_str(), one, &b[..], none), ("a", 1, &b"b"[..], None));
+        assert!(<(i64, i64)>::from_value(Frame::Array(vec![Frame::Integer(1)])).is_err());
+        assert_eq!(
+            Vec::<i64>::from_value(Frame::Null).unwrap(),
+            Vec::<i64>::new()
+        );
+    }
+}
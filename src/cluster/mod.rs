@@ -0,0 +1,15 @@
+//! 集群模式
+//!
+//! 与 Redis Cluster 相同，key 空间分为 16384 个 hash slot，见 [`key_slot`]。这里只支持手动配置的
+//! 静态拓扑：每个节点从 `cluster-slots` 读取所有 slot 的分配，访问不属于自己的 slot 时回复 MOVED，
+//! 由客户端重定向。节点之间不通信，迁移 slot 时分别在两端配置 `cluster-migrating` 和
+//! `cluster-importing`，迁出节点上已经不存在的 key 回复 ASK。
+
+mod slot;
+pub use slot::{key_slot, SLOTS};
+
+mod topology;
+pub use topology::{
+    format_importing, format_migrating, parse_importing, parse_migrating, Redirect, SlotRange,
+    Topology,
+};
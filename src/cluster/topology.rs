@@ -0,0 +1,286 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use thiserror::Error;
+
+use super::{key_slot, SLOTS};
+
+/// 命令不能在这个节点执行时的回复
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Redirect {
+    /// slot 由另一个节点负责，客户端应更新 slot 表并重新发送
+    #[error("MOVED {0} {1}")]
+    Moved(u16, String),
+    /// slot 正在迁出，key 不在这里，客户端应先发送 ASKING 再到目标节点执行这一条命令
+    #[error("ASK {0} {1}")]
+    Ask(u16, String),
+    #[error("CROSSSLOT Keys in request don't hash to the same slot")]
+    CrossSlot,
+    #[error("CLUSTERDOWN Hash slot not served")]
+    Unassigned,
+}
+
+impl Redirect {
+    /// 客户端解析 MOVED 和 ASK 错误回复，其他错误返回 None
+    pub fn parse(message: &str) -> Option<Redirect> {
+        let mut parts = message.split_whitespace();
+        let (kind, slot, node) = (parts.next()?, parts.next()?, parts.next()?);
+        let slot = parse_slot(slot)?;
+        match kind {
+            "MOVED" => Some(Redirect::Moved(slot, node.to_string())),
+            "ASK" => Some(Redirect::Ask(slot, node.to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// 由节点 `node` 负责的连续 slot，`start` 和 `end` 都包含在内
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotRange {
+    pub start: u16,
+    pub end: u16,
+    /// 节点的地址，`host:port`
+    pub node: String,
+}
+
+impl SlotRange {
+    /// 解析 `cluster-slots`：逗号分隔的 `start[-end] host:port`，范围不能重叠
+    pub fn parse_list(s: &str) -> Option<Vec<SlotRange>> {
+        let mut ranges = entries(s)
+            .map(|entry| {
+                let (range, node) = split_pair(entry)?;
+                let (start, end) = match range.split_once('-') {
+                    Some((start, end)) => (parse_slot(start)?, parse_slot(end)?),
+                    None => (parse_slot(range)?, parse_slot(range)?),
+                };
+                (start <= end).then_some(SlotRange { start, end, node })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        ranges.sort_by_key(|range| range.start);
+        ranges
+            .windows(2)
+            .all(|pair| pair[0].end < pair[1].start)
+            .then_some(ranges)
+    }
+
+    pub fn format_list(ranges: &[SlotRange]) -> String {
+        let entries: Vec<String> = ranges
+            .iter()
+            .map(|range| format!("{}-{} {}", range.start, range.end, range.node))
+            .collect();
+        entries.join(", ")
+    }
+}
+
+/// 解析 `cluster-migrating`：逗号分隔的 `slot host:port`，即迁出的 slot 和目标节点
+pub fn parse_migrating(s: &str) -> Option<BTreeMap<u16, String>> {
+    entries(s)
+        .map(|entry| {
+            let (slot, node) = split_pair(entry)?;
+            Some((parse_slot(slot)?, node))
+        })
+        .collect()
+}
+
+pub fn format_migrating(migrating: &BTreeMap<u16, String>) -> String {
+    let entries: Vec<String> = migrating
+        .iter()
+        .map(|(slot, node)| format!("{} {}", slot, node))
+        .collect();
+    entries.join(", ")
+}
+
+/// 解析 `cluster-importing`：逗号分隔的迁入的 slot
+pub fn parse_importing(s: &str) -> Option<BTreeSet<u16>> {
+    entries(s).map(parse_slot).collect()
+}
+
+pub fn format_importing(importing: &BTreeSet<u16>) -> String {
+    let entries: Vec<String> = importing.iter().map(u16::to_string).collect();
+    entries.join(", ")
+}
+
+fn entries(s: &str) -> impl Iterator<Item = &str> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+}
+
+/// `slot host:port` 形式的一项
+fn split_pair(entry: &str) -> Option<(&str, String)> {
+    let mut parts = entry.split_whitespace();
+    let (slot, node) = (parts.next()?, parts.next()?);
+    let (host, port) = node.rsplit_once(':')?;
+    let valid = parts.next().is_none() && !host.is_empty() && port.parse::<u16>().is_ok();
+    valid.then(|| (slot, node.to_string()))
+}
+
+fn parse_slot(s: &str) -> Option<u16> {
+    s.trim().parse().ok().filter(|&slot| slot < SLOTS)
+}
+
+/// 手动配置的集群拓扑：每个 slot 由哪个节点负责，以及正在迁移的 slot
+#[derive(Debug, Default)]
+pub struct Topology {
+    /// 按起点排序，互不重叠
+    ranges: Vec<SlotRange>,
+    migrating: BTreeMap<u16, String>,
+    importing: BTreeSet<u16>,
+}
+
+impl Topology {
+    pub fn new(
+        mut ranges: Vec<SlotRange>,
+        migrating: BTreeMap<u16, String>,
+        importing: BTreeSet<u16>,
+    ) -> Topology {
+        ranges.sort_by_key(|range| range.start);
+        Topology {
+            ranges,
+            migrating,
+            importing,
+        }
+    }
+
+    pub fn ranges(&self) -> &[SlotRange] {
+        &self.ranges
+    }
+
+    /// 负责 `slot` 的节点
+    pub fn owner(&self, slot: u16) -> Option<&str> {
+        let i = self.ranges.partition_point(|range| range.end < slot);
+        self.ranges
+            .get(i)
+            .filter(|range| range.start <= slot)
+            .map(|range| range.node.as_str())
+    }
+
+    /// 已经分配给节点的 slot 数量
+    pub fn assigned(&self) -> usize {
+        self.ranges
+            .iter()
+            .map(|range| (range.end - range.start) as usize + 1)
+            .sum()
+    }
+
+    /// 拓扑中出现的所有节点
+    pub fn nodes(&self) -> BTreeSet<&str> {
+        let owners = self.ranges.iter().map(|range| range.node.as_str());
+        owners
+            .chain(self.migrating.values().map(String::as_str))
+            .collect()
+    }
+
+    /// 地址为 `myself` 的节点能否执行访问 `keys` 的命令
+    ///
+    /// `asking` 表示客户端在这条命令前发送了 ASKING，迁入中的 slot 只接受这样的命令；
+    /// `exists` 判断 key 是否存在，迁出中的 slot 只有所有 key 都还在这里时才在本地执行。
+    pub fn route(
+        &self,
+        myself: &str,
+        keys: &[&str],
+        asking: bool,
+        exists: impl Fn(&str) -> bool,
+    ) -> Result<(), Redirect> {
+        let mut slots = keys.iter().map(|key| key_slot(key.as_bytes()));
+        let Some(slot) = slots.next() else {
+            return Ok(());
+        };
+        if slots.any(|other| other != slot) {
+            return Err(Redirect::CrossSlot);
+        }
+        match self.owner(slot) {
+            Some(owner) if owner == myself => match self.migrating.get(&slot) {
+                Some(target) if !keys.iter().all(|key| exists(key)) => {
+                    Err(Redirect::Ask(slot, target.clone()))
+                }
+                _ => Ok(()),
+            },
+            _ if asking && self.importing.contains(&slot) => Ok(()),
+            Some(owner) => Err(Redirect::Moved(slot, owner.to_string())),
+            None => Err(Redirect::Unassigned),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "127.0.0.1:7000";
+    const B: &str = "127.0.0.1:7001";
+
+    #[test]
+    fn config_round_trips() {
+        let ranges =
+            SlotRange::parse_list("8192-16383 127.0.0.1:7001, 0-8191 127.0.0.1:7000").unwrap();
+        assert_eq!(ranges[0].node, A);
+        assert_eq!(
+            SlotRange::format_list(&ranges),
+            "0-8191 127.0.0.1:7000, 8192-16383 127.0.0.1:7001"
+        );
+        assert_eq!(SlotRange::parse_list("").unwrap(), vec![]);
+        assert_eq!(SlotRange::parse_list("5 ::1:7000").unwrap()[0].end, 5);
+        // 重叠、越界、缺少端口都是无效的配置
+        assert!(SlotRange::parse_list("0-100 a:1, 100-200 b:1").is_none());
+        assert!(SlotRange::parse_list("0-16384 a:1").is_none());
+        assert!(SlotRange::parse_list("10-5 a:1").is_none());
+        assert!(SlotRange::parse_list("0-5 localhost").is_none());
+
+        let migrating = parse_migrating("42 127.0.0.1:7001").unwrap();
+        assert_eq!(format_migrating(&migrating), "42 127.0.0.1:7001");
+        let importing = parse_importing("7, 3").unwrap();
+        assert_eq!(format_importing(&importing), "3, 7");
+        assert!(parse_importing("x").is_none());
+    }
+
+    #[test]
+    fn routes_keys_by_slot() {
+        let foo = key_slot(b"foo"); // 12182
+        let bar = key_slot(b"bar"); // 5061
+        let ranges = SlotRange::parse_list("0-8191 127.0.0.1:7000, 8192-16000 127.0.0.1:7001");
+        let topology = Topology::new(
+            ranges.unwrap(),
+            BTreeMap::from([(bar, B.to_string())]),
+            BTreeSet::from([foo]),
+        );
+        assert_eq!(topology.assigned(), 16001);
+        assert_eq!(topology.nodes().len(), 2);
+        assert_eq!(topology.owner(16001), None);
+
+        let present = |key: &str| key != "bar";
+        assert_eq!(topology.route(A, &[], false, present), Ok(()));
+        assert_eq!(
+            topology.route(A, &["{foo}a", "{foo}b"], false, present),
+            Err(Redirect::Moved(foo, B.into()))
+        );
+        let moved = Redirect::Moved(foo, B.into()).to_string();
+        assert_eq!(
+            Redirect::parse(&moved),
+            Some(Redirect::Moved(foo, B.into()))
+        );
+        assert_eq!(Redirect::parse("ERR unknown command"), None);
+        assert_eq!(
+            topology.route(A, &["foo", "bar"], false, present),
+            Err(Redirect::CrossSlot)
+        );
+        // 迁入中的 slot 只接受 ASKING 之后的命令
+        assert_eq!(topology.route(A, &["foo"], true, present), Ok(()));
+        // 迁出中的 slot，不存在的 key 让客户端去目标节点
+        assert_eq!(
+            topology.route(A, &["bar"], false, present),
+            Err(Redirect::Ask(bar, B.into()))
+        );
+        assert_eq!(topology.route(A, &["{bar}x"], false, present), Ok(()));
+        assert_eq!(
+            topology.route(B, &["bar"], false, present),
+            Err(Redirect::Moved(bar, A.into()))
+        );
+        let unassigned = (0..)
+            .map(|i| format!("k{}", i))
+            .find(|k| key_slot(k.as_bytes()) > 16000);
+        assert_eq!(
+            topology.route(A, &[&unassigned.unwrap()], false, present),
+            Err(Redirect::Unassigned)
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use bytes::Bytes;
+
+use super::{Parse, ParseError, Session};
+use crate::{acl::Users, frame::Frame};
+
+/// ACL SETUSER username [rule ...] | ACL GETUSER username | ACL LIST | ACL WHOAMI
+///
+/// 规则的写法见 [`crate::acl`]。
+#[derive(Debug)]
+pub enum Acl {
+    SetUser {
+        username: String,
+        rules: Vec<String>,
+    },
+    GetUser {
+        username: String,
+    },
+    List,
+    WhoAmI,
+}
+
+impl Acl {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Acl, ParseError> {
+        let subcommand = parse.next_string()?.to_uppercase();
+        match &subcommand[..] {
+            "SETUSER" => {
+                let username = parse.next_string()?;
+                let mut rules = Vec::with_capacity(parse.remaining());
+                while parse.remaining() > 0 {
+                    rules.push(parse.next_string()?);
+                }
+                Ok(Acl::SetUser { username, rules })
+            }
+            "GETUSER" => Ok(Acl::GetUser {
+                username: parse.next_string()?,
+            }),
+            "LIST" => Ok(Acl::List),
+            "WHOAMI" => Ok(Acl::WhoAmI),
+            _ => Err(ParseError::Other(format!(
+                "ERR unknown subcommand '{}'. Try ACL HELP.",
+                subcommand
+            ))),
+        }
+    }
+
+    pub(crate) fn apply(self, users: &Users, session: &Session) -> Frame {
+        match self {
+            Acl::SetUser { username, rules } => match users.set_user(&username, &rules) {
+                Ok(()) => Frame::Simple("OK".into()),
+                Err(e) => Frame::Error(e.to_string()),
+            },
+            Acl::GetUser { username } => {
+                let Some(user) = users.get_user(&username) else {
+                    return Frame::Null;
+                };
+                let strings =
+                    |items: Vec<&str>| Frame::Array(items.into_iter().map(bulk).collect());
+                Frame::Array(vec![
+                    bulk("flags"),
+                    strings(user.flags()),
+                    bulk("passwords"),
+                    strings(user.passwords().collect()),
+                    bulk("commands"),
+                    bulk(&user.describe_commands()),
+                    bulk("keys"),
+                    bulk(&user.describe_keys()),
+                    bulk("channels"),
+                    bulk(&user.describe_channels()),
+                ])
+            }
+            Acl::List => Frame::Array(users.list().iter().map(|line| bulk(line)).collect()),
+            Acl::WhoAmI => bulk(&session.user),
+        }
+    }
+}
+
+fn bulk(s: &str) -> Frame {
+    Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+}
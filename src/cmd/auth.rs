@@ -0,0 +1,185 @@
+use bytes::Bytes;
+
+use super::{Parse, ParseError, Session};
+use crate::{
+    acl::{Users, DEFAULT_USER},
+    frame::Frame,
+};
+
+/// AUTH [username] password
+///
+/// 不指定用户名时为 default 用户，它的密码即配置的 requirepass。
+#[derive(Debug)]
+pub struct Auth {
+    username: Option<String>,
+    password: Bytes,
+}
+
+impl Auth {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Auth, ParseError> {
+        let first = parse.next_bytes()?;
+        if parse.remaining() == 0 {
+            return Ok(Auth {
+                username: None,
+                password: first,
+            });
+        }
+        Ok(Auth {
+            username: Some(String::from_utf8_lossy(&first).into_owned()),
+            password: parse.next_bytes()?,
+        })
+    }
+
+    /// 认证成功时修改连接的状态
+    pub(crate) fn apply(self, users: &Users, session: &mut Session) -> Frame {
+        if self.username.is_none() && users.is_nopass(DEFAULT_USER) {
+            return Frame::Error(
+                "ERR AUTH <password> called without any password configured for the default user. \
+                 Are you sure your configuration is correct?"
+                    .into(),
+            );
+        }
+        let username = self.username.as_deref().unwrap_or(DEFAULT_USER);
+        match authenticate(users, username, &self.password, session) {
+            Ok(()) => Frame::Simple("OK".into()),
+            Err(e) => e,
+        }
+    }
+}
+
+/// HELLO [protover [AUTH username password] [SETNAME clientname]]
+///
+/// 支持协议版本 2 和 3，其他版本返回 NOPROTO。切换到 3 之后响应的编码不变，只是服务端可以向
+/// 连接发送 Push，例如 CLIENT TRACKING 的失效消息。服务端设置了密码时，未认证的连接需要带上 AUTH 选项。
+#[derive(Debug)]
+pub struct Hello {
+    protover: Option<u64>,
+    auth: Option<(String, Bytes)>,
+    setname: Option<String>,
+}
+
+impl Hello {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Hello, ParseError> {
+        let mut hello = Hello {
+            protover: None,
+            auth: None,
+            setname: None,
+        };
+        if parse.remaining() == 0 {
+            return Ok(hello);
+        }
+        hello.protover = Some(parse.next_int()?);
+        while parse.remaining() > 0 {
+            match &parse.next_string()?.to_uppercase()[..] {
+                "AUTH" => hello.auth = Some((parse.next_string()?, parse.next_bytes()?)),
+                "SETNAME" => hello.setname = Some(parse.next_string()?),
+                option => {
+                    return Err(ParseError::Other(format!(
+                        "ERR Syntax error in HELLO option '{}'",
+                        option
+                    )))
+                }
+            }
+        }
+        Ok(hello)
+    }
+
+    pub(crate) fn apply(self, users: &Users, session: &mut Session) -> Frame {
+        if self
+            .protover
+            .is_some_and(|version| version != 2 && version != 3)
+        {
+            return Frame::Error("NOPROTO unsupported protocol version".into());
+        }
+        if let Some((username, password)) = &self.auth {
+            if let Err(e) = authenticate(users, username, password, session) {
+                return e;
+            }
+        }
+        if !session.authenticated {
+            return Frame::Error(
+                "NOAUTH HELLO must be called with the client already authenticated, otherwise the \
+                 HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client \
+                 and select the RESP protocol version at the same time"
+                    .into(),
+            );
+        }
+        if let Some(name) = self.setname {
+            session.name = Some(name);
+        }
+        if let Some(version) = self.protover {
+            session.protocol = version as u8;
+        }
+
+        let bulk = |s: &'static str| Frame::Bulk(Bytes::from_static(s.as_bytes()));
+        Frame::Array(vec![
+            bulk("server"),
+            bulk("redis"),
+            bulk("version"),
+            bulk(env!("CARGO_PKG_VERSION")),
+            bulk("proto"),
+            Frame::Integer(session.protocol.into()),
+            bulk("mode"),
+            bulk("standalone"),
+            bulk("role"),
+            bulk("master"),
+            bulk("modules"),
+            Frame::Array(vec![]),
+        ])
+    }
+}
+
+/// 校验用户名和密码，成功时把连接切换为这个用户
+///
+/// 密码使用常数时间比较，比较的耗时不会泄露密码的前缀。
+fn authenticate(
+    users: &Users,
+    username: &str,
+    password: &[u8],
+    session: &mut Session,
+) -> Result<(), Frame> {
+    if !users.authenticate(username, password) {
+        return Err(Frame::Error(
+            "WRONGPASS invalid username-password pair or user is disabled.".into(),
+        ));
+    }
+    session.authenticated = true;
+    session.user = username.to_string();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth(args: &[&'static str]) -> Auth {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::from_static(arg.as_bytes())))
+                .collect(),
+        );
+        let mut parse = Parse::new(frame).unwrap();
+        Auth::parse_frames(&mut parse).unwrap()
+    }
+
+    #[test]
+    fn auth_checks_password_and_user() {
+        let users = Users::new(Some("secret"));
+        let mut session = Session::new(1, &users);
+        assert!(!session.authenticated);
+
+        let wrong = auth(&["secrets"]).apply(&users, &mut session);
+        assert!(matches!(wrong, Frame::Error(e) if e.starts_with("WRONGPASS")));
+        let other_user = auth(&["alice", "secret"]).apply(&users, &mut session);
+        assert!(matches!(other_user, Frame::Error(e) if e.starts_with("WRONGPASS")));
+        assert!(!session.authenticated);
+
+        let ok = auth(&["default", "secret"]).apply(&users, &mut session);
+        assert_eq!(ok, Frame::Simple("OK".into()));
+        assert!(session.authenticated);
+
+        let users = Users::new(None);
+        let unset = auth(&["secret"]).apply(&users, &mut Session::new(1, &users));
+        assert!(matches!(unset, Frame::Error(e) if e.contains("without any password")));
+    }
+}
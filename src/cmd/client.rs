@@ -0,0 +1,206 @@
+use std::fmt::Write;
+
+use bytes::Bytes;
+
+use super::{Parse, ParseError, Session};
+use crate::{
+    frame::Frame,
+    server::{PeerAddr, State},
+};
+
+/// CLIENT ID | CLIENT SETNAME name | CLIENT GETNAME | CLIENT LIST | CLIENT KILL ... |
+/// CLIENT TRACKING ON|OFF [BCAST] [PREFIX prefix ...]
+///
+/// CLIENT KILL 支持旧的 `CLIENT KILL addr` 形式，以及 `ID`、`ADDR`、`USER`、`SKIPME` 过滤条件，
+/// 被选中的连接执行完当前命令后关闭。
+#[derive(Debug)]
+pub enum Client {
+    Id,
+    SetName(Option<String>),
+    GetName,
+    List,
+    Kill(KillFilter),
+    /// None 表示关闭
+    Tracking(Option<TrackingMode>),
+}
+
+/// 客户端缓存的失效通知方式，见 `server::tracking`
+///
+/// 默认模式下服务端记住连接读取过的 key，这些 key 被修改时通知一次；BCAST 模式下不记录读取，
+/// 所有以 `prefixes` 之一开头的 key 被修改时都通知，没有前缀时通知所有 key。
+/// 通知以 RESP3 的 Push 发送，只能在 HELLO 3 之后开启。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackingMode {
+    pub bcast: bool,
+    pub prefixes: Vec<String>,
+}
+
+/// CLIENT KILL 的过滤条件，所有条件都满足的连接被关闭
+#[derive(Debug, Default)]
+pub struct KillFilter {
+    id: Option<u64>,
+    addr: Option<PeerAddr>,
+    user: Option<String>,
+    /// 是否跳过执行命令的连接，新形式默认为 yes
+    skip_me: bool,
+    /// 旧的 `CLIENT KILL addr` 形式，返回 OK 而不是关闭的连接数
+    legacy: bool,
+}
+
+impl Client {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Client, ParseError> {
+        let subcommand = parse.next_string()?.to_uppercase();
+        match &subcommand[..] {
+            "ID" => Ok(Client::Id),
+            "SETNAME" => {
+                let name = parse.next_string()?;
+                // 与 Redis 相同，名字中不能有空格和换行等字符，CLIENT LIST 按空格分隔字段
+                if !name.bytes().all(|b| b.is_ascii_graphic()) {
+                    return Err(ParseError::Other(
+                        "ERR Client names cannot contain spaces, newlines or special characters."
+                            .into(),
+                    ));
+                }
+                Ok(Client::SetName((!name.is_empty()).then_some(name)))
+            }
+            "GETNAME" => Ok(Client::GetName),
+            "LIST" => Ok(Client::List),
+            "KILL" => KillFilter::parse_frames(parse).map(Client::Kill),
+            "TRACKING" => parse_tracking(parse).map(Client::Tracking),
+            _ => Err(ParseError::Other(format!(
+                "ERR unknown subcommand '{}'. Try CLIENT HELP.",
+                subcommand
+            ))),
+        }
+    }
+
+    pub(crate) fn apply(self, state: &State, session: &mut Session) -> Frame {
+        match self {
+            Client::Id => Frame::Integer(session.id as i64),
+            Client::SetName(name) => {
+                session.name = name;
+                Frame::Simple("OK".into())
+            }
+            Client::GetName => match &session.name {
+                Some(name) => Frame::Bulk(Bytes::copy_from_slice(name.as_bytes())),
+                None => Frame::Null,
+            },
+            Client::List => {
+                let mut list = String::new();
+                for info in state.clients.list() {
+                    let _ = writeln!(
+                        list,
+                        "id={} addr={} name={} age={} idle={} db={} cmd={} user={}",
+                        info.id,
+                        info.addr,
+                        info.name.as_deref().unwrap_or(""),
+                        info.connected.elapsed().as_secs(),
+                        info.last_activity.elapsed().as_secs(),
+                        info.db,
+                        info.last_command,
+                        info.user,
+                    );
+                }
+                Frame::Bulk(list.into())
+            }
+            Client::Kill(filter) => {
+                let killed = state.clients.kill(|info| {
+                    filter.id.is_none_or(|id| id == info.id)
+                        && filter.addr.as_ref().is_none_or(|addr| *addr == info.addr)
+                        && filter.user.as_ref().is_none_or(|user| *user == info.user)
+                        && !(filter.skip_me && info.id == session.id)
+                });
+                match (filter.legacy, killed) {
+                    (true, 0) => Frame::Error("ERR No such client".into()),
+                    (true, _) => Frame::Simple("OK".into()),
+                    (false, killed) => Frame::Integer(killed as i64),
+                }
+            }
+            Client::Tracking(mode) => {
+                if mode.is_some() && session.protocol < 3 {
+                    return Frame::Error(
+                        "ERR CLIENT TRACKING requires RESP3, switch the connection with HELLO 3"
+                            .into(),
+                    );
+                }
+                let switched = match (&session.tracking, &mode) {
+                    (Some(current), Some(mode)) => current.bcast != mode.bcast,
+                    _ => false,
+                };
+                if switched {
+                    return Frame::Error(
+                        "ERR You can't switch BCAST mode on/off before disabling tracking for \
+                         this client, and then re-enabling it with a different mode."
+                            .into(),
+                    );
+                }
+                // 连接的处理器在命令执行后按新的模式登记，见 `server::tracking`
+                session.tracking = mode;
+                Frame::Simple("OK".into())
+            }
+        }
+    }
+}
+
+/// ON|OFF [BCAST] [PREFIX prefix ...]
+fn parse_tracking(parse: &mut Parse) -> Result<Option<TrackingMode>, ParseError> {
+    let syntax_error = || ParseError::Other("ERR syntax error".into());
+    let on = match &parse.next_string()?.to_uppercase()[..] {
+        "ON" => true,
+        "OFF" => false,
+        _ => return Err(syntax_error()),
+    };
+    let mut mode = TrackingMode::default();
+    while parse.remaining() > 0 {
+        match &parse.next_string()?.to_uppercase()[..] {
+            "BCAST" => mode.bcast = true,
+            "PREFIX" => mode.prefixes.push(parse.next_string()?),
+            _ => return Err(syntax_error()),
+        }
+    }
+    if !mode.prefixes.is_empty() && !mode.bcast {
+        return Err(ParseError::Other(
+            "ERR PREFIX option requires BCAST mode to be enabled".into(),
+        ));
+    }
+    Ok(on.then_some(mode))
+}
+
+impl KillFilter {
+    fn parse_frames(parse: &mut Parse) -> Result<KillFilter, ParseError> {
+        let invalid_addr = || ParseError::Other("ERR Invalid address".into());
+        let syntax_error = || ParseError::Other("ERR syntax error".into());
+
+        if parse.remaining() == 1 {
+            let addr = parse.next_string()?.parse().map_err(|_| invalid_addr())?;
+            return Ok(KillFilter {
+                addr: Some(addr),
+                legacy: true,
+                ..KillFilter::default()
+            });
+        }
+        let mut filter = KillFilter {
+            skip_me: true,
+            ..KillFilter::default()
+        };
+        while parse.remaining() > 0 {
+            let option = parse.next_string()?.to_uppercase();
+            match &option[..] {
+                "ID" => filter.id = Some(parse.next_int()?),
+                "ADDR" => {
+                    filter.addr = Some(parse.next_string()?.parse().map_err(|_| invalid_addr())?)
+                }
+                "USER" => filter.user = Some(parse.next_string()?),
+                "SKIPME" => {
+                    filter.skip_me = match &parse.next_string()?.to_lowercase()[..] {
+                        "yes" => true,
+                        "no" => false,
+                        _ => return Err(syntax_error()),
+                    }
+                }
+                _ => return Err(syntax_error()),
+            }
+        }
+        Ok(filter)
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::BTreeSet;
+
+use bytes::Bytes;
+
+use super::{Parse, ParseError, Session};
+use crate::{
+    cluster::{key_slot, SLOTS},
+    frame::Frame,
+    server::State,
+};
+
+/// CLUSTER INFO | CLUSTER SLOTS | CLUSTER KEYSLOT key
+///
+/// SLOTS 的每一项为 `[start, end, [host, port]]`，节点之间不通信，没有 node id。
+#[derive(Debug)]
+pub enum Cluster {
+    Info,
+    Slots,
+    KeySlot(Bytes),
+}
+
+impl Cluster {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Cluster, ParseError> {
+        let subcommand = parse.next_string()?.to_uppercase();
+        match &subcommand[..] {
+            "INFO" => Ok(Cluster::Info),
+            "SLOTS" => Ok(Cluster::Slots),
+            "KEYSLOT" => Ok(Cluster::KeySlot(parse.next_bytes()?)),
+            _ => Err(ParseError::Other(format!(
+                "ERR unknown subcommand '{}'. Try CLUSTER HELP.",
+                subcommand
+            ))),
+        }
+    }
+
+    pub(crate) fn apply(self, state: &State) -> Frame {
+        let Some(cluster) = &state.cluster else {
+            return Frame::Error("ERR This instance has cluster support disabled".into());
+        };
+        let topology = cluster.topology();
+        match self {
+            Cluster::Info => {
+                let assigned = topology.assigned();
+                let serving: BTreeSet<_> = topology.ranges().iter().map(|r| &r.node).collect();
+                let state = if assigned == SLOTS as usize {
+                    "ok"
+                } else {
+                    "fail"
+                };
+                let info = format!(
+                    "cluster_enabled:1\r\ncluster_state:{}\r\ncluster_slots_assigned:{}\r\n\
+                     cluster_slots_ok:{}\r\ncluster_known_nodes:{}\r\ncluster_size:{}\r\n",
+                    state,
+                    assigned,
+                    assigned,
+                    topology.nodes().len(),
+                    serving.len(),
+                );
+                Frame::Bulk(info.into())
+            }
+            Cluster::Slots => {
+                let ranges = topology.ranges().iter().map(|range| {
+                    let (host, port) = range.node.rsplit_once(':').unwrap_or_default();
+                    Frame::Array(vec![
+                        Frame::Integer(range.start.into()),
+                        Frame::Integer(range.end.into()),
+                        Frame::Array(vec![
+                            Frame::Bulk(Bytes::copy_from_slice(host.as_bytes())),
+                            Frame::Integer(port.parse().unwrap_or_default()),
+                        ]),
+                    ])
+                });
+                Frame::Array(ranges.collect())
+            }
+            Cluster::KeySlot(key) => Frame::Integer(key_slot(&key).into()),
+        }
+    }
+}
+
+/// ASKING：下一条命令访问的 slot 正在迁入这个节点时，在这里执行而不是回复 MOVED
+#[derive(Debug)]
+pub struct Asking;
+
+impl Asking {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Asking, ParseError> {
+        Ok(Asking)
+    }
+
+    pub(crate) fn apply(self, state: &State, session: &mut Session) -> Frame {
+        if state.cluster.is_none() {
+            return Frame::Error("ERR This instance has cluster support disabled".into());
+        }
+        session.asking = true;
+        Frame::Simple("OK".into())
+    }
+}
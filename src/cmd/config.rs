@@ -0,0 +1,81 @@
+use super::{glob_match, Parse, ParseError};
+use crate::{
+    config::{ConfigError, NAMES},
+    frame::Frame,
+    server::State,
+};
+
+/// CONFIG GET pattern [pattern ...] | CONFIG SET name value [name value ...]
+///
+/// GET 返回名字匹配任何一个 glob 模式的配置项，每个配置项为名字和值两个元素。
+/// SET 只能修改可以在运行时修改的配置项，多个配置项同时生效，任何一个无效时都不修改。
+#[derive(Debug)]
+pub enum Config {
+    Get(Vec<String>),
+    Set(Vec<(String, String)>),
+}
+
+impl Config {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Config, ParseError> {
+        let subcommand = parse.next_string()?.to_uppercase();
+        match &subcommand[..] {
+            "GET" => {
+                let mut patterns = vec![parse.next_string()?];
+                while parse.remaining() > 0 {
+                    patterns.push(parse.next_string()?);
+                }
+                Ok(Config::Get(patterns))
+            }
+            "SET" => {
+                if parse.remaining() == 0 || !parse.remaining().is_multiple_of(2) {
+                    return Err(ParseError::Other(
+                        "ERR wrong number of arguments for 'config|set' command".into(),
+                    ));
+                }
+                let mut pairs = Vec::new();
+                while parse.remaining() > 0 {
+                    pairs.push((parse.next_string()?, parse.next_string()?));
+                }
+                Ok(Config::Set(pairs))
+            }
+            _ => Err(ParseError::Other(format!(
+                "ERR unknown subcommand '{}'. Try CONFIG HELP.",
+                subcommand
+            ))),
+        }
+    }
+
+    pub(crate) fn apply(self, state: &State) -> Frame {
+        match self {
+            Config::Get(patterns) => {
+                let config = state.config.borrow();
+                let matched = NAMES.iter().filter(|name| {
+                    patterns.iter().any(|pattern| {
+                        glob_match(pattern.to_lowercase().as_bytes(), name.as_bytes())
+                    })
+                });
+                let mut reply = Vec::new();
+                for name in matched {
+                    let value = config.get(name).unwrap_or_default();
+                    reply.push(Frame::Bulk((*name).into()));
+                    reply.push(Frame::Bulk(value.into()));
+                }
+                Frame::Array(reply)
+            }
+            Config::Set(pairs) => match state.set_config(&pairs) {
+                Ok(()) => Frame::Simple("OK".into()),
+                Err(e) => Frame::Error(match &e {
+                    ConfigError::Unknown(name) => format!(
+                        "ERR Unknown option or number of arguments for CONFIG SET - '{}'",
+                        name
+                    ),
+                    ConfigError::Invalid { name, .. } | ConfigError::Immutable(name) => format!(
+                        "ERR CONFIG SET failed (possibly related to argument '{}') - {}",
+                        name, e
+                    ),
+                    _ => format!("ERR {}", e),
+                }),
+            },
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! 命令的执行期限
+//!
+//! 配置了 `command-timeout` 时，服务端在 [`scope`] 中执行命令，遍历大量数据的只读命令
+//! （SCAN、XRANGE）在循环中调用 [`exceeded`]，超过期限后放弃已经收集的结果，回复 [`timed_out`]。
+//!
+//! 中止是协作式的：命令同步执行，期间不会让出线程，只能在这些检查点停下；没有检查点的命令
+//! 总会执行完。写命令不设检查点，中途停下会留下修改了一半的数据。
+
+use std::{cell::Cell, time::Instant};
+
+use crate::frame::Frame;
+
+/// 每遍历多少个元素检查一次期限，避免每个元素都读取时钟
+const CHECK_INTERVAL: usize = 128;
+
+thread_local! {
+    /// 当前线程正在执行的命令的期限，命令同步执行，不会在期间切换到其他连接
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// 在期限 `deadline` 内执行 `f`，None 表示不限制
+pub(crate) fn scope<R>(deadline: Option<Instant>, f: impl FnOnce() -> R) -> R {
+    /// `f` panic 时同样恢复之前的期限
+    struct Restore(Option<Instant>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            DEADLINE.set(self.0);
+        }
+    }
+
+    let _restore = Restore(DEADLINE.replace(deadline));
+    f()
+}
+
+/// 遍历到第 `step` 个元素时，当前命令是否已经超过期限
+pub(crate) fn exceeded(step: usize) -> bool {
+    step.is_multiple_of(CHECK_INTERVAL)
+        && DEADLINE
+            .get()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// 超过期限的命令的回复
+pub(crate) fn timed_out() -> Frame {
+    Frame::Error("TIMEOUT command exceeded command-timeout".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::{cmd::Command, db::Db};
+
+    fn scan(db: &Db) -> Frame {
+        let frame = Frame::Array(
+            ["SCAN", "0", "COUNT", "1000", "MATCH", "*"]
+                .iter()
+                .map(|arg| Frame::Bulk(Bytes::from_static(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(&frame).unwrap().unwrap().apply(db)
+    }
+
+    #[test]
+    fn expired_deadline_aborts_scan() {
+        let db = Db::new();
+        for i in 0..500 {
+            db.set(format!("key:{}", i), Bytes::from_static(b"1"), None)
+                .unwrap();
+        }
+
+        let response = scope(Some(Instant::now()), || scan(&db));
+        assert_eq!(response, timed_out());
+        // 离开 scope 后恢复为不限制
+        assert!(!exceeded(0));
+        let later = Instant::now() + Duration::from_secs(60);
+        assert!(matches!(scope(Some(later), || scan(&db)), Frame::Array(_)));
+        assert!(matches!(scan(&db), Frame::Array(_)));
+    }
+}
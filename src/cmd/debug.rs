@@ -0,0 +1,139 @@
+//! 故障注入，需要 `fault-injection` 特性，只用于测试
+//!
+//! 每个连接有自己的故障配置，由 DEBUG 命令设置，只影响执行 DEBUG 的连接：
+//!
+//! - `DEBUG LATENCY <毫秒>`：每个命令执行前等待
+//! - `DEBUG CLOSE-RATE <概率>`：每个命令执行前按概率直接关闭连接，不发送响应
+//! - `DEBUG OOM-RATE <概率>`：每个命令按概率返回内存不足的错误，模拟分配失败
+//! - `DEBUG SEED <n>`：用固定的种子重置随机数，使故障的序列可以重现
+//! - `DEBUG RESET`：清除所有故障
+//! - `DEBUG PANIC`：执行时 panic，用于验证 panic 只影响执行命令的连接
+//!
+//! DEBUG 命令本身不受故障影响，总是可以清除故障。
+
+use std::time::Duration;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{Parse, ParseError, Session};
+use crate::frame::Frame;
+
+/// 连接上注入的故障
+#[derive(Debug)]
+pub struct Faults {
+    latency: Duration,
+    close_rate: f64,
+    oom_rate: f64,
+    rng: StdRng,
+}
+
+/// 一个命令遇到的故障
+#[derive(Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// 不发送响应，关闭连接
+    Close,
+    /// 返回内存不足的错误，不执行命令
+    OutOfMemory,
+}
+
+impl Default for Faults {
+    fn default() -> Faults {
+        Faults {
+            latency: Duration::ZERO,
+            close_rate: 0.0,
+            oom_rate: 0.0,
+            rng: StdRng::from_entropy(),
+        }
+    }
+}
+
+impl Faults {
+    /// 每个命令执行前等待的时间
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// 决定下一个命令遇到的故障，先判断关闭再判断内存不足
+    pub fn roll(&mut self) -> Option<Fault> {
+        if self.close_rate > 0.0 && self.rng.gen_bool(self.close_rate) {
+            return Some(Fault::Close);
+        }
+        if self.oom_rate > 0.0 && self.rng.gen_bool(self.oom_rate) {
+            return Some(Fault::OutOfMemory);
+        }
+        None
+    }
+}
+
+/// DEBUG LATENCY | CLOSE-RATE | OOM-RATE | SEED | RESET | PANIC
+#[derive(Debug)]
+pub enum Debug {
+    Latency(Duration),
+    CloseRate(f64),
+    OomRate(f64),
+    Seed(u64),
+    Reset,
+    Panic,
+}
+
+impl Debug {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Debug, ParseError> {
+        let subcommand = parse.next_string()?.to_uppercase();
+        match &subcommand[..] {
+            "LATENCY" => Ok(Debug::Latency(Duration::from_millis(parse.next_int()?))),
+            "CLOSE-RATE" => Ok(Debug::CloseRate(probability(parse)?)),
+            "OOM-RATE" => Ok(Debug::OomRate(probability(parse)?)),
+            "SEED" => Ok(Debug::Seed(parse.next_int()?)),
+            "RESET" => Ok(Debug::Reset),
+            "PANIC" => Ok(Debug::Panic),
+            _ => Err(ParseError::Other(format!(
+                "ERR unknown subcommand '{}'. Try DEBUG HELP.",
+                subcommand
+            ))),
+        }
+    }
+
+    pub(crate) fn apply(self, session: &mut Session) -> Frame {
+        let faults = &mut session.faults;
+        match self {
+            Debug::Latency(latency) => faults.latency = latency,
+            Debug::CloseRate(rate) => faults.close_rate = rate,
+            Debug::OomRate(rate) => faults.oom_rate = rate,
+            Debug::Seed(seed) => faults.rng = StdRng::seed_from_u64(seed),
+            Debug::Reset => *faults = Faults::default(),
+            Debug::Panic => panic!("DEBUG PANIC"),
+        }
+        Frame::Simple("OK".into())
+    }
+}
+
+fn probability(parse: &mut Parse) -> Result<f64, ParseError> {
+    match parse.next_string()?.parse() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+        _ => Err(ParseError::Other(
+            "ERR probability should be between 0 and 1".into(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_faults_are_reproducible() {
+        let sequence = |seed| {
+            let mut faults = Faults {
+                close_rate: 0.2,
+                oom_rate: 0.5,
+                rng: StdRng::seed_from_u64(seed),
+                ..Faults::default()
+            };
+            (0..32).map(|_| faults.roll()).collect::<Vec<_>>()
+        };
+        assert_eq!(sequence(7), sequence(7));
+        assert!(sequence(7).contains(&Some(Fault::Close)));
+        assert!(sequence(7).contains(&Some(Fault::OutOfMemory)));
+        assert_eq!(Faults::default().roll(), None);
+    }
+}
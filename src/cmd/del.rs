@@ -0,0 +1,26 @@
+use super::{Parse, ParseError};
+use crate::{db::Db, frame::Frame};
+
+/// DEL key [key ...]
+#[derive(Debug)]
+pub struct Del {
+    keys: Vec<String>,
+}
+
+impl Del {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Del, ParseError> {
+        let mut keys = vec![parse.next_string()?];
+        while parse.remaining() > 0 {
+            keys.push(parse.next_string()?);
+        }
+        Ok(Del { keys })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        self.keys.iter().map(String::as_str).collect()
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        Frame::Integer(db.del(&self.keys) as i64)
+    }
+}
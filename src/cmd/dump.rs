@@ -0,0 +1,119 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+
+use super::{Parse, ParseError};
+use crate::{
+    db::{rdb, Db, ReadView},
+    frame::Frame,
+};
+
+/// DUMP key
+#[derive(Debug)]
+pub struct Dump {
+    key: String,
+}
+
+impl Dump {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Dump, ParseError> {
+        Ok(Dump {
+            key: parse.next_string()?,
+        })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    pub(crate) fn apply(self, db: &ReadView) -> Frame {
+        match db.dump(&self.key) {
+            Some(payload) => Frame::Bulk(payload),
+            None => Frame::Null,
+        }
+    }
+}
+
+/// RESTORE key ttl serialized-value [REPLACE] [ABSTTL]
+///
+/// `ttl` 为 0 表示不过期，带 ABSTTL 时 `ttl` 是 unix 毫秒时间戳。
+/// 和 [`Set`](super::Set) 一样，记录到 AOF 和发送给副本时相对的 TTL 改写为 ABSTTL。
+#[derive(Debug)]
+pub struct Restore {
+    key: String,
+    ttl: u64,
+    payload: Bytes,
+    replace: bool,
+    absolute: bool,
+}
+
+impl Restore {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Restore, ParseError> {
+        let key = parse.next_string()?;
+        let ttl = parse
+            .next_signed_int()?
+            .try_into()
+            .map_err(|_| ParseError::Other("ERR Invalid TTL value, must be >= 0".into()))?;
+        let mut restore = Restore {
+            key,
+            ttl,
+            payload: parse.next_bytes()?,
+            replace: false,
+            absolute: false,
+        };
+        while let Some(option) = parse.peek_upper() {
+            parse.next_string()?;
+            match &option[..] {
+                "REPLACE" => restore.replace = true,
+                "ABSTTL" => restore.absolute = true,
+                _ => return Err(ParseError::Other("ERR syntax error".into())),
+            }
+        }
+        Ok(restore)
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    /// 改写记录的命令，相对的 TTL 换算为 unix 毫秒时间戳并加上 ABSTTL
+    pub(crate) fn propagate(&self) -> impl FnOnce(Frame, &Frame) -> Option<Frame> {
+        let rewritten = (self.ttl != 0 && !self.absolute).then(|| {
+            let at = rdb::unix_millis(Instant::now() + Duration::from_millis(self.ttl));
+            let mut args = vec![
+                Frame::Bulk(Bytes::from_static(b"RESTORE")),
+                Frame::Bulk(Bytes::from(self.key.clone())),
+                Frame::Bulk(Bytes::from(at.to_string())),
+                Frame::Bulk(self.payload.clone()),
+                Frame::Bulk(Bytes::from_static(b"ABSTTL")),
+            ];
+            if self.replace {
+                args.push(Frame::Bulk(Bytes::from_static(b"REPLACE")));
+            }
+            args
+        });
+        move |frame, _| Some(rewritten.map_or(frame, Frame::Array))
+    }
+
+    /// 把 TTL 参数换算为过期时刻，已经过期的绝对时间换算为当前时刻
+    fn expires_at(&self) -> Option<Instant> {
+        if self.ttl == 0 {
+            return None;
+        }
+        let ttl = if self.absolute {
+            (UNIX_EPOCH + Duration::from_millis(self.ttl))
+                .duration_since(SystemTime::now())
+                .unwrap_or_default()
+        } else {
+            Duration::from_millis(self.ttl)
+        };
+        Some(Instant::now() + ttl)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let expires_at = self.expires_at();
+        match db.restore(&self.key, &self.payload, expires_at, self.replace) {
+            Ok(()) => Frame::Simple("OK".into()),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+}
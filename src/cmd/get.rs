@@ -0,0 +1,28 @@
+use super::{Parse, ParseError};
+use crate::{db::ReadView, frame::Frame};
+
+/// GET key
+#[derive(Debug)]
+pub struct Get {
+    key: String,
+}
+
+impl Get {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Get, ParseError> {
+        Ok(Get {
+            key: parse.next_string()?,
+        })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    pub(crate) fn apply(self, db: &ReadView) -> Frame {
+        match db.get(&self.key) {
+            Ok(Some(value)) => Frame::Bulk(value),
+            Ok(None) => Frame::Null,
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+}
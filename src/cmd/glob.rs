@@ -0,0 +1,112 @@
+//! Redis 风格的 glob 匹配，用于 SCAN MATCH、KEYS、PSUBSCRIBE 等命令
+//!
+//! 支持 `*`、`?`、`[abc]`、`[^abc]`、`[a-z]` 以及 `\` 转义。
+
+/// `text` 是否匹配 `pattern`
+pub fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // 最近一个 `*` 的位置以及它当前匹配到的文本位置，失配时回溯到这里让 `*` 多匹配一个字符
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        let step = match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(1),
+            Some(b'[') => match match_class(&pattern[p..], text[t]) {
+                Some((true, len)) => Some(len),
+                Some((false, _)) => None,
+                // 没有闭合的 `[` 按普通字符处理
+                None => (text[t] == b'[').then_some(1),
+            },
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == text[t]).then_some(2),
+            Some(&c) => (c == text[t]).then_some(1),
+            None => None,
+        };
+        match (step, star) {
+            (Some(len), _) => {
+                p += len;
+                t += 1;
+            }
+            (None, Some((star_p, star_t))) => {
+                star = Some((star_p, star_t + 1));
+                p = star_p + 1;
+                t = star_t + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// 匹配 `[...]` 字符类，`pattern` 以 `[` 开头
+///
+/// 返回是否匹配以及字符类在模式中占用的长度，没有闭合的 `]` 时返回 None。
+fn match_class(pattern: &[u8], c: u8) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = pattern.get(i) == Some(&b'^');
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    loop {
+        match *pattern.get(i)? {
+            b']' => return Some((matched != negate, i + 1)),
+            b'\\' => {
+                matched |= *pattern.get(i + 1)? == c;
+                i += 2;
+            }
+            start
+                if pattern.get(i + 1) == Some(&b'-')
+                    && pattern.get(i + 2).is_some_and(|&end| end != b']') =>
+            {
+                let end = pattern[i + 2];
+                let (low, high) = if start <= end {
+                    (start, end)
+                } else {
+                    (end, start)
+                };
+                matched |= (low..=high).contains(&c);
+                i += 3;
+            }
+            other => {
+                matched |= other == c;
+                i += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_redis_patterns() {
+        let cases: &[(&str, &str, bool)] = &[
+            ("*", "", true),
+            ("h?llo", "hello", true),
+            ("h*llo", "heeeello", true),
+            ("h*llo", "hellox", false),
+            ("h[ae]llo", "hallo", true),
+            ("h[^e]llo", "hello", false),
+            ("h[a-b]llo", "hbllo", true),
+            ("user:*:name", "user:42:name", true),
+            ("a\\*b", "a*b", true),
+            ("a\\*b", "axb", false),
+            ("[abc", "[abc", true),
+        ];
+        for (pattern, text, expected) in cases {
+            assert_eq!(
+                glob_match(pattern.as_bytes(), text.as_bytes()),
+                *expected,
+                "{} {}",
+                pattern,
+                text
+            );
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use super::{Parse, ParseError};
+use crate::{db::Db, frame::Frame};
+
+/// INCR key / DECR key / INCRBY key increment / DECRBY key decrement
+///
+/// 四个命令都归结为给整数加上一个增量。
+#[derive(Debug)]
+pub struct IncrBy {
+    key: String,
+    delta: i64,
+}
+
+impl IncrBy {
+    /// `name` 为小写的命令名
+    pub(crate) fn parse_frames(parse: &mut Parse, name: &str) -> Result<IncrBy, ParseError> {
+        let key = parse.next_string()?;
+        let delta = match name {
+            "incr" => 1,
+            "decr" => -1,
+            "incrby" => parse.next_signed_int()?,
+            _ => parse
+                .next_signed_int()?
+                .checked_neg()
+                .ok_or_else(|| ParseError::Other("ERR decrement would overflow".into()))?,
+        };
+        Ok(IncrBy { key, delta })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.incr_by(&self.key, self.delta) {
+            Ok(n) => Frame::Integer(n),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt::Write;
+
+use super::{Parse, ParseError};
+use crate::{
+    db::{Db, DbStats},
+    frame::Frame,
+};
+
+/// INFO [section]
+///
+/// 目前只有 stats 和 keyspace 两部分，不指定时返回全部，未知的部分返回空字符串。
+#[derive(Debug)]
+pub struct Info {
+    section: Option<String>,
+}
+
+impl Info {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Info, ParseError> {
+        let section = match parse.remaining() {
+            0 => None,
+            _ => Some(parse.next_string()?.to_lowercase()),
+        };
+        Ok(Info { section })
+    }
+
+    /// `databases` 为所有逻辑数据库，下标即 SELECT 使用的编号
+    pub(crate) fn apply(self, databases: &[Db]) -> Frame {
+        let all = matches!(self.section.as_deref(), None | Some("all" | "default"));
+        let wants = |name: &str| all || self.section.as_deref() == Some(name);
+
+        let mut info = String::new();
+        if wants("stats") {
+            let total: DbStats = databases.iter().map(Db::stats).sum();
+            info.push_str("# Stats\r\n");
+            let _ = write!(
+                info,
+                "expired_keys:{}\r\nevicted_keys:{}\r\nkeyspace_hits:{}\r\nkeyspace_misses:{}\r\n",
+                total.expired, total.evicted, total.hits, total.misses
+            );
+        }
+        if wants("keyspace") {
+            if !info.is_empty() {
+                info.push_str("\r\n");
+            }
+            info.push_str("# Keyspace\r\n");
+            // 与 Redis 相同，空的数据库不列出
+            for (index, db) in databases.iter().enumerate() {
+                let stats = db.keyspace_stats();
+                if stats.keys > 0 {
+                    let _ = write!(info, "db{}:{}\r\n", index, stats);
+                }
+            }
+        }
+        Frame::Bulk(info.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn stats_sum_the_counters_of_all_databases() {
+        let (db0, db1) = (Db::new(), Db::new());
+        db0.set("a".into(), Bytes::new(), None).unwrap();
+        db0.get("a").unwrap();
+        db1.get("a").unwrap();
+
+        let info = Info {
+            section: Some("stats".into()),
+        };
+        assert_eq!(
+            info.apply(&[db0, db1]),
+            Frame::Bulk(
+                "# Stats\r\nexpired_keys:0\r\nevicted_keys:0\r\nkeyspace_hits:1\r\n\
+                 keyspace_misses:1\r\n"
+                    .into()
+            )
+        );
+    }
+}
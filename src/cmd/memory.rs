@@ -0,0 +1,43 @@
+use super::{Parse, ParseError};
+use crate::{
+    db::{memory::DEFAULT_SAMPLES, ReadView},
+    frame::Frame,
+};
+
+/// MEMORY USAGE key [SAMPLES count]
+#[derive(Debug)]
+pub struct MemoryUsage {
+    key: String,
+    samples: usize,
+}
+
+impl MemoryUsage {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<MemoryUsage, ParseError> {
+        let subcommand = parse.next_string()?.to_uppercase();
+        if subcommand != "USAGE" {
+            return Err(ParseError::Other(format!(
+                "ERR unknown subcommand '{}'. Try MEMORY HELP.",
+                subcommand
+            )));
+        }
+        let key = parse.next_string()?;
+        let samples = if parse.peek_upper().as_deref() == Some("SAMPLES") {
+            parse.next_string()?;
+            parse.next_int()? as usize
+        } else {
+            DEFAULT_SAMPLES
+        };
+        Ok(MemoryUsage { key, samples })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    pub(crate) fn apply(self, db: &ReadView) -> Frame {
+        match db.memory_usage(&self.key, self.samples) {
+            Some(size) => Frame::Integer(size as i64),
+            None => Frame::Null,
+        }
+    }
+}
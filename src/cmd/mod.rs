@@ -0,0 +1,547 @@
+//! 服务端支持的命令
+//!
+//! 最初只有 `mini-redis` 不支持的命令在这里实现，现在所有命令都在这里解析和执行，
+//! 每个命令一个结构体，由 [`Command`] 统一分发。
+
+mod parse;
+pub use parse::{Parse, ParseError};
+
+mod acl;
+pub use acl::Acl;
+
+mod auth;
+pub use auth::{Auth, Hello};
+
+mod client;
+pub use client::{Client, KillFilter, TrackingMode};
+
+mod cluster;
+pub use cluster::{Asking, Cluster};
+
+mod config;
+pub use config::Config;
+
+#[cfg(feature = "fault-injection")]
+mod debug;
+#[cfg(feature = "fault-injection")]
+pub use debug::{Debug, Fault, Faults};
+
+pub(crate) mod deadline;
+
+mod del;
+pub use del::Del;
+
+mod dump;
+pub use dump::{Dump, Restore};
+
+mod get;
+pub use get::Get;
+
+mod glob;
+pub use glob::glob_match;
+
+mod info;
+pub use info::Info;
+
+mod incr;
+pub use incr::IncrBy;
+
+mod memory;
+pub use memory::MemoryUsage;
+
+mod monitor;
+pub use monitor::Monitor;
+
+mod object;
+pub use object::ObjectEncoding;
+
+mod ping;
+pub use ping::Ping;
+
+mod pubsub;
+pub(crate) use pubsub::SUBSCRIBED_COMMANDS;
+pub use pubsub::{Publish, Subscription};
+
+mod quit;
+pub use quit::Quit;
+
+mod rename;
+pub use rename::Rename;
+
+mod replication;
+pub use replication::{FullSync, Replconf, ReplicaOf, Role};
+
+mod save;
+pub use save::{BgRewriteAof, BgSave, Save};
+
+mod scan;
+pub use scan::Scan;
+
+mod shutdown;
+pub use shutdown::Shutdown;
+
+mod select;
+pub use select::Select;
+
+mod session;
+pub use session::Session;
+
+mod set;
+pub use set::Set;
+
+mod slowlog;
+pub use slowlog::Slowlog;
+
+mod stream;
+pub use stream::{XAck, XAdd, XClaim, XDel, XGroup, XLen, XRange, XReadGroup, XSetId, XTrim};
+
+use crate::{
+    db::{Db, DbError, ReadView},
+    frame::Frame,
+    server::State,
+};
+
+/// 参数不足时统一返回 redis 风格的参数个数错误，并确认没有多余的参数
+fn finish<T>(
+    mut parse: Parse,
+    command_name: &str,
+    command: Result<T, ParseError>,
+) -> Result<T, ParseError> {
+    let command = command.map_err(|e| match e {
+        ParseError::EndOfStream => ParseError::Other(format!(
+            "ERR wrong number of arguments for '{}' command",
+            command_name
+        )),
+        e => e,
+    })?;
+    parse.finish()?;
+    Ok(command)
+}
+
+/// 根据原始命令帧和响应生成 AOF 中实际记录的命令帧
+type Propagate = Box<dyn FnOnce(Frame, &Frame) -> Option<Frame>>;
+
+#[derive(Debug)]
+pub enum Command {
+    Del(Del),
+    Dump(Dump),
+    Get(Get),
+    IncrBy(IncrBy),
+    MemoryUsage(MemoryUsage),
+    ObjectEncoding(ObjectEncoding),
+    Ping(Ping),
+    Rename(Rename),
+    Restore(Restore),
+    Save(Save),
+    BgSave(BgSave),
+    BgRewriteAof(BgRewriteAof),
+    Scan(Scan),
+    Set(Set),
+    XAdd(XAdd),
+    XLen(XLen),
+    XRange(XRange),
+    XDel(XDel),
+    XTrim(XTrim),
+    XGroup(XGroup),
+    XReadGroup(XReadGroup),
+    XAck(XAck),
+    XClaim(XClaim),
+    XSetId(XSetId),
+}
+
+impl Command {
+    /// 从命令帧中解析命令，不是这里支持的命令时返回 `Ok(None)`
+    ///
+    /// 只借用帧，未识别的命令帧仍然可以交给其他解析器处理。
+    pub fn from_frame(frame: &Frame) -> Result<Option<Command>, ParseError> {
+        let mut parse = Parse::new(frame.clone())?;
+        let command_name = parse.next_string()?.to_lowercase();
+
+        let command = match &command_name[..] {
+            "del" => Del::parse_frames(&mut parse).map(Command::Del),
+            "dump" => Dump::parse_frames(&mut parse).map(Command::Dump),
+            "get" => Get::parse_frames(&mut parse).map(Command::Get),
+            "incr" | "decr" | "incrby" | "decrby" => {
+                IncrBy::parse_frames(&mut parse, &command_name).map(Command::IncrBy)
+            }
+            "memory" => MemoryUsage::parse_frames(&mut parse).map(Command::MemoryUsage),
+            "object" => ObjectEncoding::parse_frames(&mut parse).map(Command::ObjectEncoding),
+            "ping" => Ping::parse_frames(&mut parse).map(Command::Ping),
+            "rename" => Rename::parse_frames(&mut parse).map(Command::Rename),
+            "restore" => Restore::parse_frames(&mut parse).map(Command::Restore),
+            "save" => Save::parse_frames(&mut parse).map(Command::Save),
+            "bgsave" => BgSave::parse_frames(&mut parse).map(Command::BgSave),
+            "bgrewriteaof" => BgRewriteAof::parse_frames(&mut parse).map(Command::BgRewriteAof),
+            "scan" => Scan::parse_frames(&mut parse).map(Command::Scan),
+            "set" => Set::parse_frames(&mut parse).map(Command::Set),
+            "xadd" => XAdd::parse_frames(&mut parse).map(Command::XAdd),
+            "xlen" => XLen::parse_frames(&mut parse).map(Command::XLen),
+            "xrange" => XRange::parse_frames(&mut parse).map(Command::XRange),
+            "xdel" => XDel::parse_frames(&mut parse).map(Command::XDel),
+            "xtrim" => XTrim::parse_frames(&mut parse).map(Command::XTrim),
+            "xgroup" => XGroup::parse_frames(&mut parse).map(Command::XGroup),
+            "xreadgroup" => XReadGroup::parse_frames(&mut parse).map(Command::XReadGroup),
+            "xack" => XAck::parse_frames(&mut parse).map(Command::XAck),
+            "xclaim" => XClaim::parse_frames(&mut parse).map(Command::XClaim),
+            "xsetid" => XSetId::parse_frames(&mut parse).map(Command::XSetId),
+            _ => return Ok(None),
+        };
+
+        finish(parse, &command_name, command).map(Some)
+    }
+
+    /// 命令访问的 key，执行前需要从存储层加载，写命令执行后需要写回
+    pub fn keys(&self) -> Vec<&str> {
+        match self {
+            Command::Del(cmd) => cmd.keys(),
+            Command::Dump(cmd) => cmd.keys(),
+            Command::Get(cmd) => cmd.keys(),
+            Command::IncrBy(cmd) => cmd.keys(),
+            Command::MemoryUsage(cmd) => cmd.keys(),
+            Command::ObjectEncoding(cmd) => cmd.keys(),
+            Command::Rename(cmd) => cmd.keys(),
+            Command::Restore(cmd) => cmd.keys(),
+            Command::Ping(_)
+            | Command::Save(_)
+            | Command::BgSave(_)
+            | Command::BgRewriteAof(_)
+            | Command::Scan(_) => vec![],
+            Command::Set(cmd) => cmd.keys(),
+            Command::XAdd(cmd) => cmd.keys(),
+            Command::XLen(cmd) => cmd.keys(),
+            Command::XRange(cmd) => cmd.keys(),
+            Command::XDel(cmd) => cmd.keys(),
+            Command::XTrim(cmd) => cmd.keys(),
+            Command::XGroup(cmd) => cmd.keys(),
+            Command::XReadGroup(cmd) => cmd.keys(),
+            Command::XAck(cmd) => cmd.keys(),
+            Command::XClaim(cmd) => cmd.keys(),
+            Command::XSetId(cmd) => cmd.keys(),
+        }
+    }
+
+    /// 是否会修改数据，写命令执行成功后需要追加到 AOF
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Command::Del(_)
+                | Command::IncrBy(_)
+                | Command::Rename(_)
+                | Command::Restore(_)
+                | Command::Set(_)
+                | Command::XAdd(_)
+                | Command::XDel(_)
+                | Command::XTrim(_)
+                | Command::XGroup(_)
+                | Command::XReadGroup(_)
+                | Command::XAck(_)
+                | Command::XClaim(_)
+                | Command::XSetId(_)
+        )
+    }
+
+    /// 执行命令，返回响应帧以及记录到操作日志中的序号
+    ///
+    /// 读命令和执行失败的命令不需要记录，`frame` 为解析出该命令的原始帧。
+    /// 写命令在 [`Db::record`] 中执行，日志中的顺序与修改数据的顺序相同。
+    pub fn execute(self, frame: Frame, db: &Db) -> (Frame, Option<u64>) {
+        if !self.is_write() {
+            return (self.apply(db), None);
+        }
+        let propagate = match &self {
+            Command::Restore(cmd) => Some(Box::new(cmd.propagate()) as Propagate),
+            Command::Set(cmd) => Some(Box::new(cmd.propagate()) as Propagate),
+            Command::XAdd(cmd) => Some(Box::new(cmd.propagate()) as Propagate),
+            Command::XClaim(cmd) => Some(Box::new(cmd.propagate()) as Propagate),
+            _ => None,
+        };
+        db.record(|| {
+            let response = self.apply(db);
+            if let Frame::Error(_) = response {
+                return (response, None);
+            }
+            let frame = match propagate {
+                Some(propagate) => propagate(frame, &response),
+                None => Some(frame),
+            };
+            (response, frame)
+        })
+    }
+
+    /// 执行命令并返回响应帧
+    pub fn apply(self, db: &Db) -> Frame {
+        match self {
+            Command::Del(cmd) => cmd.apply(db),
+            Command::IncrBy(cmd) => cmd.apply(db),
+            Command::Rename(cmd) => cmd.apply(db),
+            Command::Restore(cmd) => cmd.apply(db),
+            Command::Set(cmd) => cmd.apply(db),
+            Command::XAdd(cmd) => cmd.apply(db),
+            Command::XDel(cmd) => cmd.apply(db),
+            Command::XTrim(cmd) => cmd.apply(db),
+            Command::XGroup(cmd) => cmd.apply(db),
+            Command::XReadGroup(cmd) => cmd.apply(db),
+            Command::XAck(cmd) => cmd.apply(db),
+            Command::XClaim(cmd) => cmd.apply(db),
+            Command::XSetId(cmd) => cmd.apply(db),
+            cmd => cmd.apply_read_only(&db.read_view()),
+        }
+    }
+
+    /// 在只读视图上执行命令，写命令返回 [`DbError::ReadOnly`]
+    ///
+    /// 读命令的实现只接受 [`ReadView`]，不可能修改数据，副本模式只需要在这里分发。
+    pub fn apply_read_only(self, db: &ReadView) -> Frame {
+        match self {
+            Command::Dump(cmd) => cmd.apply(db),
+            Command::Get(cmd) => cmd.apply(db),
+            Command::MemoryUsage(cmd) => cmd.apply(db),
+            Command::ObjectEncoding(cmd) => cmd.apply(db),
+            Command::Ping(cmd) => cmd.apply(),
+            Command::Save(cmd) => cmd.apply(db),
+            Command::BgSave(cmd) => cmd.apply(db),
+            Command::BgRewriteAof(cmd) => cmd.apply(db),
+            Command::Scan(cmd) => cmd.apply(db),
+            Command::XLen(cmd) => cmd.apply(db),
+            Command::XRange(cmd) => cmd.apply(db),
+            _ => Frame::Error(DbError::ReadOnly.to_string()),
+        }
+    }
+}
+
+/// 需要访问连接状态或所有数据库的命令，由服务端在选择数据库之前处理
+#[derive(Debug)]
+pub enum ServerCommand {
+    Acl(Acl),
+    Asking(Asking),
+    Auth(Auth),
+    Client(Client),
+    Cluster(Cluster),
+    Config(Config),
+    #[cfg(feature = "fault-injection")]
+    Debug(Debug),
+    Hello(Hello),
+    Info(Info),
+    Monitor(Monitor),
+    Publish(Publish),
+    Quit(Quit),
+    Replconf(Replconf),
+    ReplicaOf(ReplicaOf),
+    Role(Role),
+    Select(Select),
+    Shutdown(Shutdown),
+    Slowlog(Slowlog),
+    Sync(FullSync),
+}
+
+impl ServerCommand {
+    /// 从命令帧中解析命令，不是这里支持的命令时返回 `Ok(None)`
+    pub fn from_frame(frame: &Frame) -> Result<Option<ServerCommand>, ParseError> {
+        let mut parse = Parse::new(frame.clone())?;
+        let command_name = parse.next_string()?.to_lowercase();
+
+        let command = match &command_name[..] {
+            "acl" => Acl::parse_frames(&mut parse).map(ServerCommand::Acl),
+            "asking" => Asking::parse_frames(&mut parse).map(ServerCommand::Asking),
+            "auth" => Auth::parse_frames(&mut parse).map(ServerCommand::Auth),
+            "client" => Client::parse_frames(&mut parse).map(ServerCommand::Client),
+            "cluster" => Cluster::parse_frames(&mut parse).map(ServerCommand::Cluster),
+            "config" => Config::parse_frames(&mut parse).map(ServerCommand::Config),
+            #[cfg(feature = "fault-injection")]
+            "debug" => Debug::parse_frames(&mut parse).map(ServerCommand::Debug),
+            "hello" => Hello::parse_frames(&mut parse).map(ServerCommand::Hello),
+            "info" => Info::parse_frames(&mut parse).map(ServerCommand::Info),
+            "monitor" => Monitor::parse_frames(&mut parse).map(ServerCommand::Monitor),
+            "publish" => Publish::parse_frames(&mut parse).map(ServerCommand::Publish),
+            "quit" => Quit::parse_frames(&mut parse).map(ServerCommand::Quit),
+            "replconf" => Replconf::parse_frames(&mut parse).map(ServerCommand::Replconf),
+            "replicaof" => ReplicaOf::parse_frames(&mut parse).map(ServerCommand::ReplicaOf),
+            "role" => Role::parse_frames(&mut parse).map(ServerCommand::Role),
+            "select" => Select::parse_frames(&mut parse).map(ServerCommand::Select),
+            "shutdown" => Shutdown::parse_frames(&mut parse).map(ServerCommand::Shutdown),
+            "slowlog" => Slowlog::parse_frames(&mut parse).map(ServerCommand::Slowlog),
+            "sync" => FullSync::parse_frames(&mut parse).map(ServerCommand::Sync),
+            _ => return Ok(None),
+        };
+        finish(parse, &command_name, command).map(Some)
+    }
+
+    /// 未认证的连接是否不能执行，只有认证相关的命令和 QUIT 可以在认证前执行
+    pub fn requires_auth(&self) -> bool {
+        !matches!(
+            self,
+            ServerCommand::Auth(_) | ServerCommand::Hello(_) | ServerCommand::Quit(_)
+        )
+    }
+
+    /// 是否发送给 MONITOR 连接，与 Redis 相同，认证相关的命令不发送以免泄露密码
+    pub fn is_monitored(&self) -> bool {
+        !matches!(
+            self,
+            ServerCommand::Acl(_)
+                | ServerCommand::Auth(_)
+                | ServerCommand::Config(Config::Set(_))
+                | ServerCommand::Hello(_)
+                | ServerCommand::Monitor(_)
+                | ServerCommand::Replconf(_)
+        )
+    }
+
+    /// 执行命令，`state` 为所有连接共享的服务端状态，`session` 为当前连接的状态
+    pub(crate) fn apply(self, state: &State, session: &mut Session) -> Frame {
+        let users = &state.users;
+        match self {
+            ServerCommand::Acl(cmd) => cmd.apply(users, session),
+            ServerCommand::Asking(cmd) => cmd.apply(state, session),
+            ServerCommand::Auth(cmd) => cmd.apply(users, session),
+            ServerCommand::Client(cmd) => cmd.apply(state, session),
+            ServerCommand::Cluster(cmd) => cmd.apply(state),
+            ServerCommand::Config(cmd) => cmd.apply(state),
+            #[cfg(feature = "fault-injection")]
+            ServerCommand::Debug(cmd) => cmd.apply(session),
+            ServerCommand::Hello(cmd) => cmd.apply(users, session),
+            ServerCommand::Info(cmd) => cmd.apply(&state.dbs),
+            ServerCommand::Monitor(cmd) => cmd.apply(session),
+            ServerCommand::Publish(cmd) => cmd.apply(state, session),
+            ServerCommand::Quit(cmd) => cmd.apply(session),
+            ServerCommand::Replconf(cmd) => cmd.apply(state, session),
+            ServerCommand::ReplicaOf(cmd) => cmd.apply(state),
+            ServerCommand::Role(cmd) => cmd.apply(state),
+            ServerCommand::Select(cmd) => cmd.apply(state.dbs.len(), &mut session.selected),
+            ServerCommand::Shutdown(cmd) => cmd.apply(state, session),
+            ServerCommand::Slowlog(cmd) => cmd.apply(state),
+            ServerCommand::Sync(cmd) => cmd.apply(state, session),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    fn frame(args: &[&'static str]) -> Frame {
+        Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::from_static(arg.as_bytes())))
+                .collect(),
+        )
+    }
+
+    fn command(args: &[&'static str]) -> Command {
+        Command::from_frame(&frame(args)).unwrap().unwrap()
+    }
+
+    #[test]
+    fn read_view_rejects_write_commands() {
+        let db = Db::new();
+        db.set("a".into(), Bytes::from_static(b"1"), None).unwrap();
+        let view = db.read_view();
+
+        let response = command(&["DEL", "a"]).apply_read_only(&view);
+        assert!(matches!(response, Frame::Error(e) if e == DbError::ReadOnly.to_string()));
+        let response = command(&["OBJECT", "ENCODING", "a"]).apply_read_only(&view);
+        assert!(matches!(response, Frame::Bulk(encoding) if encoding == "int"));
+        let response = command(&["DEL", "a"]).apply(&db);
+        assert!(matches!(response, Frame::Integer(1)));
+    }
+
+    #[test]
+    fn relative_expire_is_logged_as_absolute_time() {
+        let db = Db::new();
+        let now = || crate::db::rdb::unix_millis(std::time::Instant::now());
+        let set = frame(&["SET", "a", "1", "EX", "100"]);
+        let before = now();
+        let (_, seq) = command(&["SET", "a", "1", "EX", "100"]).execute(set, &db);
+        let after = now();
+
+        let op = db.op_log().tail(seq.unwrap()).try_next().unwrap().unwrap();
+        let Frame::Array(args) = &op.frame else {
+            panic!("expected an array");
+        };
+        assert_eq!(args[3], Frame::Bulk("PXAT".into()));
+        let Frame::Bulk(at) = &args[4] else {
+            panic!("expected a bulk string");
+        };
+        let at: u64 = std::str::from_utf8(at).unwrap().parse().unwrap();
+        assert!((before + 100_000..=after + 100_000).contains(&at));
+
+        // 重放时已经过去的时间戳使 key 立即过期
+        let response = command(&["SET", "a", "1", "PXAT", "1"]).apply(&db);
+        assert_eq!(response, Frame::Simple("OK".into()));
+        assert_eq!(db.get("a"), Ok(None));
+    }
+
+    #[test]
+    fn out_of_range_expire_is_rejected() {
+        let expected = "ERR invalid expire time in 'set' command";
+        for args in [
+            ["SET", "a", "1", "EX", "18446744073709551615"],
+            ["SET", "a", "1", "EX", "9223372036854776"],
+            ["SET", "a", "1", "PX", "9223372036854775808"],
+            ["SET", "a", "1", "EXAT", "18446744073709551615"],
+            ["SET", "a", "1", "PX", "0"],
+        ] {
+            let err = Command::from_frame(&frame(&args)).unwrap_err();
+            assert_eq!(err.to_string(), expected);
+        }
+
+        // 范围内的时间加上当前时刻仍然可能溢出
+        let db = Db::new();
+        assert_eq!(
+            db.set(
+                "b".into(),
+                Bytes::from_static(b"1"),
+                Some(std::time::Duration::MAX)
+            ),
+            Err(DbError::InvalidExpire)
+        );
+        assert_eq!(db.get("b"), Ok(None));
+    }
+
+    #[test]
+    fn restore_ttl_is_logged_as_absolute_time() {
+        let db = Db::new();
+        db.set("a".into(), Bytes::from_static(b"1"), None).unwrap();
+        let payload = db.dump("a").unwrap();
+        let restore = |args: &[Bytes]| {
+            let mut frame = vec![Frame::Bulk("RESTORE".into()), Frame::Bulk("b".into())];
+            frame.push(Frame::Bulk(args[0].clone()));
+            frame.push(Frame::Bulk(payload.clone()));
+            frame.extend(args[1..].iter().cloned().map(Frame::Bulk));
+            Frame::Array(frame)
+        };
+
+        let now = || crate::db::rdb::unix_millis(std::time::Instant::now());
+        let frame = restore(&["100000".into(), "REPLACE".into()]);
+        let before = now();
+        let cmd = Command::from_frame(&frame).unwrap().unwrap();
+        let (_, seq) = cmd.execute(frame, &db);
+        let after = now();
+
+        let op = db.op_log().tail(seq.unwrap()).try_next().unwrap().unwrap();
+        let Frame::Array(args) = &op.frame else {
+            panic!("expected an array");
+        };
+        assert_eq!(args[4], Frame::Bulk("ABSTTL".into()));
+        assert_eq!(args[5], Frame::Bulk("REPLACE".into()));
+        let Frame::Bulk(at) = &args[2] else {
+            panic!("expected a bulk string");
+        };
+        let at: u64 = std::str::from_utf8(at).unwrap().parse().unwrap();
+        assert!((before + 100_000..=after + 100_000).contains(&at));
+
+        // 没有过期时间或者已经是绝对时间时原样记录
+        for args in [
+            vec!["0".into(), "REPLACE".into()],
+            vec![at.to_string().into(), "ABSTTL".into(), "REPLACE".into()],
+        ] {
+            let frame = restore(&args);
+            let cmd = Command::from_frame(&frame).unwrap().unwrap();
+            let (_, seq) = cmd.execute(frame.clone(), &db);
+            let op = db.op_log().tail(seq.unwrap()).try_next().unwrap().unwrap();
+            assert_eq!(op.frame, frame);
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use super::{Parse, ParseError, Session};
+use crate::frame::Frame;
+
+/// MONITOR
+///
+/// 回复 OK 后连接进入 MONITOR 状态，此后收到所有连接执行的每条命令，直到连接关闭。
+#[derive(Debug)]
+pub struct Monitor;
+
+impl Monitor {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Monitor, ParseError> {
+        Ok(Monitor)
+    }
+
+    pub(crate) fn apply(self, session: &mut Session) -> Frame {
+        session.monitoring = true;
+        Frame::Simple("OK".into())
+    }
+}
@@ -0,0 +1,34 @@
+use super::{Parse, ParseError};
+use crate::{db::ReadView, frame::Frame};
+
+/// OBJECT ENCODING key
+#[derive(Debug)]
+pub struct ObjectEncoding {
+    key: String,
+}
+
+impl ObjectEncoding {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ObjectEncoding, ParseError> {
+        let subcommand = parse.next_string()?.to_uppercase();
+        if subcommand != "ENCODING" {
+            return Err(ParseError::Other(format!(
+                "ERR unknown subcommand '{}'. Try OBJECT HELP.",
+                subcommand
+            )));
+        }
+        Ok(ObjectEncoding {
+            key: parse.next_string()?,
+        })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    pub(crate) fn apply(self, db: &ReadView) -> Frame {
+        match db.view(&self.key, |value| value.map(|value| value.encoding())) {
+            Some(encoding) => Frame::Bulk(encoding.into()),
+            None => Frame::Null,
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::{str, vec};
+
+use bytes::Bytes;
+use thiserror::Error;
+
+use crate::frame::Frame;
+
+/// 按游标的方式依次读取命令帧中的参数
+///
+/// 命令帧是一个数组帧，第一个元素是命令名，后续元素是命令参数。
+#[derive(Debug)]
+pub struct Parse {
+    parts: vec::IntoIter<Frame>,
+}
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    /// 参数已经读完
+    #[error("protocol error; unexpected end of stream")]
+    EndOfStream,
+
+    #[error("{0}")]
+    Other(String),
+}
+
+impl Parse {
+    pub fn new(frame: Frame) -> Result<Parse, ParseError> {
+        let array = match frame {
+            Frame::Array(array) => array,
+            frame => {
+                return Err(ParseError::Other(format!(
+                    "protocol error; expected array, got {:?}",
+                    frame
+                )))
+            }
+        };
+
+        Ok(Parse {
+            parts: array.into_iter(),
+        })
+    }
+
+    fn next(&mut self) -> Result<Frame, ParseError> {
+        self.parts.next().ok_or(ParseError::EndOfStream)
+    }
+
+    /// 剩余未读取的参数个数
+    pub fn remaining(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// 查看下一个参数（转为大写），不移动游标，用于解析可选参数
+    pub fn peek_upper(&self) -> Option<String> {
+        match self.parts.as_slice().first()? {
+            Frame::Simple(s) => Some(s.to_uppercase()),
+            Frame::Bulk(data) => str::from_utf8(data).ok().map(|s| s.to_uppercase()),
+            _ => None,
+        }
+    }
+
+    pub fn next_string(&mut self) -> Result<String, ParseError> {
+        match self.next()? {
+            Frame::Simple(s) => Ok(s),
+            Frame::Bulk(data) => str::from_utf8(&data[..])
+                .map(|s| s.to_string())
+                .map_err(|_| ParseError::Other("protocol error; invalid string".into())),
+            frame => Err(ParseError::Other(format!(
+                "protocol error; expected simple frame or bulk frame, got {:?}",
+                frame
+            ))),
+        }
+    }
+
+    pub fn next_bytes(&mut self) -> Result<Bytes, ParseError> {
+        match self.next()? {
+            Frame::Simple(s) => Ok(Bytes::from(s.into_bytes())),
+            Frame::Bulk(data) => Ok(data),
+            frame => Err(ParseError::Other(format!(
+                "protocol error; expected simple frame or bulk frame, got {:?}",
+                frame
+            ))),
+        }
+    }
+
+    pub fn next_int(&mut self) -> Result<u64, ParseError> {
+        const MSG: &str = "ERR value is not an integer or out of range";
+
+        match self.next()? {
+            Frame::Integer(v) => u64::try_from(v).map_err(|_| ParseError::Other(MSG.into())),
+            Frame::Simple(data) => data.parse().map_err(|_| ParseError::Other(MSG.into())),
+            Frame::Bulk(data) => str::from_utf8(&data)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| ParseError::Other(MSG.into())),
+            frame => Err(ParseError::Other(format!(
+                "protocol error; expected int frame but got {:?}",
+                frame
+            ))),
+        }
+    }
+
+    /// 读取有符号整数，如 INCRBY 的增量
+    pub fn next_signed_int(&mut self) -> Result<i64, ParseError> {
+        const MSG: &str = "ERR value is not an integer or out of range";
+
+        match self.next()? {
+            Frame::Integer(v) => Ok(v),
+            Frame::Simple(data) => data.parse().map_err(|_| ParseError::Other(MSG.into())),
+            Frame::Bulk(data) => str::from_utf8(&data)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| ParseError::Other(MSG.into())),
+            frame => Err(ParseError::Other(format!(
+                "protocol error; expected int frame but got {:?}",
+                frame
+            ))),
+        }
+    }
+
+    /// 确认所有参数都已经被读取
+    pub fn finish(&mut self) -> Result<(), ParseError> {
+        if self.parts.next().is_none() {
+            Ok(())
+        } else {
+            Err(ParseError::Other("ERR syntax error".into()))
+        }
+    }
+}
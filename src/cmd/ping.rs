@@ -0,0 +1,27 @@
+use bytes::Bytes;
+
+use super::{Parse, ParseError};
+use crate::frame::Frame;
+
+/// PING [message]
+#[derive(Debug)]
+pub struct Ping {
+    message: Option<Bytes>,
+}
+
+impl Ping {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Ping, ParseError> {
+        let message = match parse.remaining() {
+            0 => None,
+            _ => Some(parse.next_bytes()?),
+        };
+        Ok(Ping { message })
+    }
+
+    pub(crate) fn apply(self) -> Frame {
+        match self.message {
+            Some(message) => Frame::Bulk(message),
+            None => Frame::Simple("PONG".into()),
+        }
+    }
+}
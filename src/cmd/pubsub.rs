@@ -0,0 +1,126 @@
+use bytes::Bytes;
+
+use super::{Parse, ParseError, Session};
+use crate::{
+    acl::{AclError, Users},
+    frame::Frame,
+    server::{Mailbox, PubSub, PubSubKind, State},
+};
+
+/// 订阅状态下 RESP2 连接还可以执行的命令，其他命令回复错误
+pub(crate) const SUBSCRIBED_COMMANDS: &[&str] = &[
+    "subscribe",
+    "unsubscribe",
+    "psubscribe",
+    "punsubscribe",
+    "ping",
+    "quit",
+];
+
+/// SUBSCRIBE channel [channel ...] | UNSUBSCRIBE [channel ...]
+/// | PSUBSCRIBE pattern [pattern ...] | PUNSUBSCRIBE [pattern ...]
+///
+/// 每个频道或者模式回复一条确认，由连接的处理器执行：前面的确认直接放入写缓冲区，
+/// 最后一条作为命令的响应。不带参数的 UNSUBSCRIBE、PUNSUBSCRIBE 取消这一类的所有订阅。
+/// 订阅时检查用户的频道权限，有一个名字不被允许时整条命令都不执行。
+#[derive(Debug)]
+pub struct Subscription {
+    subscribe: bool,
+    kind: PubSubKind,
+    names: Vec<Bytes>,
+}
+
+impl Subscription {
+    /// 从命令帧中解析命令，不是订阅相关的命令时返回 `Ok(None)`
+    pub fn from_frame(frame: &Frame) -> Result<Option<Subscription>, ParseError> {
+        let mut parse = Parse::new(frame.clone())?;
+        let command_name = parse.next_string()?.to_lowercase();
+        let (subscribe, kind) = match &command_name[..] {
+            "subscribe" => (true, PubSubKind::Channel),
+            "unsubscribe" => (false, PubSubKind::Channel),
+            "psubscribe" => (true, PubSubKind::Pattern),
+            "punsubscribe" => (false, PubSubKind::Pattern),
+            _ => return Ok(None),
+        };
+        let names = parse_names(&mut parse, subscribe);
+        let names = super::finish(parse, &command_name, names)?;
+        Ok(Some(Subscription {
+            subscribe,
+            kind,
+            names,
+        }))
+    }
+
+    /// 检查用户是否可以订阅所有的频道或者模式，取消订阅不需要检查
+    pub(crate) fn check(&self, users: &Users, user: &str) -> Result<(), AclError> {
+        if !self.subscribe {
+            return Ok(());
+        }
+        self.names.iter().try_for_each(|name| {
+            let name = String::from_utf8_lossy(name);
+            match self.kind {
+                PubSubKind::Channel => users.check_channel(user, &name),
+                PubSubKind::Pattern => users.check_channel_pattern(user, &name),
+            }
+        })
+    }
+
+    /// 修改订阅，返回每个名字的确认，消息发送到 `messages`
+    pub(crate) fn apply(
+        self,
+        pubsub: &PubSub,
+        messages: &Mailbox,
+        session: &mut Session,
+    ) -> Vec<Frame> {
+        let (replies, count) = if self.subscribe {
+            pubsub.subscribe(session.id, messages, self.kind, self.names)
+        } else {
+            pubsub.unsubscribe(session.id, self.kind, self.names)
+        };
+        session.subscriptions = count;
+        replies
+    }
+}
+
+/// 订阅至少需要一个名字，取消订阅可以不带参数
+fn parse_names(parse: &mut Parse, subscribe: bool) -> Result<Vec<Bytes>, ParseError> {
+    let mut names = vec![];
+    if subscribe {
+        names.push(parse.next_bytes()?);
+    }
+    while parse.remaining() > 0 {
+        names.push(parse.next_bytes()?);
+    }
+    Ok(names)
+}
+
+/// PUBLISH channel message
+///
+/// 回复收到消息的订阅数，同一个连接通过频道和多个模式订阅时分别计数。用户需要有访问频道的权限。
+/// 排队的消息超过上限的订阅者被关闭，见 `server::pubsub`。
+#[derive(Debug)]
+pub struct Publish {
+    channel: Bytes,
+    message: Bytes,
+}
+
+impl Publish {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Publish, ParseError> {
+        Ok(Publish {
+            channel: parse.next_bytes()?,
+            message: parse.next_bytes()?,
+        })
+    }
+
+    pub(crate) fn apply(self, state: &State, session: &Session) -> Frame {
+        let channel = String::from_utf8_lossy(&self.channel);
+        if let Err(e) = state.users.check_channel(&session.user, &channel) {
+            return Frame::Error(e.to_string());
+        }
+        let (sent, overflowed) = state.pubsub.publish(&self.channel, &self.message);
+        if !overflowed.is_empty() {
+            state.clients.kill(|info| overflowed.contains(&info.id));
+        }
+        Frame::Integer(sent as i64)
+    }
+}
@@ -0,0 +1,19 @@
+use super::{Parse, ParseError, Session};
+use crate::frame::Frame;
+
+/// QUIT
+///
+/// 回复 OK 后由服务端关闭连接。
+#[derive(Debug)]
+pub struct Quit;
+
+impl Quit {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Quit, ParseError> {
+        Ok(Quit)
+    }
+
+    pub(crate) fn apply(self, session: &mut Session) -> Frame {
+        session.closing = true;
+        Frame::Simple("OK".into())
+    }
+}
@@ -0,0 +1,39 @@
+use super::{Parse, ParseError};
+use crate::{db::Db, frame::Frame};
+
+/// RENAME key newkey
+#[derive(Debug)]
+pub struct Rename {
+    key: String,
+    new_key: String,
+}
+
+impl Rename {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Rename, ParseError> {
+        Ok(Rename {
+            key: parse.next_string()?,
+            new_key: parse.next_string()?,
+        })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key, &self.new_key]
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let result = db.with_keys(&[&self.key, &self.new_key], |locked| {
+            // 过期时间随值一起转移
+            let expires_at = locked.expires_at(&self.key);
+            let Some(value) = locked.remove(&self.key) else {
+                return Ok(false);
+            };
+            locked.insert(&self.new_key, value, expires_at);
+            Ok(true)
+        });
+        match result {
+            Ok(true) => Frame::Simple("OK".into()),
+            Ok(false) => Frame::Error("ERR no such key".into()),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+}
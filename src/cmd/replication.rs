@@ -0,0 +1,117 @@
+use super::{Parse, ParseError, Session};
+use crate::{db::Db, frame::Frame, server::State};
+
+/// REPLICAOF host port | REPLICAOF NO ONE
+///
+/// 成为主节点的副本，或者停止复制成为主节点，见 `server::replication`。
+#[derive(Debug)]
+pub struct ReplicaOf {
+    /// None 表示 NO ONE
+    primary: Option<(String, u16)>,
+}
+
+impl ReplicaOf {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ReplicaOf, ParseError> {
+        let host = parse.next_string()?;
+        let port = parse.next_string()?;
+        if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+            return Ok(ReplicaOf { primary: None });
+        }
+        let port = port
+            .parse()
+            .map_err(|_| ParseError::Other("ERR Invalid master port".into()))?;
+        Ok(ReplicaOf {
+            primary: Some((host, port)),
+        })
+    }
+
+    pub(crate) fn apply(self, state: &State) -> Frame {
+        if state.cluster.is_some() {
+            return Frame::Error("ERR REPLICAOF not allowed in cluster mode.".into());
+        }
+        match self.primary {
+            Some((host, port)) => {
+                let (listening_port, auth) = {
+                    let config = state.config.borrow();
+                    (config.port, config.masterauth.clone())
+                };
+                let replication = &state.replication;
+                replication.replicate(host, port, &state.dbs, listening_port, auth);
+            }
+            None => state.replication.stop(),
+        }
+        Frame::Simple("OK".into())
+    }
+}
+
+/// REPLCONF listening-port port | REPLCONF ACK offset
+///
+/// 副本在 SYNC 之前报告自己的端口，之后定时报告已经执行到的日志序号。
+#[derive(Debug)]
+pub enum Replconf {
+    ListeningPort(u16),
+    Ack(u64),
+}
+
+impl Replconf {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Replconf, ParseError> {
+        let option = parse.next_string()?.to_lowercase();
+        let invalid = || ParseError::Other("ERR value is not an integer or out of range".into());
+        match &option[..] {
+            "listening-port" => {
+                let port = parse.next_string()?.parse().map_err(|_| invalid())?;
+                Ok(Replconf::ListeningPort(port))
+            }
+            "ack" => Ok(Replconf::Ack(parse.next_int()?)),
+            _ => Err(ParseError::Other(format!(
+                "ERR Unrecognized REPLCONF option: {}",
+                option
+            ))),
+        }
+    }
+
+    pub(crate) fn apply(self, state: &State, session: &mut Session) -> Frame {
+        match self {
+            Replconf::ListeningPort(port) => session.replica_port = port,
+            Replconf::Ack(offset) => state.replication.ack(session.id, offset),
+        }
+        Frame::Simple("OK".into())
+    }
+}
+
+/// SYNC
+///
+/// 同时创建所有数据库的快照并记下操作日志的进度，回复 `+FULLRESYNC <seq>`，
+/// 之后连接由服务端用于向副本发送快照和写命令。
+#[derive(Debug)]
+pub struct FullSync;
+
+impl FullSync {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<FullSync, ParseError> {
+        Ok(FullSync)
+    }
+
+    pub(crate) fn apply(self, state: &State, session: &mut Session) -> Frame {
+        let (seq, snapshots) = Db::snapshots(&state.dbs);
+        session.sync = Some((seq, snapshots));
+        Frame::Simple(format!("FULLRESYNC {}", seq))
+    }
+}
+
+/// ROLE
+///
+/// 主节点回复 `[master, offset, [[ip, port, offset]...]]`，
+/// 副本回复 `[slave, host, port, state, offset]`。
+#[derive(Debug)]
+pub struct Role;
+
+impl Role {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Role, ParseError> {
+        Ok(Role)
+    }
+
+    pub(crate) fn apply(self, state: &State) -> Frame {
+        let offset = state.dbs[0].op_log().last_seq();
+        state.replication.role(offset)
+    }
+}
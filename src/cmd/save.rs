@@ -0,0 +1,53 @@
+use super::{Parse, ParseError};
+use crate::{db::ReadView, frame::Frame};
+
+/// SAVE
+#[derive(Debug)]
+pub struct Save;
+
+/// BGSAVE
+#[derive(Debug)]
+pub struct BgSave;
+
+/// BGREWRITEAOF
+#[derive(Debug)]
+pub struct BgRewriteAof;
+
+impl Save {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Save, ParseError> {
+        Ok(Save)
+    }
+
+    pub(crate) fn apply(self, db: &ReadView) -> Frame {
+        match db.save() {
+            Ok(()) => Frame::Simple("OK".into()),
+            Err(e) => Frame::Error(format!("ERR {}", e)),
+        }
+    }
+}
+
+impl BgSave {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<BgSave, ParseError> {
+        Ok(BgSave)
+    }
+
+    pub(crate) fn apply(self, db: &ReadView) -> Frame {
+        match db.bgsave() {
+            Ok(()) => Frame::Simple("Background saving started".into()),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+}
+
+impl BgRewriteAof {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<BgRewriteAof, ParseError> {
+        Ok(BgRewriteAof)
+    }
+
+    pub(crate) fn apply(self, db: &ReadView) -> Frame {
+        match db.bgrewriteaof() {
+            Ok(()) => Frame::Simple("Background append only file rewriting started".into()),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+}
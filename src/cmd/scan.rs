@@ -0,0 +1,71 @@
+use bytes::Bytes;
+
+use super::{deadline, glob::glob_match, Parse, ParseError};
+use crate::{db::ReadView, frame::Frame};
+
+/// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+///
+/// 与 Redis 一样，MATCH 和 TYPE 在取出 COUNT 个 key 之后再过滤，返回的 key 可能少于 COUNT 个。
+#[derive(Debug)]
+pub struct Scan {
+    cursor: u64,
+    pattern: Option<Bytes>,
+    count: usize,
+    type_name: Option<String>,
+}
+
+impl Scan {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Scan, ParseError> {
+        let cursor = parse
+            .next_string()?
+            .parse()
+            .map_err(|_| ParseError::Other("ERR invalid cursor".into()))?;
+        let mut scan = Scan {
+            cursor,
+            pattern: None,
+            count: 10,
+            type_name: None,
+        };
+        while let Some(option) = parse.peek_upper() {
+            parse.next_string()?;
+            match &option[..] {
+                "MATCH" => scan.pattern = Some(parse.next_bytes()?),
+                "COUNT" => {
+                    scan.count = parse.next_int()? as usize;
+                    if scan.count == 0 {
+                        return Err(ParseError::Other("ERR syntax error".into()));
+                    }
+                }
+                "TYPE" => scan.type_name = Some(parse.next_string()?.to_lowercase()),
+                _ => return Err(ParseError::Other("ERR syntax error".into())),
+            }
+        }
+        Ok(scan)
+    }
+
+    pub(crate) fn apply(self, db: &ReadView) -> Frame {
+        let (cursor, keys) = db.iter_from(self.cursor, self.count);
+        let mut matched = vec![];
+        for (step, key) in keys.into_iter().enumerate() {
+            if deadline::exceeded(step) {
+                return deadline::timed_out();
+            }
+            let matches = self
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| glob_match(pattern, key.as_bytes()))
+                && self.type_name.as_ref().is_none_or(|type_name| {
+                    db.view(&key, |value| {
+                        value.is_some_and(|v| v.type_name() == type_name)
+                    })
+                });
+            if matches {
+                matched.push(Frame::Bulk(Bytes::from(key)));
+            }
+        }
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from(cursor.to_string())),
+            Frame::Array(matched),
+        ])
+    }
+}
@@ -0,0 +1,29 @@
+use super::{Parse, ParseError};
+use crate::frame::Frame;
+
+/// SELECT index
+///
+/// 切换的是连接当前使用的数据库，由服务端保存在连接的状态中。
+#[derive(Debug)]
+pub struct Select {
+    index: u64,
+}
+
+impl Select {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Select, ParseError> {
+        Ok(Select {
+            index: parse.next_int()?,
+        })
+    }
+
+    /// `databases` 为数据库的数量，成功时把 `selected` 改为新的下标
+    pub(crate) fn apply(self, databases: usize, selected: &mut usize) -> Frame {
+        match usize::try_from(self.index) {
+            Ok(index) if index < databases => {
+                *selected = index;
+                Frame::Simple("OK".into())
+            }
+            _ => Frame::Error("ERR DB index is out of range".into()),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use super::TrackingMode;
+use crate::{
+    acl::{Users, DEFAULT_USER},
+    db::Snapshot,
+};
+
+/// 连接上由命令修改的状态，服务端为每个连接保存一份
+#[derive(Debug)]
+pub struct Session {
+    /// 连接的 id，CLIENT ID 返回的值
+    pub id: u64,
+    /// 连接当前使用的数据库，由 SELECT 切换
+    pub selected: usize,
+    /// 是否已经认证，default 用户不需要密码时连接建立即为已认证
+    pub authenticated: bool,
+    /// 执行命令使用的用户，认证前为 default
+    pub user: String,
+    /// 由 CLIENT SETNAME 或 HELLO SETNAME 设置的连接名
+    pub name: Option<String>,
+    /// 订阅的频道和模式的数量
+    pub subscriptions: usize,
+    /// RESP 协议版本，由 HELLO 切换，3 时可以接收 Push
+    pub protocol: u8,
+    /// 由 CLIENT TRACKING 开启的客户端缓存失效通知
+    pub tracking: Option<TrackingMode>,
+    /// 是否处于 MONITOR 状态，由 MONITOR 设置
+    pub monitoring: bool,
+    /// 发送完当前命令的响应后关闭连接，由 QUIT 设置
+    pub closing: bool,
+    /// 下一条命令可以访问迁入中的 slot，由 ASKING 设置，只对紧接着的一条命令有效
+    pub asking: bool,
+    /// 副本通过 REPLCONF listening-port 报告的端口，0 表示没有报告
+    pub replica_port: u16,
+    /// 由 SYNC 设置：全量同步时操作日志的进度和各个数据库的快照，
+    /// 之后连接只用于向副本发送快照和写命令，快照发送后清空
+    pub sync: Option<(u64, Vec<Snapshot>)>,
+    /// 由 DEBUG 设置的故障
+    #[cfg(feature = "fault-injection")]
+    pub faults: super::Faults,
+}
+
+impl Session {
+    pub fn new(id: u64, users: &Users) -> Session {
+        Session {
+            id,
+            selected: 0,
+            authenticated: users.is_nopass(DEFAULT_USER),
+            user: DEFAULT_USER.to_string(),
+            name: None,
+            subscriptions: 0,
+            protocol: 2,
+            tracking: None,
+            monitoring: false,
+            closing: false,
+            asking: false,
+            replica_port: 0,
+            sync: None,
+            #[cfg(feature = "fault-injection")]
+            faults: super::Faults::default(),
+        }
+    }
+
+    /// 处于订阅或 MONITOR 状态的连接，以及副本的连接只接收数据，不会因空闲被关闭
+    pub fn is_idle_exempt(&self) -> bool {
+        self.subscriptions > 0 || self.monitoring || self.sync.is_some()
+    }
+}
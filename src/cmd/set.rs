@@ -0,0 +1,92 @@
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+
+use super::{Parse, ParseError};
+use crate::{
+    db::{rdb, Db, DbError},
+    frame::Frame,
+};
+
+/// SET key value [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds]
+///
+/// 记录到 AOF 和发送给副本时，相对的过期时间改写为 PXAT，重放时 key 仍然在原来的时刻过期。
+#[derive(Debug)]
+pub struct Set {
+    key: String,
+    value: Bytes,
+    expire: Option<Expire>,
+}
+
+/// 过期时间，EX/PX 是相对执行时刻的，EXAT/PXAT 是 unix 毫秒时间戳
+#[derive(Debug, Clone, Copy)]
+enum Expire {
+    After(Duration),
+    At(u64),
+}
+
+impl Set {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Set, ParseError> {
+        let key = parse.next_string()?;
+        let value = parse.next_bytes()?;
+        let mut expire = None;
+        while let Some(option) = parse.peek_upper() {
+            parse.next_string()?;
+            let ttl = match &option[..] {
+                "EX" | "PX" | "EXAT" | "PXAT" if expire.is_none() => parse.next_int()?,
+                _ => return Err(ParseError::Other("ERR syntax error".into())),
+            };
+            // 与 Redis 相同，换算为毫秒后不能超过 i64 的范围
+            let millis = match &option[..] {
+                "EX" | "EXAT" => ttl.checked_mul(1000),
+                _ => Some(ttl),
+            };
+            let Some(millis) = millis.filter(|&ms| ms != 0 && ms <= i64::MAX as u64) else {
+                return Err(ParseError::Other(DbError::InvalidExpire.to_string()));
+            };
+            expire = Some(match &option[..] {
+                "EX" | "PX" => Expire::After(Duration::from_millis(millis)),
+                _ => Expire::At(millis),
+            });
+        }
+        Ok(Set { key, value, expire })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    /// 改写记录的命令，过期时间统一为 PXAT
+    pub(crate) fn propagate(&self) -> impl FnOnce(Frame, &Frame) -> Option<Frame> {
+        let at = match self.expire {
+            // 超出范围时执行会失败，不会记录命令
+            Some(Expire::After(ttl)) => Instant::now().checked_add(ttl).map(rdb::unix_millis),
+            Some(Expire::At(at)) => Some(at),
+            None => None,
+        };
+        let rewritten = at.map(|at| {
+            vec![
+                Frame::Bulk(Bytes::from_static(b"SET")),
+                Frame::Bulk(Bytes::from(self.key.clone())),
+                Frame::Bulk(self.value.clone()),
+                Frame::Bulk(Bytes::from_static(b"PXAT")),
+                Frame::Bulk(Bytes::from(at.to_string())),
+            ]
+        });
+        move |frame, _| Some(rewritten.map_or(frame, Frame::Array))
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        // 已经过去的时间戳写入后立即过期
+        let expire = self.expire.map(|expire| match expire {
+            Expire::After(ttl) => ttl,
+            Expire::At(at) => rdb::from_unix_millis(at).map_or(Duration::ZERO, |at| {
+                at.saturating_duration_since(Instant::now())
+            }),
+        });
+        match db.set(self.key, self.value, expire) {
+            Ok(()) => Frame::Simple("OK".into()),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use super::{Parse, ParseError, Session};
+use crate::{frame::Frame, server::State};
+
+/// SHUTDOWN [NOSAVE|SAVE]
+///
+/// 与收到 ctrl_c 相同，服务端停止接受连接，等所有连接执行完当前命令后退出。
+/// 指定 SAVE 时在所有连接退出后保存每个数据库的快照，默认不保存；开启了 AOF 时退出前总会同步到磁盘。
+/// 回复 OK 后关闭执行命令的连接。
+#[derive(Debug)]
+pub struct Shutdown {
+    save: bool,
+}
+
+impl Shutdown {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Shutdown, ParseError> {
+        let mut save = false;
+        while parse.remaining() > 0 {
+            match &parse.next_string()?.to_uppercase()[..] {
+                "SAVE" => save = true,
+                "NOSAVE" => save = false,
+                _ => return Err(ParseError::Other("ERR syntax error".into())),
+            }
+        }
+        Ok(Shutdown { save })
+    }
+
+    pub(crate) fn apply(self, state: &State, session: &mut Session) -> Frame {
+        state.shutdown.request(self.save);
+        session.closing = true;
+        Frame::Simple("OK".into())
+    }
+}
@@ -0,0 +1,67 @@
+use bytes::Bytes;
+
+use super::{Parse, ParseError};
+use crate::{frame::Frame, server::State};
+
+/// SLOWLOG GET [count] | SLOWLOG LEN | SLOWLOG RESET
+///
+/// GET 默认返回最新的 10 条，count 为 -1 时返回全部。每条记录为
+/// `[id, timestamp, 微秒数, [参数...], 客户端地址, 客户端名]`。
+#[derive(Debug)]
+pub enum Slowlog {
+    Get(usize),
+    Len,
+    Reset,
+}
+
+impl Slowlog {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Slowlog, ParseError> {
+        let subcommand = parse.next_string()?.to_uppercase();
+        match &subcommand[..] {
+            "GET" => {
+                let count = match parse.remaining() {
+                    0 => 10,
+                    _ => match parse.next_signed_int()? {
+                        -1 => usize::MAX,
+                        count if count >= 0 => count as usize,
+                        _ => {
+                            return Err(ParseError::Other(
+                                "ERR count should be greater than or equal to -1".into(),
+                            ))
+                        }
+                    },
+                };
+                Ok(Slowlog::Get(count))
+            }
+            "LEN" => Ok(Slowlog::Len),
+            "RESET" => Ok(Slowlog::Reset),
+            _ => Err(ParseError::Other(format!(
+                "ERR unknown subcommand '{}'. Try SLOWLOG HELP.",
+                subcommand
+            ))),
+        }
+    }
+
+    pub(crate) fn apply(self, state: &State) -> Frame {
+        match self {
+            Slowlog::Get(count) => {
+                let entries = state.slowlog.get(count).into_iter().map(|entry| {
+                    Frame::Array(vec![
+                        Frame::Integer(entry.id as i64),
+                        Frame::Integer(entry.timestamp as i64),
+                        Frame::Integer(entry.duration.as_micros() as i64),
+                        Frame::Array(entry.args.into_iter().map(Frame::Bulk).collect()),
+                        Frame::Bulk(entry.addr.to_string().into()),
+                        Frame::Bulk(entry.name.map(Bytes::from).unwrap_or_default()),
+                    ])
+                });
+                Frame::Array(entries.collect())
+            }
+            Slowlog::Len => Frame::Integer(state.slowlog.len() as i64),
+            Slowlog::Reset => {
+                state.slowlog.reset();
+                Frame::Simple("OK".into())
+            }
+        }
+    }
+}
@@ -0,0 +1,678 @@
+use std::ops::Bound;
+
+use bytes::Bytes;
+
+use super::{deadline, Parse, ParseError};
+use crate::{
+    db::{Db, DbError, Event, ReadView, Value},
+    frame::Frame,
+    stream::{self, Claim, Fields, IdSpec, ReadStart, Stream, StreamError, StreamId, Trim},
+};
+
+/// XADD key [NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]] *|id field value [field value ...]
+#[derive(Debug)]
+pub struct XAdd {
+    key: String,
+    id: IdSpec,
+    fields: Fields,
+    no_mkstream: bool,
+    trim: Option<(Trim, Option<usize>)>,
+}
+
+/// XLEN key
+#[derive(Debug)]
+pub struct XLen {
+    key: String,
+}
+
+/// XRANGE key start end [COUNT count]
+#[derive(Debug)]
+pub struct XRange {
+    key: String,
+    start: Bound<StreamId>,
+    end: Bound<StreamId>,
+    count: Option<usize>,
+}
+
+/// XDEL key id [id ...]
+#[derive(Debug)]
+pub struct XDel {
+    key: String,
+    ids: Vec<StreamId>,
+}
+
+/// XTRIM key MAXLEN|MINID [=|~] threshold [LIMIT count]
+#[derive(Debug)]
+pub struct XTrim {
+    key: String,
+    trim: Trim,
+    limit: Option<usize>,
+}
+
+/// XGROUP CREATE key group id|$ [MKSTREAM]
+#[derive(Debug)]
+pub struct XGroup {
+    key: String,
+    group: String,
+    /// None 表示 `$`
+    start: Option<StreamId>,
+    mkstream: bool,
+}
+
+/// XREADGROUP GROUP group consumer [COUNT count] [BLOCK ms] [NOACK] STREAMS key [key ...] id [id ...]
+///
+/// 目前还没有阻塞等待的机制，BLOCK 参数会被接受但立即返回。
+#[derive(Debug)]
+pub struct XReadGroup {
+    group: String,
+    consumer: String,
+    count: Option<usize>,
+    noack: bool,
+    streams: Vec<(String, ReadStart)>,
+}
+
+/// XACK key group id [id ...]
+#[derive(Debug)]
+pub struct XAck {
+    key: String,
+    group: String,
+    ids: Vec<StreamId>,
+}
+
+/// XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-milliseconds]
+/// [RETRYCOUNT count] [FORCE] [JUSTID]
+#[derive(Debug)]
+pub struct XClaim {
+    key: String,
+    group: String,
+    consumer: String,
+    ids: Vec<StreamId>,
+    claim: Claim,
+}
+
+/// XSETID key last-id
+#[derive(Debug)]
+pub struct XSetId {
+    key: String,
+    last_id: StreamId,
+}
+
+fn syntax_error() -> ParseError {
+    ParseError::Other("ERR syntax error".into())
+}
+
+fn invalid_id() -> ParseError {
+    ParseError::Other(StreamError::InvalidId.to_string())
+}
+
+/// 解析 `MAXLEN|MINID [=|~] threshold [LIMIT count]`，调用前策略名尚未被读取
+///
+/// 裁剪总是精确执行，`~` 仅为兼容 redis 的语法而接受。
+fn parse_trim(parse: &mut Parse) -> Result<(Trim, Option<usize>), ParseError> {
+    let strategy = parse.next_string()?.to_uppercase();
+    if matches!(parse.peek_upper().as_deref(), Some("=") | Some("~")) {
+        parse.next_string()?;
+    }
+    let trim = match &strategy[..] {
+        "MAXLEN" => Trim::MaxLen(parse.next_int()? as usize),
+        "MINID" => Trim::MinId(parse.next_string()?.parse().map_err(|_| invalid_id())?),
+        _ => return Err(syntax_error()),
+    };
+    let limit = if parse.peek_upper().as_deref() == Some("LIMIT") {
+        parse.next_string()?;
+        Some(parse.next_int()? as usize)
+    } else {
+        None
+    };
+    Ok((trim, limit))
+}
+
+/// 把条目编码为 `[id, [field, value, ...]]`
+fn entry_frame(id: StreamId, fields: &Fields) -> Frame {
+    let fields = fields
+        .iter()
+        .flat_map(|(f, v)| [Frame::Bulk(f.clone()), Frame::Bulk(v.clone())])
+        .collect();
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from(id.to_string())),
+        Frame::Array(fields),
+    ])
+}
+
+impl XAdd {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XAdd, ParseError> {
+        let key = parse.next_string()?;
+        let mut no_mkstream = false;
+        let mut trim = None;
+
+        loop {
+            match parse.peek_upper().as_deref() {
+                Some("NOMKSTREAM") => {
+                    parse.next_string()?;
+                    no_mkstream = true;
+                }
+                Some("MAXLEN") | Some("MINID") => trim = Some(parse_trim(parse)?),
+                _ => break,
+            }
+        }
+
+        let id = parse.next_string()?.parse().map_err(|_| invalid_id())?;
+
+        // 至少需要一对 field value
+        if parse.remaining() == 0 || !parse.remaining().is_multiple_of(2) {
+            return Err(ParseError::EndOfStream);
+        }
+        let mut fields = Vec::with_capacity(parse.remaining() / 2);
+        while parse.remaining() > 0 {
+            fields.push((parse.next_bytes()?, parse.next_bytes()?));
+        }
+
+        Ok(XAdd {
+            key,
+            id,
+            fields,
+            no_mkstream,
+            trim,
+        })
+    }
+
+    /// 改写 AOF 中记录的命令，把 ID 替换为实际生成的 ID
+    ///
+    /// 自动生成的 ID 依赖执行时的时间，原样重放会得到不同的条目。
+    /// ID 是所有 field value 之前的最后一个参数。
+    pub(crate) fn propagate(&self) -> impl FnOnce(Frame, &Frame) -> Option<Frame> {
+        let offset = self.fields.len() * 2 + 1;
+        move |frame, response| {
+            // 返回 Null 表示指定了 NOMKSTREAM 且 key 不存在，没有写入
+            let (Frame::Array(mut args), Frame::Bulk(id)) = (frame, response) else {
+                return None;
+            };
+            let index = args.len() - offset;
+            args[index] = Frame::Bulk(id.clone());
+            Some(Frame::Array(args))
+        }
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let result = update_stream(db, &self.key, !self.no_mkstream, |stream| {
+            let Some(stream) = stream else {
+                return Ok(Frame::Null);
+            };
+            let id = stream.add(self.id, self.fields)?;
+            if let Some((trim, limit)) = self.trim {
+                stream.trim(trim, limit);
+            }
+            Ok(Frame::Bulk(Bytes::from(id.to_string())))
+        });
+        if let Ok(Frame::Bulk(_)) = result {
+            db.notify(&self.key, Event::Changed("xadd"));
+        }
+        reply(result)
+    }
+}
+
+impl XLen {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XLen, ParseError> {
+        Ok(XLen {
+            key: parse.next_string()?,
+        })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    pub(crate) fn apply(self, db: &ReadView) -> Frame {
+        reply(view_stream(db, &self.key, |stream| {
+            Frame::Integer(stream.map_or(0, Stream::len) as i64)
+        }))
+    }
+}
+
+impl XRange {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XRange, ParseError> {
+        let key = parse.next_string()?;
+        let start = parse_bound(&parse.next_string()?, StreamId::parse_start)?;
+        let end = parse_bound(&parse.next_string()?, StreamId::parse_end)?;
+        let count = if parse.peek_upper().as_deref() == Some("COUNT") {
+            parse.next_string()?;
+            Some(parse.next_int()? as usize)
+        } else {
+            None
+        };
+        Ok(XRange {
+            key,
+            start,
+            end,
+            count,
+        })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    pub(crate) fn apply(self, db: &ReadView) -> Frame {
+        reply(view_stream(db, &self.key, |stream| {
+            let range = stream.map_or(vec![], |stream| {
+                stream.range(self.start, self.end, self.count)
+            });
+            let mut entries = Vec::with_capacity(range.len());
+            for (step, (id, fields)) in range.into_iter().enumerate() {
+                if deadline::exceeded(step) {
+                    return deadline::timed_out();
+                }
+                entries.push(entry_frame(id, fields));
+            }
+            Frame::Array(entries)
+        }))
+    }
+}
+
+/// `(` 前缀表示开区间
+fn parse_bound(
+    s: &str,
+    parse_id: fn(&str) -> Result<StreamId, crate::stream::StreamError>,
+) -> Result<Bound<StreamId>, ParseError> {
+    match s.strip_prefix('(') {
+        Some(id) => parse_id(id).map(Bound::Excluded),
+        None => parse_id(s).map(Bound::Included),
+    }
+    .map_err(|_| invalid_id())
+}
+
+impl XDel {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XDel, ParseError> {
+        let key = parse.next_string()?;
+        let mut ids = vec![parse.next_string()?.parse().map_err(|_| invalid_id())?];
+        while parse.remaining() > 0 {
+            ids.push(parse.next_string()?.parse().map_err(|_| invalid_id())?);
+        }
+        Ok(XDel { key, ids })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let deleted = update_stream(db, &self.key, false, |stream| {
+            Ok(stream.map_or(0, |stream| stream.delete(&self.ids)))
+        });
+        notify_changed(db, &self.key, "xdel", &deleted);
+        reply(deleted.map(|deleted| Frame::Integer(deleted as i64)))
+    }
+}
+
+impl XTrim {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XTrim, ParseError> {
+        let key = parse.next_string()?;
+        let (trim, limit) = parse_trim(parse)?;
+        Ok(XTrim { key, trim, limit })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let removed = update_stream(db, &self.key, false, |stream| {
+            Ok(stream.map_or(0, |stream| stream.trim(self.trim, self.limit)))
+        });
+        notify_changed(db, &self.key, "xtrim", &removed);
+        reply(removed.map(|removed| Frame::Integer(removed as i64)))
+    }
+}
+
+impl XGroup {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XGroup, ParseError> {
+        let subcommand = parse.next_string()?.to_uppercase();
+        if subcommand != "CREATE" {
+            return Err(ParseError::Other(format!(
+                "ERR unknown subcommand '{}'. Try XGROUP HELP.",
+                subcommand
+            )));
+        }
+        let key = parse.next_string()?;
+        let group = parse.next_string()?;
+        let start = match &parse.next_string()?[..] {
+            "$" => None,
+            id => Some(id.parse().map_err(|_| invalid_id())?),
+        };
+        let mkstream = parse.peek_upper().as_deref() == Some("MKSTREAM");
+        if mkstream {
+            parse.next_string()?;
+        }
+        Ok(XGroup {
+            key,
+            group,
+            start,
+            mkstream,
+        })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let result = update_stream(db, &self.key, self.mkstream, |stream| {
+            let Some(stream) = stream else {
+                return Ok(Frame::Error(
+                    "ERR The XGROUP subcommand requires the key to exist. \
+                     Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically."
+                        .into(),
+                ));
+            };
+            stream.create_group(&self.group, self.start)?;
+            Ok(Frame::Simple("OK".into()))
+        });
+        if let Ok(Frame::Simple(_)) = result {
+            db.notify(&self.key, Event::Changed("xgroup-create"));
+        }
+        reply(result)
+    }
+}
+
+impl XReadGroup {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XReadGroup, ParseError> {
+        if parse.next_string()?.to_uppercase() != "GROUP" {
+            return Err(syntax_error());
+        }
+        let group = parse.next_string()?;
+        let consumer = parse.next_string()?;
+        let mut count = None;
+        let mut noack = false;
+
+        loop {
+            match parse.next_string()?.to_uppercase().as_str() {
+                "COUNT" => count = Some(parse.next_int()? as usize),
+                "BLOCK" => {
+                    parse.next_int()?;
+                }
+                "NOACK" => noack = true,
+                "STREAMS" => break,
+                _ => return Err(syntax_error()),
+            }
+        }
+
+        // STREAMS 之后前一半是 key，后一半是对应的 ID
+        let n = parse.remaining();
+        if n == 0 || !n.is_multiple_of(2) {
+            return Err(ParseError::Other(
+                "ERR Unbalanced 'xreadgroup' list of streams: for each stream key an ID or '>' must be specified."
+                    .into(),
+            ));
+        }
+        let mut keys = Vec::with_capacity(n / 2);
+        for _ in 0..n / 2 {
+            keys.push(parse.next_string()?);
+        }
+        let mut streams = Vec::with_capacity(n / 2);
+        for key in keys {
+            let start = parse.next_string()?.parse().map_err(|_| invalid_id())?;
+            streams.push((key, start));
+        }
+
+        Ok(XReadGroup {
+            group,
+            consumer,
+            count,
+            noack,
+            streams,
+        })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        self.streams.iter().map(|(key, _)| key.as_str()).collect()
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let mut result = vec![];
+        for (key, start) in self.streams {
+            let entries = update_stream(db, &key, false, |stream| {
+                Ok(stream.and_then(|stream| {
+                    stream
+                        .read_group(&self.group, &self.consumer, start, self.count, self.noack)
+                        .ok()
+                }))
+            });
+            let entries = match entries {
+                Ok(Some(entries)) => entries,
+                Err(e) => return Frame::Error(e.to_string()),
+                Ok(None) => {
+                    return Frame::Error(format!(
+                        "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                        key, self.group
+                    ))
+                }
+            };
+
+            // 读取新条目时，没有数据的 stream 不出现在结果中
+            if entries.is_empty() && start == ReadStart::New {
+                continue;
+            }
+            let entries = entries
+                .into_iter()
+                .map(|(id, fields)| match fields {
+                    Some(fields) => entry_frame(id, &fields),
+                    None => {
+                        Frame::Array(vec![Frame::Bulk(Bytes::from(id.to_string())), Frame::Null])
+                    }
+                })
+                .collect();
+            result.push(Frame::Array(vec![
+                Frame::Bulk(Bytes::from(key)),
+                Frame::Array(entries),
+            ]));
+        }
+
+        if result.is_empty() {
+            Frame::Null
+        } else {
+            Frame::Array(result)
+        }
+    }
+}
+
+impl XAck {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XAck, ParseError> {
+        let key = parse.next_string()?;
+        let group = parse.next_string()?;
+        let mut ids = vec![parse.next_string()?.parse().map_err(|_| invalid_id())?];
+        while parse.remaining() > 0 {
+            ids.push(parse.next_string()?.parse().map_err(|_| invalid_id())?);
+        }
+        Ok(XAck { key, group, ids })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        reply(update_stream(db, &self.key, false, |stream| {
+            let acked = stream.map_or(0, |stream| stream.ack(&self.group, &self.ids));
+            Ok(Frame::Integer(acked as i64))
+        }))
+    }
+}
+
+impl XClaim {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XClaim, ParseError> {
+        let key = parse.next_string()?;
+        let group = parse.next_string()?;
+        let consumer = parse.next_string()?;
+        let mut claim = Claim {
+            min_idle: parse.next_int()?,
+            ..Default::default()
+        };
+
+        let mut ids = vec![parse.next_string()?.parse().map_err(|_| invalid_id())?];
+        while let Some(id) = parse.peek_upper().and_then(|s| s.parse().ok()) {
+            parse.next_string()?;
+            ids.push(id);
+        }
+
+        while parse.remaining() > 0 {
+            match parse.next_string()?.to_uppercase().as_str() {
+                "IDLE" => {
+                    let idle = parse.next_int()?;
+                    claim.delivered_at = Some(stream::now_ms().saturating_sub(idle));
+                }
+                "TIME" => claim.delivered_at = Some(parse.next_int()?),
+                "RETRYCOUNT" => claim.retry_count = Some(parse.next_int()?),
+                "FORCE" => claim.force = true,
+                "JUSTID" => claim.justid = true,
+                _ => return Err(syntax_error()),
+            }
+        }
+        // 投递时间在解析时确定，AOF 中记录的命令重放时得到相同的结果
+        claim.delivered_at.get_or_insert_with(stream::now_ms);
+
+        Ok(XClaim {
+            key,
+            group,
+            consumer,
+            ids,
+            claim,
+        })
+    }
+
+    /// 改写 AOF 中记录的命令，只保留实际转移的条目
+    ///
+    /// 空闲时间在重放时已经不同，改写为 `min-idle-time` 为 0 并加上 FORCE，
+    /// 投递时间由 TIME 固定。
+    pub(crate) fn propagate(&self) -> impl FnOnce(Frame, &Frame) -> Option<Frame> {
+        let mut prefix = vec![
+            Frame::Bulk(Bytes::from_static(b"XCLAIM")),
+            Frame::Bulk(Bytes::from(self.key.clone())),
+            Frame::Bulk(Bytes::from(self.group.clone())),
+            Frame::Bulk(Bytes::from(self.consumer.clone())),
+            Frame::Bulk(Bytes::from_static(b"0")),
+        ];
+        let mut options = vec![
+            Frame::Bulk(Bytes::from_static(b"TIME")),
+            Frame::Bulk(Bytes::from(
+                self.claim.delivered_at.unwrap_or(0).to_string(),
+            )),
+        ];
+        if let Some(count) = self.claim.retry_count {
+            options.push(Frame::Bulk(Bytes::from_static(b"RETRYCOUNT")));
+            options.push(Frame::Bulk(Bytes::from(count.to_string())));
+        }
+        options.push(Frame::Bulk(Bytes::from_static(b"FORCE")));
+        if self.claim.justid {
+            options.push(Frame::Bulk(Bytes::from_static(b"JUSTID")));
+        }
+
+        move |_, response| {
+            let Frame::Array(claimed) = response else {
+                return None;
+            };
+            if claimed.is_empty() {
+                return None;
+            }
+            for item in claimed {
+                // JUSTID 时只有 ID，否则是 `[id, fields]`
+                let id = match item {
+                    Frame::Array(entry) => entry.first()?.clone(),
+                    id => id.clone(),
+                };
+                prefix.push(id);
+            }
+            prefix.extend(options);
+            Some(Frame::Array(prefix))
+        }
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        reply(update_stream(db, &self.key, false, |stream| {
+            let Some(stream) = stream else {
+                return Err(StreamError::NoGroup.into());
+            };
+            let claimed = stream.claim(&self.group, &self.consumer, &self.ids, self.claim)?;
+            let entries = claimed
+                .into_iter()
+                .map(|id| match stream.get(&id) {
+                    Some(fields) if !self.claim.justid => entry_frame(id, fields),
+                    _ => Frame::Bulk(Bytes::from(id.to_string())),
+                })
+                .collect();
+            Ok(Frame::Array(entries))
+        }))
+    }
+}
+
+impl XSetId {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XSetId, ParseError> {
+        let key = parse.next_string()?;
+        let last_id = parse.next_string()?.parse().map_err(|_| invalid_id())?;
+        Ok(XSetId { key, last_id })
+    }
+
+    pub(crate) fn keys(&self) -> Vec<&str> {
+        vec![&self.key]
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let result = update_stream(db, &self.key, false, |stream| {
+            let Some(stream) = stream else {
+                return Ok(Frame::Error("ERR no such key".into()));
+            };
+            stream.set_last_id(self.last_id)?;
+            Ok(Frame::Simple("OK".into()))
+        });
+        if let Ok(Frame::Simple(_)) = result {
+            db.notify(&self.key, Event::Changed("xsetid"));
+        }
+        reply(result)
+    }
+}
+
+/// 只读访问 stream，key 不存在时传入 None
+fn view_stream<R>(
+    db: &ReadView,
+    key: &str,
+    f: impl FnOnce(Option<&Stream>) -> R,
+) -> Result<R, DbError> {
+    db.view(key, |value| {
+        Ok(f(value.map(Value::as_stream).transpose()?))
+    })
+}
+
+/// 修改 stream，key 不存在且 `create` 为 false 时传入 None
+///
+/// 为了执行命令而新建的 stream，在命令失败时由 [`Db::update`] 丢弃，不会留下一个空的 key。
+fn update_stream<R>(
+    db: &Db,
+    key: &str,
+    create: bool,
+    f: impl FnOnce(Option<&mut Stream>) -> Result<R, DbError>,
+) -> Result<R, DbError> {
+    db.update(key, |value| {
+        if value.is_none() && create {
+            value.set(Value::Stream(Stream::default()));
+        }
+        f(value.get_mut().map(Value::as_stream_mut).transpose()?)
+    })
+}
+
+/// 修改了条目时发布变更通知，`result` 为修改的条目数量
+fn notify_changed(db: &Db, key: &str, event: &'static str, result: &Result<usize, DbError>) {
+    if let Ok(1..) = result {
+        db.notify(key, Event::Changed(event));
+    }
+}
+
+fn reply(result: Result<Frame, DbError>) -> Frame {
+    result.unwrap_or_else(|e| Frame::Error(e.to_string()))
+}
@@ -0,0 +1,575 @@
+//! 服务端配置
+//!
+//! 配置项的名字与 redis.conf 相同，依次从默认值、TOML 配置文件和环境变量加载，后面的覆盖前面的：
+//!
+//! ```toml
+//! bind = "0.0.0.0"
+//! port = 6380
+//! maxmemory = "100mb"
+//! appendonly = true
+//! ```
+//!
+//! 环境变量为 `ILEARN_` 加上大写的配置项名字，`-` 换成 `_`，如 `ILEARN_MAX_KEY_LEN=1024`。
+//! 所有来源的值都经过 [`Config::set`] 解析和校验，无效的配置在启动时报错，而不是运行时才发现。
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+use tracing::Level;
+
+use crate::{
+    cluster::{self, SlotRange, Topology},
+    db::{
+        aof::Fsync, ExpireMode, DEFAULT_DEFRAG_RATIO, DEFAULT_MAX_KEY_LEN, DEFAULT_MAX_VALUE_SIZE,
+    },
+    server::{
+        logging::{self, LogFormat},
+        runtime::Flavor,
+        Cidr, DEFAULT_PORT,
+    },
+};
+
+/// 环境变量的前缀
+const ENV_PREFIX: &str = "ILEARN_";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read config file {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    Parse(#[from] toml::de::Error),
+    #[error("unknown config option '{0}'")]
+    Unknown(String),
+    #[error("invalid value '{value}' for config option '{name}'")]
+    Invalid { name: String, value: String },
+    #[error("can't set immutable config '{0}'")]
+    Immutable(String),
+}
+
+/// 所有配置项的名字，与 [`Config::set`] 和 [`Config::get`] 接受的名字相同
+pub const NAMES: &[&str] = &[
+    "bind",
+    "port",
+    "unixsocket",
+    "protected-mode",
+    "allowlist",
+    "proxy-protocol",
+    "proxy-trusted",
+    "maxmemory",
+    "maxclients",
+    "timeout",
+    "databases",
+    "dbfilename",
+    "appendonly",
+    "appendfilename",
+    "appendfsync",
+    "storage",
+    "expire-mode",
+    "max-key-len",
+    "max-value-size",
+    "defrag-ratio",
+    "warm",
+    "requirepass",
+    "replicaof",
+    "masterauth",
+    "slowlog-log-slower-than",
+    "slowlog-max-len",
+    "command-timeout",
+    "max-connections-per-sec",
+    "max-commands-per-sec",
+    "rate-limit-burst",
+    "tls-cert-file",
+    "tls-key-file",
+    "loglevel",
+    "log-format",
+    "metrics-port",
+    "shutdown-timeout",
+    "runtime",
+    "worker-threads",
+    "accept-thread",
+    "cluster-enabled",
+    "cluster-announce",
+    "cluster-slots",
+    "cluster-migrating",
+    "cluster-importing",
+];
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// 监听的地址，配置文件中以空格分隔多个地址，每个地址都监听 `port`
+    pub bind: Vec<IpAddr>,
+    pub port: u16,
+    /// 同时监听的 unix socket 路径，本机的客户端可以绕过 TCP
+    pub unixsocket: Option<PathBuf>,
+    /// 监听了非回环地址并且没有密码时，只允许本机的客户端执行命令
+    pub protected_mode: bool,
+    /// 允许连接的网段，配置文件中以空格分隔，为空时不限制
+    pub allowlist: Vec<Cidr>,
+    /// TCP 连接先发送 PROXY 协议的头部，见 [`crate::server`] 的 `proxy` 模块
+    pub proxy_protocol: bool,
+    /// 允许发送 PROXY 头部的对端网段（负载均衡的地址），配置文件中以空格分隔，为空时只信任回环地址
+    pub proxy_trusted: Vec<Cidr>,
+    /// 每个逻辑数据库的内存上限，单位为字节，0 表示不限制
+    pub maxmemory: usize,
+    /// 最大连接数，达到上限后新的连接收到错误后被关闭
+    pub maxclients: usize,
+    /// 空闲连接的超时时间，单位为秒，0 表示不超时
+    pub timeout: u64,
+    pub databases: usize,
+    /// RDB 文件名，非 0 号数据库在文件名后加上编号
+    pub dbfilename: String,
+    pub appendonly: bool,
+    /// AOF 文件名，规则与 `dbfilename` 相同
+    pub appendfilename: String,
+    pub appendfsync: Fsync,
+    /// 存储层的路径，配置后数据在访问时从存储加载，见 [`crate::db::Db::set_storage`]
+    pub storage: Option<String>,
+    pub expire_mode: ExpireMode,
+    pub max_key_len: usize,
+    pub max_value_size: usize,
+    pub defrag_ratio: usize,
+    /// 启动时批量加载到 0 号数据库的快照或 CSV 文件
+    pub warm: Option<String>,
+    /// 连接需要先通过 AUTH 认证的密码
+    pub requirepass: Option<String>,
+    /// 启动时作为副本连接的主节点，配置文件中为 `host port`，见 [`crate::server`] 的 `replication` 模块
+    pub replicaof: Option<(String, u16)>,
+    /// 连接主节点时使用的密码
+    pub masterauth: Option<String>,
+    /// 执行时间超过多少微秒的命令记录到慢查询日志，负数表示不记录
+    pub slowlog_log_slower_than: i64,
+    /// 慢查询日志最多保留的条数
+    pub slowlog_max_len: usize,
+    /// 单条命令的最长执行时间，单位为毫秒，0 表示不限制，见 [`crate::cmd::deadline`]
+    pub command_timeout: u64,
+    /// 每个 IP 每秒允许的新连接数和命令数，0 表示不限制
+    pub max_connections_per_sec: u64,
+    pub max_commands_per_sec: u64,
+    /// 每个 IP 允许的突发数量，0 表示与每秒的速率相同
+    pub rate_limit_burst: u64,
+    /// PEM 格式的证书和私钥，都配置时只接受 TLS 连接
+    pub tls_cert_file: Option<PathBuf>,
+    pub tls_key_file: Option<PathBuf>,
+    /// 日志的最低级别，见 [`logging::parse_level`]
+    pub loglevel: Level,
+    pub log_format: LogFormat,
+    /// 导出 Prometheus 指标的 HTTP 端口，0 表示不导出，需要 `metrics` 特性
+    pub metrics_port: u16,
+    /// 关闭时等待连接处理完已读取命令的最长时间，单位为秒，0 表示一直等待
+    pub shutdown_timeout: u64,
+    /// 运行时的类型和多线程运行时的工作线程数，0 表示与 CPU 核数相同，见 [`crate::server::runtime`]
+    pub runtime: Flavor,
+    pub worker_threads: usize,
+    /// 在专门的线程中运行接受循环
+    pub accept_thread: bool,
+    /// 以集群模式运行，只执行属于自己的 slot 上的命令，见 [`crate::cluster`]
+    pub cluster_enabled: bool,
+    /// 这个节点在 `cluster-slots` 中的地址，默认为第一个监听地址加端口
+    pub cluster_announce: Option<String>,
+    /// 所有 slot 的分配，以及正在迁出和迁入的 slot
+    pub cluster_slots: Vec<SlotRange>,
+    pub cluster_migrating: BTreeMap<u16, String>,
+    pub cluster_importing: BTreeSet<u16>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            bind: vec![IpAddr::from([127, 0, 0, 1])],
+            port: DEFAULT_PORT,
+            unixsocket: None,
+            protected_mode: true,
+            allowlist: Vec::new(),
+            proxy_protocol: false,
+            proxy_trusted: Vec::new(),
+            maxmemory: 0,
+            maxclients: 10000,
+            timeout: 0,
+            databases: 16,
+            dbfilename: "dump.rdb".into(),
+            appendonly: false,
+            appendfilename: "appendonly.aof".into(),
+            appendfsync: Fsync::default(),
+            storage: None,
+            expire_mode: ExpireMode::default(),
+            max_key_len: DEFAULT_MAX_KEY_LEN,
+            max_value_size: DEFAULT_MAX_VALUE_SIZE,
+            defrag_ratio: DEFAULT_DEFRAG_RATIO,
+            warm: None,
+            requirepass: None,
+            replicaof: None,
+            masterauth: None,
+            slowlog_log_slower_than: 10_000,
+            slowlog_max_len: 128,
+            command_timeout: 0,
+            max_connections_per_sec: 0,
+            max_commands_per_sec: 0,
+            rate_limit_burst: 0,
+            tls_cert_file: None,
+            tls_key_file: None,
+            loglevel: Level::INFO,
+            log_format: LogFormat::default(),
+            metrics_port: 0,
+            shutdown_timeout: 10,
+            runtime: Flavor::default(),
+            worker_threads: 0,
+            accept_thread: false,
+            cluster_enabled: false,
+            cluster_announce: None,
+            cluster_slots: vec![],
+            cluster_migrating: BTreeMap::new(),
+            cluster_importing: BTreeSet::new(),
+        }
+    }
+}
+
+impl Config {
+    /// 加载配置：默认值、配置文件（如果有）、环境变量
+    pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        if let Some(path) = path {
+            config.merge_file(path)?;
+        }
+        config.merge_env(std::env::vars())?;
+        Ok(config)
+    }
+
+    /// 用 TOML 配置文件中的值覆盖当前配置
+    pub fn merge_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        self.merge_toml(&text)
+    }
+
+    pub fn merge_toml(&mut self, text: &str) -> Result<(), ConfigError> {
+        let table: toml::Table = text.parse()?;
+        for (name, value) in table {
+            let value = match value {
+                toml::Value::String(s) => s,
+                toml::Value::Integer(n) => n.to_string(),
+                toml::Value::Boolean(b) => b.to_string(),
+                value => {
+                    return Err(ConfigError::Invalid {
+                        name,
+                        value: value.to_string(),
+                    })
+                }
+            };
+            self.set(&name, &value)?;
+        }
+        Ok(())
+    }
+
+    /// 用 `ILEARN_` 开头的环境变量覆盖当前配置，其他变量被忽略
+    pub fn merge_env(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), ConfigError> {
+        for (key, value) in vars {
+            if let Some(name) = key.strip_prefix(ENV_PREFIX) {
+                self.set(&name.to_lowercase().replace('_', "-"), &value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 按名字设置一个配置项，`value` 为字符串形式，解析失败时配置不变
+    ///
+    /// 名字中的 `_` 和 `-` 等价。布尔值接受 `yes/no` 和 `true/false`，
+    /// 内存大小可以带 `kb`、`mb`、`gb` 单位。
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = || ConfigError::Invalid {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        let name = name.to_lowercase().replace('_', "-");
+        match &name[..] {
+            "bind" => self.bind = parse_bind(value).ok_or_else(invalid)?,
+            "port" => self.port = positive(value).ok_or_else(invalid)?,
+            "unixsocket" => self.unixsocket = non_empty(value).map(PathBuf::from),
+            "protected-mode" => self.protected_mode = parse_bool(value).ok_or_else(invalid)?,
+            "allowlist" => self.allowlist = parse_cidrs(value).ok_or_else(invalid)?,
+            "proxy-protocol" => self.proxy_protocol = parse_bool(value).ok_or_else(invalid)?,
+            "proxy-trusted" => self.proxy_trusted = parse_cidrs(value).ok_or_else(invalid)?,
+            "maxmemory" => self.maxmemory = parse_memory(value).ok_or_else(invalid)?,
+            "maxclients" => self.maxclients = positive(value).ok_or_else(invalid)?,
+            "timeout" => self.timeout = value.parse().map_err(|_| invalid())?,
+            "databases" => self.databases = positive(value).ok_or_else(invalid)?,
+            "dbfilename" => self.dbfilename = non_empty(value).ok_or_else(invalid)?,
+            "appendonly" => self.appendonly = parse_bool(value).ok_or_else(invalid)?,
+            "appendfilename" => self.appendfilename = non_empty(value).ok_or_else(invalid)?,
+            "appendfsync" => self.appendfsync = Fsync::from_name(value).ok_or_else(invalid)?,
+            "storage" => self.storage = non_empty(value),
+            "expire-mode" => self.expire_mode = ExpireMode::from_name(value).ok_or_else(invalid)?,
+            "max-key-len" => self.max_key_len = parse_memory(value).ok_or_else(invalid)?,
+            "max-value-size" => self.max_value_size = parse_memory(value).ok_or_else(invalid)?,
+            "defrag-ratio" => self.defrag_ratio = value.parse().map_err(|_| invalid())?,
+            "warm" => self.warm = non_empty(value),
+            "requirepass" => self.requirepass = non_empty(value),
+            "replicaof" => self.replicaof = parse_host_port(value).ok_or_else(invalid)?,
+            "masterauth" => self.masterauth = non_empty(value),
+            "slowlog-log-slower-than" => {
+                self.slowlog_log_slower_than = value.parse().map_err(|_| invalid())?
+            }
+            "slowlog-max-len" => self.slowlog_max_len = value.parse().map_err(|_| invalid())?,
+            "command-timeout" => self.command_timeout = value.parse().map_err(|_| invalid())?,
+            "max-connections-per-sec" => {
+                self.max_connections_per_sec = value.parse().map_err(|_| invalid())?
+            }
+            "max-commands-per-sec" => {
+                self.max_commands_per_sec = value.parse().map_err(|_| invalid())?
+            }
+            "rate-limit-burst" => self.rate_limit_burst = value.parse().map_err(|_| invalid())?,
+            "tls-cert-file" => self.tls_cert_file = non_empty(value).map(PathBuf::from),
+            "tls-key-file" => self.tls_key_file = non_empty(value).map(PathBuf::from),
+            "loglevel" => self.loglevel = logging::parse_level(value).ok_or_else(invalid)?,
+            "log-format" => self.log_format = LogFormat::from_name(value).ok_or_else(invalid)?,
+            "metrics-port" => self.metrics_port = value.parse().map_err(|_| invalid())?,
+            "shutdown-timeout" => self.shutdown_timeout = value.parse().map_err(|_| invalid())?,
+            "runtime" => self.runtime = Flavor::from_name(value).ok_or_else(invalid)?,
+            "worker-threads" => self.worker_threads = value.parse().map_err(|_| invalid())?,
+            "accept-thread" => self.accept_thread = parse_bool(value).ok_or_else(invalid)?,
+            "cluster-enabled" => self.cluster_enabled = parse_bool(value).ok_or_else(invalid)?,
+            "cluster-announce" => self.cluster_announce = non_empty(value),
+            "cluster-slots" => {
+                self.cluster_slots = SlotRange::parse_list(value).ok_or_else(invalid)?
+            }
+            "cluster-migrating" => {
+                self.cluster_migrating = cluster::parse_migrating(value).ok_or_else(invalid)?
+            }
+            "cluster-importing" => {
+                self.cluster_importing = cluster::parse_importing(value).ok_or_else(invalid)?
+            }
+            _ => return Err(ConfigError::Unknown(name)),
+        }
+        Ok(())
+    }
+
+    /// 按名字读取一个配置项，格式与 [`Config::set`] 接受的相同，未设置的可选项为空字符串
+    pub fn get(&self, name: &str) -> Option<String> {
+        fn join<T: ToString>(items: &[T]) -> String {
+            items.iter().map(T::to_string).collect::<Vec<_>>().join(" ")
+        }
+        fn path(path: &Option<PathBuf>) -> String {
+            path.as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default()
+        }
+        fn yes_no(value: bool) -> String {
+            if value { "yes" } else { "no" }.to_string()
+        }
+        let value = match &name.to_lowercase().replace('_', "-")[..] {
+            "bind" => join(&self.bind),
+            "port" => self.port.to_string(),
+            "unixsocket" => path(&self.unixsocket),
+            "protected-mode" => yes_no(self.protected_mode),
+            "allowlist" => join(&self.allowlist),
+            "proxy-protocol" => yes_no(self.proxy_protocol),
+            "proxy-trusted" => join(&self.proxy_trusted),
+            "maxmemory" => self.maxmemory.to_string(),
+            "maxclients" => self.maxclients.to_string(),
+            "timeout" => self.timeout.to_string(),
+            "databases" => self.databases.to_string(),
+            "dbfilename" => self.dbfilename.clone(),
+            "appendonly" => yes_no(self.appendonly),
+            "appendfilename" => self.appendfilename.clone(),
+            "appendfsync" => self.appendfsync.name().to_string(),
+            "storage" => self.storage.clone().unwrap_or_default(),
+            "expire-mode" => self.expire_mode.name().to_string(),
+            "max-key-len" => self.max_key_len.to_string(),
+            "max-value-size" => self.max_value_size.to_string(),
+            "defrag-ratio" => self.defrag_ratio.to_string(),
+            "warm" => self.warm.clone().unwrap_or_default(),
+            "requirepass" => self.requirepass.clone().unwrap_or_default(),
+            "replicaof" => self
+                .replicaof
+                .as_ref()
+                .map(|(host, port)| format!("{} {}", host, port))
+                .unwrap_or_default(),
+            "masterauth" => self.masterauth.clone().unwrap_or_default(),
+            "slowlog-log-slower-than" => self.slowlog_log_slower_than.to_string(),
+            "slowlog-max-len" => self.slowlog_max_len.to_string(),
+            "command-timeout" => self.command_timeout.to_string(),
+            "max-connections-per-sec" => self.max_connections_per_sec.to_string(),
+            "max-commands-per-sec" => self.max_commands_per_sec.to_string(),
+            "rate-limit-burst" => self.rate_limit_burst.to_string(),
+            "tls-cert-file" => path(&self.tls_cert_file),
+            "tls-key-file" => path(&self.tls_key_file),
+            "loglevel" => self.loglevel.to_string().to_lowercase(),
+            "log-format" => self.log_format.name().to_string(),
+            "metrics-port" => self.metrics_port.to_string(),
+            "shutdown-timeout" => self.shutdown_timeout.to_string(),
+            "runtime" => self.runtime.name().to_string(),
+            "worker-threads" => self.worker_threads.to_string(),
+            "accept-thread" => yes_no(self.accept_thread),
+            "cluster-enabled" => yes_no(self.cluster_enabled),
+            "cluster-announce" => self.cluster_announce.clone().unwrap_or_default(),
+            "cluster-slots" => SlotRange::format_list(&self.cluster_slots),
+            "cluster-migrating" => cluster::format_migrating(&self.cluster_migrating),
+            "cluster-importing" => cluster::format_importing(&self.cluster_importing),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// 集群模式下这个节点的地址，未配置 `cluster-announce` 时为第一个监听地址加端口
+    pub fn cluster_address(&self) -> String {
+        self.cluster_announce
+            .clone()
+            .unwrap_or_else(|| format!("{}:{}", self.bind[0], self.port))
+    }
+
+    /// 由 `cluster-slots`、`cluster-migrating` 和 `cluster-importing` 组成的集群拓扑
+    pub fn cluster_topology(&self) -> Topology {
+        Topology::new(
+            self.cluster_slots.clone(),
+            self.cluster_migrating.clone(),
+            self.cluster_importing.clone(),
+        )
+    }
+}
+
+fn positive<T: std::str::FromStr + Default + PartialEq>(value: &str) -> Option<T> {
+    value.parse().ok().filter(|n| *n != T::default())
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// 解析 `host port`，空字符串表示不设置，外层的 None 表示格式错误
+fn parse_host_port(value: &str) -> Option<Option<(String, u16)>> {
+    let mut parts = value.split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (None, ..) => Some(None),
+        (Some(host), Some(port), None) => Some(Some((host.to_string(), port.parse().ok()?))),
+        _ => None,
+    }
+}
+
+/// 解析以空格分隔的地址列表，至少有一个地址
+fn parse_bind(value: &str) -> Option<Vec<IpAddr>> {
+    let addrs = value
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    (!addrs.is_empty()).then_some(addrs)
+}
+
+/// 以空格分隔的网段，可以为空
+fn parse_cidrs(value: &str) -> Option<Vec<Cidr>> {
+    value
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .ok()
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match &value.to_lowercase()[..] {
+        "yes" | "true" => Some(true),
+        "no" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// 解析内存大小，单位与 redis.conf 相同：`1k` 为 1000 字节，`1kb` 为 1024 字节
+fn parse_memory(value: &str) -> Option<usize> {
+    let value = value.to_lowercase();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let multiplier = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1 << 10,
+        "m" => 1000 * 1000,
+        "mb" => 1 << 20,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1 << 30,
+        _ => return None,
+    };
+    number.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environment_overrides_file() {
+        let mut config = Config::default();
+        config
+            .merge_toml(
+                r#"
+                bind = "0.0.0.0 ::1"
+                port = 6380
+                maxmemory = "100mb"
+                appendonly = true
+                expire-mode = "sampling"
+                "#,
+            )
+            .unwrap();
+        config
+            .merge_env([
+                ("ILEARN_PORT".to_string(), "7000".to_string()),
+                ("ILEARN_MAX_KEY_LEN".to_string(), "1kb".to_string()),
+                ("PATH".to_string(), "/bin".to_string()),
+            ])
+            .unwrap();
+        assert_eq!(
+            config.bind,
+            [
+                IpAddr::from([0, 0, 0, 0]),
+                IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1])
+            ]
+        );
+        assert_eq!(config.port, 7000);
+        assert_eq!(config.maxmemory, 100 << 20);
+        assert!(config.appendonly);
+        assert_eq!(config.expire_mode, ExpireMode::Sampling);
+        assert_eq!(config.max_key_len, 1024);
+
+        assert!(matches!(
+            config.merge_toml("prot = 1"),
+            Err(ConfigError::Unknown(_))
+        ));
+        assert!(matches!(
+            config.set("databases", "0"),
+            Err(ConfigError::Invalid { .. })
+        ));
+        assert_eq!(config.databases, 16);
+    }
+
+    #[test]
+    fn every_option_round_trips_through_get() {
+        let mut config = Config {
+            allowlist: vec!["10.0.0.0/8".parse().unwrap()],
+            requirepass: Some("secret".into()),
+            ..Config::default()
+        };
+        for name in NAMES {
+            let value = config.get(name).unwrap();
+            config.set(name, &value).unwrap();
+            assert_eq!(config.get(name).unwrap(), value, "{}", name);
+        }
+        assert_eq!(config.get("protected_mode").unwrap(), "yes");
+        assert_eq!(
+            config.get("max-key-len"),
+            Some(DEFAULT_MAX_KEY_LEN.to_string())
+        );
+        assert_eq!(config.get("prot"), None);
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    io::{self, Cursor},
+    time::{Duration, Instant},
+};
+
+use crate::frame::{self, Frame};
+use bytes::{Buf, BytesMut};
+use tokio::{
+    io::{
+        self as tokio_io, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf,
+    },
+    net::TcpStream,
+};
+
+/// 以帧为单位读写的连接
+///
+/// 与 `mini-redis` 的 Connection 相同，先检查缓冲区中是否有完整的帧再解析。
+/// 写入时先把帧递归编码到缓冲区再一次写入（XRANGE 等命令的响应是数组套数组），
+/// 也可以用 [`Connection::queue_frame`] 累积多个帧后用 [`Connection::flush`] 一次写入。
+/// 同时对底层 IO 类型做了泛型化，不再局限于 `TcpStream`；读写只要求对应的一半，
+/// 可以用 [`Connection::split`] 拆分后分别在读取和写入的一端使用。
+#[derive(Debug)]
+pub struct Connection<S = TcpStream> {
+    stream: S,
+    buffer: BytesMut,
+    /// 已经编码、还没有写入的帧
+    pending: Vec<u8>,
+    /// 最近一次从对端读到数据的时间，服务端据此关闭空闲的连接
+    last_activity: Instant,
+}
+
+impl<S> Connection<S> {
+    pub fn new(socket: S) -> Connection<S> {
+        Connection {
+            stream: socket,
+            buffer: BytesMut::with_capacity(4 * 1024),
+            pending: Vec::new(),
+            last_activity: Instant::now(),
+        }
+    }
+
+    pub fn last_activity(&self) -> Instant {
+        self.last_activity
+    }
+
+    /// 距离最近一次读到数据的时间
+    pub fn idle(&self) -> Duration {
+        self.last_activity.elapsed()
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite> Connection<S> {
+    /// 拆分为只读和只写的两个连接，已经读到缓冲区的数据保留在只读的一端
+    pub fn split(self) -> (Connection<ReadHalf<S>>, Connection<WriteHalf<S>>) {
+        let (read, write) = tokio_io::split(self.stream);
+        let reader = Connection {
+            stream: read,
+            buffer: self.buffer,
+            pending: Vec::new(),
+            last_activity: self.last_activity,
+        };
+        let writer = Connection {
+            stream: write,
+            buffer: BytesMut::new(),
+            pending: self.pending,
+            last_activity: self.last_activity,
+        };
+        (reader, writer)
+    }
+}
+
+impl<S: AsyncRead + Unpin> Connection<S> {
+    /// 从连接读取一个帧
+    ///
+    /// 如果遇到EOF，则返回 None；帧的格式错误以 [`io::ErrorKind::InvalidData`] 返回
+    pub async fn read_frame(&mut self) -> io::Result<Option<Frame>> {
+        loop {
+            if let Some(frame) = self.parse_frame()? {
+                return Ok(Some(frame));
+            }
+
+            // 缓冲区中的数据不足一个帧，继续从 socket 读取
+            if 0 == self.stream.read_buf(&mut self.buffer).await? {
+                // 对端关闭连接时缓冲区中还有数据，说明帧只传输了一半
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                } else {
+                    return Err(io::ErrorKind::ConnectionReset.into());
+                }
+            }
+            self.last_activity = Instant::now();
+        }
+    }
+
+    fn parse_frame(&mut self) -> io::Result<Option<Frame>> {
+        let mut buf = Cursor::new(&self.buffer[..]);
+
+        // 先检查是否已经有完整的帧，避免解析到一半才发现数据不足
+        match Frame::check(&mut buf) {
+            Ok(_) => {
+                let len = buf.position() as usize;
+                buf.set_position(0);
+                let frame = Frame::parse(&mut buf).map_err(invalid_data)?;
+                self.buffer.advance(len);
+                Ok(Some(frame))
+            }
+            Err(frame::Error::Incomplete) => Ok(None),
+            Err(e) => Err(invalid_data(e)),
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> Connection<S> {
+    /// 将帧写入到连接中，之前用 [`Connection::queue_frame`] 累积的帧先被写入
+    pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.queue_frame(frame);
+        self.flush().await
+    }
+
+    /// 把帧编码到写缓冲区，不写入连接
+    pub fn queue_frame(&mut self, frame: &Frame) {
+        frame.encode(&mut self.pending);
+    }
+
+    /// 写缓冲区中还没有写入的字节数
+    pub fn queued(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// 一次写入写缓冲区中所有的帧
+    pub async fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            self.stream.write_all(&self.pending).await?;
+            self.pending.clear();
+            // 大的响应之后不保留过大的缓冲区
+            self.pending.shrink_to(MAX_RETAINED);
+        }
+        self.stream.flush().await
+    }
+}
+
+/// flush 之后写缓冲区最多保留的容量
+const MAX_RETAINED: usize = 64 * 1024;
+
+fn invalid_data(e: frame::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
@@ -0,0 +1,657 @@
+//! AOF（append-only file）持久化
+//!
+//! 写命令执行成功后记录到操作日志（见 [`oplog`](super::oplog)），专门的写入任务读取日志中
+//! 本数据库的记录，编码为 RESP 格式追加到文件，命令处理不会因为磁盘 IO 而阻塞。
+//! fsync 的时机由 [`Fsync`] 决定。写入任务每次取出日志中所有已追加的记录，合并为一次写入，
+//! `always` 模式下并发执行的多条命令也只需要同步一次。
+//!
+//! 启动时读出文件中的命令帧，由服务端依次执行以重建 Db。
+//! 操作日志中的顺序与修改数据的顺序相同（见 [`Db::record`]），
+//! 多个连接并发修改同一个 key 时，重放的结果与原来的数据一致。
+//!
+//! AOF 会随着写命令无限增长，BGREWRITEAOF 根据快照生成能重建当前数据的最少命令，
+//! 写入临时文件后替换原文件。重写期间新追加的命令同时缓存在内存中，
+//! 在替换前追加到新文件的末尾。
+
+use std::{
+    fs,
+    io::{self, Cursor, Write},
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::{mpsc, oneshot, watch},
+    time,
+};
+use tracing::{error, info, warn};
+
+use super::{
+    oplog::{Op, Tail, TailError},
+    Backend, Db, DbError, Snapshot, Value,
+};
+use crate::frame::{self, Frame};
+
+/// 何时把 AOF 同步到磁盘，与 Redis 的 appendfsync 配置项相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Fsync {
+    /// 每条命令都同步，命令在同步完成后才返回
+    Always,
+    /// 每秒同步一次，宕机时最多丢失一秒的数据
+    #[default]
+    EverySec,
+    /// 只写入操作系统的缓冲区，由操作系统决定何时同步
+    No,
+}
+
+impl Fsync {
+    pub fn from_name(name: &str) -> Option<Fsync> {
+        match &name.to_lowercase()[..] {
+            "always" => Some(Fsync::Always),
+            "everysec" => Some(Fsync::EverySec),
+            "no" => Some(Fsync::No),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Fsync::Always => "always",
+            Fsync::EverySec => "everysec",
+            Fsync::No => "no",
+        }
+    }
+}
+
+/// `always` 模式下收到命令后等待的时间，期间追加的命令合并为一次写入和同步（group commit）
+const GROUP_COMMIT_WINDOW: Duration = Duration::from_micros(500);
+
+/// 一次写入最多合并的日志记录条数
+const MAX_BATCH: usize = 1024;
+
+/// 已开启的 AOF
+#[derive(Debug)]
+pub(super) struct Aof {
+    path: PathBuf,
+    fsync: Fsync,
+    tx: mpsc::UnboundedSender<Message>,
+    /// 已经写入（`always` 模式下为已经同步）的最后一条操作日志的序号
+    synced: watch::Receiver<u64>,
+}
+
+/// 交给写入任务的消息
+#[derive(Debug)]
+enum Message {
+    /// 开始重写，`since` 从快照之后的第一条记录开始读取，这些命令需要额外缓存
+    RewriteStarted { since: Tail },
+    /// 新文件已经写入 `result` 中的临时文件，等待追加缓存的命令并替换原文件
+    RewriteDone {
+        result: io::Result<PathBuf>,
+        done: oneshot::Sender<()>,
+    },
+}
+
+impl<B: Backend> Db<B> {
+    /// 开启 AOF，之后记录到操作日志的写命令都会追加到 `path`，必须在 tokio 运行时中调用
+    ///
+    /// 写入任务在 Db 被释放后退出。
+    pub async fn enable_aof(&self, path: impl Into<PathBuf>, fsync: Fsync) -> io::Result<()> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        let log = self.op_log();
+        let tail = log.tail(log.last_seq() + 1);
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (synced_tx, synced) = watch::channel(log.last_seq());
+        let writer = Writer {
+            path: path.clone(),
+            fsync,
+            db: self.index(),
+            synced: synced_tx,
+        };
+        tokio::spawn(writer.run(file, tail, rx));
+        *self.shared.aof.write().unwrap() = Some(Aof {
+            path,
+            fsync,
+            tx,
+            synced,
+        });
+        Ok(())
+    }
+
+    /// 当前 AOF 文件的路径，没有开启 AOF 时返回 None
+    pub fn aof_path(&self) -> Option<PathBuf> {
+        let aof = self.shared.aof.read().unwrap();
+        aof.as_ref().map(|aof| aof.path.clone())
+    }
+
+    /// 把不经过 [`Db::record`] 执行的写操作追加到操作日志的末尾，AOF 等消费者从日志中读取
+    ///
+    /// 开启了 AOF 的 `always` 模式时，等到数据同步到磁盘后才返回。
+    pub async fn propagate(&self, frame: Frame) {
+        let seq = self.shared.log_op(frame);
+        self.wait_synced(seq).await;
+    }
+
+    /// 开启了 AOF 的 `always` 模式时，等待序号不大于 `seq` 的记录都同步到磁盘
+    pub async fn wait_synced(&self, seq: u64) {
+        let synced = {
+            let aof = self.shared.aof.read().unwrap();
+            match aof.as_ref() {
+                Some(aof) if aof.fsync == Fsync::Always => Some(aof.synced.clone()),
+                _ => None,
+            }
+        };
+        if let Some(mut synced) = synced {
+            // 写入任务退出时发送端被释放，不再等待
+            let _ = synced.wait_for(|&synced| synced >= seq).await;
+        }
+    }
+
+    /// 在后台重写 AOF，对应 BGREWRITEAOF 命令，必须在 tokio 运行时中调用
+    pub fn bgrewriteaof(&self) -> Result<(), DbError> {
+        let (path, tx) = {
+            let aof = self.shared.aof.read().unwrap();
+            let aof = aof.as_ref().ok_or(DbError::AofDisabled)?;
+            (aof.path.clone(), aof.tx.clone())
+        };
+        if self.shared.rewriting.swap(true, Ordering::AcqRel) {
+            return Err(DbError::RewriteInProgress);
+        }
+
+        // 快照和日志的进度必须同时取得，否则两者之间执行的命令可能既在快照中又在缓存中，
+        // 重放时被执行两次（INCRBY 等命令的结果会出错）
+        let (seq, mut snapshots) = Db::snapshots(std::slice::from_ref(self));
+        let snapshot = snapshots.remove(0);
+        let since = self.op_log().tail(seq + 1);
+        let _ = tx.send(Message::RewriteStarted { since });
+        let shared = Arc::clone(&self.shared);
+        tokio::spawn(async move {
+            let tmp = path.with_extension("aof.rewrite");
+            let result = tokio::task::spawn_blocking(move || {
+                let mut file = fs::File::create(&tmp)?;
+                file.write_all(&rewrite(&snapshot))?;
+                file.sync_all()?;
+                Ok(tmp)
+            })
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e)));
+
+            let (done, rx) = oneshot::channel();
+            if tx.send(Message::RewriteDone { result, done }).is_ok() {
+                let _ = rx.await;
+            }
+            shared.rewriting.store(false, Ordering::Release);
+        });
+        Ok(())
+    }
+}
+
+/// AOF 写入任务
+#[derive(Debug)]
+struct Writer {
+    path: PathBuf,
+    fsync: Fsync,
+    /// 只写入这个数据库的记录
+    db: usize,
+    synced: watch::Sender<u64>,
+}
+
+impl Writer {
+    async fn run(self, mut file: File, mut tail: Tail, mut rx: mpsc::UnboundedReceiver<Message>) {
+        let mut interval = time::interval(Duration::from_secs(1));
+        // 上次同步之后是否有新的写入
+        let mut dirty = false;
+        // 重写开始时日志的进度，以及之后追加的命令
+        let mut rewrite_buffer: Option<(u64, BytesMut)> = None;
+
+        loop {
+            tokio::select! {
+                message = rx.recv() => {
+                    let Some(message) = message else {
+                        break;
+                    };
+                    match message {
+                        Message::RewriteStarted { since } => {
+                            rewrite_buffer = Some(self.start_rewrite(since, &tail));
+                        }
+                        Message::RewriteDone { result, done } => {
+                            let (seq, buffer) = rewrite_buffer.take().unwrap_or_default();
+                            match finish_rewrite(result, &buffer, &self.path).await {
+                                Ok(new_file) => {
+                                    file = new_file;
+                                    // 新文件已经包含快照之前的命令，写入任务还没有读到时跳过它们
+                                    tail.skip_to(seq + 1);
+                                    info!("Background AOF rewrite finished successfully");
+                                }
+                                Err(e) => error!(error = %e, "Background AOF rewrite error"),
+                            }
+                            let _ = done.send(());
+                        }
+                    }
+                }
+                op = tail.next() => {
+                    // `always` 模式下稍等片刻，让并发执行的命令一起写入、只同步一次
+                    if self.fsync == Fsync::Always {
+                        time::sleep(GROUP_COMMIT_WINDOW).await;
+                    }
+                    let mut batch = vec![op];
+                    while batch.len() < MAX_BATCH {
+                        match tail.try_next() {
+                            Some(op) => batch.push(op),
+                            None => break,
+                        }
+                    }
+                    if self.write_batch(&mut file, &tail, batch, &mut rewrite_buffer).await {
+                        dirty = self.fsync == Fsync::EverySec;
+                    }
+                }
+                _ = interval.tick(), if dirty => {
+                    if let Err(e) = file.sync_data().await {
+                        error!(error = %e, "Error syncing the AOF file");
+                    }
+                    dirty = false;
+                }
+            }
+        }
+
+        // Db 已经释放，写入日志中剩余的记录并同步到磁盘后退出
+        let rest: Vec<_> = std::iter::from_fn(|| tail.try_next()).collect();
+        for batch in rest.chunks(MAX_BATCH) {
+            self.write_batch(&mut file, &tail, batch.to_vec(), &mut rewrite_buffer)
+                .await;
+        }
+        if let Err(e) = file.sync_data().await {
+            error!(error = %e, "Error syncing the AOF file");
+        }
+    }
+
+    /// 开始缓存重写期间的命令，返回缓存的起始进度和缓存
+    ///
+    /// 快照之后、收到消息之前可能已经写入了一些命令，它们也要追加到新文件，从 `since` 中补上；
+    /// 写入任务也可能还没有读到快照的进度，这时只缓存快照之后的命令。
+    fn start_rewrite(&self, mut since: Tail, tail: &Tail) -> (u64, BytesMut) {
+        let mut data = Vec::new();
+        while since.next_seq() < tail.next_seq() {
+            match since.try_next() {
+                Some(Ok(op)) if op.db == self.db => op.frame.encode(&mut data),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    error!(error = %e, "AOF rewrite is missing commands, run BGREWRITEAOF again");
+                }
+                None => break,
+            }
+        }
+        (since.next_seq() - 1, BytesMut::from(&data[..]))
+    }
+
+    /// 把一批日志记录中本数据库的命令合并为一次写入（`always` 模式下一次同步），
+    /// 返回是否写入了文件
+    async fn write_batch(
+        &self,
+        file: &mut File,
+        tail: &Tail,
+        batch: Vec<Result<Arc<Op>, TailError>>,
+        rewrite_buffer: &mut Option<(u64, BytesMut)>,
+    ) -> bool {
+        let mut data = Vec::new();
+        for op in batch {
+            let op = match op {
+                Ok(op) => op,
+                Err(e) => {
+                    error!(error = %e, "AOF is missing commands, run BGREWRITEAOF to rebuild it");
+                    continue;
+                }
+            };
+            if op.db != self.db {
+                continue;
+            }
+            let start = data.len();
+            op.frame.encode(&mut data);
+            if let Some((seq, buffer)) = rewrite_buffer {
+                if op.seq > *seq {
+                    buffer.extend_from_slice(&data[start..]);
+                }
+            }
+        }
+
+        let written = !data.is_empty();
+        if written {
+            if let Err(e) = write(file, &data, self.fsync).await {
+                error!(error = %e, "Error writing to the AOF file");
+            }
+        }
+        // 读取进度之前的命令都已写入或已丢失，等待它们的命令都可以返回
+        self.synced.send_replace(tail.next_seq() - 1);
+        written
+    }
+}
+
+/// 把重写期间缓存的命令追加到新文件，然后替换原文件，返回新文件的句柄
+async fn finish_rewrite(
+    result: io::Result<PathBuf>,
+    buffer: &[u8],
+    path: &Path,
+) -> io::Result<File> {
+    let tmp = result?;
+    let mut file = OpenOptions::new().append(true).open(&tmp).await?;
+    file.write_all(buffer).await?;
+    file.flush().await?;
+    file.sync_data().await?;
+    // rename 是原子的，任何时刻 `path` 都是一个完整的 AOF
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(file)
+}
+
+async fn write(file: &mut File, data: &[u8], fsync: Fsync) -> io::Result<()> {
+    file.write_all(data).await?;
+    file.flush().await?;
+    if fsync == Fsync::Always {
+        file.sync_data().await?;
+    }
+    Ok(())
+}
+
+/// 生成重建快照中所有数据的命令
+///
+/// 字符串使用 SET，其他类型使用 RESTORE 写入 DUMP 格式的载荷，过期时间都记录为绝对时间
+/// （SET 的 PXAT、RESTORE 的 ABSTTL），重放时剩余的时间不会因为停机而变长。
+fn rewrite(snapshot: &Snapshot) -> Bytes {
+    let mut buf = BytesMut::new();
+    let mut emit = |args: Vec<Bytes>| {
+        let frame = Frame::Array(args.into_iter().map(Frame::Bulk).collect());
+        let mut encoded = Vec::new();
+        frame.encode(&mut encoded);
+        buf.extend_from_slice(&encoded);
+    };
+
+    for (key, entry) in snapshot.iter() {
+        let key = Bytes::copy_from_slice(key.as_bytes());
+        let deadline = entry
+            .expires_at
+            .map(|when| Bytes::from(super::rdb::unix_millis(when).to_string()));
+
+        match &entry.value {
+            Value::String(_) | Value::Int(_) => {
+                let data = entry.value.to_string_bytes().unwrap();
+                let mut args = vec![Bytes::from_static(b"SET"), key, data];
+                if let Some(deadline) = deadline {
+                    args.push(Bytes::from_static(b"PXAT"));
+                    args.push(deadline);
+                }
+                emit(args);
+            }
+            _ => emit(vec![
+                Bytes::from_static(b"RESTORE"),
+                key,
+                deadline.unwrap_or_else(|| Bytes::from_static(b"0")),
+                super::dump::encode(&entry.value),
+                Bytes::from_static(b"ABSTTL"),
+            ]),
+        }
+    }
+    buf.freeze()
+}
+
+/// 读出 AOF 文件中的所有命令帧
+///
+/// 宕机时最后一条命令可能只写入了一半，这部分会被忽略，和 Redis 的 aof-load-truncated 相同。
+pub fn read_aof(path: impl AsRef<Path>) -> io::Result<Vec<Frame>> {
+    let data = fs::read(path)?;
+    let mut buf = Cursor::new(&data[..]);
+    let mut frames = vec![];
+
+    while (buf.position() as usize) < data.len() {
+        let start = buf.position();
+        match Frame::check(&mut buf) {
+            Ok(()) => {
+                buf.set_position(start);
+                let frame = Frame::parse(&mut buf)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                frames.push(frame);
+            }
+            Err(frame::Error::Incomplete) => {
+                let ignored = data.len() as u64 - start;
+                warn!(ignored, "AOF file is truncated, ignoring the last bytes");
+                break;
+            }
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Instant};
+
+    use super::*;
+    use crate::cmd::Command;
+
+    fn command(args: &[&'static [u8]]) -> Frame {
+        Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::from_static(arg)))
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn appended_commands_can_be_read_back() {
+        let path = std::env::temp_dir().join(format!("ilearn-aof-{}.aof", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let db = Db::new();
+        db.enable_aof(&path, Fsync::Always).await.unwrap();
+        let set = command(&[b"SET", b"k", b"v"]);
+        db.propagate(set.clone()).await;
+        db.propagate(set.clone()).await;
+
+        // 模拟写入一半时宕机
+        let mut data = fs::read(&path).unwrap();
+        data.extend_from_slice(b"*3\r\n$3\r\nSET");
+        fs::write(&path, data).unwrap();
+
+        let frames = read_aof(&path).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(format!("{:?}", frames[0]), format!("{:?}", set));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent_commands_are_committed_together() {
+        let path = std::env::temp_dir().join(format!("ilearn-group-{}.aof", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let db = Db::new();
+        db.enable_aof(&path, Fsync::Always).await.unwrap();
+        let tasks: Vec<_> = (0..50)
+            .map(|_| {
+                let db = db.clone();
+                tokio::spawn(async move { db.propagate(command(&[b"INCR", b"n"])).await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        // 所有命令返回时都已经写入
+        assert_eq!(read_aof(&path).unwrap().len(), 50);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn replaying_concurrent_writes_to_one_key_restores_it() {
+        let path = std::env::temp_dir().join(format!("ilearn-order-{}.aof", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let db = Db::new();
+        db.enable_aof(&path, Fsync::Always).await.unwrap();
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        // 记录的是生成的 ID，日志中的顺序不对时重放会因为 ID 变小而失败
+                        let frame = command(&[b"XADD", b"s", b"*", b"f", b"v"]);
+                        let cmd = Command::from_frame(&frame).unwrap().unwrap();
+                        cmd.execute(frame, &db);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        db.wait_synced(db.op_log().last_seq()).await;
+
+        let replayed = Db::new();
+        for frame in read_aof(&path).unwrap() {
+            let cmd = Command::from_frame(&frame).unwrap().unwrap();
+            cmd.execute(frame, &replayed);
+        }
+        let len = |db: &Db| {
+            Command::from_frame(&command(&[b"XLEN", b"s"]))
+                .unwrap()
+                .unwrap()
+                .apply(db)
+        };
+        assert_eq!(len(&db), Frame::Integer(4000));
+        assert_eq!(len(&replayed), Frame::Integer(4000));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rewrite_during_writes_does_not_replay_them_twice() {
+        let path =
+            std::env::temp_dir().join(format!("ilearn-rewrite-incr-{}.aof", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let db = Db::new();
+        db.enable_aof(&path, Fsync::Always).await.unwrap();
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    for _ in 0..2000 {
+                        let frame = command(&[b"INCRBY", b"n", b"1"]);
+                        let cmd = Command::from_frame(&frame).unwrap().unwrap();
+                        cmd.execute(frame, &db);
+                    }
+                })
+            })
+            .collect();
+        // 在写入的过程中重写
+        while db.get("n").unwrap().is_none() {
+            tokio::task::yield_now().await;
+        }
+        db.bgrewriteaof().unwrap();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        while db.shared.rewriting.load(Ordering::Acquire) {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        db.wait_synced(db.op_log().last_seq()).await;
+
+        let replayed = Db::new();
+        for frame in read_aof(&path).unwrap() {
+            let cmd = Command::from_frame(&frame).unwrap().unwrap();
+            cmd.execute(frame, &replayed);
+        }
+        assert_eq!(db.get("n"), Ok(Some(Bytes::from_static(b"8000"))));
+        assert_eq!(replayed.get("n"), Ok(Some(Bytes::from_static(b"8000"))));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rewrite_keeps_only_current_data() {
+        let path = std::env::temp_dir().join(format!("ilearn-rewrite-{}.aof", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let db = Db::new();
+        db.enable_aof(&path, Fsync::Always).await.unwrap();
+        for i in 0..10 {
+            let value = Bytes::from(i.to_string());
+            db.set("k".into(), value.clone(), None).unwrap();
+            let set = Frame::Array(vec![
+                Frame::Bulk(Bytes::from_static(b"SET")),
+                Frame::Bulk(Bytes::from_static(b"k")),
+                Frame::Bulk(value),
+            ]);
+            db.propagate(set.clone()).await;
+        }
+        assert_eq!(read_aof(&path).unwrap().len(), 10);
+
+        db.bgrewriteaof().unwrap();
+        assert_eq!(db.bgrewriteaof(), Err(DbError::RewriteInProgress));
+        while db.shared.rewriting.load(Ordering::Acquire) {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        let frames = read_aof(&path).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(
+            format!("{:?}", frames[0]),
+            format!("{:?}", command(&[b"SET", b"k", b"9"]))
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rewrite_keeps_expire_times_of_all_types() {
+        let path =
+            std::env::temp_dir().join(format!("ilearn-rewrite-ttl-{}.aof", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let db = Db::new();
+        db.enable_aof(&path, Fsync::Always).await.unwrap();
+        let expires_at = Instant::now() + Duration::from_secs(60);
+        db.with_keys(&["s", "h", "x", "p"], |locked| {
+            locked.insert("s", Value::Int(1), Some(expires_at));
+            let hash = [(Bytes::from_static(b"f"), Bytes::from_static(b"v"))];
+            locked.insert(
+                "h",
+                Value::Hash(hash.into_iter().collect()),
+                Some(expires_at),
+            );
+            locked.insert("x", Value::Stream(Default::default()), Some(expires_at));
+            locked.insert("p", Value::List([Bytes::from_static(b"a")].into()), None);
+            Ok(())
+        })
+        .unwrap();
+
+        db.bgrewriteaof().unwrap();
+        while db.shared.rewriting.load(Ordering::Acquire) {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let replayed = Db::new();
+        for frame in read_aof(&path).unwrap() {
+            let cmd = Command::from_frame(&frame).unwrap().unwrap();
+            let (reply, _) = cmd.execute(frame, &replayed);
+            assert!(!matches!(reply, Frame::Error(_)), "{:?}", reply);
+        }
+        replayed
+            .with_keys(&["s", "h", "x", "p"], |locked| {
+                for key in ["s", "h", "x"] {
+                    let when = locked.expires_at(key).unwrap();
+                    // AOF 中的过期时间精确到毫秒
+                    assert!(
+                        when.max(expires_at) - when.min(expires_at) < Duration::from_millis(50)
+                    );
+                }
+                assert_eq!(locked.expires_at("p"), None);
+                assert_eq!(locked.get("h").unwrap().type_name(), "hash");
+                assert_eq!(locked.get("p").unwrap().type_name(), "list");
+                Ok(())
+            })
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
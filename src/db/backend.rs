@@ -0,0 +1,230 @@
+//! Db 分片的存储与加锁方式
+//!
+//! Db 只关心“按下标拿到某个分片并加锁”，具体用什么并发容器由 [`Backend`] 决定：
+//! - [`Single`]：一个 `Mutex` 保护整个 HashMap，实现最简单
+//! - [`Striped`]：锁分段，每个分片一个 `Mutex`，默认的实现
+//! - `DashMapBackend`：需要开启 `dashmap` feature，分片放在 DashMap 中，读操作共享读锁
+//!
+//! 可以用 `cargo run --release --example db-bench --features dashmap` 比较它们的吞吐量。
+
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use super::Shard;
+
+/// 分片容器
+///
+/// 不同下标的分片必须由不同的锁保护：[`Db::with_keys`](super::Db::with_keys)
+/// 会在同一个线程中按下标从小到大同时持有多个分片的写锁。
+pub trait Backend: Send + Sync + fmt::Debug + 'static {
+    /// 只读访问分片时持有的锁
+    type ReadGuard<'a>: Deref<Target = Shard>
+    where
+        Self: 'a;
+
+    /// 修改分片时持有的锁
+    type WriteGuard<'a>: DerefMut<Target = Shard>
+    where
+        Self: 'a;
+
+    fn shard_count(&self) -> usize;
+
+    fn read(&self, index: usize) -> Self::ReadGuard<'_>;
+
+    fn write(&self, index: usize) -> Self::WriteGuard<'_>;
+}
+
+/// 单把全局锁
+#[derive(Debug, Default)]
+pub struct Single {
+    shard: Mutex<Shard>,
+}
+
+impl Backend for Single {
+    type ReadGuard<'a> = MutexGuard<'a, Shard>;
+    type WriteGuard<'a> = MutexGuard<'a, Shard>;
+
+    fn shard_count(&self) -> usize {
+        1
+    }
+
+    fn read(&self, _index: usize) -> Self::ReadGuard<'_> {
+        self.shard.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self, _index: usize) -> Self::WriteGuard<'_> {
+        self.shard.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// 锁分段：每个分片一把 `Mutex`
+#[derive(Debug)]
+pub struct Striped {
+    shards: Box<[Mutex<Shard>]>,
+}
+
+impl Striped {
+    /// ## Panics
+    ///
+    /// `shards` 为 0 时会 panic
+    pub fn new(shards: usize) -> Striped {
+        assert!(shards > 0);
+        Striped {
+            shards: (0..shards).map(|_| Mutex::default()).collect(),
+        }
+    }
+}
+
+impl Backend for Striped {
+    type ReadGuard<'a> = MutexGuard<'a, Shard>;
+    type WriteGuard<'a> = MutexGuard<'a, Shard>;
+
+    fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn read(&self, index: usize) -> Self::ReadGuard<'_> {
+        self.shards[index]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self, index: usize) -> Self::WriteGuard<'_> {
+        self.shards[index]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(feature = "dashmap")]
+pub use self::dash::DashMapBackend;
+
+#[cfg(feature = "dashmap")]
+mod dash {
+    use std::hash::{BuildHasher, Hasher};
+
+    use dashmap::{
+        mapref::one::{Ref, RefMut},
+        DashMap,
+    };
+
+    use super::{Backend, Shard};
+
+    /// 以分片下标为 key 存放在 DashMap 中
+    ///
+    /// DashMap 内部是分段的 `RwLock`，GET 这类只读命令在同一个分片上也可以并行。
+    #[derive(Debug)]
+    pub struct DashMapBackend {
+        shards: DashMap<usize, Shard, IndexHasher>,
+        count: usize,
+    }
+
+    /// 让第 i 个分片落在 DashMap 内部的第 i 段上，保证每个分片独占一把锁
+    ///
+    /// DashMap 取哈希值去掉最高 7 位之后的高位作为段的下标，这里把下标直接左移到这些位上。
+    #[derive(Debug, Clone, Copy)]
+    struct IndexHasher {
+        shift: u32,
+    }
+
+    impl BuildHasher for IndexHasher {
+        type Hasher = IndexHash;
+
+        fn build_hasher(&self) -> IndexHash {
+            IndexHash {
+                shift: self.shift,
+                hash: 0,
+            }
+        }
+    }
+
+    struct IndexHash {
+        shift: u32,
+        hash: u64,
+    }
+
+    impl Hasher for IndexHash {
+        fn finish(&self) -> u64 {
+            self.hash
+        }
+
+        fn write(&mut self, _bytes: &[u8]) {
+            unreachable!("IndexHash only hashes shard indices");
+        }
+
+        fn write_usize(&mut self, index: usize) {
+            self.hash = (index as u64) << self.shift;
+        }
+    }
+
+    impl DashMapBackend {
+        /// ## Panics
+        ///
+        /// `shards` 为 0 时会 panic
+        pub fn new(shards: usize) -> DashMapBackend {
+            assert!(shards > 0);
+            // DashMap 要求段数是大于 1 的 2 的幂
+            let amount = shards.next_power_of_two().max(2);
+            let shift = usize::BITS - amount.trailing_zeros() - 7;
+            let map = DashMap::with_hasher_and_shard_amount(IndexHasher { shift }, amount);
+            for i in 0..shards {
+                map.insert(i, Shard::default());
+            }
+            DashMapBackend {
+                shards: map,
+                count: shards,
+            }
+        }
+    }
+
+    impl Backend for DashMapBackend {
+        type ReadGuard<'a> = Ref<'a, usize, Shard>;
+        type WriteGuard<'a> = RefMut<'a, usize, Shard>;
+
+        fn shard_count(&self) -> usize {
+            self.count
+        }
+
+        fn read(&self, index: usize) -> Self::ReadGuard<'_> {
+            // 分片在创建时全部插入，之后不会删除
+            self.shards.get(&index).expect("shard index out of range")
+        }
+
+        fn write(&self, index: usize) -> Self::WriteGuard<'_> {
+            self.shards
+                .get_mut(&index)
+                .expect("shard index out of range")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::db::Db;
+
+    /// 同样的操作在每种分片容器上得到同样的结果
+    fn exercise<B: Backend>(db: Db<B>) {
+        for i in 0..50 {
+            db.set(format!("k{}", i), Bytes::from(i.to_string()), None)
+                .unwrap();
+        }
+        assert_eq!(db.get("k7"), Ok(Some(Bytes::from_static(b"7"))));
+        assert_eq!(db.del(&["k7".to_string(), "k8".to_string()]), 2);
+        assert_eq!(db.get("k7"), Ok(None));
+        assert_eq!(db.keyspace_stats().keys, 48);
+    }
+
+    #[test]
+    fn every_backend_serves_the_same_commands() {
+        exercise(Db::with_backend(Single::default()));
+        exercise(Db::with_backend(Striped::new(4)));
+        #[cfg(feature = "dashmap")]
+        exercise(Db::with_backend(DashMapBackend::new(4)));
+    }
+}
@@ -0,0 +1,199 @@
+//! 批量加载，用于启动时预热缓存
+//!
+//! 逐条写入时每个 key 都要加一次锁、登记一次过期时间并发布事件。批量加载先按分片分组，
+//! 每个分片只加一次锁，按本批数量预先扩容 HashMap，全部写入后再一次性重建过期索引。
+//! 加载的数据不发布变更通知，也不记录到操作日志，与从 RDB 加载相同。
+
+use std::{
+    fs, io,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use thiserror::Error;
+
+use super::{evict::AccessTime, shard_index, Backend, Db, Entry, Value};
+
+#[derive(Debug, Error)]
+pub enum CsvError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("csv line {line}: {reason}")]
+    Malformed { line: usize, reason: &'static str },
+}
+
+impl<B: Backend> Db<B> {
+    /// 批量写入键值对，已存在的 key 会被覆盖，返回写入的数量
+    ///
+    /// 已经过期的键值对直接丢弃。与 [`Db::load`] 一样不检查 maxmemory 和长度限制。
+    pub fn load_bulk(
+        &self,
+        entries: impl IntoIterator<Item = (String, Value, Option<Instant>)>,
+    ) -> usize {
+        let now = Instant::now();
+        let shard_count = self.shard_count();
+        let mut batches: Vec<Vec<_>> = (0..shard_count).map(|_| vec![]).collect();
+        for (key, value, expires_at) in entries {
+            if expires_at.is_some_and(|when| when <= now) {
+                continue;
+            }
+            batches[shard_index(&self.shared.hasher, &key, shard_count)]
+                .push((key, value, expires_at));
+        }
+
+        let mode = self.shared.expire_mode();
+        let mut count = 0;
+        let mut wake = false;
+        for (index, batch) in batches.into_iter().enumerate() {
+            if batch.is_empty() {
+                continue;
+            }
+            count += batch.len();
+            let expiring = batch.iter().any(|(_, _, expires_at)| expires_at.is_some());
+            let mut shard = self.shared.backend.write(index);
+            shard.reserve(batch.len());
+            for (key, value, expires_at) in batch {
+                shard.insert(
+                    key,
+                    Entry {
+                        value,
+                        expires_at,
+                        accessed: AccessTime::now(),
+                        version: 0,
+                    },
+                );
+            }
+            // 时间轮逐个登记时每次都要比较最早的过期时间，全部写入后重建一次更快
+            if expiring {
+                shard.rebuild_expirations(mode);
+                wake = true;
+            }
+        }
+        if wake {
+            self.shared.background_task.notify_one();
+        }
+        count
+    }
+
+    /// 删除所有 key，与批量写入一样不发布变更通知，也不记录到操作日志
+    ///
+    /// 直接替换各个分片的 HashMap，已有的快照仍然持有旧的数据。
+    pub fn clear(&self) {
+        let mode = self.shared.expire_mode();
+        for index in 0..self.shard_count() {
+            let mut shard = self.shared.backend.write(index);
+            shard.entries = Arc::default();
+            let used = shard.used_memory;
+            shard.sub_memory(used);
+            shard.scan.clear();
+            shard.record_removal();
+            shard.rebuild_expirations(mode);
+        }
+    }
+
+    /// 从 CSV 文件批量加载字符串，返回加载的 key 数量
+    ///
+    /// 每行为 `key,value[,ttl]`，`ttl` 为毫秒，省略或为 0 表示不过期。字段可以用双引号包围，
+    /// 引号内的 `""` 表示一个双引号，但字段中不能包含换行。空行会被跳过。
+    /// 先完整解析文件再写入 Db，文件格式错误时 Db 不会被修改。
+    pub fn load_csv(&self, path: impl AsRef<Path>) -> Result<usize, CsvError> {
+        let text = fs::read_to_string(path)?;
+        let now = Instant::now();
+        let mut entries = vec![];
+        for (n, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let malformed = |reason| CsvError::Malformed {
+                line: n + 1,
+                reason,
+            };
+            let mut fields = split_record(line).map_err(malformed)?.into_iter();
+            let (Some(key), Some(value)) = (fields.next(), fields.next()) else {
+                return Err(malformed("expected key and value"));
+            };
+            let ttl: u64 = match fields.next().as_deref() {
+                None | Some("") => 0,
+                Some(ttl) => ttl.trim().parse().map_err(|_| malformed("invalid ttl"))?,
+            };
+            if fields.next().is_some() {
+                return Err(malformed("too many fields"));
+            }
+            let expires_at = (ttl > 0).then(|| now + Duration::from_millis(ttl));
+            entries.push((key, Value::from(Bytes::from(value)), expires_at));
+        }
+        Ok(self.load_bulk(entries))
+    }
+}
+
+/// 按逗号拆分一行，处理双引号包围的字段
+fn split_record(line: &str) -> Result<Vec<String>, &'static str> {
+    let mut fields = vec![];
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err("unterminated quote"),
+                }
+            }
+            if !matches!(chars.peek(), None | Some(',')) {
+                return Err("unexpected character after quote");
+            }
+        } else {
+            while let Some(c) = chars.next_if(|&c| c != ',') {
+                field.push(c);
+            }
+        }
+        fields.push(field);
+        if chars.next().is_none() {
+            return Ok(fields);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::expire::ExpireIndex;
+
+    #[test]
+    fn bulk_loaded_keys_are_indexed_for_expiration() {
+        let db = Db::new();
+        let now = Instant::now();
+        let loaded = db.load_bulk((0..100).map(|i| {
+            let expires_at = match i % 3 {
+                0 => None,
+                1 => Some(now + Duration::from_secs(60)),
+                _ => Some(now - Duration::from_secs(1)),
+            };
+            (format!("key:{}", i), Value::Int(i), expires_at)
+        }));
+        assert_eq!(loaded, 67);
+        assert_eq!(db.keyspace_stats().keys, 67);
+        assert_eq!(db.get("key:1"), Ok(Some(Bytes::from_static(b"1"))));
+        let indexed: usize = (0..db.shard_count())
+            .map(|index| match &db.shared.backend.read(index).expirations {
+                ExpireIndex::Wheel(wheel) => wheel.len(),
+                ExpireIndex::Sampled(_) => 0,
+            })
+            .sum();
+        assert_eq!(indexed, 33);
+
+        assert_eq!(
+            split_record(r#"a,"b,""c""",10"#),
+            Ok(vec!["a".into(), r#"b,"c""#.into(), "10".into()])
+        );
+        assert!(split_record(r#"a,"b"#).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! 后台收缩容器
+//!
+//! HashMap、VecDeque 删除元素后不会释放容量，长时间运行、反复写入删除的服务中，
+//! 曾经很大的集合即使只剩下几个元素也一直占用着最大时的内存。
+//! 后台任务定期检查分片的哈希表以及集合类型的值，容量超过元素个数的一定倍数时收缩。
+//!
+//! 每次只锁住一个分片；有快照引用的分片会跳过，避免为了收缩而复制整个分片。
+
+use std::{
+    sync::{atomic::Ordering, Arc, Weak},
+    time::Duration,
+};
+
+use tokio::time;
+
+use super::{Backend, Db, Shared, Value};
+
+/// 默认在容量超过元素个数的 4 倍时收缩
+pub const DEFAULT_DEFRAG_RATIO: usize = 4;
+
+/// 检查的间隔
+const DEFRAG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 容量小于这个值的容器收缩不了多少内存，直接跳过
+const MIN_CAPACITY: usize = 64;
+
+fn oversized(len: usize, capacity: usize, ratio: usize) -> bool {
+    capacity >= MIN_CAPACITY && capacity > len.saturating_mul(ratio)
+}
+
+impl Value {
+    /// 容量超过元素个数的 `ratio` 倍时收缩，返回是否发生了收缩
+    fn shrink(&mut self, ratio: usize) -> bool {
+        match self {
+            Value::List(list) if oversized(list.len(), list.capacity(), ratio) => {
+                list.shrink_to_fit()
+            }
+            Value::Hash(hash) if oversized(hash.len(), hash.capacity(), ratio) => {
+                hash.shrink_to_fit()
+            }
+            Value::Set(set) if oversized(set.len(), set.capacity(), ratio) => set.shrink_to_fit(),
+            Value::ZSet(zset) if oversized(zset.len(), zset.capacity(), ratio) => {
+                zset.shrink_to_fit()
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl<B: Backend> Shared<B> {
+    /// 检查所有分片，返回收缩的容器个数，`defrag_ratio` 为 0 时什么也不做
+    fn defrag(&self) -> usize {
+        let ratio = self.defrag_ratio.load(Ordering::Relaxed);
+        if ratio == 0 {
+            return 0;
+        }
+
+        let mut shrunk = 0;
+        for index in 0..self.backend.shard_count() {
+            let mut shard = self.backend.write(index);
+            let shard = &mut *shard;
+            let Some(entries) = Arc::get_mut(&mut shard.entries) else {
+                continue;
+            };
+            if oversized(entries.len(), entries.capacity(), ratio) {
+                entries.shrink_to_fit();
+                shrunk += 1;
+            }
+            let (mut before, mut after) = (0, 0);
+            for (key, entry) in entries.iter_mut() {
+                let usage = entry.memory_usage(key);
+                if entry.value.shrink(ratio) {
+                    before += usage;
+                    after += entry.memory_usage(key);
+                    shrunk += 1;
+                }
+            }
+            shard.sub_memory(before);
+            shard.add_memory(after);
+        }
+        shrunk
+    }
+}
+
+/// 定期收缩的后台任务，只持有弱引用，Db 释放后退出
+pub(super) async fn defrag_task<B: Backend>(shared: Weak<Shared<B>>) {
+    loop {
+        time::sleep(DEFRAG_INTERVAL).await;
+        let Some(shared) = shared.upgrade() else {
+            break;
+        };
+        if shared.is_shutdown() {
+            break;
+        }
+        shared.defrag();
+    }
+}
+
+impl<B: Backend> Db<B> {
+    /// 容量超过元素个数的 `ratio` 倍时收缩，0 表示关闭后台收缩
+    pub fn set_defrag_ratio(&self, ratio: usize) {
+        self.shared.defrag_ratio.store(ratio, Ordering::Relaxed);
+    }
+
+    pub fn defrag_ratio(&self) -> usize {
+        self.shared.defrag_ratio.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn oversized_containers_are_shrunk() {
+        let db = Db::with_shards(1);
+        db.update("list", |value| {
+            let mut list: std::collections::VecDeque<_> = (0..1000).map(|_| Bytes::new()).collect();
+            list.truncate(1);
+            value.set(Value::List(list));
+            Ok(())
+        })
+        .unwrap();
+        let used = db.used_memory();
+
+        // 有快照时跳过
+        let snapshot = db.snapshot();
+        assert_eq!(db.shared.defrag(), 0);
+        drop(snapshot);
+
+        assert_eq!(db.shared.defrag(), 1);
+        assert!(db.used_memory() < used);
+        let capacity = db.view("list", |value| value.unwrap().as_list().unwrap().capacity());
+        assert!(capacity < MIN_CAPACITY);
+    }
+}
@@ -0,0 +1,141 @@
+//! DUMP/RESTORE 使用的单个值的序列化格式
+//!
+//! ```text
+//! 类型(u8) 值 版本号(u16) CRC64(u64)
+//! ```
+//!
+//! 类型和值的编码与 RDB 文件相同，版本号是 RDB 的版本号，CRC64 覆盖前面的所有字节。
+//! 过期时间不在载荷中，由 RESTORE 的参数单独传入。以后的 MIGRATE 和集群迁移也使用这个格式。
+
+use std::time::Instant;
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use super::{
+    rdb::{self, Reader},
+    Backend, Db, DbError, Value,
+};
+
+/// CRC-64/Jones 的多项式（反射形式），与 Redis 相同
+const CRC64_POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+/// 按字节查表计算 CRC64
+fn crc64(data: &[u8]) -> u64 {
+    static TABLE: std::sync::OnceLock<[u64; 256]> = std::sync::OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        let mut table = [0; 256];
+        for (i, slot) in table.iter_mut().enumerate() {
+            let mut crc = i as u64;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ CRC64_POLY
+                } else {
+                    crc >> 1
+                };
+            }
+            *slot = crc;
+        }
+        table
+    });
+    data.iter().fold(0, |crc, &byte| {
+        table[((crc ^ byte as u64) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// 序列化一个值
+pub fn encode(value: &Value) -> Bytes {
+    let mut buf = BytesMut::new();
+    buf.put_u8(rdb::type_of(value));
+    rdb::encode_value(&mut buf, value);
+    buf.put_u16_le(rdb::VERSION as u16);
+    let crc = crc64(&buf);
+    buf.put_u64_le(crc);
+    buf.freeze()
+}
+
+/// 校验并反序列化，版本号比当前新或者校验和不符时返回 None
+pub fn decode(payload: &[u8]) -> Option<Value> {
+    let body_len = payload.len().checked_sub(10)?;
+    let (body, footer) = payload.split_at(body_len + 2);
+    let crc = u64::from_le_bytes(footer[..8].try_into().ok()?);
+    if crc64(body) != crc {
+        return None;
+    }
+    let version = u16::from_le_bytes(body[body_len..].try_into().ok()?);
+    if version > rdb::VERSION as u16 {
+        return None;
+    }
+
+    let mut r = Reader::new(Bytes::copy_from_slice(&body[..body_len]));
+    let ty = r.u8().ok()?;
+    let value = rdb::decode_value(&mut r, ty).ok()?;
+    (r.remaining() == 0).then_some(value)
+}
+
+impl<B: Backend> Db<B> {
+    /// 序列化 key 的值，对应 DUMP 命令，key 不存在时返回 None
+    pub fn dump(&self, key: &str) -> Option<Bytes> {
+        self.view(key, |value| value.map(encode))
+    }
+
+    /// 用序列化的值创建 key，对应 RESTORE 命令
+    ///
+    /// key 已存在且 `replace` 为 false 时返回 [`DbError::BusyKey`]，载荷无法解析时返回
+    /// [`DbError::BadPayload`]。`expires_at` 已经过去时不创建 key（`replace` 时会删除旧值）。
+    pub fn restore(
+        &self,
+        key: &str,
+        payload: &[u8],
+        expires_at: Option<Instant>,
+        replace: bool,
+    ) -> Result<(), DbError> {
+        let value = decode(payload).ok_or(DbError::BadPayload)?;
+        self.shared.check_value(&value)?;
+        self.with_keys(&[key], |locked| {
+            if locked.get(key).is_some() {
+                if !replace {
+                    return Err(DbError::BusyKey);
+                }
+                locked.remove(key);
+            }
+            if expires_at.is_none_or(|when| when > Instant::now()) {
+                locked.insert(key, value, expires_at);
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn dump_and_restore() {
+        // Redis 文档中给出的 CRC-64/Jones 校验值
+        assert_eq!(crc64(b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+
+        let db = Db::new();
+        db.set("a".into(), Bytes::from_static(b"hello"), None)
+            .unwrap();
+        let payload = db.dump("a").unwrap();
+        assert_eq!(db.dump("missing"), None);
+
+        assert_eq!(
+            db.restore("a", &payload, None, false),
+            Err(DbError::BusyKey)
+        );
+        let ttl = Instant::now() + Duration::from_secs(60);
+        db.restore("b", &payload, Some(ttl), false).unwrap();
+        assert_eq!(db.get("b"), Ok(Some(Bytes::from_static(b"hello"))));
+
+        let mut corrupted = payload.to_vec();
+        corrupted[1] ^= 1;
+        assert_eq!(
+            db.restore("c", &corrupted, None, false),
+            Err(DbError::BadPayload)
+        );
+    }
+}
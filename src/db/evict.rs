@@ -0,0 +1,214 @@
+//! maxmemory 与淘汰策略
+//!
+//! 写入前如果内存占用超过了 maxmemory，就按 [`EvictionPolicy`] 从分片中挑选 key 删除，
+//! 直到回到限制以内；挑不出可以淘汰的 key 时写入失败，返回 OOM 错误。
+//!
+//! 和 Redis 一样，LRU、TTL 策略都是近似的：每次只从一个分片中取少量样本比较，
+//! 而不是在整个 keyspace 中找出最优的 key。
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::Instant,
+};
+
+use super::{Backend, DbError, Entry, Event, ExpireIndex, Shard, Shared, SmallString};
+
+/// 每次淘汰时采样的 key 数量，对应 Redis 的 maxmemory-samples
+const SAMPLES: usize = 5;
+
+/// volatile 策略在时间轮模式下最多查看的 key 数量，避免带过期时间的 key 很少时遍历整个分片
+const VOLATILE_SCAN: usize = SAMPLES * 20;
+
+/// 淘汰策略：从分片中选出一个要删除的 key
+pub trait EvictionPolicy: Send + Sync + fmt::Debug + 'static {
+    /// 返回 None 表示这个分片中没有可以淘汰的 key
+    fn select(&self, shard: &Shard) -> Option<String>;
+}
+
+/// 不淘汰，超出限制后所有写入都返回 OOM 错误
+#[derive(Debug, Default)]
+pub struct NoEviction;
+
+/// 在所有 key 中淘汰最久没有被访问的
+#[derive(Debug, Default)]
+pub struct AllKeysLru;
+
+/// 在所有 key 中随机淘汰
+#[derive(Debug, Default)]
+pub struct AllKeysRandom;
+
+/// 在设置了过期时间的 key 中淘汰最快过期的
+#[derive(Debug, Default)]
+pub struct VolatileTtl;
+
+impl EvictionPolicy for NoEviction {
+    fn select(&self, _shard: &Shard) -> Option<String> {
+        None
+    }
+}
+
+impl EvictionPolicy for AllKeysLru {
+    fn select(&self, shard: &Shard) -> Option<String> {
+        sample(shard)
+            .min_by_key(|(_, entry)| entry.accessed.get())
+            .map(|(key, _)| key.to_string())
+    }
+}
+
+impl EvictionPolicy for AllKeysRandom {
+    fn select(&self, shard: &Shard) -> Option<String> {
+        window(shard, 1).next().map(|(key, _)| key.to_string())
+    }
+}
+
+impl EvictionPolicy for VolatileTtl {
+    fn select(&self, shard: &Shard) -> Option<String> {
+        let expires_at = |key: &str| shard.entries.get(key)?.expires_at;
+        match &shard.expirations {
+            // 抽样模式下带过期时间的 key 单独保存，可以直接随机抽取
+            ExpireIndex::Sampled(keys) => (0..SAMPLES)
+                .filter_map(|_| keys.random())
+                .filter_map(|key| Some((key, expires_at(key)?)))
+                .min_by_key(|(_, when)| *when)
+                .map(|(key, _)| key.to_string()),
+            ExpireIndex::Wheel(_) => window(shard, VOLATILE_SCAN)
+                .filter_map(|(key, entry)| Some((key, entry.expires_at?)))
+                .take(SAMPLES)
+                .min_by_key(|(_, when)| *when)
+                .map(|(key, _)| key.to_string()),
+        }
+    }
+}
+
+/// 按名字创建淘汰策略，名字与 Redis 的 maxmemory-policy 配置项相同
+pub fn policy_from_name(name: &str) -> Option<Box<dyn EvictionPolicy>> {
+    let policy: Box<dyn EvictionPolicy> = match &name.to_lowercase()[..] {
+        "noeviction" => Box::new(NoEviction),
+        "allkeys-lru" => Box::new(AllKeysLru),
+        "allkeys-random" => Box::new(AllKeysRandom),
+        "volatile-ttl" => Box::new(VolatileTtl),
+        _ => return None,
+    };
+    Some(policy)
+}
+
+/// 从分片中取出样本
+fn sample(shard: &Shard) -> impl Iterator<Item = (&SmallString, &Entry)> {
+    window(shard, SAMPLES)
+}
+
+/// 从随机的位置开始取出最多 `count` 个相邻的元素，到末尾后绕回开头
+///
+/// 借助 SCAN 的有序索引随机定位起点，耗时只和 `count` 有关，不需要遍历分片的 HashMap。
+/// 起点每次都不同，样本不会总是迭代顺序中最前面的几个 key。
+fn window(shard: &Shard, count: usize) -> impl Iterator<Item = (&SmallString, &Entry)> {
+    shard
+        .scan
+        .sample(count)
+        .filter_map(|key| shard.entries.get_key_value(key.as_str()))
+}
+
+/// 最近一次访问的时间，单位为毫秒
+///
+/// 读命令只持有分片的读锁，因此使用原子类型记录。
+#[derive(Debug, Default)]
+pub struct AccessTime(AtomicU64);
+
+impl AccessTime {
+    pub fn now() -> AccessTime {
+        AccessTime(AtomicU64::new(clock()))
+    }
+
+    pub fn touch(&self) {
+        self.0.store(clock(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Clone for AccessTime {
+    fn clone(&self) -> AccessTime {
+        AccessTime(AtomicU64::new(self.get()))
+    }
+}
+
+/// LRU 时钟：进程启动后经过的毫秒数
+fn clock() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
+
+impl<B: Backend> Shared<B> {
+    /// 内存占用超过 maxmemory 时淘汰 key，没有设置 maxmemory 时直接返回
+    ///
+    /// 轮流从各个分片中淘汰，连续一整圈的分片都挑不出 key 时返回 OOM 错误。
+    pub(super) fn evict_if_needed(&self) -> Result<(), DbError> {
+        let max_memory = self.max_memory.load(Ordering::Relaxed);
+        if max_memory == 0 {
+            return Ok(());
+        }
+
+        let policy = self.eviction.read().unwrap();
+        let shards = self.backend.shard_count();
+        let mut misses = 0;
+        while self.used_memory() > max_memory {
+            if misses == shards {
+                return Err(DbError::OutOfMemory);
+            }
+            let index = self.next_eviction.fetch_add(1, Ordering::Relaxed) % shards;
+            let mut shard = self.backend.write(index);
+            match policy.select(&shard) {
+                Some(key) => {
+                    if shard.remove(&key).is_some() {
+                        self.log_removal(&key);
+                        drop(shard);
+                        self.notify(&key, Event::Evicted);
+                    }
+                    misses = 0;
+                }
+                None => misses += 1,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::db::ExpireMode;
+
+    /// 在同一个分片上反复选择，统计选中过的不同 key，固定取开头的样本时不会超过 SAMPLES 个
+    fn selected(policy: &dyn EvictionPolicy, shard: &Shard) -> usize {
+        let mut keys: Vec<String> = (0..50).filter_map(|_| policy.select(shard)).collect();
+        keys.sort();
+        keys.dedup();
+        keys.len()
+    }
+
+    #[test]
+    fn samples_start_at_random_positions() {
+        // 过期时间都相同，volatile-ttl 选中的是样本中的第一个 key
+        let when = Instant::now() + Duration::from_secs(60);
+        let mut shard = Shard::default();
+        for i in 0..20 {
+            shard.set(format!("k{}", i), Bytes::new().into(), Some(when));
+        }
+        assert!(selected(&AllKeysRandom, &shard) > SAMPLES);
+        assert!(selected(&VolatileTtl, &shard) > SAMPLES);
+
+        shard.rebuild_expirations(ExpireMode::Sampling);
+        assert!(selected(&VolatileTtl, &shard) > SAMPLES);
+        assert_eq!(selected(&VolatileTtl, &Shard::default()), 0);
+    }
+}
@@ -0,0 +1,267 @@
+//! 过期 key 的主动清理
+//!
+//! 读取时遇到过期的 key 会立即删除（惰性删除），后台任务负责清理不再被访问的过期 key。
+//! 后台任务有两种工作方式，见 [`ExpireMode`]。
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::time;
+
+use super::{volatile::VolatileKeys, wheel::TimerWheel, Backend, Db, Event, Shard, Shared};
+
+/// 抽样模式下每轮从一个分片抽取的 key 数量，对应 Redis 的 ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP
+const SAMPLES: usize = 20;
+/// 抽样模式下两次清理的间隔，对应 Redis 默认的 hz 10
+const SAMPLING_INTERVAL: Duration = Duration::from_millis(100);
+/// 抽样模式下一次清理最多占用的时间，避免过期的 key 很多时长时间占用线程
+const SAMPLING_BUDGET: Duration = Duration::from_millis(25);
+/// 时间轮中的元素数超过 key 数量的两倍再加上这个数时重建索引，见 [`Shard::compact_expirations`]
+const COMPACT_SLACK: usize = 1024;
+
+/// 主动清理过期 key 的方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExpireMode {
+    /// 用时间轮索引所有过期时间，key 一到期就被删除
+    #[default]
+    Deadline,
+    /// 与 Redis 相同的概率清理：定期从每个分片随机抽取带过期时间的 key，删除其中已过期的，
+    /// 过期比例超过 25% 时继续抽取。索引占用的内存和维护开销更小，但过期的 key 会多占用一段时间内存
+    Sampling,
+}
+
+impl ExpireMode {
+    /// 按名字选择清理方式：`deadline` 或 `sampling`
+    pub fn from_name(name: &str) -> Option<ExpireMode> {
+        match &name.to_lowercase()[..] {
+            "deadline" => Some(ExpireMode::Deadline),
+            "sampling" => Some(ExpireMode::Sampling),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExpireMode::Deadline => "deadline",
+            ExpireMode::Sampling => "sampling",
+        }
+    }
+}
+
+/// 分片的过期索引，key 被删除或覆盖后旧的元素不会立即移除，清理时再对照 `entries` 过滤
+///
+/// 时间轮中旧元素过多时整个重建，抽样模式下的索引每个 key 只占一个元素，不需要重建。
+#[derive(Debug)]
+pub(super) enum ExpireIndex {
+    Wheel(TimerWheel),
+    Sampled(VolatileKeys),
+}
+
+impl Default for ExpireIndex {
+    fn default() -> ExpireIndex {
+        ExpireIndex::Wheel(TimerWheel::default())
+    }
+}
+
+impl ExpireIndex {
+    fn new(mode: ExpireMode) -> ExpireIndex {
+        match mode {
+            ExpireMode::Deadline => ExpireIndex::default(),
+            ExpireMode::Sampling => ExpireIndex::Sampled(VolatileKeys::default()),
+        }
+    }
+
+    /// 登记过期时间，返回是否需要唤醒后台清理任务
+    ///
+    /// 时间轮模式下，新的过期时间早于已有的所有过期时间时，后台任务需要重新计算睡眠时间；
+    /// 抽样模式下后台任务按固定间隔运行，不需要唤醒。
+    pub(super) fn insert(&mut self, key: &str, when: Instant) -> bool {
+        match self {
+            ExpireIndex::Wheel(wheel) => {
+                let wake = wheel.next_deadline().is_none_or(|earliest| when < earliest);
+                wheel.insert(key.to_string(), when);
+                wake
+            }
+            ExpireIndex::Sampled(keys) => {
+                keys.insert(key);
+                false
+            }
+        }
+    }
+}
+
+impl Shard {
+    /// 删除该分片中已经过期的 key 并放入 `expired`，返回分片中下一个需要处理的时间点
+    fn purge_expired(&mut self, now: Instant, expired: &mut Vec<String>) -> Option<Instant> {
+        let ExpireIndex::Wheel(wheel) = &mut self.expirations else {
+            return None;
+        };
+        for key in wheel.poll(now) {
+            // 时间轮中可能是已被删除或覆盖的旧元素，以 entries 中的过期时间为准
+            if self.remove_if_expired(&key, now) {
+                expired.push(key);
+            }
+        }
+        match &self.expirations {
+            ExpireIndex::Wheel(wheel) => wheel.next_deadline(),
+            ExpireIndex::Sampled(_) => None,
+        }
+    }
+
+    /// 随机抽取最多 [`SAMPLES`] 个带过期时间的 key，删除其中已过期的并放入 `expired`
+    ///
+    /// 返回抽到的有效 key 数量，已经不存在或不再带过期时间的 key 从索引中移除，不计入数量。
+    fn sample_expired(&mut self, now: Instant, expired: &mut Vec<String>) -> usize {
+        let mut sampled = 0;
+        for _ in 0..SAMPLES {
+            let ExpireIndex::Sampled(keys) = &self.expirations else {
+                break;
+            };
+            let Some(key) = keys.random().map(str::to_string) else {
+                break;
+            };
+            let expires_at = self
+                .entries
+                .get(key.as_str())
+                .and_then(|entry| entry.expires_at);
+            if expires_at.is_some() {
+                sampled += 1;
+            }
+            if expires_at.is_some_and(|when| when > now) {
+                continue;
+            }
+            if let ExpireIndex::Sampled(keys) = &mut self.expirations {
+                keys.remove(&key);
+            }
+            if expires_at.is_some() {
+                self.remove(&key);
+                expired.push(key);
+            }
+        }
+        sampled
+    }
+
+    /// 时间轮中失效的旧元素过多时按 `entries` 重建
+    ///
+    /// 反复刷新同一个 key 的过期时间会在时间轮中留下旧元素，它们要等到旧的过期时间才被取出。
+    /// 只在元素数超过 key 数量的两倍后重建，重建的开销分摊到之前的写入上。
+    pub(super) fn compact_expirations(&mut self) {
+        let ExpireIndex::Wheel(wheel) = &self.expirations else {
+            return;
+        };
+        if wheel.len() > 2 * self.entries.len() + COMPACT_SLACK {
+            self.rebuild_expirations(ExpireMode::Deadline);
+        }
+    }
+
+    /// 切换过期索引，重新登记分片中所有的过期时间
+    pub(super) fn rebuild_expirations(&mut self, mode: ExpireMode) {
+        let mut index = ExpireIndex::new(mode);
+        for (key, entry) in self.entries.iter() {
+            if let Some(when) = entry.expires_at {
+                index.insert(key, when);
+            }
+        }
+        self.expirations = index;
+    }
+}
+
+impl<B: Backend> Shared<B> {
+    pub(super) fn expire_mode(&self) -> ExpireMode {
+        *self.expire_mode.read().unwrap()
+    }
+
+    /// 在持有分片写锁时记录从中清理的 key，见 [`Shared::log_removal`]
+    fn log_removals(&self, keys: &[String]) {
+        for key in keys {
+            self.log_removal(key);
+        }
+    }
+
+    /// 清理所有分片中的过期 key，返回所有分片中最早的下一个过期时间点
+    fn purge_expired_keys(&self) -> Option<Instant> {
+        let now = Instant::now();
+        let mut expired = vec![];
+        let next = (0..self.backend.shard_count())
+            .filter_map(|index| {
+                let mut shard = self.backend.write(index);
+                let start = expired.len();
+                let next = shard.purge_expired(now, &mut expired);
+                self.log_removals(&expired[start..]);
+                next
+            })
+            .min();
+        for key in expired {
+            self.notify(&key, Event::Expired);
+        }
+        next
+    }
+
+    /// 对每个分片抽样清理，过期比例超过 25% 时继续抽取，直到用完时间预算
+    fn sample_expired_keys(&self) {
+        let start = Instant::now();
+        let mut expired = vec![];
+        for index in 0..self.backend.shard_count() {
+            loop {
+                let before = expired.len();
+                let mut shard = self.backend.write(index);
+                let sampled = shard.sample_expired(Instant::now(), &mut expired);
+                self.log_removals(&expired[before..]);
+                drop(shard);
+                let found = expired.len() - before;
+                if sampled == 0 || found * 4 <= sampled || start.elapsed() > SAMPLING_BUDGET {
+                    break;
+                }
+            }
+        }
+        for key in expired {
+            self.notify(&key, Event::Expired);
+        }
+    }
+}
+
+impl<B: Backend> Db<B> {
+    /// 切换后台清理过期 key 的方式，默认为 [`ExpireMode::Deadline`]
+    ///
+    /// 切换时需要重建所有分片的过期索引，key 很多时开销较大；模式没有变化时什么都不做。
+    pub fn set_expire_mode(&self, mode: ExpireMode) {
+        {
+            let mut current = self.shared.expire_mode.write().unwrap();
+            if *current == mode {
+                return;
+            }
+            *current = mode;
+        }
+        for index in 0..self.shard_count() {
+            self.shared.backend.write(index).rebuild_expirations(mode);
+        }
+        self.shared.background_task.notify_one();
+    }
+}
+
+/// 后台清理任务
+///
+/// 时间轮模式下，每次清理完成后睡眠到下一个过期时间点；写入了更早过期的 key 时会被 `Notify` 提前唤醒，
+/// 没有任何带过期时间的 key 时则一直等待通知。抽样模式下按固定间隔清理。
+/// 切换模式时任务会被唤醒，`DbDropGuard` 被 drop 后任务退出。
+pub(super) async fn purge_expired_tasks<B: Backend>(shared: Arc<Shared<B>>) {
+    while !shared.is_shutdown() {
+        let next = match shared.expire_mode() {
+            ExpireMode::Deadline => shared.purge_expired_keys(),
+            ExpireMode::Sampling => {
+                shared.sample_expired_keys();
+                Some(Instant::now() + SAMPLING_INTERVAL)
+            }
+        };
+        if let Some(when) = next {
+            tokio::select! {
+                _ = time::sleep_until(when.into()) => {}
+                _ = shared.background_task.notified() => {}
+            }
+        } else {
+            shared.background_task.notified().await;
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! keyspace 中 HashMap 使用的哈希算法
+//!
+//! 默认使用标准库的 SipHash，每个 HashMap 使用随机的种子，可以抵御构造大量冲突 key 的攻击，
+//! 适合面向不可信客户端的部署。GET/SET 的性能分析中哈希的占比很高，
+//! 在可信的环境中可以通过 feature 换用更快的算法：
+//!
+//! - `ahash`：有硬件 AES 指令时更快，同样使用随机种子
+//! - `fxhash`：rustc 使用的哈希，最快，但没有种子，冲突的 key 很容易构造
+//!
+//! 同时开启两个 feature 时使用 `fxhash`。
+//!
+//! 选择分片也使用同一种算法，每个 Db 持有一个 [`KeyHasher`]，种子在创建 Db 时随机生成，
+//! 见 [`shard_index`]。
+
+use std::{collections::HashMap, hash::BuildHasher};
+
+use super::SmallString;
+
+#[cfg(feature = "fxhash")]
+pub type KeyHasher = fxhash::FxBuildHasher;
+
+#[cfg(all(feature = "ahash", not(feature = "fxhash")))]
+pub type KeyHasher = ahash::RandomState;
+
+#[cfg(not(any(feature = "ahash", feature = "fxhash")))]
+pub type KeyHasher = std::collections::hash_map::RandomState;
+
+/// 当前使用的哈希算法的名称
+pub const NAME: &str = if cfg!(feature = "fxhash") {
+    "fxhash"
+} else if cfg!(feature = "ahash") {
+    "ahash"
+} else {
+    "siphash"
+};
+
+/// 以 key 为键的 HashMap
+pub type KeyMap<V> = HashMap<SmallString, V, KeyHasher>;
+
+/// key 所在分片的下标，`hasher` 为所在 Db 的 [`KeyHasher`]
+///
+/// 分片内的 HashMap 用哈希值的低位定位槽位、最高的 7 位作为标记，这里取中间的位，
+/// 即使是没有种子的 fxhash，同一个分片中的 key 也不会集中在少数几个槽位上。
+pub fn shard_index(hasher: &KeyHasher, key: &str, shard_count: usize) -> usize {
+    ((hasher.hash_one(key) >> 24) as u32 as usize) % shard_count
+}
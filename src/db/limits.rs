@@ -0,0 +1,87 @@
+//! key 和值的大小限制
+//!
+//! 在 Db 层统一检查，一个异常的客户端无法写入超大的值，避免内存估算和持久化被拖垮。
+//! 值的大小只限制字符串，集合类型的元素逐个写入，由各自的命令限制。
+
+use std::sync::atomic::Ordering;
+
+use super::{Backend, Db, DbError, Shared, Value};
+
+/// key 的默认最大长度，与 Redis 的 proto-max-bulk-len 相同
+pub const DEFAULT_MAX_KEY_LEN: usize = 512 * 1024 * 1024;
+
+/// 字符串值的默认最大长度
+pub const DEFAULT_MAX_VALUE_SIZE: usize = 512 * 1024 * 1024;
+
+impl<B> Shared<B> {
+    /// key 超过最大长度时返回 [`DbError::KeyTooLong`]
+    pub(super) fn check_key(&self, key: &str) -> Result<(), DbError> {
+        let max = self.max_key_len.load(Ordering::Relaxed);
+        if max != 0 && key.len() > max {
+            return Err(DbError::KeyTooLong);
+        }
+        Ok(())
+    }
+
+    /// 字符串值超过最大长度时返回 [`DbError::ValueTooLarge`]
+    pub(super) fn check_value_size(&self, len: usize) -> Result<(), DbError> {
+        let max = self.max_value_size.load(Ordering::Relaxed);
+        if max != 0 && len > max {
+            return Err(DbError::ValueTooLarge);
+        }
+        Ok(())
+    }
+
+    pub(super) fn check_value(&self, value: &Value) -> Result<(), DbError> {
+        match value {
+            Value::String(data) => self.check_value_size(data.len()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<B: Backend> Db<B> {
+    /// 设置 key 的最大长度，0 表示不限制
+    pub fn set_max_key_len(&self, len: usize) {
+        self.shared.max_key_len.store(len, Ordering::Relaxed);
+    }
+
+    pub fn max_key_len(&self) -> usize {
+        self.shared.max_key_len.load(Ordering::Relaxed)
+    }
+
+    /// 设置字符串值的最大长度，0 表示不限制
+    pub fn set_max_value_size(&self, size: usize) {
+        self.shared.max_value_size.store(size, Ordering::Relaxed);
+    }
+
+    pub fn max_value_size(&self) -> usize {
+        self.shared.max_value_size.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn oversized_keys_and_values_are_rejected() {
+        let db = Db::new();
+        db.set_max_key_len(4);
+        db.set_max_value_size(8);
+
+        assert_eq!(
+            db.set("long-key".into(), Bytes::new(), None),
+            Err(DbError::KeyTooLong)
+        );
+        assert_eq!(
+            db.set("k".into(), Bytes::from(vec![0; 9]), None),
+            Err(DbError::ValueTooLarge)
+        );
+        assert_eq!(db.incr_by("long-key", 1), Err(DbError::KeyTooLong));
+        db.set("k".into(), Bytes::from(vec![0; 8]), None).unwrap();
+        assert_eq!(db.get("k").unwrap().unwrap().len(), 8);
+    }
+}
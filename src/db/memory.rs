@@ -0,0 +1,156 @@
+//! 内存占用估算
+//!
+//! 估算值用于 MEMORY USAGE 命令和 maxmemory 的统计，两者使用同一套计算方式。
+//! 除了数据本身，还考虑了容器的结构大小、哈希表的空槽位以及分配器按尺寸分级带来的浪费，
+//! 但不追求和实际占用完全一致。
+//!
+//! 元素很多的集合类型只计算前 `samples` 个元素，再按元素个数放大，
+//! 避免每次修改大集合都要遍历所有元素。`samples` 为 0 时计算所有元素。
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    mem::size_of,
+};
+
+use bytes::Bytes;
+
+use super::{small, SmallBytes, Value, ZSet};
+
+/// 默认的采样数量，与 Redis MEMORY USAGE 的默认值相同
+pub const DEFAULT_SAMPLES: usize = 5;
+
+pub trait MemoryUsage {
+    /// 估算在堆上占用的字节数，不包括自身结构的大小（它已经算在所在容器的槽位中）
+    fn memory_usage(&self, samples: usize) -> usize;
+}
+
+/// 申请 `size` 字节时分配器实际分配的大小
+///
+/// 参考 jemalloc 的尺寸分级：小于 128 字节时按 16 字节对齐，
+/// 更大的分配在每个 2 的幂区间内分为 4 级。
+pub fn malloc_size(size: usize) -> usize {
+    match size {
+        0 => 0,
+        1..=8 => 8,
+        9..=128 => size.next_multiple_of(16),
+        _ => {
+            let step = size.next_power_of_two() / 8;
+            size.next_multiple_of(step)
+        }
+    }
+}
+
+/// 哈希表（hashbrown）的桶数组大小
+///
+/// 桶的数量是 2 的幂，最多装满 7/8，每个桶还有 1 字节的控制位。
+pub fn hash_table_size<T>(len: usize) -> usize {
+    if len == 0 {
+        return 0;
+    }
+    let buckets = (len * 8 / 7).next_power_of_two().max(4);
+    malloc_size(buckets * (size_of::<T>() + 1) + 16)
+}
+
+/// B 树节点平均只装满约 2/3，按元素大小的 1.5 倍估算
+pub fn btree_size<T>(len: usize) -> usize {
+    len * size_of::<T>() * 3 / 2
+}
+
+/// 对前 `samples` 个元素求和，再按元素总数放大
+pub fn sampled<T>(
+    iter: impl ExactSizeIterator<Item = T>,
+    samples: usize,
+    size: impl Fn(T) -> usize,
+) -> usize {
+    let len = iter.len();
+    if samples == 0 || len <= samples {
+        return iter.map(size).sum();
+    }
+    iter.take(samples).map(size).sum::<usize>() * len / samples
+}
+
+fn bytes_heap(bytes: &Bytes) -> usize {
+    malloc_size(bytes.len())
+}
+
+impl MemoryUsage for Bytes {
+    fn memory_usage(&self, _samples: usize) -> usize {
+        bytes_heap(self)
+    }
+}
+
+impl MemoryUsage for SmallBytes {
+    fn memory_usage(&self, _samples: usize) -> usize {
+        small::heap_size(self.len())
+    }
+}
+
+impl MemoryUsage for str {
+    fn memory_usage(&self, _samples: usize) -> usize {
+        malloc_size(self.len())
+    }
+}
+
+impl MemoryUsage for VecDeque<Bytes> {
+    fn memory_usage(&self, samples: usize) -> usize {
+        malloc_size(self.capacity() * size_of::<Bytes>())
+            + sampled(self.iter(), samples, bytes_heap)
+    }
+}
+
+impl MemoryUsage for HashMap<Bytes, Bytes> {
+    fn memory_usage(&self, samples: usize) -> usize {
+        hash_table_size::<(Bytes, Bytes)>(self.capacity())
+            + sampled(self.iter(), samples, |(k, v)| bytes_heap(k) + bytes_heap(v))
+    }
+}
+
+impl MemoryUsage for HashSet<Bytes> {
+    fn memory_usage(&self, samples: usize) -> usize {
+        hash_table_size::<Bytes>(self.capacity()) + sampled(self.iter(), samples, bytes_heap)
+    }
+}
+
+impl MemoryUsage for ZSet {
+    fn memory_usage(&self, samples: usize) -> usize {
+        // 成员同时存放在哈希表和 B 树中，但 Bytes 的 clone 共享同一份数据
+        hash_table_size::<(Bytes, f64)>(self.len())
+            + btree_size::<(f64, Bytes)>(self.len())
+            + sampled(self.iter(), samples, |(member, _)| bytes_heap(member))
+    }
+}
+
+impl MemoryUsage for Value {
+    fn memory_usage(&self, samples: usize) -> usize {
+        match self {
+            Value::String(data) => data.memory_usage(samples),
+            Value::Int(_) => 0,
+            Value::List(list) => list.memory_usage(samples),
+            Value::Hash(hash) => hash.memory_usage(samples),
+            Value::Set(set) => set.memory_usage(samples),
+            Value::ZSet(zset) => zset.memory_usage(samples),
+            Value::Stream(stream) => stream.memory_usage(samples),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malloc_size_rounds_up_to_size_classes() {
+        assert_eq!(malloc_size(0), 0);
+        assert_eq!(malloc_size(5), 8);
+        assert_eq!(malloc_size(17), 32);
+        assert_eq!(malloc_size(129), 160);
+        assert_eq!(malloc_size(1000), 1024);
+    }
+
+    #[test]
+    fn sampling_scales_by_len() {
+        let items = vec![10; 100];
+        assert_eq!(sampled(items.iter(), 5, |n| *n), 1000);
+        assert_eq!(sampled(items.iter(), 0, |n| *n), 1000);
+    }
+}
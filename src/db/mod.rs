@@ -0,0 +1,1181 @@
+use std::{
+    collections::hash_map,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use thiserror::Error;
+use tokio::sync::{broadcast, Notify};
+
+use crate::stream::StreamError;
+
+pub mod aof;
+use aof::Aof;
+
+pub mod backend;
+pub use backend::{Backend, Single, Striped};
+
+pub mod bulk;
+
+mod defrag;
+pub use defrag::DEFAULT_DEFRAG_RATIO;
+
+pub mod dump;
+
+pub mod evict;
+use evict::AccessTime;
+pub use evict::EvictionPolicy;
+
+pub mod hasher;
+use hasher::{shard_index, KeyHasher, KeyMap};
+
+mod expire;
+use expire::ExpireIndex;
+pub use expire::ExpireMode;
+
+mod limits;
+pub use limits::{DEFAULT_MAX_KEY_LEN, DEFAULT_MAX_VALUE_SIZE};
+
+pub mod memory;
+use memory::{MemoryUsage, DEFAULT_SAMPLES};
+
+mod multi;
+pub use multi::LockedKeys;
+
+mod notify;
+pub use notify::{Event, Notification};
+
+pub mod oplog;
+use oplog::OpLog;
+
+pub mod rdb;
+
+mod readonly;
+pub use readonly::ReadView;
+
+mod scan;
+use scan::ScanIndex;
+
+mod slot;
+pub use slot::Slot;
+
+mod small;
+pub use small::{SmallBytes, SmallString};
+
+mod snapshot;
+pub use snapshot::Snapshot;
+
+mod stats;
+pub use stats::{DbStats, KeyspaceStats};
+
+pub mod storage;
+use storage::StorageBackend;
+
+mod tx;
+pub use tx::{TxGuard, TxOp};
+
+mod value;
+pub use value::{Value, ZSet};
+
+mod volatile;
+
+mod wheel;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DbError {
+    #[error("OOM command not allowed when used memory > 'maxmemory'.")]
+    OutOfMemory,
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+    #[error("ERR value is not an integer or out of range")]
+    NotInteger,
+    #[error("ERR increment or decrement would overflow")]
+    Overflow,
+    #[error("ERR Background save already in progress")]
+    SaveInProgress,
+    #[error("ERR Append only file is not enabled")]
+    AofDisabled,
+    #[error("ERR Background append only file rewriting already in progress")]
+    RewriteInProgress,
+    #[error("BUSYKEY Target key name already exists.")]
+    BusyKey,
+    #[error("ERR DUMP payload version or checksum are wrong")]
+    BadPayload,
+    #[error("ERR key exceeds maximum allowed length (max-key-len)")]
+    KeyTooLong,
+    #[error("ERR string exceeds maximum allowed size (max-value-size)")]
+    ValueTooLarge,
+    #[error("READONLY You can't write against a read only replica.")]
+    ReadOnly,
+    #[error("ERR invalid expire time in 'set' command")]
+    InvalidExpire,
+    #[error(transparent)]
+    Stream(#[from] StreamError),
+}
+
+/// 持有 Db 并负责后台任务的生命周期
+///
+/// 创建时启动后台清理过期 key 和收缩容器的任务。被 drop 时通知清理任务退出并关闭 AOF，
+/// AOF 写入任务把剩余的数据同步到磁盘后退出，即使还有连接持有 `Db` 也不会继续运行。
+/// 淘汰在写入时同步进行，没有后台任务。
+/// 服务端持有一个 `DbDropGuard`，各个连接通过 `db()` 获得共享的 `Db`。
+#[derive(Debug)]
+pub struct DbDropGuard<B: Backend = Striped> {
+    db: Db<B>,
+}
+
+/// 所有连接共享的数据库
+///
+/// 内部状态都放在 `Arc` 中，`clone` 只会增加引用计数，
+/// 因此每个连接的处理任务都可以持有一个 `Db` 并访问同一份数据。
+///
+/// key 按哈希值分散到多个分片中，每个分片各自加锁，
+/// 多线程运行时下访问不同分片的命令可以并行执行，不会被同一把锁串行化。
+/// 分片的存储方式由 [`Backend`] 决定，默认使用锁分段的 [`Striped`]。
+#[derive(Debug)]
+pub struct Db<B: Backend = Striped> {
+    shared: Arc<Shared<B>>,
+}
+
+#[derive(Debug)]
+struct Shared<B> {
+    backend: B,
+    /// 选择分片使用的哈希，种子在创建 Db 时生成
+    hasher: KeyHasher,
+    /// 唤醒后台清理任务：出现了更早的过期时间，或者 Db 即将关闭
+    background_task: Notify,
+    shutdown: AtomicBool,
+    /// 内存上限，单位为字节，0 表示不限制
+    max_memory: AtomicUsize,
+    /// key 与字符串值的最大长度，0 表示不限制
+    max_key_len: AtomicUsize,
+    max_value_size: AtomicUsize,
+    /// 后台收缩容器的阈值，见 [`Db::set_defrag_ratio`]
+    defrag_ratio: AtomicUsize,
+    eviction: RwLock<Box<dyn EvictionPolicy>>,
+    /// 下一次从哪个分片开始淘汰
+    next_eviction: AtomicUsize,
+    /// SAVE/BGSAVE 写入的文件
+    rdb_path: RwLock<PathBuf>,
+    /// 是否有正在进行的后台保存
+    saving: AtomicBool,
+    aof: RwLock<Option<Aof>>,
+    /// 是否有正在进行的 AOF 重写
+    rewriting: AtomicBool,
+    /// key 的变更通知
+    events: broadcast::Sender<Notification>,
+    /// 后台清理过期 key 的方式
+    expire_mode: RwLock<ExpireMode>,
+    /// 读穿透与写穿透使用的存储层
+    storage: RwLock<Option<Arc<dyn StorageBackend>>>,
+    /// 写操作日志以及本数据库的编号
+    op_log: RwLock<(Arc<OpLog>, usize)>,
+    /// 所有分片估算的内存占用之和，各个分片修改自己的 `used_memory` 时一起维护
+    used_memory: Arc<AtomicUsize>,
+    /// 读取命中与未命中的次数
+    hits: AtomicU64,
+    misses: AtomicU64,
+    /// 因过期和淘汰被删除的 key 的数量
+    expired: AtomicU64,
+    evicted: AtomicU64,
+}
+
+impl<B> Shared<B> {
+    fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::Acquire)
+    }
+
+    /// 通知所有后台任务退出
+    fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Release);
+        self.background_task.notify_one();
+        // 写入任务在所有发送端都释放后退出，正在进行的 AOF 重写完成后它持有的发送端也会释放
+        self.aof.write().unwrap().take();
+    }
+}
+
+impl<B> Shared<B> {
+    /// key 已过期时从 `shard` 中删除并以 DEL 记录到操作日志，返回是否发生了删除
+    ///
+    /// `shard` 为 key 所在分片的写锁，在锁内记录，日志中的顺序与其他修改这个 key 的命令一致。
+    fn remove_if_expired(&self, shard: &mut Shard, key: &str, now: Instant) -> bool {
+        let expired = shard.remove_if_expired(key, now);
+        if expired {
+            self.log_removal(key);
+        }
+        expired
+    }
+}
+
+impl<B: Backend> Shared<B> {
+    /// 所有分片估算的内存占用之和，不需要锁住分片
+    fn used_memory(&self) -> usize {
+        self.used_memory.load(Ordering::Relaxed)
+    }
+}
+
+impl DbDropGuard {
+    /// 使用默认的 Db 并启动后台任务，必须在 tokio 运行时中调用
+    pub fn new() -> DbDropGuard {
+        DbDropGuard::with_db(Db::new())
+    }
+}
+
+impl Default for DbDropGuard {
+    fn default() -> DbDropGuard {
+        DbDropGuard::new()
+    }
+}
+
+impl<B: Backend> DbDropGuard<B> {
+    pub fn with_db(db: Db<B>) -> DbDropGuard<B> {
+        tokio::spawn(expire::purge_expired_tasks(Arc::clone(&db.shared)));
+        tokio::spawn(defrag::defrag_task(Arc::downgrade(&db.shared)));
+        DbDropGuard { db }
+    }
+
+    /// 获取共享的 Db，clone 只增加引用计数
+    pub fn db(&self) -> Db<B> {
+        self.db.clone()
+    }
+}
+
+impl<B: Backend> Drop for DbDropGuard<B> {
+    fn drop(&mut self) {
+        self.db.shared.shutdown();
+    }
+}
+
+/// 一个分片，包含该分片的键值对以及过期索引
+#[derive(Debug, Default)]
+pub struct Shard {
+    /// 放在 `Arc` 中以便快照共享，有快照存在时第一次修改会复制整个分片（写时复制）
+    entries: Arc<KeyMap<Entry>>,
+    /// 分片中所有键值对估算的内存占用
+    used_memory: usize,
+    /// 所在 Db 所有分片的内存占用之和，由 [`Db::with_backend`] 设置为 [`Shared`] 中的计数器
+    db_memory: Arc<AtomicUsize>,
+    /// 过期索引，key 被删除或覆盖后旧的元素不会立即移除，清理时再对照 `entries` 过滤
+    expirations: ExpireIndex,
+    /// 分片中最近一次写入或删除的版本号
+    version: u64,
+    /// 最近一次删除 key 时分配的版本号，作为分片中所有不存在的 key 的版本号，见 [`Db::version`]
+    removed: u64,
+    /// SCAN 使用的有序索引，包含分片中所有的 key
+    scan: ScanIndex,
+}
+
+/// 数据库中的一个键值对
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub value: Value,
+    /// 过期时间点，None 表示永不过期
+    pub expires_at: Option<Instant>,
+    /// 最近一次访问的时间，供 LRU 淘汰使用
+    pub accessed: AccessTime,
+    /// 写入时的版本号，由 [`Shard::insert`] 分配，见 [`Db::version`]
+    pub version: u64,
+}
+
+impl Entry {
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|when| when <= now)
+    }
+
+    /// 估算键值对占用的内存：HashMap 中的槽位加上 key 与 value 在堆上的数据
+    pub fn memory_usage(&self, key: &str) -> usize {
+        self.memory_usage_sampled(key, DEFAULT_SAMPLES)
+    }
+
+    fn memory_usage_sampled(&self, key: &str, samples: usize) -> usize {
+        // 槽位的大小，每个槽位还有 1 字节的控制位
+        std::mem::size_of::<(SmallString, Entry)>()
+            + 1
+            + small::heap_size(key.len())
+            + self.value.memory_usage(samples)
+    }
+}
+
+impl Shard {
+    /// 写入键值对并维护内存占用，返回被覆盖的旧值
+    ///
+    /// 每次写入都分配一个新的版本号。版本号在分片内递增，同一个 key 总在同一个分片中，
+    /// 删除后重新创建的 key 的版本号也一定比删除前大。
+    fn insert(&mut self, key: impl Into<SmallString>, mut entry: Entry) -> Option<Entry> {
+        self.version += 1;
+        entry.version = self.version;
+        match Arc::make_mut(&mut self.entries).entry(key.into()) {
+            hash_map::Entry::Occupied(mut occupied) => {
+                let key = occupied.key();
+                let (added, removed) = (entry.memory_usage(key), occupied.get().memory_usage(key));
+                let old = occupied.insert(entry);
+                self.add_memory(added);
+                self.sub_memory(removed);
+                Some(old)
+            }
+            hash_map::Entry::Vacant(vacant) => {
+                let added = entry.memory_usage(vacant.key());
+                self.scan.insert(vacant.key());
+                vacant.insert(entry);
+                self.add_memory(added);
+                None
+            }
+        }
+    }
+
+    fn add_memory(&mut self, bytes: usize) {
+        self.used_memory += bytes;
+        self.db_memory.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn sub_memory(&mut self, bytes: usize) {
+        self.used_memory -= bytes;
+        self.db_memory.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    /// 预留至少 `additional` 个键值对的空间，批量写入前调用避免多次扩容
+    fn reserve(&mut self, additional: usize) {
+        Arc::make_mut(&mut self.entries).reserve(additional);
+    }
+
+    /// 放回 [`Shard::take`] 取出的键值对，保留原来的版本号
+    fn restore(&mut self, key: &str, entry: Entry) {
+        self.add_memory(entry.memory_usage(key));
+        Arc::make_mut(&mut self.entries).insert(key.into(), entry);
+    }
+
+    /// 暂时取出键值对，之后放回或者由 [`Shard::forget`] 记录为删除
+    ///
+    /// SCAN 索引中保留 key，放回时不需要重新登记。
+    fn take(&mut self, key: &str) -> Option<Entry> {
+        let entry = Arc::make_mut(&mut self.entries).remove(key)?;
+        self.sub_memory(entry.memory_usage(key));
+        Some(entry)
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.take(key)?;
+        self.forget(key);
+        Some(entry)
+    }
+
+    /// 记录 key 已被删除：移出 SCAN 索引并分配新的版本号
+    fn forget(&mut self, key: &str) {
+        self.scan.remove(key);
+        self.record_removal();
+    }
+
+    /// 删除 key 后分配一个新的版本号，分片中不存在的 key 的版本号随之变化
+    ///
+    /// 版本号不会回到之前的值：key 被创建后又被删除，版本号也和创建之前不同。
+    fn record_removal(&mut self) {
+        self.version += 1;
+        self.removed = self.version;
+    }
+
+    /// 写入值并登记过期时间，返回是否需要唤醒后台清理任务，见 [`ExpireIndex::insert`]
+    fn set(&mut self, key: String, value: Value, expires_at: Option<Instant>) -> bool {
+        let wake = expires_at.is_some_and(|when| self.expirations.insert(&key, when));
+        // 覆盖旧值时，旧值在时间轮中的元素留到清理时再过滤
+        self.insert(
+            key,
+            Entry {
+                value,
+                expires_at,
+                accessed: AccessTime::now(),
+                version: 0,
+            },
+        );
+        self.compact_expirations();
+        wake
+    }
+
+    /// key 已过期时立即删除，返回是否发生了删除
+    fn remove_if_expired(&mut self, key: &str, now: Instant) -> bool {
+        match self.entries.get(key) {
+            Some(entry) if entry.is_expired(now) => self.remove(key).is_some(),
+            _ => false,
+        }
+    }
+}
+
+/// [`Db::update`] 暂时取出的键值对，`f` panic 时在 drop 中放回
+struct Taken<'a> {
+    shard: &'a mut Shard,
+    key: &'a str,
+    slot: Slot,
+    expires_at: Option<Instant>,
+    /// 取出的键值对的访问时间和版本号，key 不存在时为 None
+    old: Option<(AccessTime, u64)>,
+}
+
+impl<'a> Taken<'a> {
+    fn new(shard: &'a mut Shard, key: &'a str) -> Taken<'a> {
+        let (value, expires_at, old) = match shard.take(key) {
+            Some(entry) => (
+                Some(entry.value),
+                entry.expires_at,
+                Some((entry.accessed, entry.version)),
+            ),
+            None => (None, None, None),
+        };
+        Taken {
+            shard,
+            key,
+            slot: Slot::new(value),
+            expires_at,
+            old,
+        }
+    }
+
+    /// `f` 返回后由调用方决定如何放回，`f` 失败时取回原来的值
+    fn finish(
+        mut self,
+        succeeded: bool,
+    ) -> (Option<Value>, Option<Instant>, Option<(AccessTime, u64)>) {
+        let value = if succeeded {
+            self.slot.commit()
+        } else {
+            self.slot.rollback()
+        };
+        (value, self.expires_at, self.old.take())
+    }
+}
+
+impl Drop for Taken<'_> {
+    fn drop(&mut self) {
+        // `f` panic 时放回原来的值
+        if let (Some(value), Some((accessed, version))) = (self.slot.rollback(), self.old.take()) {
+            self.shard.restore(
+                self.key,
+                Entry {
+                    value,
+                    expires_at: self.expires_at,
+                    accessed,
+                    version,
+                },
+            );
+        }
+    }
+}
+
+// 手动实现 Clone，派生宏会要求 B: Clone
+impl<B: Backend> Clone for Db<B> {
+    fn clone(&self) -> Db<B> {
+        Db {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl Default for Db {
+    fn default() -> Db {
+        Db::new()
+    }
+}
+
+impl Db {
+    /// 分片数量默认为 CPU 核数的 4 倍，降低不同 key 落到同一分片的概率
+    pub fn new() -> Db {
+        let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+        Db::with_shards(cpus * 4)
+    }
+
+    /// 指定分片数量创建 Db，`shards` 为 1 时退化为单把全局锁
+    ///
+    /// ## Panics
+    ///
+    /// `shards` 为 0 时会 panic
+    pub fn with_shards(shards: usize) -> Db {
+        Db::with_backend(Striped::new(shards))
+    }
+}
+
+impl<B: Backend> Db<B> {
+    pub fn with_backend(backend: B) -> Db<B> {
+        let used_memory = Arc::new(AtomicUsize::new(0));
+        for index in 0..backend.shard_count() {
+            let mut shard = backend.write(index);
+            used_memory.fetch_add(shard.used_memory, Ordering::Relaxed);
+            shard.db_memory = Arc::clone(&used_memory);
+        }
+        Db {
+            shared: Arc::new(Shared {
+                backend,
+                hasher: KeyHasher::default(),
+                background_task: Notify::new(),
+                shutdown: AtomicBool::new(false),
+                max_memory: AtomicUsize::new(0),
+                max_key_len: AtomicUsize::new(DEFAULT_MAX_KEY_LEN),
+                max_value_size: AtomicUsize::new(DEFAULT_MAX_VALUE_SIZE),
+                defrag_ratio: AtomicUsize::new(DEFAULT_DEFRAG_RATIO),
+                eviction: RwLock::new(Box::new(evict::NoEviction)),
+                next_eviction: AtomicUsize::new(0),
+                rdb_path: RwLock::new(PathBuf::from("dump.rdb")),
+                saving: AtomicBool::new(false),
+                aof: RwLock::new(None),
+                rewriting: AtomicBool::new(false),
+                events: broadcast::channel(notify::CAPACITY).0,
+                expire_mode: RwLock::new(ExpireMode::default()),
+                storage: RwLock::new(None),
+                op_log: RwLock::new((Arc::new(OpLog::new()), 0)),
+                used_memory,
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                expired: AtomicU64::new(0),
+                evicted: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shared.backend.shard_count()
+    }
+
+    fn shard_index(&self, key: &str) -> usize {
+        shard_index(&self.shared.hasher, key, self.shard_count())
+    }
+
+    /// 读取字符串值，已过期的 key 视为不存在，其他类型的值返回 [`DbError::WrongType`]
+    pub fn get(&self, key: &str) -> Result<Option<Bytes>, DbError> {
+        // 堆上的值 clone 只是增加引用计数，内联的短值和整数需要复制
+        self.view(key, |value| value.map(Value::to_string_bytes).transpose())
+    }
+
+    /// 只读访问 key 对应的值，key 不存在时传入 None
+    ///
+    /// 过期的 key 在访问时就地删除，不依赖后台任务的清理时机。`f` 执行期间持有分片的读锁。
+    /// 每次访问都计入命中或未命中次数，见 [`Db::keyspace_stats`]。
+    pub fn view<R>(&self, key: &str, f: impl FnOnce(Option<&Value>) -> R) -> R {
+        let index = self.shard_index(key);
+        let now = Instant::now();
+        {
+            let shard = self.shared.backend.read(index);
+            match shard.entries.get(key) {
+                None => {
+                    self.shared.misses.fetch_add(1, Ordering::Relaxed);
+                    return f(None);
+                }
+                Some(entry) if !entry.is_expired(now) => {
+                    self.shared.hits.fetch_add(1, Ordering::Relaxed);
+                    entry.accessed.touch();
+                    return f(Some(&entry.value));
+                }
+                Some(_) => {}
+            }
+        }
+        self.shared.misses.fetch_add(1, Ordering::Relaxed);
+
+        // 读锁下不能修改分片，换成写锁后再删除（期间可能已被其他连接重新写入，所以要再检查一次）
+        let mut shard = self.shared.backend.write(index);
+        let expired = self.shared.remove_if_expired(&mut shard, key, now);
+        drop(shard);
+        if expired {
+            self.shared.notify(key, Event::Expired);
+        }
+        f(None)
+    }
+
+    /// key 的版本号，key 被写入、修改、删除或过期后都会变化
+    ///
+    /// 执行事务前记下版本号，提交时再比较，就能知道 key 是否被其他连接修改过。
+    /// 不存在的 key 使用分片最近一次删除的版本号：同一分片中删除其他 key 也会改变它，
+    /// 但在这期间创建又删除这个 key 时，版本号一定与之前不同。过期的 key 在这里就地删除。
+    pub fn version(&self, key: &str) -> u64 {
+        let mut shard = self.shared.backend.write(self.shard_index(key));
+        let expired = self
+            .shared
+            .remove_if_expired(&mut shard, key, Instant::now());
+        let version = shard
+            .entries
+            .get(key)
+            .map_or(shard.removed, |entry| entry.version);
+        drop(shard);
+        if expired {
+            self.shared.notify(key, Event::Expired);
+        }
+        version
+    }
+
+    /// 修改 key 对应的值
+    ///
+    /// key 不存在时 `f` 收到空的 [`Slot`]，[`Slot::set`] 会创建 key，[`Slot::remove`] 会删除 key。
+    /// 修改已有的值不会改变它的过期时间。和 [`Db::set`] 一样，执行前会检查 key 的长度和 maxmemory。
+    ///
+    /// 删除 key 时会自动发布 [`Event::Del`]，原地修改的事件由调用方通过 [`Db::notify`] 发布。
+    ///
+    /// `f` 返回错误或者 panic 时放回原来的值：`f` 新建的 key 被丢弃，删除或替换的值被放回，
+    /// 版本号不变，WATCH 这个 key 的事务不受失败的命令影响。原地修改无法撤销，见 [`Slot`]。
+    pub fn update<R>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&mut Slot) -> Result<R, DbError>,
+    ) -> Result<R, DbError> {
+        self.shared.check_key(key)?;
+        self.shared.evict_if_needed()?;
+
+        let mut shard = self.shared.backend.write(self.shard_index(key));
+        let expired = self
+            .shared
+            .remove_if_expired(&mut shard, key, Instant::now());
+        // 先取出再放回，放回时会重新计算值的内存占用
+        let mut taken = Taken::new(&mut shard, key);
+        let existed = !taken.slot.is_none();
+        let result = f(&mut taken.slot);
+        let (value, expires_at, old) = taken.finish(result.is_ok());
+        let deleted = existed && value.is_none();
+        match (value, old) {
+            (Some(value), Some((accessed, version))) if result.is_err() => {
+                accessed.touch();
+                shard.restore(
+                    key,
+                    Entry {
+                        value,
+                        expires_at,
+                        accessed,
+                        version,
+                    },
+                );
+            }
+            (Some(value), _) => {
+                shard.insert(
+                    key,
+                    Entry {
+                        value,
+                        expires_at,
+                        accessed: AccessTime::now(),
+                        version: 0,
+                    },
+                );
+            }
+            (None, Some(_)) => shard.forget(key),
+            (None, None) => {}
+        }
+        self.shared.reserve_op();
+
+        drop(shard);
+        if expired {
+            self.shared.notify(key, Event::Expired);
+        }
+        if deleted {
+            self.shared.notify(key, Event::Del);
+        }
+        result
+    }
+
+    /// 写入字符串值，`expire` 为 None 时永不过期；已存在的 key 不论什么类型都会被覆盖
+    ///
+    /// 设置了 maxmemory 时，写入前会先按淘汰策略腾出空间，无法腾出时返回 [`DbError::OutOfMemory`]。
+    /// key 或值超过长度限制时返回 [`DbError::KeyTooLong`] 或 [`DbError::ValueTooLarge`]，
+    /// 过期时刻超出 [`Instant`] 的范围时返回 [`DbError::InvalidExpire`]。
+    pub fn set(&self, key: String, value: Bytes, expire: Option<Duration>) -> Result<(), DbError> {
+        let expires_at = match expire {
+            Some(d) => Some(
+                Instant::now()
+                    .checked_add(d)
+                    .ok_or(DbError::InvalidExpire)?,
+            ),
+            None => None,
+        };
+        self.shared.check_key(&key)?;
+        self.shared.check_value_size(value.len())?;
+        self.shared.evict_if_needed()?;
+        self.insert(key.clone(), Value::from(value), expires_at);
+        self.shared.notify(&key, Event::Set);
+        Ok(())
+    }
+
+    /// 把 key 中的整数加上 `delta` 并返回结果，key 不存在时视为 0，对应 INCRBY/DECRBY 命令
+    ///
+    /// 保留原有的过期时间。值不是整数时返回 [`DbError::NotInteger`]，溢出时返回 [`DbError::Overflow`]。
+    pub fn incr_by(&self, key: &str, delta: i64) -> Result<i64, DbError> {
+        let n = self.update(key, |value| {
+            let current = match value.get() {
+                Some(value) => value.as_int()?,
+                None => 0,
+            };
+            let n = current.checked_add(delta).ok_or(DbError::Overflow)?;
+            value.set(Value::Int(n));
+            Ok(n)
+        })?;
+        self.shared.notify(key, Event::Changed("incrby"));
+        Ok(n)
+    }
+
+    /// 写入值并登记过期时间，不检查 maxmemory
+    fn insert(&self, key: String, value: Value, expires_at: Option<Instant>) {
+        let mut shard = self.shared.backend.write(self.shard_index(&key));
+        let wake = shard.set(key, value, expires_at);
+        self.shared.reserve_op();
+
+        // 先释放分片锁，避免后台任务被唤醒后立即阻塞在锁上
+        drop(shard);
+        if wake {
+            self.shared.background_task.notify_one();
+        }
+    }
+
+    /// 删除 key，返回实际删除的数量，已经过期的 key 同样会被删除，但不计入删除数量
+    ///
+    /// 同时锁住所有 key 所在的分片，对其他命令来说一次删除多个 key 是原子的。
+    /// 只会减少内存占用，不检查 maxmemory。
+    pub fn del(&self, keys: &[String]) -> usize {
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        self.lock_keys(&keys, |locked| {
+            keys.iter()
+                .filter(|key| locked.remove(key).is_some())
+                .count()
+        })
+    }
+
+    /// 估算 key 占用的内存，key 不存在时返回 None
+    ///
+    /// 集合类型只计算前 `samples` 个元素再按元素个数放大，为 0 时计算所有元素。
+    pub fn memory_usage(&self, key: &str, samples: usize) -> Option<usize> {
+        let index = self.shard_index(key);
+        let shard = self.shared.backend.read(index);
+        let entry = shard.entries.get(key)?;
+        if entry.is_expired(Instant::now()) {
+            return None;
+        }
+        Some(entry.memory_usage_sampled(key, samples))
+    }
+
+    /// 估算的内存占用，单位为字节
+    pub fn used_memory(&self) -> usize {
+        self.shared.used_memory()
+    }
+
+    /// 设置内存上限，0 表示不限制
+    pub fn set_max_memory(&self, bytes: usize) {
+        self.shared.max_memory.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn max_memory(&self) -> usize {
+        self.shared.max_memory.load(Ordering::Relaxed)
+    }
+
+    pub fn rdb_path(&self) -> PathBuf {
+        self.shared.rdb_path.read().unwrap().clone()
+    }
+
+    /// 设置 SAVE/BGSAVE 写入的文件，默认为当前目录下的 `dump.rdb`
+    pub fn set_rdb_path(&self, path: impl Into<PathBuf>) {
+        *self.shared.rdb_path.write().unwrap() = path.into();
+    }
+
+    /// 设置超出内存上限时的淘汰策略，默认为 [`evict::NoEviction`]
+    pub fn set_eviction_policy(&self, policy: Box<dyn EvictionPolicy>) {
+        *self.shared.eviction.write().unwrap() = policy;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::*;
+
+    #[test]
+    fn clones_share_the_keyspace() {
+        let db = Db::new();
+        let other = db.clone();
+        thread::spawn(move || {
+            other
+                .set("a".into(), Bytes::from_static(b"1"), None)
+                .unwrap();
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(db.get("a"), Ok(Some(Bytes::from_static(b"1"))));
+        assert_eq!(db.clone().del(&["a".to_string(), "b".to_string()]), 1);
+        assert_eq!(db.get("a"), Ok(None));
+    }
+
+    #[test]
+    fn locked_shard_does_not_block_other_shards() {
+        let db = Db::with_shards(4);
+        let keys: Vec<String> = (0..100).map(|i| format!("k{}", i)).collect();
+        let locked = db.shard_index(&keys[0]);
+        let other = keys
+            .iter()
+            .find(|key| db.shard_index(key) != locked)
+            .unwrap()
+            .clone();
+
+        let _guard = db.shared.backend.write(locked);
+        let (tx, rx) = std::sync::mpsc::channel();
+        let writer = db.clone();
+        thread::spawn(move || {
+            writer.set(other, Bytes::new(), None).unwrap();
+            tx.send(()).unwrap();
+        });
+        assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn purge_task_removes_expired_keys() {
+        let guard = DbDropGuard::new();
+        let db = guard.db();
+        db.set(
+            "a".into(),
+            Bytes::from_static(b"1"),
+            Some(Duration::from_millis(20)),
+        )
+        .unwrap();
+        db.set("b".into(), Bytes::from_static(b"2"), None).unwrap();
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(db.get("a"), Ok(None));
+        assert_eq!(db.get("b"), Ok(Some(Bytes::from_static(b"2"))));
+    }
+
+    #[tokio::test]
+    async fn keys_live_and_expire_in_their_own_shard() {
+        let guard = DbDropGuard::with_db(Db::with_shards(4));
+        let db = guard.db();
+        for i in 0..100 {
+            db.set(
+                format!("k{}", i),
+                Bytes::new(),
+                Some(Duration::from_millis(20)),
+            )
+            .unwrap();
+        }
+
+        for index in 0..db.shard_count() {
+            let shard = db.shared.backend.read(index);
+            // 哈希取模后每个分片都分到了 key，过期时间登记在 key 所在的分片中
+            assert!(!shard.entries.is_empty());
+            assert!(shard.entries.keys().all(|key| db.shard_index(key) == index));
+            let ExpireIndex::Wheel(wheel) = &shard.expirations else {
+                panic!("expected the timer wheel");
+            };
+            assert_eq!(wheel.len(), shard.entries.len());
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        for index in 0..db.shard_count() {
+            assert!(db.shared.backend.read(index).entries.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn refreshing_ttls_does_not_grow_the_wheel() {
+        let guard = DbDropGuard::with_db(Db::with_shards(1));
+        let db = guard.db();
+        for i in 0..10_000 {
+            let ttl = Duration::from_secs(3600) + Duration::from_millis(i);
+            db.set("a".into(), Bytes::new(), Some(ttl)).unwrap();
+        }
+        db.set("b".into(), Bytes::new(), Some(Duration::from_millis(20)))
+            .unwrap();
+
+        {
+            let shard = db.shared.backend.read(0);
+            let ExpireIndex::Wheel(wheel) = &shard.expirations else {
+                panic!("expected the timer wheel");
+            };
+            assert!(wheel.len() <= 2 * shard.entries.len() + 1024);
+        }
+        // 重建后的索引仍然包含所有 key 的过期时间，到期的 key 由后台任务删除
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let shard = db.shared.backend.read(0);
+        assert!(!shard.entries.contains_key("b"));
+        assert!(shard.entries.contains_key("a"));
+    }
+
+    #[tokio::test]
+    async fn sampling_mode_removes_expired_keys() {
+        let guard = DbDropGuard::with_db(Db::with_shards(2));
+        let db = guard.db();
+        db.set(
+            "a".into(),
+            Bytes::from_static(b"1"),
+            Some(Duration::from_millis(20)),
+        )
+        .unwrap();
+        db.set_expire_mode(ExpireMode::Sampling);
+        for i in 0..10 {
+            db.set(format!("k{}", i), Bytes::new(), Some(Duration::ZERO))
+                .unwrap();
+        }
+        db.set("b".into(), Bytes::from_static(b"2"), None).unwrap();
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        // 不经过读取，直接检查分片中是否还有过期的 key
+        let remaining: usize = (0..db.shard_count())
+            .map(|index| db.shared.backend.read(index).entries.len())
+            .sum();
+        assert_eq!(remaining, 1);
+        assert_eq!(db.get("b"), Ok(Some(Bytes::from_static(b"2"))));
+    }
+
+    #[test]
+    fn setting_the_same_expire_mode_keeps_the_index() {
+        let db = Db::with_shards(1);
+        let ttl = Some(Duration::from_secs(60));
+        db.set("a".into(), Bytes::new(), ttl).unwrap();
+        db.set("a".into(), Bytes::new(), ttl).unwrap();
+        let wheel_len = |db: &Db| match &db.shared.backend.read(0).expirations {
+            ExpireIndex::Wheel(wheel) => wheel.len(),
+            ExpireIndex::Sampled(_) => panic!("expected the timer wheel"),
+        };
+        assert_eq!(wheel_len(&db), 2);
+
+        // 模式没有变化，不重建索引，覆盖留下的旧元素仍在时间轮中
+        db.set_expire_mode(ExpireMode::Deadline);
+        assert_eq!(wheel_len(&db), 2);
+
+        db.set_expire_mode(ExpireMode::Sampling);
+        db.set_expire_mode(ExpireMode::Deadline);
+        assert_eq!(wheel_len(&db), 1);
+    }
+
+    #[test]
+    fn versions_change_on_every_mutation() {
+        let db = Db::new();
+        assert_eq!(db.version("a"), 0);
+        db.set("a".into(), Bytes::from_static(b"1"), None).unwrap();
+        let v1 = db.version("a");
+        db.get("a").unwrap();
+        assert_eq!(db.version("a"), v1);
+
+        db.incr_by("a", 1).unwrap();
+        let v2 = db.version("a");
+        assert!(v2 > v1);
+        db.del(&["a".to_string()]);
+        let v3 = db.version("a");
+        assert!(v3 > v2);
+        db.set("a".into(), Bytes::from_static(b"1"), None).unwrap();
+        assert!(db.version("a") > v3);
+    }
+
+    #[test]
+    fn panic_while_holding_a_shard_lock_does_not_poison_it() {
+        let db = Db::with_shards(1);
+        db.set("a".into(), Bytes::from_static(b"1"), None).unwrap();
+
+        let view = panic::catch_unwind(AssertUnwindSafe(|| db.view("a", |_| panic!("view"))));
+        assert!(view.is_err());
+        let update = panic::catch_unwind(AssertUnwindSafe(|| {
+            db.update("b", |_| -> Result<(), DbError> { panic!("update") })
+        }));
+        assert!(update.is_err());
+
+        // 其他线程（其他连接）仍然可以访问同一个分片
+        let other = db.clone();
+        thread::spawn(move || {
+            assert_eq!(other.get("a"), Ok(Some(Bytes::from_static(b"1"))));
+            other
+                .set("b".into(), Bytes::from_static(b"2"), None)
+                .unwrap();
+        })
+        .join()
+        .unwrap();
+        assert_eq!(db.get("b"), Ok(Some(Bytes::from_static(b"2"))));
+    }
+
+    #[test]
+    fn panic_in_update_keeps_the_entry() {
+        let db = Db::with_shards(1);
+        db.set(
+            "a".into(),
+            Bytes::from_static(b"1"),
+            Some(Duration::from_secs(60)),
+        )
+        .unwrap();
+        let version = db.version("a");
+        let memory = db.shared.backend.read(0).used_memory;
+
+        let update = panic::catch_unwind(AssertUnwindSafe(|| {
+            db.update("a", |_| -> Result<(), DbError> { panic!("update") })
+        }));
+        assert!(update.is_err());
+
+        assert_eq!(db.get("a"), Ok(Some(Bytes::from_static(b"1"))));
+        assert_eq!(db.version("a"), version);
+        let shard = db.shared.backend.read(0);
+        assert_eq!(shard.used_memory, memory);
+        assert!(shard.entries["a"].expires_at.is_some());
+    }
+
+    #[test]
+    fn failed_update_does_not_create_the_key() {
+        let db = Db::with_shards(1);
+        let version = db.version("a");
+        let mut events = db.subscribe();
+
+        let result = db.update("a", |value| {
+            value.set(Value::Int(1));
+            Err::<(), _>(DbError::Overflow)
+        });
+        assert_eq!(result, Err(DbError::Overflow));
+
+        assert_eq!(db.get("a"), Ok(None));
+        assert_eq!(db.version("a"), version);
+        assert_eq!(db.shared.backend.read(0).used_memory, 0);
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn failed_update_does_not_delete_the_key() {
+        let db = Db::with_shards(1);
+        db.set(
+            "a".into(),
+            Bytes::from_static(b"1"),
+            Some(Duration::from_secs(60)),
+        )
+        .unwrap();
+        let version = db.version("a");
+        let memory = db.shared.backend.read(0).used_memory;
+        let mut events = db.subscribe();
+
+        let result = db.update("a", |value| {
+            value.remove();
+            Err::<(), _>(DbError::WrongType)
+        });
+        assert_eq!(result, Err(DbError::WrongType));
+
+        assert_eq!(db.get("a"), Ok(Some(Bytes::from_static(b"1"))));
+        assert_eq!(db.version("a"), version);
+        let shard = db.shared.backend.read(0);
+        assert_eq!(shard.used_memory, memory);
+        assert!(shard.entries["a"].expires_at.is_some());
+        drop(shard);
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn drop_guard_stops_background_tasks() {
+        let path = std::env::temp_dir().join(format!("ilearn-guard-{}.aof", std::process::id()));
+        let guard = DbDropGuard::new();
+        let db = guard.db();
+        db.enable_aof(&path, aof::Fsync::EverySec).await.unwrap();
+
+        drop(guard);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        // 清理任务已经退出并释放了它持有的引用
+        assert_eq!(Arc::strong_count(&db.shared), 1);
+        assert_eq!(db.aof_path(), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn expired_keys_are_missing_without_purge_task() {
+        let db = Db::new();
+        db.set("a".into(), Bytes::from_static(b"1"), Some(Duration::ZERO))
+            .unwrap();
+        db.set("b".into(), Bytes::from_static(b"2"), Some(Duration::ZERO))
+            .unwrap();
+
+        assert_eq!(db.get("a"), Ok(None));
+        assert_eq!(db.del(&["b".to_string()]), 0);
+    }
+
+    #[test]
+    fn evicts_keys_when_over_max_memory() {
+        let db = Db::with_shards(1);
+        let value = Bytes::from(vec![0; 100]);
+        db.set("a".into(), value.clone(), None).unwrap();
+        db.set_max_memory(db.used_memory());
+
+        // 默认不淘汰，超出限制后的写入失败
+        db.set("b".into(), value.clone(), None).unwrap();
+        assert_eq!(
+            db.set("c".into(), value.clone(), None),
+            Err(DbError::OutOfMemory)
+        );
+
+        db.set_eviction_policy(evict::policy_from_name("allkeys-lru").unwrap());
+        thread::sleep(Duration::from_millis(5));
+        db.get("a").unwrap();
+        db.set("c".into(), value, None).unwrap();
+        assert!(db.get("a").unwrap().is_some());
+        assert!(db.get("b").unwrap().is_none());
+    }
+
+    #[test]
+    fn shards_are_picked_with_the_key_hasher_of_each_db() {
+        let (a, b) = (Db::with_shards(16), Db::with_shards(16));
+        let keys: Vec<String> = (0..200).map(|i| format!("k{}", i)).collect();
+        for key in &keys {
+            a.set(key.clone(), Bytes::new(), None).unwrap();
+        }
+        assert!((0..a.shard_count()).all(|index| !a.shared.backend.read(index).entries.is_empty()));
+        let snapshot = a.snapshot();
+        assert!(keys.iter().all(|key| snapshot.get(key).is_some()));
+
+        // 有种子的算法下每个 Db 的种子不同，同一个 key 所在的分片也不同
+        let moved = keys
+            .iter()
+            .filter(|key| a.shard_index(key) != b.shard_index(key))
+            .count();
+        if hasher::NAME == "fxhash" {
+            assert_eq!(moved, 0);
+        } else {
+            assert!(moved > 0);
+        }
+    }
+
+    #[test]
+    fn used_memory_is_the_sum_of_all_shards() {
+        let db = Db::with_shards(4);
+        let shard_sum = |db: &Db| -> usize {
+            (0..db.shard_count())
+                .map(|index| db.shared.backend.read(index).used_memory)
+                .sum()
+        };
+        for i in 0..100 {
+            db.set(format!("k{}", i), Bytes::from(vec![0; i]), None)
+                .unwrap();
+        }
+        db.set("k0".into(), Bytes::from(vec![0; 1000]), None)
+            .unwrap();
+        db.incr_by("n", 1).unwrap();
+        db.del(&["k1".to_string(), "k2".to_string()]);
+        assert!(db.used_memory() > 0);
+        assert_eq!(db.used_memory(), shard_sum(&db));
+
+        db.clear();
+        assert_eq!(db.used_memory(), 0);
+    }
+
+    #[test]
+    fn commands_on_wrong_type_fail() {
+        let db = Db::new();
+        db.update("s", |value| {
+            value.set(Value::Stream(Default::default()));
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(db.get("s"), Err(DbError::WrongType));
+        // SET 会覆盖任何类型的值
+        db.set("s".into(), Bytes::from_static(b"1"), None).unwrap();
+        assert_eq!(db.get("s"), Ok(Some(Bytes::from_static(b"1"))));
+    }
+
+    #[test]
+    fn integers_are_stored_as_int() {
+        let db = Db::new();
+        db.set("n".into(), Bytes::from_static(b"10"), None).unwrap();
+        db.set("z".into(), Bytes::from_static(b"010"), None)
+            .unwrap();
+        assert_eq!(db.view("n", |v| v.unwrap().encoding()), "int");
+        assert_eq!(db.view("z", |v| v.unwrap().encoding()), "embstr");
+
+        assert_eq!(db.incr_by("n", -15), Ok(-5));
+        assert_eq!(db.incr_by("new", 1), Ok(1));
+        assert_eq!(db.get("n"), Ok(Some(Bytes::from_static(b"-5"))));
+        assert_eq!(db.incr_by("z", 1), Err(DbError::NotInteger));
+        assert_eq!(db.incr_by("n", i64::MIN), Err(DbError::Overflow));
+    }
+}
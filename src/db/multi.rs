@@ -0,0 +1,211 @@
+//! 同时修改多个 key 的原子操作
+//!
+//! MSET、RENAME 这类命令涉及的 key 可能分布在不同的分片上。
+//! [`Db::with_keys`] 按分片下标从小到大依次加写锁，所有多 key 操作都遵循同一个顺序，
+//! 单 key 操作同一时刻只持有一把锁，因此不会出现循环等待。
+
+use std::time::Instant;
+
+use super::{shard_index, Backend, Db, DbError, Event, KeyHasher, Shard, Value};
+
+/// 已经加锁的一组 key，只能访问传给 [`Db::with_keys`] 的 key
+pub struct LockedKeys<'a, B: Backend + 'a> {
+    /// 按分片下标排序
+    shards: Vec<(usize, B::WriteGuard<'a>)>,
+    hasher: &'a KeyHasher,
+    shard_count: usize,
+    /// 释放锁之后再发布的通知
+    events: Vec<(String, Event)>,
+    /// 是否需要唤醒后台清理任务
+    wake: bool,
+}
+
+impl<'a, B: Backend> LockedKeys<'a, B> {
+    /// ## Panics
+    ///
+    /// key 所在的分片没有被加锁时会 panic
+    fn shard(&self, key: &str) -> &Shard {
+        let index = shard_index(self.hasher, key, self.shard_count);
+        match self.shards.binary_search_by_key(&index, |(i, _)| *i) {
+            Ok(pos) => &self.shards[pos].1,
+            Err(_) => panic!("key `{}` is not locked by with_keys", key),
+        }
+    }
+
+    fn shard_mut(&mut self, key: &str) -> &mut Shard {
+        let index = shard_index(self.hasher, key, self.shard_count);
+        match self.shards.binary_search_by_key(&index, |(i, _)| *i) {
+            Ok(pos) => &mut self.shards[pos].1,
+            Err(_) => panic!("key `{}` is not locked by with_keys", key),
+        }
+    }
+
+    /// 读取 key 对应的值，过期的 key 在加锁时已经被删除
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        let entry = self.shard(key).entries.get(key)?;
+        entry.accessed.touch();
+        Some(&entry.value)
+    }
+
+    pub fn expires_at(&self, key: &str) -> Option<Instant> {
+        self.shard(key).entries.get(key)?.expires_at
+    }
+
+    /// key 的版本号，不存在时为分片最近一次删除的版本号，见 [`Db::version`]
+    pub fn version(&self, key: &str) -> u64 {
+        let shard = self.shard(key);
+        shard
+            .entries
+            .get(key)
+            .map_or(shard.removed, |entry| entry.version)
+    }
+
+    /// 写入值，已存在的 key 会被覆盖
+    pub fn insert(&mut self, key: &str, value: Value, expires_at: Option<Instant>) {
+        let wake = self.shard_mut(key).set(key.to_string(), value, expires_at);
+        self.wake |= wake;
+        self.events.push((key.to_string(), Event::Set));
+    }
+
+    /// 删除 key，返回被删除的值
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        let entry = self.shard_mut(key).remove(key)?;
+        self.events.push((key.to_string(), Event::Del));
+        Some(entry.value)
+    }
+}
+
+impl<B: Backend> Db<B> {
+    /// 同时锁住多个 key 所在的分片并执行 `f`，`f` 中的修改对其他连接来说是原子的
+    ///
+    /// `f` 只能访问 `keys` 中的 key。和 [`Db::update`] 一样，执行前会检查 key 的长度和 maxmemory；
+    /// 变更通知在释放锁之后发布。
+    pub fn with_keys<R>(
+        &self,
+        keys: &[&str],
+        f: impl FnOnce(&mut LockedKeys<'_, B>) -> Result<R, DbError>,
+    ) -> Result<R, DbError> {
+        for key in keys {
+            self.shared.check_key(key)?;
+        }
+        self.shared.evict_if_needed()?;
+        self.lock_keys(keys, f)
+    }
+
+    /// 与 [`Db::with_keys`] 相同，但不检查 key 的长度和 maxmemory，供只删除数据的 DEL 使用
+    ///
+    /// 命令在 [`Db::record`] 中执行时，在释放锁之前预留操作日志的序号。
+    pub(super) fn lock_keys<R>(
+        &self,
+        keys: &[&str],
+        f: impl FnOnce(&mut LockedKeys<'_, B>) -> R,
+    ) -> R {
+        let shard_count = self.shard_count();
+        let hasher = &self.shared.hasher;
+        let mut indices: Vec<_> = keys
+            .iter()
+            .map(|k| shard_index(hasher, k, shard_count))
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        let mut locked = LockedKeys {
+            shards: indices
+                .into_iter()
+                .map(|index| (index, self.shared.backend.write(index)))
+                .collect(),
+            hasher,
+            shard_count,
+            events: vec![],
+            wake: false,
+        };
+
+        let now = Instant::now();
+        for key in keys {
+            if self
+                .shared
+                .remove_if_expired(locked.shard_mut(key), key, now)
+            {
+                locked.events.push((key.to_string(), Event::Expired));
+            }
+        }
+        let result = f(&mut locked);
+        self.shared.reserve_op();
+
+        let LockedKeys {
+            shards,
+            events,
+            wake,
+            ..
+        } = locked;
+        drop(shards);
+        if wake {
+            self.shared.background_task.notify_one();
+        }
+        for (key, event) in events {
+            self.shared.notify(&key, event);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use bytes::Bytes;
+
+    use super::*;
+
+    fn concurrent_updates<B: Backend>(db: Db<B>) {
+        let db = Arc::new(db);
+        let keys: Vec<String> = (0..16).map(|i| format!("k{}", i)).collect();
+
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let db = Arc::clone(&db);
+                let mut keys = keys.clone();
+                // 每个线程以不同的顺序传入 key
+                keys.rotate_left(t * 3);
+                thread::spawn(move || {
+                    let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+                    for _ in 0..200 {
+                        db.with_keys(&keys, |locked| {
+                            for key in &keys {
+                                let n = match locked.get(key) {
+                                    Some(value) => value.as_int().unwrap(),
+                                    None => 0,
+                                };
+                                locked.insert(
+                                    key,
+                                    Value::from(Bytes::from((n + 1).to_string())),
+                                    None,
+                                );
+                            }
+                            Ok(())
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        for key in &keys {
+            assert_eq!(db.get(key), Ok(Some(Bytes::from_static(b"800"))));
+        }
+    }
+
+    #[test]
+    fn concurrent_multi_key_updates_do_not_deadlock() {
+        concurrent_updates(Db::with_shards(4));
+    }
+
+    #[cfg(feature = "dashmap")]
+    #[test]
+    fn dashmap_shards_do_not_share_locks() {
+        // 分片数不是 2 的幂时，DashMap 的段数多于分片数
+        concurrent_updates(Db::with_backend(crate::db::backend::DashMapBackend::new(6)));
+    }
+}
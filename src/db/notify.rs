@@ -0,0 +1,113 @@
+//! Db 内部的变更通知
+//!
+//! 每次修改 key 都会发布一条 [`Notification`]。阻塞命令、键空间通知、客户端缓存失效等功能
+//! 都订阅同一个通道，不需要各自在命令执行的路径上埋点。
+//!
+//! 覆盖写入、删除、过期和淘汰由 Db 自动发布；原地修改值的命令（如 XADD）
+//! 由命令在修改成功后调用 [`Db::notify`] 发布，事件名与 Redis 的键空间通知相同。
+
+use std::sync::atomic::Ordering;
+
+use tokio::sync::broadcast;
+
+use super::{Backend, Db, Shared};
+
+/// 通道容量，订阅者处理不及时时会丢失最早的通知，并在下一次接收时收到 `Lagged` 错误
+pub(super) const CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// 整个值被覆盖写入
+    Set,
+    /// 被命令删除
+    Del,
+    /// 到达过期时间被删除
+    Expired,
+    /// 超出 maxmemory 被淘汰
+    Evicted,
+    /// 值被命令原地修改，参数为事件名，如 `xadd`
+    Changed(&'static str),
+}
+
+impl Event {
+    /// 键空间通知中使用的事件名
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Set => "set",
+            Event::Del => "del",
+            Event::Expired => "expired",
+            Event::Evicted => "evicted",
+            Event::Changed(name) => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub key: String,
+    pub event: Event,
+}
+
+impl<B> Shared<B> {
+    pub(super) fn notify(&self, key: &str, event: Event) {
+        match event {
+            Event::Expired => self.expired.fetch_add(1, Ordering::Relaxed),
+            Event::Evicted => self.evicted.fetch_add(1, Ordering::Relaxed),
+            _ => 0,
+        };
+        // 没有订阅者时不必构造通知
+        if self.events.receiver_count() == 0 {
+            return;
+        }
+        let _ = self.events.send(Notification {
+            key: key.to_string(),
+            event,
+        });
+    }
+}
+
+impl<B: Backend> Db<B> {
+    /// 订阅所有 key 的变更通知，需要关注特定 key 的订阅者自行过滤
+    pub fn subscribe(&self) -> broadcast::Receiver<Notification> {
+        self.shared.events.subscribe()
+    }
+
+    /// 发布一条变更通知，供原地修改值的命令在修改成功后调用
+    pub fn notify(&self, key: &str, event: Event) {
+        self.shared.notify(key, event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn mutations_are_published() {
+        let db = Db::new();
+        let mut events = db.subscribe();
+
+        db.set("a".into(), Bytes::from_static(b"1"), None).unwrap();
+        db.set("b".into(), Bytes::from_static(b"2"), Some(Duration::ZERO))
+            .unwrap();
+        db.del(&["a".to_string()]);
+        db.get("b").unwrap();
+
+        let received: Vec<_> = std::iter::from_fn(|| events.try_recv().ok())
+            .map(|n| (n.key, n.event))
+            .collect();
+        assert_eq!(
+            received,
+            [
+                ("a".to_string(), Event::Set),
+                ("b".to_string(), Event::Set),
+                ("a".to_string(), Event::Del),
+                ("b".to_string(), Event::Expired),
+            ]
+        );
+    }
+}
@@ -0,0 +1,388 @@
+//! 写操作日志
+//!
+//! 写命令执行成功后追加到内存中的环形缓冲区，每条记录带有递增的序号和所属数据库的编号。
+//! AOF 写入任务、以后的复制等消费者各自持有一个 [`Tail`]，按自己的进度读取、互不影响，
+//! 命令执行的路径上只需要追加一次，不需要为每个消费者分别埋点。
+//! 过期和淘汰删除的 key 不经过命令，由 Db 以 DEL 命令记录，消费者看到的是完整的修改序列。
+//!
+//! 日志中的顺序必须与修改数据的顺序相同，否则重放的结果会与原来的数据不同。写命令在
+//! [`Db::record`] 中执行，第一次修改数据时在分片锁内预留序号，执行完再填入命令帧；
+//! 过期和淘汰的 DEL 同样在分片锁内追加。消费者读到还没有填入的记录时等待，
+//! 执行失败的命令取消预留的记录，消费者直接跳过。
+//!
+//! 多个数据库共享同一个日志，见 [`Db::set_op_log`]。
+//! 缓冲区满时丢弃最旧的记录，落后太多的消费者会收到 [`TailError::Lagged`]。
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use thiserror::Error;
+use tokio::sync::watch;
+
+use super::{Backend, Db, Shared};
+use crate::frame::Frame;
+
+/// 默认保留的记录条数
+pub const DEFAULT_CAPACITY: usize = 1 << 16;
+
+/// 一条写操作
+#[derive(Debug)]
+pub struct Op {
+    /// 从 1 开始递增的序号
+    pub seq: u64,
+    /// 所属数据库的编号
+    pub db: usize,
+    /// 需要重放的命令帧
+    pub frame: Frame,
+}
+
+#[derive(Debug)]
+pub struct OpLog {
+    ring: Mutex<Ring>,
+    /// 下一条记录的序号，追加后通知等待的消费者
+    appended: watch::Sender<u64>,
+}
+
+#[derive(Debug)]
+struct Ring {
+    ops: VecDeque<Slot>,
+    capacity: usize,
+    next_seq: u64,
+}
+
+/// 缓冲区中的一个位置
+#[derive(Debug)]
+enum Slot {
+    /// 已经预留了序号，命令还没有执行完
+    Reserved,
+    Filled(Arc<Op>),
+    /// 预留了序号但命令执行失败，没有需要重放的内容
+    Cancelled,
+}
+
+impl Ring {
+    /// 缓冲区中最旧一条记录的序号
+    fn first_seq(&self) -> u64 {
+        self.next_seq - self.ops.len() as u64
+    }
+
+    /// 分配下一个序号并放入 `slot` 生成的记录，缓冲区满时丢弃最旧的记录
+    fn push(&mut self, slot: impl FnOnce(u64) -> Slot) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if self.ops.len() == self.capacity {
+            self.ops.pop_front();
+        }
+        self.ops.push_back(slot(seq));
+        seq
+    }
+}
+
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum TailError {
+    /// 消费者落后太多，参数为被丢弃而没有读到的记录条数
+    #[error("op log consumer lagged behind by {0} ops")]
+    Lagged(u64),
+}
+
+impl Default for OpLog {
+    fn default() -> OpLog {
+        OpLog::new()
+    }
+}
+
+impl OpLog {
+    pub fn new() -> OpLog {
+        OpLog::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// 最多保留 `capacity` 条记录
+    ///
+    /// ## Panics
+    ///
+    /// `capacity` 为 0 时会 panic
+    pub fn with_capacity(capacity: usize) -> OpLog {
+        assert!(capacity > 0, "op log capacity must be positive");
+        OpLog {
+            ring: Mutex::new(Ring {
+                ops: VecDeque::new(),
+                capacity,
+                next_seq: 1,
+            }),
+            appended: watch::channel(1).0,
+        }
+    }
+
+    /// 追加一条记录，返回它的序号
+    pub fn append(&self, db: usize, frame: Frame) -> u64 {
+        let mut ring = self.ring.lock().unwrap();
+        let seq = ring.push(|seq| Slot::Filled(Arc::new(Op { seq, db, frame })));
+        drop(ring);
+        self.appended.send_replace(seq + 1);
+        seq
+    }
+
+    /// 预留下一个序号，之后由 [`OpLog::fill`] 填入记录，消费者读到这里时等待
+    fn reserve(&self) -> u64 {
+        self.ring.lock().unwrap().push(|_| Slot::Reserved)
+    }
+
+    /// 填入预留的记录，`op` 为 None 时取消，消费者跳过这个序号
+    ///
+    /// 落后太多、预留的位置已被丢弃时什么也不做。
+    fn fill(&self, seq: u64, op: Option<(usize, Frame)>) {
+        let mut ring = self.ring.lock().unwrap();
+        let Some(index) = seq.checked_sub(ring.first_seq()) else {
+            return;
+        };
+        if let Some(slot) = ring.ops.get_mut(index as usize) {
+            *slot = match op {
+                Some((db, frame)) => Slot::Filled(Arc::new(Op { seq, db, frame })),
+                None => Slot::Cancelled,
+            };
+        }
+        let next_seq = ring.next_seq;
+        drop(ring);
+        self.appended.send_replace(next_seq);
+    }
+
+    /// 最近一条记录（包括预留的）的序号，还没有记录时为 0
+    pub fn last_seq(&self) -> u64 {
+        self.ring.lock().unwrap().next_seq - 1
+    }
+
+    /// 从序号 `from` 开始读取，`last_seq() + 1` 表示只读取之后追加的记录
+    pub fn tail(self: &Arc<OpLog>, from: u64) -> Tail {
+        Tail {
+            log: Arc::clone(self),
+            next: from,
+            appended: self.appended.subscribe(),
+        }
+    }
+}
+
+/// 一个消费者的读取进度
+#[derive(Debug)]
+pub struct Tail {
+    log: Arc<OpLog>,
+    next: u64,
+    appended: watch::Receiver<u64>,
+}
+
+impl Tail {
+    /// 下一次读取的序号
+    pub fn next_seq(&self) -> u64 {
+        self.next
+    }
+
+    /// 跳过序号小于 `seq` 的记录
+    pub fn skip_to(&mut self, seq: u64) {
+        self.next = self.next.max(seq);
+    }
+
+    /// 读取下一条记录，没有新记录或者下一条记录还没有填入时返回 None
+    ///
+    /// 要读取的记录已被丢弃时返回 [`TailError::Lagged`]，之后从最旧的记录继续读取。
+    pub fn try_next(&mut self) -> Option<Result<Arc<Op>, TailError>> {
+        let ring = self.log.ring.lock().unwrap();
+        let first = ring.first_seq();
+        if self.next < first {
+            let missed = first - self.next;
+            self.next = first;
+            return Some(Err(TailError::Lagged(missed)));
+        }
+        loop {
+            match ring.ops.get((self.next - first) as usize)? {
+                Slot::Reserved => return None,
+                Slot::Cancelled => self.next += 1,
+                Slot::Filled(op) => {
+                    self.next += 1;
+                    return Some(Ok(Arc::clone(op)));
+                }
+            }
+        }
+    }
+
+    /// 读取下一条记录，没有新记录时等待追加
+    ///
+    /// 可以在 `select!` 中使用，取消后不会丢失记录。
+    pub async fn next(&mut self) -> Result<Arc<Op>, TailError> {
+        loop {
+            // 先标记已读再检查缓冲区，检查之后追加的记录一定会唤醒下面的等待
+            self.appended.borrow_and_update();
+            if let Some(result) = self.try_next() {
+                return result;
+            }
+            // `Tail` 持有日志，发送端不会被释放
+            let _ = self.appended.changed().await;
+        }
+    }
+}
+
+thread_local! {
+    /// 当前线程在 [`Db::record`] 中执行的写命令预留的记录，命令同步执行，不会在期间切换到其他连接
+    static RECORDING: RefCell<Option<Recording>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Default)]
+struct Recording {
+    reserved: Option<Reservation>,
+}
+
+/// 预留的记录
+#[derive(Debug)]
+struct Reservation {
+    log: Arc<OpLog>,
+    db: usize,
+    seq: u64,
+}
+
+impl Reservation {
+    /// 填入命令帧，None 表示取消
+    fn fill(self, frame: Option<Frame>) -> u64 {
+        self.log.fill(self.seq, frame.map(|frame| (self.db, frame)));
+        self.seq
+    }
+}
+
+impl<B> Shared<B> {
+    /// 以本数据库的编号追加一条记录
+    pub(super) fn log_op(&self, frame: Frame) -> u64 {
+        let (log, index) = &*self.op_log.read().unwrap();
+        log.append(*index, frame)
+    }
+
+    /// 记录过期或者淘汰删除的 key，必须在持有 key 所在分片的写锁时调用
+    pub(super) fn log_removal(&self, key: &str) {
+        self.log_op(Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"DEL")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_bytes())),
+        ]));
+    }
+
+    /// 为 [`Db::record`] 中正在执行的命令预留序号，已经预留过或者不在其中时什么也不做
+    ///
+    /// 必须在持有被修改的分片的写锁时调用，序号的顺序因此与修改的顺序相同。
+    pub(super) fn reserve_op(&self) {
+        RECORDING.with_borrow_mut(|recording| {
+            let Some(recording) = recording else {
+                return;
+            };
+            if recording.reserved.is_none() {
+                let (log, db) = &*self.op_log.read().unwrap();
+                recording.reserved = Some(Reservation {
+                    log: Arc::clone(log),
+                    db: *db,
+                    seq: log.reserve(),
+                });
+            }
+        });
+    }
+}
+
+impl<B: Backend> Db<B> {
+    /// 执行一条写命令并记录到操作日志，`f` 返回响应以及需要重放的命令帧，返回响应和记录的序号
+    ///
+    /// 命令修改数据时预留的序号填入 `f` 返回的命令帧；`f` 返回 None（命令执行失败）或者 panic 时
+    /// 取消预留的记录。没有修改数据的命令直接追加到日志末尾。
+    pub fn record<R>(&self, f: impl FnOnce() -> (R, Option<Frame>)) -> (R, Option<u64>) {
+        /// 离开时恢复之前的状态，`f` panic 时取消预留的记录，消费者不会一直等待
+        struct Scope(Option<Recording>);
+
+        impl Drop for Scope {
+            fn drop(&mut self) {
+                if let Some(Recording {
+                    reserved: Some(reserved),
+                }) = RECORDING.replace(self.0.take())
+                {
+                    reserved.fill(None);
+                }
+            }
+        }
+
+        let _scope = Scope(RECORDING.replace(Some(Recording::default())));
+        let (response, frame) = f();
+        let reserved = RECORDING.with_borrow_mut(|recording| {
+            recording
+                .as_mut()
+                .and_then(|recording| recording.reserved.take())
+        });
+        let seq = match (reserved, frame) {
+            (Some(reserved), Some(frame)) => Some(reserved.fill(Some(frame))),
+            (Some(reserved), None) => {
+                reserved.fill(None);
+                None
+            }
+            (None, frame) => frame.map(|frame| self.shared.log_op(frame)),
+        };
+        (response, seq)
+    }
+
+    /// 使用共享的操作日志，`index` 为本数据库的编号，必须在开启 AOF 之前调用
+    pub fn set_op_log(&self, log: Arc<OpLog>, index: usize) {
+        *self.shared.op_log.write().unwrap() = (log, index);
+    }
+
+    /// 当前使用的操作日志
+    pub fn op_log(&self) -> Arc<OpLog> {
+        Arc::clone(&self.shared.op_log.read().unwrap().0)
+    }
+
+    /// 数据库的编号
+    pub fn index(&self) -> usize {
+        self.shared.op_log.read().unwrap().1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn tails_read_independently() {
+        let log = Arc::new(OpLog::with_capacity(2));
+        let mut fast = log.tail(1);
+        let mut slow = log.tail(1);
+        for i in 0..3 {
+            log.append(i, Frame::Bulk(Bytes::from(i.to_string())));
+        }
+
+        // 第一条记录已被丢弃
+        assert_eq!(slow.try_next().unwrap().unwrap_err(), TailError::Lagged(1));
+        assert_eq!(slow.next().await.unwrap().seq, 2);
+        assert_eq!(fast.try_next().unwrap().unwrap_err(), TailError::Lagged(1));
+        assert_eq!(fast.next().await.unwrap().db, 1);
+        assert_eq!(fast.next().await.unwrap().seq, 3);
+        assert!(fast.try_next().is_none());
+
+        let waiting = tokio::spawn(async move { fast.next().await.unwrap().seq });
+        tokio::task::yield_now().await;
+        log.append(0, Frame::Null);
+        assert_eq!(waiting.await.unwrap(), 4);
+        assert_eq!(log.last_seq(), 4);
+    }
+
+    #[test]
+    fn tails_wait_for_reserved_ops() {
+        let log = Arc::new(OpLog::new());
+        let mut tail = log.tail(1);
+        let first = log.reserve();
+        let cancelled = log.reserve();
+        log.append(0, Frame::Null);
+
+        // 预留的记录没有填入之前，之后追加的记录也读不到
+        assert!(tail.try_next().is_none());
+        log.fill(cancelled, None);
+        assert!(tail.try_next().is_none());
+        log.fill(first, Some((1, Frame::Null)));
+        assert_eq!(tail.try_next().unwrap().unwrap().db, 1);
+        assert_eq!(tail.try_next().unwrap().unwrap().seq, 3);
+        assert!(tail.try_next().is_none());
+    }
+}
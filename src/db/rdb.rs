@@ -0,0 +1,438 @@
+//! RDB 风格的二进制快照
+//!
+//! 文件格式：
+//!
+//! ```text
+//! "ILEARN-RDB" 版本号(u8)
+//! { 类型(u8) key 过期时间 值 }*
+//! 0xFF
+//! ```
+//!
+//! 长度和整数都是小端序，长度为 u32；过期时间为 1 字节标记加上 u64 的 unix 毫秒时间戳。
+//! 使用绝对时间，服务停止期间到期的 key 在加载时直接丢弃，重启后不会复活。
+//! 版本 1 的过期时间是保存时的剩余毫秒数，仍然可以加载。
+//! 写入时先写临时文件再重命名，保存过程中出错不会破坏已有的文件。
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    io::{self, Write},
+    path::Path,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use thiserror::Error;
+use tracing::{error, info};
+
+use super::{Backend, Db, DbError, Snapshot, Value, ZSet};
+use crate::stream::Stream;
+
+const MAGIC: &[u8] = b"ILEARN-RDB";
+pub(super) const VERSION: u8 = 2;
+const EOF: u8 = 0xff;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_HASH: u8 = 2;
+const TYPE_SET: u8 = 3;
+const TYPE_ZSET: u8 = 4;
+const TYPE_STREAM: u8 = 5;
+
+#[derive(Debug, Error)]
+pub enum RdbError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("not an rdb file")]
+    BadMagic,
+    #[error("unsupported rdb version {0}")]
+    UnsupportedVersion(u8),
+    #[error("rdb file is corrupted: {0}")]
+    Corrupted(&'static str),
+}
+
+impl Snapshot {
+    /// 把快照编码为 RDB 格式
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_slice(MAGIC);
+        buf.put_u8(VERSION);
+
+        for (key, entry) in self.iter() {
+            buf.put_u8(type_of(&entry.value));
+            put_bytes(&mut buf, key.as_bytes());
+            match entry.expires_at {
+                Some(when) => {
+                    buf.put_u8(1);
+                    buf.put_u64_le(unix_millis(when));
+                }
+                None => buf.put_u8(0),
+            }
+            encode_value(&mut buf, &entry.value);
+        }
+
+        buf.put_u8(EOF);
+        buf.freeze()
+    }
+
+    /// 保存到文件，先写入同目录下的临时文件再重命名
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), RdbError> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("rdb.tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(&self.encode())?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+impl<B: Backend> Db<B> {
+    /// 在当前线程保存快照，对应 SAVE 命令
+    pub fn save(&self) -> Result<(), RdbError> {
+        self.snapshot().save(self.rdb_path())
+    }
+
+    /// 在后台线程保存快照，对应 BGSAVE 命令
+    ///
+    /// 快照在调用时立即创建，之后的写入不会影响保存的内容。同一时间只允许一个后台保存，
+    /// 必须在 tokio 运行时中调用。
+    pub fn bgsave(&self) -> Result<(), DbError> {
+        if self.shared.saving.swap(true, Ordering::AcqRel) {
+            return Err(DbError::SaveInProgress);
+        }
+        let snapshot = self.snapshot();
+        let path = self.rdb_path();
+        let shared = Arc::clone(&self.shared);
+        tokio::task::spawn_blocking(move || {
+            match snapshot.save(&path) {
+                Ok(()) => info!("Background saving terminated with success"),
+                Err(e) => error!(error = %e, "Background saving error"),
+            }
+            shared.saving.store(false, Ordering::Release);
+        });
+        Ok(())
+    }
+
+    /// 从 RDB 文件加载数据，返回加载的 key 数量，已经过期的 key 不会被加载
+    ///
+    /// 先完整解析文件再写入 Db，文件损坏时 Db 不会被修改。
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<usize, RdbError> {
+        let entries = decode(Bytes::from(fs::read(path)?))?;
+        Ok(self.load_bulk(entries))
+    }
+
+    /// 用 [`Snapshot::encode`] 编码的快照替换所有数据，返回加载的 key 数量，用于副本的全量同步
+    ///
+    /// 与 [`Db::load`] 相同，先完整解析再清空，快照损坏时 Db 不会被修改。
+    pub fn replace_with(&self, encoded: Bytes) -> Result<usize, RdbError> {
+        let entries = decode(encoded)?;
+        self.clear();
+        Ok(self.load_bulk(entries))
+    }
+}
+
+/// 把过期时刻换算为 unix 毫秒时间戳
+pub(crate) fn unix_millis(when: Instant) -> u64 {
+    let (now, wall) = (Instant::now(), SystemTime::now());
+    let at = if when >= now {
+        wall + (when - now)
+    } else {
+        wall - (now - when)
+    };
+    at.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+/// 把 unix 毫秒时间戳换算为过期时刻，已经过去时返回 None
+pub(crate) fn from_unix_millis(millis: u64) -> Option<Instant> {
+    let at = UNIX_EPOCH + Duration::from_millis(millis);
+    let ttl = at.duration_since(SystemTime::now()).ok()?;
+    Some(Instant::now() + ttl)
+}
+
+type Decoded = (String, Value, Option<Instant>);
+
+fn decode(buf: Bytes) -> Result<Vec<Decoded>, RdbError> {
+    let mut r = Reader::new(buf);
+    if r.0.len() < MAGIC.len() || &r.0[..MAGIC.len()] != MAGIC {
+        return Err(RdbError::BadMagic);
+    }
+    r.0.advance(MAGIC.len());
+    let version = r.u8()?;
+    if version == 0 || version > VERSION {
+        return Err(RdbError::UnsupportedVersion(version));
+    }
+    let now = Instant::now();
+
+    let mut entries = vec![];
+    loop {
+        let ty = r.u8()?;
+        if ty == EOF {
+            break;
+        }
+        let key = r.string()?;
+        // 外层的 None 表示已经过期，值仍然要解析以便读取下一个 key
+        let expires_at = match (r.u8()?, version) {
+            (0, _) => Some(None),
+            (1, 1) => Some(Some(now + Duration::from_millis(r.u64()?))),
+            (1, _) => from_unix_millis(r.u64()?).map(Some),
+            _ => return Err(RdbError::Corrupted("invalid expire flag")),
+        };
+        let value = decode_value(&mut r, ty)?;
+        if let Some(expires_at) = expires_at {
+            entries.push((key, value, expires_at));
+        }
+    }
+    if r.0.has_remaining() {
+        return Err(RdbError::Corrupted("trailing bytes after EOF"));
+    }
+    Ok(entries)
+}
+
+pub(super) fn type_of(value: &Value) -> u8 {
+    match value {
+        Value::String(_) | Value::Int(_) => TYPE_STRING,
+        Value::List(_) => TYPE_LIST,
+        Value::Hash(_) => TYPE_HASH,
+        Value::Set(_) => TYPE_SET,
+        Value::ZSet(_) => TYPE_ZSET,
+        Value::Stream(_) => TYPE_STREAM,
+    }
+}
+
+pub(super) fn encode_value(buf: &mut BytesMut, value: &Value) {
+    match value {
+        Value::String(data) => put_bytes(buf, data),
+        Value::Int(n) => put_bytes(buf, n.to_string().as_bytes()),
+        Value::List(list) => {
+            put_len(buf, list.len());
+            list.iter().for_each(|item| put_bytes(buf, item));
+        }
+        Value::Hash(hash) => {
+            put_len(buf, hash.len());
+            for (field, value) in hash {
+                put_bytes(buf, field);
+                put_bytes(buf, value);
+            }
+        }
+        Value::Set(set) => {
+            put_len(buf, set.len());
+            set.iter().for_each(|member| put_bytes(buf, member));
+        }
+        Value::ZSet(zset) => {
+            put_len(buf, zset.len());
+            for (member, score) in zset.iter() {
+                put_bytes(buf, member);
+                buf.put_f64_le(score);
+            }
+        }
+        Value::Stream(stream) => stream.encode(buf),
+    }
+}
+
+pub(super) fn decode_value(r: &mut Reader, ty: u8) -> Result<Value, RdbError> {
+    let value = match ty {
+        TYPE_STRING => Value::from(r.bytes()?),
+        TYPE_LIST => {
+            let len = r.len()?;
+            let mut list = VecDeque::with_capacity(r.capacity(len));
+            for _ in 0..len {
+                list.push_back(r.bytes()?);
+            }
+            Value::List(list)
+        }
+        TYPE_HASH => {
+            let len = r.len()?;
+            let mut hash = HashMap::with_capacity(r.capacity(len));
+            for _ in 0..len {
+                hash.insert(r.bytes()?, r.bytes()?);
+            }
+            Value::Hash(hash)
+        }
+        TYPE_SET => {
+            let len = r.len()?;
+            let mut set = HashSet::with_capacity(r.capacity(len));
+            for _ in 0..len {
+                set.insert(r.bytes()?);
+            }
+            Value::Set(set)
+        }
+        TYPE_ZSET => {
+            let mut zset = ZSet::default();
+            for _ in 0..r.len()? {
+                let member = r.bytes()?;
+                zset.insert(member, r.f64()?);
+            }
+            Value::ZSet(zset)
+        }
+        TYPE_STREAM => Value::Stream(Stream::decode(r)?),
+        _ => return Err(RdbError::Corrupted("unknown value type")),
+    };
+    Ok(value)
+}
+
+pub(crate) fn put_len(buf: &mut BytesMut, len: usize) {
+    buf.put_u32_le(len as u32);
+}
+
+pub(crate) fn put_bytes(buf: &mut BytesMut, data: &[u8]) {
+    put_len(buf, data.len());
+    buf.put_slice(data);
+}
+
+/// 带边界检查的读取，数据不足时返回错误而不是 panic
+pub(crate) struct Reader(Bytes);
+
+impl Reader {
+    pub(crate) fn new(buf: Bytes) -> Reader {
+        Reader(buf)
+    }
+
+    fn need(&self, n: usize) -> Result<(), RdbError> {
+        if self.0.remaining() < n {
+            return Err(RdbError::Corrupted("unexpected end of file"));
+        }
+        Ok(())
+    }
+
+    /// 预分配容量时使用，避免损坏的长度字段导致申请巨大的内存
+    pub(crate) fn capacity(&self, len: usize) -> usize {
+        len.min(self.0.remaining())
+    }
+
+    /// 剩余未读取的字节数
+    pub(crate) fn remaining(&self) -> usize {
+        self.0.remaining()
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, RdbError> {
+        self.need(1)?;
+        Ok(self.0.get_u8())
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, RdbError> {
+        self.need(8)?;
+        Ok(self.0.get_u64_le())
+    }
+
+    pub(crate) fn f64(&mut self) -> Result<f64, RdbError> {
+        self.need(8)?;
+        Ok(self.0.get_f64_le())
+    }
+
+    pub(crate) fn len(&mut self) -> Result<usize, RdbError> {
+        self.need(4)?;
+        Ok(self.0.get_u32_le() as usize)
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<Bytes, RdbError> {
+        let len = self.len()?;
+        self.need(len)?;
+        Ok(self.0.split_to(len))
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, RdbError> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| RdbError::Corrupted("invalid utf-8"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expired_keys_are_not_loaded() {
+        let db = Db::new();
+        db.set("live".into(), Bytes::new(), Some(Duration::from_secs(60)))
+            .unwrap();
+        db.set("dead".into(), Bytes::new(), Some(Duration::from_millis(20)))
+            .unwrap();
+        let encoded = db.snapshot().encode();
+
+        // 模拟服务停止期间 `dead` 到期
+        std::thread::sleep(Duration::from_millis(40));
+        let decoded = decode(encoded).unwrap();
+        assert_eq!(decoded.len(), 1);
+        let (key, _, expires_at) = &decoded[0];
+        assert_eq!(key, "live");
+        let ttl = expires_at.unwrap() - Instant::now();
+        assert!(ttl > Duration::from_secs(59) && ttl <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn round_trip_all_types() {
+        let db = Db::new();
+        db.set(
+            "s".into(),
+            Bytes::from_static(b"v"),
+            Some(Duration::from_secs(60)),
+        )
+        .unwrap();
+        db.update("z", |value| {
+            let mut zset = ZSet::default();
+            zset.insert(Bytes::from_static(b"m"), 1.5);
+            value.set(Value::ZSet(zset));
+            Ok(())
+        })
+        .unwrap();
+        db.update("x", |value| {
+            let mut stream = Stream::new();
+            stream
+                .add(
+                    crate::stream::IdSpec::Auto,
+                    vec![(Bytes::from_static(b"f"), Bytes::from_static(b"v"))],
+                )
+                .unwrap();
+            stream.create_group("g", None).unwrap();
+            value.set(Value::Stream(stream));
+            Ok(())
+        })
+        .unwrap();
+
+        let decoded = decode(db.snapshot().encode()).unwrap();
+        assert_eq!(decoded.len(), 3);
+        let (_, value, ttl) = decoded.iter().find(|(key, ..)| key == "s").unwrap();
+        assert_eq!(
+            value.as_string().map(|v| v.to_bytes()),
+            Ok(Bytes::from_static(b"v"))
+        );
+        assert!(ttl.is_some());
+        let (_, value, _) = decoded.iter().find(|(key, ..)| key == "z").unwrap();
+        assert_eq!(value.as_zset().unwrap().score(b"m"), Some(1.5));
+        let (_, value, _) = decoded.iter().find(|(key, ..)| key == "x").unwrap();
+        let stream = value.as_stream().unwrap();
+        assert_eq!(stream.len(), 1);
+        assert!(stream.group("g").is_some());
+
+        assert!(matches!(
+            decode(Bytes::from_static(b"ILEARN-RDB\x02\x00")),
+            Err(RdbError::Corrupted(_))
+        ));
+    }
+
+    #[test]
+    fn replace_with_drops_existing_keys() {
+        let primary = Db::new();
+        primary
+            .set("a".into(), Bytes::from_static(b"1"), None)
+            .unwrap();
+        let replica = Db::new();
+        replica
+            .set("stale".into(), Bytes::from_static(b"2"), None)
+            .unwrap();
+
+        assert!(replica
+            .replace_with(Bytes::from_static(b"garbage"))
+            .is_err());
+        assert!(replica.get("stale").unwrap().is_some());
+        assert_eq!(
+            replica.replace_with(primary.snapshot().encode()).unwrap(),
+            1
+        );
+        assert!(replica.get("stale").unwrap().is_none());
+        assert_eq!(replica.get("a").unwrap(), Some(Bytes::from_static(b"1")));
+        assert_eq!(replica.used_memory(), primary.used_memory());
+    }
+}
@@ -0,0 +1,93 @@
+//! 只读视图
+//!
+//! 作为副本运行时数据只能由主节点修改，读命令在本地执行，写命令返回 `-READONLY`。
+//! [`ReadView`] 只提供不修改数据的方法，读命令的实现只接受它，
+//! 是否允许写入由命令分发统一判断（见 `cmd::Command::apply_read_only`），各个命令不需要检查模式。
+//!
+//! 访问时发现的过期 key 仍然会被删除，这只是回收内存，不改变读到的结果。
+
+use bytes::Bytes;
+
+use super::{
+    rdb::RdbError, Backend, Db, DbError, DbStats, KeyspaceStats, Snapshot, Striped, Value,
+};
+
+/// 不能修改数据的 Db 句柄，clone 只增加引用计数
+#[derive(Debug)]
+pub struct ReadView<B: Backend = Striped> {
+    db: Db<B>,
+}
+
+impl<B: Backend> Clone for ReadView<B> {
+    fn clone(&self) -> ReadView<B> {
+        ReadView {
+            db: self.db.clone(),
+        }
+    }
+}
+
+impl<B: Backend> Db<B> {
+    /// 获取只读视图
+    pub fn read_view(&self) -> ReadView<B> {
+        ReadView { db: self.clone() }
+    }
+}
+
+impl<B: Backend> ReadView<B> {
+    /// 见 [`Db::get`]
+    pub fn get(&self, key: &str) -> Result<Option<Bytes>, DbError> {
+        self.db.get(key)
+    }
+
+    /// 见 [`Db::view`]
+    pub fn view<R>(&self, key: &str, f: impl FnOnce(Option<&Value>) -> R) -> R {
+        self.db.view(key, f)
+    }
+
+    /// 见 [`Db::version`]
+    pub fn version(&self, key: &str) -> u64 {
+        self.db.version(key)
+    }
+
+    /// 见 [`Db::dump`]
+    pub fn dump(&self, key: &str) -> Option<Bytes> {
+        self.db.dump(key)
+    }
+
+    /// 见 [`Db::memory_usage`]
+    pub fn memory_usage(&self, key: &str, samples: usize) -> Option<usize> {
+        self.db.memory_usage(key, samples)
+    }
+
+    /// 见 [`Db::iter_from`]
+    pub fn iter_from(&self, cursor: u64, count: usize) -> (u64, Vec<String>) {
+        self.db.iter_from(cursor, count)
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        self.db.snapshot()
+    }
+
+    pub fn stats(&self) -> DbStats {
+        self.db.stats()
+    }
+
+    pub fn keyspace_stats(&self) -> KeyspaceStats {
+        self.db.keyspace_stats()
+    }
+
+    /// 持久化不修改数据，副本同样可以执行，见 [`Db::save`]
+    pub fn save(&self) -> Result<(), RdbError> {
+        self.db.save()
+    }
+
+    /// 见 [`Db::bgsave`]
+    pub fn bgsave(&self) -> Result<(), DbError> {
+        self.db.bgsave()
+    }
+
+    /// 见 [`Db::bgrewriteaof`]
+    pub fn bgrewriteaof(&self) -> Result<(), DbError> {
+        self.db.bgrewriteaof()
+    }
+}
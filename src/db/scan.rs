@@ -0,0 +1,191 @@
+//! SCAN 使用的游标遍历
+//!
+//! 游标的高 16 位是分片下标，低 48 位是分片内的位置。分片中的 key 按固定种子的哈希值排序，
+//! 位置是下一批要返回的最小哈希值。哈希只由 key 的内容决定，与 HashMap 的扩容、
+//! 其他 key 的增删都无关，因此：
+//! - 遍历期间一直存在的 key 一定会被返回
+//! - 同一个 key 不会被返回两次，它的哈希不变，而位置只增不减
+//!
+//! 遍历期间新增或删除的 key 可能返回也可能不返回，这与 Redis 的 SCAN 相同。
+//! 每个分片维护一个按位置排序的索引 [`ScanIndex`]，一批只需要从位置开始读取 `count` 个 key。
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeSet},
+    hash::{Hash, Hasher},
+    time::Instant,
+};
+
+use rand::Rng;
+
+use super::{small::SmallString, Backend, Db};
+
+const POSITION_BITS: u32 = 48;
+const POSITION_MASK: u64 = (1 << POSITION_BITS) - 1;
+
+/// key 在分片内的位置，`DefaultHasher::new` 的种子固定，同一个进程中结果不变
+fn position(key: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish() >> (u64::BITS - POSITION_BITS)
+}
+
+/// 分片中所有 key 按位置排序的索引
+///
+/// 只在 key 第一次写入和真正删除时修改，覆盖写入不需要改动。长 key 与 HashMap 共享堆上的数据，
+/// 不计入 `used_memory`。
+#[derive(Debug, Default)]
+pub(super) struct ScanIndex(BTreeSet<(u64, SmallString)>);
+
+impl ScanIndex {
+    /// 登记 key，已经登记过时什么也不做
+    pub(super) fn insert(&mut self, key: &SmallString) {
+        let position = position(key);
+        let indexed = self
+            .from(position)
+            .take_while(|(other, _)| *other == position)
+            .any(|(_, other)| other == key);
+        if !indexed {
+            self.0.insert((position, key.clone()));
+        }
+    }
+
+    pub(super) fn remove(&mut self, key: &str) {
+        self.0.remove(&(position(key), SmallString::from(key)));
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// 从随机的位置开始取出最多 `count` 个相邻的 key，到末尾后绕回开头
+    ///
+    /// 位置是 key 的哈希值，起点随机时取到的 key 也近似随机，查找起点是 O(log n)。
+    pub(super) fn sample(&self, count: usize) -> impl Iterator<Item = &SmallString> {
+        let start = rand::thread_rng().gen_range(0..=POSITION_MASK);
+        self.from(start)
+            .chain(self.from(0))
+            .map(|(_, key)| key)
+            .take(count.min(self.0.len()))
+    }
+
+    /// 位置不小于 `start` 的 key，按位置排序
+    fn from(&self, start: u64) -> impl Iterator<Item = (u64, &SmallString)> {
+        self.0
+            .range((start, SmallString::from(""))..)
+            .map(|(position, key)| (*position, key))
+    }
+}
+
+fn encode_cursor(shard: usize, position: u64) -> u64 {
+    ((shard as u64) << POSITION_BITS) | position
+}
+
+impl<B: Backend> Db<B> {
+    /// 从 `cursor` 开始取出大约 `count` 个 key，返回下一次的游标，游标为 0 表示遍历结束
+    ///
+    /// 第一次调用时传入 0。哈希值相同的 key 总在同一批中返回，因此一批可能略多于 `count` 个。
+    pub fn iter_from(&self, cursor: u64, count: usize) -> (u64, Vec<String>) {
+        let count = count.max(1);
+        let mut shard_index = (cursor >> POSITION_BITS) as usize;
+        let mut start = cursor & POSITION_MASK;
+        let mut keys = vec![];
+
+        while shard_index < self.shard_count() {
+            let now = Instant::now();
+            let shard = self.shared.backend.read(shard_index);
+            let mut previous = None;
+            for (position, key) in shard.scan.from(start) {
+                // 取够之后在位置变化处停下，与上一个 key 哈希相同的 key 也放进这一批
+                if keys.len() >= count && previous != Some(position) {
+                    return (encode_cursor(shard_index, position), keys);
+                }
+                previous = Some(position);
+                let live = shard.entries.get(key.as_str());
+                if live.is_some_and(|entry| !entry.is_expired(now)) {
+                    keys.push(key.to_string());
+                }
+            }
+            drop(shard);
+
+            shard_index += 1;
+            start = 0;
+            if keys.len() >= count {
+                break;
+            }
+        }
+
+        if shard_index >= self.shard_count() {
+            (0, keys)
+        } else {
+            (encode_cursor(shard_index, 0), keys)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn scan_returns_each_stable_key_once() {
+        let db = Db::with_shards(4);
+        for i in 0..100 {
+            db.set(format!("k{}", i), Bytes::new(), None).unwrap();
+        }
+
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        let mut round = 0;
+        loop {
+            let (next, keys) = db.iter_from(cursor, 7);
+            for key in keys {
+                assert!(seen.insert(key), "duplicated key");
+            }
+            // 遍历过程中删除一部分 key 并写入新的 key
+            db.del(&[format!("k{}", 90 + round % 10)]);
+            db.set(format!("new{}", round), Bytes::new(), None).unwrap();
+            round += 1;
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+        for i in 0..90 {
+            assert!(seen.contains(&format!("k{}", i)));
+        }
+    }
+
+    fn indexed(db: &Db) -> usize {
+        (0..db.shard_count())
+            .map(|index| {
+                let shard = db.shared.backend.read(index);
+                assert_eq!(shard.scan.0.len(), shard.entries.len());
+                shard.scan.0.len()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn index_follows_writes_and_removals() {
+        let db = Db::with_shards(4);
+        let long = "long key that does not fit inline".repeat(2);
+        for key in ["a", "b", long.as_str()] {
+            db.set(key.to_string(), Bytes::from("1"), None).unwrap();
+        }
+        // 覆盖写入与原地修改不会重复登记
+        db.set("a".to_string(), Bytes::from("2"), None).unwrap();
+        db.incr_by(&long, 1).unwrap();
+        assert_eq!(indexed(&db), 3);
+
+        db.del(&["b".to_string(), long.clone()]);
+        assert_eq!(indexed(&db), 1);
+        assert_eq!(db.iter_from(0, 10), (0, vec!["a".to_string()]));
+
+        db.clear();
+        assert_eq!(indexed(&db), 0);
+    }
+}
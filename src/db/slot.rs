@@ -0,0 +1,65 @@
+//! [`Db::update`](super::Db::update) 交给回调的值
+//!
+//! 回调可以原地修改值，也可以写入新的值或者删除 key。替换和删除时被换下的值先保留在这里，
+//! 回调返回错误或者 panic 时放回原来的值，失败的命令不会创建或者删除 key。
+//! 原地修改无法撤销，回调应当在确认不会失败之后再修改。
+
+use super::Value;
+
+#[derive(Debug)]
+pub struct Slot {
+    value: Option<Value>,
+    /// 第一次替换或删除之前的值，没有替换过时为 None
+    replaced: Option<Option<Value>>,
+}
+
+impl Slot {
+    pub(super) fn new(value: Option<Value>) -> Slot {
+        Slot {
+            value,
+            replaced: None,
+        }
+    }
+
+    pub fn get(&self) -> Option<&Value> {
+        self.value.as_ref()
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut Value> {
+        self.value.as_mut()
+    }
+
+    /// key 不存在或者已经被 [`Slot::remove`] 删除
+    pub fn is_none(&self) -> bool {
+        self.value.is_none()
+    }
+
+    /// 写入新的值，key 不存在时会创建 key
+    pub fn set(&mut self, value: Value) {
+        let old = self.value.replace(value);
+        self.replaced.get_or_insert(old);
+    }
+
+    /// 删除 key
+    pub fn remove(&mut self) {
+        let old = self.value.take();
+        self.replaced.get_or_insert(old);
+    }
+
+    /// 回调成功，取出最终的值
+    pub(super) fn commit(&mut self) -> Option<Value> {
+        self.replaced = None;
+        self.value.take()
+    }
+
+    /// 回调失败，取出原来的值
+    pub(super) fn rollback(&mut self) -> Option<Value> {
+        match self.replaced.take() {
+            Some(original) => {
+                self.value = None;
+                original
+            }
+            None => self.value.take(),
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! 短字符串的内联存储
+//!
+//! 缓存场景中大部分 key 和值都很短，每个都单独在堆上分配会给分配器带来很大压力。
+//! 不超过 [`INLINE_CAP`] 字节的数据直接存放在结构体内部，更长的才放在堆上，
+//! 对应 Redis 字符串的 embstr 与 raw 两种编码。
+
+use std::{
+    borrow::Borrow,
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+};
+
+use bytes::Bytes;
+
+use super::memory::malloc_size;
+
+/// 内联存储的最大长度，加上 1 字节的长度正好是 24 字节
+pub const INLINE_CAP: usize = 23;
+
+/// 长度为 `len` 的数据在堆上占用的大小，内联存储时为 0
+pub fn heap_size(len: usize) -> usize {
+    if len <= INLINE_CAP {
+        0
+    } else {
+        malloc_size(len)
+    }
+}
+
+#[derive(Clone)]
+enum Repr {
+    Inline { len: u8, buf: [u8; INLINE_CAP] },
+    Heap(Bytes),
+}
+
+impl Repr {
+    fn inline(data: &[u8]) -> Repr {
+        let mut buf = [0; INLINE_CAP];
+        buf[..data.len()].copy_from_slice(data);
+        Repr::Inline {
+            len: data.len() as u8,
+            buf,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Repr::Inline { len, buf } => &buf[..*len as usize],
+            Repr::Heap(data) => data,
+        }
+    }
+}
+
+/// 字符串类型的值
+#[derive(Clone)]
+pub struct SmallBytes(Repr);
+
+impl SmallBytes {
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Repr::Inline { .. })
+    }
+
+    /// 转换为 `Bytes`，内联存储时需要复制一次
+    pub fn to_bytes(&self) -> Bytes {
+        match &self.0 {
+            Repr::Inline { .. } => Bytes::copy_from_slice(self),
+            Repr::Heap(data) => data.clone(),
+        }
+    }
+}
+
+impl From<Bytes> for SmallBytes {
+    /// 短数据复制到内联存储中，同时释放对原缓冲区（如读取命令的缓冲区）的引用
+    fn from(data: Bytes) -> SmallBytes {
+        if data.len() <= INLINE_CAP {
+            SmallBytes(Repr::inline(&data))
+        } else {
+            SmallBytes(Repr::Heap(data))
+        }
+    }
+}
+
+impl From<&[u8]> for SmallBytes {
+    fn from(data: &[u8]) -> SmallBytes {
+        if data.len() <= INLINE_CAP {
+            SmallBytes(Repr::inline(data))
+        } else {
+            SmallBytes(Repr::Heap(Bytes::copy_from_slice(data)))
+        }
+    }
+}
+
+impl Deref for SmallBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+impl AsRef<[u8]> for SmallBytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl PartialEq for SmallBytes {
+    fn eq(&self, other: &SmallBytes) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for SmallBytes {}
+
+impl PartialEq<[u8]> for SmallBytes {
+    fn eq(&self, other: &[u8]) -> bool {
+        **self == *other
+    }
+}
+
+impl fmt::Debug for SmallBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.to_bytes(), f)
+    }
+}
+
+/// Db 中的 key，保证是合法的 UTF-8
+///
+/// 哈希、比较都与 `str` 一致，HashMap 可以直接用 `&str` 查找。
+#[derive(Clone)]
+pub struct SmallString(Repr);
+
+impl SmallString {
+    pub fn as_str(&self) -> &str {
+        // SAFETY: 只能从 `String` 或 `&str` 构造，内容一定是合法的 UTF-8
+        unsafe { std::str::from_utf8_unchecked(self.0.as_bytes()) }
+    }
+}
+
+impl From<String> for SmallString {
+    fn from(s: String) -> SmallString {
+        if s.len() <= INLINE_CAP {
+            SmallString(Repr::inline(s.as_bytes()))
+        } else {
+            SmallString(Repr::Heap(Bytes::from(s)))
+        }
+    }
+}
+
+impl From<&str> for SmallString {
+    fn from(s: &str) -> SmallString {
+        if s.len() <= INLINE_CAP {
+            SmallString(Repr::inline(s.as_bytes()))
+        } else {
+            SmallString(Repr::Heap(Bytes::copy_from_slice(s.as_bytes())))
+        }
+    }
+}
+
+impl Deref for SmallString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for SmallString {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Hash for SmallString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl PartialEq for SmallString {
+    fn eq(&self, other: &SmallString) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for SmallString {}
+
+impl PartialOrd for SmallString {
+    fn partial_cmp(&self, other: &SmallString) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SmallString {
+    fn cmp(&self, other: &SmallString) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl fmt::Debug for SmallString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for SmallString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn short_data_is_inline() {
+        let short = SmallBytes::from(Bytes::from_static(b"hello"));
+        assert!(short.is_inline());
+        assert_eq!(&*short, b"hello");
+        let long = SmallBytes::from(Bytes::from(vec![b'x'; INLINE_CAP + 1]));
+        assert!(!long.is_inline());
+        assert_eq!(long.len(), INLINE_CAP + 1);
+
+        let mut map = HashMap::new();
+        map.insert(SmallString::from("key"), 1);
+        map.insert(SmallString::from("k".repeat(40)), 2);
+        assert_eq!(map.get("key"), Some(&1));
+        assert_eq!(map.get(&"k".repeat(40)[..]), Some(&2));
+    }
+}
@@ -0,0 +1,136 @@
+use std::{sync::Arc, time::Instant};
+
+use super::{shard_index, Backend, Db, Entry, KeyHasher, KeyMap, SmallString};
+
+/// 某一时刻整个 keyspace 的只读快照
+///
+/// 创建时只复制各个分片的 `Arc`，不复制数据；之后对分片的修改会触发写时复制，
+/// 快照看到的内容保持不变。持久化和 SCAN 可以在快照上遍历一致的状态，同时不阻塞写入。
+///
+/// 快照存在期间，被修改过的分片会同时保留新旧两份数据，用完后应尽快释放。
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    shards: Vec<Arc<KeyMap<Entry>>>,
+    /// 所在 Db 选择分片使用的哈希
+    hasher: KeyHasher,
+    /// 创建快照的时间，此时已经过期的 key 视为不存在
+    taken_at: Instant,
+}
+
+impl<B: Backend> Db<B> {
+    /// 创建快照
+    ///
+    /// 同时持有所有分片的读锁再复制引用，快照中不会出现只执行了一半的跨分片操作。
+    /// 按下标顺序加锁，并且只是读锁，不会和单个分片上的操作形成死锁。
+    pub fn snapshot(&self) -> Snapshot {
+        Db::snapshots(std::slice::from_ref(self)).1.remove(0)
+    }
+
+    /// 同时创建多个数据库的快照，并返回此时操作日志的进度，所有数据库必须共用同一个操作日志
+    ///
+    /// 写命令在持有被修改分片的写锁时预留日志序号（见 [`Db::record`]），这里持有所有分片的读锁
+    /// 再读取进度：序号不大于返回值的命令都已体现在快照中，之后的命令都不在快照中，
+    /// 从下一条记录开始重放不会重复执行命令。数据库之间没有跨库的操作，依次加锁不会死锁。
+    pub fn snapshots(dbs: &[Db<B>]) -> (u64, Vec<Snapshot>) {
+        let guards: Vec<Vec<_>> = dbs
+            .iter()
+            .map(|db| {
+                let backend = &db.shared.backend;
+                (0..backend.shard_count())
+                    .map(|index| backend.read(index))
+                    .collect()
+            })
+            .collect();
+        let seq = dbs.first().map_or(0, |db| db.op_log().last_seq());
+        let taken_at = Instant::now();
+        let snapshots = guards
+            .iter()
+            .zip(dbs)
+            .map(|(guards, db)| Snapshot {
+                shards: guards
+                    .iter()
+                    .map(|shard| Arc::clone(&shard.entries))
+                    .collect(),
+                hasher: db.shared.hasher.clone(),
+                taken_at,
+            })
+            .collect();
+        (seq, snapshots)
+    }
+}
+
+impl Snapshot {
+    pub fn taken_at(&self) -> Instant {
+        self.taken_at
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.shards[shard_index(&self.hasher, key, self.shards.len())]
+            .get(key)
+            .filter(|entry| !entry.is_expired(self.taken_at))
+    }
+
+    /// 遍历快照中所有未过期的键值对，顺序不确定
+    pub fn iter(&self) -> impl Iterator<Item = (&SmallString, &Entry)> {
+        self.shards
+            .iter()
+            .flat_map(|shard| shard.iter())
+            .filter(|(_, entry)| !entry.is_expired(self.taken_at))
+    }
+
+    /// 未过期的 key 数量，需要遍历所有 key
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{slice, thread};
+
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::frame::Frame;
+
+    #[test]
+    fn snapshot_is_not_affected_by_later_writes() {
+        let db = Db::with_shards(4);
+        db.set("a".into(), Bytes::from_static(b"1"), None).unwrap();
+
+        let snapshot = db.snapshot();
+        db.set("a".into(), Bytes::from_static(b"2"), None).unwrap();
+        db.set("b".into(), Bytes::from_static(b"3"), None).unwrap();
+
+        assert_eq!(snapshot.len(), 1);
+        let entry = snapshot.get("a").unwrap();
+        assert_eq!(entry.value.to_string_bytes(), Ok(Bytes::from_static(b"1")));
+        assert_eq!(db.get("a"), Ok(Some(Bytes::from_static(b"2"))));
+    }
+
+    #[test]
+    fn snapshots_match_the_op_log_position() {
+        let db = Db::with_shards(4);
+        let writer = {
+            let db = db.clone();
+            thread::spawn(move || {
+                for _ in 0..20000 {
+                    db.record(|| (db.incr_by("n", 1).unwrap(), Some(Frame::Null)));
+                }
+            })
+        };
+        // 日志中只有 INCRBY，快照中的值应当正好等于进度
+        while !writer.is_finished() {
+            let (seq, snapshots) = Db::snapshots(slice::from_ref(&db));
+            let n = snapshots[0]
+                .get("n")
+                .map_or(0, |entry| entry.value.as_int().unwrap());
+            assert_eq!(n as u64, seq);
+        }
+        writer.join().unwrap();
+    }
+}
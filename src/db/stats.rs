@@ -0,0 +1,172 @@
+//! INFO 命令使用的统计信息
+//!
+//! 每个逻辑数据库是一个独立的 [`Db`]，key 数量、计数器和过期索引都各自维护，
+//! 服务端把所有数据库的统计汇总到 INFO 的 stats 和 keyspace 部分。
+
+use std::{fmt, iter::Sum, sync::atomic::Ordering, time::Instant};
+
+use super::{Backend, Db};
+
+/// 一个数据库的统计，格式化为 INFO keyspace 中的一行：
+/// `keys=1,expires=0,avg_ttl=0,hits=3,misses=1`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyspaceStats {
+    /// 未过期的 key 数量
+    pub keys: usize,
+    /// 其中设置了过期时间的 key 数量
+    pub expires: usize,
+    /// 设置了过期时间的 key 的平均剩余时间，单位为毫秒
+    pub avg_ttl: u64,
+    /// 读取时 key 存在的次数
+    pub hits: u64,
+    /// 读取时 key 不存在或已过期的次数
+    pub misses: u64,
+}
+
+/// Db 内部维护的计数器，读取只需要几次原子操作
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DbStats {
+    /// 读取时 key 存在的次数
+    pub hits: u64,
+    /// 读取时 key 不存在或已过期的次数
+    pub misses: u64,
+    /// 因过期被删除的 key 的数量，包括访问时发现过期和后台清理
+    pub expired: u64,
+    /// 因超出 maxmemory 被淘汰的 key 的数量
+    pub evicted: u64,
+}
+
+/// 汇总多个数据库的计数器
+impl Sum for DbStats {
+    fn sum<I: Iterator<Item = DbStats>>(iter: I) -> DbStats {
+        iter.fold(DbStats::default(), |total, stats| DbStats {
+            hits: total.hits + stats.hits,
+            misses: total.misses + stats.misses,
+            expired: total.expired + stats.expired,
+            evicted: total.evicted + stats.evicted,
+        })
+    }
+}
+
+impl fmt::Display for KeyspaceStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "keys={},expires={},avg_ttl={},hits={},misses={}",
+            self.keys, self.expires, self.avg_ttl, self.hits, self.misses
+        )
+    }
+}
+
+impl<B: Backend> Db<B> {
+    /// 读取计数器，INFO stats 以及指标导出使用
+    pub fn stats(&self) -> DbStats {
+        DbStats {
+            hits: self.shared.hits.load(Ordering::Relaxed),
+            misses: self.shared.misses.load(Ordering::Relaxed),
+            expired: self.shared.expired.load(Ordering::Relaxed),
+            evicted: self.shared.evicted.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 统计 key 数量和命中次数，需要遍历所有分片
+    pub fn keyspace_stats(&self) -> KeyspaceStats {
+        let now = Instant::now();
+        let counters = self.stats();
+        let mut stats = KeyspaceStats {
+            hits: counters.hits,
+            misses: counters.misses,
+            ..KeyspaceStats::default()
+        };
+        let mut total_ttl: u128 = 0;
+        for index in 0..self.shard_count() {
+            let shard = self.shared.backend.read(index);
+            for entry in shard.entries.values() {
+                match entry.expires_at {
+                    Some(when) if when <= now => continue,
+                    Some(when) => {
+                        stats.expires += 1;
+                        total_ttl += (when - now).as_millis();
+                    }
+                    None => {}
+                }
+                stats.keys += 1;
+            }
+        }
+        if stats.expires > 0 {
+            stats.avg_ttl = (total_ttl / stats.expires as u128) as u64;
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn databases_are_counted_separately() {
+        let (db0, db1) = (Db::new(), Db::new());
+        db0.set("a".into(), Bytes::new(), None).unwrap();
+        db0.set("b".into(), Bytes::new(), Some(Duration::from_secs(60)))
+            .unwrap();
+        db0.set("c".into(), Bytes::new(), Some(Duration::ZERO))
+            .unwrap();
+        db1.set("a".into(), Bytes::new(), None).unwrap();
+        db0.get("a").unwrap();
+        db0.get("missing").unwrap();
+
+        let stats = db0.keyspace_stats();
+        assert_eq!((stats.keys, stats.expires), (2, 1));
+        assert!(stats.avg_ttl > 50_000);
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        db0.get("c").unwrap();
+        assert_eq!(
+            db0.stats(),
+            DbStats {
+                hits: 1,
+                misses: 2,
+                expired: 1,
+                evicted: 0,
+            }
+        );
+        assert_eq!(
+            db1.keyspace_stats().to_string(),
+            "keys=1,expires=0,avg_ttl=0,hits=0,misses=0"
+        );
+    }
+
+    #[test]
+    fn counters_track_hits_misses_expired_and_evicted() {
+        let db = Db::with_shards(1);
+        let value = Bytes::from(vec![0; 100]);
+        db.set("a".into(), value.clone(), None).unwrap();
+        db.set("b".into(), value.clone(), Some(Duration::ZERO))
+            .unwrap();
+
+        assert!(db.get("a").unwrap().is_some());
+        assert!(db.get("missing").unwrap().is_none());
+        // 访问已过期的 key 时删除，同时计入未命中和过期
+        assert!(db.get("b").unwrap().is_none());
+        assert_eq!(
+            db.stats(),
+            DbStats {
+                hits: 1,
+                misses: 2,
+                expired: 1,
+                evicted: 0,
+            }
+        );
+
+        db.set_max_memory(db.used_memory());
+        db.set_eviction_policy(crate::db::evict::policy_from_name("allkeys-random").unwrap());
+        db.set("c".into(), value.clone(), None).unwrap();
+        db.set("d".into(), value, None).unwrap();
+        assert_eq!(db.stats().evicted, 1);
+        assert_eq!(db.stats().expired, 1);
+    }
+}
@@ -0,0 +1,242 @@
+//! 内存之外的存储层
+//!
+//! Db 本身只保存在内存中。配置了 [`StorageBackend`] 之后，热数据留在内存，
+//! 冷数据放在更慢但容量更大的存储中：
+//! - 读穿透：命令执行前调用 [`Db::read_through`]，把内存中没有的 key 从存储加载进来
+//! - 写穿透：写命令执行后调用 [`Db::write_through`]，把 key 的最新值写回存储
+//!
+//! 所有数据都已经写回存储，内存中的 key 可以随时被淘汰，下一次访问时再加载。
+//! 并发写同一个 key 时，先读取内存值的写回可能晚于后读取的写回，存储中的值会暂时落后，
+//! 直到该 key 的下一次写入。
+
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    ops::Bound,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::future::BoxFuture;
+
+use super::{
+    rdb::{self, Reader},
+    Backend, Db, Entry, Value,
+};
+
+#[cfg(feature = "sled")]
+mod sled;
+#[cfg(feature = "sled")]
+pub use self::sled::SledStorage;
+
+/// 异步的键值存储，值是 Db 编码后的字节
+///
+/// 返回 `BoxFuture` 而不是使用 `async fn`，以便通过 `Arc<dyn StorageBackend>` 在运行时配置。
+pub trait StorageBackend: Send + Sync + fmt::Debug + 'static {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<Option<Bytes>>>;
+
+    fn put<'a>(&'a self, key: &'a str, value: Bytes) -> BoxFuture<'a, io::Result<()>>;
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<()>>;
+
+    /// 按 key 的顺序返回大于 `after` 的最多 `count` 个键值对，`after` 为 None 时从头开始
+    fn scan<'a>(
+        &'a self,
+        after: Option<&'a str>,
+        count: usize,
+    ) -> BoxFuture<'a, io::Result<Vec<(String, Bytes)>>>;
+}
+
+/// 保存在内存中的存储，用于测试
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    map: Mutex<BTreeMap<String, Bytes>>,
+}
+
+impl StorageBackend for MemoryStorage {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<Option<Bytes>>> {
+        let value = self.map.lock().unwrap().get(key).cloned();
+        Box::pin(async move { Ok(value) })
+    }
+
+    fn put<'a>(&'a self, key: &'a str, value: Bytes) -> BoxFuture<'a, io::Result<()>> {
+        self.map.lock().unwrap().insert(key.to_string(), value);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<()>> {
+        self.map.lock().unwrap().remove(key);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn scan<'a>(
+        &'a self,
+        after: Option<&'a str>,
+        count: usize,
+    ) -> BoxFuture<'a, io::Result<Vec<(String, Bytes)>>> {
+        let start = match after {
+            Some(after) => Bound::Excluded(after),
+            None => Bound::Unbounded,
+        };
+        let entries = self
+            .map
+            .lock()
+            .unwrap()
+            .range::<str, _>((start, Bound::Unbounded))
+            .take(count)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        Box::pin(async { Ok(entries) })
+    }
+}
+
+impl<B: Backend> Db<B> {
+    /// 设置存储层，之后的读穿透和写穿透都使用它
+    pub fn set_storage(&self, storage: Arc<dyn StorageBackend>) {
+        *self.shared.storage.write().unwrap() = Some(storage);
+    }
+
+    fn storage(&self) -> Option<Arc<dyn StorageBackend>> {
+        self.shared.storage.read().unwrap().clone()
+    }
+
+    /// 从存储加载内存中不存在的 key，没有配置存储时什么也不做
+    ///
+    /// 存储中已经过期的 key 会被顺便删除。
+    pub async fn read_through(&self, keys: &[&str]) -> io::Result<()> {
+        let Some(storage) = self.storage() else {
+            return Ok(());
+        };
+        for key in keys {
+            if self.contains(key) {
+                continue;
+            }
+            let Some(data) = storage.get(key).await? else {
+                continue;
+            };
+            let (value, expires_at) = decode_entry(data)?;
+            if expires_at.is_some_and(|when| when <= Instant::now()) {
+                storage.delete(key).await?;
+                continue;
+            }
+            // 淘汰失败时仍然加载，否则命令看不到存储中已有的数据
+            let _ = self.shared.evict_if_needed();
+            // 加载期间其他连接可能已经写入了这个 key，以内存中的值为准
+            let mut shard = self.shared.backend.write(self.shard_index(key));
+            if !shard.entries.contains_key(*key) {
+                let wake = shard.set(key.to_string(), value, expires_at);
+                drop(shard);
+                if wake {
+                    self.shared.background_task.notify_one();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 把 key 在内存中的最新值写回存储，key 不存在时从存储中删除；没有配置存储时什么也不做
+    pub async fn write_through(&self, keys: &[&str]) -> io::Result<()> {
+        let Some(storage) = self.storage() else {
+            return Ok(());
+        };
+        for key in keys {
+            let encoded = {
+                let shard = self.shared.backend.read(self.shard_index(key));
+                shard
+                    .entries
+                    .get(*key)
+                    .filter(|entry| !entry.is_expired(Instant::now()))
+                    .map(encode_entry)
+            };
+            match encoded {
+                Some(data) => storage.put(key, data).await?,
+                None => storage.delete(key).await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// 内存中是否有未过期的 key，不更新访问时间
+    fn contains(&self, key: &str) -> bool {
+        let shard = self.shared.backend.read(self.shard_index(key));
+        shard
+            .entries
+            .get(key)
+            .is_some_and(|entry| !entry.is_expired(Instant::now()))
+    }
+}
+
+/// 编码为 类型(u8) 过期时间 值，过期时间是 1 字节标记加上 u64 的 Unix 毫秒时间戳
+///
+/// `Instant` 在进程重启后没有意义，存储中使用绝对的系统时间。
+fn encode_entry(entry: &Entry) -> Bytes {
+    let mut buf = BytesMut::new();
+    buf.put_u8(rdb::type_of(&entry.value));
+    match entry.expires_at {
+        Some(when) => {
+            buf.put_u8(1);
+            let ttl = when.saturating_duration_since(Instant::now());
+            buf.put_u64_le(unix_millis(SystemTime::now() + ttl));
+        }
+        None => buf.put_u8(0),
+    }
+    rdb::encode_value(&mut buf, &entry.value);
+    buf.freeze()
+}
+
+fn decode_entry(data: Bytes) -> io::Result<(Value, Option<Instant>)> {
+    let mut r = Reader::new(data);
+    let decode = |r: &mut Reader| {
+        let ty = r.u8()?;
+        let expires_at = match r.u8()? {
+            0 => None,
+            _ => {
+                let at = UNIX_EPOCH + Duration::from_millis(r.u64()?);
+                let ttl = at.duration_since(SystemTime::now()).unwrap_or_default();
+                Some(Instant::now() + ttl)
+            }
+        };
+        Ok::<_, rdb::RdbError>((rdb::decode_value(r, ty)?, expires_at))
+    };
+    decode(&mut r).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn evicted_keys_are_read_back_from_storage() {
+        let db = Db::new();
+        let storage = Arc::new(MemoryStorage::default());
+        db.set_storage(storage.clone());
+
+        db.set(
+            "a".into(),
+            Bytes::from_static(b"1"),
+            Some(Duration::from_secs(60)),
+        )
+        .unwrap();
+        db.write_through(&["a"]).await.unwrap();
+        assert_eq!(storage.scan(None, 10).await.unwrap().len(), 1);
+
+        // 模拟被淘汰
+        db.shared.backend.write(db.shard_index("a")).remove("a");
+        assert_eq!(db.get("a"), Ok(None));
+
+        db.read_through(&["a"]).await.unwrap();
+        assert_eq!(db.get("a"), Ok(Some(Bytes::from_static(b"1"))));
+        let expires_at = db.shared.backend.read(db.shard_index("a")).entries["a"].expires_at;
+        assert!(expires_at.is_some());
+
+        db.del(&["a".to_string()]);
+        db.write_through(&["a"]).await.unwrap();
+        assert_eq!(storage.get("a").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,278 @@
+//! 基于 sled 的持久化存储层
+//!
+//! 写入先放进内存中的待写队列，由后台线程批量写入 sled，命令不需要等待磁盘。
+//! 同一个 key 的多次写入在队列中合并为最后一次，读取时优先读队列，保证能读到自己的写入。
+//! 代价是进程崩溃时会丢失还在队列中的写入，正常关闭（drop）时会等待队列写完。
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    ops::Bound,
+    path::Path,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use tracing::error;
+
+use super::StorageBackend;
+
+#[derive(Debug)]
+pub struct SledStorage {
+    queue: Arc<Queue>,
+    tree: ::sled::Db,
+    writer: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    state: Mutex<QueueState>,
+    /// 有新的写入或者即将关闭
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    /// 还没有写入 sled 的修改，None 表示删除；序号用于判断写入期间 key 是否又被修改
+    pending: HashMap<String, (u64, Option<Bytes>)>,
+    next_seq: u64,
+    closed: bool,
+}
+
+impl SledStorage {
+    /// 打开或创建 `path` 目录下的 sled 数据库，并启动后台写入线程
+    pub fn open(path: impl AsRef<Path>) -> io::Result<SledStorage> {
+        let tree = ::sled::open(path).map_err(into_io)?;
+        let queue = Arc::new(Queue::default());
+        let writer = {
+            let queue = Arc::clone(&queue);
+            let tree = tree.clone();
+            thread::Builder::new()
+                .name("sled-writer".into())
+                .spawn(move || write_behind(&queue, &tree))?
+        };
+        Ok(SledStorage {
+            queue,
+            tree,
+            writer: Some(writer),
+        })
+    }
+
+    fn enqueue(&self, key: &str, value: Option<Bytes>) {
+        let mut state = self.queue.state.lock().unwrap();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.pending.insert(key.to_string(), (seq, value));
+        self.queue.changed.notify_one();
+    }
+
+    /// 队列中 key 的修改，外层 None 表示队列中没有这个 key
+    fn pending(&self, key: &str) -> Option<Option<Bytes>> {
+        let state = self.queue.state.lock().unwrap();
+        state.pending.get(key).map(|(_, value)| value.clone())
+    }
+}
+
+impl Drop for SledStorage {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().closed = true;
+        self.queue.changed.notify_one();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// 后台写入线程：每次取出队列中的所有修改，作为一个批次写入 sled
+fn write_behind(queue: &Queue, tree: &::sled::Db) {
+    loop {
+        let batch: Vec<_> = {
+            let mut state = queue.state.lock().unwrap();
+            while state.pending.is_empty() && !state.closed {
+                state = queue.changed.wait(state).unwrap();
+            }
+            if state.pending.is_empty() {
+                break;
+            }
+            state
+                .pending
+                .iter()
+                .map(|(key, (seq, value))| (key.clone(), *seq, value.clone()))
+                .collect()
+        };
+
+        let mut sled_batch = ::sled::Batch::default();
+        for (key, _, value) in &batch {
+            match value {
+                Some(value) => sled_batch.insert(key.as_bytes(), &value[..]),
+                None => sled_batch.remove(key.as_bytes()),
+            }
+        }
+        if let Err(e) = tree.apply_batch(sled_batch) {
+            // 写入失败时修改留在队列中，下一轮重试；正在关闭时放弃
+            error!(error = %e, "Error writing to sled");
+            if queue.state.lock().unwrap().closed {
+                return;
+            }
+            thread::sleep(Duration::from_secs(1));
+            continue;
+        }
+
+        // 写入期间又被修改的 key 留在队列中，等下一轮写入
+        let mut state = queue.state.lock().unwrap();
+        for (key, seq, _) in batch {
+            if state.pending.get(&key).is_some_and(|(s, _)| *s == seq) {
+                state.pending.remove(&key);
+            }
+        }
+    }
+    if let Err(e) = tree.flush() {
+        error!(error = %e, "Error flushing sled");
+    }
+}
+
+impl StorageBackend for SledStorage {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<Option<Bytes>>> {
+        let result = match self.pending(key) {
+            Some(value) => Ok(value),
+            None => self
+                .tree
+                .get(key)
+                .map(|value| value.map(|v| Bytes::copy_from_slice(&v)))
+                .map_err(into_io),
+        };
+        Box::pin(async { result })
+    }
+
+    fn put<'a>(&'a self, key: &'a str, value: Bytes) -> BoxFuture<'a, io::Result<()>> {
+        self.enqueue(key, Some(value));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<()>> {
+        self.enqueue(key, None);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn scan<'a>(
+        &'a self,
+        after: Option<&'a str>,
+        count: usize,
+    ) -> BoxFuture<'a, io::Result<Vec<(String, Bytes)>>> {
+        Box::pin(async move { self.scan_merged(after, count) })
+    }
+}
+
+impl SledStorage {
+    /// 合并 sled 中的数据与队列中的修改，队列中的修改优先
+    fn scan_merged(&self, after: Option<&str>, count: usize) -> io::Result<Vec<(String, Bytes)>> {
+        let overlay: BTreeMap<String, Option<Bytes>> = {
+            let state = self.queue.state.lock().unwrap();
+            state
+                .pending
+                .iter()
+                .filter(|(key, _)| after.is_none_or(|after| key.as_str() > after))
+                .map(|(key, (_, value))| (key.clone(), value.clone()))
+                .collect()
+        };
+        let start = match after {
+            Some(after) => Bound::Excluded(after.as_bytes()),
+            None => Bound::Unbounded,
+        };
+        let mut stored = self
+            .tree
+            .range::<&[u8], _>((start, Bound::Unbounded))
+            .map(|item| -> io::Result<(String, Bytes)> {
+                let (key, value) = item.map_err(into_io)?;
+                let key = String::from_utf8(key.to_vec())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                Ok((key, Bytes::copy_from_slice(&value)))
+            })
+            .peekable();
+        let mut overlay = overlay.into_iter().peekable();
+
+        let mut entries = Vec::with_capacity(count);
+        while entries.len() < count {
+            let take_overlay = match (stored.peek(), overlay.peek()) {
+                (_, None) => false,
+                (None, Some(_)) => true,
+                (Some(Err(_)), Some(_)) => false,
+                (Some(Ok((stored_key, _))), Some((pending_key, _))) => pending_key <= stored_key,
+            };
+            if take_overlay {
+                let (key, value) = overlay.next().unwrap();
+                // 队列中的修改覆盖 sled 中的同名 key
+                if stored
+                    .peek()
+                    .is_some_and(|item| matches!(item, Ok((k, _)) if *k == key))
+                {
+                    stored.next();
+                }
+                if let Some(value) = value {
+                    entries.push((key, value));
+                }
+            } else {
+                match stored.next() {
+                    Some(item) => entries.push(item?),
+                    None => break,
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
+
+fn into_io(e: ::sled::Error) -> io::Error {
+    match e {
+        ::sled::Error::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn pending_writes_are_visible_and_persisted() {
+        let dir = std::env::temp_dir().join(format!("ilearn-sled-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        {
+            let storage = SledStorage::open(&dir).unwrap();
+            storage.put("a", Bytes::from_static(b"1")).await.unwrap();
+            storage.put("c", Bytes::from_static(b"3")).await.unwrap();
+            storage.put("b", Bytes::from_static(b"2")).await.unwrap();
+            storage.delete("c").await.unwrap();
+            assert_eq!(
+                storage.get("a").await.unwrap(),
+                Some(Bytes::from_static(b"1"))
+            );
+            let keys: Vec<_> = storage
+                .scan(Some("a"), 10)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect();
+            assert_eq!(keys, ["b"]);
+        }
+
+        // drop 时队列已经写完，重新打开后数据仍然存在。sled 的后台刷盘线程退出后才释放文件锁，
+        // 重新打开可能需要稍等
+        let storage = (0..100)
+            .find_map(|_| {
+                SledStorage::open(&dir)
+                    .inspect_err(|_| thread::sleep(Duration::from_millis(10)))
+                    .ok()
+            })
+            .unwrap();
+        assert_eq!(storage.scan(None, 10).await.unwrap().len(), 2);
+        assert_eq!(storage.get("c").await.unwrap(), None);
+        drop(storage);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
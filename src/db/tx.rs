@@ -0,0 +1,161 @@
+//! 乐观事务
+//!
+//! [`Db::transaction`] 记下被监视的 key 当前的版本号（见 [`Db::version`]），
+//! [`TxGuard::commit`] 锁住所有相关的分片后再比较一次：版本号都没有变化时才执行整批写入，
+//! 否则什么也不做。这对应 Redis 的 WATCH + MULTI/EXEC，监视期间不持有任何锁。
+
+use std::time::Instant;
+
+use super::{Backend, Db, DbError, Value};
+
+/// 事务中的一个写操作
+#[derive(Debug, Clone)]
+pub enum TxOp {
+    /// 写入值，已存在的 key 会被覆盖
+    Set {
+        key: String,
+        value: Value,
+        expires_at: Option<Instant>,
+    },
+    Del {
+        key: String,
+    },
+}
+
+impl TxOp {
+    fn key(&self) -> &str {
+        match self {
+            TxOp::Set { key, .. } | TxOp::Del { key } => key,
+        }
+    }
+}
+
+/// 被监视的 key 及其版本号
+#[derive(Debug)]
+pub struct TxGuard<'a, B: Backend> {
+    db: &'a Db<B>,
+    watched: Vec<(String, u64)>,
+}
+
+impl<B: Backend> Db<B> {
+    /// 开始一个事务，记下 `watched` 中每个 key 当前的版本号
+    pub fn transaction(&self, watched: &[&str]) -> TxGuard<'_, B> {
+        TxGuard {
+            db: self,
+            watched: watched
+                .iter()
+                .map(|key| (key.to_string(), self.version(key)))
+                .collect(),
+        }
+    }
+}
+
+impl<B: Backend> TxGuard<'_, B> {
+    /// 是否已经有被监视的 key 发生了变化，用于提前放弃事务
+    pub fn is_dirty(&self) -> bool {
+        self.watched
+            .iter()
+            .any(|(key, version)| self.db.version(key) != *version)
+    }
+
+    /// 被监视的 key 都没有变化时原子地执行 `ops`，返回是否执行
+    ///
+    /// 被监视的 key 被修改、删除或者过期都会导致放弃执行，不存在的 key 被创建后又删除也一样。
+    pub fn commit(self, ops: Vec<TxOp>) -> Result<bool, DbError> {
+        let keys: Vec<String> = self
+            .watched
+            .iter()
+            .map(|(key, _)| key.clone())
+            .chain(ops.iter().map(|op| op.key().to_string()))
+            .collect();
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        self.db.with_keys(&keys, |locked| {
+            let changed = self
+                .watched
+                .iter()
+                .any(|(key, version)| locked.version(key) != *version);
+            if changed {
+                return Ok(false);
+            }
+            for op in ops {
+                match op {
+                    TxOp::Set {
+                        key,
+                        value,
+                        expires_at,
+                    } => locked.insert(&key, value, expires_at),
+                    TxOp::Del { key } => {
+                        locked.remove(&key);
+                    }
+                }
+            }
+            Ok(true)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    fn set(key: &str, value: &'static [u8]) -> TxOp {
+        TxOp::Set {
+            key: key.to_string(),
+            value: Value::from(Bytes::from_static(value)),
+            expires_at: None,
+        }
+    }
+
+    #[test]
+    fn commit_aborts_when_watched_key_changes() {
+        let db = Db::new();
+        db.set("a".into(), Bytes::from_static(b"1"), None).unwrap();
+
+        let tx = db.transaction(&["a"]);
+        assert!(tx.commit(vec![set("b", b"1")]).unwrap());
+        assert_eq!(db.get("b"), Ok(Some(Bytes::from_static(b"1"))));
+
+        let tx = db.transaction(&["a", "missing"]);
+        db.incr_by("a", 1).unwrap();
+        assert!(tx.is_dirty());
+        assert!(!tx.commit(vec![set("b", b"2")]).unwrap());
+        assert_eq!(db.get("b"), Ok(Some(Bytes::from_static(b"1"))));
+
+        // 被监视的 key 不存在时，创建它同样会导致放弃
+        let tx = db.transaction(&["missing"]);
+        db.set("missing".into(), Bytes::new(), None).unwrap();
+        assert!(!tx
+            .commit(vec![TxOp::Del {
+                key: "b".to_string()
+            }])
+            .unwrap());
+    }
+
+    #[test]
+    fn commit_aborts_when_watched_key_is_created_and_deleted() {
+        let db = Db::new();
+        let tx = db.transaction(&["missing"]);
+        db.set("missing".into(), Bytes::new(), None).unwrap();
+        db.del(&["missing".to_string()]);
+        assert!(tx.is_dirty());
+        assert!(!tx.commit(vec![set("b", b"1")]).unwrap());
+        assert_eq!(db.get("b"), Ok(None));
+    }
+
+    #[test]
+    fn failed_commands_do_not_abort_the_transaction() {
+        let db = Db::new();
+        db.set("s".into(), Bytes::from_static(b"text"), None)
+            .unwrap();
+
+        // WATCH s，INCR s 失败，EXEC 仍然执行
+        let tx = db.transaction(&["s"]);
+        assert_eq!(db.incr_by("s", 1), Err(DbError::NotInteger));
+        assert!(!tx.is_dirty());
+        assert!(tx.commit(vec![set("b", b"1")]).unwrap());
+        assert_eq!(db.get("s"), Ok(Some(Bytes::from_static(b"text"))));
+        assert_eq!(db.get("b"), Ok(Some(Bytes::from_static(b"1"))));
+    }
+}
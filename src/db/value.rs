@@ -0,0 +1,200 @@
+//! Db 中保存的值
+//!
+//! 所有数据类型都存放在同一个 [`Value`] 枚举中，共用过期、淘汰和内存统计的逻辑。
+//! 命令按自己需要的类型访问值，类型不匹配时返回 [`DbError::WrongType`]。
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+};
+
+use bytes::Bytes;
+
+use super::{DbError, SmallBytes};
+use crate::stream::Stream;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    String(SmallBytes),
+    /// 内容是整数的字符串，对外仍然是 string 类型，INCR 等命令修改它不需要分配内存
+    Int(i64),
+    List(VecDeque<Bytes>),
+    Hash(HashMap<Bytes, Bytes>),
+    Set(HashSet<Bytes>),
+    ZSet(ZSet),
+    Stream(Stream),
+}
+
+/// 为每种类型生成 `as_xxx`、`as_xxx_mut` 两个访问方法
+macro_rules! accessors {
+    ($($variant:ident => $name:ident, $name_mut:ident: $ty:ty;)*) => {
+        $(
+            pub fn $name(&self) -> Result<&$ty, DbError> {
+                match self {
+                    Value::$variant(value) => Ok(value),
+                    _ => Err(DbError::WrongType),
+                }
+            }
+
+            pub fn $name_mut(&mut self) -> Result<&mut $ty, DbError> {
+                match self {
+                    Value::$variant(value) => Ok(value),
+                    _ => Err(DbError::WrongType),
+                }
+            }
+        )*
+    };
+}
+
+impl Value {
+    accessors! {
+        String => as_string, as_string_mut: SmallBytes;
+        List => as_list, as_list_mut: VecDeque<Bytes>;
+        Hash => as_hash, as_hash_mut: HashMap<Bytes, Bytes>;
+        Set => as_set, as_set_mut: HashSet<Bytes>;
+        ZSet => as_zset, as_zset_mut: ZSet;
+        Stream => as_stream, as_stream_mut: Stream;
+    }
+
+    /// TYPE 命令返回的类型名
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) | Value::Int(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// OBJECT ENCODING 命令返回的内部编码，名字取自 Redis 中对应的编码
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Value::String(data) if data.is_inline() => "embstr",
+            Value::String(_) => "raw",
+            Value::Int(_) => "int",
+            Value::List(_) => "quicklist",
+            Value::Hash(_) | Value::Set(_) => "hashtable",
+            Value::ZSet(_) => "skiplist",
+            Value::Stream(_) => "stream",
+        }
+    }
+}
+
+impl Value {
+    /// 字符串类型的内容，整数会被格式化为十进制
+    pub fn to_string_bytes(&self) -> Result<Bytes, DbError> {
+        match self {
+            Value::String(data) => Ok(data.to_bytes()),
+            Value::Int(n) => Ok(Bytes::from(n.to_string())),
+            _ => Err(DbError::WrongType),
+        }
+    }
+
+    /// 字符串类型的整数值，内容不是整数时返回 [`DbError::NotInteger`]
+    pub fn as_int(&self) -> Result<i64, DbError> {
+        match self {
+            Value::Int(n) => Ok(*n),
+            Value::String(data) => parse_int(data).ok_or(DbError::NotInteger),
+            _ => Err(DbError::WrongType),
+        }
+    }
+}
+
+impl From<Bytes> for Value {
+    /// 内容是规范形式的整数时保存为 [`Value::Int`]
+    fn from(data: Bytes) -> Value {
+        match parse_int(&data) {
+            Some(n) => Value::Int(n),
+            None => Value::String(data.into()),
+        }
+    }
+}
+
+/// 解析规范形式的十进制整数，与 Redis 的 string2ll 一致
+///
+/// 不接受前导的 `+`、多余的 0 和 `-0`，保证格式化回字符串时与原内容完全相同。
+pub fn parse_int(data: &[u8]) -> Option<i64> {
+    let digits = data.strip_prefix(b"-").unwrap_or(data);
+    if digits.is_empty() || digits.len() > 19 || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    if digits[0] == b'0' && (digits.len() > 1 || data.len() > 1) {
+        return None;
+    }
+    std::str::from_utf8(data).ok()?.parse().ok()
+}
+
+/// 有序集合：成员按分数排序，分数相同时按成员排序
+#[derive(Debug, Clone, Default)]
+pub struct ZSet {
+    scores: HashMap<Bytes, f64>,
+    ordered: BTreeSet<(Score, Bytes)>,
+}
+
+/// 可以排序的分数，NaN 不会被写入
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Score(f64);
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Score) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Score) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl ZSet {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// 写入成员的分数，返回是否为新成员
+    pub fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        let prev = self.scores.insert(member.clone(), score);
+        if let Some(prev) = prev {
+            self.ordered.remove(&(Score(prev), member.clone()));
+        }
+        self.ordered.insert((Score(score), member));
+        prev.is_none()
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove_entry(member) {
+            Some((member, score)) => {
+                self.ordered.remove(&(Score(score), member));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 成员索引的容量，供后台收缩判断
+    pub(super) fn capacity(&self) -> usize {
+        self.scores.capacity()
+    }
+
+    pub(super) fn shrink_to_fit(&mut self) {
+        self.scores.shrink_to_fit();
+    }
+
+    /// 按分数从小到大遍历
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&Bytes, f64)> + ExactSizeIterator {
+        self.ordered.iter().map(|(score, member)| (member, score.0))
+    }
+}
@@ -0,0 +1,44 @@
+//! 带过期时间的 key 的集合，支持 O(1) 随机抽样
+//!
+//! 抽样模式的过期清理使用它代替时间轮。与时间轮一样不会在 key 被删除或覆盖时同步移除，
+//! 抽到已经不存在或不再带过期时间的 key 时再由调用方移除。
+
+use std::collections::HashMap;
+
+use rand::Rng;
+
+#[derive(Debug, Default)]
+pub struct VolatileKeys {
+    keys: Vec<String>,
+    /// key 在 `keys` 中的下标
+    positions: HashMap<String, usize>,
+}
+
+impl VolatileKeys {
+    pub fn insert(&mut self, key: &str) {
+        if self.positions.contains_key(key) {
+            return;
+        }
+        self.positions.insert(key.to_string(), self.keys.len());
+        self.keys.push(key.to_string());
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        let Some(index) = self.positions.remove(key) else {
+            return;
+        };
+        self.keys.swap_remove(index);
+        if let Some(moved) = self.keys.get(index) {
+            self.positions.insert(moved.clone(), index);
+        }
+    }
+
+    /// 随机取出一个 key，集合为空时返回 None
+    pub fn random(&self) -> Option<&str> {
+        if self.keys.is_empty() {
+            return None;
+        }
+        let index = rand::thread_rng().gen_range(0..self.keys.len());
+        Some(&self.keys[index])
+    }
+}
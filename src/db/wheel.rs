@@ -0,0 +1,320 @@
+//! 分层时间轮，用于管理 key 的过期时间
+//!
+//! 与 `BTreeSet` 相比，插入是 O(1) 的位运算加一次 `Vec::push`，
+//! 到期时一次取出整个槽位，适合大量设置短 TTL 的场景。
+//!
+//! 共 6 层，每层 64 个槽位，最底层一个槽位代表 1ms，上一层的一个槽位覆盖下一层的整圈。
+//! 高层槽位到期时，其中还没真正到期的元素会被重新插入（下沉）到更低的层。
+//! 设计参考了 tokio 的时间轮实现。
+//!
+//! 时间轮不支持删除：key 被删除或覆盖后，旧的元素仍留在槽位中，
+//! 由调用方在取出时对照实际的过期时间过滤，旧元素过多时由调用方重建。
+
+use std::{collections::BTreeMap, fmt, time::Instant};
+
+const LEVELS: usize = 6;
+const SLOTS: usize = 64;
+const SLOT_BITS: usize = 6;
+const SLOT_MASK: u64 = (1 << SLOT_BITS) - 1;
+/// 时间轮能表示的最大时间跨度（毫秒），约 2.2 年
+const MAX_DURATION: u64 = (1 << (SLOT_BITS * LEVELS)) - 1;
+/// 超过这个跨度的时间点先放在 overflow 中，保证最高层的槽位不会绕回当前槽位
+const MAX_SPAN: u64 = (SLOTS as u64 - 1) << (SLOT_BITS * (LEVELS - 1));
+
+pub struct TimerWheel {
+    /// 时间轮的零点，内部的时间都是相对它的毫秒数
+    origin: Instant,
+    /// 已经处理到的时间点
+    elapsed: u64,
+    levels: Vec<Level>,
+    /// 超出时间轮跨度的元素，按过期时间排序
+    overflow: BTreeMap<u64, Vec<String>>,
+    /// 元素总数，包含已失效的旧元素
+    len: usize,
+}
+
+struct Level {
+    level: usize,
+    /// 第 i 位为 1 表示第 i 个槽位非空
+    occupied: u64,
+    slots: Vec<Vec<(String, u64)>>,
+}
+
+/// 下一个需要处理的槽位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Expiration {
+    level: usize,
+    slot: usize,
+    deadline: u64,
+}
+
+impl fmt::Debug for TimerWheel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimerWheel")
+            .field("elapsed", &self.elapsed)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl Default for TimerWheel {
+    fn default() -> TimerWheel {
+        TimerWheel::new(Instant::now())
+    }
+}
+
+impl TimerWheel {
+    pub fn new(origin: Instant) -> TimerWheel {
+        TimerWheel {
+            origin,
+            elapsed: 0,
+            levels: (0..LEVELS).map(Level::new).collect(),
+            overflow: BTreeMap::new(),
+            len: 0,
+        }
+    }
+
+    /// 槽位中的元素总数（包含已失效的旧元素）
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 过期时间向上取整到毫秒，保证不会提前过期
+    fn to_ms(&self, when: Instant) -> u64 {
+        let nanos = when.saturating_duration_since(self.origin).as_nanos();
+        nanos.div_ceil(1_000_000) as u64
+    }
+
+    /// 当前时间向下取整到毫秒，取出的 key 的过期时间一定不晚于 `now`
+    fn to_ms_floor(&self, now: Instant) -> u64 {
+        let nanos = now.saturating_duration_since(self.origin).as_nanos();
+        (nanos / 1_000_000) as u64
+    }
+
+    fn to_instant(&self, ms: u64) -> Instant {
+        self.origin + std::time::Duration::from_millis(ms)
+    }
+
+    pub fn insert(&mut self, key: String, when: Instant) {
+        // 已经过去的时间点放到当前槽位，下一次 poll 时取出
+        let when = self.to_ms(when).max(self.elapsed);
+        self.len += 1;
+        self.insert_ms(key, when);
+    }
+
+    fn insert_ms(&mut self, key: String, when: u64) {
+        if when - self.elapsed >= MAX_SPAN {
+            self.overflow.entry(when).or_default().push(key);
+            return;
+        }
+        let level = level_for(self.elapsed, when);
+        self.levels[level].add(key, when);
+    }
+
+    /// 下一个需要处理的时间点，可以据此决定后台任务睡眠多久
+    ///
+    /// 对于高层的槽位，这是槽位的起始时间而不一定是元素的实际过期时间。
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let deadline = match (self.next_expiration(), self.next_migration()) {
+            (Some(expiration), Some(migration)) => expiration.deadline.min(migration),
+            (Some(expiration), None) => expiration.deadline,
+            (None, migration) => migration?,
+        };
+        Some(self.to_instant(deadline))
+    }
+
+    fn next_expiration(&self) -> Option<Expiration> {
+        // 低层的槽位总是先于高层到期，找到第一个非空的层即可
+        self.levels
+            .iter()
+            .find_map(|level| level.next_expiration(self.elapsed))
+    }
+
+    /// overflow 中最早的元素进入时间轮跨度的时间点
+    fn next_migration(&self) -> Option<u64> {
+        let when = self.overflow.keys().next()?;
+        Some(when.saturating_sub(MAX_SPAN - 1).max(self.elapsed))
+    }
+
+    /// 把已经进入时间轮跨度的 overflow 元素移入时间轮
+    fn migrate_overflow(&mut self) {
+        while let Some(entry) = self.overflow.first_entry() {
+            if *entry.key() - self.elapsed >= MAX_SPAN {
+                break;
+            }
+            let (when, keys) = entry.remove_entry();
+            for key in keys {
+                self.insert_ms(key, when);
+            }
+        }
+    }
+
+    /// 推进时间轮到 `now`，取出所有到期的 key
+    pub fn poll(&mut self, now: Instant) -> Vec<String> {
+        let now = self.to_ms_floor(now);
+        let mut expired = vec![];
+
+        loop {
+            self.migrate_overflow();
+            let migration = self.next_migration();
+
+            let expiration = match self.next_expiration() {
+                Some(expiration)
+                    if expiration.deadline <= now
+                        && migration.is_none_or(|at| expiration.deadline <= at) =>
+                {
+                    expiration
+                }
+                // 时间轮中没有更早到期的槽位，直接推进到 overflow 的迁移时间点
+                _ => match migration {
+                    Some(at) if at <= now => {
+                        self.elapsed = at;
+                        continue;
+                    }
+                    _ => break,
+                },
+            };
+            self.elapsed = expiration.deadline;
+
+            // 一次取出整个槽位
+            let items = self.levels[expiration.level].take(expiration.slot);
+            for (key, when) in items {
+                if when <= expiration.deadline {
+                    expired.push(key);
+                } else {
+                    // 高层槽位中尚未到期的元素下沉到更低的层
+                    self.insert_ms(key, when);
+                }
+            }
+        }
+
+        self.elapsed = self.elapsed.max(now);
+        self.len -= expired.len();
+        expired
+    }
+}
+
+impl Level {
+    fn new(level: usize) -> Level {
+        Level {
+            level,
+            occupied: 0,
+            slots: (0..SLOTS).map(|_| vec![]).collect(),
+        }
+    }
+
+    fn add(&mut self, key: String, when: u64) {
+        let slot = slot_for(when, self.level);
+        self.slots[slot].push((key, when));
+        self.occupied |= 1 << slot;
+    }
+
+    fn take(&mut self, slot: usize) -> Vec<(String, u64)> {
+        self.occupied &= !(1 << slot);
+        std::mem::take(&mut self.slots[slot])
+    }
+
+    fn next_expiration(&self, now: u64) -> Option<Expiration> {
+        if self.occupied == 0 {
+            return None;
+        }
+
+        // 从当前时间对应的槽位开始，找到下一个非空槽位（可能绕回到本圈的开头，即下一圈）
+        let slot_range = slot_range(self.level);
+        let level_range = slot_range * SLOTS as u64;
+        let now_slot = (now / slot_range) as u32;
+        let occupied = self.occupied.rotate_right(now_slot);
+        let slot = (occupied.trailing_zeros() as usize + now_slot as usize) % SLOTS;
+
+        let level_start = now & !(level_range - 1);
+        let mut deadline = level_start + slot as u64 * slot_range;
+        if deadline < now {
+            deadline += level_range;
+        }
+
+        Some(Expiration {
+            level: self.level,
+            slot,
+            deadline,
+        })
+    }
+}
+
+fn slot_range(level: usize) -> u64 {
+    1 << (SLOT_BITS * level)
+}
+
+fn slot_for(when: u64, level: usize) -> usize {
+    ((when >> (level * SLOT_BITS)) & SLOT_MASK) as usize
+}
+
+/// 根据 `when` 与当前时间最高的不同位决定放在哪一层
+fn level_for(elapsed: u64, when: u64) -> usize {
+    let mut masked = (elapsed ^ when) | SLOT_MASK;
+    if masked >= MAX_DURATION {
+        masked = MAX_DURATION - 1;
+    }
+    let significant = 63 - masked.leading_zeros() as usize;
+    significant / SLOT_BITS
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn pops_keys_in_deadline_order_across_levels() {
+        let origin = Instant::now();
+        let at = |ms| origin + Duration::from_millis(ms);
+        let mut wheel = TimerWheel::new(origin);
+
+        for (key, ms) in [("a", 5), ("b", 70), ("c", 5_000), ("d", 300_000)] {
+            wheel.insert(key.to_string(), at(ms));
+        }
+
+        assert!(wheel.poll(at(4)).is_empty());
+        assert_eq!(wheel.poll(at(5)), vec!["a"]);
+        assert_eq!(wheel.poll(at(4_999)), vec!["b"]);
+        assert_eq!(wheel.poll(at(5_000)), vec!["c"]);
+        assert_eq!(wheel.next_deadline().map(|d| d <= at(300_000)), Some(true));
+        assert_eq!(wheel.poll(at(400_000)), vec!["d"]);
+        assert_eq!(wheel.len(), 0);
+    }
+
+    #[test]
+    fn far_deadlines_go_through_overflow() {
+        let origin = Instant::now();
+        let mut wheel = TimerWheel::new(origin);
+        let far = MAX_DURATION + 10;
+        wheel.insert("far".into(), origin + Duration::from_millis(far));
+        assert!(!wheel.overflow.is_empty());
+
+        assert!(wheel
+            .poll(origin + Duration::from_millis(far - 1))
+            .is_empty());
+        assert_eq!(wheel.poll(origin + Duration::from_millis(far)), vec!["far"]);
+    }
+
+    #[test]
+    fn past_deadlines_fire_on_next_poll() {
+        let origin = Instant::now();
+        let mut wheel = TimerWheel::new(origin);
+        wheel.poll(origin + Duration::from_millis(100));
+        wheel.insert("a".into(), origin);
+        assert_eq!(wheel.poll(origin + Duration::from_millis(100)), vec!["a"]);
+    }
+
+    #[test]
+    fn keys_are_not_popped_before_their_deadline() {
+        let origin = Instant::now();
+        let mut wheel = TimerWheel::new(origin);
+        wheel.insert("a".into(), origin + Duration::from_micros(5_500));
+
+        // 不到 1ms 之后才过期，不能因为取整而提前取出
+        assert!(wheel.poll(origin + Duration::from_micros(5_200)).is_empty());
+        assert_eq!(wheel.len(), 1);
+        assert_eq!(wheel.poll(origin + Duration::from_millis(6)), vec!["a"]);
+    }
+}
@@ -0,0 +1,344 @@
+//! RESP 协议的帧
+//!
+//! 最初直接使用 `mini-redis` 的 `Frame`，它的整数帧只支持 u64，也无法编码嵌套数组，
+//! 现在改为自己实现：解析沿用 `mini-redis` 先 `check` 再 `parse` 的两步方式，编码支持任意嵌套。
+
+use std::{
+    fmt,
+    io::Cursor,
+    str::{self, Utf8Error},
+};
+
+use bytes::{Buf, Bytes};
+use thiserror::Error;
+
+/// RESP2 的一个帧，以及 RESP3 中服务端主动发送的 Push
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Bytes),
+    Null,
+    Array(Vec<Frame>),
+    /// 不是对命令的响应，只发送给 HELLO 3 切换到 RESP3 的连接，例如客户端缓存的失效消息
+    Push(Vec<Frame>),
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// 数据不足一个完整的帧，需要继续读取
+    #[error("stream ended early")]
+    Incomplete,
+    #[error("protocol error; {0}")]
+    Invalid(String),
+}
+
+/// 数组和 Push 允许的最大嵌套层数，`check` 和 `parse` 是递归实现，不限制层数时
+/// 一个深度嵌套的帧就能耗尽线程的栈
+pub const MAX_DEPTH: usize = 128;
+
+/// Bulk 允许的最大长度，与 Redis 的 `proto-max-bulk-len` 默认值相同，
+/// 避免连接声明一个巨大的长度让服务端一直缓冲数据
+pub const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// 数组和 Push 允许的最大元素个数，与 Redis 相同
+pub const MAX_ARRAY_LEN: usize = i32::MAX as usize;
+
+impl Frame {
+    /// 检查 `src` 中是否有一个完整的帧，检查通过后游标位于帧的末尾
+    pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
+        check_at(src, 0)
+    }
+
+    /// 解析一个帧，调用前应当已经通过 [`Frame::check`]
+    pub fn parse(src: &mut Cursor<&[u8]>) -> Result<Frame, Error> {
+        parse_at(src, 0)
+    }
+
+    /// 按 RESP 协议递归编码到 `dst`
+    pub fn encode(&self, dst: &mut Vec<u8>) {
+        match self {
+            Frame::Simple(val) => {
+                dst.push(b'+');
+                dst.extend_from_slice(val.as_bytes());
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Error(val) => {
+                dst.push(b'-');
+                dst.extend_from_slice(val.as_bytes());
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Integer(val) => {
+                dst.extend_from_slice(format!(":{}\r\n", val).as_bytes());
+            }
+            Frame::Null => dst.extend_from_slice(b"$-1\r\n"),
+            Frame::Bulk(val) => {
+                dst.extend_from_slice(format!("${}\r\n", val.len()).as_bytes());
+                dst.extend_from_slice(val);
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Array(vals) => {
+                dst.extend_from_slice(format!("*{}\r\n", vals.len()).as_bytes());
+                for val in vals {
+                    val.encode(dst);
+                }
+            }
+            Frame::Push(vals) => {
+                dst.extend_from_slice(format!(">{}\r\n", vals.len()).as_bytes());
+                for val in vals {
+                    val.encode(dst);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Frame::Simple(response) => response.fmt(f),
+            Frame::Error(msg) => write!(f, "error: {}", msg),
+            Frame::Integer(num) => num.fmt(f),
+            Frame::Bulk(msg) => match str::from_utf8(msg) {
+                Ok(string) => string.fmt(f),
+                Err(_) => write!(f, "{:?}", msg),
+            },
+            Frame::Null => "(nil)".fmt(f),
+            Frame::Array(parts) | Frame::Push(parts) => {
+                for (i, part) in parts.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    part.fmt(f)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// `depth` 是当前帧所在的层数，最外层为 0
+fn check_at(src: &mut Cursor<&[u8]>, depth: usize) -> Result<(), Error> {
+    match get_u8(src)? {
+        b'+' | b'-' => {
+            get_line(src)?;
+        }
+        b':' => {
+            get_decimal(src)?;
+        }
+        b'$' => {
+            if let Some(len) = get_bulk_length(src)? {
+                skip(src, len + 2)?;
+            }
+        }
+        b'*' | b'>' => {
+            if let Some(len) = get_array_length(src, depth)? {
+                for _ in 0..len {
+                    check_at(src, depth + 1)?;
+                }
+            }
+        }
+        actual => {
+            return Err(Error::Invalid(format!(
+                "invalid frame type byte `{}`",
+                actual
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn parse_at(src: &mut Cursor<&[u8]>, depth: usize) -> Result<Frame, Error> {
+    match get_u8(src)? {
+        b'+' => Ok(Frame::Simple(get_string(src)?)),
+        b'-' => Ok(Frame::Error(get_string(src)?)),
+        b':' => Ok(Frame::Integer(get_decimal(src)?)),
+        b'$' => match get_bulk_length(src)? {
+            None => Ok(Frame::Null),
+            Some(len) => {
+                if src.remaining() < len + 2 {
+                    return Err(Error::Incomplete);
+                }
+                let data = Bytes::copy_from_slice(&src.chunk()[..len]);
+                skip(src, len + 2)?;
+                Ok(Frame::Bulk(data))
+            }
+        },
+        b'*' => match get_array_length(src, depth)? {
+            None => Ok(Frame::Null),
+            Some(len) => {
+                let mut frames = Vec::with_capacity(len);
+                for _ in 0..len {
+                    frames.push(parse_at(src, depth + 1)?);
+                }
+                Ok(Frame::Array(frames))
+            }
+        },
+        b'>' => {
+            let len = get_array_length(src, depth)?.unwrap_or(0);
+            let mut frames = Vec::with_capacity(len);
+            for _ in 0..len {
+                frames.push(parse_at(src, depth + 1)?);
+            }
+            Ok(Frame::Push(frames))
+        }
+        actual => Err(Error::Invalid(format!(
+            "invalid frame type byte `{}`",
+            actual
+        ))),
+    }
+}
+
+fn get_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
+    if !src.has_remaining() {
+        return Err(Error::Incomplete);
+    }
+    Ok(src.get_u8())
+}
+
+fn skip(src: &mut Cursor<&[u8]>, n: usize) -> Result<(), Error> {
+    if src.remaining() < n {
+        return Err(Error::Incomplete);
+    }
+    src.advance(n);
+    Ok(())
+}
+
+/// 读取到 `\r\n` 为止的一行，不包含换行
+fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
+    let start = src.position() as usize;
+    let data: &'a [u8] = src.get_ref();
+    let end = data[start..]
+        .windows(2)
+        .position(|window| window == b"\r\n")
+        .ok_or(Error::Incomplete)?;
+    src.set_position((start + end + 2) as u64);
+    Ok(&data[start..start + end])
+}
+
+fn get_string(src: &mut Cursor<&[u8]>) -> Result<String, Error> {
+    let line = get_line(src)?;
+    Ok(str::from_utf8(line).map_err(invalid_utf8)?.to_string())
+}
+
+fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<i64, Error> {
+    let line = get_line(src)?;
+    str::from_utf8(line)
+        .map_err(invalid_utf8)?
+        .parse()
+        .map_err(|_| Error::Invalid("invalid frame format".into()))
+}
+
+/// 读取 Bulk 和数组的长度，-1 表示 Null
+fn get_length(src: &mut Cursor<&[u8]>) -> Result<Option<usize>, Error> {
+    match get_decimal(src)? {
+        -1 => Ok(None),
+        len => usize::try_from(len)
+            .map(Some)
+            .map_err(|_| Error::Invalid(format!("invalid length {}", len))),
+    }
+}
+
+fn get_bulk_length(src: &mut Cursor<&[u8]>) -> Result<Option<usize>, Error> {
+    match get_length(src)? {
+        Some(len) if len > MAX_BULK_LEN => {
+            Err(Error::Invalid(format!("invalid bulk length {}", len)))
+        }
+        len => Ok(len),
+    }
+}
+
+/// 读取数组的长度，`depth` 是这个数组所在的层数，最外层为 0
+fn get_array_length(src: &mut Cursor<&[u8]>, depth: usize) -> Result<Option<usize>, Error> {
+    if depth >= MAX_DEPTH {
+        return Err(Error::Invalid(format!(
+            "nesting depth exceeds {}",
+            MAX_DEPTH
+        )));
+    }
+    match get_length(src)? {
+        Some(len) if len > MAX_ARRAY_LEN => {
+            Err(Error::Invalid(format!("invalid multibulk length {}", len)))
+        }
+        len => Ok(len),
+    }
+}
+
+fn invalid_utf8(e: Utf8Error) -> Error {
+    Error::Invalid(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoded_frames_parse_back() {
+        let frame = Frame::Array(vec![
+            Frame::Simple("OK".into()),
+            Frame::Error("ERR oops".into()),
+            Frame::Integer(-2),
+            Frame::Bulk(Bytes::from_static(b"a\r\nb")),
+            Frame::Null,
+            Frame::Array(vec![Frame::Bulk(Bytes::new())]),
+            Frame::Push(vec![
+                Frame::Bulk(Bytes::from_static(b"invalidate")),
+                Frame::Null,
+            ]),
+        ]);
+        let mut buf = vec![];
+        frame.encode(&mut buf);
+
+        for len in 0..buf.len() {
+            let mut partial = Cursor::new(&buf[..len]);
+            assert!(matches!(Frame::check(&mut partial), Err(Error::Incomplete)));
+        }
+        let mut src = Cursor::new(&buf[..]);
+        Frame::check(&mut src).unwrap();
+        assert_eq!(src.position() as usize, buf.len());
+        src.set_position(0);
+        assert_eq!(Frame::parse(&mut src).unwrap(), frame);
+    }
+
+    #[test]
+    fn deeply_nested_arrays_are_rejected() {
+        let buf = b"*1\r\n".repeat(200_000);
+        let mut src = Cursor::new(&buf[..]);
+        assert!(matches!(Frame::check(&mut src), Err(Error::Invalid(_))));
+        src.set_position(0);
+        assert!(matches!(Frame::parse(&mut src), Err(Error::Invalid(_))));
+
+        let mut frame = Frame::Array(vec![]);
+        for _ in 1..MAX_DEPTH {
+            frame = Frame::Array(vec![frame]);
+        }
+        let mut buf = vec![];
+        frame.encode(&mut buf);
+        let mut src = Cursor::new(&buf[..]);
+        Frame::check(&mut src).unwrap();
+        src.set_position(0);
+        assert_eq!(Frame::parse(&mut src).unwrap(), frame);
+
+        let frame = Frame::Push(vec![frame]);
+        let mut buf = vec![];
+        frame.encode(&mut buf);
+        assert!(matches!(
+            Frame::check(&mut Cursor::new(&buf[..])),
+            Err(Error::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn oversized_lengths_are_rejected() {
+        let bulk = format!("${}\r\n", MAX_BULK_LEN + 1);
+        let array = format!("*{}\r\n", MAX_ARRAY_LEN + 1);
+        for buf in [bulk, array] {
+            let mut src = Cursor::new(buf.as_bytes());
+            assert!(matches!(Frame::check(&mut src), Err(Error::Invalid(_))));
+        }
+
+        let mut src = Cursor::new(&b"$5\r\nab"[..]);
+        assert!(matches!(Frame::check(&mut src), Err(Error::Incomplete)));
+    }
+}
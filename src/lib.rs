@@ -245,3 +245,23 @@ pub fn search_case_insensitive_right<'a>(query: &'a str, content: &'a str) -> Ve
 }
 
 pub mod threadpool;
+
+pub mod stream;
+
+pub mod acl;
+
+pub mod client;
+
+pub mod cluster;
+
+pub mod cmd;
+
+pub mod config;
+
+pub mod connection;
+
+pub mod db;
+
+pub mod frame;
+
+pub mod server;
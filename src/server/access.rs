@@ -0,0 +1,144 @@
+//! 按对端地址限制访问
+//!
+//! 配置了 `allowlist` 时，地址不在任何一个网段中的连接在接受时就被拒绝。
+//! 保护模式与 Redis 相同：开启 `protected-mode`、监听了非回环地址并且 default 用户没有密码时，
+//! 只有本机的客户端可以执行命令，其他客户端的每个命令都收到说明原因的错误。
+//! 密码可以在运行时通过 ACL SETUSER 设置，所以每个命令都会检查。
+
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use super::{Listener, PeerAddr};
+use crate::{
+    acl::{Users, DEFAULT_USER},
+    config::Config,
+};
+
+/// 保护模式下非本机客户端收到的错误
+pub(crate) const PROTECTED_MODE_ERROR: &str = "DENIED Running in protected mode because \
+    protected mode is enabled and no password is set for the default user. \
+    In this mode connections are only accepted from the loopback interface. \
+    Set a password with --requirepass or ACL SETUSER, or disable protected mode \
+    with --protected-mode no if you want to connect from external computers.";
+
+/// 一个网段，如 `10.0.0.0/8`，不带前缀长度时只包含这一个地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 客户端连接到双栈的 IPv6 地址时，对端地址为 ::ffff:a.b.c.d
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                masked(u32::from(net).into(), 32, self.prefix)
+                    == masked(u32::from(ip).into(), 32, self.prefix)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                masked(net.into(), 128, self.prefix) == masked(ip.into(), 128, self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// 保留 `bits` 位地址的前 `prefix` 位
+fn masked(addr: u128, bits: u8, prefix: u8) -> u128 {
+    match bits - prefix {
+        0 => addr,
+        n if n >= 128 => 0,
+        n => addr >> n << n,
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Cidr, ()> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| ())?;
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| ())?,
+            None => bits,
+        };
+        if prefix > bits {
+            return Err(());
+        }
+        Ok(Cidr { addr, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Access {
+    /// 开启了保护模式并且监听了非回环地址
+    protected: bool,
+    /// 为空时不限制
+    allowlist: Vec<Cidr>,
+}
+
+impl Access {
+    /// 是否监听了非回环地址按实际监听的端点判断
+    pub(crate) fn new(config: &Config, listeners: &[Listener]) -> Access {
+        let exposed = listeners.iter().any(|listener| match listener {
+            Listener::Tcp(listener) => listener
+                .local_addr()
+                .is_ok_and(|addr| !addr.ip().is_loopback()),
+            #[cfg(unix)]
+            Listener::Unix(_) => false,
+        });
+        Access {
+            protected: config.protected_mode && exposed,
+            allowlist: config.allowlist.clone(),
+        }
+    }
+
+    /// 接受连接时检查对端地址，unix socket 的客户端总是允许
+    pub(crate) fn allow_connection(&self, peer: &PeerAddr) -> bool {
+        match peer.ip() {
+            Some(ip) if !self.allowlist.is_empty() => {
+                self.allowlist.iter().any(|cidr| cidr.contains(ip))
+            }
+            _ => true,
+        }
+    }
+
+    /// 保护模式下拒绝非本机客户端的命令
+    ///
+    /// `local` 为客户端是否在本机：经过负载均衡的连接，传输层的对端和 PROXY 头部中的地址都要在本机。
+    pub(crate) fn allow_command(&self, local: bool, users: &Users) -> bool {
+        !self.protected || local || !users.is_nopass(DEFAULT_USER)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cidr_matches_prefix() {
+        let net: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(net.contains("10.1.200.3".parse().unwrap()));
+        assert!(net.contains("::ffff:10.1.0.1".parse().unwrap()));
+        assert!(!net.contains("10.2.0.1".parse().unwrap()));
+
+        let host: Cidr = "::1".parse().unwrap();
+        assert_eq!(host.to_string(), "::1/128");
+        assert!(host.contains("::1".parse().unwrap()));
+        assert!(!host.contains("127.0.0.1".parse().unwrap()));
+
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains("192.168.1.1".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+    }
+}
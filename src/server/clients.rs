@@ -0,0 +1,132 @@
+//! 所有连接的登记表，CLIENT LIST 和 CLIENT KILL 使用
+//!
+//! 每个连接建立时登记，得到一个从 1 开始递增的 id，连接关闭时移除。
+//! 连接在执行命令前后更新自己的信息，其他连接看到的是最近一次更新的结果。
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use tokio::sync::Notify;
+
+use super::PeerAddr;
+
+/// 一个连接对外可见的信息
+#[derive(Debug, Clone)]
+pub(crate) struct ClientInfo {
+    pub(crate) id: u64,
+    pub(crate) addr: PeerAddr,
+    pub(crate) name: Option<String>,
+    pub(crate) connected: Instant,
+    /// 最近一次执行命令的时间
+    pub(crate) last_activity: Instant,
+    /// 最近执行的命令名，小写
+    pub(crate) last_command: String,
+    pub(crate) db: usize,
+    pub(crate) user: String,
+}
+
+#[derive(Debug)]
+struct Entry {
+    info: ClientInfo,
+    /// CLIENT KILL 通过它通知连接退出，见 [`super::Shutdown`]
+    kill: Arc<Notify>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Clients {
+    next_id: AtomicU64,
+    clients: Mutex<HashMap<u64, Entry>>,
+}
+
+impl Clients {
+    /// 登记一个新的连接，返回它的 id 以及 CLIENT KILL 时收到通知的 [`Notify`]
+    pub(crate) fn register(&self, addr: PeerAddr, user: &str) -> (u64, Arc<Notify>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = Instant::now();
+        let kill = Arc::new(Notify::new());
+        let info = ClientInfo {
+            id,
+            addr,
+            name: None,
+            connected: now,
+            last_activity: now,
+            last_command: "NULL".into(),
+            db: 0,
+            user: user.to_string(),
+        };
+        let entry = Entry {
+            info,
+            kill: Arc::clone(&kill),
+        };
+        self.clients.lock().unwrap().insert(id, entry);
+        (id, kill)
+    }
+
+    pub(crate) fn unregister(&self, id: u64) {
+        self.clients.lock().unwrap().remove(&id);
+    }
+
+    /// 修改一个连接的信息
+    pub(crate) fn update(&self, id: u64, f: impl FnOnce(&mut ClientInfo)) {
+        if let Some(entry) = self.clients.lock().unwrap().get_mut(&id) {
+            f(&mut entry.info);
+        }
+    }
+
+    /// 按 id 排序的所有连接
+    pub(crate) fn list(&self) -> Vec<ClientInfo> {
+        let mut clients: Vec<ClientInfo> = self
+            .clients
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| entry.info.clone())
+            .collect();
+        clients.sort_by_key(|info| info.id);
+        clients
+    }
+
+    /// 通知所有满足条件的连接退出，返回连接的数量
+    ///
+    /// 连接执行完当前的命令并发送响应后才退出，与服务端关闭时相同。
+    pub(crate) fn kill(&self, filter: impl Fn(&ClientInfo) -> bool) -> usize {
+        let clients = self.clients.lock().unwrap();
+        let mut killed = 0;
+        for entry in clients.values().filter(|entry| filter(&entry.info)) {
+            entry.kill.notify_one();
+            killed += 1;
+        }
+        killed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn kill_notifies_matching_clients() {
+        let clients = Clients::default();
+        let addr: PeerAddr = "127.0.0.1:5000".parse().unwrap();
+        let (first, first_kill) = clients.register(addr.clone(), "default");
+        let (second, _) = clients.register("127.0.0.1:5001".parse().unwrap(), "default");
+        assert_eq!((first, second), (1, 2));
+
+        clients.update(first, |info| info.name = Some("worker".into()));
+        let names: Vec<_> = clients.list().into_iter().map(|info| info.name).collect();
+        assert_eq!(names, [Some("worker".into()), None]);
+
+        assert_eq!(clients.kill(|info| info.addr == addr), 1);
+        // 通知在连接开始等待之前发出也不会丢失
+        first_kill.notified().await;
+
+        clients.unregister(first);
+        assert_eq!(clients.kill(|info| info.addr == addr), 0);
+    }
+}
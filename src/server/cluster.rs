@@ -0,0 +1,49 @@
+//! 集群模式下这个节点的状态
+//!
+//! 拓扑可以通过 CONFIG SET 修改，迁移 slot 时依次修改两端的 `cluster-migrating`、
+//! `cluster-importing`，迁移完成后在所有节点上修改 `cluster-slots`，不需要重启。
+
+use std::sync::{Arc, RwLock};
+
+use crate::{
+    cluster::{Redirect, Topology},
+    config::Config,
+};
+
+#[derive(Debug)]
+pub(crate) struct ClusterState {
+    /// 这个节点在拓扑中的地址，见 [`Config::cluster_address`]
+    pub(crate) myself: String,
+    topology: RwLock<Arc<Topology>>,
+}
+
+impl ClusterState {
+    /// 没有开启集群模式时返回 None
+    pub(crate) fn new(config: &Config) -> Option<ClusterState> {
+        config.cluster_enabled.then(|| ClusterState {
+            myself: config.cluster_address(),
+            topology: RwLock::new(Arc::new(config.cluster_topology())),
+        })
+    }
+
+    pub(crate) fn configure(&self, config: &Config) {
+        *self.topology.write().unwrap() = Arc::new(config.cluster_topology());
+    }
+
+    pub(crate) fn topology(&self) -> Arc<Topology> {
+        Arc::clone(&self.topology.read().unwrap())
+    }
+
+    /// 见 [`Topology::route`]
+    pub(crate) fn route(
+        &self,
+        keys: &[&str],
+        asking: bool,
+        exists: impl Fn(&str) -> bool,
+    ) -> Result<(), Redirect> {
+        self.topology
+            .read()
+            .unwrap()
+            .route(&self.myself, keys, asking, exists)
+    }
+}
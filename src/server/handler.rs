@@ -0,0 +1,702 @@
+use std::{
+    any::Any,
+    future, io, mem,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use futures::FutureExt;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf},
+    net::TcpStream,
+    sync::{
+        broadcast,
+        mpsc::{self, error::TryRecvError},
+    },
+    task,
+};
+use tracing::{error, info, trace};
+
+use super::{
+    access, execute_command, monitor, parse_command, pubsub, replication, PeerAddr, Result,
+    Shutdown, State,
+};
+use crate::{
+    acl::DEFAULT_USER,
+    cmd::{self, Session, TrackingMode},
+    connection::Connection,
+    db::{
+        oplog::{Op, Tail, TailError},
+        Snapshot,
+    },
+    frame::Frame,
+};
+
+/// 每个连接最多缓存的已读取但还未执行的命令数
+///
+/// 读取和执行分开进行，执行一条命令时可以继续解析后面的命令；队列满时暂停读取，
+/// 由 TCP 的流量控制让客户端等待，一个连接无法用大量的 pipeline 命令占满内存。
+const MAX_IN_FLIGHT: usize = 16;
+
+/// 写缓冲区中累积的响应超过这个大小时不等 pipeline 结束就写入
+const MAX_QUEUED: usize = 64 * 1024;
+
+/// 一个连接的处理器，持有连接本身以及处理命令需要的状态
+///
+/// 命令执行失败的错误转换为错误帧返回给客户端，连接继续使用；
+/// 只有 IO 错误和协议错误会结束连接，由调用方带上对端地址记录日志，不会影响其他连接。
+/// 底层可以是普通的 TCP 连接、TLS 连接或者 unix socket 连接。
+#[derive(Debug)]
+pub(crate) struct Handler<S = TcpStream> {
+    /// 读取命令的一端，在 [`Handler::run`] 中移交给读取命令的 future
+    reader: Option<Connection<ReadHalf<S>>>,
+    writer: Connection<WriteHalf<S>>,
+    peer: PeerAddr,
+    /// 客户端是否在本机，保护模式据此判断，见 [`access::Access::allow_command`]
+    local: bool,
+    state: Arc<State>,
+    /// 当前使用的数据库、是否已认证等连接的状态
+    session: Session,
+    /// 订阅的频道收到的消息，订阅时把投递的一端登记到 [`State::pubsub`]
+    messages: pubsub::Mailbox,
+    /// 接收消息的一端，在 [`Handler::run`] 中移交给执行命令的 future
+    received: Option<pubsub::Inbox>,
+    shutdown: Shutdown,
+    /// 不会被使用，处理器被 drop 时一起 drop，服务端据此知道连接已经退出
+    _shutdown_complete: mpsc::Sender<()>,
+}
+
+/// 读取和执行两端共享的状态，读取的一端据此判断连接是否空闲
+#[derive(Debug)]
+struct Activity {
+    /// 正在执行命令或者处于订阅、MONITOR 状态，不会因空闲被关闭
+    exempt: AtomicBool,
+    /// 最近一次发送响应的时间
+    last_response: Mutex<Instant>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Handler<S> {
+    pub(crate) fn new(
+        stream: S,
+        peer: PeerAddr,
+        local: bool,
+        state: Arc<State>,
+        notify_shutdown: broadcast::Receiver<()>,
+        shutdown_complete: mpsc::Sender<()>,
+    ) -> Handler<S> {
+        let (reader, writer) = Connection::new(stream).split();
+        let (id, kill) = state.clients.register(peer.clone(), DEFAULT_USER);
+        let (messages, received) = pubsub::mailbox();
+        Handler {
+            reader: Some(reader),
+            writer,
+            peer,
+            local,
+            session: Session::new(id, &state.users),
+            messages,
+            received: Some(received),
+            state,
+            shutdown: Shutdown::new(notify_shutdown, kill),
+            _shutdown_complete: shutdown_complete,
+        }
+    }
+
+    /// 连接的 id，与 CLIENT ID 相同
+    pub(crate) fn id(&self) -> u64 {
+        self.session.id
+    }
+
+    /// 读取并执行命令，直到对端关闭连接或者服务端关闭
+    ///
+    /// 读取的一端把命令放入容量为 [`MAX_IN_FLIGHT`] 的队列，执行的一端依次执行并发送响应，
+    /// 两者在同一个任务中并发进行。服务端关闭时执行完已经读取的命令，之后的命令收到
+    /// `SHUTDOWN in progress` 错误，见 [`Handler::drain`]；超过 `shutdown-timeout` 仍未退出时
+    /// 直接中止。被 CLIENT KILL 时只执行完当前的命令。配置了 `timeout` 时，空闲超时的连接被关闭，
+    /// 执行命令期间（包括阻塞命令）不计入空闲时间。
+    pub(crate) async fn run(&mut self) -> Result<()> {
+        let Some(reader) = self.reader.take() else {
+            return Ok(());
+        };
+        let activity = Activity {
+            exempt: AtomicBool::new(false),
+            last_response: Mutex::new(Instant::now()),
+        };
+        let (tx, rx) = mpsc::channel(MAX_IN_FLIGHT);
+
+        let state = Arc::clone(&self.state);
+        let reading = read_frames(reader, tx, &state.timeout, &activity);
+        let mut abort = state.abort.subscribe();
+        let executing = self.execute_frames(rx, &activity);
+        tokio::pin!(executing);
+        let running = async {
+            tokio::select! {
+                res = &mut executing => res,
+                // 对端关闭连接后仍然执行完队列中的命令
+                () = reading => executing.await,
+            }
+        };
+        tokio::select! {
+            res = running => res,
+            _ = abort.wait_for(|aborted| *aborted) => {
+                info!("Aborting connection after shutdown timeout");
+                Ok(())
+            }
+        }
+    }
+
+    /// 依次执行队列中的命令并发送响应，读取的一端出错时把错误发送过来
+    async fn execute_frames(
+        &mut self,
+        mut rx: mpsc::Receiver<io::Result<Frame>>,
+        activity: &Activity,
+    ) -> Result<()> {
+        // 进入 MONITOR 状态后订阅所有连接执行的命令
+        let mut monitor: Option<broadcast::Receiver<String>> = None;
+        // 开启 CLIENT TRACKING 后接收失效消息，`tracked` 为登记时的模式
+        let mut invalidations: Option<mpsc::UnboundedReceiver<Frame>> = None;
+        let mut tracked: Option<TrackingMode> = None;
+        // 订阅的频道收到的消息
+        let mut messages = self.received.take();
+        while !self.shutdown.is_shutdown() {
+            let maybe_frame = tokio::select! {
+                res = rx.recv() => res,
+                line = recv_monitor(&mut monitor) => {
+                    self.writer.write_frame(&Frame::Simple(line)).await?;
+                    continue;
+                }
+                push = recv_push(&mut invalidations) => {
+                    self.writer.write_frame(&push).await?;
+                    continue;
+                }
+                message = recv_message(&mut messages) => {
+                    let message = pubsub::for_protocol(message, self.session.protocol);
+                    self.writer.write_frame(&message).await?;
+                    continue;
+                }
+                _ = self.shutdown.recv() => break,
+            };
+            let frame = match maybe_frame {
+                Some(Ok(frame)) => frame,
+                None => return Ok(()),
+                // 格式错误之后的数据无法再分帧，告知客户端后关闭连接，与 Redis 相同
+                Some(Err(e)) if e.kind() == io::ErrorKind::InvalidData => {
+                    let error = Frame::Error(format!("ERR {}", e));
+                    self.writer.write_frame(&error).await?;
+                    return Err(e.into());
+                }
+                Some(Err(e)) => return Err(e.into()),
+            };
+            self.execute_frame(frame, activity).await?;
+            if let Some((seq, snapshots)) = self.session.sync.as_mut() {
+                let (seq, snapshots) = (*seq, mem::take(snapshots));
+                return self.feed_replica(seq, snapshots, rx).await;
+            }
+            // pipeline 中的命令的响应累积起来，队列中没有命令时一次写入
+            if self.session.closing || rx.is_empty() || self.writer.queued() >= MAX_QUEUED {
+                self.writer.flush().await?;
+            }
+            if self.session.closing {
+                return Ok(());
+            }
+            if self.session.monitoring && monitor.is_none() {
+                monitor = Some(self.state.monitor.subscribe());
+            }
+            if self.session.tracking != tracked {
+                tracked.clone_from(&self.session.tracking);
+                let tracking = &self.state.tracking;
+                invalidations = match &tracked {
+                    Some(mode) => {
+                        Some(tracking.register(self.session.id, mode.clone(), &self.state.dbs))
+                    }
+                    None => {
+                        tracking.unregister(self.session.id);
+                        None
+                    }
+                };
+            }
+        }
+        if self.shutdown.is_killed() {
+            return Ok(self.writer.flush().await?);
+        }
+        self.drain(rx, activity).await
+    }
+
+    /// SYNC 之后向副本发送 SYNC 时创建的快照以及日志中序号 `seq` 之后的写命令，见 [`replication`]
+    ///
+    /// 副本断开连接、落后太多或者服务端关闭时返回。副本发送的命令中只处理 REPLCONF，没有回复。
+    async fn feed_replica(
+        &mut self,
+        seq: u64,
+        snapshots: Vec<Snapshot>,
+        mut rx: mpsc::Receiver<io::Result<Frame>>,
+    ) -> Result<()> {
+        self.writer.flush().await?;
+        let payloads = task::spawn_blocking(move || {
+            let payloads = snapshots
+                .iter()
+                .map(|snapshot| Frame::Bulk(snapshot.encode()));
+            Frame::Array(payloads.collect())
+        })
+        .await?;
+        self.writer.write_frame(&payloads).await?;
+        info!(offset = seq, "Replica synchronized");
+
+        let state = Arc::clone(&self.state);
+        let replication = &state.replication;
+        replication.register(self.session.id, &self.peer, self.session.replica_port, seq);
+        let mut tail = self.state.dbs[0].op_log().tail(seq + 1);
+        let mut selected = None;
+        let result = loop {
+            tokio::select! {
+                op = tail.next() => {
+                    let lagged = self.queue_ops(op, &mut tail, &mut selected);
+                    if let Err(e) = self.writer.flush().await {
+                        break Err(e.into());
+                    }
+                    if lagged {
+                        break Ok(());
+                    }
+                }
+                frame = rx.recv() => match frame {
+                    Some(Ok(frame)) => {
+                        if let Ok(Some(cmd::ServerCommand::Replconf(cmd))) =
+                            cmd::ServerCommand::from_frame(&frame)
+                        {
+                            cmd.apply(&self.state, &mut self.session);
+                        }
+                    }
+                    Some(Err(e)) => break Err(e.into()),
+                    None => break Ok(()),
+                },
+                _ = self.shutdown.recv() => break Ok(()),
+            }
+        };
+        replication.unregister(self.session.id);
+        result
+    }
+
+    /// 把 `first` 以及日志中已经追加的记录放入写缓冲区，副本落后太多时返回 true
+    ///
+    /// `selected` 为副本当前的数据库，记录属于其他数据库时先放入 SELECT。
+    fn queue_ops(
+        &mut self,
+        first: std::result::Result<Arc<Op>, TailError>,
+        tail: &mut Tail,
+        selected: &mut Option<usize>,
+    ) -> bool {
+        let mut next = Some(first);
+        while let Some(op) = next.take() {
+            let op = match op {
+                Ok(op) => op,
+                Err(TailError::Lagged(missed)) => {
+                    info!(missed, "Replica lagged behind, closing the link");
+                    return true;
+                }
+            };
+            if *selected != Some(op.db) {
+                self.writer.queue_frame(&replication::select_command(op.db));
+                *selected = Some(op.db);
+            }
+            self.writer.queue_frame(&op.frame);
+            if self.writer.queued() < MAX_QUEUED {
+                next = tail.try_next();
+            }
+        }
+        false
+    }
+
+    /// 服务端关闭时执行收到关闭信号之前已经读取的命令，之后读取的命令回复错误，然后关闭连接
+    ///
+    /// 读取的一端在同一个任务中运行，队列为空时让出执行机会，使已经到达的数据被分帧放入队列。
+    async fn drain(
+        &mut self,
+        mut rx: mpsc::Receiver<io::Result<Frame>>,
+        activity: &Activity,
+    ) -> Result<()> {
+        for _ in 0..rx.len() {
+            let Ok(Ok(frame)) = rx.try_recv() else {
+                break;
+            };
+            self.execute_frame(frame, activity).await?;
+            if self.session.closing {
+                break;
+            }
+        }
+        if self.session.closing {
+            return Ok(self.writer.flush().await?);
+        }
+        // 队列为空时让出一次，读取的一端把已经到达的数据分帧放入队列，仍然为空时关闭
+        let mut yielded = false;
+        loop {
+            match rx.try_recv() {
+                Ok(Ok(_)) => {
+                    let error = Frame::Error("SHUTDOWN in progress".into());
+                    self.writer.queue_frame(&error);
+                    yielded = false;
+                }
+                // 两端在 select! 中以随机的顺序被轮询，让出两次才能保证读取的一端在此期间被轮询过
+                Err(TryRecvError::Empty) if !yielded => {
+                    task::yield_now().await;
+                    task::yield_now().await;
+                    yielded = true;
+                }
+                _ => return Ok(self.writer.flush().await?),
+            }
+        }
+    }
+
+    /// 执行一条命令，响应放入写缓冲区，由调用者 flush
+    async fn execute_frame(&mut self, frame: Frame, activity: &Activity) -> Result<()> {
+        trace!(command = %frame, "Received command");
+
+        activity.exempt.store(true, Ordering::Relaxed);
+        self.state.clients.update(self.session.id, |info| {
+            info.last_command = command_name(&frame);
+            info.last_activity = Instant::now();
+        });
+        let allowed = match self.peer.ip() {
+            Some(ip) if !self.state.limiter.allow_command(ip) => {
+                Err("ERR max command rate exceeded".to_string())
+            }
+            _ => Ok(()),
+        };
+        #[cfg(feature = "fault-injection")]
+        let allowed = match self.inject_fault(&frame).await {
+            Some(cmd::Fault::Close) => {
+                info!("Closing connection by injected fault");
+                self.session.closing = true;
+                return Ok(());
+            }
+            Some(cmd::Fault::OutOfMemory) => Err(crate::db::DbError::OutOfMemory.to_string()),
+            None => allowed,
+        };
+        let response = if let Err(e) = allowed {
+            Frame::Error(e)
+        } else {
+            // 只有开启慢查询日志时才需要在执行后保留命令的参数
+            let args = self.state.slowlog.is_enabled().then(|| frame.clone());
+            #[cfg(feature = "metrics")]
+            let name = command_name(&frame);
+            let started = Instant::now();
+            let response = self.dispatch_isolated(frame).await;
+            #[cfg(feature = "metrics")]
+            self.state.metrics.record_command(&name, started.elapsed());
+            if let Some(args) = args {
+                let name = self.session.name.as_deref();
+                self.state
+                    .slowlog
+                    .record(&args, started.elapsed(), &self.peer, name);
+            }
+            response
+        };
+        let session = &self.session;
+        self.state.clients.update(session.id, |info| {
+            info.name.clone_from(&session.name);
+            info.db = session.selected;
+            info.user.clone_from(&session.user);
+        });
+        self.writer.queue_frame(&response);
+        *activity
+            .last_response
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Instant::now();
+        activity
+            .exempt
+            .store(self.session.is_idle_exempt(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// 按 DEBUG 设置的故障等待并决定命令遇到的故障，DEBUG 命令本身不受影响
+    #[cfg(feature = "fault-injection")]
+    async fn inject_fault(&mut self, frame: &Frame) -> Option<cmd::Fault> {
+        if command_name(frame) == "debug" {
+            return None;
+        }
+        let latency = self.session.faults.latency();
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        self.session.faults.roll()
+    }
+
+    /// 与 [`Handler::dispatch`] 相同，命令执行时 panic 只影响这个连接
+    ///
+    /// panic 被捕获并记录，客户端收到错误后连接被关闭，连接的状态可能已经不一致，不再继续使用。
+    async fn dispatch_isolated(&mut self, frame: Frame) -> Frame {
+        let command = command_name(&frame);
+        match AssertUnwindSafe(self.dispatch(frame)).catch_unwind().await {
+            Ok(response) => response,
+            Err(panic) => {
+                error!(%command, panic = panic_message(&*panic), "Command panicked");
+                self.session.closing = true;
+                Frame::Error("ERR internal error while executing the command".into())
+            }
+        }
+    }
+
+    /// 执行一条命令，错误以错误帧的形式返回
+    ///
+    /// 未认证的连接只能执行 AUTH、HELLO 和 QUIT，其他命令执行前检查当前用户的 ACL 权限。
+    async fn dispatch(&mut self, frame: Frame) -> Frame {
+        // ASKING 只对紧接着的一条命令有效，无论这条命令是否执行成功
+        let asking = mem::take(&mut self.session.asking);
+        if !self
+            .state
+            .access
+            .allow_command(self.local, &self.state.users)
+        {
+            return Frame::Error(access::PROTECTED_MODE_ERROR.into());
+        }
+        let command = match cmd::ServerCommand::from_frame(&frame) {
+            Ok(command) => command,
+            Err(e) => return Frame::Error(e.to_string()),
+        };
+        if !self.session.authenticated
+            && command
+                .as_ref()
+                .is_none_or(cmd::ServerCommand::requires_auth)
+        {
+            return Frame::Error("NOAUTH Authentication required.".into());
+        }
+        let name = command_name(&frame);
+        // RESP2 的订阅状态下消息和响应无法区分，只能执行订阅相关的命令；RESP3 的消息以 Push 发送，不受限制
+        if self.session.subscriptions > 0
+            && self.session.protocol < 3
+            && !cmd::SUBSCRIBED_COMMANDS.contains(&&name[..])
+        {
+            return Frame::Error(format!(
+                "ERR Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / QUIT are \
+                 allowed in this context",
+                name
+            ));
+        }
+        let users = &self.state.users;
+        match command {
+            Some(cmd) => {
+                if cmd.requires_auth() {
+                    if let Err(e) = users.check(&self.session.user, &name, &[]) {
+                        return Frame::Error(e.to_string());
+                    }
+                }
+                if cmd.is_monitored() {
+                    self.feed_monitors(&frame);
+                }
+                cmd.apply(&self.state, &mut self.session)
+            }
+            None => {
+                match cmd::Subscription::from_frame(&frame) {
+                    Ok(Some(cmd)) => return self.subscription(cmd, &name, &frame),
+                    Ok(None) => {}
+                    Err(e) => return Frame::Error(e.to_string()),
+                }
+                let cmd = match parse_command(&frame) {
+                    Ok(cmd) => cmd,
+                    Err(e) => return e,
+                };
+                if let Err(e) = users.check(&self.session.user, &name, &cmd.keys()) {
+                    return Frame::Error(e.to_string());
+                }
+                let db = &self.state.dbs[self.session.selected];
+                if let Some(cluster) = &self.state.cluster {
+                    let exists = |key: &str| db.view(key, |value| value.is_some());
+                    if let Err(redirect) = cluster.route(&cmd.keys(), asking, exists) {
+                        return Frame::Error(redirect.to_string());
+                    }
+                }
+                self.feed_monitors(&frame);
+                // 在执行之前记下读取的 key，执行期间的修改也会通知
+                if self
+                    .session
+                    .tracking
+                    .as_ref()
+                    .is_some_and(|mode| !mode.bcast)
+                    && !cmd.is_write()
+                {
+                    self.state.tracking.remember(self.session.id, &cmd.keys());
+                }
+                let timeout = self.state.command_timeout.load(Ordering::Relaxed);
+                let deadline =
+                    (timeout > 0).then(|| Instant::now() + Duration::from_millis(timeout));
+                // 副本的数据只由主节点修改，命令在只读视图上执行，写命令回复 READONLY
+                let (response, logged) = if self.state.replication.is_replica() {
+                    let view = db.read_view();
+                    let response = cmd::deadline::scope(deadline, || cmd.apply_read_only(&view));
+                    (response, None)
+                } else {
+                    execute_command(db, cmd, frame, deadline).await
+                };
+                // AOF 为 `always` 模式时等到数据落盘再响应
+                if let Some(seq) = logged {
+                    db.wait_synced(seq).await;
+                }
+                response
+            }
+        }
+    }
+
+    /// 修改订阅，每个频道或者模式一条确认：前面的确认直接放入写缓冲区，最后一条作为响应返回
+    fn subscription(&mut self, cmd: cmd::Subscription, name: &str, frame: &Frame) -> Frame {
+        let users = &self.state.users;
+        let checked = users
+            .check(&self.session.user, name, &[])
+            .and_then(|()| cmd.check(users, &self.session.user));
+        if let Err(e) = checked {
+            return Frame::Error(e.to_string());
+        }
+        self.feed_monitors(frame);
+        let protocol = self.session.protocol;
+        let mut replies = cmd.apply(&self.state.pubsub, &self.messages, &mut self.session);
+        let last = replies
+            .pop()
+            .expect("every subscription command is confirmed");
+        for reply in replies {
+            self.writer
+                .queue_frame(&pubsub::for_protocol(reply, protocol));
+        }
+        pubsub::for_protocol(last, protocol)
+    }
+}
+
+impl<S> Handler<S> {
+    /// 把即将执行的命令发送给 MONITOR 连接
+    fn feed_monitors(&self, frame: &Frame) {
+        if self.state.monitor.receiver_count() == 0 {
+            return;
+        }
+        let line = monitor::format_line(frame, self.session.selected, &self.peer);
+        // 没有接收端时返回错误，可以忽略
+        let _ = self.state.monitor.send(line);
+    }
+}
+
+impl<S> Drop for Handler<S> {
+    fn drop(&mut self) {
+        self.state.clients.unregister(self.session.id);
+        self.state.tracking.unregister(self.session.id);
+        self.state.pubsub.unregister(self.session.id);
+    }
+}
+
+/// 读取命令放入队列，直到对端关闭连接、读取出错或者连接空闲超时
+///
+/// 空闲时间从最近一次读到数据或者发送响应开始计算。
+async fn read_frames<S: AsyncRead + Unpin>(
+    mut reader: Connection<S>,
+    tx: mpsc::Sender<io::Result<Frame>>,
+    timeout: &AtomicU64,
+    activity: &Activity,
+) {
+    // 执行命令期间到达超时时刻时，从这一刻重新计时
+    let mut busy_since = Instant::now();
+    loop {
+        let deadline = idle_deadline(&reader, timeout, activity, busy_since);
+        let res = tokio::select! {
+            res = reader.read_frame() => res,
+            _ = sleep_until(deadline) => {
+                if activity.exempt.load(Ordering::Relaxed) {
+                    busy_since = Instant::now();
+                } else if idle_deadline(&reader, timeout, activity, busy_since)
+                    .is_some_and(|deadline| deadline <= Instant::now())
+                {
+                    info!("Closing idle client");
+                    return;
+                }
+                continue;
+            }
+        };
+        let item = match res {
+            Ok(Some(frame)) => Ok(frame),
+            Ok(None) => return,
+            Err(e) => Err(e),
+        };
+        let failed = item.is_err();
+        // 执行的一端已经退出时停止读取
+        if tx.send(item).await.is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// 连接空闲超时的时刻，没有配置超时时为 None
+///
+/// 超时时间可以在运行时修改，每次都重新读取。
+fn idle_deadline<S>(
+    reader: &Connection<S>,
+    timeout: &AtomicU64,
+    activity: &Activity,
+    busy_since: Instant,
+) -> Option<Instant> {
+    let timeout = timeout.load(Ordering::Relaxed);
+    let last_response = *activity
+        .last_response
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let since = reader.last_activity().max(last_response).max(busy_since);
+    (timeout > 0).then(|| since + Duration::from_secs(timeout))
+}
+
+/// 等待下一条 MONITOR 命令，不在 MONITOR 状态时一直等待
+async fn recv_monitor(monitor: &mut Option<broadcast::Receiver<String>>) -> String {
+    let Some(rx) = monitor else {
+        return future::pending().await;
+    };
+    loop {
+        match rx.recv().await {
+            Ok(line) => return line,
+            // 落后太多时跳过丢失的命令
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return future::pending().await,
+        }
+    }
+}
+
+/// 等待下一条失效消息或者订阅的消息，没有开启 CLIENT TRACKING 或者没有通道时一直等待
+async fn recv_push(pushes: &mut Option<mpsc::UnboundedReceiver<Frame>>) -> Frame {
+    let Some(rx) = pushes else {
+        return future::pending().await;
+    };
+    match rx.recv().await {
+        Some(frame) => frame,
+        None => future::pending().await,
+    }
+}
+
+/// 等待订阅的频道的下一条消息，还没有订阅过时一直等待
+async fn recv_message(inbox: &mut Option<pubsub::Inbox>) -> Frame {
+    match inbox {
+        Some(inbox) => inbox.recv().await,
+        None => future::pending().await,
+    }
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => future::pending().await,
+    }
+}
+
+/// panic 的参数，`panic!` 的参数通常是字符串
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => panic
+            .downcast_ref::<String>()
+            .map_or("Box<dyn Any>", String::as_str),
+    }
+}
+
+/// 小写的命令名，ACL 按它检查权限
+fn command_name(frame: &Frame) -> String {
+    match frame {
+        Frame::Array(parts) => parts
+            .first()
+            .map(|name| name.to_string().to_lowercase())
+            .unwrap_or_default(),
+        _ => String::new(),
+    }
+}
@@ -0,0 +1,226 @@
+//! 按对端 IP 限制新连接和命令的速率
+//!
+//! 每个 IP 一个令牌桶，以配置的速率补充令牌，桶的容量即允许的突发数量。
+//! 超过速率的新连接收到错误后被关闭，超过速率的命令返回错误，连接继续使用。
+//!
+//! 桶按 IP 的哈希分到多把锁中，不同 IP 的命令很少争用同一把锁。
+//! 空闲到令牌补满的桶与新建的桶没有区别，每把锁下的桶每隔 [`SWEEP_INTERVAL`] 清理一次，
+//! 清理的开销分摊到这段时间内的所有请求上。
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    net::IpAddr,
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::config::Config;
+
+const SHARDS: usize = 16;
+/// 同一把锁下两次清理空闲桶的间隔
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    connections: RwLock<Option<Limiter>>,
+    commands: RwLock<Option<Limiter>>,
+}
+
+impl RateLimiter {
+    /// 速率为 0 时不限制，`rate-limit-burst` 为 0 时突发数量等于每秒的速率
+    pub(crate) fn new(config: &Config) -> RateLimiter {
+        RateLimiter {
+            connections: RwLock::new(Limiter::new(
+                config.max_connections_per_sec,
+                config.rate_limit_burst,
+            )),
+            commands: RwLock::new(Limiter::new(
+                config.max_commands_per_sec,
+                config.rate_limit_burst,
+            )),
+        }
+    }
+
+    /// 按新的配置修改速率，速率变化时所有 IP 的桶重新开始计算
+    pub(crate) fn configure(&self, config: &Config) {
+        let burst = config.rate_limit_burst;
+        reconfigure(&self.connections, config.max_connections_per_sec, burst);
+        reconfigure(&self.commands, config.max_commands_per_sec, burst);
+    }
+
+    /// 是否允许这个 IP 建立新的连接
+    pub(crate) fn allow_connection(&self, ip: IpAddr) -> bool {
+        allow(&self.connections, ip)
+    }
+
+    /// 是否允许这个 IP 执行一条命令，同一个 IP 的所有连接共享速率
+    pub(crate) fn allow_command(&self, ip: IpAddr) -> bool {
+        allow(&self.commands, ip)
+    }
+}
+
+fn allow(limiter: &RwLock<Option<Limiter>>, ip: IpAddr) -> bool {
+    limiter
+        .read()
+        .unwrap()
+        .as_ref()
+        .is_none_or(|limiter| limiter.allow(ip, Instant::now()))
+}
+
+fn reconfigure(limiter: &RwLock<Option<Limiter>>, per_sec: u64, burst: u64) {
+    let new = Limiter::new(per_sec, burst);
+    let mut limiter = limiter.write().unwrap();
+    let unchanged = match (&*limiter, &new) {
+        (Some(old), Some(new)) => (old.rate, old.burst) == (new.rate, new.burst),
+        (old, new) => old.is_none() && new.is_none(),
+    };
+    if !unchanged {
+        *limiter = new;
+    }
+}
+
+#[derive(Debug)]
+struct Limiter {
+    /// 每秒补充的令牌数
+    rate: f64,
+    /// 桶的容量
+    burst: f64,
+    /// 空桶补满需要的时间，空闲这么久的桶可以删除
+    idle: Duration,
+    shards: Box<[Mutex<Buckets>]>,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    buckets: HashMap<IpAddr, Bucket>,
+    /// 上一次清理空闲桶的时间
+    swept: Instant,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Limiter {
+    fn new(per_sec: u64, burst: u64) -> Option<Limiter> {
+        if per_sec == 0 {
+            return None;
+        }
+        let burst = if burst == 0 { per_sec } else { burst };
+        let now = Instant::now();
+        Some(Limiter {
+            rate: per_sec as f64,
+            burst: burst as f64,
+            idle: Duration::from_secs_f64(burst as f64 / per_sec as f64),
+            shards: (0..SHARDS)
+                .map(|_| {
+                    Mutex::new(Buckets {
+                        buckets: HashMap::new(),
+                        swept: now,
+                    })
+                })
+                .collect(),
+        })
+    }
+
+    fn allow(&self, ip: IpAddr, now: Instant) -> bool {
+        let mut shard = self.shards[shard_index(ip)].lock().unwrap();
+        if now.saturating_duration_since(shard.swept) >= SWEEP_INTERVAL {
+            shard
+                .buckets
+                .retain(|_, bucket| now.saturating_duration_since(bucket.updated) < self.idle);
+            shard.swept = now;
+        }
+        let bucket = shard.buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        if self.refill(bucket, now) < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    /// 按经过的时间补充令牌，返回当前的令牌数
+    fn refill(&self, bucket: &mut Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.updated = now;
+        bucket.tokens
+    }
+}
+
+fn shard_index(ip: IpAddr) -> usize {
+    let mut hasher = DefaultHasher::new();
+    ip.hash(&mut hasher);
+    hasher.finish() as usize % SHARDS
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn tokens_refill_at_the_configured_rate() {
+        let limiter = Limiter::new(10, 3).unwrap();
+        let (a, b) = (IpAddr::from([10, 0, 0, 1]), IpAddr::from([10, 0, 0, 2]));
+        let start = Instant::now();
+
+        // 突发 3 个之后被限制，其他 IP 不受影响
+        assert!((0..3).all(|_| limiter.allow(a, start)));
+        assert!(!limiter.allow(a, start));
+        assert!(limiter.allow(b, start));
+
+        // 每秒 10 个，100ms 补充一个
+        assert!(limiter.allow(a, start + Duration::from_millis(100)));
+        assert!(!limiter.allow(a, start + Duration::from_millis(150)));
+        // 补充的令牌不超过容量
+        let later = start + Duration::from_secs(10);
+        assert!((0..3).all(|_| limiter.allow(a, later)));
+        assert!(!limiter.allow(a, later));
+    }
+
+    fn tracked(limiter: &Limiter) -> usize {
+        limiter
+            .shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().buckets.len())
+            .sum()
+    }
+
+    #[test]
+    fn idle_buckets_are_swept() {
+        let limiter = Limiter::new(10, 3).unwrap();
+        let start = Instant::now();
+        for i in 0..=255 {
+            assert!(limiter.allow(IpAddr::from([10, 0, 0, i]), start));
+        }
+        assert_eq!(tracked(&limiter), 256);
+
+        // 清理只在访问同一把锁时进行，这些 IP 覆盖了所有的锁
+        let later = start + SWEEP_INTERVAL;
+        for i in 0..=255 {
+            assert!(limiter.allow(IpAddr::from([10, 0, 1, i]), later));
+        }
+        assert_eq!(tracked(&limiter), 256);
+    }
+
+    #[test]
+    fn draining_buckets_survive_a_sweep() {
+        // 补满需要 100 秒，比清理间隔长
+        let limiter = Limiter::new(1, 100).unwrap();
+        let a = IpAddr::from([10, 0, 0, 1]);
+        let start = Instant::now();
+        assert!((0..100).all(|_| limiter.allow(a, start)));
+
+        let later = start + SWEEP_INTERVAL;
+        assert!((0..10).all(|_| limiter.allow(a, later)));
+        assert!(!limiter.allow(a, later));
+    }
+}
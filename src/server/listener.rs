@@ -0,0 +1,211 @@
+//! 监听的端点
+//!
+//! 服务端可以同时监听多个 TCP 地址和一个 unix socket，每个端点有自己的接受循环，
+//! 所有连接交给同样的 [`Handler`](super::Handler)，共享同一组数据库和连接数限制。
+//! unix socket 只能被本机的进程连接，不经过 TLS 和按 IP 的限流。
+
+use std::{
+    fmt, io,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+};
+
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+
+/// 一个监听的端点
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixSocket),
+}
+
+/// 监听的 unix socket，drop 时删除 socket 文件
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct UnixSocket {
+    listener: UnixListener,
+    file: SocketFile,
+}
+
+/// socket 文件的路径，drop 时删除文件
+#[cfg(unix)]
+#[derive(Debug)]
+struct SocketFile(Arc<Path>);
+
+impl Listener {
+    pub async fn tcp(addr: SocketAddr) -> io::Result<Listener> {
+        Ok(Listener::Tcp(TcpListener::bind(addr).await?))
+    }
+
+    /// 监听 unix socket，与 Redis 相同，先删除上次运行留下的 socket 文件
+    #[cfg(unix)]
+    pub fn unix(path: &Path) -> io::Result<Listener> {
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        Ok(Listener::Unix(UnixSocket {
+            listener: UnixListener::bind(path)?,
+            file: SocketFile(path.into()),
+        }))
+    }
+
+    /// 把端点注册到当前的运行时，端点只能在创建它的运行时中使用
+    pub(crate) fn reregister(self) -> io::Result<Listener> {
+        match self {
+            Listener::Tcp(listener) => {
+                Ok(Listener::Tcp(TcpListener::from_std(listener.into_std()?)?))
+            }
+            #[cfg(unix)]
+            Listener::Unix(UnixSocket { listener, file }) => Ok(Listener::Unix(UnixSocket {
+                listener: UnixListener::from_std(listener.into_std()?)?,
+                file,
+            })),
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn unix(_path: &Path) -> io::Result<Listener> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "unix sockets are not supported on this platform",
+        ))
+    }
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => write!(f, "{}", addr),
+                Err(_) => write!(f, "tcp"),
+            },
+            #[cfg(unix)]
+            Listener::Unix(socket) => write!(f, "{}", socket.file.0.display()),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// 接受连接，不同端点的连接类型不同，接受循环对它们是泛型的
+pub(crate) trait Accept {
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    fn accept(
+        &self,
+    ) -> impl std::future::Future<Output = io::Result<(Self::Stream, PeerAddr)>> + Send;
+
+    /// 把连接注册到当前的运行时，见 [`Listener::reregister`]
+    fn reregister(stream: Self::Stream) -> io::Result<Self::Stream>;
+}
+
+impl Accept for TcpListener {
+    type Stream = TcpStream;
+
+    async fn accept(&self) -> io::Result<(TcpStream, PeerAddr)> {
+        let (stream, addr) = TcpListener::accept(self).await?;
+        Ok((stream, PeerAddr::Tcp(addr)))
+    }
+
+    fn reregister(stream: TcpStream) -> io::Result<TcpStream> {
+        TcpStream::from_std(stream.into_std()?)
+    }
+}
+
+#[cfg(unix)]
+impl Accept for UnixSocket {
+    type Stream = UnixStream;
+
+    async fn accept(&self) -> io::Result<(UnixStream, PeerAddr)> {
+        let (stream, _) = self.listener.accept().await?;
+        Ok((stream, PeerAddr::Unix(Arc::clone(&self.file.0))))
+    }
+
+    fn reregister(stream: UnixStream) -> io::Result<UnixStream> {
+        UnixStream::from_std(stream.into_std()?)
+    }
+}
+
+/// 连接的对端地址
+///
+/// unix socket 的客户端通常没有地址，与 Redis 相同，显示为 socket 的路径加上 `:0`。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PeerAddr {
+    Tcp(SocketAddr),
+    Unix(Arc<Path>),
+}
+
+impl PeerAddr {
+    /// 对端的 IP，unix socket 没有
+    pub(crate) fn ip(&self) -> Option<IpAddr> {
+        match self {
+            PeerAddr::Tcp(addr) => Some(addr.ip()),
+            PeerAddr::Unix(_) => None,
+        }
+    }
+
+    /// 是否来自本机：回环地址或者 unix socket
+    pub(crate) fn is_local(&self) -> bool {
+        self.ip().is_none_or(|ip| ip.to_canonical().is_loopback())
+    }
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerAddr::Tcp(addr) => write!(f, "{}", addr),
+            PeerAddr::Unix(path) => write!(f, "{}:0", path.display()),
+        }
+    }
+}
+
+impl From<SocketAddr> for PeerAddr {
+    fn from(addr: SocketAddr) -> PeerAddr {
+        PeerAddr::Tcp(addr)
+    }
+}
+
+/// 解析 CLIENT KILL 中的地址，格式与 CLIENT LIST 中的 `addr` 相同
+impl FromStr for PeerAddr {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<PeerAddr, ()> {
+        if let Ok(addr) = s.parse() {
+            return Ok(PeerAddr::Tcp(addr));
+        }
+        match s.strip_suffix(":0") {
+            Some(path) if path.starts_with('/') => Ok(PeerAddr::Unix(PathBuf::from(path).into())),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_addr_round_trips_through_display() {
+        for addr in ["127.0.0.1:6000", "[::1]:6000", "/tmp/ilearn.sock:0"] {
+            let peer: PeerAddr = addr.parse().unwrap();
+            assert_eq!(peer.to_string(), addr);
+        }
+        assert_eq!("/tmp/ilearn.sock:0".parse::<PeerAddr>().unwrap().ip(), None);
+        assert!("localhost".parse::<PeerAddr>().is_err());
+    }
+}
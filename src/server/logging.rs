@@ -0,0 +1,75 @@
+//! 日志
+//!
+//! 服务端通过 `tracing` 记录事件，每个连接的事件都在一个 `connection` span 中，
+//! 带有对端地址和连接的 id。嵌入服务端的程序可以安装自己的 subscriber，
+//! 服务端的二进制按配置调用 [`init`]：
+//!
+//! - `text`：每个事件一行，适合终端
+//! - `pretty`：多行，带有事件的源码位置
+//! - `json`：每个事件一个 JSON 对象，适合日志收集系统
+
+use tracing::Level;
+
+use super::Result;
+use crate::config::Config;
+
+/// 日志的输出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Pretty,
+    Json,
+}
+
+impl LogFormat {
+    pub fn from_name(name: &str) -> Option<LogFormat> {
+        match &name.to_lowercase()[..] {
+            "text" => Some(LogFormat::Text),
+            "pretty" => Some(LogFormat::Pretty),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogFormat::Text => "text",
+            LogFormat::Pretty => "pretty",
+            LogFormat::Json => "json",
+        }
+    }
+}
+
+/// 解析日志级别，除了 tracing 的级别名，还接受 redis.conf 中的 `verbose`、`notice` 和 `warning`
+pub fn parse_level(name: &str) -> Option<Level> {
+    match &name.to_lowercase()[..] {
+        "verbose" => Some(Level::DEBUG),
+        "notice" => Some(Level::INFO),
+        "warning" => Some(Level::WARN),
+        name => name.parse().ok(),
+    }
+}
+
+/// 按配置安装全局的 subscriber，只能调用一次
+pub fn init(config: &Config) -> Result<()> {
+    let builder = tracing_subscriber::fmt().with_max_level(config.loglevel);
+    match config.log_format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Pretty => builder.pretty().try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).try_init(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_names_accept_redis_aliases() {
+        assert_eq!(parse_level("warning"), Some(Level::WARN));
+        assert_eq!(parse_level("DEBUG"), Some(Level::DEBUG));
+        assert_eq!(parse_level("loud"), None);
+        assert_eq!(LogFormat::from_name("JSON"), Some(LogFormat::Json));
+    }
+}
@@ -0,0 +1,316 @@
+//! Prometheus 指标
+//!
+//! 配置了 `metrics-port` 时在 bind 的第一个地址上监听这个端口，`GET /metrics` 返回文本格式的指标：
+//! 连接数、每个命令的调用次数和耗时分布、每个数据库的 key 数量、内存使用、淘汰和过期的 key 数量。
+//! 需要开启 `metrics` 特性，没有开启时不统计命令和连接。
+//!
+//! 只实现了 Prometheus 抓取需要的最小的 HTTP：每个连接一个请求，响应后关闭。
+
+use std::sync::Arc;
+
+use super::{Result, State};
+use crate::config::Config;
+
+#[cfg(feature = "metrics")]
+pub(crate) use enabled::{serve, Metrics};
+
+/// 按配置监听指标的端口，没有配置时返回 None
+#[cfg(feature = "metrics")]
+pub(crate) async fn bind(config: &Config) -> Result<Option<tokio::net::TcpListener>> {
+    if config.metrics_port == 0 {
+        return Ok(None);
+    }
+    let ip = config.bind.first().copied();
+    let ip = ip.unwrap_or_else(|| std::net::Ipv4Addr::LOCALHOST.into());
+    Ok(Some(
+        tokio::net::TcpListener::bind((ip, config.metrics_port)).await?,
+    ))
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) async fn bind(config: &Config) -> Result<Option<std::convert::Infallible>> {
+    if config.metrics_port == 0 {
+        return Ok(None);
+    }
+    Err("metrics-port requires the `metrics` feature".into())
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) async fn serve(
+    listener: Option<std::convert::Infallible>,
+    _state: Arc<State>,
+) -> Result<()> {
+    match listener {
+        Some(never) => match never {},
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(feature = "metrics")]
+mod enabled {
+    use std::{
+        collections::HashMap,
+        fmt::Write,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            RwLock,
+        },
+        time::Duration,
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+    use tracing::{info, warn};
+
+    use super::*;
+    use crate::{acl, db::DbStats};
+
+    /// 命令耗时分布的上界，单位为秒
+    const BUCKETS: [f64; 9] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0];
+
+    /// 请求头的最大长度
+    const MAX_REQUEST: usize = 8 * 1024;
+
+    #[derive(Debug, Default)]
+    pub(crate) struct Metrics {
+        connections_received: AtomicU64,
+        connections_rejected: AtomicU64,
+        /// 只统计 ACL 表中的命令，未知的命令计入 `unknown`，避免标签的数量无限增长
+        commands: RwLock<HashMap<&'static str, CommandMetrics>>,
+    }
+
+    #[derive(Debug, Default)]
+    struct CommandMetrics {
+        /// 每个桶的计数，不累加，输出时再求前缀和
+        buckets: [AtomicU64; BUCKETS.len()],
+        count: AtomicU64,
+        sum_micros: AtomicU64,
+    }
+
+    impl Metrics {
+        pub(crate) fn connection_received(&self) {
+            self.connections_received.fetch_add(1, Ordering::Relaxed);
+        }
+
+        pub(crate) fn connection_rejected(&self) {
+            self.connections_rejected.fetch_add(1, Ordering::Relaxed);
+        }
+
+        /// 记录一次命令的耗时，`name` 为小写的命令名
+        pub(crate) fn record_command(&self, name: &str, duration: Duration) {
+            let name = acl::command_name(name).unwrap_or("unknown");
+            let commands = self.commands.read().unwrap();
+            match commands.get(name) {
+                Some(metrics) => metrics.record(duration),
+                None => {
+                    drop(commands);
+                    let mut commands = self.commands.write().unwrap();
+                    commands.entry(name).or_default().record(duration);
+                }
+            }
+        }
+    }
+
+    impl CommandMetrics {
+        fn record(&self, duration: Duration) {
+            let seconds = duration.as_secs_f64();
+            if let Some(index) = BUCKETS.iter().position(|le| seconds <= *le) {
+                self.buckets[index].fetch_add(1, Ordering::Relaxed);
+            }
+            self.count.fetch_add(1, Ordering::Relaxed);
+            self.sum_micros
+                .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// 接受抓取请求，直到服务端关闭
+    pub(crate) async fn serve(listener: Option<TcpListener>, state: Arc<State>) -> Result<()> {
+        let Some(listener) = listener else {
+            return std::future::pending().await;
+        };
+        info!(addr = %listener.local_addr()?, "Serving metrics");
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let state = Arc::clone(&state);
+            tokio::spawn(async move {
+                if let Err(e) = respond(stream, &state).await {
+                    warn!(error = %e, "Error serving metrics");
+                }
+            });
+        }
+    }
+
+    async fn respond(mut stream: TcpStream, state: &State) -> std::io::Result<()> {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+        let path = request
+            .split(|b| *b == b' ')
+            .nth(1)
+            .map(String::from_utf8_lossy);
+        let response = match (request.starts_with(b"GET "), path.as_deref()) {
+            (true, Some("/metrics")) => {
+                let body = render(state);
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            }
+            _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_string(),
+        };
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    }
+
+    /// 文本格式的所有指标
+    pub(super) fn render(state: &State) -> String {
+        let metrics = &state.metrics;
+        let mut out = String::new();
+        header(&mut out, "connected_clients", "gauge", "Client connections");
+        let _ = writeln!(
+            out,
+            "ilearn_connected_clients {}",
+            state.clients.list().len()
+        );
+        let received = metrics.connections_received.load(Ordering::Relaxed);
+        header(
+            &mut out,
+            "connections_received_total",
+            "counter",
+            "Connections accepted",
+        );
+        let _ = writeln!(out, "ilearn_connections_received_total {}", received);
+        let rejected = metrics.connections_rejected.load(Ordering::Relaxed);
+        header(
+            &mut out,
+            "rejected_connections_total",
+            "counter",
+            "Connections rejected by limits",
+        );
+        let _ = writeln!(out, "ilearn_rejected_connections_total {}", rejected);
+
+        let commands = metrics.commands.read().unwrap();
+        let mut names: Vec<_> = commands.keys().copied().collect();
+        names.sort_unstable();
+        header(&mut out, "commands_total", "counter", "Commands processed");
+        for name in &names {
+            let count = commands[name].count.load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "ilearn_commands_total{{command=\"{}\"}} {}",
+                name, count
+            );
+        }
+        header(
+            &mut out,
+            "command_duration_seconds",
+            "histogram",
+            "Command execution time",
+        );
+        for name in &names {
+            let command = &commands[name];
+            let mut cumulative = 0;
+            for (le, bucket) in BUCKETS.iter().zip(&command.buckets) {
+                cumulative += bucket.load(Ordering::Relaxed);
+                let _ = writeln!(
+                    out,
+                    "ilearn_command_duration_seconds_bucket{{command=\"{}\",le=\"{}\"}} {}",
+                    name, le, cumulative
+                );
+            }
+            let count = command.count.load(Ordering::Relaxed);
+            let sum = command.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+            let _ = writeln!(
+                out,
+                "ilearn_command_duration_seconds_bucket{{command=\"{}\",le=\"+Inf\"}} {}\n\
+                 ilearn_command_duration_seconds_sum{{command=\"{}\"}} {}\n\
+                 ilearn_command_duration_seconds_count{{command=\"{}\"}} {}",
+                name, count, name, sum, name, count
+            );
+        }
+        drop(commands);
+
+        let keyspace: Vec<_> = state.dbs.iter().map(|db| db.keyspace_stats()).collect();
+        let stats: Vec<DbStats> = state.dbs.iter().map(|db| db.stats()).collect();
+        let per_db = [
+            ("keyspace_keys", "gauge", "Keys in the database"),
+            ("keyspace_expires", "gauge", "Keys with an expiration"),
+            ("used_memory_bytes", "gauge", "Memory used by the database"),
+            ("evicted_keys_total", "counter", "Keys evicted by maxmemory"),
+            (
+                "expired_keys_total",
+                "counter",
+                "Keys removed after expiring",
+            ),
+            (
+                "keyspace_hits_total",
+                "counter",
+                "Lookups that found the key",
+            ),
+            (
+                "keyspace_misses_total",
+                "counter",
+                "Lookups that missed the key",
+            ),
+        ];
+        for (metric, kind, help) in per_db {
+            header(&mut out, metric, kind, help);
+            for (index, db) in state.dbs.iter().enumerate() {
+                let value = match metric {
+                    "keyspace_keys" => keyspace[index].keys as u64,
+                    "keyspace_expires" => keyspace[index].expires as u64,
+                    "used_memory_bytes" => db.used_memory() as u64,
+                    "evicted_keys_total" => stats[index].evicted,
+                    "expired_keys_total" => stats[index].expired,
+                    "keyspace_hits_total" => stats[index].hits,
+                    _ => stats[index].misses,
+                };
+                let _ = writeln!(out, "ilearn_{}{{db=\"{}\"}} {}", metric, index, value);
+            }
+        }
+        out
+    }
+
+    fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(out, "# HELP ilearn_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE ilearn_{} {}", name, kind);
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn latency_is_counted_in_cumulative_buckets() {
+            let metrics = Metrics::default();
+            metrics.record_command("get", Duration::from_micros(50));
+            metrics.record_command("get", Duration::from_millis(3));
+            metrics.record_command("nosuchcommand", Duration::from_secs(5));
+
+            let commands = metrics.commands.read().unwrap();
+            let get = &commands["get"];
+            assert_eq!(get.count.load(Ordering::Relaxed), 2);
+            assert_eq!(get.sum_micros.load(Ordering::Relaxed), 3050);
+            assert_eq!(get.buckets[0].load(Ordering::Relaxed), 1);
+            assert_eq!(get.buckets[3].load(Ordering::Relaxed), 1);
+            // 超过最大的上界时只计入 +Inf
+            let unknown = &commands["unknown"];
+            assert_eq!(unknown.count.load(Ordering::Relaxed), 1);
+            assert!(unknown
+                .buckets
+                .iter()
+                .all(|bucket| bucket.load(Ordering::Relaxed) == 0));
+        }
+    }
+}
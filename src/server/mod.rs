@@ -0,0 +1,1677 @@
+//! 服务端
+//!
+//! [`run`] 在给定的 listener 上接受连接，直到 `shutdown` 完成，[`run_listeners`] 同时监听多个端点。数据库的数量、持久化等配置见 [`Config`]，
+//! 启动时按配置创建所有逻辑数据库并恢复数据。
+//! 服务端的二进制只负责解析参数，集成测试和其他程序也可以直接嵌入服务端。
+//!
+//! 关闭时先停止接受连接，再通知所有连接退出：已经读取的命令会执行完并发送响应，
+//! 之后的命令收到 `SHUTDOWN in progress` 错误，连接被关闭；超过 `shutdown-timeout` 仍未退出的连接被中止。
+//! 所有连接都退出后才关闭后台任务并把 AOF 同步到磁盘，已经响应的写命令不会丢失。
+
+use std::{
+    future::Future,
+    io,
+    path::Path,
+    sync::{atomic::AtomicU64, Arc},
+    time::{Duration, Instant},
+};
+
+use futures::{future, FutureExt};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    runtime::Handle,
+    sync::{broadcast, mpsc, watch, OwnedSemaphorePermit, Semaphore},
+    time,
+};
+use tracing::{debug, error, field, info, info_span, warn, Instrument, Span};
+
+mod access;
+use access::Access;
+pub use access::Cidr;
+
+mod clients;
+use clients::Clients;
+
+mod cluster;
+use cluster::ClusterState;
+
+mod handler;
+use handler::Handler;
+
+mod monitor;
+
+mod shutdown;
+
+mod proxy;
+
+mod pubsub;
+pub(crate) use pubsub::{Kind as PubSubKind, Mailbox, PubSub};
+
+mod reload;
+
+mod replication;
+use replication::Replication;
+
+pub mod runtime;
+
+mod slowlog;
+use shutdown::{Shutdown, ShutdownRequest};
+use slowlog::SlowLog;
+
+mod limiter;
+use limiter::RateLimiter;
+
+pub mod logging;
+
+mod metrics;
+
+mod listener;
+use listener::Accept;
+pub use listener::Listener;
+pub(crate) use listener::PeerAddr;
+
+mod tls;
+use tls::TlsAcceptor;
+
+mod tracking;
+use tracking::Tracking;
+
+use crate::{
+    acl::Users,
+    cmd,
+    config::Config,
+    connection::Connection,
+    db::{aof::read_aof, hasher, oplog::OpLog, Db, DbDropGuard},
+    frame::Frame,
+};
+
+#[cfg(feature = "sled")]
+use crate::db::storage::SledStorage;
+
+/// 服务端使用的错误类型，启动时的错误来源较多，统一装箱
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// 默认端口，与 Redis 相同
+pub const DEFAULT_PORT: u16 = 6379;
+
+/// 使用默认配置运行服务端，见 [`run_with`]
+pub async fn run(listener: TcpListener, shutdown: impl Future) -> Result<()> {
+    run_with(listener, &Config::default(), shutdown).await
+}
+
+/// 按配置创建数据库并在一个 TCP listener 上运行服务端，见 [`run_listeners`]
+pub async fn run_with(listener: TcpListener, config: &Config, shutdown: impl Future) -> Result<()> {
+    run_listeners(vec![Listener::Tcp(listener)], config, shutdown).await
+}
+
+/// 按配置创建数据库并运行服务端，`shutdown` 完成后停止接受连接并返回
+///
+/// 每个端点有自己的接受循环，所有连接共享数据库和 `maxclients` 限制。
+/// 接受连接出错时等待一段时间后重试，服务端不会因此退出。
+pub async fn run_listeners(
+    listeners: Vec<Listener>,
+    config: &Config,
+    shutdown: impl Future,
+) -> Result<()> {
+    let (_reload, config) = watch::channel(config.clone());
+    run_reloadable(listeners, config, shutdown).await
+}
+
+/// 与 [`run_listeners`] 相同，之后通过 `updates` 发送的配置在运行时重新加载
+///
+/// 只有部分配置项可以在运行时修改，见 `reload` 模块。
+pub async fn run_reloadable(
+    listeners: Vec<Listener>,
+    mut updates: watch::Receiver<Config>,
+    shutdown: impl Future,
+) -> Result<()> {
+    let config = &updates.borrow_and_update().clone();
+    info!(hasher = hasher::NAME, "Starting server");
+    // guard 在返回时被 drop，同时停止后台任务并关闭 AOF
+    let tls = tls::acceptor(config)?;
+    let exporter = metrics::bind(config).await?;
+    let holders = open(config).await?;
+    // 所有连接共享同一组 Db，clone 只增加内部 Arc 的引用计数
+    let access = Access::new(config, &listeners);
+    let state = Arc::new(State {
+        access,
+        dbs: holders.iter().map(DbDropGuard::db).collect(),
+        users: Users::new(config.requirepass.as_deref()),
+        timeout: AtomicU64::new(config.timeout),
+        command_timeout: AtomicU64::new(config.command_timeout),
+        limiter: RateLimiter::new(config),
+        cluster: ClusterState::new(config),
+        replication: Replication::default(),
+        clients: Clients::default(),
+        monitor: broadcast::channel(monitor::CAPACITY).0,
+        tracking: Tracking::default(),
+        pubsub: PubSub::default(),
+        slowlog: SlowLog::new(config.slowlog_log_slower_than, config.slowlog_max_len),
+        config: watch::channel(config.clone()).0,
+        shutdown: ShutdownRequest::default(),
+        abort: watch::channel(false).0,
+        #[cfg(feature = "metrics")]
+        metrics: metrics::Metrics::default(),
+    });
+    if let Some((host, port)) = &config.replicaof {
+        let auth = config.masterauth.clone();
+        let replication = &state.replication;
+        replication.replicate(host.clone(), *port, &state.dbs, config.port, auth);
+    }
+
+    // 关闭时 drop 发送端通知所有连接；每个连接持有一个完成通道的发送端，全部 drop 后接收端返回 None
+    let (notify_shutdown, _) = broadcast::channel(1);
+    let (shutdown_complete_tx, mut shutdown_complete_rx) = mpsc::channel::<()>(1);
+
+    let context = AcceptContext {
+        state: Arc::clone(&state),
+        limit: Arc::new(Semaphore::new(config.maxclients)),
+        tls,
+        notify_shutdown: notify_shutdown.clone(),
+        shutdown_complete: shutdown_complete_tx.clone(),
+        runtime: config.accept_thread.then(Handle::current),
+        proxy_protocol: config.proxy_protocol,
+        proxy_trusted: config.proxy_trusted.clone(),
+    };
+    for listener in &listeners {
+        info!(%listener, "Listening");
+    }
+    let accepting = if config.accept_thread {
+        // 端点需要注册到接受线程的运行时
+        runtime::spawn_thread("accept", move || async move {
+            let listeners = listeners
+                .into_iter()
+                .map(Listener::reregister)
+                .collect::<io::Result<Vec<_>>>()?;
+            accept_all(&listeners, &context).await;
+            Ok(())
+        })?
+        .boxed()
+    } else {
+        async move {
+            accept_all(&listeners, &context).await;
+            Ok(())
+        }
+        .boxed()
+    };
+    let mut save = false;
+    let result = tokio::select! {
+        res = accepting => res,
+        _ = reload::watch(&state, updates) => unreachable!(),
+        res = metrics::serve(exporter, Arc::clone(&state)) => res,
+        _ = shutdown => {
+            info!("Shutting down");
+            Ok(())
+        }
+        requested = state.shutdown.requested() => {
+            info!("Shutting down by SHUTDOWN command");
+            save = requested;
+            Ok(())
+        }
+    };
+
+    drop(notify_shutdown);
+    // 关闭期间不再接收主节点的写命令，保存的快照与已经响应的命令一致
+    state.replication.stop();
+    drop(shutdown_complete_tx);
+    // 等待所有连接处理完已经读取的命令，超时后中止剩余的连接
+    let shutdown_timeout = state.config.borrow().shutdown_timeout;
+    let deadline = Duration::from_secs(shutdown_timeout);
+    let drained = shutdown_complete_rx.recv();
+    tokio::pin!(drained);
+    if shutdown_timeout == 0 {
+        drained.await;
+    } else if time::timeout(deadline, &mut drained).await.is_err() {
+        warn!(
+            clients = state.clients.list().len(),
+            "Shutdown timeout reached, aborting remaining connections"
+        );
+        state.abort.send_replace(true);
+        drained.await;
+    }
+    // 所有连接都已退出，快照包含所有已经响应的写命令
+    if save {
+        for (index, db) in state.dbs.iter().enumerate() {
+            db.save()?;
+            info!(db = index, "DB saved on disk");
+        }
+    }
+    result
+}
+
+/// 所有连接共享的状态，[`cmd::ServerCommand`] 通过它访问服务端
+#[derive(Debug)]
+pub(crate) struct State {
+    /// 允许连接的网段和保护模式
+    pub(crate) access: Access,
+    /// 所有逻辑数据库，下标即 SELECT 使用的编号
+    pub(crate) dbs: Vec<Db>,
+    /// ACL 用户，`requirepass` 为 default 用户的密码
+    pub(crate) users: Users,
+    /// 空闲连接的超时时间
+    pub(crate) timeout: AtomicU64,
+    /// 单条命令的最长执行时间，单位为毫秒，0 表示不限制
+    pub(crate) command_timeout: AtomicU64,
+    /// 按对端 IP 限制新连接和命令的速率
+    pub(crate) limiter: RateLimiter,
+    /// 集群模式下这个节点的拓扑，没有开启集群模式时为 None
+    pub(crate) cluster: Option<ClusterState>,
+    /// 主从复制的状态，见 [`replication`]
+    pub(crate) replication: Replication,
+    /// 所有连接的登记表
+    pub(crate) clients: Clients,
+    /// 发送给 MONITOR 连接的命令，见 [`monitor`]
+    pub(crate) monitor: broadcast::Sender<String>,
+    /// CLIENT TRACKING 的登记表，见 [`tracking`]
+    pub(crate) tracking: Tracking,
+    /// 发布订阅的登记表，见 [`pubsub`]
+    pub(crate) pubsub: PubSub,
+    /// 慢查询日志
+    pub(crate) slowlog: SlowLog,
+    /// 当前生效的配置，CONFIG SET 和重新加载都通过它修改，见 [`reload`]
+    pub(crate) config: watch::Sender<Config>,
+    /// SHUTDOWN 命令通过它通知服务端关闭
+    pub(crate) shutdown: ShutdownRequest,
+    /// 关闭超时后设为 true，还没有退出的连接直接中止
+    pub(crate) abort: watch::Sender<bool>,
+    /// 导出给 Prometheus 的连接和命令统计
+    #[cfg(feature = "metrics")]
+    pub(crate) metrics: metrics::Metrics,
+}
+
+/// 接受循环需要的共享状态
+struct AcceptContext {
+    state: Arc<State>,
+    /// 每个连接占用一个许可
+    limit: Arc<Semaphore>,
+    tls: Option<TlsAcceptor>,
+    notify_shutdown: broadcast::Sender<()>,
+    shutdown_complete: mpsc::Sender<()>,
+    /// 接受循环在专门的线程中运行时，处理连接的运行时
+    runtime: Option<Handle>,
+    proxy_protocol: bool,
+    /// 允许发送 PROXY 头部的对端，见 [`proxy::is_trusted`]
+    proxy_trusted: Vec<Cidr>,
+}
+
+/// 在所有端点上接受连接，接受循环不会退出，只有没有端点时才返回
+async fn accept_all(listeners: &[Listener], context: &AcceptContext) {
+    let accepting = listeners.iter().map(|listener| match listener {
+        Listener::Tcp(listener) => accept(listener, context, context.tls.clone()).boxed(),
+        // unix socket 只有本机能连接，不需要加密
+        #[cfg(unix)]
+        Listener::Unix(socket) => accept(socket, context, None).boxed(),
+    });
+    future::join_all(accepting).await;
+}
+
+/// 接受连接，每个连接在自己的任务中建立，见 [`Connecting::establish`]
+///
+/// 每个连接的任务在一个 `connection` span 中运行，其中的事件都带有对端地址和连接的 id。
+/// 出错时由 [`next_connection`] 等待后重试，这个函数不会返回。
+async fn accept<L: Accept>(listener: &L, context: &AcceptContext, tls: Option<TlsAcceptor>) {
+    loop {
+        let (stream, peer) = next_connection(listener).await;
+        let span = info_span!("connection", %peer, id = field::Empty);
+        let connecting = Connecting {
+            state: Arc::clone(&context.state),
+            limit: Arc::clone(&context.limit),
+            tls: tls.clone(),
+            proxy_protocol: context.proxy_protocol
+                && proxy::is_trusted(&context.proxy_trusted, &peer),
+            notify_shutdown: context.notify_shutdown.subscribe(),
+            shutdown_complete: context.shutdown_complete.clone(),
+        };
+        context.spawn::<L, _>(span, stream, move |stream| {
+            connecting.establish(stream, peer)
+        });
+    }
+}
+
+/// 一个已经接受、还没有开始处理命令的连接
+struct Connecting {
+    state: Arc<State>,
+    limit: Arc<Semaphore>,
+    tls: Option<TlsAcceptor>,
+    proxy_protocol: bool,
+    notify_shutdown: broadcast::Receiver<()>,
+    shutdown_complete: mpsc::Sender<()>,
+}
+
+impl Connecting {
+    /// 读取 PROXY 协议的头部（如果开启并且对端受信任），检查连接是否允许，完成 TLS 握手后处理命令
+    ///
+    /// 每个连接占用 `limit` 的一个许可。没有许可时回复错误后立即关闭，而不是让连接堆积在内核的队列中等待超时，
+    /// 与 Redis 达到 maxclients 时的行为相同；对端地址不在 `allowlist` 中或者同一个 IP 新建连接过快时同样处理。
+    /// TLS 模式下无法在握手前回复，直接关闭。
+    async fn establish<S>(self, mut stream: S, peer: PeerAddr)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let state = &self.state;
+        let transport_local = peer.is_local();
+        let peer = if self.proxy_protocol {
+            match time::timeout(proxy::HEADER_TIMEOUT, proxy::read_header(&mut stream)).await {
+                Ok(Ok(Some(client))) => {
+                    Span::current().record("peer", field::display(client));
+                    PeerAddr::Tcp(client)
+                }
+                Ok(Ok(None)) => peer,
+                Ok(Err(e)) => {
+                    warn!(error = %e, "Invalid PROXY header");
+                    return;
+                }
+                Err(_) => {
+                    warn!("Timed out waiting for PROXY header");
+                    return;
+                }
+            }
+        } else {
+            peer
+        };
+        let permit = if !state.access.allow_connection(&peer) {
+            Err("ERR client address not allowed")
+        } else if !peer
+            .ip()
+            .is_none_or(|ip| state.limiter.allow_connection(ip))
+        {
+            Err("ERR max connection rate exceeded")
+        } else {
+            Arc::clone(&self.limit)
+                .try_acquire_owned()
+                .map_err(|_| "ERR max number of clients reached")
+        };
+        #[cfg(feature = "metrics")]
+        match permit {
+            Ok(_) => state.metrics.connection_received(),
+            Err(_) => state.metrics.connection_rejected(),
+        }
+        let permit = match permit {
+            Ok(permit) => permit,
+            Err(reason) => {
+                reject(self.tls.is_none().then_some(stream), reason).await;
+                return;
+            }
+        };
+        // 头部中的地址只能收紧保护模式的判断，不能让远程的连接变成本机客户端
+        let local = transport_local && peer.is_local();
+        let Some(acceptor) = self.tls else {
+            let handler = Handler::new(
+                stream,
+                peer,
+                local,
+                self.state,
+                self.notify_shutdown,
+                self.shutdown_complete,
+            );
+            serve(handler, permit).await;
+            return;
+        };
+        match tls::handshake(&acceptor, stream).await {
+            Ok(stream) => {
+                let handler = Handler::new(
+                    stream,
+                    peer,
+                    local,
+                    self.state,
+                    self.notify_shutdown,
+                    self.shutdown_complete,
+                );
+                serve(handler, permit).await;
+            }
+            Err(e) => warn!(error = %e, "TLS handshake failed"),
+        }
+    }
+}
+
+/// accept 出错后第一次等待的时间，之后每次翻倍
+const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(5);
+
+/// accept 出错后最长的等待时间
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+/// 接受下一个连接，出错时记录并等待后重试，不会返回错误
+///
+/// 文件描述符用尽（EMFILE、ENFILE）等错误通常是暂时的，已有的连接关闭后就会恢复，
+/// 立即重试只会空转，退出则会断开所有连接。连接在被接受前已经断开的错误与端点无关，直接重试。
+async fn next_connection<L: Accept>(listener: &L) -> (L::Stream, PeerAddr) {
+    let mut delay = ACCEPT_BACKOFF_MIN;
+    loop {
+        match listener.accept().await {
+            Ok(accepted) => return accepted,
+            Err(e) if is_connection_error(&e) => {
+                debug!(error = %e, "Connection aborted before accept")
+            }
+            Err(e) => {
+                error!(error = %e, backoff = ?delay, "Error accepting connection");
+                time::sleep(delay).await;
+                delay = (delay * 2).min(ACCEPT_BACKOFF_MAX);
+            }
+        }
+    }
+}
+
+fn is_connection_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::Interrupted
+    )
+}
+
+impl AcceptContext {
+    /// 在处理连接的运行时中运行连接的任务，接受循环在另一个运行时中时先把连接注册到处理连接的运行时
+    fn spawn<L: Accept, F>(
+        &self,
+        span: Span,
+        stream: L::Stream,
+        task: impl FnOnce(L::Stream) -> F + Send + 'static,
+    ) where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let Some(runtime) = &self.runtime else {
+            tokio::spawn(task(stream).instrument(span));
+            return;
+        };
+        let task = async move {
+            match L::reregister(stream) {
+                Ok(stream) => task(stream).await,
+                Err(e) => warn!(error = %e, "Error moving connection to the runtime"),
+            }
+        };
+        runtime.spawn(task.instrument(span));
+    }
+}
+
+async fn serve<S: AsyncRead + AsyncWrite + Unpin>(
+    mut handler: Handler<S>,
+    permit: OwnedSemaphorePermit,
+) {
+    Span::current().record("id", handler.id());
+    debug!("Connection accepted");
+    if let Err(e) = handler.run().await {
+        warn!(error = %e, "Connection error");
+    }
+    debug!("Connection closed");
+    // 处理器退出后才归还许可
+    drop(permit);
+}
+
+/// 连接数或者新连接的速率达到上限，回复错误后关闭连接，`stream` 为 None 时无法回复，直接关闭
+async fn reject<S: AsyncWrite + Unpin>(stream: Option<S>, reason: &str) {
+    warn!(reason, "Connection rejected");
+    let Some(stream) = stream else {
+        return;
+    };
+    let mut connection = Connection::new(stream);
+    let error = Frame::Error(reason.into());
+    if let Err(e) = connection.write_frame(&error).await {
+        warn!(error = %e, "Error rejecting connection");
+    }
+}
+
+/// 按配置创建所有逻辑数据库并恢复数据
+///
+/// 每个逻辑数据库是一个独立的 Db，各自有 key、过期索引、统计和持久化文件。
+async fn open(config: &Config) -> Result<Vec<DbDropGuard>> {
+    let holders: Vec<DbDropGuard> = (0..config.databases).map(|_| DbDropGuard::new()).collect();
+    // 所有数据库的写命令记录到同一个操作日志，AOF 等消费者各自读取
+    let op_log = Arc::new(OpLog::new());
+    for (index, holder) in holders.iter().enumerate() {
+        let db = &holder.db();
+        db.set_op_log(Arc::clone(&op_log), index);
+        configure_db(db, config);
+        db.set_rdb_path(db_file(&config.dbfilename, index));
+        let aof_path = db_file(&config.appendfilename, index);
+        // 启动时恢复数据：配置了存储层时数据在访问时从存储加载，不需要重放；
+        // 否则开启了 AOF 时优先使用 AOF，它比 RDB 更完整
+        if let Some(path) = &config.storage {
+            let path = db_file(path, index);
+            open_storage(db, &path)?;
+            info!(db = index, %path, "Using storage");
+        } else if config.appendonly && Path::new(&aof_path).exists() {
+            let frames = read_aof(&aof_path)?;
+            let count = frames.len();
+            for frame in frames {
+                if let (Frame::Error(e), _) = execute(db, frame).await {
+                    error!(error = %e, "Error replaying AOF command");
+                }
+            }
+            info!(
+                db = index,
+                commands = count,
+                "DB loaded from append only file"
+            );
+        } else if db.rdb_path().exists() {
+            let loaded = db.load(db.rdb_path())?;
+            info!(db = index, keys = loaded, "DB loaded from disk");
+        }
+        // 预热的数据只写入 0 号数据库，不经过命令，也不会追加到 AOF
+        if let (0, Some(path)) = (index, &config.warm) {
+            let loaded = warm_up(db, path)?;
+            info!(db = index, %path, keys = loaded, "DB warmed up");
+        }
+        // 重放完成后才开启，避免重放的命令被再次追加
+        if config.appendonly {
+            db.enable_aof(aof_path, config.appendfsync).await?;
+        }
+    }
+    Ok(holders)
+}
+
+/// 每个数据库可以在运行时修改的配置
+fn configure_db(db: &Db, config: &Config) {
+    db.set_max_memory(config.maxmemory);
+    db.set_expire_mode(config.expire_mode);
+    db.set_max_key_len(config.max_key_len);
+    db.set_max_value_size(config.max_value_size);
+    db.set_defrag_ratio(config.defrag_ratio);
+}
+
+/// 第 `index` 个数据库的持久化文件，0 号数据库沿用原来的文件名，其他数据库在文件名后加上编号
+fn db_file(name: &str, index: usize) -> String {
+    match (index, name.rsplit_once('.')) {
+        (0, _) => name.to_string(),
+        (_, Some((stem, ext))) => format!("{}-{}.{}", stem, index, ext),
+        (_, None) => format!("{}-{}", name, index),
+    }
+}
+
+/// 从快照或 CSV 文件批量加载数据，按扩展名区分
+fn warm_up(db: &Db, path: &str) -> Result<usize> {
+    if path.ends_with(".csv") {
+        Ok(db.load_csv(path)?)
+    } else {
+        Ok(db.load(path)?)
+    }
+}
+
+#[cfg(feature = "sled")]
+fn open_storage(db: &Db, path: &str) -> Result<()> {
+    db.set_storage(Arc::new(SledStorage::open(path)?));
+    Ok(())
+}
+
+#[cfg(not(feature = "sled"))]
+fn open_storage(_db: &Db, _path: &str) -> Result<()> {
+    Err("--storage requires the `sled` feature".into())
+}
+
+/// 执行一条命令，返回响应帧以及记录到操作日志中的序号
+///
+/// 配置了存储层时，执行前从存储加载命令访问的 key，写命令执行成功后把它们写回存储。
+async fn execute(db: &Db, frame: Frame) -> (Frame, Option<u64>) {
+    match parse_command(&frame) {
+        Ok(cmd) => execute_command(db, cmd, frame, None).await,
+        Err(e) => (e, None),
+    }
+}
+
+/// 解析数据库命令，未知命令和参数错误以错误帧返回
+fn parse_command(frame: &Frame) -> std::result::Result<cmd::Command, Frame> {
+    match cmd::Command::from_frame(frame) {
+        Ok(Some(cmd)) => Ok(cmd),
+        Ok(None) => Err(unknown_command(frame)),
+        Err(e) => Err(Frame::Error(e.to_string())),
+    }
+}
+
+/// 执行已经解析的命令，`frame` 为命令的原始帧
+///
+/// `deadline` 为命令的执行期限，从存储加载 key 和执行命令都受它限制，见 [`cmd::deadline`]。
+async fn execute_command(
+    db: &Db,
+    cmd: cmd::Command,
+    frame: Frame,
+    deadline: Option<Instant>,
+) -> (Frame, Option<u64>) {
+    let keys: Vec<String> = cmd.keys().into_iter().map(String::from).collect();
+    let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+    let loaded = match deadline {
+        Some(deadline) => time::timeout_at(deadline.into(), db.read_through(&keys)).await,
+        None => Ok(db.read_through(&keys).await),
+    };
+    match loaded {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return (Frame::Error(format!("ERR storage error: {}", e)), None),
+        Err(_) => return (cmd::deadline::timed_out(), None),
+    }
+
+    let (response, logged) = cmd::deadline::scope(deadline, || cmd.execute(frame, db));
+    if logged.is_some() {
+        if let Err(e) = db.write_through(&keys).await {
+            error!(error = %e, "Error writing back to storage");
+        }
+    }
+    (response, logged)
+}
+
+fn unknown_command(frame: &Frame) -> Frame {
+    let name = match frame {
+        Frame::Array(parts) => parts.first().map(Frame::to_string).unwrap_or_default(),
+        _ => String::new(),
+    };
+    Frame::Error(format!("ERR unknown command '{}'", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use tokio::{net::TcpStream, sync::oneshot};
+
+    use super::*;
+
+    fn command(args: &[&'static str]) -> Frame {
+        Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::from_static(arg.as_bytes())))
+                .collect(),
+        )
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn listeners_share_databases() {
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let path = std::env::temp_dir().join(format!("ilearn-{}.sock", std::process::id()));
+        let listeners = vec![Listener::Tcp(tcp), Listener::unix(&path).unwrap()];
+        let (tx, rx) = oneshot::channel::<()>();
+        let config = Config {
+            databases: 1,
+            ..Config::default()
+        };
+        let server =
+            tokio::spawn(async move { run_listeners(listeners, &config, rx).await.unwrap() });
+
+        let mut tcp = Connection::new(TcpStream::connect(addr).await.unwrap());
+        tcp.write_frame(&command(&["SET", "a", "1"])).await.unwrap();
+        tcp.read_frame().await.unwrap();
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let mut unix = Connection::new(stream);
+        unix.write_frame(&command(&["GET", "a"])).await.unwrap();
+        assert_eq!(
+            unix.read_frame().await.unwrap(),
+            Some(Frame::Bulk("1".into()))
+        );
+        unix.write_frame(&command(&["CLIENT", "LIST"]))
+            .await
+            .unwrap();
+        let Some(Frame::Bulk(list)) = unix.read_frame().await.unwrap() else {
+            panic!("expected a bulk string");
+        };
+        let expected = format!("addr={}:0 ", path.display());
+        assert!(String::from_utf8_lossy(&list).contains(&expected));
+
+        tx.send(()).unwrap();
+        drop((tcp, unix));
+        server.await.unwrap();
+        // 关闭后删除 socket 文件
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn shutdown_waits_for_connections_to_exit() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = oneshot::channel::<()>();
+        let config = Config {
+            databases: 1,
+            ..Config::default()
+        };
+        let server = tokio::spawn(async move { run_with(listener, &config, rx).await.unwrap() });
+
+        let mut connection = Connection::new(TcpStream::connect(addr).await.unwrap());
+        connection
+            .write_frame(&command(&["SET", "a", "1"]))
+            .await
+            .unwrap();
+        assert_eq!(
+            connection.read_frame().await.unwrap(),
+            Some(Frame::Simple("OK".into()))
+        );
+
+        tx.send(()).unwrap();
+        // 连接被服务端关闭后服务端才返回
+        assert_eq!(connection.read_frame().await.unwrap(), None);
+        server.await.unwrap();
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reloaded_config_applies_to_running_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config {
+            databases: 1,
+            ..Config::default()
+        };
+        let (updates, rx) = watch::channel(config.clone());
+        let listeners = vec![Listener::Tcp(listener)];
+        tokio::spawn(
+            async move { run_reloadable(listeners, rx, std::future::pending::<()>()).await },
+        );
+
+        let mut connection = Connection::new(TcpStream::connect(addr).await.unwrap());
+        connection.write_frame(&command(&["PING"])).await.unwrap();
+        connection.read_frame().await.unwrap();
+        updates.send_replace(Config {
+            requirepass: Some("secret".into()),
+            ..config
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // 已经认证的连接不受影响，新的连接需要认证
+        connection.write_frame(&command(&["PING"])).await.unwrap();
+        assert_eq!(
+            connection.read_frame().await.unwrap(),
+            Some(Frame::Simple("PONG".into()))
+        );
+        let mut connection = Connection::new(TcpStream::connect(addr).await.unwrap());
+        connection.write_frame(&command(&["PING"])).await.unwrap();
+        assert_eq!(
+            connection.read_frame().await.unwrap(),
+            Some(Frame::Error("NOAUTH Authentication required.".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn published_messages_reach_channels_and_patterns() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { run(listener, std::future::pending::<()>()).await });
+
+        let mut subscriber = Connection::new(TcpStream::connect(addr).await.unwrap());
+        subscriber
+            .write_frame(&command(&["SUBSCRIBE", "a", "news.a"]))
+            .await
+            .unwrap();
+        subscriber
+            .write_frame(&command(&["PSUBSCRIBE", "news.*"]))
+            .await
+            .unwrap();
+        let confirmations = [("subscribe", "a", 1), ("subscribe", "news.a", 2)];
+        let confirmations = confirmations
+            .into_iter()
+            .chain([("psubscribe", "news.*", 3)]);
+        for (kind, name, count) in confirmations {
+            assert_eq!(
+                subscriber.read_frame().await.unwrap(),
+                Some(Frame::Array(vec![
+                    Frame::Bulk(kind.into()),
+                    Frame::Bulk(name.into()),
+                    Frame::Integer(count)
+                ]))
+            );
+        }
+        // 订阅状态下只能执行订阅相关的命令
+        subscriber
+            .write_frame(&command(&["GET", "a"]))
+            .await
+            .unwrap();
+        assert!(matches!(
+            subscriber.read_frame().await.unwrap(),
+            Some(Frame::Error(e)) if e.starts_with("ERR Can't execute 'get'")
+        ));
+
+        let mut publisher = Connection::new(TcpStream::connect(addr).await.unwrap());
+        publisher
+            .write_frame(&command(&["PUBLISH", "news.a", "hi"]))
+            .await
+            .unwrap();
+        assert_eq!(
+            publisher.read_frame().await.unwrap(),
+            Some(Frame::Integer(2))
+        );
+        assert_eq!(
+            subscriber.read_frame().await.unwrap(),
+            Some(command(&["message", "news.a", "hi"]))
+        );
+        assert_eq!(
+            subscriber.read_frame().await.unwrap(),
+            Some(command(&["pmessage", "news.*", "news.a", "hi"]))
+        );
+
+        // 取消所有订阅后回到普通状态
+        subscriber
+            .write_frame(&command(&["PUNSUBSCRIBE"]))
+            .await
+            .unwrap();
+        subscriber
+            .write_frame(&command(&["UNSUBSCRIBE"]))
+            .await
+            .unwrap();
+        let mut counts = vec![];
+        for _ in 0..3 {
+            match subscriber.read_frame().await.unwrap() {
+                Some(Frame::Array(parts)) => counts.push(parts[2].clone()),
+                frame => panic!("expected a confirmation, got {:?}", frame),
+            }
+        }
+        assert_eq!(counts[0], Frame::Integer(2));
+        assert_eq!(counts[2], Frame::Integer(0));
+        subscriber
+            .write_frame(&command(&["GET", "a"]))
+            .await
+            .unwrap();
+        assert_eq!(subscriber.read_frame().await.unwrap(), Some(Frame::Null));
+    }
+
+    #[tokio::test]
+    async fn slow_subscribers_are_disconnected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { run(listener, std::future::pending::<()>()).await });
+
+        let mut subscriber = Connection::new(TcpStream::connect(addr).await.unwrap());
+        subscriber
+            .write_frame(&command(&["SUBSCRIBE", "news"]))
+            .await
+            .unwrap();
+        subscriber.read_frame().await.unwrap();
+
+        // 订阅者不读取，消息堆积在服务端，超过上限后不再投递
+        let mut publisher = Connection::new(TcpStream::connect(addr).await.unwrap());
+        let publish = Frame::Array(vec![
+            Frame::Bulk("PUBLISH".into()),
+            Frame::Bulk("news".into()),
+            Frame::Bulk(Bytes::from(vec![b'x'; 1 << 20])),
+        ]);
+        let mut delivered = 0;
+        loop {
+            publisher.write_frame(&publish).await.unwrap();
+            match publisher.read_frame().await.unwrap() {
+                Some(Frame::Integer(1)) => delivered += 1,
+                Some(Frame::Integer(0)) => break,
+                frame => panic!("unexpected reply {:?}", frame),
+            }
+            assert!(delivered < 256, "the subscriber was never disconnected");
+        }
+        assert!(delivered >= pubsub::OUTPUT_LIMIT >> 20);
+
+        // 连接被关闭，读完已经写出的消息后结束
+        let drained = async { while let Ok(Some(_)) = subscriber.read_frame().await {} };
+        time::timeout(Duration::from_secs(10), drained)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn pubsub_commands_check_channel_permissions() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { run(listener, std::future::pending::<()>()).await });
+
+        let mut connection = Connection::new(TcpStream::connect(addr).await.unwrap());
+        let requests = [
+            &["ACL", "SETUSER", "alice", "on", ">pw", "&news.*", "+@all"][..],
+            &["AUTH", "alice", "pw"],
+        ];
+        for request in requests {
+            connection.write_frame(&command(request)).await.unwrap();
+            assert_eq!(
+                connection.read_frame().await.unwrap(),
+                Some(Frame::Simple("OK".to_string()))
+            );
+        }
+        let denied = [
+            &["PUBLISH", "sports", "hi"][..],
+            &["SUBSCRIBE", "news.a", "sports"],
+            &["PSUBSCRIBE", "*"],
+            &["PSUBSCRIBE", "news.a*"],
+        ];
+        for request in denied {
+            connection.write_frame(&command(request)).await.unwrap();
+            assert!(matches!(
+                connection.read_frame().await.unwrap(),
+                Some(Frame::Error(e)) if e.starts_with("NOPERM")
+            ));
+        }
+        // 被拒绝的 SUBSCRIBE 没有订阅其中允许的频道
+        connection
+            .write_frame(&command(&["PUBLISH", "news.a", "hi"]))
+            .await
+            .unwrap();
+        assert_eq!(
+            connection.read_frame().await.unwrap(),
+            Some(Frame::Integer(0))
+        );
+        connection
+            .write_frame(&command(&["PSUBSCRIBE", "news.*"]))
+            .await
+            .unwrap();
+        assert_eq!(
+            connection.read_frame().await.unwrap(),
+            Some(Frame::Array(vec![
+                Frame::Bulk("psubscribe".into()),
+                Frame::Bulk("news.*".into()),
+                Frame::Integer(1)
+            ]))
+        );
+    }
+
+    #[tokio::test]
+    async fn reload_keeps_options_that_need_a_restart() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config {
+            port: addr.port(),
+            databases: 1,
+            ..Config::default()
+        };
+        let (updates, rx) = watch::channel(config.clone());
+        let listeners = vec![Listener::Tcp(listener)];
+        tokio::spawn(
+            async move { run_reloadable(listeners, rx, std::future::pending::<()>()).await },
+        );
+
+        let mut connection = Connection::new(TcpStream::connect(addr).await.unwrap());
+        connection.write_frame(&command(&["PING"])).await.unwrap();
+        connection.read_frame().await.unwrap();
+        updates.send_replace(Config {
+            port: addr.port() + 1,
+            max_key_len: 4,
+            ..config
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let mut get = async |name: &'static str| {
+            connection
+                .write_frame(&command(&["CONFIG", "GET", name]))
+                .await
+                .unwrap();
+            match connection.read_frame().await.unwrap() {
+                Some(Frame::Array(pair)) => pair[1].to_string(),
+                frame => panic!("unexpected reply {:?}", frame),
+            }
+        };
+        assert_eq!(get("port").await, addr.port().to_string());
+        assert_eq!(get("max-key-len").await, "4");
+    }
+
+    #[tokio::test]
+    async fn config_set_applies_to_running_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config {
+            databases: 1,
+            ..Config::default()
+        };
+        tokio::spawn(
+            async move { run_with(listener, &config, std::future::pending::<()>()).await },
+        );
+
+        let mut connection = Connection::new(TcpStream::connect(addr).await.unwrap());
+        let mut send = async |args: &[&'static str]| {
+            connection.write_frame(&command(args)).await.unwrap();
+            connection.read_frame().await.unwrap().unwrap()
+        };
+        let ok = Frame::Simple("OK".into());
+        assert_eq!(send(&["CONFIG", "SET", "max-key-len", "4"]).await, ok);
+        assert_eq!(
+            send(&["CONFIG", "GET", "max-key-*"]).await,
+            Frame::Array(vec![
+                Frame::Bulk("max-key-len".into()),
+                Frame::Bulk("4".into())
+            ])
+        );
+        assert!(matches!(
+            send(&["SET", "toolong", "1"]).await,
+            Frame::Error(_)
+        ));
+        assert_eq!(
+            send(&["CONFIG", "SET", "port", "7000"]).await,
+            Frame::Error(
+                "ERR CONFIG SET failed (possibly related to argument 'port') - \
+                 can't set immutable config 'port'"
+                    .into()
+            )
+        );
+        // 任何一个无效时都不修改
+        assert!(matches!(
+            send(&["CONFIG", "SET", "max-key-len", "8", "timeout", "x"]).await,
+            Frame::Error(_)
+        ));
+        assert_eq!(
+            send(&["CONFIG", "GET", "max-key-len"]).await,
+            Frame::Array(vec![
+                Frame::Bulk("max-key-len".into()),
+                Frame::Bulk("4".into())
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn replica_follows_primary() {
+        let mut addrs = vec![];
+        for _ in 0..2 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            addrs.push(listener.local_addr().unwrap());
+            let config = Config {
+                databases: 2,
+                ..Config::default()
+            };
+            tokio::spawn(
+                async move { run_with(listener, &config, std::future::pending::<()>()).await },
+            );
+        }
+        let connect = async |addr| Connection::new(TcpStream::connect(addr).await.unwrap());
+        let (mut primary, mut replica) = (connect(addrs[0]).await, connect(addrs[1]).await);
+        let send = async |connection: &mut Connection<TcpStream>, args: &[&str]| {
+            let args = args.iter().map(|arg| Frame::Bulk(arg.to_string().into()));
+            connection
+                .write_frame(&Frame::Array(args.collect()))
+                .await
+                .unwrap();
+            connection.read_frame().await.unwrap().unwrap()
+        };
+        // 等待副本执行完主节点的命令
+        let wait_for = async |connection: &mut Connection<TcpStream>, key: &str, value: &str| {
+            for _ in 0..100 {
+                if send(&mut *connection, &["GET", key]).await
+                    == Frame::Bulk(value.to_string().into())
+                {
+                    return;
+                }
+                time::sleep(Duration::from_millis(20)).await;
+            }
+            panic!("{} was not replicated", key);
+        };
+
+        send(&mut primary, &["SET", "before", "1"]).await;
+        let port = addrs[0].port().to_string();
+        let ok = Frame::Simple("OK".into());
+        assert_eq!(
+            send(&mut replica, &["REPLICAOF", "127.0.0.1", &port]).await,
+            ok
+        );
+        wait_for(&mut replica, "before", "1").await;
+
+        // 全量同步之后的写命令，包括其他数据库的
+        send(&mut primary, &["INCR", "counter"]).await;
+        send(&mut primary, &["SELECT", "1"]).await;
+        send(&mut primary, &["SET", "other", "2"]).await;
+        send(&mut replica, &["SELECT", "1"]).await;
+        wait_for(&mut replica, "other", "2").await;
+        send(&mut replica, &["SELECT", "0"]).await;
+        wait_for(&mut replica, "counter", "1").await;
+
+        assert_eq!(
+            send(&mut replica, &["SET", "x", "1"]).await,
+            Frame::Error(crate::db::DbError::ReadOnly.to_string())
+        );
+        let Frame::Array(role) = send(&mut replica, &["ROLE"]).await else {
+            panic!("ROLE should return an array");
+        };
+        assert_eq!(role[0], Frame::Bulk("slave".into()));
+        assert_eq!(role[3], Frame::Bulk("connected".into()));
+        let Frame::Array(role) = send(&mut primary, &["ROLE"]).await else {
+            panic!("ROLE should return an array");
+        };
+        assert_eq!(role[0], Frame::Bulk("master".into()));
+        assert!(matches!(&role[2], Frame::Array(replicas) if replicas.len() == 1));
+
+        // 停止复制后成为主节点，数据保留并且可以写入
+        assert_eq!(send(&mut replica, &["REPLICAOF", "NO", "ONE"]).await, ok);
+        assert_eq!(send(&mut replica, &["SET", "x", "1"]).await, ok);
+        wait_for(&mut replica, "counter", "1").await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn writes_during_sync_are_applied_once() {
+        let mut addrs = vec![];
+        for _ in 0..2 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            addrs.push(listener.local_addr().unwrap());
+            let config = Config::default();
+            tokio::spawn(
+                async move { run_with(listener, &config, std::future::pending::<()>()).await },
+            );
+        }
+        let connect = async |addr| Connection::new(TcpStream::connect(addr).await.unwrap());
+        let send = async |connection: &mut Connection<TcpStream>, args: &[&str]| {
+            let args = args.iter().map(|arg| Frame::Bulk(arg.to_string().into()));
+            connection
+                .write_frame(&Frame::Array(args.collect()))
+                .await
+                .unwrap();
+            connection.read_frame().await.unwrap().unwrap()
+        };
+
+        // 全量同步的同时不断执行 INCRBY，每一条都应当在快照中或者在之后的日志中，只执行一次
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let addr = addrs[0];
+                tokio::spawn(async move {
+                    let mut primary = connect(addr).await;
+                    for _ in 0..1000 {
+                        send(&mut primary, &["INCRBY", "counter", "1"]).await;
+                    }
+                })
+            })
+            .collect();
+        let mut primary = connect(addrs[0]).await;
+        while send(&mut primary, &["GET", "counter"]).await == Frame::Null {
+            tokio::task::yield_now().await;
+        }
+        let mut replica = connect(addrs[1]).await;
+        let port = addrs[0].port().to_string();
+        send(&mut replica, &["REPLICAOF", "127.0.0.1", &port]).await;
+        for writer in writers {
+            writer.await.unwrap();
+        }
+
+        let expected = Frame::Bulk("4000".into());
+        for _ in 0..100 {
+            if send(&mut replica, &["GET", "counter"]).await == expected {
+                return;
+            }
+            time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!(
+            "replica has {:?}",
+            send(&mut replica, &["GET", "counter"]).await
+        );
+    }
+
+    #[tokio::test]
+    async fn cluster_redirects_keys_of_other_slots() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut config = Config {
+            databases: 1,
+            ..Config::default()
+        };
+        config.set("cluster-enabled", "yes").unwrap();
+        config.set("cluster-announce", "127.0.0.1:7000").unwrap();
+        config
+            .set(
+                "cluster-slots",
+                "0-8191 127.0.0.1:7000, 8192-16383 127.0.0.1:7001",
+            )
+            .unwrap();
+        tokio::spawn(
+            async move { run_with(listener, &config, std::future::pending::<()>()).await },
+        );
+
+        let mut connection = Connection::new(TcpStream::connect(addr).await.unwrap());
+        let mut send = async |args: &[&'static str]| {
+            connection.write_frame(&command(args)).await.unwrap();
+            connection.read_frame().await.unwrap().unwrap()
+        };
+        let ok = Frame::Simple("OK".into());
+        let moved = Frame::Error("MOVED 12182 127.0.0.1:7001".into());
+        // bar 在 slot 5061，foo 在 slot 12182
+        assert_eq!(send(&["SET", "bar", "1"]).await, ok);
+        assert_eq!(send(&["GET", "foo"]).await, moved);
+        assert_eq!(
+            send(&["CLUSTER", "KEYSLOT", "foo"]).await,
+            Frame::Integer(12182)
+        );
+        assert!(
+            matches!(send(&["CLUSTER", "INFO"]).await, Frame::Bulk(info) if info.starts_with(b"cluster_enabled:1\r\ncluster_state:ok"))
+        );
+
+        // 迁入中的 slot 只有紧跟在 ASKING 之后的一条命令可以执行
+        assert_eq!(
+            send(&["CONFIG", "SET", "cluster-importing", "12182"]).await,
+            ok
+        );
+        assert_eq!(send(&["ASKING"]).await, ok);
+        assert_eq!(send(&["GET", "foo"]).await, Frame::Null);
+        assert_eq!(send(&["GET", "foo"]).await, moved);
+
+        // 迁出中的 slot，已经不在这里的 key 回复 ASK
+        let migrating = "5061 127.0.0.1:7001";
+        assert_eq!(
+            send(&["CONFIG", "SET", "cluster-migrating", migrating]).await,
+            ok
+        );
+        assert_eq!(send(&["GET", "bar"]).await, Frame::Bulk("1".into()));
+        assert_eq!(
+            send(&["GET", "{bar}.x"]).await,
+            Frame::Error("ASK 5061 127.0.0.1:7001".into())
+        );
+    }
+
+    #[cfg(feature = "fault-injection")]
+    #[tokio::test]
+    async fn injected_faults_affect_only_their_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config {
+            databases: 1,
+            ..Config::default()
+        };
+        tokio::spawn(
+            async move { run_with(listener, &config, std::future::pending::<()>()).await },
+        );
+
+        let mut faulty = Connection::new(TcpStream::connect(addr).await.unwrap());
+        let mut healthy = Connection::new(TcpStream::connect(addr).await.unwrap());
+        let ok = Some(Frame::Simple("OK".into()));
+        for args in [
+            &["DEBUG", "OOM-RATE", "1"][..],
+            &["DEBUG", "LATENCY", "100"],
+        ] {
+            faulty.write_frame(&command(args)).await.unwrap();
+            assert_eq!(faulty.read_frame().await.unwrap(), ok);
+        }
+        let started = std::time::Instant::now();
+        faulty.write_frame(&command(&["GET", "a"])).await.unwrap();
+        assert_eq!(
+            faulty.read_frame().await.unwrap(),
+            Some(Frame::Error(crate::db::DbError::OutOfMemory.to_string()))
+        );
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        healthy.write_frame(&command(&["GET", "a"])).await.unwrap();
+        assert_eq!(healthy.read_frame().await.unwrap(), Some(Frame::Null));
+
+        // 关闭连接时不发送响应
+        faulty
+            .write_frame(&command(&["DEBUG", "CLOSE-RATE", "1"]))
+            .await
+            .unwrap();
+        assert_eq!(faulty.read_frame().await.unwrap(), ok);
+        faulty.write_frame(&command(&["PING"])).await.unwrap();
+        assert_eq!(faulty.read_frame().await.unwrap(), None);
+    }
+
+    #[cfg(feature = "fault-injection")]
+    #[tokio::test]
+    async fn panicking_command_closes_only_its_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config {
+            databases: 1,
+            ..Config::default()
+        };
+        tokio::spawn(
+            async move { run_with(listener, &config, std::future::pending::<()>()).await },
+        );
+
+        let mut panicking = Connection::new(TcpStream::connect(addr).await.unwrap());
+        let mut other = Connection::new(TcpStream::connect(addr).await.unwrap());
+        panicking
+            .write_frame(&command(&["DEBUG", "PANIC"]))
+            .await
+            .unwrap();
+        assert!(matches!(
+            panicking.read_frame().await.unwrap(),
+            Some(Frame::Error(_))
+        ));
+        assert_eq!(panicking.read_frame().await.unwrap(), None);
+
+        other.write_frame(&command(&["PING"])).await.unwrap();
+        assert_eq!(
+            other.read_frame().await.unwrap(),
+            Some(Frame::Simple("PONG".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn accept_thread_hands_connections_to_the_runtime() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config {
+            databases: 1,
+            accept_thread: true,
+            ..Config::default()
+        };
+        let (tx, rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(async move { run_with(listener, &config, rx).await });
+
+        let mut connection = Connection::new(TcpStream::connect(addr).await.unwrap());
+        connection.write_frame(&command(&["PING"])).await.unwrap();
+        assert_eq!(
+            connection.read_frame().await.unwrap(),
+            Some(Frame::Simple("PONG".into()))
+        );
+        tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        assert_eq!(connection.read_frame().await.unwrap(), None);
+    }
+
+    /// 前 `failures` 次 accept 返回 EMFILE
+    struct Exhausted {
+        failures: AtomicUsize,
+    }
+
+    impl Accept for Exhausted {
+        type Stream = tokio::io::DuplexStream;
+
+        async fn accept(&self) -> io::Result<(Self::Stream, PeerAddr)> {
+            let remaining = self.failures.load(Ordering::Relaxed);
+            if remaining > 0 {
+                self.failures.store(remaining - 1, Ordering::Relaxed);
+                return Err(io::Error::from_raw_os_error(24));
+            }
+            let peer = PeerAddr::Tcp("127.0.0.1:6000".parse().unwrap());
+            Ok((tokio::io::duplex(64).0, peer))
+        }
+
+        fn reregister(stream: Self::Stream) -> io::Result<Self::Stream> {
+            Ok(stream)
+        }
+    }
+
+    #[tokio::test]
+    async fn accept_errors_back_off_instead_of_failing() {
+        let listener = Exhausted { failures: 3.into() };
+        let started = std::time::Instant::now();
+        let (_, peer) = next_connection(&listener).await;
+        assert_eq!(peer.to_string(), "127.0.0.1:6000");
+        // 5ms、10ms、20ms
+        assert!(started.elapsed() >= ACCEPT_BACKOFF_MIN * 7);
+    }
+
+    #[tokio::test]
+    async fn proxy_protocol_sets_client_address() {
+        use tokio::io::AsyncWriteExt;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config {
+            databases: 1,
+            proxy_protocol: true,
+            allowlist: vec!["192.0.2.0/24".parse().unwrap()],
+            ..Config::default()
+        };
+        tokio::spawn(
+            async move { run_with(listener, &config, std::future::pending::<()>()).await },
+        );
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"PROXY TCP4 192.0.2.7 127.0.0.1 40000 6379\r\n")
+            .await
+            .unwrap();
+        let mut connection = Connection::new(stream);
+        connection
+            .write_frame(&command(&["CLIENT", "LIST"]))
+            .await
+            .unwrap();
+        let Some(Frame::Bulk(info)) = connection.read_frame().await.unwrap() else {
+            panic!("expected a bulk string");
+        };
+        assert!(String::from_utf8_lossy(&info).contains("addr=192.0.2.7:40000"));
+
+        // 头部中的地址同样要通过 allowlist
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"PROXY TCP4 198.51.100.1 127.0.0.1 40000 6379\r\n")
+            .await
+            .unwrap();
+        let mut connection = Connection::new(stream);
+        assert_eq!(
+            connection.read_frame().await.unwrap(),
+            Some(Frame::Error("ERR client address not allowed".into()))
+        );
+
+        // 没有头部的连接不回复直接关闭，未读取的数据可能使关闭表现为 reset
+        let mut connection = Connection::new(TcpStream::connect(addr).await.unwrap());
+        connection.write_frame(&command(&["PING"])).await.unwrap();
+        assert!(!matches!(connection.read_frame().await, Ok(Some(_))));
+    }
+
+    #[tokio::test]
+    async fn proxy_headers_are_read_only_from_trusted_peers() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config {
+            databases: 1,
+            proxy_protocol: true,
+            proxy_trusted: vec!["192.0.2.1".parse().unwrap()],
+            ..Config::default()
+        };
+        tokio::spawn(
+            async move { run_with(listener, &config, std::future::pending::<()>()).await },
+        );
+
+        // 不受信任的对端按直接连接处理，不需要也不能发送头部
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let expected = format!("addr={} ", stream.local_addr().unwrap());
+        let mut connection = Connection::new(stream);
+        connection
+            .write_frame(&command(&["CLIENT", "LIST"]))
+            .await
+            .unwrap();
+        let Some(Frame::Bulk(info)) = connection.read_frame().await.unwrap() else {
+            panic!("expected a bulk string");
+        };
+        assert!(String::from_utf8_lossy(&info).contains(&expected));
+    }
+
+    #[tokio::test]
+    async fn pipelined_replies_arrive_in_order() {
+        use tokio::io::AsyncWriteExt;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config {
+            databases: 1,
+            ..Config::default()
+        };
+        tokio::spawn(
+            async move { run_with(listener, &config, std::future::pending::<()>()).await },
+        );
+
+        // 一次写入整个 pipeline，响应在写缓冲区中累积后写入
+        let mut pipeline = Vec::new();
+        for _ in 0..100 {
+            command(&["INCR", "n"]).encode(&mut pipeline);
+        }
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(&pipeline).await.unwrap();
+        let mut connection = Connection::new(stream);
+        for n in 1..=100 {
+            assert_eq!(
+                connection.read_frame().await.unwrap(),
+                Some(Frame::Integer(n))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn shutdown_command_saves_snapshot() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let path = std::env::temp_dir().join(format!("ilearn-{}.rdb", std::process::id()));
+        let config = Config {
+            databases: 1,
+            dbfilename: path.to_string_lossy().into(),
+            ..Config::default()
+        };
+        let server =
+            tokio::spawn(
+                async move { run_with(listener, &config, std::future::pending::<()>()).await },
+            );
+
+        let mut connection = Connection::new(TcpStream::connect(addr).await.unwrap());
+        for args in [&["SET", "a", "1"][..], &["SHUTDOWN", "SAVE"]] {
+            connection.write_frame(&command(args)).await.unwrap();
+            assert_eq!(
+                connection.read_frame().await.unwrap(),
+                Some(Frame::Simple("OK".into()))
+            );
+        }
+        assert_eq!(connection.read_frame().await.unwrap(), None);
+        server.await.unwrap().unwrap();
+
+        let db = Db::new();
+        assert_eq!(db.load(&path).unwrap(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn shutdown_aborts_connections_after_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config {
+            databases: 1,
+            shutdown_timeout: 1,
+            ..Config::default()
+        };
+        let (tx, rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(async move { run_with(listener, &config, rx).await });
+
+        // 客户端不读取响应，连接在发送响应时阻塞，只能在超时后中止
+        let mut stuck = Connection::new(TcpStream::connect(addr).await.unwrap());
+        let value = Bytes::from(vec![b'x'; 1024 * 1024]);
+        let set = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"SET")),
+            Frame::Bulk(Bytes::from_static(b"big")),
+            Frame::Bulk(value),
+        ]);
+        stuck.write_frame(&set).await.unwrap();
+        assert!(stuck.read_frame().await.unwrap().is_some());
+        for _ in 0..64 {
+            stuck.write_frame(&command(&["GET", "big"])).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        tx.send(()).unwrap();
+
+        let finished = tokio::time::timeout(Duration::from_secs(5), server).await;
+        finished.unwrap().unwrap().unwrap();
+        // 读完已经发送的响应后连接被关闭
+        while let Ok(Some(_)) = stuck.read_frame().await {}
+    }
+
+    #[tokio::test]
+    async fn connections_over_maxclients_are_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config {
+            databases: 1,
+            maxclients: 1,
+            ..Config::default()
+        };
+        tokio::spawn(
+            async move { run_with(listener, &config, std::future::pending::<()>()).await },
+        );
+
+        let mut first = Connection::new(TcpStream::connect(addr).await.unwrap());
+        first.write_frame(&command(&["PING"])).await.unwrap();
+        assert!(first.read_frame().await.unwrap().is_some());
+
+        let mut second = Connection::new(TcpStream::connect(addr).await.unwrap());
+        assert_eq!(
+            second.read_frame().await.unwrap(),
+            Some(Frame::Error("ERR max number of clients reached".into()))
+        );
+        assert_eq!(second.read_frame().await.unwrap(), None);
+
+        // 第一个连接关闭后许可被归还
+        drop(first);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let mut third = Connection::new(TcpStream::connect(addr).await.unwrap());
+        third.write_frame(&command(&["PING"])).await.unwrap();
+        let response = third.read_frame().await.unwrap();
+        assert_eq!(response, Some(Frame::Simple("PONG".into())));
+    }
+
+    #[tokio::test]
+    async fn pipelined_commands_are_answered_in_order() {
+        use tokio::io::AsyncWriteExt;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config {
+            databases: 1,
+            ..Config::default()
+        };
+        tokio::spawn(
+            async move { run_with(listener, &config, std::future::pending::<()>()).await },
+        );
+
+        // 一次写入远多于队列容量的命令
+        let mut buf = Vec::new();
+        for _ in 0..100 {
+            command(&["INCR", "n"]).encode(&mut buf);
+        }
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(&buf).await.unwrap();
+        let mut connection = Connection::new(stream);
+        for n in 1..=100 {
+            let response = connection.read_frame().await.unwrap();
+            assert_eq!(response, Some(Frame::Integer(n)));
+        }
+    }
+
+    #[tokio::test]
+    async fn command_errors_keep_the_connection_and_protocol_errors_close_it() {
+        use tokio::io::AsyncWriteExt;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config {
+            databases: 1,
+            ..Config::default()
+        };
+        tokio::spawn(
+            async move { run_with(listener, &config, std::future::pending::<()>()).await },
+        );
+
+        let mut connection = Connection::new(TcpStream::connect(addr).await.unwrap());
+        for bad in [command(&["GET"]), command(&["NOSUCHCOMMAND"])] {
+            connection.write_frame(&bad).await.unwrap();
+            assert!(matches!(
+                connection.read_frame().await.unwrap(),
+                Some(Frame::Error(_))
+            ));
+        }
+        connection.write_frame(&command(&["PING"])).await.unwrap();
+        assert_eq!(
+            connection.read_frame().await.unwrap(),
+            Some(Frame::Simple("PONG".into()))
+        );
+
+        // 无法分帧的数据：先返回错误再关闭连接，服务端继续接受新的连接
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"?garbage\r\n").await.unwrap();
+        let mut broken = Connection::new(stream);
+        assert!(matches!(
+            broken.read_frame().await.unwrap(),
+            Some(Frame::Error(e)) if e.starts_with("ERR")
+        ));
+        assert_eq!(broken.read_frame().await.unwrap(), None);
+
+        connection.write_frame(&command(&["PING"])).await.unwrap();
+        assert!(connection.read_frame().await.unwrap().is_some());
+        let mut fresh = Connection::new(TcpStream::connect(addr).await.unwrap());
+        fresh.write_frame(&command(&["PING"])).await.unwrap();
+        assert!(fresh.read_frame().await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn idle_connections_are_closed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config {
+            databases: 1,
+            timeout: 1,
+            ..Config::default()
+        };
+        tokio::spawn(
+            async move { run_with(listener, &config, std::future::pending::<()>()).await },
+        );
+
+        let mut connection = Connection::new(TcpStream::connect(addr).await.unwrap());
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        connection.write_frame(&command(&["PING"])).await.unwrap();
+        assert!(connection.read_frame().await.unwrap().is_some());
+
+        // 执行命令后重新计时，超时后服务端关闭连接
+        assert_eq!(connection.read_frame().await.unwrap(), None);
+        assert!(connection.idle() >= Duration::from_millis(900));
+    }
+}
@@ -0,0 +1,87 @@
+//! MONITOR 输出的命令行
+//!
+//! 所有连接执行的命令经过 [`super::State`] 中的广播通道发送给处于 MONITOR 状态的连接，
+//! 没有 MONITOR 连接时不会格式化命令。格式与 Redis 相同：
+//!
+//! ```text
+//! 1339518083.107412 [0 127.0.0.1:60866] "set" "key" "value"
+//! ```
+
+use std::{
+    fmt::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::PeerAddr;
+use crate::frame::Frame;
+
+/// 广播通道的容量，MONITOR 连接落后更多时丢弃最旧的命令
+pub(crate) const CAPACITY: usize = 1024;
+
+/// 格式化一条命令，`db` 为执行命令的连接当前使用的数据库
+pub(crate) fn format_line(frame: &Frame, db: usize, addr: &PeerAddr) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut line = format!(
+        "{}.{:06} [{} {}]",
+        now.as_secs(),
+        now.subsec_micros(),
+        db,
+        addr
+    );
+    if let Frame::Array(parts) = frame {
+        for part in parts {
+            line.push(' ');
+            match part {
+                Frame::Bulk(bytes) => quote(&mut line, bytes),
+                other => quote(&mut line, other.to_string().as_bytes()),
+            }
+        }
+    }
+    line
+}
+
+/// 加上引号并转义不可打印的字符，与 Redis 的 sdscatrepr 相同
+fn quote(out: &mut String, bytes: &[u8]) {
+    out.push('"');
+    for &b in bytes {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            b if b.is_ascii_graphic() || b == b' ' => out.push(b as char),
+            b => {
+                let _ = write!(out, "\\x{:02x}", b);
+            }
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn arguments_are_quoted_and_escaped() {
+        let frame = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"set")),
+            Frame::Bulk(Bytes::from_static(b"a \"key\"")),
+            Frame::Bulk(Bytes::from_static(b"line\n\x00\xff")),
+        ]);
+        let line = format_line(&frame, 3, &"127.0.0.1:6000".parse().unwrap());
+        let (timestamp, rest) = line.split_once(' ').unwrap();
+        assert!(timestamp.contains('.'));
+        assert_eq!(
+            rest,
+            r#"[3 127.0.0.1:6000] "set" "a \"key\"" "line\n\x00\xff""#
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! HAProxy PROXY 协议
+//!
+//! 服务端在负载均衡后面时，对端地址是负载均衡的地址。开启 `proxy-protocol` 后，
+//! 每个 TCP 连接在 RESP 数据之前必须先发送 PROXY 协议的头部，其中的源地址作为客户端的地址，
+//! 用于 allowlist、限流、日志和 CLIENT LIST。支持文本格式的 v1 和二进制格式的 v2，
+//! 没有头部或者头部无效的连接被关闭。
+//!
+//! 只有 `proxy-trusted` 中的对端（负载均衡）发送的头部才会被读取，其他对端按直接连接处理，
+//! 客户端不能通过伪造头部绕过 allowlist 和限流。保护模式还要求传输层的对端在本机，
+//! 头部中的回环地址不能让远程的连接被视为本机客户端。
+//!
+//! 头部之后的数据属于 RESP 或 TLS，读取时不能多读：v1 逐字节读到行尾，v2 按头部中的长度读取。
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{Cidr, PeerAddr};
+
+/// 接受连接后等待头部的最长时间
+pub(super) const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// v1 头部的最大长度，包括结尾的 CRLF
+const V1_MAX_LEN: usize = 107;
+
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// 是否读取对端发送的头部：`trusted` 中的网段，为空时只有回环地址，unix socket 的客户端不经过负载均衡
+pub(super) fn is_trusted(trusted: &[Cidr], peer: &PeerAddr) -> bool {
+    match peer {
+        PeerAddr::Tcp(addr) if trusted.is_empty() => addr.ip().to_canonical().is_loopback(),
+        PeerAddr::Tcp(addr) => trusted.iter().any(|cidr| cidr.contains(addr.ip())),
+        PeerAddr::Unix(_) => false,
+    }
+}
+
+/// 读取 PROXY 协议的头部，返回客户端的地址
+///
+/// 头部没有携带地址时（v1 的 `UNKNOWN`、v2 的 `LOCAL` 命令或者非 TCP 的地址族）返回 None，
+/// 这时使用连接本身的对端地址。
+pub(super) async fn read_header<S: AsyncRead + Unpin>(
+    stream: &mut S,
+) -> io::Result<Option<SocketAddr>> {
+    let mut prefix = [0; 6];
+    stream.read_exact(&mut prefix).await?;
+    if &prefix == b"PROXY " {
+        let mut line = prefix.to_vec();
+        while !line.ends_with(b"\r\n") {
+            if line.len() == V1_MAX_LEN {
+                return Err(invalid("PROXY header too long"));
+            }
+            line.push(stream.read_u8().await?);
+        }
+        parse_v1(&line[..line.len() - 2])
+    } else if prefix == V2_SIGNATURE[..6] {
+        let mut header = [0; 10];
+        stream.read_exact(&mut header).await?;
+        if header[..6] != V2_SIGNATURE[6..] {
+            return Err(invalid("missing PROXY header"));
+        }
+        let len = u16::from_be_bytes([header[8], header[9]]) as usize;
+        let mut addrs = vec![0; len];
+        stream.read_exact(&mut addrs).await?;
+        parse_v2(header[6], header[7], &addrs)
+    } else {
+        Err(invalid("missing PROXY header"))
+    }
+}
+
+/// `PROXY TCP4 源地址 目的地址 源端口 目的端口`，不包括 CRLF
+fn parse_v1(line: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let line = std::str::from_utf8(line).map_err(|_| invalid("invalid PROXY header"))?;
+    let fields: Vec<_> = line.split(' ').collect();
+    match fields[..] {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", family @ ("TCP4" | "TCP6"), source, _, port, _] => {
+            let ip: IpAddr = source
+                .parse()
+                .map_err(|_| invalid("invalid PROXY source address"))?;
+            if ip.is_ipv4() != (family == "TCP4") {
+                return Err(invalid("invalid PROXY source address"));
+            }
+            let port = port
+                .parse()
+                .map_err(|_| invalid("invalid PROXY source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("invalid PROXY header")),
+    }
+}
+
+/// v2 头部中签名之后的版本和命令、地址族，以及地址部分
+fn parse_v2(version_command: u8, family: u8, addrs: &[u8]) -> io::Result<Option<SocketAddr>> {
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    match version_command & 0x0f {
+        // LOCAL：负载均衡自己的连接，如健康检查
+        0 => return Ok(None),
+        1 => {}
+        _ => return Err(invalid("invalid PROXY command")),
+    }
+    let port = |offset: usize| u16::from_be_bytes([addrs[offset], addrs[offset + 1]]);
+    match family {
+        // TCP over IPv4：源地址、目的地址、源端口、目的端口
+        0x11 if addrs.len() >= 12 => {
+            let ip = Ipv4Addr::from(<[u8; 4]>::try_from(&addrs[..4]).unwrap());
+            Ok(Some(SocketAddr::new(ip.into(), port(8))))
+        }
+        0x21 if addrs.len() >= 36 => {
+            let ip = Ipv6Addr::from(<[u8; 16]>::try_from(&addrs[..16]).unwrap());
+            Ok(Some(SocketAddr::new(ip.into(), port(32))))
+        }
+        0x11 | 0x21 => Err(invalid("truncated PROXY addresses")),
+        _ => Ok(None),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(input: &[u8]) -> (io::Result<Option<SocketAddr>>, Vec<u8>) {
+        let mut stream = input;
+        let header = read_header(&mut stream).await;
+        (header, stream.to_vec())
+    }
+
+    #[tokio::test]
+    async fn header_is_consumed_exactly() {
+        let (addr, rest) = read(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 6379\r\n*1\r\n").await;
+        assert_eq!(addr.unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(rest, b"*1\r\n");
+
+        let (addr, rest) = read(b"PROXY UNKNOWN\r\nPING").await;
+        assert_eq!(addr.unwrap(), None);
+        assert_eq!(rest, b"PING");
+
+        let mut v2 = V2_SIGNATURE.to_vec();
+        v2.extend_from_slice(&[0x21, 0x21, 0, 36]);
+        v2.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        v2.extend_from_slice(&[0; 16]);
+        v2.extend_from_slice(&[0x1f, 0x90, 0x18, 0xeb]);
+        v2.extend_from_slice(b"*1\r\n");
+        let (addr, rest) = read(&v2).await;
+        assert_eq!(addr.unwrap(), Some("[2001:db8::1]:8080".parse().unwrap()));
+        assert_eq!(rest, b"*1\r\n");
+
+        let (addr, _) = read(b"*1\r\n$4\r\nPING\r\n").await;
+        assert_eq!(addr.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let (addr, _) = read(b"PROXY TCP4 ::1 ::1 1 2\r\n").await;
+        assert!(addr.is_err());
+    }
+}
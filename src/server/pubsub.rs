@@ -0,0 +1,449 @@
+//! 发布订阅：SUBSCRIBE、PSUBSCRIBE 的登记表以及 PUBLISH 的投递
+//!
+//! 连接的处理器持有一个接收消息的通道，订阅时把发送端登记在这里。PUBLISH 在持有锁时把消息
+//! 发送给订阅了这个频道的连接，以及每个匹配的模式的订阅者：一个连接订阅了多个匹配的模式时，
+//! 每个模式各收到一条 `pmessage`。订阅的确认也在持有锁时生成，确认之后发布的消息一定在确认之后到达。
+//!
+//! 消息和确认以 RESP2 的数组表示，发送给 RESP3 连接时由处理器转换为 Push，见 [`for_protocol`]：
+//!
+//! ```text
+//! *3
+//! $7
+//! message
+//! $4
+//! news
+//! $5
+//! hello
+//! ```
+//!
+//! 与 Redis 相同，频道不区分数据库。
+//!
+//! 每个连接记录已经投递、还没有被连接取出写入套接字的消息的字节数。读取得慢的订阅者超过
+//! [`OUTPUT_LIMIT`] 时取消它的所有订阅，由调用方关闭连接，与 Redis 的
+//! `client-output-buffer-limit pubsub` 相同，消息不会无限堆积在内存中。
+
+use std::{
+    collections::{HashMap, HashSet},
+    future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use bytes::Bytes;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::{cmd::glob_match, frame::Frame};
+
+/// 一个连接排队等待写出的消息的字节数上限
+pub(crate) const OUTPUT_LIMIT: usize = 32 << 20;
+
+/// 订阅的是频道还是模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Channel,
+    Pattern,
+}
+
+impl Kind {
+    fn subscribe(self) -> &'static [u8] {
+        match self {
+            Kind::Channel => b"subscribe",
+            Kind::Pattern => b"psubscribe",
+        }
+    }
+
+    fn unsubscribe(self) -> &'static [u8] {
+        match self {
+            Kind::Channel => b"unsubscribe",
+            Kind::Pattern => b"punsubscribe",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct PubSub {
+    table: Mutex<Table>,
+    /// 每个连接排队的消息的字节数上限
+    limit: usize,
+}
+
+impl Default for PubSub {
+    fn default() -> PubSub {
+        PubSub::with_limit(OUTPUT_LIMIT)
+    }
+}
+
+/// 投递消息的一端，订阅时登记，记录排队的字节数
+#[derive(Debug, Clone)]
+pub(crate) struct Mailbox {
+    messages: mpsc::UnboundedSender<Frame>,
+    queued: Arc<AtomicUsize>,
+}
+
+/// 连接接收消息的一端
+#[derive(Debug)]
+pub(crate) struct Inbox {
+    messages: mpsc::UnboundedReceiver<Frame>,
+    queued: Arc<AtomicUsize>,
+}
+
+/// 创建一个连接接收消息的队列
+pub(crate) fn mailbox() -> (Mailbox, Inbox) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let queued = Arc::new(AtomicUsize::new(0));
+    let mailbox = Mailbox {
+        messages: tx,
+        queued: Arc::clone(&queued),
+    };
+    (
+        mailbox,
+        Inbox {
+            messages: rx,
+            queued,
+        },
+    )
+}
+
+impl Mailbox {
+    /// 投递一条消息，排队的字节数会超过 `limit` 时不投递并返回 false
+    fn send(&self, frame: Frame, limit: usize) -> bool {
+        let size = payload_len(&frame);
+        if self.queued.fetch_add(size, Ordering::Relaxed) + size > limit {
+            self.queued.fetch_sub(size, Ordering::Relaxed);
+            return false;
+        }
+        // 接收端已经关闭的连接稍后会取消登记
+        let _ = self.messages.send(frame);
+        true
+    }
+}
+
+impl Inbox {
+    /// 取出下一条消息，可以在 `select!` 中使用
+    pub(crate) async fn recv(&mut self) -> Frame {
+        // 连接自己持有投递的一端，通道不会关闭
+        let Some(frame) = self.messages.recv().await else {
+            return future::pending().await;
+        };
+        self.queued
+            .fetch_sub(payload_len(&frame), Ordering::Relaxed);
+        frame
+    }
+}
+
+/// 消息中各个参数的总长度，作为排队的字节数
+fn payload_len(frame: &Frame) -> usize {
+    match frame {
+        Frame::Array(parts) => parts.iter().map(payload_len).sum(),
+        Frame::Bulk(data) => data.len(),
+        _ => 0,
+    }
+}
+
+#[derive(Debug, Default)]
+struct Table {
+    /// 每个频道的订阅者
+    channels: HashMap<Bytes, HashSet<u64>>,
+    /// 每个模式的订阅者
+    patterns: HashMap<Bytes, HashSet<u64>>,
+    clients: HashMap<u64, Subscriber>,
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    messages: Mailbox,
+    channels: HashSet<Bytes>,
+    patterns: HashSet<Bytes>,
+}
+
+impl Subscriber {
+    fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    fn names(&mut self, kind: Kind) -> &mut HashSet<Bytes> {
+        match kind {
+            Kind::Channel => &mut self.channels,
+            Kind::Pattern => &mut self.patterns,
+        }
+    }
+}
+
+impl PubSub {
+    /// 每个连接排队的消息不超过 `limit` 字节
+    pub(crate) fn with_limit(limit: usize) -> PubSub {
+        PubSub {
+            table: Mutex::default(),
+            limit,
+        }
+    }
+
+    /// 订阅频道或者模式，返回每个名字的确认以及连接的订阅总数，确认中带有当时的订阅总数
+    ///
+    /// 消息发送到 `messages`，连接第一次订阅时登记。
+    pub(crate) fn subscribe(
+        &self,
+        id: u64,
+        messages: &Mailbox,
+        kind: Kind,
+        names: Vec<Bytes>,
+    ) -> (Vec<Frame>, usize) {
+        let mut table = self.lock();
+        let table = &mut *table;
+        let subscriber = table.clients.entry(id).or_insert_with(|| Subscriber {
+            messages: messages.clone(),
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+        });
+        let index = match kind {
+            Kind::Channel => &mut table.channels,
+            Kind::Pattern => &mut table.patterns,
+        };
+        let replies = names
+            .into_iter()
+            .map(|name| {
+                if subscriber.names(kind).insert(name.clone()) {
+                    index.entry(name.clone()).or_default().insert(id);
+                }
+                confirmation(kind.subscribe(), Frame::Bulk(name), subscriber.count())
+            })
+            .collect();
+        (replies, subscriber.count())
+    }
+
+    /// 取消订阅，`names` 为空时取消这一类的所有订阅，返回每个名字的确认以及连接的订阅总数
+    ///
+    /// 没有可以取消的订阅时也回复一条确认，名字为 Null。订阅数变为 0 时取消登记。
+    pub(crate) fn unsubscribe(
+        &self,
+        id: u64,
+        kind: Kind,
+        names: Vec<Bytes>,
+    ) -> (Vec<Frame>, usize) {
+        let mut table = self.lock();
+        let table = &mut *table;
+        let Some(subscriber) = table.clients.get_mut(&id) else {
+            return (vec![confirmation(kind.unsubscribe(), Frame::Null, 0)], 0);
+        };
+        let names = match names.is_empty() {
+            true => subscriber.names(kind).iter().cloned().collect(),
+            false => names,
+        };
+        let index = match kind {
+            Kind::Channel => &mut table.channels,
+            Kind::Pattern => &mut table.patterns,
+        };
+        let mut replies: Vec<Frame> = names
+            .into_iter()
+            .map(|name| {
+                if subscriber.names(kind).remove(&name) {
+                    if let Some(ids) = index.get_mut(&name) {
+                        ids.remove(&id);
+                        if ids.is_empty() {
+                            index.remove(&name);
+                        }
+                    }
+                }
+                confirmation(kind.unsubscribe(), Frame::Bulk(name), subscriber.count())
+            })
+            .collect();
+        if replies.is_empty() {
+            replies.push(confirmation(
+                kind.unsubscribe(),
+                Frame::Null,
+                subscriber.count(),
+            ));
+        }
+        let count = subscriber.count();
+        if count == 0 {
+            table.clients.remove(&id);
+        }
+        (replies, count)
+    }
+
+    /// 把消息发送给频道的订阅者和匹配的模式的订阅者，返回发送的消息数以及排队的消息超过上限的连接
+    ///
+    /// 超过上限的连接的订阅全部被取消，调用方应当关闭这些连接。
+    pub(crate) fn publish(&self, channel: &Bytes, message: &Bytes) -> (usize, Vec<u64>) {
+        let mut table = self.lock();
+        let mut sent = 0;
+        let mut overflowed = vec![];
+        let mut send = |id: &u64, frame: Frame| {
+            if let Some(subscriber) = table.clients.get(id) {
+                if subscriber.messages.send(frame, self.limit) {
+                    sent += 1;
+                } else if !overflowed.contains(id) {
+                    overflowed.push(*id);
+                }
+            }
+        };
+        for id in table.channels.get(channel).into_iter().flatten() {
+            let parts = vec![
+                Frame::Bulk(Bytes::from_static(b"message")),
+                Frame::Bulk(channel.clone()),
+                Frame::Bulk(message.clone()),
+            ];
+            send(id, Frame::Array(parts));
+        }
+        for (pattern, ids) in &table.patterns {
+            if !glob_match(pattern, channel) {
+                continue;
+            }
+            for id in ids {
+                let parts = vec![
+                    Frame::Bulk(Bytes::from_static(b"pmessage")),
+                    Frame::Bulk(pattern.clone()),
+                    Frame::Bulk(channel.clone()),
+                    Frame::Bulk(message.clone()),
+                ];
+                send(id, Frame::Array(parts));
+            }
+        }
+        for &id in &overflowed {
+            warn!(
+                client = id,
+                "Pub/Sub output buffer limit reached, closing the client"
+            );
+            table.remove(id);
+        }
+        (sent, overflowed)
+    }
+
+    /// 连接关闭时取消它的所有订阅
+    pub(crate) fn unregister(&self, id: u64) {
+        self.lock().remove(id);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Table> {
+        self.table.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Table {
+    /// 取消连接的所有订阅
+    fn remove(&mut self, id: u64) {
+        let Some(subscriber) = self.clients.remove(&id) else {
+            return;
+        };
+        for (kind, names) in [
+            (Kind::Channel, subscriber.channels),
+            (Kind::Pattern, subscriber.patterns),
+        ] {
+            let index = match kind {
+                Kind::Channel => &mut self.channels,
+                Kind::Pattern => &mut self.patterns,
+            };
+            for name in names {
+                if let Some(ids) = index.get_mut(&name) {
+                    ids.remove(&id);
+                    if ids.is_empty() {
+                        index.remove(&name);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn confirmation(kind: &'static [u8], name: Frame, count: usize) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from_static(kind)),
+        name,
+        Frame::Integer(count as i64),
+    ])
+}
+
+/// 按连接的协议版本发送消息和确认：RESP3 连接收到 Push
+pub(crate) fn for_protocol(frame: Frame, protocol: u8) -> Frame {
+    match frame {
+        Frame::Array(parts) if protocol >= 3 => Frame::Push(parts),
+        frame => frame,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(name: &'static str) -> Bytes {
+        Bytes::from_static(name.as_bytes())
+    }
+
+    fn count(frame: &Frame) -> i64 {
+        match frame {
+            Frame::Array(parts) => match parts[2] {
+                Frame::Integer(count) => count,
+                _ => panic!("expected a count"),
+            },
+            frame => panic!("expected a confirmation, got {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn messages_reach_channels_and_every_matching_pattern() {
+        let pubsub = PubSub::default();
+        let (tx, mut rx) = mailbox();
+        let (replies, _) = pubsub.subscribe(1, &tx, Kind::Channel, vec![bytes("news.a")]);
+        assert_eq!(count(&replies[0]), 1);
+        let patterns = vec![bytes("news.*"), bytes("*")];
+        let (replies, total) = pubsub.subscribe(1, &tx, Kind::Pattern, patterns);
+        assert_eq!(replies.iter().map(count).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(total, 3);
+
+        assert_eq!(pubsub.publish(&bytes("news.a"), &bytes("hi")).0, 3);
+        assert_eq!(pubsub.publish(&bytes("sport"), &bytes("hi")).0, 1);
+        let mut patterns = vec![];
+        while let Ok(Frame::Array(parts)) = rx.messages.try_recv() {
+            patterns.push(match parts.len() {
+                3 => None,
+                _ => Some(parts[1].to_string()),
+            });
+        }
+        patterns[1..3].sort();
+        assert_eq!(
+            patterns,
+            [
+                None,
+                Some("*".to_string()),
+                Some("news.*".to_string()),
+                Some("*".to_string())
+            ]
+        );
+
+        // 不带参数时取消所有模式，频道的订阅保留
+        let (replies, total) = pubsub.unsubscribe(1, Kind::Pattern, vec![]);
+        assert_eq!(replies.iter().map(count).collect::<Vec<_>>(), [2, 1]);
+        assert_eq!(total, 1);
+        assert_eq!(pubsub.publish(&bytes("sport"), &bytes("hi")).0, 0);
+        pubsub.unregister(1);
+        assert_eq!(pubsub.publish(&bytes("news.a"), &bytes("hi")).0, 0);
+        assert_eq!(
+            pubsub.unsubscribe(1, Kind::Channel, vec![]),
+            (vec![confirmation(b"unsubscribe", Frame::Null, 0)], 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_subscribers_are_dropped_past_the_limit() {
+        // 每条消息的参数共 16 字节："message"、"news"、"hello"
+        let pubsub = PubSub::with_limit(40);
+        let (slow, _slow_rx) = mailbox();
+        let (fast, mut fast_rx) = mailbox();
+        pubsub.subscribe(1, &slow, Kind::Channel, vec![bytes("news")]);
+        pubsub.subscribe(2, &fast, Kind::Channel, vec![bytes("news")]);
+        for _ in 0..2 {
+            let published = pubsub.publish(&bytes("news"), &bytes("hello"));
+            assert_eq!(published, (2, vec![]));
+            fast_rx.recv().await;
+        }
+
+        // 第三条消息会使 1 排队的字节数超过上限，1 的订阅被取消，取出消息的 2 不受影响
+        let published = pubsub.publish(&bytes("news"), &bytes("hello"));
+        assert_eq!(published, (1, vec![1]));
+        let published = pubsub.publish(&bytes("news"), &bytes("hello"));
+        assert_eq!(published, (1, vec![]));
+    }
+}
@@ -0,0 +1,120 @@
+//! 运行时修改配置
+//!
+//! 当前生效的配置保存在 [`State::config`] 中，有两个来源：CONFIG SET，以及服务端的二进制
+//! 收到 SIGHUP 时重新读取的配置文件，后者通过 watch 通道发送给服务端，覆盖之前 CONFIG SET 的修改。
+//! 只有不需要重新创建监听端点和数据库的配置项可以在运行时修改，见 [`RELOADABLE`]，
+//! 重新加载的配置文件中其他配置项的修改在重启后生效。
+
+use std::{future, sync::atomic::Ordering};
+
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use super::{configure_db, State};
+use crate::config::{Config, ConfigError, NAMES};
+
+/// 可以通过 CONFIG SET 修改的配置项
+pub(crate) const RELOADABLE: &[&str] = &[
+    "maxmemory",
+    "timeout",
+    "expire-mode",
+    "max-key-len",
+    "max-value-size",
+    "defrag-ratio",
+    "requirepass",
+    "slowlog-log-slower-than",
+    "slowlog-max-len",
+    "command-timeout",
+    "max-connections-per-sec",
+    "max-commands-per-sec",
+    "rate-limit-burst",
+    "shutdown-timeout",
+    "cluster-slots",
+    "cluster-migrating",
+    "cluster-importing",
+];
+
+impl State {
+    /// 应用 `new` 中可以在运行时修改的配置项，`old` 为之前的配置
+    pub(crate) fn reload(&self, old: &Config, new: &Config) {
+        for db in &self.dbs {
+            configure_db(db, new);
+        }
+        // 只有配置的密码变化时才修改，避免覆盖 ACL SETUSER 对 default 用户的修改
+        if old.requirepass != new.requirepass {
+            self.users.set_requirepass(new.requirepass.as_deref());
+        }
+        self.timeout.store(new.timeout, Ordering::Relaxed);
+        self.command_timeout
+            .store(new.command_timeout, Ordering::Relaxed);
+        self.slowlog
+            .configure(new.slowlog_log_slower_than, new.slowlog_max_len);
+        self.limiter.configure(new);
+        if let Some(cluster) = &self.cluster {
+            cluster.configure(new);
+        }
+    }
+
+    /// 用重新读取的配置文件更新配置，只应用 [`RELOADABLE`] 中的配置项
+    ///
+    /// 其他配置项保持启动时的值，CONFIG GET 返回的仍然是实际生效的配置，修改了它们时记录警告。
+    pub(crate) fn replace_config(&self, file: Config) {
+        self.config.send_modify(|config| {
+            let mut new = config.clone();
+            for &name in NAMES {
+                let value = file.get(name).unwrap_or_default();
+                if config.get(name).as_ref() == Some(&value) {
+                    continue;
+                }
+                if !RELOADABLE.contains(&name) {
+                    warn!(
+                        option = name,
+                        "Config option can't be reloaded, restart to apply it"
+                    );
+                    continue;
+                }
+                if let Err(e) = new.set(name, &value) {
+                    warn!(error = %e, "Invalid reloaded config option");
+                }
+            }
+            self.reload(config, &new);
+            *config = new;
+        });
+    }
+
+    /// CONFIG SET：同时修改多个配置项，任何一个无效时都不修改
+    pub(crate) fn set_config(&self, pairs: &[(String, String)]) -> Result<(), ConfigError> {
+        let mut result = Ok(());
+        self.config.send_if_modified(|config| {
+            let mut new = config.clone();
+            for (name, value) in pairs {
+                let key = name.to_lowercase().replace('_', "-");
+                if !RELOADABLE.contains(&&key[..]) {
+                    result = Err(match config.get(&key) {
+                        Some(_) => ConfigError::Immutable(key),
+                        None => ConfigError::Unknown(key),
+                    });
+                    return false;
+                }
+                if let Err(e) = new.set(&key, value) {
+                    result = Err(e);
+                    return false;
+                }
+            }
+            self.reload(config, &new);
+            *config = new;
+            true
+        });
+        result
+    }
+}
+
+/// 每次收到新的配置时重新加载，发送端被 drop 后不再返回
+pub(super) async fn watch(state: &State, mut config: watch::Receiver<Config>) {
+    while config.changed().await.is_ok() {
+        let new = config.borrow_and_update().clone();
+        state.replace_config(new);
+        info!("Configuration reloaded");
+    }
+    future::pending().await
+}
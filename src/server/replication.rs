@@ -0,0 +1,332 @@
+//! 主从复制
+//!
+//! 副本执行 REPLICAOF 后连接主节点，发送 `REPLCONF listening-port` 和 SYNC。主节点同时为每个数据库
+//! 创建快照并记下操作日志的进度，回复 `+FULLRESYNC <seq>`，然后把快照以 RDB 格式
+//! （见 [`Snapshot::encode`]）在一个数组中发送，之后把日志中序号在 `seq` 之后的写命令依次发送给副本，
+//! 切换数据库时先发送 SELECT。
+//! 副本清空数据后加载快照，然后执行收到的命令，每秒用 `REPLCONF ACK <offset>` 报告进度，
+//! offset 为已经执行的最后一条日志记录的序号。
+//!
+//! 快照和进度在持有所有分片的锁时取得（见 [`Db::snapshots`]），每条写命令要么在快照中，
+//! 要么在之后发送的日志中，不会在副本上执行两次。副本落后太多、要读取的日志记录已被丢弃时，主节点断开连接；连接断开后副本每秒重新连接一次，
+//! 每次都重新全量同步。副本只接受读命令，写命令回复 `-READONLY`。
+//!
+//! [`Snapshot::encode`]: crate::db::Snapshot::encode
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use tokio::{
+    net::TcpStream,
+    task::{self, AbortHandle},
+    time,
+};
+use tracing::{error, info, warn};
+
+use super::{execute, PeerAddr, Result};
+use crate::{connection::Connection, db::Db, frame::Frame};
+
+/// 与主节点的连接断开后重新连接的间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 副本报告进度的间隔
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 副本与主节点之间连接的状态，ROLE 中显示
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkState {
+    Connect,
+    Sync,
+    Connected,
+}
+
+impl LinkState {
+    fn name(self) -> &'static str {
+        match self {
+            LinkState::Connect => "connect",
+            LinkState::Sync => "sync",
+            LinkState::Connected => "connected",
+        }
+    }
+}
+
+/// 同步任务和 ROLE 共享的进度
+#[derive(Debug)]
+struct LinkStatus {
+    state: Mutex<LinkState>,
+    /// 已经执行的最后一条日志记录的序号
+    offset: AtomicU64,
+}
+
+impl LinkStatus {
+    fn set_state(&self, state: LinkState) {
+        *self.state.lock().unwrap() = state;
+    }
+}
+
+/// 作为副本时与主节点的连接，drop 时取消同步任务
+#[derive(Debug)]
+struct Link {
+    host: String,
+    port: u16,
+    status: Arc<LinkStatus>,
+    task: AbortHandle,
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// 连接到这里的一个副本
+#[derive(Debug, Clone)]
+struct ReplicaInfo {
+    ip: String,
+    /// 副本通过 `REPLCONF listening-port` 报告的端口
+    port: u16,
+    /// 副本最近一次报告的进度
+    offset: u64,
+}
+
+/// 主从复制的状态，作为主节点时记录连接到这里的副本，作为副本时持有与主节点的连接
+#[derive(Debug, Default)]
+pub(crate) struct Replication {
+    /// 与 `primary` 是否为 Some 相同，每条命令都要判断，不需要加锁
+    replica: AtomicBool,
+    primary: Mutex<Option<Link>>,
+    /// 连接到这里的副本，键为连接的 id
+    replicas: Mutex<HashMap<u64, ReplicaInfo>>,
+}
+
+impl Replication {
+    pub(crate) fn is_replica(&self) -> bool {
+        self.replica.load(Ordering::Relaxed)
+    }
+
+    /// 成为 `host:port` 的副本，之前的同步任务被取消
+    ///
+    /// `listening_port` 为这个节点的端口，`auth` 为主节点的密码。必须在 tokio 运行时中调用。
+    pub(crate) fn replicate(
+        &self,
+        host: String,
+        port: u16,
+        dbs: &[Db],
+        listening_port: u16,
+        auth: Option<String>,
+    ) {
+        let status = Arc::new(LinkStatus {
+            state: Mutex::new(LinkState::Connect),
+            offset: AtomicU64::new(0),
+        });
+        let follower = Follower {
+            addr: format!("{}:{}", host, port),
+            dbs: dbs.to_vec(),
+            listening_port,
+            auth,
+            status: Arc::clone(&status),
+        };
+        let task = tokio::spawn(follower.run()).abort_handle();
+        info!(%host, port, "Replicating from primary");
+        let link = Link {
+            host,
+            port,
+            status,
+            task,
+        };
+        *self.primary.lock().unwrap() = Some(link);
+        self.replica.store(true, Ordering::Relaxed);
+    }
+
+    /// 停止复制，成为主节点，已经同步的数据保留
+    pub(crate) fn stop(&self) {
+        if self.primary.lock().unwrap().take().is_some() {
+            info!("Replication stopped");
+        }
+        self.replica.store(false, Ordering::Relaxed);
+    }
+
+    /// 登记一个完成 SYNC 的副本
+    pub(crate) fn register(&self, id: u64, peer: &PeerAddr, port: u16, offset: u64) {
+        let ip = peer.ip().map(|ip| ip.to_string()).unwrap_or_default();
+        let replica = ReplicaInfo { ip, port, offset };
+        self.replicas.lock().unwrap().insert(id, replica);
+    }
+
+    pub(crate) fn unregister(&self, id: u64) {
+        self.replicas.lock().unwrap().remove(&id);
+    }
+
+    /// 记录副本报告的进度，不是副本的连接被忽略
+    pub(crate) fn ack(&self, id: u64, offset: u64) {
+        if let Some(replica) = self.replicas.lock().unwrap().get_mut(&id) {
+            replica.offset = offset;
+        }
+    }
+
+    /// ROLE 的回复，`offset` 为主节点操作日志的进度
+    pub(crate) fn role(&self, offset: u64) -> Frame {
+        if let Some(link) = &*self.primary.lock().unwrap() {
+            let state = *link.status.state.lock().unwrap();
+            return Frame::Array(vec![
+                Frame::Bulk("slave".into()),
+                Frame::Bulk(Bytes::copy_from_slice(link.host.as_bytes())),
+                Frame::Integer(link.port.into()),
+                Frame::Bulk(state.name().into()),
+                Frame::Integer(link.status.offset.load(Ordering::Relaxed) as i64),
+            ]);
+        }
+        let replicas = self.replicas.lock().unwrap();
+        let replicas = replicas.values().map(|replica| {
+            Frame::Array(vec![
+                Frame::Bulk(Bytes::copy_from_slice(replica.ip.as_bytes())),
+                Frame::Bulk(replica.port.to_string().into()),
+                Frame::Bulk(replica.offset.to_string().into()),
+            ])
+        });
+        Frame::Array(vec![
+            Frame::Bulk("master".into()),
+            Frame::Integer(offset as i64),
+            Frame::Array(replicas.collect()),
+        ])
+    }
+}
+
+/// 副本上的同步任务
+struct Follower {
+    /// 主节点的地址
+    addr: String,
+    dbs: Vec<Db>,
+    listening_port: u16,
+    auth: Option<String>,
+    status: Arc<LinkStatus>,
+}
+
+impl Follower {
+    /// 连接主节点并同步，连接断开后等待一段时间重新连接，直到任务被取消
+    async fn run(self) {
+        loop {
+            self.status.set_state(LinkState::Connect);
+            match self.sync().await {
+                Ok(()) => warn!(primary = %self.addr, "Connection with primary lost"),
+                Err(e) => warn!(primary = %self.addr, error = %e, "Replication error"),
+            }
+            time::sleep(RETRY_INTERVAL).await;
+        }
+    }
+
+    /// 全量同步，然后执行主节点发送的写命令，主节点关闭连接时返回 Ok
+    async fn sync(&self) -> Result<()> {
+        let mut connection = Connection::new(TcpStream::connect(&self.addr).await?);
+        if let Some(password) = &self.auth {
+            request(&mut connection, &["AUTH", password]).await?;
+        }
+        let port = self.listening_port.to_string();
+        request(&mut connection, &["REPLCONF", "listening-port", &port]).await?;
+        let reply = request(&mut connection, &["SYNC"]).await?;
+        let seq = match &reply {
+            Frame::Simple(reply) => reply
+                .strip_prefix("FULLRESYNC ")
+                .and_then(|seq| seq.parse::<u64>().ok()),
+            _ => None,
+        };
+        let seq = seq.ok_or_else(|| format!("unexpected reply to SYNC: {}", reply))?;
+
+        self.status.set_state(LinkState::Sync);
+        let payloads = match connection.read_frame().await? {
+            Some(Frame::Array(payloads)) if payloads.len() == self.dbs.len() => payloads,
+            _ => return Err("primary sent an invalid snapshot".into()),
+        };
+        for (db, payload) in self.dbs.iter().zip(payloads) {
+            let Frame::Bulk(payload) = payload else {
+                return Err("primary sent an invalid snapshot".into());
+            };
+            let db = db.clone();
+            task::spawn_blocking(move || db.replace_with(payload)).await??;
+        }
+        self.status.offset.store(seq, Ordering::Relaxed);
+        self.status.set_state(LinkState::Connected);
+        info!(primary = %self.addr, offset = seq, "Full resync with primary finished");
+
+        self.apply_stream(connection).await
+    }
+
+    /// 执行主节点发送的写命令，定时报告进度
+    async fn apply_stream(&self, mut connection: Connection<TcpStream>) -> Result<()> {
+        let mut selected = 0;
+        let mut ack = time::interval(ACK_INTERVAL);
+        loop {
+            tokio::select! {
+                frame = connection.read_frame() => {
+                    let Some(frame) = frame? else {
+                        return Ok(());
+                    };
+                    if let Some(index) = select_index(&frame) {
+                        if index >= self.dbs.len() {
+                            return Err(format!("primary selected unknown db {}", index).into());
+                        }
+                        selected = index;
+                        continue;
+                    }
+                    let db = &self.dbs[selected];
+                    // 命令同样记录到副本自己的操作日志，供副本的 AOF 和副本的副本读取
+                    let (response, logged) = execute(db, frame).await;
+                    if let Frame::Error(e) = response {
+                        error!(error = %e, "Error applying replicated command");
+                    }
+                    if let Some(seq) = logged {
+                        db.wait_synced(seq).await;
+                    }
+                    self.status.offset.fetch_add(1, Ordering::Relaxed);
+                }
+                _ = ack.tick() => {
+                    let offset = self.status.offset.load(Ordering::Relaxed).to_string();
+                    connection.write_frame(&command(&["REPLCONF", "ACK", &offset])).await?;
+                }
+            }
+        }
+    }
+}
+
+/// 发送一条命令并读取回复，错误回复转换为错误
+async fn request(connection: &mut Connection<TcpStream>, args: &[&str]) -> Result<Frame> {
+    connection.write_frame(&command(args)).await?;
+    match connection.read_frame().await? {
+        Some(Frame::Error(e)) => Err(format!("primary replied to {}: {}", args[0], e).into()),
+        Some(frame) => Ok(frame),
+        None => Err("connection closed by primary".into()),
+    }
+}
+
+fn command(args: &[&str]) -> Frame {
+    Frame::Array(
+        args.iter()
+            .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+            .collect(),
+    )
+}
+
+/// 主节点切换数据库时发送的 SELECT
+fn select_index(frame: &Frame) -> Option<usize> {
+    let Frame::Array(parts) = frame else {
+        return None;
+    };
+    match &parts[..] {
+        [name, index] if name.to_string().eq_ignore_ascii_case("select") => {
+            index.to_string().parse().ok()
+        }
+        _ => None,
+    }
+}
+
+/// 主节点发送给副本的切换数据库的命令
+pub(super) fn select_command(index: usize) -> Frame {
+    command(&["SELECT", &index.to_string()])
+}
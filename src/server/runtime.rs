@@ -0,0 +1,118 @@
+//! 运行时的拓扑
+//!
+//! 服务端的二进制按配置创建 tokio 运行时：
+//!
+//! - `runtime`：`multi-thread` 为默认的多线程运行时，`current-thread` 在一个线程中运行所有连接，
+//!   没有跨线程的同步和任务迁移，一些负载下更快，也更容易调试
+//! - `worker-threads`：多线程运行时的工作线程数，0 表示与 CPU 核数相同
+//! - `accept-thread`：在专门的线程中运行接受循环，连接仍然在主运行时中处理，
+//!   连接的处理繁忙时不会推迟接受新的连接
+
+use std::{future::Future, io, thread};
+
+use tokio::{
+    runtime::{self, Runtime},
+    sync::oneshot,
+};
+
+use super::Result;
+use crate::config::Config;
+
+/// 运行时的类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Flavor {
+    #[default]
+    MultiThread,
+    CurrentThread,
+}
+
+impl Flavor {
+    pub fn from_name(name: &str) -> Option<Flavor> {
+        match &name.to_lowercase()[..] {
+            "multi-thread" => Some(Flavor::MultiThread),
+            "current-thread" => Some(Flavor::CurrentThread),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Flavor::MultiThread => "multi-thread",
+            Flavor::CurrentThread => "current-thread",
+        }
+    }
+}
+
+/// 按配置创建运行服务端的运行时
+pub fn build(config: &Config) -> io::Result<Runtime> {
+    let mut builder = match config.runtime {
+        Flavor::MultiThread => runtime::Builder::new_multi_thread(),
+        Flavor::CurrentThread => runtime::Builder::new_current_thread(),
+    };
+    if config.runtime == Flavor::MultiThread && config.worker_threads > 0 {
+        builder.worker_threads(config.worker_threads);
+    }
+    builder.enable_all().build()
+}
+
+/// 在一个新线程的单线程运行时中运行 `make` 创建的 future
+///
+/// 返回的 future 在线程中的 future 完成时完成；被 drop 时通知线程退出，
+/// 线程中的 future 和运行时随之被 drop。
+pub(super) fn spawn_thread<F, Fut>(
+    name: &str,
+    make: F,
+) -> io::Result<impl Future<Output = Result<()>>>
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<()>>,
+{
+    let (cancel, cancelled) = oneshot::channel::<()>();
+    let (done, finished) = oneshot::channel();
+    thread::Builder::new().name(name.into()).spawn(move || {
+        let result = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(Into::into)
+            .and_then(|runtime| {
+                runtime.block_on(async move {
+                    tokio::select! {
+                        res = make() => res,
+                        _ = cancelled => Ok(()),
+                    }
+                })
+            });
+        let _ = done.send(result);
+    })?;
+    Ok(async move {
+        let _cancel = cancel;
+        finished.await.unwrap_or(Ok(()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_thread_runtime_runs_on_the_calling_thread() {
+        let config = Config {
+            runtime: Flavor::CurrentThread,
+            ..Config::default()
+        };
+        let runtime = build(&config).unwrap();
+        let caller = thread::current().id();
+        let id = runtime.block_on(async { tokio::spawn(async { thread::current().id() }).await });
+        assert_eq!(id.unwrap(), caller);
+
+        let runtime = build(&Config::default()).unwrap();
+        let id = runtime.block_on(async {
+            spawn_thread("test", || async { Ok(()) })
+                .unwrap()
+                .await
+                .unwrap();
+            tokio::spawn(async { thread::current().id() }).await
+        });
+        assert_ne!(id.unwrap(), caller);
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use tokio::sync::{broadcast, Notify};
+
+/// 监听服务端的关闭信号以及 CLIENT KILL
+///
+/// 服务端关闭时 drop 广播的发送端，所有连接各自持有的接收端都会收到通知；
+/// CLIENT KILL 只通知一个连接。只需要收到一次，之后 [`Shutdown::recv`] 立即返回。
+#[derive(Debug)]
+pub(crate) struct Shutdown {
+    is_shutdown: bool,
+    /// 通知来自 CLIENT KILL 而不是服务端关闭
+    is_killed: bool,
+    notify: broadcast::Receiver<()>,
+    kill: Arc<Notify>,
+}
+
+impl Shutdown {
+    pub(crate) fn new(notify: broadcast::Receiver<()>, kill: Arc<Notify>) -> Shutdown {
+        Shutdown {
+            is_shutdown: false,
+            is_killed: false,
+            notify,
+            kill,
+        }
+    }
+
+    pub(crate) fn is_shutdown(&self) -> bool {
+        self.is_shutdown
+    }
+
+    pub(crate) fn is_killed(&self) -> bool {
+        self.is_killed
+    }
+
+    /// 等待关闭信号
+    pub(crate) async fn recv(&mut self) {
+        if self.is_shutdown {
+            return;
+        }
+        tokio::select! {
+            // 发送端被 drop 时返回错误，这正是关闭信号，不需要区分
+            _ = self.notify.recv() => {}
+            // `notify_one` 在没有等待者时保留通知，不会丢失
+            _ = self.kill.notified() => self.is_killed = true,
+        }
+        self.is_shutdown = true;
+    }
+}
+
+/// SHUTDOWN 命令发出的关闭请求，服务端收到后走与 ctrl_c 相同的关闭流程
+#[derive(Debug, Default)]
+pub(crate) struct ShutdownRequest {
+    notify: Notify,
+    /// 所有连接退出后是否保存快照
+    save: AtomicBool,
+}
+
+impl ShutdownRequest {
+    pub(crate) fn request(&self, save: bool) {
+        self.save.fetch_or(save, Ordering::Relaxed);
+        self.notify.notify_one();
+    }
+
+    /// 等待关闭请求，返回是否需要保存快照
+    pub(crate) async fn requested(&self) -> bool {
+        self.notify.notified().await;
+        self.save.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,172 @@
+//! 慢查询日志
+//!
+//! 执行时间超过 `slowlog-log-slower-than` 微秒的命令记录在内存中，最多保留
+//! `slowlog-max-len` 条，新的记录挤掉最旧的。阈值为负数时不记录，为 0 时记录所有命令。
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+
+use super::PeerAddr;
+use crate::frame::Frame;
+
+/// 每条记录最多保存的参数个数，与 Redis 相同
+const MAX_ARGC: usize = 32;
+/// 每个参数最多保存的字节数
+const MAX_STRING: usize = 128;
+
+#[derive(Debug, Clone)]
+pub(crate) struct SlowLogEntry {
+    pub(crate) id: u64,
+    /// 记录时的 Unix 时间戳，单位为秒
+    pub(crate) timestamp: u64,
+    pub(crate) duration: Duration,
+    pub(crate) args: Vec<Bytes>,
+    pub(crate) addr: PeerAddr,
+    pub(crate) name: Option<String>,
+}
+
+#[derive(Debug)]
+pub(crate) struct SlowLog {
+    /// 单位为微秒
+    slower_than: AtomicI64,
+    max_len: AtomicUsize,
+    next_id: AtomicU64,
+    entries: Mutex<VecDeque<SlowLogEntry>>,
+}
+
+impl SlowLog {
+    pub(crate) fn new(slower_than: i64, max_len: usize) -> SlowLog {
+        SlowLog {
+            slower_than: AtomicI64::new(slower_than),
+            max_len: AtomicUsize::new(max_len),
+            next_id: AtomicU64::new(0),
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// 修改阈值和保留的条数，多出的旧记录立即删除
+    pub(crate) fn configure(&self, slower_than: i64, max_len: usize) {
+        self.slower_than.store(slower_than, Ordering::Relaxed);
+        self.max_len.store(max_len, Ordering::Relaxed);
+        self.entries.lock().unwrap().truncate(max_len);
+    }
+
+    /// 是否记录，不记录时调用方不需要保留命令的参数
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.slower_than.load(Ordering::Relaxed) >= 0
+    }
+
+    /// 执行时间超过阈值时记录命令
+    pub(crate) fn record(
+        &self,
+        frame: &Frame,
+        duration: Duration,
+        addr: &PeerAddr,
+        name: Option<&str>,
+    ) {
+        let slower_than = self.slower_than.load(Ordering::Relaxed);
+        if slower_than < 0 || duration.as_micros() < slower_than as u128 {
+            return;
+        }
+        let entry = SlowLogEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            duration,
+            args: truncated_args(frame),
+            addr: addr.clone(),
+            name: name.map(String::from),
+        };
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_front(entry);
+        entries.truncate(self.max_len.load(Ordering::Relaxed));
+    }
+
+    /// 最新的 `count` 条记录，新的在前
+    pub(crate) fn get(&self, count: usize) -> Vec<SlowLogEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().take(count).cloned().collect()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub(crate) fn reset(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// 过长的参数和过多的参数被截断，只保留说明
+fn truncated_args(frame: &Frame) -> Vec<Bytes> {
+    let Frame::Array(parts) = frame else {
+        return vec![];
+    };
+    let mut args = Vec::with_capacity(parts.len().min(MAX_ARGC));
+    for (i, part) in parts.iter().enumerate() {
+        if i == MAX_ARGC - 1 && parts.len() > MAX_ARGC {
+            let more = parts.len() - i;
+            args.push(Bytes::from(format!("... ({} more arguments)", more)));
+            break;
+        }
+        let bytes = match part {
+            Frame::Bulk(bytes) => bytes.clone(),
+            other => Bytes::from(other.to_string()),
+        };
+        if bytes.len() > MAX_STRING {
+            let mut truncated = bytes[..MAX_STRING].to_vec();
+            let more = format!("... ({} more bytes)", bytes.len() - MAX_STRING);
+            truncated.extend_from_slice(more.as_bytes());
+            args.push(Bytes::from(truncated));
+        } else {
+            args.push(bytes);
+        }
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(args: Vec<Bytes>) -> Frame {
+        Frame::Array(args.into_iter().map(Frame::Bulk).collect())
+    }
+
+    #[test]
+    fn slow_commands_are_kept_newest_first() {
+        let slowlog = SlowLog::new(1000, 2);
+        let addr = "127.0.0.1:6000".parse().unwrap();
+        let get = command(vec![Bytes::from("GET"), Bytes::from("a")]);
+
+        slowlog.record(&get, Duration::from_micros(999), &addr, None);
+        assert_eq!(slowlog.len(), 0);
+        for _ in 0..3 {
+            slowlog.record(&get, Duration::from_millis(1), &addr, Some("worker"));
+        }
+        let ids: Vec<u64> = slowlog.get(10).iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, [2, 1]);
+
+        let mut args = vec![Bytes::from("SET"), Bytes::from(vec![b'x'; 200])];
+        args.extend((0..40).map(|i| Bytes::from(i.to_string())));
+        slowlog.record(&command(args), Duration::from_secs(1), &addr, None);
+        let args = &slowlog.get(1)[0].args;
+        assert_eq!(args.len(), MAX_ARGC);
+        assert!(args[1].ends_with(b"... (72 more bytes)"));
+        assert_eq!(args[MAX_ARGC - 1], "... (11 more arguments)");
+
+        slowlog.reset();
+        assert_eq!(slowlog.len(), 0);
+        assert!(!SlowLog::new(-1, 128).is_enabled());
+    }
+}
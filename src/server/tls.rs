@@ -0,0 +1,168 @@
+//! TLS 监听
+//!
+//! 配置了证书和私钥时，每个连接先完成 TLS 握手再交给 [`Handler`](super::Handler)，
+//! 握手在连接自己的任务中进行，失败或超时只关闭这个连接，不会阻塞接受新的连接。
+//! 需要开启 `tls` 特性，证书和私钥为 PEM 格式。
+
+use std::{io, path::Path, time::Duration};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::Result;
+use crate::config::Config;
+
+/// 握手的超时时间，避免不发送数据的连接一直占用连接数
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 通过 ALPN 协商的协议名
+#[cfg(feature = "tls")]
+const ALPN_PROTOCOLS: &[&[u8]] = &[b"redis"];
+
+#[cfg(feature = "tls")]
+pub(crate) use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+/// 没有开启 `tls` 特性时无法构造
+#[cfg(not(feature = "tls"))]
+#[derive(Debug, Clone)]
+pub(crate) enum TlsAcceptor {}
+
+#[cfg(not(feature = "tls"))]
+pub(crate) type TlsStream<S> = S;
+
+/// 按配置创建 TLS acceptor，没有配置证书时返回 None
+pub(crate) fn acceptor(config: &Config) -> Result<Option<TlsAcceptor>> {
+    match (&config.tls_cert_file, &config.tls_key_file) {
+        (None, None) => Ok(None),
+        (Some(cert), Some(key)) => load(cert, key).map(Some),
+        _ => Err("tls-cert-file and tls-key-file must be set together".into()),
+    }
+}
+
+#[cfg(feature = "tls")]
+fn load(cert: &Path, key: &Path) -> Result<TlsAcceptor> {
+    use std::{fs::File, io::BufReader, sync::Arc};
+
+    use tokio_rustls::rustls::{crypto::ring, ServerConfig};
+
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(cert)?))
+        .collect::<io::Result<Vec<_>>>()?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(key)?))?
+        .ok_or_else(|| format!("no private key found in {}", key.display()))?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    config.alpn_protocols = ALPN_PROTOCOLS.iter().map(|p| p.to_vec()).collect();
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+#[cfg(not(feature = "tls"))]
+fn load(_cert: &Path, _key: &Path) -> Result<TlsAcceptor> {
+    Err("TLS requires the `tls` feature".into())
+}
+
+/// 完成 TLS 握手
+pub(crate) async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    acceptor: &TlsAcceptor,
+    stream: S,
+) -> io::Result<TlsStream<S>> {
+    #[cfg(feature = "tls")]
+    {
+        tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out"))?
+    }
+    #[cfg(not(feature = "tls"))]
+    {
+        let _ = (stream, HANDSHAKE_TIMEOUT);
+        match *acceptor {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cert_and_key_must_be_set_together() {
+        let mut config = Config::default();
+        assert!(acceptor(&config).unwrap().is_none());
+        config.set("tls-cert-file", "server.pem").unwrap();
+        assert!(acceptor(&config).is_err());
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn failed_handshakes_close_only_their_connection() {
+        use std::sync::Arc;
+
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::{TcpListener, TcpStream},
+        };
+        use tokio_rustls::{
+            rustls::{crypto::ring, pki_types::ServerName, ClientConfig, RootCertStore},
+            TlsConnector,
+        };
+
+        use crate::{connection::Connection, frame::Frame, server};
+
+        let testdata = |name: &str| {
+            format!(
+                "{}/src/client/testdata/{}",
+                env!("CARGO_MANIFEST_DIR"),
+                name
+            )
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut config = Config::default();
+        config
+            .set("tls-cert-file", &testdata("server.pem"))
+            .unwrap();
+        config.set("tls-key-file", &testdata("server.key")).unwrap();
+        tokio::spawn(async move {
+            server::run_with(listener, &config, std::future::pending::<()>()).await
+        });
+
+        // 不做握手直接发送命令，服务端关闭这个连接
+        let mut plain = TcpStream::connect(addr).await.unwrap();
+        plain.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+        let mut reply = vec![];
+        let closed = tokio::time::timeout(Duration::from_secs(5), plain.read_to_end(&mut reply));
+        // 服务端可能先发送 TLS 告警，连接被重置时读取返回错误
+        let _ = closed.await.expect("the connection is closed");
+        assert!(!reply.starts_with(b"+PONG"));
+
+        let mut roots = RootCertStore::empty();
+        let ca = std::fs::read(testdata("ca.pem")).unwrap();
+        for cert in rustls_pemfile::certs(&mut &ca[..]) {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let mut client = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        client.alpn_protocols = vec![b"redis".to_vec()];
+        let stream = TlsConnector::from(Arc::new(client))
+            .connect(
+                ServerName::try_from("localhost").unwrap(),
+                TcpStream::connect(addr).await.unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"redis"[..]));
+
+        let mut connection = Connection::new(stream);
+        connection
+            .write_frame(&Frame::Array(vec![Frame::Bulk("PING".into())]))
+            .await
+            .unwrap();
+        assert_eq!(
+            connection.read_frame().await.unwrap(),
+            Some(Frame::Simple("PONG".into()))
+        );
+    }
+}
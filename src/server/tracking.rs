@@ -0,0 +1,203 @@
+//! CLIENT TRACKING 的失效通知
+//!
+//! 开启了 tracking 的连接在这里登记，得到一个接收失效消息的通道，由连接的处理器以 RESP3 Push
+//! 发送给客户端：
+//!
+//! ```text
+//! >2
+//! $10
+//! invalidate
+//! *1
+//! $3
+//! foo
+//! ```
+//!
+//! 默认模式下处理器在执行读命令之前记下命令的 key，key 被修改时通知读过它的连接并移除记录，
+//! 之后再次读取才会重新记录；BCAST 模式下按前缀通知。与 Redis 相同，记录不区分数据库。
+//!
+//! key 的修改来自 Db 的变更通知（见 `db::notify`）。只有存在登记的连接时才订阅变更通知，
+//! 没有连接使用 tracking 时写命令不需要构造通知。通知积压丢失时无法知道哪些 key 被修改，
+//! 向所有连接发送 key 为 Null 的失效消息，客户端应当清空缓存。
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc,
+};
+
+use crate::{
+    cmd::TrackingMode,
+    db::{Db, Notification},
+    frame::Frame,
+};
+
+#[derive(Debug, Default)]
+pub(crate) struct Tracking {
+    table: Arc<Mutex<Table>>,
+}
+
+#[derive(Debug, Default)]
+struct Table {
+    /// 默认模式下每个 key 被哪些连接读取过，连接关闭后的 id 在 key 被修改时清理
+    keys: HashMap<String, HashSet<u64>>,
+    clients: HashMap<u64, Tracked>,
+    /// 订阅变更通知的任务是否在运行
+    listening: bool,
+    /// 每次开始订阅时递增，上一轮还没有退出的任务据此退出
+    generation: u64,
+}
+
+#[derive(Debug)]
+struct Tracked {
+    mode: TrackingMode,
+    invalidations: mpsc::UnboundedSender<Frame>,
+}
+
+impl Tracking {
+    /// 按 `mode` 登记连接，已经登记时替换之前的模式
+    pub(crate) fn register(
+        &self,
+        id: u64,
+        mode: TrackingMode,
+        dbs: &[Db],
+    ) -> mpsc::UnboundedReceiver<Frame> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut table = self.table.lock().unwrap();
+        table.clients.insert(
+            id,
+            Tracked {
+                mode,
+                invalidations: tx,
+            },
+        );
+        if !table.listening {
+            table.listening = true;
+            table.generation += 1;
+            // 在持有锁时订阅，登记之后的修改都不会错过
+            for db in dbs {
+                let events = db.subscribe();
+                tokio::spawn(listen(Arc::clone(&self.table), table.generation, events));
+            }
+        }
+        rx
+    }
+
+    pub(crate) fn unregister(&self, id: u64) {
+        let mut table = self.table.lock().unwrap();
+        if table.clients.remove(&id).is_some() && table.clients.is_empty() {
+            table.keys.clear();
+            table.listening = false;
+        }
+    }
+
+    /// 默认模式的连接即将读取 `keys`
+    pub(crate) fn remember(&self, id: u64, keys: &[&str]) {
+        let mut table = self.table.lock().unwrap();
+        for key in keys {
+            table.keys.entry(key.to_string()).or_default().insert(id);
+        }
+    }
+}
+
+impl Table {
+    fn invalidate(&mut self, key: &str) {
+        let readers = self.keys.remove(key).unwrap_or_default();
+        let message = invalidate(Frame::Array(vec![Frame::Bulk(Bytes::copy_from_slice(
+            key.as_bytes(),
+        ))]));
+        for (id, tracked) in &self.clients {
+            let notify = if tracked.mode.bcast {
+                tracked.mode.prefixes.is_empty()
+                    || tracked.mode.prefixes.iter().any(|p| key.starts_with(p))
+            } else {
+                readers.contains(id)
+            };
+            if notify {
+                let _ = tracked.invalidations.send(message.clone());
+            }
+        }
+    }
+
+    /// 通知所有连接清空缓存
+    fn invalidate_all(&mut self) {
+        self.keys.clear();
+        for tracked in self.clients.values() {
+            let _ = tracked.invalidations.send(invalidate(Frame::Null));
+        }
+    }
+}
+
+fn invalidate(keys: Frame) -> Frame {
+    Frame::Push(vec![Frame::Bulk(Bytes::from_static(b"invalidate")), keys])
+}
+
+/// 把一个 Db 的变更通知转换为失效消息，所有连接都取消登记后退出
+async fn listen(
+    table: Arc<Mutex<Table>>,
+    generation: u64,
+    mut events: broadcast::Receiver<Notification>,
+) {
+    loop {
+        let event = events.recv().await;
+        let mut table = table.lock().unwrap();
+        if !table.listening || table.generation != generation {
+            return;
+        }
+        match event {
+            Ok(notification) => table.invalidate(&notification.key),
+            Err(RecvError::Lagged(_)) => table.invalidate_all(),
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(frame: Frame) -> Frame {
+        match frame {
+            Frame::Push(mut parts) if parts.len() == 2 => parts.pop().unwrap(),
+            frame => panic!("expected an invalidation, got {:?}", frame),
+        }
+    }
+
+    fn key(key: &'static str) -> Frame {
+        Frame::Array(vec![Frame::Bulk(Bytes::from_static(key.as_bytes()))])
+    }
+
+    #[tokio::test]
+    async fn modified_keys_are_invalidated_once() {
+        let dbs = [Db::new()];
+        let tracking = Tracking::default();
+        let mut reader = tracking.register(1, TrackingMode::default(), &dbs);
+        let bcast = TrackingMode {
+            bcast: true,
+            prefixes: vec!["user:".into()],
+        };
+        let mut broadcast = tracking.register(2, bcast, &dbs);
+
+        tracking.remember(1, &["a", "user:1"]);
+        dbs[0].set("a".into(), "1".into(), None).unwrap();
+        dbs[0].set("a".into(), "2".into(), None).unwrap();
+        dbs[0].set("user:1".into(), "x".into(), None).unwrap();
+        dbs[0].set("user:1".into(), "y".into(), None).unwrap();
+
+        // 读过的 key 只通知一次，BCAST 每次修改都通知匹配前缀的 key
+        assert_eq!(keys(reader.recv().await.unwrap()), key("a"));
+        assert_eq!(keys(reader.recv().await.unwrap()), key("user:1"));
+        assert!(reader.try_recv().is_err());
+        assert_eq!(keys(broadcast.recv().await.unwrap()), key("user:1"));
+        assert_eq!(keys(broadcast.recv().await.unwrap()), key("user:1"));
+        assert!(broadcast.try_recv().is_err());
+
+        tracking.unregister(1);
+        tracking.unregister(2);
+        assert!(!tracking.table.lock().unwrap().listening);
+    }
+}
@@ -0,0 +1,91 @@
+//! Stream 在 RDB 文件中的编码，DUMP/RESTORE 和 AOF 重写也使用这个编码
+//!
+//! 依次写入条目、`last_id` 和消费者组，消费者组包括 PEL 和消费者，重启后未确认的消息不会丢失。
+
+use bytes::{BufMut, BytesMut};
+
+use super::{ConsumerGroup, PendingEntry, Stream, StreamId};
+use crate::db::rdb::{put_bytes, put_len, RdbError, Reader};
+
+impl Stream {
+    pub(crate) fn encode(&self, buf: &mut BytesMut) {
+        put_len(buf, self.entries.len());
+        for (id, fields) in &self.entries {
+            put_id(buf, *id);
+            put_len(buf, fields.len());
+            for (field, value) in fields {
+                put_bytes(buf, field);
+                put_bytes(buf, value);
+            }
+        }
+        put_id(buf, self.last_id);
+
+        put_len(buf, self.groups.len());
+        for (name, group) in &self.groups {
+            put_bytes(buf, name.as_bytes());
+            put_id(buf, group.last_delivered);
+            put_len(buf, group.pending.len());
+            for (id, entry) in &group.pending {
+                put_id(buf, *id);
+                put_bytes(buf, entry.consumer.as_bytes());
+                buf.put_u64_le(entry.delivered_at);
+                buf.put_u64_le(entry.delivery_count);
+            }
+            // 消费者的 PEL 可以由组的 PEL 还原，只需要保存名字和活跃时间
+            put_len(buf, group.consumers.len());
+            for (name, consumer) in &group.consumers {
+                put_bytes(buf, name.as_bytes());
+                buf.put_u64_le(consumer.seen_at);
+            }
+        }
+    }
+
+    pub(crate) fn decode(r: &mut Reader) -> Result<Stream, RdbError> {
+        let mut stream = Stream::new();
+        for _ in 0..r.len()? {
+            let id = read_id(r)?;
+            let len = r.len()?;
+            let mut fields = Vec::with_capacity(r.capacity(len));
+            for _ in 0..len {
+                fields.push((r.bytes()?, r.bytes()?));
+            }
+            stream.entries.insert(id, fields);
+        }
+        stream.last_id = read_id(r)?;
+
+        for _ in 0..r.len()? {
+            let name = r.string()?;
+            let mut group = ConsumerGroup::new(read_id(r)?);
+            for _ in 0..r.len()? {
+                let id = read_id(r)?;
+                let entry = PendingEntry {
+                    consumer: r.string()?,
+                    delivered_at: r.u64()?,
+                    delivery_count: r.u64()?,
+                };
+                group
+                    .consumers
+                    .entry(entry.consumer.clone())
+                    .or_default()
+                    .pending
+                    .insert(id);
+                group.pending.insert(id, entry);
+            }
+            for _ in 0..r.len()? {
+                let name = r.string()?;
+                group.consumers.entry(name).or_default().seen_at = r.u64()?;
+            }
+            stream.groups.insert(name, group);
+        }
+        Ok(stream)
+    }
+}
+
+fn put_id(buf: &mut BytesMut, id: StreamId) {
+    buf.put_u64_le(id.ms);
+    buf.put_u64_le(id.seq);
+}
+
+fn read_id(r: &mut Reader) -> Result<StreamId, RdbError> {
+    Ok(StreamId::new(r.u64()?, r.u64()?))
+}
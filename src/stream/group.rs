@@ -0,0 +1,139 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use super::StreamId;
+use crate::db::memory::{btree_size, hash_table_size, sampled, MemoryUsage};
+
+/// 已投递但尚未确认的条目（PEL，Pending Entries List）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingEntry {
+    pub consumer: String,
+    /// 最近一次投递的毫秒时间戳
+    pub delivered_at: u64,
+    pub delivery_count: u64,
+}
+
+/// 组内的消费者，只记录属于它的待确认条目
+#[derive(Debug, Clone, Default)]
+pub struct Consumer {
+    pub(super) pending: BTreeSet<StreamId>,
+    pub(super) seen_at: u64,
+}
+
+impl Consumer {
+    pub fn pending(&self) -> &BTreeSet<StreamId> {
+        &self.pending
+    }
+
+    pub fn seen_at(&self) -> u64 {
+        self.seen_at
+    }
+}
+
+/// 消费者组
+///
+/// `last_delivered` 是组内已经投递过的最大 ID，`>` 读取时从它之后开始投递，
+/// 所以同一个条目在一个组内只会被投递给一个消费者。
+#[derive(Debug, Clone, Default)]
+pub struct ConsumerGroup {
+    pub(super) last_delivered: StreamId,
+    pub(super) pending: BTreeMap<StreamId, PendingEntry>,
+    pub(super) consumers: HashMap<String, Consumer>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_delivered: StreamId) -> ConsumerGroup {
+        ConsumerGroup {
+            last_delivered,
+            ..Default::default()
+        }
+    }
+
+    pub fn last_delivered(&self) -> StreamId {
+        self.last_delivered
+    }
+
+    pub fn pending(&self) -> &BTreeMap<StreamId, PendingEntry> {
+        &self.pending
+    }
+
+    pub fn consumer(&self, name: &str) -> Option<&Consumer> {
+        self.consumers.get(name)
+    }
+
+    /// 获取消费者，不存在时自动创建（与 redis 一致，第一次读取即创建）
+    pub(super) fn consumer_mut(&mut self, name: &str, now_ms: u64) -> &mut Consumer {
+        let consumer = self.consumers.entry(name.to_string()).or_default();
+        consumer.seen_at = now_ms;
+        consumer
+    }
+
+    /// 记录一次新投递，`noack` 时不进入 PEL
+    pub(super) fn deliver(&mut self, consumer: &str, id: StreamId, noack: bool, now_ms: u64) {
+        self.last_delivered = self.last_delivered.max(id);
+        if noack {
+            return;
+        }
+        let delivery_count = self.pending.get(&id).map_or(0, |e| e.delivery_count) + 1;
+        self.claim(consumer, id, now_ms, delivery_count, now_ms);
+    }
+
+    /// 把条目交给 `consumer`，条目不在 PEL 中时新建
+    pub(super) fn claim(
+        &mut self,
+        consumer: &str,
+        id: StreamId,
+        delivered_at: u64,
+        delivery_count: u64,
+        now_ms: u64,
+    ) {
+        // 条目原来属于其他消费者时，需要从原消费者的 PEL 中移除
+        if let Some(prev) = self.pending.get(&id) {
+            if prev.consumer != consumer {
+                if let Some(c) = self.consumers.get_mut(&prev.consumer) {
+                    c.pending.remove(&id);
+                }
+            }
+        }
+        self.pending.insert(
+            id,
+            PendingEntry {
+                consumer: consumer.to_string(),
+                delivered_at,
+                delivery_count,
+            },
+        );
+        self.consumer_mut(consumer, now_ms).pending.insert(id);
+    }
+
+    /// 确认条目，返回实际从 PEL 中移除的数量
+    pub fn ack(&mut self, ids: &[StreamId]) -> usize {
+        let mut acked = 0;
+        for id in ids {
+            if let Some(entry) = self.pending.remove(id) {
+                if let Some(consumer) = self.consumers.get_mut(&entry.consumer) {
+                    consumer.pending.remove(id);
+                }
+                acked += 1;
+            }
+        }
+        acked
+    }
+}
+
+impl MemoryUsage for ConsumerGroup {
+    fn memory_usage(&self, samples: usize) -> usize {
+        let pending = btree_size::<(StreamId, PendingEntry)>(self.pending.len())
+            + sampled(self.pending.values(), samples, |entry| {
+                entry.consumer.memory_usage(samples)
+            });
+        let consumers = hash_table_size::<(String, Consumer)>(self.consumers.len())
+            + self
+                .consumers
+                .iter()
+                .map(|(name, consumer)| {
+                    name.memory_usage(samples) + btree_size::<StreamId>(consumer.pending.len())
+                })
+                .sum::<usize>();
+        pending + consumers
+    }
+}
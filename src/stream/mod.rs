@@ -0,0 +1,544 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, mem,
+    ops::Bound,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use thiserror::Error;
+
+use crate::db::memory::{btree_size, hash_table_size, malloc_size, sampled, MemoryUsage};
+
+mod encode;
+mod group;
+pub use group::{Consumer, ConsumerGroup, PendingEntry};
+
+/// Stream 条目的字段列表，保持客户端写入时的顺序
+pub type Fields = Vec<(Bytes, Bytes)>;
+
+/// Stream 条目 ID，格式为 `<毫秒时间戳>-<序号>`
+///
+/// 先比较毫秒再比较序号，派生的 `Ord` 正好满足这个顺序（字段声明顺序即比较顺序）。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    /// 紧随其后的 ID，已经是最大值时返回 None
+    pub fn next(self) -> Option<StreamId> {
+        if self.seq < u64::MAX {
+            Some(StreamId::new(self.ms, self.seq + 1))
+        } else if self.ms < u64::MAX {
+            Some(StreamId::new(self.ms + 1, 0))
+        } else {
+            None
+        }
+    }
+
+    /// 解析范围查询的起点：`-` 表示最小 ID，只写毫秒时序号取 0
+    pub fn parse_start(s: &str) -> Result<StreamId, StreamError> {
+        match s {
+            "-" => Ok(StreamId::MIN),
+            _ => parse_id(s, 0),
+        }
+    }
+
+    /// 解析范围查询的终点：`+` 表示最大 ID，只写毫秒时序号取最大值
+    pub fn parse_end(s: &str) -> Result<StreamId, StreamError> {
+        match s {
+            "+" => Ok(StreamId::MAX),
+            _ => parse_id(s, u64::MAX),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl FromStr for StreamId {
+    type Err = StreamError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_id(s, 0)
+    }
+}
+
+/// `ms-seq` 或者只有 `ms`，缺省的序号由调用方决定
+fn parse_id(s: &str, default_seq: u64) -> Result<StreamId, StreamError> {
+    let (ms, seq) = match s.split_once('-') {
+        Some((ms, seq)) => (ms, Some(seq)),
+        None => (s, None),
+    };
+    let ms = ms.parse().map_err(|_| StreamError::InvalidId)?;
+    let seq = match seq {
+        Some(seq) => seq.parse().map_err(|_| StreamError::InvalidId)?,
+        None => default_seq,
+    };
+    Ok(StreamId::new(ms, seq))
+}
+
+/// XADD 中的 ID 参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdSpec {
+    /// `*`：毫秒和序号都自动生成
+    Auto,
+    /// `<ms>-*`：指定毫秒，序号自动生成
+    Partial(u64),
+    /// 完整指定的 ID
+    Explicit(StreamId),
+}
+
+impl FromStr for IdSpec {
+    type Err = StreamError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "*" {
+            return Ok(IdSpec::Auto);
+        }
+        if let Some(ms) = s.strip_suffix("-*") {
+            let ms = ms.parse().map_err(|_| StreamError::InvalidId)?;
+            return Ok(IdSpec::Partial(ms));
+        }
+        s.parse().map(IdSpec::Explicit)
+    }
+}
+
+/// XREADGROUP 的读取起点
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadStart {
+    /// `>`：读取从未投递给组内任何消费者的新条目
+    New,
+    /// 指定 ID：读取该消费者 PEL 中大于此 ID 的历史条目
+    Pending(StreamId),
+}
+
+impl FromStr for ReadStart {
+    type Err = StreamError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            ">" => Ok(ReadStart::New),
+            _ => s.parse().map(ReadStart::Pending),
+        }
+    }
+}
+
+/// XCLAIM 的选项
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Claim {
+    /// 只转移空闲时间不小于此值的条目，单位为毫秒
+    pub min_idle: u64,
+    /// 新的投递时间，None 表示当前时间
+    pub delivered_at: Option<u64>,
+    /// 新的投递次数，None 表示在原来的基础上加一（`justid` 时保持不变）
+    pub retry_count: Option<u64>,
+    /// 条目不在 PEL 中时也为它创建 PEL 记录，前提是条目仍在 stream 中
+    pub force: bool,
+    pub justid: bool,
+}
+
+/// 裁剪策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trim {
+    /// 只保留最新的 n 条
+    MaxLen(usize),
+    /// 删除所有小于该 ID 的条目
+    MinId(StreamId),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum StreamError {
+    #[error("ERR Invalid stream ID specified as stream command argument")]
+    InvalidId,
+    #[error("ERR The ID specified in XADD must be greater than 0-0")]
+    IdZero,
+    #[error("ERR The ID specified in XADD is equal or smaller than the target stream top item")]
+    IdTooSmall,
+    #[error("ERR The stream has exhausted the last possible ID, unable to add more items")]
+    Exhausted,
+    #[error("BUSYGROUP Consumer Group name already exists")]
+    BusyGroup,
+    #[error("NOGROUP No such consumer group")]
+    NoGroup,
+    #[error("ERR The ID specified in XSETID is smaller than the target stream top item")]
+    SetIdTooSmall,
+}
+
+/// Stream 类型：按 ID 有序保存的追加日志
+///
+/// 使用 `BTreeMap` 存储条目，按 ID 的范围查询和从头部裁剪都是 O(log n)。
+#[derive(Debug, Clone, Default)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    // 即使条目被删除或裁剪，新 ID 也必须大于曾经出现过的最大 ID
+    last_id: StreamId,
+    groups: HashMap<String, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn new() -> Stream {
+        Stream::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// 修改最后一个 ID，对应 XSETID，不能小于现有的最大条目 ID
+    pub fn set_last_id(&mut self, id: StreamId) -> Result<(), StreamError> {
+        if let Some((max, _)) = self.entries.last_key_value() {
+            if id < *max {
+                return Err(StreamError::SetIdTooSmall);
+            }
+        }
+        self.last_id = id;
+        Ok(())
+    }
+
+    /// 追加一个条目，自动生成的 ID 基于当前系统时间
+    pub fn add(&mut self, id: IdSpec, fields: Fields) -> Result<StreamId, StreamError> {
+        self.add_at(id, fields, now_ms())
+    }
+
+    /// 与 `add` 相同，但由调用方提供当前毫秒时间，便于测试
+    pub fn add_at(
+        &mut self,
+        id: IdSpec,
+        fields: Fields,
+        now_ms: u64,
+    ) -> Result<StreamId, StreamError> {
+        let id = self.next_id(id, now_ms)?;
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        Ok(id)
+    }
+
+    fn next_id(&self, id: IdSpec, now_ms: u64) -> Result<StreamId, StreamError> {
+        let last = self.last_id;
+        let id = match id {
+            // 系统时钟回拨时沿用上一个 ID 的毫秒值，保证 ID 单调递增
+            IdSpec::Auto if now_ms > last.ms => StreamId::new(now_ms, 0),
+            IdSpec::Auto => last.next().ok_or(StreamError::Exhausted)?,
+            IdSpec::Partial(ms) if ms > last.ms => StreamId::new(ms, 0),
+            IdSpec::Partial(ms) if ms == last.ms => match last.seq.checked_add(1) {
+                Some(seq) => StreamId::new(ms, seq),
+                None => return Err(StreamError::IdTooSmall),
+            },
+            IdSpec::Partial(_) => return Err(StreamError::IdTooSmall),
+            IdSpec::Explicit(id) => id,
+        };
+
+        if id == StreamId::MIN {
+            return Err(StreamError::IdZero);
+        }
+        if id <= last {
+            return Err(StreamError::IdTooSmall);
+        }
+        Ok(id)
+    }
+
+    /// 按 ID 范围查询，`count` 限制返回的条目数量
+    pub fn range(
+        &self,
+        start: Bound<StreamId>,
+        end: Bound<StreamId>,
+        count: Option<usize>,
+    ) -> Vec<(StreamId, &Fields)> {
+        // BTreeMap::range 在起点大于终点时会 panic，这里提前返回空结果
+        if is_empty_range(start, end) {
+            return vec![];
+        }
+        self.entries
+            .range((start, end))
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(id, fields)| (*id, fields))
+            .collect()
+    }
+
+    pub fn get(&self, id: &StreamId) -> Option<&Fields> {
+        self.entries.get(id)
+    }
+
+    /// 删除指定的条目，返回实际删除的数量
+    pub fn delete(&mut self, ids: &[StreamId]) -> usize {
+        ids.iter()
+            .filter(|id| self.entries.remove(id).is_some())
+            .count()
+    }
+
+    /// 按策略裁剪，`limit` 限制单次最多删除的条目数，返回删除的数量
+    pub fn trim(&mut self, strategy: Trim, limit: Option<usize>) -> usize {
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut removed = 0;
+        while removed < limit {
+            let oldest = match self.entries.first_key_value() {
+                Some((id, _)) => *id,
+                None => break,
+            };
+            let evict = match strategy {
+                Trim::MaxLen(max) => self.entries.len() > max,
+                Trim::MinId(min) => oldest < min,
+            };
+            if !evict {
+                break;
+            }
+            self.entries.pop_first();
+            removed += 1;
+        }
+        removed
+    }
+}
+
+/// 消费者组相关操作
+impl Stream {
+    /// 创建消费者组，`start` 为 None 时表示 `$`，即只消费创建之后写入的条目
+    pub fn create_group(&mut self, name: &str, start: Option<StreamId>) -> Result<(), StreamError> {
+        if self.groups.contains_key(name) {
+            return Err(StreamError::BusyGroup);
+        }
+        let start = start.unwrap_or(self.last_id);
+        self.groups
+            .insert(name.to_string(), ConsumerGroup::new(start));
+        Ok(())
+    }
+
+    pub fn group(&self, name: &str) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = (&String, &ConsumerGroup)> {
+        self.groups.iter()
+    }
+
+    /// 以组内某个消费者的身份读取条目
+    ///
+    /// 历史条目已经被 XDEL 删除时，对应的字段为 None。
+    pub fn read_group(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        start: ReadStart,
+        count: Option<usize>,
+        noack: bool,
+    ) -> Result<Vec<(StreamId, Option<Fields>)>, StreamError> {
+        let now = now_ms();
+        let count = count.unwrap_or(usize::MAX);
+        let group = self.groups.get_mut(group).ok_or(StreamError::NoGroup)?;
+
+        match start {
+            ReadStart::New => {
+                let start = Bound::Excluded(group.last_delivered());
+                let entries: Vec<_> = self
+                    .entries
+                    .range((start, Bound::Unbounded))
+                    .take(count)
+                    .map(|(id, fields)| (*id, Some(fields.clone())))
+                    .collect();
+                for (id, _) in &entries {
+                    group.deliver(consumer, *id, noack, now);
+                }
+                // 没有读到条目也要登记消费者
+                group.consumer_mut(consumer, now);
+                Ok(entries)
+            }
+            ReadStart::Pending(after) => {
+                let ids: Vec<_> = group
+                    .consumer_mut(consumer, now)
+                    .pending()
+                    .range((Bound::Excluded(after), Bound::Unbounded))
+                    .take(count)
+                    .copied()
+                    .collect();
+                Ok(ids
+                    .into_iter()
+                    .map(|id| (id, self.entries.get(&id).cloned()))
+                    .collect())
+            }
+        }
+    }
+
+    /// 把组内的待确认条目转移给 `consumer`，返回转移成功的 ID
+    ///
+    /// 条目已经被 XDEL 删除时，会从 PEL 中移除而不是转移。
+    pub fn claim(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        ids: &[StreamId],
+        claim: Claim,
+    ) -> Result<Vec<StreamId>, StreamError> {
+        let now = now_ms();
+        let group = self.groups.get_mut(group).ok_or(StreamError::NoGroup)?;
+        group.consumer_mut(consumer, now);
+
+        let mut claimed = vec![];
+        for &id in ids {
+            let exists = self.entries.contains_key(&id);
+            let delivery_count = match group.pending.get(&id) {
+                Some(entry) if now.saturating_sub(entry.delivered_at) < claim.min_idle => continue,
+                Some(_) if !exists => {
+                    group.ack(&[id]);
+                    continue;
+                }
+                Some(entry) => entry.delivery_count,
+                None if claim.force && exists => 0,
+                None => continue,
+            };
+            let delivery_count = match claim.retry_count {
+                Some(count) => count,
+                None if claim.justid => delivery_count,
+                None => delivery_count + 1,
+            };
+            let delivered_at = claim.delivered_at.unwrap_or(now);
+            group.claim(consumer, id, delivered_at, delivery_count, now);
+            claimed.push(id);
+        }
+        Ok(claimed)
+    }
+
+    /// 确认组内已处理的条目，返回确认成功的数量
+    pub fn ack(&mut self, group: &str, ids: &[StreamId]) -> usize {
+        self.groups.get_mut(group).map_or(0, |g| g.ack(ids))
+    }
+}
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn is_empty_range(start: Bound<StreamId>, end: Bound<StreamId>) -> bool {
+    match (start, end) {
+        (Bound::Included(s), Bound::Included(e)) => s > e,
+        (Bound::Included(s), Bound::Excluded(e))
+        | (Bound::Excluded(s), Bound::Included(e))
+        | (Bound::Excluded(s), Bound::Excluded(e)) => s >= e,
+        _ => false,
+    }
+}
+
+impl MemoryUsage for Stream {
+    fn memory_usage(&self, samples: usize) -> usize {
+        let entries = btree_size::<(StreamId, Fields)>(self.entries.len())
+            + sampled(self.entries.values(), samples, |fields| {
+                malloc_size(fields.capacity() * mem::size_of::<(Bytes, Bytes)>())
+                    + fields
+                        .iter()
+                        .map(|(field, value)| malloc_size(field.len()) + malloc_size(value.len()))
+                        .sum::<usize>()
+            });
+        let groups = hash_table_size::<(String, ConsumerGroup)>(self.groups.len())
+            + self
+                .groups
+                .iter()
+                .map(|(name, group)| name.memory_usage(samples) + group.memory_usage(samples))
+                .sum::<usize>();
+        entries + groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(v: &'static str) -> Fields {
+        vec![(Bytes::from_static(b"f"), Bytes::from_static(v.as_bytes()))]
+    }
+
+    #[test]
+    fn auto_id_is_monotonic() {
+        let mut stream = Stream::new();
+        let a = stream.add_at(IdSpec::Auto, fields("a"), 100).unwrap();
+        let b = stream.add_at(IdSpec::Auto, fields("b"), 100).unwrap();
+        // 时钟回拨
+        let c = stream.add_at(IdSpec::Auto, fields("c"), 50).unwrap();
+
+        assert_eq!(a, StreamId::new(100, 0));
+        assert_eq!(b, StreamId::new(100, 1));
+        assert_eq!(c, StreamId::new(100, 2));
+        assert_eq!(
+            stream.add_at(IdSpec::Explicit(StreamId::new(100, 2)), fields("d"), 0),
+            Err(StreamError::IdTooSmall)
+        );
+    }
+
+    #[test]
+    fn range_and_trim() {
+        let mut stream = Stream::new();
+        for ms in 1..=5 {
+            stream
+                .add_at(IdSpec::Explicit(StreamId::new(ms, 0)), fields("v"), 0)
+                .unwrap();
+        }
+
+        let start = StreamId::parse_start("2").unwrap();
+        let end = StreamId::parse_end("4").unwrap();
+        let ids: Vec<_> = stream
+            .range(Bound::Included(start), Bound::Included(end), None)
+            .into_iter()
+            .map(|(id, _)| id.ms)
+            .collect();
+        assert_eq!(ids, vec![2, 3, 4]);
+
+        assert_eq!(stream.trim(Trim::MaxLen(3), None), 2);
+        assert_eq!(stream.trim(Trim::MinId(StreamId::new(5, 0)), None), 2);
+        assert_eq!(stream.len(), 1);
+        assert_eq!(stream.last_id(), StreamId::new(5, 0));
+    }
+
+    #[test]
+    fn consumer_group_delivery_and_ack() {
+        let mut stream = Stream::new();
+        let a = stream.add_at(IdSpec::Auto, fields("a"), 1).unwrap();
+        stream.create_group("g", Some(StreamId::MIN)).unwrap();
+        assert_eq!(stream.create_group("g", None), Err(StreamError::BusyGroup));
+        let b = stream.add_at(IdSpec::Auto, fields("b"), 2).unwrap();
+
+        // 同组内的条目只会投递一次
+        let first = stream.read_group("g", "c1", ReadStart::New, Some(1), false);
+        let second = stream.read_group("g", "c2", ReadStart::New, None, false);
+        assert_eq!(first.unwrap()[0].0, a);
+        assert_eq!(second.unwrap()[0].0, b);
+        assert!(stream
+            .read_group("g", "c2", ReadStart::New, None, false)
+            .unwrap()
+            .is_empty());
+
+        // 历史条目只包含自己的 PEL
+        let history = stream
+            .read_group("g", "c1", ReadStart::Pending(StreamId::MIN), None, false)
+            .unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].0, a);
+
+        assert_eq!(stream.ack("g", &[a, a]), 1);
+        assert_eq!(stream.group("g").unwrap().pending().len(), 1);
+    }
+}